@@ -32,6 +32,9 @@ protoc -I . --go_out=. --go_opt=$GO_OPT \
 protoc -I . --go_out=. --go_opt=$GO_OPT \
 ./encore/runtime/v1/secretdata.proto
 
+protoc -I . --go_out=. --go_opt=$GO_OPT --go-grpc_out=. --go-grpc_opt=$GRPC_OPT \
+./encore/runtime/v1/telemetry.proto
+
 # Prometheus protos for metrics exporter
 protoc -I . --go_out=../runtimes/go/appruntime/infrasdk/metrics/prometheus --go_opt=$GO_OPT \
 ./prompb/types.proto
@@ -4,6 +4,7 @@ fn main() -> std::io::Result<()> {
     prost_build::compile_protos(
         &[
             "../../proto/encore/runtime/v1/runtime.proto",
+            "../../proto/encore/runtime/v1/telemetry.proto",
             "../../proto/encore/parser/meta/v1/meta.proto",
             "../../proto/prompb/remote.proto",
         ],
@@ -0,0 +1,664 @@
+//! Serves a read-only admin API describing the services, endpoints,
+//! infrastructure bindings and health of the deployment,
+//! to power self-hosted dashboards.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::{routing, Json, Router};
+use serde::Serialize;
+
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::secrets;
+use crate::startup;
+
+/// How long a dependency may take to respond to a health check.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A description of the deployment, computed at startup from
+/// the runtime config and the app metadata.
+///
+/// It never includes credentials.
+#[derive(Debug, Serialize)]
+pub struct Description {
+    app_revision: String,
+    deploy_id: String,
+    services: Vec<Service>,
+    gateways: Vec<Gateway>,
+    infra: Infra,
+}
+
+#[derive(Debug, Serialize)]
+struct Service {
+    name: String,
+    /// Whether the service is hosted by this process.
+    hosted: bool,
+    /// Where the service is reached, if it's hosted elsewhere.
+    base_url: Option<String>,
+    endpoints: Vec<Endpoint>,
+    /// The services this service calls.
+    dependencies: BTreeSet<String>,
+    databases: Vec<String>,
+    buckets: Vec<String>,
+    publishes: Vec<String>,
+    subscriptions: Vec<SubscriptionRef>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Endpoint {
+    service: String,
+    name: String,
+    path: String,
+    methods: Vec<String>,
+    access: &'static str,
+    raw: bool,
+    streaming: bool,
+    /// The gateways the endpoint is exposed on.
+    exposed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionRef {
+    topic: String,
+    subscription: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Gateway {
+    name: String,
+    hosted: bool,
+    base_url: String,
+    hostnames: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Infra {
+    sql_databases: Vec<SqlDatabase>,
+    redis: Vec<Redis>,
+    pubsub_topics: Vec<PubSubTopic>,
+    pubsub_subscriptions: Vec<PubSubSubscription>,
+    buckets: Vec<Bucket>,
+}
+
+#[derive(Debug, Serialize)]
+struct SqlDatabase {
+    name: String,
+    cloud_name: String,
+    hosts: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Redis {
+    name: String,
+    hosts: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PubSubTopic {
+    name: String,
+    cloud_name: String,
+    provider: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct PubSubSubscription {
+    topic: String,
+    name: String,
+    cloud_name: String,
+    provider: &'static str,
+    push_only: bool,
+    paused: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Bucket {
+    name: String,
+    cloud_name: String,
+    provider: &'static str,
+    region: Option<String>,
+}
+
+impl Description {
+    pub fn new(
+        md: &meta::Data,
+        deployment: &pb::Deployment,
+        resources: &pb::infrastructure::Resources,
+        service_discovery: &pb::ServiceDiscovery,
+    ) -> Self {
+        let hosted: HashSet<&str> = deployment
+            .hosted_services
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+
+        // Map packages to the service they belong to, to find the services called.
+        let pkg_services: HashMap<&str, &str> = md
+            .pkgs
+            .iter()
+            .filter(|pkg| !pkg.service_name.is_empty())
+            .map(|pkg| (pkg.rel_path.as_str(), pkg.service_name.as_str()))
+            .collect();
+
+        let services = md
+            .svcs
+            .iter()
+            .map(|svc| {
+                let dependencies = md
+                    .pkgs
+                    .iter()
+                    .filter(|pkg| pkg.service_name == svc.name)
+                    .flat_map(|pkg| &pkg.rpc_calls)
+                    .filter_map(|call| pkg_services.get(call.pkg.as_str()))
+                    .filter(|name| **name != svc.name)
+                    .map(|name| name.to_string())
+                    .collect();
+
+                Service {
+                    name: svc.name.clone(),
+                    hosted: hosted.contains(svc.name.as_str()),
+                    base_url: service_discovery
+                        .services
+                        .get(&svc.name)
+                        .map(|loc| loc.base_url.clone())
+                        .filter(|url| !url.is_empty()),
+                    endpoints: svc.rpcs.iter().map(endpoint).collect(),
+                    dependencies,
+                    databases: svc.databases.clone(),
+                    buckets: svc.buckets.iter().map(|b| b.bucket.clone()).collect(),
+                    publishes: md
+                        .pubsub_topics
+                        .iter()
+                        .filter(|t| t.publishers.iter().any(|p| p.service_name == svc.name))
+                        .map(|t| t.name.clone())
+                        .collect(),
+                    subscriptions: md
+                        .pubsub_topics
+                        .iter()
+                        .flat_map(|t| t.subscriptions.iter().map(move |s| (t, s)))
+                        .filter(|(_, s)| s.service_name == svc.name)
+                        .map(|(t, s)| SubscriptionRef {
+                            topic: t.name.clone(),
+                            subscription: s.name.clone(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let gateways = resources
+            .gateways
+            .iter()
+            .map(|gw| Gateway {
+                name: gw.encore_name.clone(),
+                hosted: deployment.hosted_gateways.contains(&gw.rid),
+                base_url: gw.base_url.clone(),
+                hostnames: gw.hostnames.clone(),
+            })
+            .collect();
+
+        Self {
+            app_revision: md.app_revision.clone(),
+            deploy_id: deployment.deploy_id.clone(),
+            services,
+            gateways,
+            infra: Infra::new(resources),
+        }
+    }
+
+    fn endpoints(&self) -> Vec<Endpoint> {
+        self.services
+            .iter()
+            .flat_map(|svc| svc.endpoints.iter().cloned())
+            .collect()
+    }
+}
+
+impl Infra {
+    fn new(resources: &pb::infrastructure::Resources) -> Self {
+        use pb::bucket_cluster::Provider as BucketProvider;
+        use pb::pub_sub_cluster::Provider as PubSubProvider;
+
+        let mut infra = Infra {
+            sql_databases: Vec::new(),
+            redis: Vec::new(),
+            pubsub_topics: Vec::new(),
+            pubsub_subscriptions: Vec::new(),
+            buckets: Vec::new(),
+        };
+
+        for cluster in &resources.sql_clusters {
+            let hosts: Vec<String> = cluster.servers.iter().map(|s| s.host.clone()).collect();
+            for db in &cluster.databases {
+                infra.sql_databases.push(SqlDatabase {
+                    name: db.encore_name.clone(),
+                    cloud_name: db.cloud_name.clone(),
+                    hosts: hosts.clone(),
+                });
+            }
+        }
+
+        for cluster in &resources.redis_clusters {
+            let hosts: Vec<String> = cluster.servers.iter().map(|s| s.host.clone()).collect();
+            for db in &cluster.databases {
+                infra.redis.push(Redis {
+                    name: db.encore_name.clone(),
+                    hosts: hosts.clone(),
+                });
+            }
+        }
+
+        for cluster in &resources.pubsub_clusters {
+            let provider = match &cluster.provider {
+                Some(PubSubProvider::Encore(_)) => "encore",
+                Some(PubSubProvider::Aws(_)) => "aws",
+                Some(PubSubProvider::Gcp(_)) => "gcp",
+                Some(PubSubProvider::Azure(_)) => "azure",
+                Some(PubSubProvider::Nsq(_)) => "nsq",
+                Some(PubSubProvider::Kafka(_)) => "kafka",
+                Some(PubSubProvider::Rabbitmq(_)) => "rabbitmq",
+                None => "unknown",
+            };
+            for topic in &cluster.topics {
+                infra.pubsub_topics.push(PubSubTopic {
+                    name: topic.encore_name.clone(),
+                    cloud_name: topic.cloud_name.clone(),
+                    provider,
+                });
+            }
+            for sub in &cluster.subscriptions {
+                infra.pubsub_subscriptions.push(PubSubSubscription {
+                    topic: sub.topic_encore_name.clone(),
+                    name: sub.subscription_encore_name.clone(),
+                    cloud_name: sub.subscription_cloud_name.clone(),
+                    provider,
+                    push_only: sub.push_only,
+                    paused: sub.paused,
+                });
+            }
+        }
+
+        for cluster in &resources.bucket_clusters {
+            let provider = match &cluster.provider {
+                Some(BucketProvider::S3(_)) => "s3",
+                Some(BucketProvider::Gcs(_)) => "gcs",
+                Some(BucketProvider::AzureBlob(_)) => "azure_blob",
+                None => "unknown",
+            };
+            for bucket in &cluster.buckets {
+                infra.buckets.push(Bucket {
+                    name: bucket.encore_name.clone(),
+                    cloud_name: bucket.cloud_name.clone(),
+                    provider,
+                    region: bucket.region.clone(),
+                });
+            }
+        }
+
+        infra
+    }
+}
+
+fn endpoint(rpc: &meta::Rpc) -> Endpoint {
+    use meta::rpc::{AccessType, Protocol};
+
+    Endpoint {
+        service: rpc.service_name.clone(),
+        name: rpc.name.clone(),
+        path: rpc.path.as_ref().map(path_to_str).unwrap_or_default(),
+        methods: rpc.http_methods.clone(),
+        access: match rpc.access_type() {
+            AccessType::Public => "public",
+            AccessType::Auth => "auth",
+            AccessType::Private => "private",
+        },
+        raw: rpc.proto() == Protocol::Raw,
+        streaming: rpc.streaming_request || rpc.streaming_response,
+        exposed: rpc.expose.keys().cloned().collect(),
+    }
+}
+
+fn path_to_str(path: &meta::Path) -> String {
+    use meta::path_segment::SegmentType;
+
+    let mut result = String::new();
+    for seg in &path.segments {
+        result.push('/');
+        match seg.r#type() {
+            SegmentType::Literal => {}
+            SegmentType::Param => result.push(':'),
+            SegmentType::Wildcard | SegmentType::Fallback => result.push('*'),
+        }
+        result.push_str(&seg.value);
+    }
+    result
+}
+
+/// The result of a health check.
+#[derive(Debug, Serialize)]
+struct Check {
+    name: String,
+    passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Health {
+    status: &'static str,
+    uptime_secs: u64,
+    checks: Vec<Check>,
+}
+
+struct AppState {
+    token: Option<Vec<u8>>,
+    description: Description,
+    /// Checks performed at startup, such as secret resolution.
+    startup_checks: Vec<(String, Option<String>)>,
+    dependencies: Vec<startup::Dependency>,
+    started: Instant,
+}
+
+impl AppState {
+    async fn health(&self) -> Health {
+        let results =
+            futures::future::join_all(self.dependencies.iter().map(|dep| dep.check(CHECK_TIMEOUT)))
+                .await;
+
+        let checks: Vec<Check> = self
+            .startup_checks
+            .iter()
+            .map(|(name, error)| Check {
+                name: name.clone(),
+                passed: error.is_none(),
+                error: error.clone(),
+            })
+            .chain(
+                self.dependencies
+                    .iter()
+                    .zip(results)
+                    .map(|(dep, result)| Check {
+                        name: dep.name().to_string(),
+                        passed: result.is_ok(),
+                        error: result.err().map(|err| format!("{err:#}")),
+                    }),
+            )
+            .collect();
+
+        Health {
+            status: if checks.iter().all(|c| c.passed) {
+                "ok"
+            } else {
+                "degraded"
+            },
+            uptime_secs: self.started.elapsed().as_secs(),
+            checks,
+        }
+    }
+}
+
+/// The admin API server.
+pub struct Server {
+    addr: SocketAddr,
+    prefix: String,
+    state: AppState,
+}
+
+impl Server {
+    pub fn new(
+        cfg: &pb::AdminApi,
+        description: Description,
+        secrets: &secrets::Manager,
+    ) -> anyhow::Result<Self> {
+        let addr = cfg
+            .listen_addr
+            .parse()
+            .with_context(|| format!("invalid listen address {:?}", cfg.listen_addr))?;
+        let token = match &cfg.auth_token {
+            Some(data) => Some(
+                secrets
+                    .load(data.clone())
+                    .get()
+                    .context("unable to resolve auth token")?
+                    .to_vec(),
+            ),
+            None => None,
+        };
+        let startup_checks = secrets
+            .report()
+            .iter()
+            .map(|s| {
+                (
+                    format!("secret:{}", s.name),
+                    s.error
+                        .as_ref()
+                        .map(|err| format!("{} ({})", err, s.source)),
+                )
+            })
+            .collect();
+
+        let prefix = cfg
+            .path_prefix
+            .as_deref()
+            .unwrap_or_default()
+            .trim_matches('/');
+        Ok(Self {
+            addr,
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("/{prefix}")
+            },
+            state: AppState {
+                token,
+                description,
+                startup_checks,
+                dependencies: Vec::new(),
+                started: Instant::now(),
+            },
+        })
+    }
+
+    /// Checks the dependencies are reachable when the health is requested.
+    pub fn with_dependencies(mut self, deps: Vec<startup::Dependency>) -> Self {
+        self.state.dependencies.extend(deps);
+        self
+    }
+
+    fn router(self) -> Router {
+        let state = Arc::new(self.state);
+        let routes = Router::new()
+            .route(
+                "/services",
+                routing::get(|State(s): State<Arc<AppState>>| async move {
+                    Json(&s.description.services).into_response()
+                }),
+            )
+            .route(
+                "/endpoints",
+                routing::get(|State(s): State<Arc<AppState>>| async move {
+                    Json(s.description.endpoints()).into_response()
+                }),
+            )
+            .route(
+                "/gateways",
+                routing::get(|State(s): State<Arc<AppState>>| async move {
+                    Json(&s.description.gateways).into_response()
+                }),
+            )
+            .route(
+                "/infra",
+                routing::get(|State(s): State<Arc<AppState>>| async move {
+                    Json(&s.description.infra).into_response()
+                }),
+            )
+            .route(
+                "/health",
+                routing::get(|State(s): State<Arc<AppState>>| async move {
+                    let health = s.health().await;
+                    let status = if health.status == "ok" {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    };
+                    (status, Json(health)).into_response()
+                }),
+            )
+            .route(
+                "/",
+                routing::get(|State(s): State<Arc<AppState>>| async move {
+                    Json(&s.description).into_response()
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+            .with_state(state);
+
+        if self.prefix.is_empty() {
+            routes
+        } else {
+            Router::new().nest(&self.prefix, routes)
+        }
+    }
+
+    /// Starts serving the API on the given runtime.
+    pub fn start(self, rt: &tokio::runtime::Handle) {
+        let addr = self.addr;
+        let router = self.router();
+        rt.spawn(async move {
+            let ln = match tokio::net::TcpListener::bind(addr).await {
+                Ok(ln) => ln,
+                Err(err) => {
+                    log::error!("admin: unable to listen on {addr}: {err}");
+                    return;
+                }
+            };
+            log::info!("admin: serving the admin API on {addr}");
+            if let Err(err) = axum::serve(ln, router).await {
+                log::error!("admin: server failed: {err}");
+            }
+        });
+    }
+}
+
+async fn authorize(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    if let Some(token) = &state.token {
+        let provided = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::as_bytes);
+        let valid =
+            provided.is_some_and(|p| p.len() == token.len() && openssl::memcmp::eq(p, token));
+        if !valid {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segs: &[(meta::path_segment::SegmentType, &str)]) -> meta::Path {
+        meta::Path {
+            segments: segs
+                .iter()
+                .map(|(typ, value)| meta::PathSegment {
+                    r#type: *typ as i32,
+                    value: value.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_description() {
+        use meta::path_segment::SegmentType;
+
+        let md = meta::Data {
+            app_revision: "rev".to_string(),
+            pkgs: vec![
+                meta::Package {
+                    rel_path: "users".to_string(),
+                    service_name: "users".to_string(),
+                    ..Default::default()
+                },
+                meta::Package {
+                    rel_path: "orders".to_string(),
+                    service_name: "orders".to_string(),
+                    rpc_calls: vec![meta::QualifiedName {
+                        pkg: "users".to_string(),
+                        name: "Get".to_string(),
+                    }],
+                    ..Default::default()
+                },
+            ],
+            svcs: vec![
+                meta::Service {
+                    name: "users".to_string(),
+                    rpcs: vec![meta::Rpc {
+                        name: "Get".to_string(),
+                        service_name: "users".to_string(),
+                        access_type: meta::rpc::AccessType::Auth as i32,
+                        path: Some(path(&[
+                            (SegmentType::Literal, "users"),
+                            (SegmentType::Param, "id"),
+                        ])),
+                        http_methods: vec!["GET".to_string()],
+                        ..Default::default()
+                    }],
+                    databases: vec!["users".to_string()],
+                    ..Default::default()
+                },
+                meta::Service {
+                    name: "orders".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let deployment = pb::Deployment {
+            deploy_id: "deploy".to_string(),
+            hosted_services: vec![pb::HostedService {
+                name: "orders".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let discovery = pb::ServiceDiscovery {
+            services: HashMap::from([(
+                "users".to_string(),
+                pb::service_discovery::Location {
+                    base_url: "http://users:8080".to_string(),
+                    ..Default::default()
+                },
+            )]),
+        };
+
+        let desc = Description::new(&md, &deployment, &Default::default(), &discovery);
+        let users = &desc.services[0];
+        assert!(!users.hosted);
+        assert_eq!(users.base_url.as_deref(), Some("http://users:8080"));
+        assert_eq!(users.databases, vec!["users".to_string()]);
+
+        let orders = &desc.services[1];
+        assert!(orders.hosted);
+        assert_eq!(orders.dependencies, BTreeSet::from(["users".to_string()]));
+
+        let endpoints = desc.endpoints();
+        assert_eq!(endpoints.len(), 1);
+        assert_eq!(endpoints[0].path, "/users/:id");
+        assert_eq!(endpoints[0].access, "auth");
+    }
+}
@@ -73,6 +73,7 @@ impl AuthHandler for LocalAuthHandler {
                 parent_span,
                 caller_event_id: meta.parent_event_id,
                 ext_correlation_id: meta.ext_correlation_id,
+                deadline: meta.deadline,
                 is_platform_request: false, // TODO
                 internal_caller: None,      // TODO
                 start: tokio::time::Instant::now(),
@@ -11,12 +11,14 @@ use crate::{api, EndpointName};
 
 use crate::api::schema::encoding::Schema;
 pub use local::LocalAuthHandler;
+pub use oidc::OidcValidator;
 pub use remote::RemoteAuthHandler;
 
 use super::jsonschema::JSONSchema;
 use super::PValues;
 
 mod local;
+mod oidc;
 mod remote;
 
 pub type AxumRequest = axum::http::Request<axum::body::Body>;
@@ -140,8 +142,11 @@ impl Authenticator {
         inbound: &R,
         mut call_meta: CallMeta,
     ) -> AuthRequest {
-        // Ignore the parent span id as gateways don't currently record a span.
-        call_meta.parent_span_id = None;
+        // Ignore the parent span id as gateways don't currently record a span,
+        // unless it was received from a non-Encore service.
+        if !call_meta.ext_parent_span {
+            call_meta.parent_span_id = None;
+        }
 
         // Headers.
         let mut headers = match &self.schema.header {
@@ -0,0 +1,378 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use http::header::AUTHORIZATION;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::Algorithm;
+use serde::Deserialize;
+
+use crate::api::auth::AuthResponse;
+use crate::api::{self, PValues};
+use crate::encore::runtime::v1 as pb;
+
+/// How long a fetched JWK set is used before it's refreshed.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// The minimum time between refetching the JWK set when a token
+/// references an unknown key, to avoid hammering the identity provider.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+type Claims = serde_json::Map<String, serde_json::Value>;
+
+/// Validates bearer tokens issued by an OpenID Connect identity provider.
+pub struct OidcValidator {
+    issuer: String,
+    jwks_url: Option<String>,
+    audiences: Vec<String>,
+    user_id_claim: String,
+    claim_mappings: Vec<(String, String)>,
+    replace_handler: bool,
+    http_client: reqwest::Client,
+    keys: tokio::sync::RwLock<Option<CachedKeys>>,
+}
+
+struct CachedKeys {
+    set: Arc<JwkSet>,
+    fetched_at: Instant,
+}
+
+impl OidcValidator {
+    pub fn new(cfg: &pb::gateway::Oidc, http_client: reqwest::Client) -> anyhow::Result<Self> {
+        if cfg.issuer.is_empty() {
+            anyhow::bail!("missing OIDC issuer");
+        }
+
+        let mut claim_mappings: Vec<_> = cfg
+            .claim_mappings
+            .iter()
+            .map(|(field, claim)| (field.clone(), claim.clone()))
+            .collect();
+        claim_mappings.sort();
+
+        Ok(Self {
+            issuer: cfg.issuer.clone(),
+            jwks_url: cfg.jwks_url.clone(),
+            audiences: cfg.audiences.clone(),
+            user_id_claim: cfg.user_id_claim.clone().unwrap_or_else(|| "sub".into()),
+            claim_mappings,
+            replace_handler: cfg.mode() == pb::gateway::oidc::Mode::ReplaceHandler,
+            http_client,
+            keys: tokio::sync::RwLock::new(None),
+        })
+    }
+
+    /// Reports whether requests are authenticated from the token claims
+    /// instead of by the app's auth handler.
+    pub fn replaces_handler(&self) -> bool {
+        self.replace_handler
+    }
+
+    /// Validates the bearer token in the request headers, if any.
+    /// It returns Ok(None) if the request has no bearer token,
+    /// and an unauthenticated error if the token is invalid.
+    pub async fn validate(&self, headers: &http::HeaderMap) -> Result<Option<Claims>, api::Error> {
+        let Some(token) = bearer_token(headers) else {
+            return Ok(None);
+        };
+
+        self.validate_token(token)
+            .await
+            .map(Some)
+            .map_err(|err| api::Error {
+                internal_message: Some(format!("invalid OIDC token: {:#}", err)),
+                ..api::Error::unauthenticated()
+            })
+    }
+
+    /// Computes the auth response for a request with the given validated claims.
+    pub fn authenticated(&self, claims: Claims) -> AuthResponse {
+        let Some(auth_uid) = claims
+            .get(&self.user_id_claim)
+            .and_then(|v| v.as_str())
+            .filter(|uid| !uid.is_empty())
+        else {
+            return AuthResponse::Unauthenticated {
+                error: api::Error {
+                    internal_message: Some(format!(
+                        "OIDC token is missing the {:?} claim",
+                        self.user_id_claim
+                    )),
+                    ..api::Error::unauthenticated()
+                },
+            };
+        };
+        let auth_uid = auth_uid.to_string();
+
+        AuthResponse::Authenticated {
+            auth_uid,
+            auth_data: self.auth_data(claims),
+        }
+    }
+
+    fn auth_data(&self, mut claims: Claims) -> PValues {
+        if self.claim_mappings.is_empty() {
+            return claims.into_iter().map(|(k, v)| (k, v.into())).collect();
+        }
+
+        self.claim_mappings
+            .iter()
+            .filter_map(|(field, claim)| Some((field.clone(), claims.remove(claim)?.into())))
+            .collect()
+    }
+
+    async fn validate_token(&self, token: &str) -> anyhow::Result<Claims> {
+        let header = jsonwebtoken::decode_header(token).context("invalid token header")?;
+
+        // Only accept asymmetric algorithms, since the keys are public.
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            anyhow::bail!("unsupported algorithm: {:?}", header.alg);
+        }
+
+        let keys = self.keys(false).await?;
+        let jwk = match find_key(&keys, header.kid.as_deref()) {
+            Some(jwk) => jwk.clone(),
+            None => {
+                // The identity provider may have rotated its keys.
+                let keys = self.keys(true).await?;
+                find_key(&keys, header.kid.as_deref())
+                    .with_context(|| format!("unknown signing key: {:?}", header.kid))?
+                    .clone()
+            }
+        };
+
+        let decoding_key = jsonwebtoken::DecodingKey::from_jwk(&jwk)
+            .context("unable to create JWT decoding key")?;
+
+        let mut validation = jsonwebtoken::Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        if self.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audiences);
+        }
+        validation.set_required_spec_claims(&["exp", "iss"]);
+
+        let data = jsonwebtoken::decode::<Claims>(token, &decoding_key, &validation)
+            .context("unable to validate token")?;
+        Ok(data.claims)
+    }
+
+    /// Returns the JWK set, fetching it if it's not cached or too old.
+    /// If refresh is true the set is refetched unless it was fetched very recently.
+    async fn keys(&self, refresh: bool) -> anyhow::Result<Arc<JwkSet>> {
+        let max_age = if refresh {
+            JWKS_MIN_REFRESH_INTERVAL
+        } else {
+            JWKS_MAX_AGE
+        };
+
+        if let Some(cached) = self.keys.read().await.as_ref() {
+            if cached.fetched_at.elapsed() < max_age {
+                return Ok(cached.set.clone());
+            }
+        }
+
+        let mut guard = self.keys.write().await;
+
+        // Another request may have fetched the keys while we waited for the lock.
+        if let Some(cached) = guard.as_ref() {
+            if cached.fetched_at.elapsed() < max_age {
+                return Ok(cached.set.clone());
+            }
+        }
+
+        let set = match self.fetch_keys().await {
+            Ok(set) => Arc::new(set),
+            Err(err) => match guard.as_ref() {
+                // Keep using the stale keys if the identity provider is unavailable.
+                Some(cached) if !refresh => {
+                    log::warn!("unable to refresh OIDC signing keys: {:#}", err);
+                    return Ok(cached.set.clone());
+                }
+                _ => return Err(err),
+            },
+        };
+
+        *guard = Some(CachedKeys {
+            set: set.clone(),
+            fetched_at: Instant::now(),
+        });
+        Ok(set)
+    }
+
+    async fn fetch_keys(&self) -> anyhow::Result<JwkSet> {
+        let jwks_url = match &self.jwks_url {
+            Some(url) => url.clone(),
+            None => self.discover_jwks_url().await?,
+        };
+
+        #[derive(Deserialize)]
+        struct RawKeyList {
+            keys: Vec<serde_json::Value>,
+        }
+
+        let key_list = self
+            .http_client
+            .get(&jwks_url)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .with_context(|| format!("unable to fetch JWK set from {}", jwks_url))?
+            .json::<RawKeyList>()
+            .await
+            .context("unable to parse JWK set")?;
+
+        // Skip keys we can't use, such as encryption keys with unsupported algorithms.
+        let keys = key_list
+            .keys
+            .into_iter()
+            .filter_map(|key| serde_json::from_value(key).ok())
+            .collect();
+        Ok(JwkSet { keys })
+    }
+
+    async fn discover_jwks_url(&self) -> anyhow::Result<String> {
+        #[derive(Deserialize)]
+        struct Discovery {
+            jwks_uri: String,
+        }
+
+        let url = discovery_url(&self.issuer);
+        let discovery = self
+            .http_client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .with_context(|| format!("unable to fetch OIDC discovery document from {}", url))?
+            .json::<Discovery>()
+            .await
+            .context("unable to parse OIDC discovery document")?;
+        Ok(discovery.jwks_uri)
+    }
+}
+
+fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a jsonwebtoken::jwk::Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        // Without a key id, only an unambiguous key can be used.
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+fn bearer_token(headers: &http::HeaderMap) -> Option<&str> {
+    headers.get_all(AUTHORIZATION).iter().find_map(|val| {
+        let val = val.to_str().ok()?;
+        let (scheme, token) = val.split_once(' ')?;
+        let token = token.trim();
+        (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+    })
+}
+
+fn discovery_url(issuer: &str) -> String {
+    format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator(claim_mappings: &[(&str, &str)]) -> OidcValidator {
+        OidcValidator::new(
+            &pb::gateway::Oidc {
+                issuer: "https://auth.example.com/".into(),
+                jwks_url: None,
+                audiences: vec![],
+                user_id_claim: None,
+                claim_mappings: claim_mappings
+                    .iter()
+                    .map(|(f, c)| (f.to_string(), c.to_string()))
+                    .collect(),
+                mode: pb::gateway::oidc::Mode::ReplaceHandler as i32,
+            },
+            reqwest::Client::new(),
+        )
+        .unwrap()
+    }
+
+    fn claims(json: serde_json::Value) -> Claims {
+        match json {
+            serde_json::Value::Object(map) => map,
+            _ => panic!("claims must be an object"),
+        }
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(AUTHORIZATION, "Basic dXNlcjpwYXNz".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(AUTHORIZATION, "bearer abc.def.ghi".parse().unwrap());
+        assert_eq!(bearer_token(&headers), Some("abc.def.ghi"));
+
+        headers.insert(AUTHORIZATION, "Bearer ".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+    }
+
+    #[test]
+    fn test_discovery_url() {
+        assert_eq!(
+            discovery_url("https://auth.example.com/"),
+            "https://auth.example.com/.well-known/openid-configuration"
+        );
+        assert_eq!(
+            discovery_url("https://auth.example.com/realms/app"),
+            "https://auth.example.com/realms/app/.well-known/openid-configuration"
+        );
+    }
+
+    #[test]
+    fn test_authenticated() {
+        let v = validator(&[("email", "email"), ("orgId", "org_id")]);
+        let resp = v.authenticated(claims(serde_json::json!({
+            "sub": "user-1",
+            "email": "user@example.com",
+            "org_id": "org-1",
+            "scope": "read",
+        })));
+        let AuthResponse::Authenticated {
+            auth_uid,
+            auth_data,
+        } = resp
+        else {
+            panic!("expected authenticated response");
+        };
+        assert_eq!(auth_uid, "user-1");
+        assert_eq!(
+            auth_data.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["email", "orgId"]
+        );
+
+        let resp = v.authenticated(claims(serde_json::json!({"email": "user@example.com"})));
+        assert!(matches!(resp, AuthResponse::Unauthenticated { .. }));
+    }
+
+    #[test]
+    fn test_authenticated_all_claims() {
+        let v = validator(&[]);
+        let resp = v.authenticated(claims(serde_json::json!({
+            "sub": "user-1",
+            "scope": "read",
+        })));
+        let AuthResponse::Authenticated { auth_data, .. } = resp else {
+            panic!("expected authenticated response");
+        };
+        assert_eq!(auth_data.len(), 2);
+    }
+}
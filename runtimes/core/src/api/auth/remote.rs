@@ -4,7 +4,7 @@ use crate::api::httputil::{convert_headers, join_url_path, merge_query};
 use crate::api::jsonschema::{DecodeConfig, JSONSchema};
 use crate::api::reqauth::caller::Caller;
 use crate::api::reqauth::meta::{MetaKey, MetaMap};
-use crate::api::reqauth::svcauth;
+use crate::api::reqauth::{svcauth, Propagation};
 use crate::api::{APIResult, PValues};
 use crate::{api, EndpointName};
 use anyhow::Context;
@@ -19,6 +19,7 @@ pub struct RemoteAuthHandler {
     auth_handler_url: reqwest::Url,
     http_client: reqwest::Client,
     auth_data_schema: JSONSchema,
+    propagation: Propagation,
 }
 
 impl RemoteAuthHandler {
@@ -52,6 +53,7 @@ impl RemoteAuthHandler {
             auth_handler_url,
             http_client,
             auth_data_schema,
+            propagation: reg.propagation(),
         })
     }
 
@@ -83,10 +85,13 @@ impl RemoteAuthHandler {
             caller: &caller,
             parent_span: meta.parent_span_id.map(|sp| meta.trace_id.with_span(sp)),
             parent_event_id: None,
+            ext_parent_span: meta.ext_parent_span,
+            propagation: self.propagation,
             ext_correlation_id: meta
                 .ext_correlation_id
                 .as_ref()
                 .map(|s| Cow::Borrowed(s.as_str())),
+            deadline: meta.deadline,
             auth_user_id: None,
             auth_data: None,
             svc_auth_method: self.svc_auth_method.as_ref(),
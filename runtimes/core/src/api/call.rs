@@ -10,15 +10,17 @@ use url::Url;
 
 use encore::runtime::v1 as pb;
 
+use crate::api::discovery::{DnsConfig, HealthCheckConfig, KubernetesConfig, ServiceLocation};
+use crate::api::hedging::{HedgeRequest, Hedger};
 use crate::api::reqauth::caller::Caller;
 use crate::api::reqauth::meta::MetaKey;
-use crate::api::reqauth::{service_auth_method, svcauth};
+use crate::api::reqauth::{service_auth_method, svcauth, Propagation};
 use crate::api::schema::{JSONPayload, ToOutgoingRequest};
 use crate::api::{schema, APIResult, Endpoint, EndpointMap};
 use crate::model::{SpanKey, TraceEventId};
 use crate::names::EndpointName;
 use crate::trace::Tracer;
-use crate::{api, encore, model, secrets, EncoreName, Hosted};
+use crate::{api, encore, mesh, model, secrets, EncoreName, Hosted};
 
 use super::reqauth::meta::MetaMapMut;
 use super::websocket_client::WebSocketClient;
@@ -28,11 +30,14 @@ use super::ResponsePayload;
 /// Tracks where services are located and how to call them.
 pub struct ServiceRegistry {
     endpoints: Arc<EndpointMap>,
-    base_urls: HashMap<EncoreName, String>,
+    locations: HashMap<EncoreName, Arc<ServiceLocation>>,
     http_client: reqwest::Client,
+    mesh: Option<Arc<mesh::Manager>>,
     tracer: Tracer,
     service_auth: HashMap<EncoreName, Arc<dyn svcauth::ServiceAuthMethod>>,
+    hedgers: HashMap<EncoreName, Arc<Hedger>>,
     deploy_id: String,
+    propagation: Propagation,
 }
 
 impl ServiceRegistry {
@@ -47,13 +52,55 @@ impl ServiceRegistry {
         hosted_services: &Hosted,
         deploy_id: String,
         http_client: reqwest::Client,
+        mesh: Option<Arc<mesh::Manager>>,
         tracer: Tracer,
+        propagation: Propagation,
+        runtime: &tokio::runtime::Handle,
     ) -> anyhow::Result<Self> {
-        let mut base_urls = HashMap::with_capacity(sd.services.len());
+        let mut locations = HashMap::with_capacity(sd.services.len());
         let mut service_auth = HashMap::with_capacity(sd.services.len());
+        let mut hedgers = HashMap::new();
         for (svc, mut loc) in sd.services {
             let svc = EncoreName::from(svc);
-            base_urls.insert(svc.clone(), loc.base_url);
+
+            let needs_health_checks = loc.dns.is_some()
+                || loc.kubernetes.is_some()
+                || !loc.additional_base_urls.is_empty();
+            let location = match (loc.dns, loc.kubernetes) {
+                // The base URLs are resolved at runtime.
+                (Some(_), Some(_)) => anyhow::bail!(
+                    "service {svc} cannot use both dns and kubernetes service discovery"
+                ),
+                (Some(dns), None) => {
+                    let dns = DnsConfig::from_pb(&svc, dns)?;
+                    let location = Arc::new(ServiceLocation::new(svc.clone(), vec![]));
+                    location.start_dns_resolution(dns, runtime);
+                    location
+                }
+                (None, Some(k8s)) => {
+                    let k8s = KubernetesConfig::from_pb(&svc, k8s)?;
+                    let location = Arc::new(ServiceLocation::new(svc.clone(), vec![]));
+                    location.start_kubernetes_watch(k8s, runtime);
+                    location
+                }
+                (None, None) => {
+                    let mut base_urls = vec![loc.base_url];
+                    base_urls.append(&mut loc.additional_base_urls);
+                    Arc::new(ServiceLocation::new(svc.clone(), base_urls))
+                }
+            };
+            if needs_health_checks {
+                let health_check = loc
+                    .health_check
+                    .map(HealthCheckConfig::from)
+                    .unwrap_or_default();
+                location.start_health_checks(health_check, &http_client, runtime);
+            }
+            locations.insert(svc.clone(), location);
+
+            if let Some(hedging) = loc.hedging {
+                hedgers.insert(svc.clone(), Arc::new(Hedger::new(hedging)));
+            }
 
             let auth_method = if loc.auth_methods.is_empty() {
                 Arc::new(svcauth::Noop)
@@ -67,9 +114,10 @@ impl ServiceRegistry {
         if let Some(own_address) = own_address {
             let own_address = format!("http://{own_address}");
             for svc_name in hosted_services.iter() {
-                if !base_urls.contains_key(svc_name) {
+                if !locations.contains_key(svc_name) {
                     let svc = EncoreName::from(svc_name);
-                    base_urls.insert(svc.clone(), own_address.clone());
+                    let location = ServiceLocation::new(svc.clone(), vec![own_address.clone()]);
+                    locations.insert(svc.clone(), Arc::new(location));
 
                     let auth_method = if own_auth_methods.is_empty() {
                         Arc::new(svcauth::Noop)
@@ -88,24 +136,60 @@ impl ServiceRegistry {
 
         Ok(Self {
             endpoints,
-            base_urls,
+            locations,
             http_client,
+            mesh,
             tracer,
             service_auth,
+            hedgers,
             deploy_id,
+            propagation,
         })
     }
 
+    /// The client for calls to other services, which presents
+    /// the service mesh identity if one is configured.
+    fn http_client(&self) -> impl Future<Output = APIResult<reqwest::Client>> + 'static {
+        let mesh = self.mesh.clone();
+        let http_client = self.http_client.clone();
+        async move {
+            match mesh {
+                Some(mesh) => mesh.http_client().await.map_err(api::Error::internal),
+                None => Ok(http_client),
+            }
+        }
+    }
+
     pub fn endpoints(&self) -> &EndpointMap {
         self.endpoints.as_ref()
     }
 
-    pub fn service_base_url<Q>(&self, service_name: &Q) -> Option<&String>
+    /// The trace context formats to exchange with non-Encore services.
+    pub fn propagation(&self) -> Propagation {
+        self.propagation
+    }
+
+    pub fn service_base_url<Q>(&self, service_name: &Q) -> Option<String>
     where
         EncoreName: Borrow<Q>,
         Q: Eq + std::hash::Hash + ?Sized,
     {
-        self.base_urls.get(service_name)
+        self.locations
+            .get(service_name)
+            .and_then(|loc| loc.base_url())
+    }
+
+    /// Replaces the base URLs of a service, for example when the
+    /// service discovery configuration is reloaded.
+    /// Returns false if the service is not known.
+    pub fn set_service_base_urls(&self, service_name: &str, base_urls: Vec<String>) -> bool {
+        match self.locations.get(service_name) {
+            Some(loc) => {
+                loc.set_base_urls(base_urls);
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn service_auth_method<Q>(
@@ -182,13 +266,23 @@ impl ServiceRegistry {
         start_event_id: Option<TraceEventId>,
         opts: Option<&api::CallOpts>,
     ) -> impl Future<Output = APIResult<ResponsePayload>> + 'static {
-        let http_client = self.http_client.clone();
+        let http_client = self.http_client();
         let req = self.prepare_api_call_request(target, data, source, start_event_id, opts);
+        let hedge = match &req {
+            Ok((req, _, base_url)) => self.prepare_hedge(target, req, base_url),
+            Err(_) => None,
+        };
         async move {
             match req {
-                Ok((req, resp_schema)) => {
-                    let fut = http_client.execute(req);
-                    match fut.await {
+                Ok((req, resp_schema, _)) => {
+                    let http_client = http_client.await?;
+                    let resp = match hedge {
+                        Some((hedger, hedge_req)) => {
+                            hedger.execute(&http_client, req, hedge_req).await
+                        }
+                        None => http_client.execute(req).await,
+                    };
+                    match resp {
                         Ok(resp) => {
                             if !resp.status().is_success() {
                                 return Err(extract_error(resp).await);
@@ -210,10 +304,9 @@ impl ServiceRegistry {
         source: Option<&model::Request>,
         start_event_id: Option<TraceEventId>,
         opts: Option<&api::CallOpts>,
-    ) -> APIResult<(reqwest::Request, Arc<schema::Response>)> {
+    ) -> APIResult<(reqwest::Request, Arc<schema::Response>, String)> {
         let base_url = self
-            .base_urls
-            .get(target.service())
+            .service_base_url(target.service())
             .ok_or_else(|| api::Error {
                 code: api::ErrCode::NotFound,
                 message: "service not found".into(),
@@ -291,7 +384,43 @@ impl ServiceRegistry {
 
         let resp_schema = endpoint.response.clone();
 
-        Ok((req, resp_schema))
+        Ok((req, resp_schema, base_url))
+    }
+
+    /// Prepares a hedged copy of the request, for services with a hedging policy.
+    ///
+    /// The hedged request is sent to the service's next base URL, which is only
+    /// picked when the hedge is dispatched, so calls that complete within the
+    /// hedging delay don't skew the spread of calls across base URLs.
+    fn prepare_hedge(
+        &self,
+        target: &EndpointName,
+        req: &reqwest::Request,
+        base_url: &str,
+    ) -> Option<(Arc<Hedger>, HedgeRequest)> {
+        let hedger = self.hedgers.get(target.service())?;
+        if req.method() != reqwest::Method::GET {
+            return None;
+        }
+
+        let mut hedge = req.try_clone()?;
+        let location = self.locations.get(target.service())?.clone();
+        let base_url = base_url.to_string();
+        let build = move || {
+            if let Some(next_base_url) = location.base_url() {
+                let rebased = hedge
+                    .url()
+                    .as_str()
+                    .strip_prefix(&base_url)
+                    .and_then(|rest| Url::parse(&format!("{next_base_url}{rest}")).ok());
+                if let Some(url) = rebased {
+                    *hedge.url_mut() = url;
+                }
+            }
+            hedge
+        };
+
+        Some((hedger.clone(), Box::new(build)))
     }
 
     fn do_connect_stream(
@@ -327,8 +456,7 @@ impl ServiceRegistry {
         Arc<schema::Response>,
     )> {
         let base_url = self
-            .base_urls
-            .get(target.service())
+            .service_base_url(target.service())
             .ok_or_else(|| api::Error {
                 code: api::ErrCode::NotFound,
                 message: "service not found".into(),
@@ -477,11 +605,14 @@ impl ServiceRegistry {
             svc_auth_method: svc_auth_method.as_ref(),
             parent_span: source.map(|r| r.span),
             parent_event_id,
+            ext_parent_span: false,
+            propagation: self.propagation,
             ext_correlation_id: source.and_then(|r| {
                 r.ext_correlation_id
                     .as_ref()
                     .map(|id| Cow::Borrowed(id.as_str()))
             }),
+            deadline: source.and_then(|r| r.deadline),
             auth_user_id,
             auth_data,
         };
@@ -497,7 +628,11 @@ pub struct CallDesc<'a, AuthData> {
 
     pub parent_span: Option<SpanKey>,
     pub parent_event_id: Option<TraceEventId>,
+    /// Whether parent_span was received from a non-Encore service.
+    pub ext_parent_span: bool,
+    pub propagation: Propagation,
     pub ext_correlation_id: Option<Cow<'a, str>>,
+    pub deadline: Option<SystemTime>,
 
     pub auth_user_id: Option<Cow<'a, str>>,
     pub auth_data: Option<AuthData>,
@@ -528,7 +663,16 @@ where
                 trace_state.push_str(",encore/event-id=");
                 trace_state.push_str(event_id.to_string().as_str());
             }
+            if self.ext_parent_span {
+                trace_state.push_str(",encore/ext-parent=1");
+            }
             headers.set(MetaKey::TraceState, trace_state)?;
+
+            if self.propagation.b3 {
+                headers.set(MetaKey::B3TraceId, span.0.serialize_std())?;
+                headers.set(MetaKey::B3SpanId, span.1.serialize_std())?;
+                headers.set(MetaKey::B3Sampled, "1".to_string())?;
+            }
         }
 
         // TODO handle GCP span propagation with tracestate key.
@@ -538,6 +682,12 @@ where
             headers.set(MetaKey::XCorrelationId, corr_id.into_owned())?;
         }
 
+        if let Some(deadline) = self.deadline {
+            let deadline = chrono::DateTime::<chrono::Utc>::from(deadline)
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+            headers.set(MetaKey::Deadline, deadline)?;
+        }
+
         // Add auth data.
         if let Some(auth_uid) = self.auth_user_id {
             headers.set(MetaKey::UserId, auth_uid.into_owned())?;
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::api::{self, ErrCode};
+use crate::encore::runtime::v1 as pb;
+use crate::{EncoreName, EndpointName};
+
+/// Limits the number of requests processed concurrently,
+/// queueing a bounded number of requests and shedding the rest.
+#[derive(Debug)]
+pub struct Limiter {
+    semaphore: Arc<Semaphore>,
+    max_queued: usize,
+    queued: AtomicUsize,
+    queue_timeout: Option<Duration>,
+}
+
+impl Limiter {
+    /// Returns a limiter for the given config, or None if it doesn't limit anything.
+    pub fn new(cfg: &pb::ConcurrencyLimit) -> Option<Self> {
+        if cfg.max_in_flight == 0 {
+            return None;
+        }
+        Some(Self {
+            semaphore: Arc::new(Semaphore::new(cfg.max_in_flight as usize)),
+            max_queued: cfg.max_queued as usize,
+            queued: AtomicUsize::new(0),
+            queue_timeout: cfg
+                .queue_timeout
+                .and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero()),
+        })
+    }
+
+    /// Acquires a permit to process a request, waiting in the queue if necessary.
+    /// Reports a ResourceExhausted error if the queue is full or the wait times out.
+    pub async fn acquire(&self) -> api::APIResult<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        // The slot is released when the guard is dropped, which also happens
+        // if this future is dropped while queued (e.g. on client disconnect).
+        let slot = QueueSlot::new(&self.queued);
+        if slot.position >= self.max_queued {
+            return Err(overloaded("too many concurrent requests"));
+        }
+
+        let acquire = self.semaphore.clone().acquire_owned();
+        let result = match self.queue_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, acquire).await.ok(),
+            None => Some(acquire.await),
+        };
+        drop(slot);
+
+        match result {
+            Some(Ok(permit)) => Ok(permit),
+            Some(Err(_)) => Err(overloaded("concurrency limiter closed")),
+            None => Err(overloaded("timed out waiting for capacity")),
+        }
+    }
+}
+
+/// A slot in a limiter's queue, released on drop.
+struct QueueSlot<'a> {
+    queued: &'a AtomicUsize,
+    /// The number of requests that were queued before this one.
+    position: usize,
+}
+
+impl<'a> QueueSlot<'a> {
+    fn new(queued: &'a AtomicUsize) -> Self {
+        let position = queued.fetch_add(1, Ordering::AcqRel);
+        Self { queued, position }
+    }
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn overloaded(internal_message: &str) -> api::Error {
+    api::Error {
+        code: ErrCode::ResourceExhausted,
+        message: "the service is overloaded, try again later".to_string(),
+        internal_message: Some(internal_message.to_string()),
+        stack: None,
+        details: None,
+    }
+}
+
+/// The concurrency limiters that apply to a single endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointLimiter {
+    endpoint: Option<Arc<Limiter>>,
+    service: Option<Arc<Limiter>>,
+}
+
+/// Holds the permits for a request while it is being processed.
+pub struct Permits {
+    _endpoint: Option<OwnedSemaphorePermit>,
+    _service: Option<OwnedSemaphorePermit>,
+}
+
+impl EndpointLimiter {
+    pub async fn acquire(&self) -> api::APIResult<Permits> {
+        // Acquire the endpoint permit first, so requests queued for a saturated
+        // endpoint don't hold service-wide capacity while waiting.
+        let endpoint = match &self.endpoint {
+            Some(l) => Some(l.acquire().await?),
+            None => None,
+        };
+        let service = match &self.service {
+            Some(l) => Some(l.acquire().await?),
+            None => None,
+        };
+        Ok(Permits {
+            _endpoint: endpoint,
+            _service: service,
+        })
+    }
+}
+
+/// The concurrency limits for all hosted services.
+#[derive(Debug, Default)]
+pub struct Limits {
+    services: HashMap<EncoreName, Arc<Limiter>>,
+    endpoints: HashMap<EndpointName, Arc<Limiter>>,
+}
+
+impl Limits {
+    pub fn new(hosted_services: &[pb::HostedService]) -> Self {
+        let mut limits = Self::default();
+        for svc in hosted_services {
+            if let Some(l) = svc.concurrency_limit.as_ref().and_then(Limiter::new) {
+                limits
+                    .services
+                    .insert(EncoreName::from(&svc.name), Arc::new(l));
+            }
+            for (endpoint, cfg) in &svc.endpoint_concurrency_limits {
+                if let Some(l) = Limiter::new(cfg) {
+                    limits.endpoints.insert(
+                        EndpointName::new(svc.name.as_str(), endpoint.as_str()),
+                        Arc::new(l),
+                    );
+                }
+            }
+        }
+        limits
+    }
+
+    pub fn for_endpoint(&self, name: &EndpointName) -> EndpointLimiter {
+        EndpointLimiter {
+            endpoint: self.endpoints.get(name).cloned(),
+            service: self.services.get(name.service()).cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_in_flight: u32, max_queued: u32, timeout_ms: Option<i32>) -> Limiter {
+        Limiter::new(&pb::ConcurrencyLimit {
+            max_in_flight,
+            max_queued,
+            queue_timeout: timeout_ms.map(|ms| prost_types::Duration {
+                seconds: 0,
+                nanos: ms * 1_000_000,
+            }),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn zero_is_unlimited() {
+        assert!(Limiter::new(&pb::ConcurrencyLimit::default()).is_none());
+    }
+
+    #[tokio::test]
+    async fn sheds_without_queue() {
+        let l = limiter(1, 0, None);
+        let permit = l.acquire().await.unwrap();
+        let err = l.acquire().await.unwrap_err();
+        assert_eq!(err.code, ErrCode::ResourceExhausted);
+
+        drop(permit);
+        assert!(l.acquire().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn queues_until_capacity() {
+        let l = Arc::new(limiter(1, 1, None));
+        let permit = l.acquire().await.unwrap();
+
+        let queued = tokio::spawn({
+            let l = l.clone();
+            async move { l.acquire().await.map(|_| ()) }
+        });
+        while l.queued.load(Ordering::Acquire) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // The queue is full.
+        assert!(l.acquire().await.is_err());
+
+        drop(permit);
+        assert!(queued.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn dropped_waiter_frees_slot() {
+        let l = Arc::new(limiter(1, 1, None));
+        let permit = l.acquire().await.unwrap();
+
+        let queued = tokio::spawn({
+            let l = l.clone();
+            async move { l.acquire().await.map(|_| ()) }
+        });
+        while l.queued.load(Ordering::Acquire) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // Dropping the queued future, as on client disconnect, frees its slot.
+        queued.abort();
+        assert!(queued.await.unwrap_err().is_cancelled());
+        assert_eq!(l.queued.load(Ordering::Acquire), 0);
+
+        let queued = tokio::spawn({
+            let l = l.clone();
+            async move { l.acquire().await.map(|_| ()) }
+        });
+        while l.queued.load(Ordering::Acquire) == 0 {
+            tokio::task::yield_now().await;
+        }
+        drop(permit);
+        assert!(queued.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn queue_timeout() {
+        let l = limiter(1, 1, Some(10));
+        let _permit = l.acquire().await.unwrap();
+        let err = l.acquire().await.unwrap_err();
+        assert_eq!(
+            err.internal_message.as_deref(),
+            Some("timed out waiting for capacity")
+        );
+    }
+}
@@ -8,8 +8,11 @@ use std::collections::HashSet;
 use std::str::FromStr;
 
 use self::cors_headers_config::{ensure_usable_cors_rules, CorsHeadersConfig};
+use self::origins_source::DynamicOrigins;
+pub use self::origins_source::SourceLoader;
 
 pub mod cors_headers_config;
+mod origins_source;
 
 #[cfg(test)]
 mod tests;
@@ -34,7 +37,15 @@ pub const ALWAYS_EXPOSED_HEADERS: [HeaderName; 3] = [
     HeaderName::from_static("x-encore-trace-id"),
 ];
 
-pub fn config(cfg: &pb::gateway::Cors, meta: MetaHeaders) -> anyhow::Result<CorsHeadersConfig> {
+/// Computes the CORS configuration for a gateway.
+///
+/// The loader is used for allowed origins loaded from external sources,
+/// and is required if the config references any.
+pub fn config(
+    cfg: &pb::gateway::Cors,
+    meta: MetaHeaders,
+    loader: Option<&SourceLoader>,
+) -> anyhow::Result<CorsHeadersConfig> {
     let allow_any_headers = cfg.extra_allowed_headers.iter().any(|val| val == "*");
 
     let allow_headers = if allow_any_headers {
@@ -66,23 +77,32 @@ pub fn config(cfg: &pb::gateway::Cors, meta: MetaHeaders) -> anyhow::Result<Cors
     // Compute the allowed origins.
     let allow_origin = {
         use pb::gateway::cors::AllowedOriginsWithCredentials;
+        let load = |list: &CorsAllowedOrigins| -> anyhow::Result<AllowedOrigins> {
+            let dynamic = match (&list.source, loader) {
+                (Some(src), Some(loader)) => Some(loader.load(src)?),
+                (Some(_), None) => anyhow::bail!("CORS origins sources are not supported"),
+                (None, _) => None,
+            };
+            Ok(AllowedOrigins {
+                fixed: OriginSet::new(list.allowed_origins.clone()),
+                dynamic,
+            })
+        };
+
         let with_creds = match &cfg.allowed_origins_with_credentials {
             Some(AllowedOriginsWithCredentials::UnsafeAllowAllOriginsWithCredentials(true)) => {
-                OriginSet::All
+                AllowedOrigins::from(OriginSet::All)
             }
             Some(AllowedOriginsWithCredentials::AllowedOrigins(list)) => {
-                OriginSet::new(list.allowed_origins.clone())
+                load(list).context("failed to load allowed origins with credentials")?
             }
-            _ => OriginSet::Some(vec![]),
+            _ => AllowedOrigins::from(OriginSet::Some(vec![])),
         };
-        let without_creds = {
-            if let Some(CorsAllowedOrigins { allowed_origins }) =
-                &cfg.allowed_origins_without_credentials
-            {
-                OriginSet::new(allowed_origins.to_vec())
-            } else {
-                OriginSet::All
+        let without_creds = match &cfg.allowed_origins_without_credentials {
+            Some(list) => {
+                load(list).context("failed to load allowed origins without credentials")?
             }
+            None => AllowedOrigins::from(OriginSet::All),
         };
 
         let request_has_creds = |req: &axum::http::request::Parts| -> bool {
@@ -134,6 +154,28 @@ pub fn config(cfg: &pb::gateway::Cors, meta: MetaHeaders) -> anyhow::Result<Cors
     Ok(config)
 }
 
+/// A static set of allowed origins, optionally extended
+/// by origins loaded from an external source.
+struct AllowedOrigins {
+    fixed: OriginSet,
+    dynamic: Option<DynamicOrigins>,
+}
+
+impl From<OriginSet> for AllowedOrigins {
+    fn from(fixed: OriginSet) -> Self {
+        Self {
+            fixed,
+            dynamic: None,
+        }
+    }
+}
+
+impl AllowedOrigins {
+    fn allows(&self, origin: &str) -> bool {
+        self.fixed.allows(origin) || self.dynamic.as_ref().is_some_and(|d| d.allows(origin))
+    }
+}
+
 enum OriginSet {
     All,
     Some(Vec<Origin>),
@@ -0,0 +1,255 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::encore::runtime::v1 as pb;
+
+use super::OriginSet;
+
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Loads allowed origins from external sources referenced in the CORS config,
+/// and keeps them up to date in the background.
+#[derive(Clone)]
+pub struct SourceLoader {
+    http_client: reqwest::Client,
+    runtime: tokio::runtime::Handle,
+}
+
+impl SourceLoader {
+    pub fn new(http_client: reqwest::Client, runtime: tokio::runtime::Handle) -> Self {
+        Self {
+            http_client,
+            runtime,
+        }
+    }
+
+    /// Starts loading origins from the given source.
+    ///
+    /// Files are read once up front so their origins apply from the first request,
+    /// while URLs are fetched in the background. Until a URL has been fetched
+    /// only the static origins apply.
+    pub(super) fn load(
+        &self,
+        cfg: &pb::gateway::CorsOriginsSource,
+    ) -> anyhow::Result<DynamicOrigins> {
+        use pb::gateway::cors_origins_source::Location as PbLocation;
+        let location = match &cfg.location {
+            Some(PbLocation::File(path)) => Location::File(PathBuf::from(path)),
+            Some(PbLocation::Url(url)) => {
+                reqwest::Url::parse(url)
+                    .with_context(|| format!("invalid CORS origins source url {url:?}"))?;
+                Location::Url(url.clone())
+            }
+            None => anyhow::bail!("CORS origins source requires a file or url"),
+        };
+        let refresh_interval = cfg
+            .refresh_interval
+            .and_then(|d| Duration::try_from(d).ok())
+            .filter(|d| !d.is_zero())
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL)
+            .max(MIN_REFRESH_INTERVAL);
+
+        let source = Source {
+            location,
+            refresh_interval,
+            http_client: self.http_client.clone(),
+        };
+        let origins = DynamicOrigins::default();
+
+        let mut last = None;
+        if let Location::File(path) = &source.location {
+            match read_file(path) {
+                Ok(list) => {
+                    origins.set(list.clone());
+                    last = Some(list);
+                }
+                Err(err) => log::warn!("cors: unable to load allowed origins: {:#}", err),
+            }
+        }
+
+        self.runtime
+            .spawn(refresh_loop(origins.clone(), source, last));
+        Ok(origins)
+    }
+}
+
+/// A set of origins loaded from an external source.
+#[derive(Clone)]
+pub(super) struct DynamicOrigins(Arc<RwLock<OriginSet>>);
+
+impl Default for DynamicOrigins {
+    fn default() -> Self {
+        Self(Arc::new(RwLock::new(OriginSet::Some(vec![]))))
+    }
+}
+
+impl DynamicOrigins {
+    pub(super) fn allows(&self, origin: &str) -> bool {
+        self.0.read().unwrap().allows(origin)
+    }
+
+    fn set(&self, origins: Vec<String>) {
+        *self.0.write().unwrap() = OriginSet::new(origins);
+    }
+}
+
+enum Location {
+    File(PathBuf),
+    Url(String),
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Url(url) => write!(f, "url {url}"),
+        }
+    }
+}
+
+struct Source {
+    location: Location,
+    refresh_interval: Duration,
+    http_client: reqwest::Client,
+}
+
+impl Source {
+    async fn fetch(&self) -> anyhow::Result<Vec<String>> {
+        let data = match &self.location {
+            Location::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("unable to read {}", path.display()))?,
+            Location::Url(url) => self
+                .http_client
+                .get(url)
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status())
+                .with_context(|| format!("unable to fetch {url}"))?
+                .text()
+                .await
+                .with_context(|| format!("unable to read response from {url}"))?,
+        };
+        parse(&data)
+    }
+}
+
+/// Periodically reloads the origins from the source.
+/// If loading fails the last known set of origins is kept.
+async fn refresh_loop(origins: DynamicOrigins, source: Source, mut last: Option<Vec<String>>) {
+    // Sources that were already loaded up front wait for the first refresh.
+    if last.is_some() {
+        tokio::time::sleep(source.refresh_interval).await;
+    }
+
+    loop {
+        match source.fetch().await {
+            Ok(list) => {
+                if last.as_ref() != Some(&list) {
+                    log::info!(
+                        "cors: loaded {} allowed origins from {}",
+                        list.len(),
+                        source.location
+                    );
+                    origins.set(list.clone());
+                    last = Some(list);
+                }
+            }
+            Err(err) => {
+                log::warn!(
+                    "cors: unable to refresh allowed origins from {}, keeping previous origins: {:#}",
+                    source.location,
+                    err
+                );
+            }
+        }
+        tokio::time::sleep(source.refresh_interval).await;
+    }
+}
+
+fn read_file(path: &std::path::Path) -> anyhow::Result<Vec<String>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read {}", path.display()))?;
+    parse(&data)
+}
+
+/// Parses a list of origins, either as a JSON array of strings
+/// or as one origin per line, ignoring blank lines and '#' comments.
+fn parse(data: &str) -> anyhow::Result<Vec<String>> {
+    let data = data.trim();
+    if data.starts_with('[') {
+        return serde_json::from_str(data).context("invalid JSON list of origins");
+    }
+    Ok(data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(r#" ["https://a.com", "https://*.b.com"] "#).unwrap(),
+            ["https://a.com", "https://*.b.com"]
+        );
+        assert_eq!(
+            parse("# customers\nhttps://a.com\n\n  https://b.com  \n").unwrap(),
+            ["https://a.com", "https://b.com"]
+        );
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("[1, 2]").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_refresh() {
+        let path = std::env::temp_dir().join(format!("encore-cors-{}.txt", xid::new()));
+        std::fs::write(&path, "https://a.com\n").unwrap();
+
+        let loader = SourceLoader::new(reqwest::Client::new(), tokio::runtime::Handle::current());
+        let origins = loader
+            .load(&pb::gateway::CorsOriginsSource {
+                location: Some(pb::gateway::cors_origins_source::Location::File(
+                    path.to_string_lossy().into_owned(),
+                )),
+                refresh_interval: Some(prost_types::Duration {
+                    seconds: 1,
+                    nanos: 0,
+                }),
+            })
+            .unwrap();
+        // The file is read up front.
+        assert!(origins.allows("https://a.com"));
+        assert!(!origins.allows("https://b.com"));
+
+        std::fs::write(&path, r#"["https://b.com"]"#).unwrap();
+        let wait_for = |want: bool| {
+            let origins = origins.clone();
+            async move {
+                for _ in 0..50 {
+                    if origins.allows("https://b.com") == want {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                panic!("origins were not refreshed");
+            }
+        };
+        wait_for(true).await;
+        assert!(!origins.allows("https://a.com"));
+
+        // A failed refresh keeps the previous origins.
+        std::fs::remove_file(&path).unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(origins.allows("https://b.com"));
+    }
+}
@@ -104,7 +104,7 @@ fn run_test_case(test_case: TestCase) {
         expose_headers: [HeaderName::from_static("x-exposed-test")].into(),
     };
 
-    let cors = config(&test_case.cors_cfg, meta).expect("run cors config");
+    let cors = config(&test_case.cors_cfg, meta, None).expect("run cors config");
 
     check_origins(&cors, true, true, test_case.creds_good_origins);
     check_origins(&cors, true, false, test_case.creds_bad_origins);
@@ -165,6 +165,7 @@ fn test_allowed_creds() {
                 pb::gateway::cors::AllowedOriginsWithCredentials::AllowedOrigins(
                     pb::gateway::CorsAllowedOrigins {
                         allowed_origins: vec![String::from("localhost"), String::from("ok.org")],
+                        source: None,
                     },
                 ),
             ),
@@ -208,6 +209,7 @@ fn test_allowed_glob_creds() {
                             String::from("wss://ok1-*.example.com"),
                             String::from("https://*-ok2.example.com"),
                         ],
+                        source: None,
                     },
                 ),
             ),
@@ -244,6 +246,7 @@ fn test_allowed_nocreds() {
             allowed_origins_with_credentials: None,
             allowed_origins_without_credentials: Some(pb::gateway::CorsAllowedOrigins {
                 allowed_origins: vec![String::from("localhost"), String::from("ok.org")],
+                source: None,
             }),
             extra_allowed_headers: vec![],
             extra_exposed_headers: vec![],
@@ -280,11 +283,13 @@ fn test_allowed_disjoint_sets() {
                 pb::gateway::cors::AllowedOriginsWithCredentials::AllowedOrigins(
                     pb::gateway::CorsAllowedOrigins {
                         allowed_origins: vec![String::from("foo.com")],
+                        source: None,
                     },
                 ),
             ),
             allowed_origins_without_credentials: Some(pb::gateway::CorsAllowedOrigins {
                 allowed_origins: vec![String::from("bar.org")],
+                source: None,
             }),
             extra_allowed_headers: vec![],
             extra_exposed_headers: vec![],
@@ -316,6 +321,7 @@ fn test_allowed_wildcard_without_creds() {
             allowed_origins_with_credentials: None,
             allowed_origins_without_credentials: Some(pb::gateway::CorsAllowedOrigins {
                 allowed_origins: vec![String::from("*")],
+                source: None,
             }),
             extra_allowed_headers: vec![],
             extra_exposed_headers: vec![],
@@ -471,11 +477,13 @@ fn test_wildcard_without_creds() {
                 pb::gateway::cors::AllowedOriginsWithCredentials::AllowedOrigins(
                     pb::gateway::CorsAllowedOrigins {
                         allowed_origins: vec![String::from("https://vercel.app")],
+                        source: None,
                     },
                 ),
             ),
             allowed_origins_without_credentials: Some(pb::gateway::CorsAllowedOrigins {
                 allowed_origins: vec![String::from("https://*-foo.vercel.app")],
+                source: None,
             }),
             extra_allowed_headers: vec![],
             extra_exposed_headers: vec![],
@@ -0,0 +1,138 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hickory_resolver::TokioAsyncResolver;
+
+use crate::encore::runtime::v1 as pb;
+use crate::EncoreName;
+
+use super::ServiceLocation;
+
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Describes how to resolve a service's base URLs from DNS.
+#[derive(Debug, Clone)]
+pub struct DnsConfig {
+    pub name: String,
+    pub record_type: RecordType,
+    pub scheme: String,
+    pub port: Option<u16>,
+    pub refresh_interval: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    /// SRV records, which provide both the target host and port.
+    Srv,
+    /// A and AAAA records, as served for headless Kubernetes services.
+    Address,
+}
+
+impl DnsConfig {
+    pub fn from_pb(
+        svc: &EncoreName,
+        cfg: pb::service_discovery::DnsDiscovery,
+    ) -> anyhow::Result<Self> {
+        use pb::service_discovery::dns_discovery::RecordType as PbRecordType;
+        let record_type = match cfg.record_type() {
+            PbRecordType::Srv => RecordType::Srv,
+            PbRecordType::Address | PbRecordType::Unspecified => RecordType::Address,
+        };
+
+        let port = cfg
+            .port
+            .map(u16::try_from)
+            .transpose()
+            .map_err(|_| anyhow::anyhow!("invalid dns discovery port for service {svc}"))?;
+        if record_type == RecordType::Address && port.is_none() {
+            anyhow::bail!("dns discovery for service {svc} requires a port for address records");
+        }
+
+        Ok(Self {
+            name: cfg.name,
+            record_type,
+            scheme: cfg.scheme.unwrap_or_else(|| "http".to_string()),
+            port,
+            refresh_interval: super::non_zero_duration(cfg.refresh_interval)
+                .unwrap_or(DEFAULT_REFRESH_INTERVAL),
+        })
+    }
+
+    fn base_url(&self, host: &str, port: u16) -> String {
+        format!("{}://{}:{}", self.scheme, host, port)
+    }
+}
+
+/// Periodically resolves the base URLs for a service and updates its location.
+/// Records are re-resolved when their TTL expires, but at least every refresh interval.
+/// If resolution fails the last known set of base URLs is kept.
+pub(super) async fn resolve_loop(loc: Arc<ServiceLocation>, cfg: DnsConfig) {
+    let resolver = match TokioAsyncResolver::tokio_from_system_conf() {
+        Ok(resolver) => resolver,
+        Err(err) => {
+            log::error!(service = loc.svc.as_ref(); "unable to create dns resolver, disabling dns service discovery: {}", err);
+            return;
+        }
+    };
+
+    loop {
+        let wait = match resolve(&resolver, &cfg).await {
+            Ok((base_urls, valid_until)) => {
+                if base_urls.is_empty() {
+                    log::warn!(service = loc.svc.as_ref(), name = cfg.name.as_str(); "dns service discovery returned no records");
+                } else {
+                    loc.set_base_urls(base_urls);
+                }
+                valid_until.saturating_duration_since(Instant::now()).clamp(
+                    MIN_REFRESH_INTERVAL,
+                    cfg.refresh_interval.max(MIN_REFRESH_INTERVAL),
+                )
+            }
+            Err(err) => {
+                log::warn!(service = loc.svc.as_ref(), name = cfg.name.as_str(); "dns service discovery failed: {}", err);
+                RETRY_INTERVAL.min(cfg.refresh_interval)
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+async fn resolve(
+    resolver: &TokioAsyncResolver,
+    cfg: &DnsConfig,
+) -> anyhow::Result<(Vec<String>, Instant)> {
+    let (mut base_urls, valid_until) = match cfg.record_type {
+        RecordType::Srv => {
+            let lookup = resolver.srv_lookup(cfg.name.as_str()).await?;
+            let urls = lookup
+                .iter()
+                .map(|srv| {
+                    let target = srv.target().to_utf8();
+                    let host = target.trim_end_matches('.');
+                    cfg.base_url(host, cfg.port.unwrap_or(srv.port()))
+                })
+                .collect::<Vec<_>>();
+            (urls, lookup.as_lookup().valid_until())
+        }
+        RecordType::Address => {
+            let lookup = resolver.lookup_ip(cfg.name.as_str()).await?;
+            let port = cfg.port.unwrap_or_default();
+            let urls = lookup
+                .iter()
+                .map(|ip| match ip {
+                    IpAddr::V4(ip) => cfg.base_url(&ip.to_string(), port),
+                    IpAddr::V6(ip) => cfg.base_url(&format!("[{ip}]"), port),
+                })
+                .collect::<Vec<_>>();
+            (urls, lookup.valid_until())
+        }
+    };
+
+    // Keep a stable order so that unchanged record sets aren't treated as changes.
+    base_urls.sort();
+    base_urls.dedup();
+    Ok((base_urls, valid_until))
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::encore::runtime::v1 as pb;
+use crate::EncoreName;
+
+use super::ServiceLocation;
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+const SERVICE_NAME_LABEL: &str = "kubernetes.io/service-name";
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+const WATCH_TIMEOUT_SECS: u32 = 300;
+
+/// Describes which Kubernetes EndpointSlices to watch for a service's base URLs.
+#[derive(Debug, Clone)]
+pub struct KubernetesConfig {
+    pub namespace: Option<String>,
+    pub label_selector: String,
+    pub port: PortSelector,
+    pub scheme: String,
+    pub api_server: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortSelector {
+    /// Use the EndpointSlice port with the given name.
+    Named(String),
+    /// Use the given port number, regardless of the EndpointSlice ports.
+    Number(u16),
+    /// Use the first port of the EndpointSlice.
+    First,
+}
+
+impl KubernetesConfig {
+    pub fn from_pb(
+        svc: &EncoreName,
+        cfg: pb::service_discovery::KubernetesDiscovery,
+    ) -> anyhow::Result<Self> {
+        let label_selector = match (cfg.service_name, cfg.label_selector) {
+            (Some(name), None) => format!("{SERVICE_NAME_LABEL}={name}"),
+            (None, Some(selector)) => selector,
+            (Some(name), Some(selector)) => format!("{SERVICE_NAME_LABEL}={name},{selector}"),
+            (None, None) => anyhow::bail!(
+                "kubernetes discovery for service {svc} requires a service name or label selector"
+            ),
+        };
+
+        let port = match (cfg.port_name, cfg.port) {
+            (Some(name), _) => PortSelector::Named(name),
+            (None, Some(port)) => {
+                PortSelector::Number(u16::try_from(port).with_context(|| {
+                    format!("invalid kubernetes discovery port for service {svc}")
+                })?)
+            }
+            (None, None) => PortSelector::First,
+        };
+
+        Ok(Self {
+            namespace: cfg.namespace,
+            label_selector,
+            port,
+            scheme: cfg.scheme.unwrap_or_else(|| "http".to_string()),
+            api_server: cfg.api_server,
+        })
+    }
+}
+
+/// Watches the EndpointSlices matching the config and keeps the service location up to date.
+/// If the watch fails the last known set of base URLs is kept while it's re-established.
+pub(super) async fn watch_loop(loc: Arc<ServiceLocation>, cfg: KubernetesConfig) {
+    let client = match ApiClient::in_cluster(&cfg) {
+        Ok(client) => client,
+        Err(err) => {
+            log::error!(service = loc.svc.as_ref(); "unable to create kubernetes client, disabling kubernetes service discovery: {:?}", err);
+            return;
+        }
+    };
+
+    loop {
+        if let Err(err) = watch(&client, &loc, &cfg).await {
+            log::warn!(service = loc.svc.as_ref(); "kubernetes service discovery failed: {:?}", err);
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+}
+
+/// Lists the matching EndpointSlices and then watches for changes until the watch ends.
+async fn watch(
+    client: &ApiClient,
+    loc: &ServiceLocation,
+    cfg: &KubernetesConfig,
+) -> anyhow::Result<()> {
+    let list: EndpointSliceList = client
+        .get(&[("labelSelector", cfg.label_selector.as_str())])
+        .await?
+        .json()
+        .await
+        .context("unable to parse endpoint slices")?;
+
+    let mut slices: HashMap<String, EndpointSlice> = list
+        .items
+        .into_iter()
+        .map(|slice| (slice.metadata.name.clone(), slice))
+        .collect();
+    loc.set_base_urls(base_urls(slices.values(), cfg));
+
+    let timeout = WATCH_TIMEOUT_SECS.to_string();
+    let resp = client
+        .get(&[
+            ("labelSelector", cfg.label_selector.as_str()),
+            ("watch", "true"),
+            ("allowWatchBookmarks", "true"),
+            ("resourceVersion", list.metadata.resource_version.as_str()),
+            ("timeoutSeconds", timeout.as_str()),
+        ])
+        .await?;
+
+    // Watch events are sent as newline-delimited JSON.
+    let mut stream = resp.bytes_stream();
+    let mut buf: Vec<u8> = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        buf.extend_from_slice(&chunk.context("unable to read watch event")?);
+        while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=pos).collect();
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            let event: WatchEvent =
+                serde_json::from_slice(&line).context("unable to parse watch event")?;
+            match event {
+                WatchEvent::Added(slice) | WatchEvent::Modified(slice) => {
+                    slices.insert(slice.metadata.name.clone(), slice);
+                }
+                WatchEvent::Deleted(slice) => {
+                    slices.remove(&slice.metadata.name);
+                }
+                WatchEvent::Bookmark(_) => continue,
+                WatchEvent::Error(status) => {
+                    // Typically 410 Gone, meaning our resource version is too old. Re-list.
+                    anyhow::bail!("watch error: {}", status);
+                }
+            }
+            loc.set_base_urls(base_urls(slices.values(), cfg));
+        }
+    }
+
+    Ok(())
+}
+
+/// Computes the base URLs of the ready endpoints in the given EndpointSlices.
+fn base_urls<'a>(
+    slices: impl Iterator<Item = &'a EndpointSlice>,
+    cfg: &KubernetesConfig,
+) -> Vec<String> {
+    let mut urls = Vec::new();
+    for slice in slices {
+        let port = match &cfg.port {
+            PortSelector::Number(port) => Some(*port),
+            PortSelector::Named(name) => slice
+                .ports
+                .iter()
+                .find(|p| p.name.as_deref() == Some(name.as_str()))
+                .and_then(|p| p.port),
+            PortSelector::First => slice.ports.first().and_then(|p| p.port),
+        };
+        let Some(port) = port else {
+            continue;
+        };
+
+        for endpoint in &slice.endpoints {
+            if endpoint.conditions.ready == Some(false) {
+                continue;
+            }
+            for addr in &endpoint.addresses {
+                let host = match addr.parse::<IpAddr>() {
+                    Ok(IpAddr::V6(ip)) => format!("[{ip}]"),
+                    _ => addr.clone(),
+                };
+                urls.push(format!("{}://{}:{}", cfg.scheme, host, port));
+            }
+        }
+    }
+
+    // Keep a stable order so that unchanged endpoint sets aren't treated as changes.
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+struct ApiClient {
+    http_client: reqwest::Client,
+    url: reqwest::Url,
+    token_path: String,
+}
+
+impl ApiClient {
+    fn in_cluster(cfg: &KubernetesConfig) -> anyhow::Result<Self> {
+        let api_server = match &cfg.api_server {
+            Some(api_server) => api_server.clone(),
+            None => {
+                let host = std::env::var("KUBERNETES_SERVICE_HOST")
+                    .context("KUBERNETES_SERVICE_HOST not set")?;
+                let port = std::env::var("KUBERNETES_SERVICE_PORT")
+                    .context("KUBERNETES_SERVICE_PORT not set")?;
+                match host.parse::<IpAddr>() {
+                    Ok(IpAddr::V6(ip)) => format!("https://[{ip}]:{port}"),
+                    _ => format!("https://{host}:{port}"),
+                }
+            }
+        };
+
+        let namespace = match &cfg.namespace {
+            Some(ns) => ns.clone(),
+            None => std::fs::read_to_string(format!("{SERVICE_ACCOUNT_DIR}/namespace"))
+                .context("unable to determine kubernetes namespace")?
+                .trim()
+                .to_string(),
+        };
+
+        let url = format!(
+            "{}/apis/discovery.k8s.io/v1/namespaces/{}/endpointslices",
+            api_server.trim_end_matches('/'),
+            namespace
+        )
+        .parse()
+        .context("invalid kubernetes api server url")?;
+
+        let mut builder = crate::egress::client_builder();
+        if let Ok(ca) = std::fs::read(format!("{SERVICE_ACCOUNT_DIR}/ca.crt")) {
+            let cert =
+                reqwest::Certificate::from_pem(&ca).context("invalid kubernetes ca certificate")?;
+            builder = builder.add_root_certificate(cert);
+        }
+        let http_client = builder
+            .build()
+            .context("unable to build kubernetes http client")?;
+
+        Ok(Self {
+            http_client,
+            url,
+            token_path: format!("{SERVICE_ACCOUNT_DIR}/token"),
+        })
+    }
+
+    async fn get(&self, query: &[(&str, &str)]) -> anyhow::Result<reqwest::Response> {
+        let mut req = self.http_client.get(self.url.clone()).query(query);
+
+        // Service account tokens are rotated, so read the token for every request.
+        if let Ok(token) = tokio::fs::read_to_string(&self.token_path).await {
+            req = req.bearer_auth(token.trim());
+        }
+
+        let resp = req
+            .send()
+            .await
+            .context("kubernetes api request failed")?
+            .error_for_status()
+            .context("kubernetes api request failed")?;
+        Ok(resp)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointSliceList {
+    metadata: ListMeta,
+    #[serde(default)]
+    items: Vec<EndpointSlice>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListMeta {
+    #[serde(default)]
+    resource_version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointSlice {
+    metadata: ObjectMeta,
+    #[serde(default)]
+    endpoints: Vec<Endpoint>,
+    #[serde(default)]
+    ports: Vec<EndpointPort>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectMeta {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Endpoint {
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default)]
+    conditions: EndpointConditions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct EndpointConditions {
+    ready: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointPort {
+    name: Option<String>,
+    port: Option<u16>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "object", rename_all = "UPPERCASE")]
+enum WatchEvent {
+    Added(EndpointSlice),
+    Modified(EndpointSlice),
+    Deleted(EndpointSlice),
+    Bookmark(serde_json::Value),
+    Error(serde_json::Value),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(port: PortSelector) -> KubernetesConfig {
+        KubernetesConfig {
+            namespace: Some("default".into()),
+            label_selector: "kubernetes.io/service-name=svc".into(),
+            port,
+            scheme: "http".into(),
+            api_server: None,
+        }
+    }
+
+    fn slice(json: &str) -> EndpointSlice {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn ready_endpoints_only() {
+        let s = slice(
+            r#"{
+                "metadata": {"name": "svc-abc"},
+                "addressType": "IPv4",
+                "endpoints": [
+                    {"addresses": ["10.0.0.2"], "conditions": {"ready": true}},
+                    {"addresses": ["10.0.0.1"], "conditions": {"ready": false}},
+                    {"addresses": ["10.0.0.3"]}
+                ],
+                "ports": [{"name": "metrics", "port": 9090}, {"name": "http", "port": 8080}]
+            }"#,
+        );
+
+        let cfg = config(PortSelector::Named("http".into()));
+        assert_eq!(
+            base_urls([&s].into_iter(), &cfg),
+            ["http://10.0.0.2:8080", "http://10.0.0.3:8080"]
+        );
+
+        let cfg = config(PortSelector::First);
+        assert_eq!(
+            base_urls([&s].into_iter(), &cfg),
+            ["http://10.0.0.2:9090", "http://10.0.0.3:9090"]
+        );
+    }
+
+    #[test]
+    fn ipv6_and_missing_port() {
+        let s = slice(
+            r#"{
+                "metadata": {"name": "svc-v6"},
+                "endpoints": [{"addresses": ["fd00::1"]}],
+                "ports": [{"name": "grpc", "port": 9000}]
+            }"#,
+        );
+
+        let cfg = config(PortSelector::Number(8080));
+        assert_eq!(base_urls([&s].into_iter(), &cfg), ["http://[fd00::1]:8080"]);
+
+        let cfg = config(PortSelector::Named("http".into()));
+        assert!(base_urls([&s].into_iter(), &cfg).is_empty());
+    }
+
+    #[test]
+    fn parse_watch_event() {
+        let event: WatchEvent = serde_json::from_str(
+            r#"{"type": "DELETED", "object": {"metadata": {"name": "svc-abc"}}}"#,
+        )
+        .unwrap();
+        assert!(matches!(event, WatchEvent::Deleted(s) if s.metadata.name == "svc-abc"));
+    }
+}
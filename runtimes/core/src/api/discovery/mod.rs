@@ -0,0 +1,309 @@
+mod dns;
+mod kubernetes;
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::encore::runtime::v1 as pb;
+use crate::EncoreName;
+
+pub use dns::DnsConfig;
+pub use kubernetes::KubernetesConfig;
+
+const DEFAULT_HEALTH_CHECK_PATH: &str = "/__encore/healthz";
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_UNHEALTHY_THRESHOLD: u32 = 3;
+const DEFAULT_HEALTHY_THRESHOLD: u32 = 1;
+
+/// The set of base URLs at which a single service can be reached.
+///
+/// Requests are spread round-robin across the healthy base URLs.
+/// If every base URL is unhealthy, all of them are used rather than failing outright.
+///
+/// The set of base URLs can change at runtime, for example when they are resolved from DNS.
+pub struct ServiceLocation {
+    svc: EncoreName,
+    upstreams: RwLock<Arc<[Arc<Upstream>]>>,
+    next: AtomicUsize,
+}
+
+struct Upstream {
+    base_url: String,
+    healthy: AtomicBool,
+    consecutive_failures: AtomicU32,
+    consecutive_successes: AtomicU32,
+}
+
+impl Upstream {
+    fn new(base_url: String) -> Self {
+        Self {
+            base_url,
+            healthy: AtomicBool::new(true),
+            consecutive_failures: AtomicU32::new(0),
+            consecutive_successes: AtomicU32::new(0),
+        }
+    }
+}
+
+impl ServiceLocation {
+    pub fn new(svc: EncoreName, base_urls: Vec<String>) -> Self {
+        let upstreams = base_urls
+            .into_iter()
+            .map(|base_url| Arc::new(Upstream::new(base_url)))
+            .collect();
+        Self {
+            svc,
+            upstreams: RwLock::new(upstreams),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the base URL to use for the next request to the service,
+    /// or None if there are no known base URLs.
+    pub fn base_url(&self) -> Option<String> {
+        let upstreams = self.upstreams();
+        match upstreams.len() {
+            0 => None,
+            1 => Some(upstreams[0].base_url.clone()),
+            n => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                let upstream = (0..n)
+                    .map(|i| &upstreams[(start + i) % n])
+                    .find(|u| u.healthy.load(Ordering::Relaxed))
+                    .unwrap_or(&upstreams[start % n]);
+                Some(upstream.base_url.clone())
+            }
+        }
+    }
+
+    /// Replaces the set of base URLs for the service.
+    /// Health state is kept for base URLs that are in both the old and new set.
+    pub fn set_base_urls(&self, base_urls: Vec<String>) {
+        let existing = self.upstreams();
+        let upstreams: Arc<[Arc<Upstream>]> = base_urls
+            .into_iter()
+            .map(|base_url| {
+                existing
+                    .iter()
+                    .find(|u| u.base_url == base_url)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(Upstream::new(base_url)))
+            })
+            .collect();
+
+        let changed = upstreams.len() != existing.len()
+            || upstreams
+                .iter()
+                .zip(existing.iter())
+                .any(|(a, b)| a.base_url != b.base_url);
+        if changed {
+            log::debug!(service = self.svc.as_ref(), count = upstreams.len(); "updated service base urls");
+            *self.upstreams.write().unwrap() = upstreams;
+        }
+    }
+
+    fn upstreams(&self) -> Arc<[Arc<Upstream>]> {
+        self.upstreams.read().unwrap().clone()
+    }
+
+    /// Starts actively health checking the base URLs, ejecting unhealthy ones.
+    /// Probing is skipped while there is only a single base URL, since there is nothing to fail over to.
+    pub fn start_health_checks(
+        self: &Arc<Self>,
+        cfg: HealthCheckConfig,
+        http_client: &reqwest::Client,
+        runtime: &tokio::runtime::Handle,
+    ) {
+        let this = self.clone();
+        let http_client = http_client.clone();
+        runtime.spawn(async move {
+            let mut interval = tokio::time::interval(cfg.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let upstreams = this.upstreams();
+                if upstreams.len() < 2 {
+                    continue;
+                }
+
+                let (svc, cfg) = (&this.svc, &cfg);
+                let probes = upstreams.iter().map(|upstream| {
+                    let url = format!("{}{}", upstream.base_url.trim_end_matches('/'), cfg.path);
+                    let req = http_client.get(url).timeout(cfg.timeout).send();
+                    async move {
+                        let ok = match req.await {
+                            Ok(resp) => resp.status().is_success(),
+                            Err(_) => false,
+                        };
+                        upstream.record_probe(svc, ok, cfg);
+                    }
+                });
+                futures::future::join_all(probes).await;
+            }
+        });
+    }
+
+    /// Starts periodically resolving the base URLs from DNS.
+    pub fn start_dns_resolution(
+        self: &Arc<Self>,
+        cfg: DnsConfig,
+        runtime: &tokio::runtime::Handle,
+    ) {
+        runtime.spawn(dns::resolve_loop(self.clone(), cfg));
+    }
+
+    /// Starts watching Kubernetes EndpointSlices for the base URLs.
+    pub fn start_kubernetes_watch(
+        self: &Arc<Self>,
+        cfg: KubernetesConfig,
+        runtime: &tokio::runtime::Handle,
+    ) {
+        runtime.spawn(kubernetes::watch_loop(self.clone(), cfg));
+    }
+}
+
+impl Upstream {
+    fn record_probe(&self, svc: &EncoreName, ok: bool, cfg: &HealthCheckConfig) {
+        if ok {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            let successes = self.consecutive_successes.fetch_add(1, Ordering::Relaxed) + 1;
+            if successes >= cfg.healthy_threshold && !self.healthy.swap(true, Ordering::Relaxed) {
+                log::info!(service = svc.as_ref(), base_url = self.base_url.as_str(); "service base url is healthy again, restoring");
+            }
+        } else {
+            self.consecutive_successes.store(0, Ordering::Relaxed);
+            let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if failures >= cfg.unhealthy_threshold && self.healthy.swap(false, Ordering::Relaxed) {
+                log::warn!(service = svc.as_ref(), base_url = self.base_url.as_str(); "service base url failed health checks, ejecting");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthCheckConfig {
+    pub path: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub unhealthy_threshold: u32,
+    pub healthy_threshold: u32,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_HEALTH_CHECK_PATH.to_string(),
+            interval: DEFAULT_HEALTH_CHECK_INTERVAL,
+            timeout: DEFAULT_HEALTH_CHECK_TIMEOUT,
+            unhealthy_threshold: DEFAULT_UNHEALTHY_THRESHOLD,
+            healthy_threshold: DEFAULT_HEALTHY_THRESHOLD,
+        }
+    }
+}
+
+impl From<pb::service_discovery::HealthCheck> for HealthCheckConfig {
+    fn from(hc: pb::service_discovery::HealthCheck) -> Self {
+        let def = Self::default();
+        Self {
+            path: hc
+                .path
+                .map(|p| {
+                    if p.starts_with('/') {
+                        p
+                    } else {
+                        format!("/{p}")
+                    }
+                })
+                .unwrap_or(def.path),
+            interval: non_zero_duration(hc.interval).unwrap_or(def.interval),
+            timeout: non_zero_duration(hc.timeout).unwrap_or(def.timeout),
+            unhealthy_threshold: hc
+                .unhealthy_threshold
+                .filter(|n| *n > 0)
+                .unwrap_or(def.unhealthy_threshold),
+            healthy_threshold: hc
+                .healthy_threshold
+                .filter(|n| *n > 0)
+                .unwrap_or(def.healthy_threshold),
+        }
+    }
+}
+
+fn non_zero_duration(d: Option<prost_types::Duration>) -> Option<Duration> {
+    d.and_then(|d| Duration::try_from(d).ok())
+        .filter(|d| !d.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(urls: &[&str]) -> ServiceLocation {
+        ServiceLocation::new(
+            EncoreName::from("svc"),
+            urls.iter().map(|s| s.to_string()).collect(),
+        )
+    }
+
+    fn next_urls(loc: &ServiceLocation, n: usize) -> Vec<String> {
+        (0..n).map(|_| loc.base_url().unwrap()).collect()
+    }
+
+    #[test]
+    fn round_robin() {
+        let loc = location(&["http://a", "http://b", "http://c"]);
+        assert_eq!(
+            next_urls(&loc, 6),
+            ["http://a", "http://b", "http://c", "http://a", "http://b", "http://c"]
+        );
+    }
+
+    #[test]
+    fn skips_ejected() {
+        let loc = location(&["http://a", "http://b", "http://c"]);
+        let cfg = HealthCheckConfig {
+            unhealthy_threshold: 2,
+            ..Default::default()
+        };
+        let svc = EncoreName::from("svc");
+        let b = loc.upstreams()[1].clone();
+
+        // A single failure is not enough to eject.
+        b.record_probe(&svc, false, &cfg);
+        assert!(b.healthy.load(Ordering::Relaxed));
+
+        b.record_probe(&svc, false, &cfg);
+        assert!(!b.healthy.load(Ordering::Relaxed));
+        assert!(!next_urls(&loc, 6).contains(&"http://b".to_string()));
+
+        // A successful probe restores it.
+        b.record_probe(&svc, true, &cfg);
+        assert!(next_urls(&loc, 3).contains(&"http://b".to_string()));
+    }
+
+    #[test]
+    fn all_ejected_falls_back() {
+        let loc = location(&["http://a", "http://b"]);
+        for u in loc.upstreams().iter() {
+            u.healthy.store(false, Ordering::Relaxed);
+        }
+        assert_eq!(next_urls(&loc, 2), ["http://a", "http://b"]);
+    }
+
+    #[test]
+    fn set_base_urls_keeps_health() {
+        let loc = location(&["http://a", "http://b"]);
+        loc.upstreams()[1].healthy.store(false, Ordering::Relaxed);
+
+        loc.set_base_urls(vec!["http://b".into(), "http://c".into()]);
+        let upstreams = loc.upstreams();
+        assert_eq!(upstreams[0].base_url, "http://b");
+        assert!(!upstreams[0].healthy.load(Ordering::Relaxed));
+        assert!(upstreams[1].healthy.load(Ordering::Relaxed));
+
+        loc.set_base_urls(vec![]);
+        assert_eq!(loc.base_url(), None);
+    }
+}
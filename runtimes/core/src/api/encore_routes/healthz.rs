@@ -1,4 +1,5 @@
 use axum::extract::Request;
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 
@@ -6,19 +7,31 @@ use serde::{Deserialize, Serialize};
 pub struct Handler {
     pub app_revision: String,
     pub deploy_id: String,
+    /// The outcome of checks performed at startup, such as secret resolution.
+    /// Only the counts are reported, as the endpoint is public; the individual
+    /// checks are available from the admin API.
+    pub checks: CheckCounts,
 }
 
 impl Handler {
     pub fn health_check(self) -> Response {
-        log::trace!(code = "ok"; "handling incoming health check request");
+        let (code, message) = if self.checks.failed == 0 {
+            ("ok", "Your Encore app is up and running!")
+        } else {
+            (
+                "checks_failed",
+                "Your Encore app is running, but some startup checks failed.",
+            )
+        };
+        log::trace!(code = code; "handling incoming health check request");
         Response {
-            code: "ok".into(),
-            message: "Your Encore app is up and running!".into(),
+            code: code.into(),
+            message: message.into(),
             details: Details {
                 app_revision: self.app_revision,
                 encore_compiler: "".into(),
                 deploy_id: self.deploy_id,
-                checks: vec![],
+                checks: self.checks,
                 enabled_experiments: vec![],
             },
         }
@@ -32,7 +45,7 @@ impl axum::handler::Handler<(), ()> for Handler {
 
     fn call(self, _req: Request, _state: ()) -> Self::Future {
         let resp = self.health_check();
-        Box::pin(async move { Json(resp).into_response() })
+        Box::pin(async move { (resp.status(), Json(resp)).into_response() })
     }
 }
 
@@ -43,19 +56,29 @@ pub struct Response {
     pub details: Details,
 }
 
+impl Response {
+    /// The HTTP status to respond with, which signals failing checks
+    /// to load balancers and orchestrators.
+    pub fn status(&self) -> StatusCode {
+        if self.details.checks.failed == 0 {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Details {
     pub app_revision: String,
     pub encore_compiler: String,
     pub deploy_id: String,
-    pub checks: Vec<CheckResult>,
+    pub checks: CheckCounts,
     pub enabled_experiments: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct CheckResult {
-    pub name: String,
-    pub passed: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CheckCounts {
+    pub passed: usize,
+    pub failed: usize,
 }
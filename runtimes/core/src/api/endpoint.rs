@@ -9,6 +9,7 @@ use axum::http::HeaderValue;
 use axum::response::IntoResponse;
 use bytes::{BufMut, BytesMut};
 use http::HeaderMap;
+use http_body_util::BodyExt;
 use indexmap::IndexMap;
 use percent_encoding::percent_decode_str;
 use serde::Serialize;
@@ -19,11 +20,11 @@ use crate::api::schema::encoding::{
     ReqSchemaUnderConstruction, SchemaUnderConstruction,
 };
 use crate::api::schema::{JSONPayload, Method};
-use crate::api::{jsonschema, schema, ErrCode, Error};
+use crate::api::{concurrency, etag, jsonschema, respcache, schema, ErrCode, Error};
 use crate::encore::parser::meta::v1::rpc;
 use crate::encore::parser::meta::v1::{self as meta, selector};
 use crate::log::LogFromRust;
-use crate::metrics::counter;
+use crate::metrics::{counter, histogram, slo};
 use crate::model::StreamDirection;
 use crate::names::EndpointName;
 use crate::trace;
@@ -55,8 +56,6 @@ pub type APIResult<T> = Result<T, Error>;
 impl IntoResponse for SuccessResponse {
     fn into_response(self) -> axum::http::Response<axum::body::Body> {
         // Serialize the response body.
-        // Use a small initial capacity of 128 bytes like serde_json::to_vec
-        // https://docs.rs/serde_json/1.0.82/src/serde_json/ser.rs.html#2189
         let bld = {
             let mut bld = axum::http::Response::builder();
             *(bld.headers_mut().unwrap()) = self.headers;
@@ -70,11 +69,9 @@ impl IntoResponse for SuccessResponse {
                     axum::http::header::CONTENT_TYPE,
                     HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
                 );
-                let mut buf = BytesMut::with_capacity(128).writer();
-                match serde_json::to_writer(&mut buf, &body) {
-                    Ok(()) => bld
-                        .body(axum::body::Body::from(buf.into_inner().freeze()))
-                        .unwrap(),
+                let mut w = schema::BodyWriter::new();
+                match serde_json::to_writer(&mut w, &body) {
+                    Ok(()) => w.into_body(bld).unwrap(),
                     Err(err) => Error::internal(err).to_response(None),
                 }
             }
@@ -191,6 +188,9 @@ pub struct Endpoint {
 
     /// Treat endpoint as sensitive and redact details from traces.
     pub sensitive: bool,
+
+    /// The kind of ETag computed for successful GET and HEAD responses, if any.
+    pub etag: Option<etag::Kind>,
 }
 
 impl Endpoint {
@@ -392,6 +392,7 @@ pub fn endpoints_from_meta(
             static_assets: ep.ep.static_assets.clone(),
             tags,
             sensitive: ep.ep.sensitive,
+            etag: etag::Kind::from_meta(ep.ep.etag()),
         };
 
         endpoint_map.insert(
@@ -408,6 +409,10 @@ pub(super) struct EndpointHandler {
     pub handler: Arc<dyn BoxedHandler>,
     pub shared: Arc<SharedEndpointData>,
     pub requests_total: counter::Schema<u64>,
+    pub request_duration: histogram::Schema,
+    pub concurrency: concurrency::EndpointLimiter,
+    pub response_cache: Option<Arc<respcache::ResponseCache>>,
+    pub slo: Option<Arc<slo::Tracker>>,
 }
 
 #[derive(Debug)]
@@ -421,6 +426,10 @@ pub(super) struct SharedEndpointData {
     /// When we support multiple this needs to be made into a map, and the
     /// correct schema looked up based on the gateway being used.
     pub auth_data_schemas: HashMap<String, Option<jsonschema::JSONSchema>>,
+
+    /// Whether to check handler responses against the response schema,
+    /// logging any mismatches. Enabled in development environments.
+    pub check_response_schema: bool,
 }
 
 impl Clone for EndpointHandler {
@@ -430,6 +439,10 @@ impl Clone for EndpointHandler {
             handler: self.handler.clone(),
             shared: self.shared.clone(),
             requests_total: self.requests_total.clone(),
+            request_duration: self.request_duration.clone(),
+            concurrency: self.concurrency.clone(),
+            response_cache: self.response_cache.clone(),
+            slo: self.slo.clone(),
         }
     }
 }
@@ -549,6 +562,7 @@ impl EndpointHandler {
             parent_span,
             caller_event_id: meta.parent_event_id,
             ext_correlation_id: meta.ext_correlation_id,
+            deadline: meta.deadline,
             start: tokio::time::Instant::now(),
             start_time: std::time::SystemTime::now(),
             is_platform_request: platform_seal_of_approval.is_some(),
@@ -565,6 +579,24 @@ impl EndpointHandler {
     ) -> Pin<Box<dyn Future<Output = axum::http::Response<axum::body::Body>> + Send + 'static>>
     {
         Box::pin(async move {
+            // Shed load before doing any work on the request.
+            let _permits = match self.concurrency.acquire().await {
+                Ok(permits) => permits,
+                Err(err) => {
+                    self.requests_total
+                        .with([("code", err.code.to_string())])
+                        .increment();
+                    return err.to_response(None);
+                }
+            };
+
+            // Negotiate the response body format before the request is consumed.
+            let resp_format = schema::BodyFormat::from_accept(axum_req.headers());
+            let etag = self
+                .endpoint
+                .etag
+                .filter(|_| matches!(*axum_req.method(), http::Method::GET | http::Method::HEAD));
+
             let request = match self.parse_request(axum_req).await {
                 Ok(req) => req,
                 Err(err) => return err.to_response(None),
@@ -594,6 +626,23 @@ impl EndpointHandler {
                 .to_response(internal_caller);
             }
 
+            // Serve the response from the cache, if possible.
+            let cache_key = self
+                .response_cache
+                .as_ref()
+                .and_then(|c| Some((c, c.request_key(&request, resp_format)?)));
+            if let Some((cache, key)) = &cache_key {
+                if let Some(mut resp) = cache.get(key).await {
+                    self.requests_total.with([("code", "ok")]).increment();
+                    if let Ok(val) =
+                        HeaderValue::from_str(request.span.0.serialize_encore().as_str())
+                    {
+                        resp.headers_mut().insert("x-encore-trace-id", val);
+                    }
+                    return resp;
+                }
+            }
+
             let logger = crate::log::root();
             logger.info(Some(&request), "starting request", None);
 
@@ -656,12 +705,33 @@ impl EndpointHandler {
                 Some(fields)
             });
 
+            let mut schema_mismatch = None;
+            if self.shared.check_response_schema {
+                if let ResponseData::Typed(Ok(response)) = &resp {
+                    if let Err(err) = self.endpoint.response.check_conformance(&response.payload) {
+                        logger.warn(
+                            Some(&request),
+                            "response does not match the declared schema",
+                            Some(&err),
+                            None,
+                        );
+                        schema_mismatch = Some(err);
+                    }
+                }
+            }
+
+            let cacheable = schema_mismatch.is_none() && matches!(resp, ResponseData::Typed(Ok(_)));
             let (mut encoded_resp, resp_payload, extra_headers, error) = match resp {
                 ResponseData::Raw(resp) => (resp, None, None, None),
                 ResponseData::Typed(Ok(response)) => (
                     self.endpoint
                         .response
-                        .encode(&response.payload, response.status.unwrap_or(200))
+                        .encode(
+                            &response.payload,
+                            response.status.unwrap_or(200),
+                            resp_format,
+                            etag,
+                        )
                         .unwrap_or_else(|err| err.to_response(internal_caller)),
                     Some(response.payload),
                     response.extra_headers,
@@ -687,17 +757,51 @@ impl EndpointHandler {
                     }),
                 };
                 self.shared.tracer.request_span_end(&model_resp, sensitive);
+
+                let histogram = self.request_duration.with([("code", code.as_str())]);
+                if self.shared.tracer.is_reported(request.span.0) {
+                    histogram.observe_traced(duration.as_secs_f64(), request.span.0);
+                } else {
+                    histogram.observe(duration.as_secs_f64());
+                }
                 self.requests_total.with([("code", code)]).increment();
+
+                if let Some(slo) = &self.slo {
+                    slo.record(!encoded_resp.status().is_server_error(), duration);
+                }
             }
 
             if let Ok(val) = HeaderValue::from_str(request.span.0.serialize_encore().as_str()) {
                 encoded_resp.headers_mut().insert("x-encore-trace-id", val);
             }
 
+            // Flag the mismatch on the response, so it's visible to the caller.
+            if let Some(err) = schema_mismatch {
+                if let Ok(val) = HeaderValue::from_str(&err.message) {
+                    encoded_resp
+                        .headers_mut()
+                        .insert("x-encore-response-schema-mismatch", val);
+                }
+            }
+
             if let Some(extra_headers) = extra_headers {
                 encoded_resp.headers_mut().extend(extra_headers)
             }
 
+            if let Some((cache, key)) = cache_key.filter(|_| cacheable) {
+                if encoded_resp.status() == axum::http::StatusCode::OK {
+                    let (parts, body) = encoded_resp.into_parts();
+                    return match body.collect().await {
+                        Ok(body) => {
+                            let body = body.to_bytes();
+                            cache.put(key, &parts, &body);
+                            axum::http::Response::from_parts(parts, axum::body::Body::from(body))
+                        }
+                        Err(err) => Error::internal(err).to_response(None),
+                    };
+                }
+            }
+
             encoded_resp
         })
     }
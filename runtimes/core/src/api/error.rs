@@ -2,8 +2,10 @@ use std::fmt::{Debug, Display};
 use std::str::FromStr;
 
 use crate::api::jsonschema;
+use crate::encore::runtime::v1 as pb;
 use crate::error::{AppError, StackTrace};
 use axum::extract::ws::rejection::WebSocketUpgradeRejection;
+use once_cell::sync::OnceCell;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::{DeserializeFromStr, SerializeDisplay};
@@ -42,39 +44,64 @@ where
 #[derive(Debug)]
 pub struct ExternalError<'a>(&'a Error);
 
-/// Cached environment variable for whether to include internal message in all errors.
-static ENCORE_API_INCLUDE_INTERNAL_MESSAGE: once_cell::sync::Lazy<bool> =
-    once_cell::sync::Lazy::new(|| {
-        std::env::var("ENCORE_API_INCLUDE_INTERNAL_MESSAGE")
-            .map(|v| !v.is_empty() && v != "0")
-            .unwrap_or(false)
-    });
-
-/// Cached environment variable for whether to include the stack in error responses.
-static ENCORE_API_INCLUDE_ERROR_STACK: once_cell::sync::Lazy<bool> =
-    once_cell::sync::Lazy::new(|| {
-        std::env::var("ENCORE_API_INCLUDE_ERROR_STACK")
-            .map(|v| !v.is_empty() && v != "0")
-            .unwrap_or(false)
-    });
+/// Controls which internal details are included in errors returned to external callers.
+#[derive(Debug, Default, Clone, Copy)]
+struct ResponseConfig {
+    include_internal_message: bool,
+    include_stack: bool,
+}
+
+static RESPONSE_CONFIG: OnceCell<ResponseConfig> = OnceCell::new();
+
+/// Configures which internal details are included in external error responses.
+/// The ENCORE_API_INCLUDE_INTERNAL_MESSAGE and ENCORE_API_INCLUDE_ERROR_STACK
+/// environment variables enable them regardless of the configuration.
+pub fn configure_responses(cfg: &pb::ErrorResponses) {
+    let env = ResponseConfig::from_env();
+    let cfg = ResponseConfig {
+        include_internal_message: cfg.include_internal_message || env.include_internal_message,
+        include_stack: cfg.include_stack || env.include_stack,
+    };
+    if RESPONSE_CONFIG.set(cfg).is_err() {
+        log::warn!("error responses already configured, ignoring");
+    }
+}
+
+impl ResponseConfig {
+    fn get() -> &'static Self {
+        RESPONSE_CONFIG.get_or_init(Self::from_env)
+    }
+
+    fn from_env() -> Self {
+        let enabled = |key: &str| {
+            std::env::var(key)
+                .map(|v| !v.is_empty() && v != "0")
+                .unwrap_or(false)
+        };
+        Self {
+            include_internal_message: enabled("ENCORE_API_INCLUDE_INTERNAL_MESSAGE"),
+            include_stack: enabled("ENCORE_API_INCLUDE_ERROR_STACK"),
+        }
+    }
+}
 
 impl Serialize for ExternalError<'_> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut error = serializer.serialize_struct("Error", 3)?;
+        let cfg = ResponseConfig::get();
+        let mut error = serializer.serialize_struct("Error", 4)?;
         error.serialize_field("code", &self.0.code)?;
         error.serialize_field("message", &self.0.message)?;
         error.serialize_field("details", &self.0.details)?;
+        error.serialize_field("retryable", &self.0.code.is_retryable())?;
 
-        // Include internal message even in external errors iff the environment variable is set.
-        if *ENCORE_API_INCLUDE_INTERNAL_MESSAGE {
+        // Include internal details in external errors only if configured to.
+        if cfg.include_internal_message {
             error.serialize_field("internal_message", &self.0.internal_message)?;
         }
-
-        // Do the same for stack traces.
-        if *ENCORE_API_INCLUDE_ERROR_STACK {
+        if cfg.include_stack {
             error.serialize_field("stack", &self.0.stack)?;
         }
 
@@ -127,6 +154,17 @@ impl Error {
         }
     }
 
+    /// Returns an error with the code's default public message.
+    pub fn from_code(code: ErrCode) -> Self {
+        Self {
+            code,
+            message: code.default_public_message().into(),
+            internal_message: None,
+            stack: None,
+            details: None,
+        }
+    }
+
     pub fn unauthenticated() -> Self {
         Self {
             code: ErrCode::Unauthenticated,
@@ -377,6 +415,18 @@ impl ErrCode {
         }
     }
 
+    /// Reports whether the operation may succeed if retried unchanged,
+    /// typically after a backoff.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrCode::Unavailable
+                | ErrCode::ResourceExhausted
+                | ErrCode::Aborted
+                | ErrCode::DeadlineExceeded
+        )
+    }
+
     pub fn status_code(&self) -> axum::http::StatusCode {
         match self {
             ErrCode::Canceled => axum::http::StatusCode::from_u16(499).unwrap(),
@@ -481,9 +531,55 @@ impl From<axum::http::status::StatusCode> for ErrCode {
             499 => ErrCode::Canceled,
             500 => ErrCode::Internal,
             501 => ErrCode::Unimplemented,
-            503 => ErrCode::Unavailable,
+            502 | 503 => ErrCode::Unavailable,
             504 => ErrCode::DeadlineExceeded,
             _ => ErrCode::Unknown,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn external_error() {
+        let err = Error {
+            code: ErrCode::Unavailable,
+            message: "try again".into(),
+            internal_message: Some("connection refused".into()),
+            details: None,
+            stack: None,
+        };
+        let json = serde_json::to_value(err.as_external()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "code": "unavailable",
+                "message": "try again",
+                "details": null,
+                "retryable": true,
+            })
+        );
+
+        let json = serde_json::to_value(Error::from_code(ErrCode::NotFound).as_external()).unwrap();
+        assert_eq!(json["retryable"], false);
+        assert_eq!(json["message"], "the requested resource was not found");
+    }
+
+    #[test]
+    fn status_codes() {
+        for code in [
+            ErrCode::InvalidArgument,
+            ErrCode::NotFound,
+            ErrCode::Unavailable,
+            ErrCode::Unauthenticated,
+        ] {
+            assert_eq!(ErrCode::from(code.status_code()), code);
+        }
+        assert_eq!(
+            ErrCode::from(axum::http::StatusCode::BAD_GATEWAY),
+            ErrCode::Unavailable
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use axum::http::header::{ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::encore::parser::meta::v1 as meta;
+
+/// How the ETag of an endpoint's responses is computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Strong,
+    Weak,
+}
+
+impl Kind {
+    pub fn from_meta(etag: meta::rpc::Etag) -> Option<Self> {
+        match etag {
+            meta::rpc::Etag::Disabled => None,
+            meta::rpc::Etag::Strong => Some(Kind::Strong),
+            meta::rpc::Etag::Weak => Some(Kind::Weak),
+        }
+    }
+
+    /// Computes the ETag of a response body, given its chunks.
+    pub fn compute<'a>(self, chunks: impl IntoIterator<Item = &'a [u8]>) -> HeaderValue {
+        let mut hasher = Sha256::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        // Half the digest is plenty to tell versions of a response apart.
+        let digest = hasher.finalize();
+        let tag = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&digest[..16]);
+        let value = match self {
+            Kind::Strong => format!("\"{tag}\""),
+            Kind::Weak => format!("W/\"{tag}\""),
+        };
+        HeaderValue::from_str(&value).expect("etag is a valid header value")
+    }
+}
+
+/// Reports whether a response can be replaced with 304 Not Modified,
+/// because the client's If-None-Match header matches its ETag.
+pub fn is_not_modified(
+    method: &Method,
+    req_headers: &HeaderMap,
+    status: StatusCode,
+    resp_headers: &HeaderMap,
+) -> bool {
+    if !(method == Method::GET || method == Method::HEAD) || status != StatusCode::OK {
+        return false;
+    }
+    let Some(etag) = resp_headers.get(ETAG).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    // If-None-Match uses the weak comparison function, ignoring the W/ prefix.
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    req_headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Headers that are kept when a response is replaced with 304 Not Modified.
+const NOT_MODIFIED_HEADERS: &[&str] = &[
+    "cache-control",
+    "content-location",
+    "date",
+    "etag",
+    "expires",
+    "vary",
+];
+
+/// Returns the names of the response headers to remove
+/// when replacing it with 304 Not Modified.
+pub fn not_modified_removed_headers(resp_headers: &HeaderMap) -> Vec<axum::http::HeaderName> {
+    resp_headers
+        .keys()
+        .filter(|name| !NOT_MODIFIED_HEADERS.contains(&name.as_str()))
+        .filter(|name| !name.as_str().starts_with("access-control-"))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_not_modified() {
+        let etag = Kind::Strong.compute([b"hello".as_slice()]);
+        let mut resp = HeaderMap::new();
+        resp.insert(ETAG, etag.clone());
+
+        let req = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        let etag = etag.to_str().unwrap();
+        let check =
+            |method: &Method, req: &HeaderMap, status| is_not_modified(method, req, status, &resp);
+
+        assert!(check(&Method::GET, &req(etag), StatusCode::OK));
+        assert!(check(
+            &Method::HEAD,
+            &req(&format!("\"x\", W/{etag}")),
+            StatusCode::OK
+        ));
+        assert!(check(&Method::GET, &req("*"), StatusCode::OK));
+        assert!(!check(&Method::GET, &req("\"other\""), StatusCode::OK));
+        assert!(!check(&Method::GET, &HeaderMap::new(), StatusCode::OK));
+        assert!(!check(&Method::POST, &req(etag), StatusCode::OK));
+        assert!(!check(&Method::GET, &req(etag), StatusCode::CREATED));
+    }
+
+    #[test]
+    fn test_compute() {
+        let strong = Kind::Strong.compute([b"hel".as_slice(), b"lo".as_slice()]);
+        assert_eq!(strong, Kind::Strong.compute([b"hello".as_slice()]));
+        assert_ne!(strong, Kind::Strong.compute([b"hello!".as_slice()]));
+
+        let weak = Kind::Weak.compute([b"hello".as_slice()]);
+        assert_eq!(
+            weak.to_str().unwrap(),
+            format!("W/{}", strong.to_str().unwrap())
+        );
+    }
+}
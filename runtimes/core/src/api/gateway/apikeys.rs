@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use tokio_postgres::types::ToSql;
+
+use crate::api::{self, PValue};
+use crate::encore::runtime::v1 as pb;
+use crate::metrics::{counter, Registry};
+use crate::sqldb::{self, is_valid_table_name, RowValue};
+use crate::EncoreName;
+
+const DEFAULT_HEADER: &str = "x-api-key";
+const DEFAULT_TABLE: &str = "encore_api_keys";
+
+/// How long a key found in the database is cached.
+const SQL_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// The maximum number of keys found in the database to cache.
+const SQL_CACHE_SIZE: usize = 10_000;
+
+/// The SHA-256 hash of an API key.
+type KeyHash = [u8; 32];
+
+/// Authenticates and rate limits requests carrying an API key.
+///
+/// Rate limits are enforced per gateway instance, so the effective
+/// limit for a key scales with the number of gateway replicas.
+pub struct ApiKeys {
+    header: http::HeaderName,
+    keys: HashMap<KeyHash, Arc<Key>>,
+    sql: Option<SqlSource>,
+    default_rate_limit: Option<RateLimit>,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+    requests_total: counter::Schema<u64>,
+}
+
+#[derive(Debug)]
+struct Key {
+    id: String,
+    rate_limit: Option<RateLimit>,
+}
+
+impl ApiKeys {
+    pub fn new(
+        gateway: &EncoreName,
+        cfg: &pb::gateway::ApiKeys,
+        sqldb: &sqldb::Manager,
+        metrics: &Arc<Registry>,
+    ) -> anyhow::Result<Self> {
+        let header = cfg
+            .header
+            .as_deref()
+            .unwrap_or(DEFAULT_HEADER)
+            .parse()
+            .context("invalid API key header")?;
+        let keys = parse_keys(&cfg.keys)?;
+
+        let sql = cfg
+            .sql
+            .as_ref()
+            .map(|s| {
+                let table = s.table.as_deref().unwrap_or(DEFAULT_TABLE);
+                if !is_valid_table_name(table) {
+                    anyhow::bail!("invalid API key table name {table:?}");
+                }
+                let db = sqldb.database(&EncoreName::from(&s.database));
+                let pool = db.new_pool().with_context(|| {
+                    format!("unable to connect to API key database {}", s.database)
+                })?;
+                Ok(SqlSource {
+                    pool,
+                    table: table.to_string(),
+                    cache: Mutex::new(LruCache::new(NonZeroUsize::new(SQL_CACHE_SIZE).unwrap())),
+                })
+            })
+            .transpose()?;
+
+        let default_rate_limit = cfg
+            .default_rate_limit
+            .as_ref()
+            .map(|r| RateLimit::new(r.rate, r.burst))
+            .transpose()
+            .context("invalid default API key rate limit")?;
+
+        let requests_total = metrics
+            .counter_schema::<u64>("e_gateway_api_key_requests_total")
+            .static_label("gateway", gateway.as_ref())
+            .require_dynamic_keys(["result"])
+            .build();
+
+        Ok(Self {
+            header,
+            keys,
+            sql,
+            default_rate_limit,
+            buckets: Mutex::default(),
+            requests_total,
+        })
+    }
+
+    /// The request header containing the API key.
+    pub fn header(&self) -> &http::HeaderName {
+        &self.header
+    }
+
+    /// Checks the API key in the request headers, and consumes
+    /// from its rate limit. It returns the id of the key.
+    pub async fn check(&self, headers: &http::HeaderMap) -> Result<String, api::Error> {
+        let Some(raw) = headers.get(&self.header) else {
+            self.record("missing");
+            return Err(api::Error {
+                message: "missing API key".to_string(),
+                ..api::Error::unauthenticated()
+            });
+        };
+
+        let hash: KeyHash = Sha256::digest(raw.as_bytes()).into();
+        let key = match self.lookup(&hash).await {
+            Ok(Some(key)) => key,
+            Ok(None) => {
+                self.record("invalid");
+                return Err(api::Error {
+                    message: "invalid API key".to_string(),
+                    ..api::Error::unauthenticated()
+                });
+            }
+            Err(err) => {
+                // Fail closed: we can't tell whether the key is valid.
+                log::error!("unable to look up API key: {:?}", err);
+                self.record("error");
+                return Err(api::Error {
+                    code: api::ErrCode::Unavailable,
+                    message: "unable to verify API key".to_string(),
+                    internal_message: Some(format!("{:#}", err)),
+                    stack: None,
+                    details: None,
+                });
+            }
+        };
+
+        if let Some(limit) = key.rate_limit.or(self.default_rate_limit) {
+            if !self.take(&key.id, limit) {
+                self.record("rate_limited");
+                return Err(api::Error {
+                    code: api::ErrCode::ResourceExhausted,
+                    message: "API key rate limit exceeded".to_string(),
+                    internal_message: None,
+                    stack: None,
+                    details: None,
+                });
+            }
+        }
+
+        self.record("allowed");
+        Ok(key.id.clone())
+    }
+
+    async fn lookup(&self, hash: &KeyHash) -> anyhow::Result<Option<Arc<Key>>> {
+        if let Some(key) = self.keys.get(hash) {
+            return Ok(Some(key.clone()));
+        }
+        match &self.sql {
+            Some(sql) => sql.lookup(hash).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Takes a token from the key's bucket, reporting whether one was available.
+    fn take(&self, key_id: &str, limit: RateLimit) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.get_mut(key_id) {
+            Some(bucket) => bucket.take(limit, now),
+            None => {
+                let mut bucket = TokenBucket::new(limit, now);
+                let ok = bucket.take(limit, now);
+                buckets.insert(key_id.to_string(), bucket);
+                ok
+            }
+        }
+    }
+
+    /// Counts the request by result. Keys aren't used as a label,
+    /// as each would add a series.
+    fn record(&self, result: &str) {
+        self.requests_total.with([("result", result)]).increment();
+    }
+}
+
+fn parse_keys(keys: &[pb::gateway::api_keys::Key]) -> anyhow::Result<HashMap<KeyHash, Arc<Key>>> {
+    let mut parsed = HashMap::with_capacity(keys.len());
+    for k in keys {
+        let hash =
+            parse_hash(&k.sha256).with_context(|| format!("invalid hash for API key {}", k.id))?;
+        let rate_limit = k
+            .rate_limit
+            .as_ref()
+            .map(|r| RateLimit::new(r.rate, r.burst))
+            .transpose()
+            .with_context(|| format!("invalid rate limit for API key {}", k.id))?;
+        let key = Arc::new(Key {
+            id: k.id.clone(),
+            rate_limit,
+        });
+        if parsed.insert(hash, key).is_some() {
+            anyhow::bail!("duplicate hash for API key {}", k.id);
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_hash(hex_hash: &str) -> anyhow::Result<KeyHash> {
+    let bytes = hex::decode(hex_hash.trim()).context("not hex-encoded")?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("not a SHA-256 hash"))
+}
+
+/// Looks up keys in a database table, which the app creates in a migration.
+struct SqlSource {
+    pool: sqldb::Pool,
+    table: String,
+    /// The keys found, by hash. Unknown keys aren't cached, so keys added to
+    /// the table apply immediately and unknown keys can't evict valid ones.
+    cache: Mutex<LruCache<KeyHash, (Arc<Key>, Instant)>>,
+}
+
+impl SqlSource {
+    async fn lookup(&self, hash: &KeyHash) -> anyhow::Result<Option<Arc<Key>>> {
+        if let Some((key, cached_at)) = self.cache.lock().unwrap().get(hash) {
+            if cached_at.elapsed() < SQL_CACHE_TTL {
+                return Ok(Some(key.clone()));
+            }
+        }
+
+        let query = format!(
+            "SELECT id, rate, burst FROM {} WHERE key_sha256 = $1 AND revoked_at IS NULL",
+            self.table
+        );
+        let Some(row) = self.query(&query, &[&hex::encode(hash)]).await?.pop() else {
+            self.cache.lock().unwrap().pop(hash);
+            return Ok(None);
+        };
+
+        let key = Arc::new(parse_row(row)?);
+        self.cache
+            .lock()
+            .unwrap()
+            .put(*hash, (key.clone(), Instant::now()));
+        Ok(Some(key))
+    }
+
+    async fn query(
+        &self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> anyhow::Result<Vec<HashMap<String, RowValue>>> {
+        let mut cursor = self
+            .pool
+            .query_raw(query, params.iter().copied(), None)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        let mut rows = Vec::new();
+        while let Some(row) = cursor.next().await {
+            rows.push(row?.values()?);
+        }
+        Ok(rows)
+    }
+}
+
+fn parse_row(mut row: HashMap<String, RowValue>) -> anyhow::Result<Key> {
+    let id = match row.remove("id") {
+        Some(RowValue::PVal(PValue::String(id))) => id,
+        _ => anyhow::bail!("missing API key id"),
+    };
+    let rate = match row.remove("rate") {
+        Some(RowValue::PVal(PValue::Number(n))) => n.as_f64(),
+        _ => None,
+    };
+    let burst = match row.remove("burst") {
+        Some(RowValue::PVal(PValue::Number(n))) => n.as_u64().map(|b| b as u32),
+        _ => None,
+    };
+    let rate_limit = rate
+        .map(|rate| RateLimit::new(rate, burst))
+        .transpose()
+        .with_context(|| format!("invalid rate limit for API key {id}"))?;
+    Ok(Key { id, rate_limit })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RateLimit {
+    rate: f64,
+    burst: f64,
+}
+
+impl RateLimit {
+    fn new(rate: f64, burst: Option<u32>) -> anyhow::Result<Self> {
+        if !(rate > 0.0 && rate.is_finite()) {
+            anyhow::bail!("rate must be positive");
+        }
+        let burst = match burst {
+            Some(0) => anyhow::bail!("burst must be positive"),
+            Some(burst) => burst as f64,
+            None => rate.ceil(),
+        };
+        Ok(Self { rate, burst })
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst,
+            updated_at: now,
+        }
+    }
+
+    fn take(&mut self, limit: RateLimit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * limit.rate).min(limit.burst);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::new(2.5, None).unwrap();
+        assert_eq!(limit.burst, 3.0);
+        assert_eq!(RateLimit::new(1.0, Some(10)).unwrap().burst, 10.0);
+        assert!(RateLimit::new(0.0, None).is_err());
+        assert!(RateLimit::new(f64::NAN, None).is_err());
+        assert!(RateLimit::new(1.0, Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = RateLimit::new(2.0, Some(2)).unwrap();
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(limit, start);
+
+        assert!(bucket.take(limit, start));
+        assert!(bucket.take(limit, start));
+        assert!(!bucket.take(limit, start));
+
+        // Half a second refills one token at 2 requests per second.
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.take(limit, later));
+        assert!(!bucket.take(limit, later));
+
+        // The bucket never holds more than the burst size.
+        let much_later = later + Duration::from_secs(60);
+        assert!(bucket.take(limit, much_later));
+        assert!(bucket.take(limit, much_later));
+        assert!(!bucket.take(limit, much_later));
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let hash = hex::encode(Sha256::digest(b"secret-key"));
+        let keys = parse_keys(&[pb::gateway::api_keys::Key {
+            id: "partner".to_string(),
+            sha256: hash.to_uppercase(),
+            rate_limit: None,
+        }])
+        .unwrap();
+
+        let lookup: KeyHash = Sha256::digest(b"secret-key").into();
+        assert_eq!(keys[&lookup].id, "partner");
+
+        let invalid = parse_keys(&[pb::gateway::api_keys::Key {
+            id: "partner".to_string(),
+            sha256: "abcd".to_string(),
+            rate_limit: None,
+        }]);
+        assert!(invalid.is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio_postgres::types::ToSql;
+
+use crate::api::PValue;
+use crate::encore::runtime::v1 as pb;
+use crate::sqldb::is_valid_table_name;
+use crate::{pubsub, sqldb, EncoreName};
+
+const DEFAULT_TABLE: &str = "encore_audit_log";
+
+/// The number of records to buffer before new records are dropped.
+const BUFFER_SIZE: usize = 4096;
+
+/// The maximum number of records written to the sink at once.
+const BATCH_SIZE: usize = 100;
+
+/// A record of an authenticated, mutating API call made through a gateway.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub gateway: String,
+    pub user_id: String,
+    pub service: String,
+    pub endpoint: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub trace_id: String,
+}
+
+/// Records audit records to the configured sink.
+///
+/// Records are written by a background task so that recording never blocks
+/// the request path. If the sink falls too far behind, new records are dropped
+/// and an error is logged.
+#[derive(Debug, Clone)]
+pub struct AuditLogger {
+    tx: mpsc::Sender<AuditRecord>,
+}
+
+impl AuditLogger {
+    pub fn new(
+        gateway: &EncoreName,
+        cfg: &pb::gateway::AuditLog,
+        sqldb: &sqldb::Manager,
+        pubsub: &pubsub::Manager,
+        runtime: &tokio::runtime::Handle,
+    ) -> anyhow::Result<Self> {
+        use pb::gateway::audit_log::Sink as PbSink;
+        let sink = match cfg.sink.as_ref().context("missing audit log sink")? {
+            PbSink::File(f) => Sink::File {
+                path: PathBuf::from(&f.path),
+                file: None,
+            },
+            PbSink::Sql(s) => {
+                let table = s.table.as_deref().unwrap_or(DEFAULT_TABLE);
+                if !is_valid_table_name(table) {
+                    anyhow::bail!("invalid audit log table name {table:?}");
+                }
+                let db = sqldb.database(&EncoreName::from(&s.database));
+                let pool = db.new_pool().with_context(|| {
+                    format!("unable to connect to audit log database {}", s.database)
+                })?;
+                Sink::Sql {
+                    pool,
+                    table: table.to_string(),
+                }
+            }
+            PbSink::Pubsub(p) => {
+                let topic = pubsub
+                    .topic(EncoreName::from(&p.topic))
+                    .with_context(|| format!("audit log topic {} not found", p.topic))?;
+                Sink::PubSub(topic)
+            }
+        };
+
+        let (tx, rx) = mpsc::channel(BUFFER_SIZE);
+        runtime.spawn(write_loop(gateway.clone(), rx, sink));
+        Ok(Self { tx })
+    }
+
+    pub fn record(&self, record: AuditRecord) {
+        if let Err(err) = self.tx.try_send(record) {
+            let record = match err {
+                mpsc::error::TrySendError::Full(r) | mpsc::error::TrySendError::Closed(r) => r,
+            };
+            log::error!(
+                gateway = record.gateway.as_str(),
+                user_id = record.user_id.as_str(),
+                path = record.path.as_str(),
+                trace_id = record.trace_id.as_str();
+                "audit log sink unavailable, dropping audit record"
+            );
+        }
+    }
+}
+
+enum Sink {
+    File {
+        path: PathBuf,
+        file: Option<tokio::fs::File>,
+    },
+    /// Inserts into a table the app creates in a migration.
+    Sql {
+        pool: sqldb::Pool,
+        table: String,
+    },
+    PubSub(pubsub::TopicObj),
+}
+
+impl Sink {
+    async fn write(&mut self, records: &[AuditRecord]) -> anyhow::Result<()> {
+        match self {
+            Sink::File { path, file } => {
+                let mut buf = Vec::new();
+                for record in records {
+                    serde_json::to_writer(&mut buf, record)?;
+                    buf.push(b'\n');
+                }
+
+                let f = match file {
+                    Some(f) => f,
+                    None => file.insert(
+                        tokio::fs::OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(&path)
+                            .await
+                            .with_context(|| format!("unable to open {}", path.display()))?,
+                    ),
+                };
+                if let Err(err) = write_all(f, &buf).await {
+                    // Reopen the file on the next write.
+                    *file = None;
+                    return Err(err);
+                }
+                Ok(())
+            }
+
+            Sink::Sql { pool, table } => {
+                let query = format!(
+                    "INSERT INTO {table} (timestamp, gateway, user_id, service, endpoint, method, path, status, trace_id)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+                );
+                for r in records {
+                    let status = r.status as i32;
+                    exec(
+                        pool,
+                        &query,
+                        &[
+                            &r.timestamp,
+                            &r.gateway,
+                            &r.user_id,
+                            &r.service,
+                            &r.endpoint,
+                            &r.method,
+                            &r.path,
+                            &status,
+                            &r.trace_id,
+                        ],
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+
+            Sink::PubSub(topic) => {
+                for r in records {
+                    let PValue::Object(payload) = PValue::from(serde_json::to_value(r)?) else {
+                        unreachable!("audit records serialize to objects");
+                    };
+                    topic.publish(payload, None).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn write_all(f: &mut tokio::fs::File, buf: &[u8]) -> anyhow::Result<()> {
+    f.write_all(buf).await?;
+    f.flush().await?;
+    Ok(())
+}
+
+async fn exec(
+    pool: &sqldb::Pool,
+    query: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> anyhow::Result<()> {
+    let mut cursor = pool
+        .query_raw(query, params.iter().copied(), None)
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    while let Some(row) = cursor.next().await {
+        row?;
+    }
+    Ok(())
+}
+
+async fn write_loop(gateway: EncoreName, mut rx: mpsc::Receiver<AuditRecord>, mut sink: Sink) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while rx.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        if let Err(err) = sink.write(&batch).await {
+            log::error!(gateway = gateway.as_ref(), count = batch.len(); "failed to write audit records: {:?}", err);
+        }
+        batch.clear();
+    }
+}
+
+/// Reports whether requests with the given method should be audited.
+pub fn is_mutating(method: &http::Method) -> bool {
+    !matches!(
+        *method,
+        http::Method::GET | http::Method::HEAD | http::Method::OPTIONS | http::Method::TRACE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_names() {
+        assert!(is_valid_table_name("encore_audit_log"));
+        assert!(is_valid_table_name("audit.records"));
+        assert!(!is_valid_table_name(""));
+        assert!(!is_valid_table_name("1abc"));
+        assert!(!is_valid_table_name("a.b.c"));
+        assert!(!is_valid_table_name("audit; DROP TABLE users"));
+    }
+
+    #[test]
+    fn mutating_methods() {
+        assert!(is_mutating(&http::Method::POST));
+        assert!(is_mutating(&http::Method::DELETE));
+        assert!(!is_mutating(&http::Method::GET));
+        assert!(!is_mutating(&http::Method::OPTIONS));
+    }
+}
@@ -0,0 +1,304 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use base64::Engine;
+use bytes::{Bytes, BytesMut};
+use pingora::http::{RequestHeader, ResponseHeader};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::encore::runtime::v1 as pb;
+use crate::{cache, EncoreName};
+
+use super::audit::is_mutating;
+
+const DEFAULT_HEADER: &str = "idempotency-key";
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a key is reserved while the original request is in progress.
+/// If the gateway dies mid-request the key becomes usable again after this.
+const IN_PROGRESS_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The value stored for keys whose original request is still in progress.
+const IN_PROGRESS: &str = "in-progress";
+
+/// Responses with larger bodies than this are not stored.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Response headers that are not stored, since they are either
+/// connection-specific or recomputed when the response is replayed.
+const SKIP_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "date",
+    "keep-alive",
+    "transfer-encoding",
+];
+
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Stores responses to mutating requests carrying an idempotency key,
+/// and replays them for subsequent requests with the same key.
+pub struct Idempotency {
+    cluster: Arc<cache::Cluster>,
+    gateway: EncoreName,
+    header: http::HeaderName,
+    ttl: Duration,
+}
+
+/// The outcome of looking up an idempotency key.
+pub enum Lookup {
+    /// The key was not seen before, and has been reserved for this request.
+    Acquired(InFlight),
+    /// A previous request with the key is still in progress.
+    InProgress,
+    /// A previous request with the key completed with the given response.
+    Replay(StoredResponse),
+}
+
+impl Idempotency {
+    pub fn new(
+        gateway: EncoreName,
+        cfg: &pb::gateway::Idempotency,
+        cache: &cache::Manager,
+    ) -> anyhow::Result<Self> {
+        let cluster = cache
+            .cluster(&EncoreName::from(&cfg.redis))
+            .with_context(|| format!("redis database {} not found", cfg.redis))?;
+        let header = cfg
+            .header
+            .as_deref()
+            .unwrap_or(DEFAULT_HEADER)
+            .parse()
+            .context("invalid idempotency key header")?;
+        let ttl = cfg
+            .ttl
+            .and_then(|d| Duration::try_from(d).ok())
+            .filter(|d| !d.is_zero())
+            .unwrap_or(DEFAULT_TTL);
+
+        Ok(Self {
+            cluster,
+            gateway,
+            header,
+            ttl,
+        })
+    }
+
+    /// Reports whether the request is a mutating request with an idempotency key.
+    pub fn applies_to(&self, req: &RequestHeader) -> bool {
+        is_mutating(&req.method) && req.headers.get(&self.header).is_some_and(|v| !v.is_empty())
+    }
+
+    /// Returns the storage key for the request, if it is a mutating request
+    /// with an idempotency key.
+    ///
+    /// The key is scoped to the request's method, path and authenticated user,
+    /// so that the same idempotency key used by different callers or for
+    /// different endpoints doesn't collide.
+    pub fn request_key(&self, req: &RequestHeader, auth_uid: &str) -> Option<String> {
+        if !self.applies_to(req) {
+            return None;
+        }
+        let key = req.headers.get(&self.header)?.as_bytes();
+
+        let mut hasher = Sha256::new();
+        for part in [
+            req.method.as_str().as_bytes(),
+            req.uri.path().as_bytes(),
+            auth_uid.as_bytes(),
+            key,
+        ] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        let hash = hex::encode(hasher.finalize());
+        Some(
+            self.cluster
+                .key(&format!("encore:idempotency:{}:{}", self.gateway, hash)),
+        )
+    }
+
+    pub async fn begin(&self, key: String) -> anyhow::Result<Lookup> {
+        let mut conn = self.cluster.conn().await?;
+
+        // Retry once, in case the key expires between reserving and reading it.
+        for _ in 0..2 {
+            let opts = redis::SetOptions::default()
+                .conditional_set(redis::ExistenceCheck::NX)
+                .with_expiration(redis::SetExpiry::PX(IN_PROGRESS_TTL.as_millis() as u64));
+            let reserved: Option<String> = conn.set_options(&key, IN_PROGRESS, opts).await?;
+            if reserved.is_some() {
+                return Ok(Lookup::Acquired(InFlight::new(key)));
+            }
+
+            let existing: Option<Vec<u8>> = conn.get(&key).await?;
+            match existing {
+                Some(val) if val == IN_PROGRESS.as_bytes() => return Ok(Lookup::InProgress),
+                Some(val) => {
+                    let resp =
+                        serde_json::from_slice(&val).context("unable to parse stored response")?;
+                    return Ok(Lookup::Replay(resp));
+                }
+                None => continue,
+            }
+        }
+        Ok(Lookup::InProgress)
+    }
+
+    /// Completes the request, storing its response if it can be replayed,
+    /// and otherwise releasing the key so the request can be retried.
+    pub async fn complete(&self, inflight: InFlight, succeeded: bool) -> anyhow::Result<()> {
+        let mut conn = self.cluster.conn().await?;
+        let key = inflight.key.clone();
+        match inflight.into_stored().filter(|_| succeeded) {
+            Some(resp) => {
+                let val = serde_json::to_vec(&resp)?;
+                let _: () = conn.pset_ex(&key, val, self.ttl.as_millis() as u64).await?;
+            }
+            None => {
+                let _: () = conn.del(&key).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A request holding an idempotency key, whose response is being recorded.
+pub struct InFlight {
+    key: String,
+    resp: Option<ResponseHeader>,
+    body: BytesMut,
+    storable: bool,
+}
+
+impl InFlight {
+    fn new(key: String) -> Self {
+        Self {
+            key,
+            resp: None,
+            body: BytesMut::new(),
+            storable: true,
+        }
+    }
+
+    pub fn set_response(&mut self, resp: &ResponseHeader) {
+        // Only store successful responses. Errors are either expected to be
+        // retried, or depend on state that may change, such as credentials.
+        if resp.status.as_u16() >= 400 {
+            self.storable = false;
+        }
+        self.resp = Some(resp.clone());
+    }
+
+    pub fn push_body(&mut self, chunk: &Bytes) {
+        if !self.storable {
+            return;
+        }
+        if self.body.len() + chunk.len() > MAX_BODY_SIZE {
+            log::debug!("response too large to store for idempotency key");
+            self.storable = false;
+            self.body = BytesMut::new();
+            return;
+        }
+        self.body.extend_from_slice(chunk);
+    }
+
+    fn into_stored(self) -> Option<StoredResponse> {
+        let resp = self.resp.filter(|_| self.storable)?;
+        let headers = resp
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                !SKIP_HEADERS.contains(&name.as_str())
+                    && !name.as_str().starts_with("access-control-")
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        Some(StoredResponse {
+            status: resp.status.as_u16(),
+            headers,
+            body: base64::engine::general_purpose::STANDARD.encode(&self.body),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl StoredResponse {
+    pub fn into_parts(self) -> anyhow::Result<(ResponseHeader, Bytes)> {
+        let body = base64::engine::general_purpose::STANDARD
+            .decode(self.body)
+            .context("invalid stored response body")?;
+        let mut resp = ResponseHeader::build(self.status, Some(self.headers.len() + 2))?;
+        for (name, value) in self.headers {
+            resp.append_header(name, value)?;
+        }
+        resp.insert_header(http::header::CONTENT_LENGTH, body.len())?;
+        resp.insert_header(REPLAYED_HEADER, "true")?;
+        Ok((resp, body.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_response_roundtrip() {
+        let mut resp = ResponseHeader::build(201, None).unwrap();
+        resp.insert_header("content-type", "application/json")
+            .unwrap();
+        resp.insert_header("content-length", "9").unwrap();
+        resp.insert_header("access-control-allow-origin", "*")
+            .unwrap();
+
+        let mut inflight = InFlight::new("key".into());
+        inflight.set_response(&resp);
+        inflight.push_body(&Bytes::from_static(b"{\"id\":"));
+        inflight.push_body(&Bytes::from_static(b"42}"));
+
+        let stored = inflight.into_stored().unwrap();
+        assert_eq!(
+            stored.headers,
+            [("content-type".to_string(), "application/json".to_string())]
+        );
+
+        let (resp, body) = stored.into_parts().unwrap();
+        assert_eq!(resp.status.as_u16(), 201);
+        assert_eq!(resp.headers.get(REPLAYED_HEADER).unwrap(), "true");
+        assert_eq!(resp.headers.get("content-length").unwrap(), "9");
+        assert_eq!(body, Bytes::from_static(b"{\"id\":42}"));
+    }
+
+    #[test]
+    fn server_errors_are_not_stored() {
+        let mut inflight = InFlight::new("key".into());
+        inflight.set_response(&ResponseHeader::build(503, None).unwrap());
+        assert!(inflight.into_stored().is_none());
+    }
+
+    #[test]
+    fn client_errors_are_not_stored() {
+        for status in [401, 403, 409] {
+            let mut inflight = InFlight::new("key".into());
+            inflight.set_response(&ResponseHeader::build(status, None).unwrap());
+            assert!(inflight.into_stored().is_none());
+        }
+    }
+
+    #[test]
+    fn large_bodies_are_not_stored() {
+        let mut inflight = InFlight::new("key".into());
+        inflight.set_response(&ResponseHeader::build(200, None).unwrap());
+        inflight.push_body(&Bytes::from(vec![0; MAX_BODY_SIZE + 1]));
+        assert!(inflight.into_stored().is_none());
+    }
+}
@@ -1,4 +1,8 @@
+pub mod apikeys;
+pub mod audit;
+pub mod idempotency;
 mod router;
+pub mod validation;
 mod websocket;
 
 use std::borrow::Cow;
@@ -7,12 +11,15 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use anyhow::Context;
+use apikeys::ApiKeys;
+use audit::{AuditLogger, AuditRecord};
 use axum::async_trait;
 use bytes::{BufMut, Bytes, BytesMut};
 use http::uri::Scheme;
 use hyper::header;
+use idempotency::{Idempotency, Lookup};
+use pingora::apps::HttpServerOptions;
 use pingora::http::{RequestHeader, ResponseHeader};
-use pingora::protocols::http::error_resp;
 use pingora::proxy::{http_proxy_service, ProxyHttp, Session};
 use pingora::server::configuration::{Opt, ServerConf};
 use pingora::services::Service;
@@ -20,14 +27,18 @@ use pingora::upstreams::peer::HttpPeer;
 use pingora::{Error, ErrorSource, ErrorType, OkOrErr, OrErr};
 use router::Target;
 use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
 use url::Url;
+use validation::{BodyValidator, RequestValidation};
 
-use crate::api::auth;
 use crate::api::call::{CallDesc, ServiceRegistry};
 use crate::api::paths::PathSet;
 use crate::api::reqauth::caller::Caller;
+use crate::api::reqauth::meta::MetaKey;
 use crate::api::reqauth::{svcauth, CallMeta};
-use crate::{api, model, EncoreName};
+use crate::api::{auth, etag};
+use crate::encore::runtime::v1 as runtime;
+use crate::{api, model, EncoreName, EndpointName};
 
 use super::cors::cors_headers_config::CorsHeadersConfig;
 use super::encore_routes::healthz;
@@ -45,13 +56,45 @@ struct Inner {
     healthz: healthz::Handler,
     own_api_address: Option<SocketAddr>,
     proxied_push_subs: HashMap<String, EncoreName>,
+    audit_logger: Option<AuditLogger>,
+    idempotency: Option<Idempotency>,
+    oidc: Option<auth::OidcValidator>,
+    api_keys: Option<ApiKeys>,
+    request_validation: Option<RequestValidation>,
+    http_server: runtime::HttpServer,
+    tls: Option<runtime::gateway::Tls>,
+}
+
+#[derive(Default)]
+pub struct RequestCtx {
+    gateway: Option<GatewayCtx>,
+
+    /// The in-flight request holding an idempotency key, if any.
+    idempotency: Option<idempotency::InFlight>,
+
+    /// The result of authenticating the request, if it was authenticated
+    /// before routing to scope its idempotency key to the caller.
+    auth: Option<Option<auth::AuthResponse>>,
+
+    /// Validates the request body, if it is to be validated.
+    body_validator: Option<BodyValidator>,
+
+    /// Whether the response was replaced with 304 Not Modified,
+    /// in which case its body is dropped.
+    not_modified: bool,
 }
 
 pub struct GatewayCtx {
     upstream_service_name: EncoreName,
+    upstream_endpoint_name: Option<EndpointName>,
     upstream_base_path: String,
     upstream_host: Option<String>,
     upstream_require_auth: bool,
+    upstream_etag: bool,
+
+    /// The audit record for the request, if it is to be audited.
+    /// The response status is filled in once the request completes.
+    audit_record: Option<AuditRecord>,
 }
 
 impl GatewayCtx {
@@ -86,6 +129,13 @@ impl Gateway {
         healthz: healthz::Handler,
         own_api_address: Option<SocketAddr>,
         proxied_push_subs: HashMap<String, EncoreName>,
+        audit_logger: Option<AuditLogger>,
+        idempotency: Option<Idempotency>,
+        oidc: Option<auth::OidcValidator>,
+        api_keys: Option<ApiKeys>,
+        request_validation: Option<RequestValidation>,
+        http_server: runtime::HttpServer,
+        tls: Option<runtime::gateway::Tls>,
     ) -> anyhow::Result<Self> {
         let shared = Arc::new(SharedGatewayData {
             name,
@@ -104,6 +154,13 @@ impl Gateway {
                 healthz,
                 own_api_address,
                 proxied_push_subs,
+                audit_logger,
+                idempotency,
+                oidc,
+                api_keys,
+                request_validation,
+                http_server,
+                tls,
             }),
         })
     }
@@ -112,7 +169,40 @@ impl Gateway {
         self.inner.shared.auth.as_ref()
     }
 
-    pub async fn serve(self, listen_addr: &str) -> anyhow::Result<()> {
+    /// Authenticates the request using the gateway's OIDC validator and auth handler.
+    /// It returns None if the gateway has neither.
+    async fn authenticate(
+        &self,
+        req: &RequestHeader,
+        call_meta: &CallMeta,
+    ) -> api::APIResult<Option<auth::AuthResponse>> {
+        let auth_handler = self.inner.shared.auth.as_ref();
+        if let Some(oidc) = &self.inner.oidc {
+            let claims_only = oidc.replaces_handler() || auth_handler.is_none();
+            match oidc.validate(&req.headers).await {
+                // Requests with invalid tokens never reach the auth handler.
+                Err(error) => return Ok(Some(auth::AuthResponse::Unauthenticated { error })),
+                Ok(Some(claims)) if claims_only => return Ok(Some(oidc.authenticated(claims))),
+                Ok(None) if claims_only => {
+                    return Ok(Some(auth::AuthResponse::Unauthenticated {
+                        error: api::Error::unauthenticated(),
+                    }))
+                }
+                Ok(_) => {}
+            }
+        }
+
+        match auth_handler {
+            Some(auth_handler) => auth_handler
+                .authenticate(req, call_meta.clone())
+                .await
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Serves the gateway until the shutdown token is cancelled.
+    pub async fn serve(self, listen_addr: &str, shutdown: CancellationToken) -> anyhow::Result<()> {
         let conf = Arc::new(
             ServerConf::new_with_opt_override(&Opt {
                 upgrade: false,
@@ -123,11 +213,26 @@ impl Gateway {
             })
             .unwrap(),
         );
+        let h2c = self.inner.http_server.h2c.unwrap_or(false);
         let mut proxy = http_proxy_service(&conf, self);
+        if h2c {
+            if let Some(app) = proxy.app_logic_mut() {
+                app.server_options = Some(HttpServerOptions { h2c: true });
+            }
+        }
 
-        proxy.add_tcp(listen_addr);
+        match &self.inner.tls {
+            Some(tls) => proxy
+                .add_tls(listen_addr, &tls.cert_path, &tls.key_path)
+                .context("unable to load gateway TLS certificate")?,
+            None => proxy.add_tcp(listen_addr),
+        }
 
-        let (_tx, rx) = watch::channel(false);
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            let _ = tx.send(true);
+        });
         proxy
             .start_service(
                 #[cfg(unix)]
@@ -142,10 +247,10 @@ impl Gateway {
 
 #[async_trait]
 impl ProxyHttp for Gateway {
-    type CTX = Option<GatewayCtx>;
+    type CTX = RequestCtx;
 
     fn new_ctx(&self) -> Self::CTX {
-        None
+        RequestCtx::default()
     }
 
     // see https://github.com/cloudflare/pingora/blob/main/docs/user_guide/internals.md for
@@ -154,17 +259,38 @@ impl ProxyHttp for Gateway {
     async fn request_filter(
         &self,
         session: &mut Session,
-        _ctx: &mut Self::CTX,
+        ctx: &mut Self::CTX,
     ) -> pingora::Result<bool>
     where
         Self::CTX: Send + Sync,
     {
+        let http_server = &self.inner.http_server;
+        if let Some(max) = http_server.max_header_size {
+            let size: usize = session
+                .req_header()
+                .headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum();
+            if size > max as usize {
+                return Err(Error::explain(
+                    ErrorType::HTTPStatus(431),
+                    "request headers too large",
+                ));
+            }
+        }
+        if http_server.keep_alive == Some(false) {
+            session.set_keepalive(None);
+        } else if let Some(timeout) = &http_server.keep_alive_timeout {
+            session.set_keepalive(Some(timeout.seconds.max(1) as u64));
+        }
+
         if session.req_header().uri.path() == "/__encore/healthz" {
             let healthz_resp = self.inner.healthz.clone().health_check();
             let healthz_bytes: Vec<u8> = serde_json::to_vec(&healthz_resp)
                 .or_err(ErrorType::HTTPStatus(500), "could not encode response")?;
 
-            let mut header = ResponseHeader::build(200, None)?;
+            let mut header = ResponseHeader::build(healthz_resp.status().as_u16(), None)?;
             header.insert_header(header::CONTENT_LENGTH, healthz_bytes.len())?;
             header.insert_header(header::CONTENT_TYPE, "application/json")?;
             session
@@ -189,6 +315,81 @@ impl ProxyHttp for Gateway {
             return Ok(true);
         }
 
+        // API keys are checked before routing, except for Encore's own routes
+        // such as Pub/Sub push endpoints, which are authenticated separately.
+        if let Some(api_keys) = &self.inner.api_keys {
+            if !session.req_header().uri.path().starts_with("/__encore/") {
+                api_keys.check(&session.req_header().headers).await?;
+            }
+        }
+
+        if let Some(idempotency) = &self.inner.idempotency {
+            if idempotency.applies_to(session.req_header()) {
+                // Stored responses are only replayed to the caller they were
+                // returned to, so the request is authenticated first.
+                let req = session.req_header();
+                let call_meta = CallMeta::parse_without_caller(
+                    &req.headers,
+                    self.inner.service_registry.propagation(),
+                )
+                .or_err(
+                    ErrorType::InternalError,
+                    "couldn't parse CallMeta from request",
+                )?;
+                let auth_response = self
+                    .authenticate(req, &call_meta)
+                    .await
+                    .or_err(ErrorType::InternalError, "couldn't authenticate request")?;
+                let auth_uid = match &auth_response {
+                    Some(auth::AuthResponse::Authenticated { auth_uid, .. }) => {
+                        Some(auth_uid.clone())
+                    }
+                    _ => None,
+                };
+                ctx.auth = Some(auth_response);
+
+                // Requests without an authenticated caller are processed
+                // as if they had no idempotency key.
+                let key =
+                    auth_uid.and_then(|uid| idempotency.request_key(session.req_header(), &uid));
+                if let Some(key) = key {
+                    match idempotency.begin(key).await {
+                        Ok(Lookup::Acquired(inflight)) => {
+                            ctx.idempotency = Some(inflight);
+                        }
+                        Ok(Lookup::InProgress) => {
+                            return Err(api::Error {
+                                code: api::ErrCode::Aborted,
+                                message:
+                                    "a request with this idempotency key is already in progress"
+                                        .to_string(),
+                                internal_message: None,
+                                stack: None,
+                                details: None,
+                            }
+                            .into());
+                        }
+                        Ok(Lookup::Replay(stored)) => {
+                            let (mut resp, body) = stored.into_parts().or_err(
+                                ErrorType::InternalError,
+                                "couldn't replay stored response",
+                            )?;
+                            self.inner
+                                .cors_config
+                                .apply(session.req_header(), &mut resp)?;
+                            session.write_response_header(Box::new(resp), false).await?;
+                            session.write_response_body(Some(body), true).await?;
+                            return Ok(true);
+                        }
+                        Err(err) => {
+                            // Fail open: process the request as if it had no idempotency key.
+                            log::error!("unable to look up idempotency key: {:?}", err);
+                        }
+                    }
+                }
+            }
+        }
+
         Ok(false)
     }
 
@@ -205,7 +406,11 @@ impl ProxyHttp for Gateway {
             .and_then(|sub_id| self.inner.proxied_push_subs.get(sub_id))
             .map(|svc| Target {
                 service_name: svc.clone(),
+                endpoint_name: None,
                 requires_auth: false,
+                request: None,
+                body_limit: None,
+                etag: false,
             });
 
         if let Some(own_api_addr) = &self.inner.own_api_address {
@@ -213,26 +418,28 @@ impl ProxyHttp for Gateway {
                 return Ok(Box::new(HttpPeer::new(own_api_addr, false, "".to_string())));
             }
         }
-        let target = push_proxy_svc.map_or_else(
-            || {
+        let target = match push_proxy_svc {
+            Some(target) => target,
+            None => {
                 // Find which service handles the path route
-                session
-                    .req_header()
-                    .method
-                    .as_ref()
-                    .try_into()
-                    .map_err(|e: anyhow::Error| api::Error {
+                let method = session.req_header().method.as_ref().try_into().map_err(
+                    |e: anyhow::Error| api::Error {
                         code: api::ErrCode::InvalidArgument,
                         message: "invalid method".to_string(),
                         internal_message: Some(e.to_string()),
                         stack: None,
                         details: None,
-                    })
-                    .and_then(|method| self.inner.router.route_to_service(method, path))
-                    .cloned()
-            },
-            Ok,
-        )?;
+                    },
+                )?;
+                let (target, params) = self.inner.router.route_to_service(method, path)?;
+
+                if let Some(validation) = &self.inner.request_validation {
+                    ctx.body_validator =
+                        validation.validate_parts(target, session.req_header(), &params)?;
+                }
+                target.clone()
+            }
+        };
 
         let upstream = self
             .inner
@@ -264,11 +471,14 @@ impl ProxyHttp for Gateway {
         let host = upstream_url.host().map(|h| h.to_string());
         let peer = HttpPeer::new(upstream_addr, tls, host.clone().unwrap_or_default());
 
-        ctx.replace(GatewayCtx {
+        ctx.gateway = Some(GatewayCtx {
             upstream_base_path: upstream_url.path().to_string(),
             upstream_host: host,
             upstream_service_name: target.service_name.clone(),
+            upstream_endpoint_name: target.endpoint_name.clone(),
             upstream_require_auth: target.requires_auth,
+            upstream_etag: target.etag,
+            audit_record: None,
         });
 
         Ok(Box::new(peer))
@@ -283,15 +493,69 @@ impl ProxyHttp for Gateway {
     where
         Self::CTX: Send + Sync,
     {
-        if ctx.is_some() {
+        if ctx.gateway.is_some() {
             self.inner
                 .cors_config
                 .apply(session.req_header(), upstream_response)?;
         }
 
+        if let Some(inflight) = &mut ctx.idempotency {
+            inflight.set_response(upstream_response);
+        }
+
+        let etag = ctx.gateway.as_ref().is_some_and(|gw| gw.upstream_etag);
+        if etag
+            && etag::is_not_modified(
+                &session.req_header().method,
+                &session.req_header().headers,
+                upstream_response.status,
+                &upstream_response.headers,
+            )
+        {
+            for name in etag::not_modified_removed_headers(&upstream_response.headers) {
+                upstream_response.remove_header(&name);
+            }
+            upstream_response.set_status(http::StatusCode::NOT_MODIFIED)?;
+            ctx.not_modified = true;
+        }
+
+        Ok(())
+    }
+
+    async fn request_body_filter(
+        &self,
+        _session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()>
+    where
+        Self::CTX: Send + Sync,
+    {
+        if let Some(validator) = &mut ctx.body_validator {
+            validator.filter(body, end_of_stream)?;
+        }
         Ok(())
     }
 
+    fn response_body_filter(
+        &self,
+        _session: &mut Session,
+        body: &mut Option<Bytes>,
+        _end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<Option<std::time::Duration>>
+    where
+        Self::CTX: Send + Sync,
+    {
+        if ctx.not_modified {
+            *body = None;
+        } else if let (Some(inflight), Some(body)) = (&mut ctx.idempotency, body) {
+            inflight.push_body(body);
+        }
+        Ok(None)
+    }
+
     async fn upstream_request_filter(
         &self,
         session: &mut Session,
@@ -301,7 +565,7 @@ impl ProxyHttp for Gateway {
     where
         Self::CTX: Send + Sync,
     {
-        if let Some(gateway_ctx) = ctx.as_ref() {
+        if let Some(gateway_ctx) = ctx.gateway.as_mut() {
             let new_uri = gateway_ctx
                 .prepend_base_path(&upstream_request.uri)
                 .or_err(
@@ -367,12 +631,18 @@ impl ProxyHttp for Gateway {
                 .service_auth_method(&gateway_ctx.upstream_service_name)
                 .unwrap_or_else(|| Arc::new(svcauth::Noop));
 
+            // Deadlines set by external callers aren't trusted,
+            // so don't forward them as if they were set by the gateway.
+            upstream_request.remove_header(MetaKey::Deadline.header_key());
+
             let headers = &upstream_request.headers;
 
-            let mut call_meta = CallMeta::parse_without_caller(headers).or_err(
-                ErrorType::InternalError,
-                "couldn't parse CallMeta from request",
-            )?;
+            let mut call_meta =
+                CallMeta::parse_without_caller(headers, self.inner.service_registry.propagation())
+                    .or_err(
+                        ErrorType::InternalError,
+                        "couldn't parse CallMeta from request",
+                    )?;
             if call_meta.parent_span_id.is_none() {
                 call_meta.parent_span_id = Some(model::SpanId::generate());
             }
@@ -386,26 +656,49 @@ impl ProxyHttp for Gateway {
                     .parent_span_id
                     .map(|sp| call_meta.trace_id.with_span(sp)),
                 parent_event_id: None,
+                ext_parent_span: call_meta.ext_parent_span,
+                propagation: self.inner.service_registry.propagation(),
                 ext_correlation_id: call_meta
                     .ext_correlation_id
                     .as_ref()
                     .map(|s| Cow::Borrowed(s.as_str())),
+                deadline: call_meta.deadline,
                 auth_user_id: None,
                 auth_data: None,
                 svc_auth_method: svc_auth_method.as_ref(),
             };
 
-            if let Some(auth_handler) = &self.inner.shared.auth {
-                let auth_response = auth_handler
-                    .authenticate(upstream_request, call_meta.clone())
+            let auth_response = match ctx.auth.take() {
+                Some(auth_response) => auth_response,
+                None => self
+                    .authenticate(upstream_request, &call_meta)
                     .await
-                    .or_err(ErrorType::InternalError, "couldn't authenticate request")?;
-
+                    .or_err(ErrorType::InternalError, "couldn't authenticate request")?,
+            };
+            if let Some(auth_response) = auth_response {
                 match auth_response {
                     auth::AuthResponse::Authenticated {
                         auth_uid,
                         auth_data,
                     } => {
+                        if self.inner.audit_logger.is_some()
+                            && audit::is_mutating(&session.req_header().method)
+                        {
+                            gateway_ctx.audit_record = Some(AuditRecord {
+                                timestamp: chrono::Utc::now(),
+                                gateway: self.inner.shared.name.to_string(),
+                                user_id: auth_uid.clone(),
+                                service: gateway_ctx.upstream_service_name.to_string(),
+                                endpoint: gateway_ctx
+                                    .upstream_endpoint_name
+                                    .as_ref()
+                                    .map(|ep| ep.endpoint().to_string()),
+                                method: session.req_header().method.to_string(),
+                                path: session.req_header().uri.path().to_string(),
+                                status: 0,
+                                trace_id: call_meta.trace_id.serialize_encore(),
+                            });
+                        }
                         desc.auth_user_id = Some(Cow::Owned(auth_uid));
                         desc.auth_data = Some(auth_data);
                     }
@@ -424,6 +717,29 @@ impl ProxyHttp for Gateway {
         Ok(())
     }
 
+    async fn logging(&self, session: &mut Session, e: Option<&Error>, ctx: &mut Self::CTX)
+    where
+        Self::CTX: Send + Sync,
+    {
+        if let (Some(idempotency), Some(inflight)) =
+            (&self.inner.idempotency, ctx.idempotency.take())
+        {
+            if let Err(err) = idempotency.complete(inflight, e.is_none()).await {
+                log::error!("unable to store idempotent response: {:?}", err);
+            }
+        }
+
+        if let (Some(audit_logger), Some(mut record)) = (
+            &self.inner.audit_logger,
+            ctx.gateway.as_mut().and_then(|ctx| ctx.audit_record.take()),
+        ) {
+            record.status = session
+                .response_written()
+                .map_or(0, |resp| resp.status.as_u16());
+            audit_logger.record(record);
+        }
+    }
+
     async fn fail_to_proxy(&self, session: &mut Session, e: &Error, _ctx: &mut Self::CTX) -> u16
     where
         Self::CTX: Send + Sync,
@@ -452,19 +768,17 @@ impl ProxyHttp for Gateway {
             }
         };
 
-        let (mut resp, body) = if let Some(api_error) = as_api_error(e) {
-            let (resp, body) = api_error_response(api_error);
-            (resp, Some(body))
-        } else {
-            (
-                match code {
-                    /* common error responses are pre-generated */
-                    502 => error_resp::HTTP_502_RESPONSE.clone(),
-                    400 => error_resp::HTTP_400_RESPONSE.clone(),
-                    _ => error_resp::gen_error_response(code),
-                },
-                None,
-            )
+        // Respond with the same error format as handlers do, even when
+        // the error originates in the proxy itself.
+        let (mut resp, body) = match as_api_error(e) {
+            Some(api_error) => api_error_response(api_error),
+            None => {
+                let status =
+                    http::StatusCode::from_u16(code).unwrap_or(http::StatusCode::BAD_GATEWAY);
+                let mut api_error = api::Error::from_code(status.into());
+                api_error.internal_message = Some(e.to_string());
+                api_error_response(&api_error)
+            }
         };
 
         if let Err(e) = self
@@ -474,6 +788,7 @@ impl ProxyHttp for Gateway {
         {
             log::error!("failed setting cors header in error response: {e}");
         }
+        let code = resp.status.as_u16();
         session.set_keepalive(None);
         session
             .write_response_header(Box::new(resp), false)
@@ -483,7 +798,7 @@ impl ProxyHttp for Gateway {
             });
 
         session
-            .write_response_body(body, true)
+            .write_response_body(Some(body), true)
             .await
             .unwrap_or_else(|e| log::error!("failed to write body: {e}"));
 
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use crate::{
-    api::{self, paths::PathSet, schema::Method},
-    EncoreName,
+    api::{self, paths::PathSet, schema, schema::Method},
+    EncoreName, EndpointName,
 };
 
 #[derive(Clone)]
@@ -48,9 +48,18 @@ impl Router {
                         ::log::error!(method = method.as_str(), path = path; "tried to register same route twice, skipping");
                         continue;
                     }
+                    let request = endpoint
+                        .request
+                        .iter()
+                        .find(|req| req.methods.contains(&method))
+                        .cloned();
                     dst.replace(Target {
                         service_name: service.clone(),
+                        endpoint_name: Some(endpoint.name.clone()),
                         requires_auth: endpoint.requires_auth,
+                        request,
+                        body_limit: endpoint.body_limit,
+                        etag: endpoint.etag.is_some(),
                     });
                 }
             }
@@ -78,18 +87,19 @@ impl Router {
         Ok(())
     }
 
-    pub fn route_to_service(
-        &self,
+    /// Finds the target for the given method and path,
+    /// along with the path parameters of the matched route.
+    pub fn route_to_service<'a, 'p>(
+        &'a self,
         method: api::schema::Method,
-        path: &str,
-    ) -> Result<&Target, api::Error> {
+        path: &'p str,
+    ) -> Result<(&'a Target, matchit::Params<'a, 'p>), api::Error> {
         let mut found_path_match = false;
         for router in [&self.main, &self.fallback] {
-            if let Ok(service) = router.at(path) {
+            if let Ok(matched) = router.at(path) {
                 found_path_match = true;
-                let service = service.value.for_method(method);
-                if let Some(service) = service {
-                    return Ok(service);
+                if let Some(service) = matched.value.for_method(method) {
+                    return Ok((service, matched.params));
                 }
             }
         }
@@ -118,7 +128,17 @@ impl Router {
 #[derive(Clone, Debug)]
 pub struct Target {
     pub service_name: EncoreName,
+    pub endpoint_name: Option<EndpointName>,
     pub requires_auth: bool,
+
+    /// The request schema for the method, used for validating requests.
+    pub request: Option<Arc<schema::Request>>,
+
+    /// The maximum size of the request body, if limited.
+    pub body_limit: Option<u64>,
+
+    /// Whether the endpoint's responses carry ETags.
+    pub etag: bool,
 }
 
 #[derive(Clone, Default)]
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use percent_encoding::percent_decode_str;
+use pingora::http::RequestHeader;
+
+use crate::api::schema::{self, BodyFormat, RequestBody};
+use crate::api::{self, APIResult};
+use crate::encore::runtime::v1 as pb;
+
+use super::router::Target;
+
+/// The largest request body buffered for validation, if not configured.
+const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// Validates requests against the schemas of the endpoints they are routed to,
+/// so that invalid requests are rejected before they reach the service.
+pub struct RequestValidation {
+    max_body_size: u64,
+}
+
+impl RequestValidation {
+    pub fn new(cfg: &pb::gateway::RequestValidation) -> Self {
+        Self {
+            max_body_size: cfg.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE),
+        }
+    }
+
+    /// Validates the path parameters, query string, headers and cookies of a request.
+    /// If the request has a typed body, returns a validator for it.
+    pub fn validate_parts(
+        &self,
+        target: &Target,
+        req: &RequestHeader,
+        params: &matchit::Params<'_, '_>,
+    ) -> APIResult<Option<BodyValidator>> {
+        let Some(schema) = &target.request else {
+            return Ok(None);
+        };
+
+        // Streaming endpoints are parsed from the handshake,
+        // which is validated by the service.
+        if schema.stream {
+            return Ok(None);
+        }
+
+        let params = params
+            .iter()
+            .map(|(name, value)| {
+                let value = percent_decode_str(value)
+                    .decode_utf8()
+                    .map_err(|e| api::Error::invalid_argument("invalid path parameter", e))?;
+                Ok((name.to_string(), value.into_owned()))
+            })
+            .collect::<APIResult<Vec<_>>>()?;
+        schema.path.parse_params(params)?;
+
+        if let Some(query) = &schema.query {
+            query.validate(req.uri.query())?;
+        }
+        if let Some(header) = &schema.header {
+            header.parse(&req.headers)?;
+        }
+        if let Some(cookie) = &schema.cookie {
+            cookie.parse_req(&req.headers)?;
+        }
+
+        Ok(match &schema.body {
+            RequestBody::Typed(Some(_)) => Some(BodyValidator {
+                schema: schema.clone(),
+                format: BodyFormat::from_content_type(&req.headers),
+                buffer: BodyBuffer::new(self.max_body_size, target.body_limit),
+            }),
+            RequestBody::Typed(None) | RequestBody::Raw => None,
+        })
+    }
+}
+
+/// Validates a request body as it's proxied to the service.
+pub struct BodyValidator {
+    schema: Arc<schema::Request>,
+    format: BodyFormat,
+    buffer: BodyBuffer,
+}
+
+impl BodyValidator {
+    /// Buffers the body chunk, holding it back from the service
+    /// until the whole body has been received and validated.
+    pub fn filter(&mut self, body: &mut Option<Bytes>, end_of_stream: bool) -> APIResult<()> {
+        let Some(complete) = self.buffer.push(body, end_of_stream)? else {
+            return Ok(());
+        };
+
+        if let RequestBody::Typed(Some(schema)) = &self.schema.body {
+            schema.validate_request_body(&complete, self.format)?;
+        }
+        *body = Some(complete);
+        Ok(())
+    }
+}
+
+/// Buffers a request body up to a size limit.
+struct BodyBuffer {
+    buf: BytesMut,
+
+    /// The largest body to buffer. Larger bodies are passed through.
+    max_buffered: usize,
+
+    /// The largest body the endpoint accepts, if limited.
+    limit: Option<usize>,
+
+    /// Whether the body exceeded the buffer size and is being passed through.
+    passthrough: bool,
+}
+
+impl BodyBuffer {
+    fn new(max_buffered: u64, limit: Option<u64>) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            max_buffered: max_buffered as usize,
+            limit: limit.map(|l| l as usize),
+            passthrough: false,
+        }
+    }
+
+    /// Adds a body chunk to the buffer, taking it out of `body`.
+    /// Returns the complete body once the end of the stream is reached.
+    ///
+    /// If the body is larger than the buffer size, the buffered data is put
+    /// back into `body` and the rest of the body is passed through as is.
+    fn push(&mut self, body: &mut Option<Bytes>, end_of_stream: bool) -> APIResult<Option<Bytes>> {
+        if self.passthrough {
+            return Ok(None);
+        }
+
+        if let Some(chunk) = body.take() {
+            self.buf.extend_from_slice(&chunk);
+        }
+
+        if self.limit.is_some_and(|limit| self.buf.len() > limit) {
+            return Err(api::Error {
+                code: api::ErrCode::InvalidArgument,
+                message: "request body too large".into(),
+                internal_message: None,
+                stack: None,
+                details: None,
+            });
+        }
+
+        if end_of_stream {
+            return Ok(Some(self.buf.split().freeze()));
+        }
+
+        if self.buf.len() > self.max_buffered {
+            self.passthrough = true;
+            *body = Some(self.buf.split().freeze());
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_buffer() {
+        let mut buffer = BodyBuffer::new(1024, None);
+
+        // Chunks are held back until the end of the stream.
+        let mut body = Some(Bytes::from_static(b"{\"a\":"));
+        assert_eq!(buffer.push(&mut body, false).unwrap(), None);
+        assert_eq!(body, None);
+
+        let mut body = Some(Bytes::from_static(b"1}"));
+        assert_eq!(
+            buffer.push(&mut body, true).unwrap(),
+            Some(Bytes::from_static(b"{\"a\":1}"))
+        );
+    }
+
+    #[test]
+    fn test_body_buffer_passthrough() {
+        let mut buffer = BodyBuffer::new(4, None);
+
+        let mut body = Some(Bytes::from_static(b"abc"));
+        assert_eq!(buffer.push(&mut body, false).unwrap(), None);
+        assert_eq!(body, None);
+
+        // Exceeding the buffer size releases the buffered data.
+        let mut body = Some(Bytes::from_static(b"def"));
+        assert_eq!(buffer.push(&mut body, false).unwrap(), None);
+        assert_eq!(body, Some(Bytes::from_static(b"abcdef")));
+
+        // The rest of the body is passed through unvalidated.
+        let mut body = Some(Bytes::from_static(b"ghi"));
+        assert_eq!(buffer.push(&mut body, true).unwrap(), None);
+        assert_eq!(body, Some(Bytes::from_static(b"ghi")));
+    }
+
+    #[test]
+    fn test_body_buffer_limit() {
+        let mut buffer = BodyBuffer::new(1024, Some(4));
+
+        let mut body = Some(Bytes::from_static(b"abcdef"));
+        let err = buffer.push(&mut body, true).unwrap_err();
+        assert_eq!(err.code, api::ErrCode::InvalidArgument);
+    }
+}
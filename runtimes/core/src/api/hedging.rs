@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::encore::runtime::v1 as pb;
+
+const DEFAULT_PERCENTILE: f64 = 0.95;
+const DEFAULT_MIN_DELAY: Duration = Duration::from_millis(5);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// The number of latency samples to keep per service.
+const WINDOW_SIZE: usize = 256;
+
+/// The number of samples needed before the observed latency is used.
+/// Until then hedged requests are sent after the max delay.
+const MIN_SAMPLES: usize = 20;
+
+/// Builds the hedged request when it's dispatched.
+pub type HedgeRequest = Box<dyn FnOnce() -> reqwest::Request + Send>;
+
+/// Sends a second, hedged request for idempotent calls that are slower
+/// than a latency percentile of recent calls, and uses whichever response arrives first.
+pub struct Hedger {
+    percentile: f64,
+    min_delay: Duration,
+    max_delay: Duration,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl Hedger {
+    pub fn new(cfg: pb::service_discovery::HedgingPolicy) -> Self {
+        let duration = |d: Option<prost_types::Duration>| {
+            d.and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero())
+        };
+        let min_delay = duration(cfg.min_delay).unwrap_or(DEFAULT_MIN_DELAY);
+        let max_delay = duration(cfg.max_delay)
+            .unwrap_or(DEFAULT_MAX_DELAY)
+            .max(min_delay);
+        Self {
+            percentile: cfg
+                .percentile
+                .filter(|p| *p > 0.0 && *p <= 1.0)
+                .unwrap_or(DEFAULT_PERCENTILE),
+            min_delay,
+            max_delay,
+            latencies: Mutex::new(VecDeque::with_capacity(WINDOW_SIZE)),
+        }
+    }
+
+    /// Reports how long to wait for a response before sending a hedged request.
+    pub fn delay(&self) -> Duration {
+        let mut samples: Vec<Duration> = {
+            let latencies = self.latencies.lock().unwrap();
+            if latencies.len() < MIN_SAMPLES {
+                return self.max_delay;
+            }
+            latencies.iter().copied().collect()
+        };
+        samples.sort_unstable();
+
+        let idx = ((samples.len() as f64 * self.percentile).ceil() as usize)
+            .saturating_sub(1)
+            .min(samples.len() - 1);
+        samples[idx].clamp(self.min_delay, self.max_delay)
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == WINDOW_SIZE {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    /// Executes the primary request, sending the hedged request if the primary
+    /// hasn't completed within the hedging delay.
+    ///
+    /// The first response to arrive is used. If one of the requests fails
+    /// outright, the other one's response is used instead.
+    ///
+    /// The latency of every attempt that completes is recorded. When the hedge
+    /// wins, the time the primary has taken so far is recorded as well, as a lower
+    /// bound of its latency, so slow calls aren't left out of the observed latency.
+    pub async fn execute(
+        &self,
+        http_client: &reqwest::Client,
+        primary: reqwest::Request,
+        hedge: HedgeRequest,
+    ) -> reqwest::Result<reqwest::Response> {
+        let start = Instant::now();
+        let primary = self.timed(http_client.execute(primary), start);
+        tokio::pin!(primary);
+
+        tokio::select! {
+            res = &mut primary => return res,
+            _ = tokio::time::sleep(self.delay()) => {}
+        }
+
+        let hedge = hedge();
+        log::trace!(url = hedge.url().as_str(); "sending hedged request");
+        let hedge = self.timed(http_client.execute(hedge), Instant::now());
+        tokio::pin!(hedge);
+
+        tokio::select! {
+            res = &mut primary => match res {
+                Ok(resp) => Ok(resp),
+                Err(_) => hedge.await,
+            },
+            res = &mut hedge => match res {
+                Ok(resp) => {
+                    self.record(start.elapsed());
+                    Ok(resp)
+                }
+                Err(_) => primary.await,
+            },
+        }
+    }
+
+    /// Records the latency of the request if it completes successfully.
+    async fn timed(
+        &self,
+        req: impl std::future::Future<Output = reqwest::Result<reqwest::Response>>,
+        start: Instant,
+    ) -> reqwest::Result<reqwest::Response> {
+        let res = req.await;
+        if res.is_ok() {
+            self.record(start.elapsed());
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hedger(percentile: f64) -> Hedger {
+        Hedger::new(pb::service_discovery::HedgingPolicy {
+            percentile: Some(percentile),
+            min_delay: Some(prost_types::Duration {
+                seconds: 0,
+                nanos: 2_000_000,
+            }),
+            max_delay: Some(prost_types::Duration {
+                seconds: 0,
+                nanos: 500_000_000,
+            }),
+        })
+    }
+
+    #[test]
+    fn delay_uses_max_until_warmed_up() {
+        let h = hedger(0.9);
+        for _ in 0..MIN_SAMPLES - 1 {
+            h.record(Duration::from_millis(10));
+        }
+        assert_eq!(h.delay(), Duration::from_millis(500));
+
+        h.record(Duration::from_millis(10));
+        assert_eq!(h.delay(), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn delay_percentile() {
+        let h = hedger(0.9);
+        for i in 1..=100 {
+            h.record(Duration::from_millis(i));
+        }
+        assert_eq!(h.delay(), Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn records_completed_attempts() {
+        let h = hedger(0.9);
+        let resp = async { Ok(reqwest::Response::from(http::Response::new(""))) };
+        h.timed(resp, Instant::now()).await.unwrap();
+        assert_eq!(h.latencies.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn delay_is_clamped() {
+        let h = hedger(0.5);
+        for _ in 0..MIN_SAMPLES {
+            h.record(Duration::from_micros(100));
+        }
+        assert_eq!(h.delay(), Duration::from_millis(2));
+
+        let h = hedger(0.5);
+        for _ in 0..MIN_SAMPLES {
+            h.record(Duration::from_secs(3));
+        }
+        assert_eq!(h.delay(), Duration::from_millis(500));
+    }
+}
@@ -1,5 +1,7 @@
 use std::convert::Infallible;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use axum::body::HttpBody;
 use axum::http::Request;
@@ -7,8 +9,18 @@ use axum::response::Response;
 use axum::routing::future::RouteFuture;
 use axum::serve::IncomingStream;
 use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use tokio_util::sync::CancellationToken;
 use tower_service::Service;
 
+use crate::encore::runtime::v1 as pb;
+
+/// The smallest read buffer hyper accepts for HTTP/1 connections.
+const MIN_HTTP1_BUF_SIZE: usize = 8192;
+
 #[derive(Clone)]
 pub struct HttpServer {
     encore_routes: Router,
@@ -69,3 +81,83 @@ where
         router.call(req)
     }
 }
+
+impl HttpServer {
+    /// Serves requests on the listener until shutdown is requested,
+    /// then waits for in-flight connections to complete.
+    pub async fn serve(
+        self,
+        listener: tokio::net::TcpListener,
+        cfg: &pb::HttpServer,
+        shutdown: CancellationToken,
+    ) -> anyhow::Result<()> {
+        let builder = Arc::new(connection_builder(cfg));
+        let graceful = GracefulShutdown::new();
+
+        loop {
+            let stream = tokio::select! {
+                res = listener.accept() => match res {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        log::error!("unable to accept connection: {}", err);
+                        continue;
+                    }
+                },
+                _ = shutdown.cancelled() => break,
+            };
+
+            let builder = builder.clone();
+            let watcher = graceful.watcher();
+            let svc = TowerToHyperService::new(self.clone());
+            tokio::spawn(async move {
+                let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), svc);
+                if let Err(err) = watcher.watch(conn).await {
+                    log::trace!("connection error: {}", err);
+                }
+            });
+        }
+
+        graceful.shutdown().await;
+        Ok(())
+    }
+}
+
+fn connection_builder(cfg: &pb::HttpServer) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    if cfg.h2c == Some(false) {
+        builder = builder.http1_only();
+    }
+
+    let duration =
+        |d: &prost_types::Duration| Duration::new(d.seconds.max(0) as u64, d.nanos.max(0) as u32);
+
+    let mut http1 = builder.http1();
+    http1.timer(TokioTimer::new());
+    if let Some(keep_alive) = cfg.keep_alive {
+        http1.keep_alive(keep_alive);
+    }
+    if let Some(timeout) = &cfg.keep_alive_timeout {
+        // The header read timeout also applies while waiting
+        // for the next request on a kept-alive connection.
+        http1.header_read_timeout(duration(timeout));
+    }
+    if let Some(size) = cfg.max_header_size {
+        http1.max_buf_size((size as usize).max(MIN_HTTP1_BUF_SIZE));
+    }
+
+    let mut http2 = builder.http2();
+    http2.timer(TokioTimer::new());
+    if let Some(streams) = cfg.max_concurrent_streams {
+        http2.max_concurrent_streams(streams);
+    }
+    if let Some(interval) = &cfg.http2_keep_alive_interval {
+        let interval = duration(interval);
+        http2.keep_alive_interval(interval);
+        http2.keep_alive_timeout(interval);
+    }
+    if let Some(size) = cfg.max_header_size {
+        http2.max_header_list_size(size);
+    }
+
+    builder
+}
@@ -34,6 +34,9 @@ pub enum Value {
     /// Consume an array of values.
     Array(BasicOrValue),
 
+    /// Consume an array with a fixed number of values, each of their own type.
+    Tuple(Tuple),
+
     /// Consume a single value, one of a union of possible types.
     Union(Vec<BasicOrValue>),
 
@@ -61,6 +64,29 @@ impl Struct {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Tuple {
+    /// The types of the elements, by position.
+    pub elems: Vec<BasicOrValue>,
+    /// The number of leading elements that are required.
+    pub min_len: usize,
+}
+
+impl Tuple {
+    /// Reports an error unless the tuple accepts `len` elements.
+    fn check_len<E: serde::de::Error>(&self, len: usize) -> Result<(), E> {
+        if len < self.min_len || len > self.elems.len() {
+            let expected = if self.min_len == self.elems.len() {
+                format!("{}", self.elems.len())
+            } else {
+                format!("{} to {}", self.min_len, self.elems.len())
+            };
+            return Err(serde::de::Error::invalid_length(len, &expected.as_str()));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Field {
     pub value: BasicOrValue,
@@ -95,7 +121,7 @@ impl Value {
 
     pub fn expecting<'a>(&'a self, reg: &'a Registry) -> Cow<'a, str> {
         match self {
-            Value::Array(_) => Cow::Borrowed("a JSON array"),
+            Value::Array(_) | Value::Tuple(_) => Cow::Borrowed("a JSON array"),
             Value::Basic(basic) => Cow::Borrowed(basic.expecting()),
             Value::Map(_) => Cow::Borrowed("a JSON map"),
             Value::Literal(lit) => Cow::Owned(lit.expecting()),
@@ -340,7 +366,7 @@ impl<'de> Visitor<'de> for DecodeValue<'_> {
                 Basic::Decimal => "a decimal",
             }),
             Value::Map(_) => formatter.write_str("a JSON object"),
-            Value::Array(_) => formatter.write_str("a JSON array"),
+            Value::Array(_) | Value::Tuple(_) => formatter.write_str("a JSON array"),
             Value::Union(union) => {
                 let num = union.len();
                 let mut s = String::new();
@@ -723,6 +749,7 @@ impl<'de> Visitor<'de> for DecodeValue<'_> {
                     visit_seq(visitor, seq)
                 }
             },
+            Value::Tuple(tuple) => visit_tuple(self, tuple, seq),
             Value::Ref(idx) => recurse_ref!(self, idx, visit_seq, seq),
             Value::Option(bov) => recurse!(self, bov, visit_seq, seq),
             Value::Validation(v) => validate_pval!(self, v, visit_seq, seq),
@@ -948,6 +975,42 @@ where
     Ok(PValue::Array(vec))
 }
 
+fn visit_tuple<'de, A>(dv: DecodeValue, tuple: &Tuple, mut seq: A) -> Result<PValue, A::Error>
+where
+    A: SeqAccess<'de>,
+{
+    let mut vec = Vec::with_capacity(tuple.elems.len());
+    loop {
+        let elem = match tuple.elems.get(vec.len()) {
+            Some(BasicOrValue::Basic(basic)) => {
+                let basic_val = Value::Basic(*basic);
+                let visitor = DecodeValue {
+                    value: &basic_val,
+                    ..dv
+                };
+                seq.next_element_seed(visitor)?
+            }
+            Some(BasicOrValue::Value(idx)) => {
+                let visitor = DecodeValue {
+                    value: &dv.reg.values[*idx],
+                    ..dv
+                };
+                seq.next_element_seed(visitor)?
+            }
+            // Count the extra elements, to report the length in the error.
+            None => seq
+                .next_element::<serde::de::IgnoredAny>()?
+                .map(|_| PValue::Null),
+        };
+        match elem {
+            Some(elem) => vec.push(elem),
+            None => break,
+        }
+    }
+    tuple.check_len(vec.len())?;
+    Ok(PValue::Array(vec))
+}
+
 fn visit_map<'de, A>(elem: DecodeValue, mut map: A) -> Result<PValue, A::Error>
 where
     A: MapAccess<'de>,
@@ -1121,6 +1184,13 @@ impl DecodeValue<'_> {
                         Ok(())
                     }
                 },
+                Value::Tuple(tuple) => {
+                    tuple.check_len(array.len())?;
+                    for (bov, elem) in tuple.elems.iter().zip(array) {
+                        recurse!(self, bov, validate, elem)?;
+                    }
+                    Ok(())
+                }
                 Value::Ref(idx) => recurse_ref!(self, idx, validate, value),
                 Value::Option(bov) => {
                     for elem in array {
@@ -1288,6 +1358,15 @@ impl DecodeValue<'_> {
                     }
                     PValue::Array(new_vals)
                 }
+                Value::Tuple(tuple) => {
+                    tuple.check_len(vals.len())?;
+                    let mut new_vals = Vec::with_capacity(vals.len());
+                    for (bov, val) in tuple.elems.iter().zip(vals) {
+                        let val = recurse!(self, bov, transform, val)?;
+                        new_vals.push(val);
+                    }
+                    PValue::Array(new_vals)
+                }
                 Value::Union(candidates) => {
                     let val = JVal::Array(vals);
                     for c in candidates {
@@ -1411,7 +1490,7 @@ impl DecodeValue<'_> {
                         self,
                     ))
                 }
-                Value::Array(_) => {
+                Value::Array(_) | Value::Tuple(_) => {
                     return Err(serde::de::Error::invalid_type(Unexpected::Seq, self))
                 }
             },
@@ -1450,7 +1529,7 @@ impl DecodeValue<'_> {
                         self,
                     ));
                 }
-                Value::Map(_) | Value::Struct(_) | Value::Array(_) => {
+                Value::Map(_) | Value::Struct(_) | Value::Array(_) | Value::Tuple(_) => {
                     return Err(serde::de::Error::invalid_type(Unexpected::Str(&str), self))
                 }
             },
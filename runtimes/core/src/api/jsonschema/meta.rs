@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 
-use crate::api::jsonschema::de::{Basic, BasicOrValue, Field, Literal, Struct};
+use crate::api::jsonschema::de::{Basic, BasicOrValue, Field, Literal, Struct, Tuple};
 use crate::api::jsonschema::{JSONSchema, Registry, Value};
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::parser::schema::v1 as schema;
@@ -126,6 +126,7 @@ impl BuilderCtx<'_, '_> {
             Typ::Struct(st) => Ok(Value::Struct(self.struct_val(st)?)),
             Typ::Map(map) => self.map(map),
             Typ::List(list) => self.list(list),
+            Typ::Tuple(tuple) => self.tuple(tuple),
             Typ::Union(union) => self.union(union),
             Typ::Literal(lit) => self.literal(lit),
             Typ::Config(_) => anyhow::bail!("config not yet supported"),
@@ -280,6 +281,19 @@ impl BuilderCtx<'_, '_> {
         Ok(Value::Array(self.bov(value)))
     }
 
+    #[inline]
+    fn tuple(&mut self, tuple: &schema::Tuple) -> Result<Value> {
+        let elems: Result<Vec<BasicOrValue>> = tuple
+            .elems
+            .iter()
+            .map(|t| self.typ(t).map(|v| self.bov(v)))
+            .collect();
+        Ok(Value::Tuple(Tuple {
+            elems: elems?,
+            min_len: tuple.min_len as usize,
+        }))
+    }
+
     #[inline]
     fn union(&mut self, union: &schema::Union) -> Result<Value> {
         let values: Result<Vec<BasicOrValue>> = union
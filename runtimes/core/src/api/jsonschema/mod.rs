@@ -3,7 +3,7 @@ use std::sync::Arc;
 
 use serde::de::{DeserializeSeed, Deserializer};
 
-pub use de::{Basic, BasicOrValue, Field, Struct, Value};
+pub use de::{Basic, BasicOrValue, Field, Struct, Tuple, Value};
 
 pub use crate::api::jsonschema::de::DecodeConfig;
 use crate::api::jsonschema::de::DecodeValue;
@@ -131,6 +131,11 @@ impl Value {
             }
             Value::Option(v) => f.debug_struct("Option").field("value", &v).finish(),
             Value::Array(v) => f.debug_struct("Array").field("value", &v).finish(),
+            Value::Tuple(Tuple { elems, min_len }) => f
+                .debug_struct("Tuple")
+                .field("elems", &elems)
+                .field("min_len", &min_len)
+                .finish(),
             Value::Map(v) => f.debug_struct("Map").field("value", &v).finish(),
             Value::Union(v) => f.debug_struct("Union").field("types", &v).finish(),
             Value::Literal(v) => f.debug_struct("Literal").field("value", &v).finish(),
@@ -211,4 +216,51 @@ mod tests {
         let res = schema.deserialize(&mut jsonde, DecodeConfig::default());
         println!("{res:?}");
     }
+
+    #[test]
+    fn test_tuple() {
+        let reg = Arc::new(Registry {
+            values: vec![
+                Value::Struct(Struct {
+                    fields: HashMap::from([(
+                        "range".to_string(),
+                        Field {
+                            value: BasicOrValue::Value(1),
+                            optional: false,
+                            name_override: None,
+                        },
+                    )]),
+                }),
+                Value::Tuple(Tuple {
+                    elems: vec![
+                        BasicOrValue::Basic(Basic::String),
+                        BasicOrValue::Basic(Basic::Number),
+                        BasicOrValue::Basic(Basic::Bool),
+                    ],
+                    min_len: 2,
+                }),
+            ],
+        });
+        let schema = JSONSchema {
+            registry: reg,
+            root: 0,
+        };
+        let decode = |s: &str| {
+            let mut jsonde = serde_json::Deserializer::from_str(s);
+            schema.deserialize(&mut jsonde, DecodeConfig::default())
+        };
+
+        let res = decode(r#"{"range": ["a", 1]}"#).unwrap();
+        assert_eq!(
+            res["range"],
+            PValue::Array(vec![PValue::String("a".into()), PValue::Number(1.into())])
+        );
+        assert!(decode(r#"{"range": ["a", 1, true]}"#).is_ok());
+
+        // Elements are checked by position.
+        assert!(decode(r#"{"range": [1, "a"]}"#).is_err());
+        // The length must be within the tuple's bounds.
+        assert!(decode(r#"{"range": ["a"]}"#).is_err());
+        assert!(decode(r#"{"range": ["a", 1, true, false]}"#).is_err());
+    }
 }
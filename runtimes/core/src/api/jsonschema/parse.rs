@@ -1,4 +1,4 @@
-use crate::api::jsonschema::{Basic, BasicOrValue, JSONSchema, Registry, Struct, Value};
+use crate::api::jsonschema::{Basic, BasicOrValue, JSONSchema, Registry, Struct, Tuple, Value};
 use crate::api::{self, Cookie, PValue, PValues, SameSite};
 use crate::api::{schema, APIResult};
 use schema::ToHeaderStr;
@@ -154,7 +154,9 @@ fn parse_str_value(
 
         Value::Basic(basic) => parse_basic_str(basic, value_str),
 
-        Value::Struct { .. } | Value::Map(_) | Value::Array(_) => unsupported(reg, schema),
+        Value::Struct { .. } | Value::Map(_) | Value::Array(_) | Value::Tuple(_) => {
+            unsupported(reg, schema)
+        }
 
         Value::Literal(lit) => match lit {
             Literal::Str(want) if value_str == want => Ok(PValue::String(want.to_string())),
@@ -435,6 +437,35 @@ fn parse_json_value(this: PValue, reg: &Registry, schema: &Value) -> APIResult<P
             _ => unexpected_json(reg, schema, &this),
         },
 
+        Value::Tuple(Tuple { elems, min_len }) => match this {
+            PValue::Array(arr) if arr.len() >= *min_len && arr.len() <= elems.len() => {
+                let mut result = Vec::with_capacity(arr.len());
+                for (value_type, val) in elems.iter().zip(arr) {
+                    let value = match value_type {
+                        BasicOrValue::Basic(basic) => parse_basic_json(reg, basic, val)?,
+                        BasicOrValue::Value(idx) => parse_json_value(val, reg, &reg.values[*idx])?,
+                    };
+                    result.push(value);
+                }
+                Ok(PValue::Array(result))
+            }
+
+            PValue::Array(arr) => Err(api::Error {
+                code: api::ErrCode::InvalidArgument,
+                message: "invalid value".to_string(),
+                internal_message: Some(format!(
+                    "expected an array of {} to {} elements, got {}",
+                    min_len,
+                    elems.len(),
+                    arr.len()
+                )),
+                stack: None,
+                details: None,
+            }),
+
+            _ => unexpected_json(reg, schema, &this),
+        },
+
         Value::Union(types) => {
             // Find the first type that matches.
             for candidate in types {
@@ -1,11 +1,17 @@
 use std::collections::HashMap;
-use std::future::{Future, IntoFuture};
+use std::future::Future;
 use std::sync::{Arc, Mutex};
 
 use anyhow::Context;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
 
 use crate::api::auth::{LocalAuthHandler, RemoteAuthHandler};
 use crate::api::call::ServiceRegistry;
+use crate::api::gateway::apikeys::ApiKeys;
+use crate::api::gateway::audit::AuditLogger;
+use crate::api::gateway::idempotency::Idempotency;
+use crate::api::gateway::validation::RequestValidation;
 use crate::api::gateway::Gateway;
 use crate::api::http_server::HttpServer;
 use crate::api::paths::Pather;
@@ -13,13 +19,15 @@ use crate::api::reqauth::platform;
 use crate::api::schema::encoding::EncodingConfig;
 use crate::api::schema::JSONPayload;
 use crate::api::{
-    auth, cors, encore_routes, endpoints_from_meta, jsonschema, paths, reqauth, server, APIResult,
-    Endpoint, ToResponse,
+    auth, concurrency, cors, encore_routes, endpoints_from_meta, jsonschema, paths, reqauth,
+    respcache, server, APIResult, Endpoint, ToResponse,
 };
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as runtime;
 use crate::trace::Tracer;
-use crate::{api, metrics, model, pubsub, secrets, EncoreName, EndpointName, Hosted};
+use crate::{
+    api, cache, mesh, metrics, model, pubsub, secrets, sqldb, EncoreName, EndpointName, Hosted,
+};
 
 use super::encore_routes::healthz;
 use super::websocket_client::WebSocketClient;
@@ -37,9 +45,14 @@ pub struct ManagerConfig<'a> {
     pub secrets: &'a secrets::Manager,
     pub service_discovery: runtime::ServiceDiscovery,
     pub http_client: reqwest::Client,
+    pub mesh: Option<Arc<mesh::Manager>>,
     pub tracer: Tracer,
+    pub propagation: reqauth::Propagation,
     pub platform_validator: Arc<platform::RequestValidator>,
     pub pubsub_push_registry: pubsub::PushHandlerRegistry,
+    pub pubsub: &'a pubsub::Manager,
+    pub sqldb: &'a sqldb::Manager,
+    pub cache: &'a cache::Manager,
     pub runtime: tokio::runtime::Handle,
     pub testing: bool,
     pub proxied_push_subs: HashMap<String, EncoreName>,
@@ -54,11 +67,18 @@ pub struct Manager {
     pubsub_push_registry: pubsub::PushHandlerRegistry,
 
     api_server: Option<server::Server>,
+    /// Connection-level tuning of the API server.
+    api_server_cfg: runtime::HttpServer,
     runtime: tokio::runtime::Handle,
 
     gateways: HashMap<EncoreName, Gateway>,
     testing: bool,
     metrics: metrics::Manager,
+
+    /// Cancelled to stop accepting new requests.
+    shutdown: CancellationToken,
+    /// Whether the API server or gateway is currently serving requests.
+    serving: watch::Sender<bool>,
 }
 
 impl ManagerConfig<'_> {
@@ -104,8 +124,31 @@ impl ManagerConfig<'_> {
                 .strip_prefix("roll_")
                 .unwrap_or(&self.deploy_id)
                 .to_string(),
+            checks: self.secrets.report().iter().fold(
+                encore_routes::healthz::CheckCounts::default(),
+                |mut counts, s| {
+                    match s.error {
+                        Some(_) => counts.failed += 1,
+                        None => counts.passed += 1,
+                    }
+                    counts
+                },
+            ),
         };
 
+        let concurrency_limits = concurrency::Limits::new(&self.hosted_services);
+        let response_caches = respcache::Caches::new(
+            &self.hosted_services,
+            self.meta,
+            self.cache,
+            self.metrics.registry(),
+        )?;
+        let slos = metrics::slo::endpoint_trackers(&self.hosted_services, self.metrics.registry())?;
+        let api_server_cfg = self
+            .hosted_services
+            .iter()
+            .find_map(|s| s.http_server.clone())
+            .unwrap_or_default();
         let hosted_services = Hosted::from_iter(self.hosted_services.into_iter().map(|s| s.name));
         let (endpoints, hosted_endpoints) = endpoints_from_meta(self.meta, &hosted_services)
             .context("unable to compute endpoints descriptions")?;
@@ -134,7 +177,10 @@ impl ManagerConfig<'_> {
             &hosted_services,
             self.deploy_id.clone(),
             self.http_client.clone(),
+            self.mesh.clone(),
             self.tracer.clone(),
+            self.propagation,
+            &self.runtime,
         )
         .context("unable to create service registry")?;
         let service_registry = Arc::new(service_registry);
@@ -152,6 +198,7 @@ impl ManagerConfig<'_> {
         let routes = paths::compute(endpoints.values().map(|ep| RoutePerService(ep.to_owned())));
 
         let mut auth_data_schemas = HashMap::new();
+        let cors_loader = cors::SourceLoader::new(self.http_client.clone(), self.runtime.clone());
         for gw in &self.meta.gateways {
             let Some(gw_cfg) = hosted_gateways.get(gw.encore_name.as_str()) else {
                 continue;
@@ -170,10 +217,79 @@ impl ManagerConfig<'_> {
             )
             .context("unable to build authenticator")?;
 
-            let meta_headers = cors::MetaHeaders::from_schema(&endpoints, auth_handler.as_ref());
-            let cors_config = cors::config(cors_cfg, meta_headers)
+            let api_keys = gw_cfg
+                .api_keys
+                .as_ref()
+                .map(|cfg| {
+                    ApiKeys::new(
+                        &gw.encore_name.clone().into(),
+                        cfg,
+                        self.sqldb,
+                        self.metrics.registry(),
+                    )
+                })
+                .transpose()
+                .with_context(|| {
+                    format!(
+                        "unable to configure API keys for gateway {}",
+                        gw.encore_name
+                    )
+                })?;
+
+            let mut meta_headers =
+                cors::MetaHeaders::from_schema(&endpoints, auth_handler.as_ref());
+            if let Some(api_keys) = &api_keys {
+                meta_headers.allow_headers.insert(api_keys.header().clone());
+            }
+            let cors_config = cors::config(cors_cfg, meta_headers, Some(&cors_loader))
                 .context("failed to parse CORS configuration")?;
 
+            let audit_logger = gw_cfg
+                .audit_log
+                .as_ref()
+                .map(|cfg| {
+                    AuditLogger::new(
+                        &gw.encore_name.clone().into(),
+                        cfg,
+                        self.sqldb,
+                        self.pubsub,
+                        &self.runtime,
+                    )
+                })
+                .transpose()
+                .with_context(|| {
+                    format!(
+                        "unable to create audit logger for gateway {}",
+                        gw.encore_name
+                    )
+                })?;
+
+            let idempotency = gw_cfg
+                .idempotency
+                .as_ref()
+                .map(|cfg| Idempotency::new(gw.encore_name.clone().into(), cfg, self.cache))
+                .transpose()
+                .with_context(|| {
+                    format!(
+                        "unable to configure idempotency keys for gateway {}",
+                        gw.encore_name
+                    )
+                })?;
+
+            let oidc = gw_cfg
+                .oidc
+                .as_ref()
+                .map(|cfg| auth::OidcValidator::new(cfg, self.http_client.clone()))
+                .transpose()
+                .with_context(|| {
+                    format!("unable to configure OIDC for gateway {}", gw.encore_name)
+                })?;
+
+            let request_validation = gw_cfg
+                .request_validation
+                .as_ref()
+                .map(RequestValidation::new);
+
             auth_data_schemas.insert(
                 gw.encore_name.clone(),
                 auth_handler.as_ref().map(|ah| ah.auth_data().clone()),
@@ -190,6 +306,13 @@ impl ManagerConfig<'_> {
                     healthz_handler.clone(),
                     own_api_address,
                     self.proxied_push_subs.clone(),
+                    audit_logger,
+                    idempotency,
+                    oidc,
+                    api_keys,
+                    request_validation,
+                    gw_cfg.http_server.clone().unwrap_or_default(),
+                    gw_cfg.tls.clone(),
                 )
                 .context("couldn't create gateway")?,
             );
@@ -204,6 +327,12 @@ impl ManagerConfig<'_> {
                 self.tracer.clone(),
                 auth_data_schemas,
                 Arc::clone(self.metrics.registry()),
+                concurrency_limits,
+                response_caches,
+                slos,
+                // Check responses against their schemas in development,
+                // to catch drift between the types and the handlers early.
+                self.environment.env_type() == runtime::environment::Type::Development,
             )
             .context("unable to create API server")?;
             Some(server)
@@ -216,12 +345,15 @@ impl ManagerConfig<'_> {
             api_listener: Mutex::new(api_listener),
             service_registry,
             api_server,
+            api_server_cfg,
             gateways,
             pubsub_push_registry: self.pubsub_push_registry,
             runtime: self.runtime,
             healthz: healthz_handler,
             testing: self.testing,
             metrics: self.metrics.clone(),
+            shutdown: CancellationToken::new(),
+            serving: watch::channel(false).0,
         })
     }
 }
@@ -335,6 +467,10 @@ impl Manager {
         self.service_registry.api_call(target, data, source, opts)
     }
 
+    pub fn service_registry(&self) -> &Arc<ServiceRegistry> {
+        &self.service_registry
+    }
+
     pub fn endpoints(&self) -> &api::EndpointMap {
         self.service_registry.endpoints()
     }
@@ -386,14 +522,27 @@ impl Manager {
         // TODO handle multiple gateways
         let gateway = self.gateways.values().next().cloned();
         let testing = self.testing;
+        let api_server_cfg = self.api_server_cfg.clone();
+        let shutdown = self.shutdown.clone();
+        let serving = self.serving.clone();
+        serving.send_replace(true);
 
         self.runtime.spawn(async move {
+            // Mark the server as stopped when this task completes, however it completes.
+            struct StoppedGuard(watch::Sender<bool>);
+            impl Drop for StoppedGuard {
+                fn drop(&mut self) {
+                    self.0.send_replace(false);
+                }
+            }
+            let _stopped = StoppedGuard(serving);
+
             let gateway_parts = (gateway, gateway_listener);
             let gateway_fut = match gateway_parts {
                 (Some(gw), Some(ref ln)) => {
                     if !testing {
                         log::debug!(addr=ln; "gateway listening for incoming requests");
-                        Some(gw.serve(ln))
+                        Some(gw.serve(ln, shutdown.clone()))
                     } else {
                         // No need running the gateway in tests
                         None
@@ -420,27 +569,42 @@ impl Manager {
                         .context("unable to set nonblocking")?;
                     let axum_listener = tokio::net::TcpListener::from_std(ln)
                         .context("unable to convert listener to tokio")?;
-                    let fut = axum::serve(axum_listener, server).into_future();
-                    Some(fut)
+                    Some(server.serve(axum_listener, &api_server_cfg, shutdown.clone()))
                 }
                 None => None,
             };
 
-            tokio::select! {
-                res = async { gateway_fut.unwrap().await }, if gateway_fut.is_some() => {
-                    res.context("serve gateway").inspect_err(|err| log::error!("api gateway failed: {:?}", err))?;
+            if gateway_fut.is_none() && api_fut.is_none() {
+                // Nothing to serve.
+                ::log::debug!("no api server or gateway to serve");
+                return Ok(());
+            }
+
+            // Wait for both to complete, so that a graceful shutdown
+            // drains both of them. If either fails, stop serving.
+            tokio::try_join!(
+                async {
+                    match gateway_fut {
+                        Some(fut) => fut.await.context("serve gateway").inspect_err(|err| log::error!("api gateway failed: {:?}", err)),
+                        None => Ok(()),
+                    }
                 },
-                res = async { api_fut.unwrap().await }, if api_fut.is_some() => {
-                    res.context("serve api").inspect_err(|err| log::error!("api server failed: {:?}", err))?;
+                async {
+                    match api_fut {
+                        Some(fut) => fut.await.context("serve api").inspect_err(|err| log::error!("api server failed: {:?}", err)),
+                        None => Ok(()),
+                    }
                 },
-                else => {
-                    // Nothing to serve.
-                    ::log::debug!("no api server or gateway to serve");
-                }
-            };
+            )?;
             Ok(())
         })
     }
+
+    /// Stops accepting new requests and waits for in-flight requests to complete.
+    pub async fn shutdown(&self) {
+        self.shutdown.cancel();
+        let _ = self.serving.subscribe().wait_for(|serving| !*serving).await;
+    }
 }
 
 fn listen_addr() -> String {
@@ -1,10 +1,14 @@
 pub mod auth;
 pub mod call;
+mod concurrency;
 mod cors;
+mod discovery;
 mod encore_routes;
 mod endpoint;
 mod error;
+pub mod etag;
 pub mod gateway;
+mod hedging;
 mod http_server;
 mod httputil;
 pub mod jsonschema;
@@ -12,6 +16,7 @@ mod manager;
 mod paths;
 mod pvalue;
 pub mod reqauth;
+mod respcache;
 pub mod schema;
 mod server;
 mod static_assets;
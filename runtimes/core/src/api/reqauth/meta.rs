@@ -24,9 +24,14 @@ pub enum MetaKey {
     UserData,
     Caller,
     Callee,
+    Deadline,
     SvcAuthMethod,
     SvcAuthEncoreAuthHash,
     SvcAuthEncoreAuthDate,
+    B3,
+    B3TraceId,
+    B3SpanId,
+    B3Sampled,
 }
 
 impl MetaKey {
@@ -41,9 +46,14 @@ impl MetaKey {
             UserData => "x-encore-meta-authdata",
             Caller => "x-encore-meta-caller",
             Callee => "x-encore-meta-callee",
+            Deadline => "x-encore-meta-deadline",
             SvcAuthMethod => "x-encore-meta-svc-auth-method",
             SvcAuthEncoreAuthHash => "x-encore-meta-svc-auth",
             SvcAuthEncoreAuthDate => "x-encore-meta-date",
+            B3 => "b3",
+            B3TraceId => "x-b3-traceid",
+            B3SpanId => "x-b3-spanid",
+            B3Sampled => "x-b3-sampled",
         }
     }
 }
@@ -64,9 +74,14 @@ impl FromStr for MetaKey {
             "x-encore-meta-authdata" => UserData,
             "x-encore-meta-caller" => Caller,
             "x-encore-meta-callee" => Callee,
+            "x-encore-meta-deadline" => Deadline,
             "x-encore-meta-svc-auth-method" => SvcAuthMethod,
             "x-encore-meta-svc-auth" => SvcAuthEncoreAuthHash,
             "x-encore-meta-date" => SvcAuthEncoreAuthDate,
+            "b3" => B3,
+            "x-b3-traceid" => B3TraceId,
+            "x-b3-spanid" => B3SpanId,
+            "x-b3-sampled" => B3Sampled,
             _ => return Err(NotMetaKey),
         })
     }
@@ -52,6 +52,25 @@ pub fn service_auth_method(
     Ok(obj)
 }
 
+/// Which trace context formats to exchange with non-Encore services.
+/// W3C trace context is always used for calls between services.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Propagation {
+    /// Accept and emit B3 headers.
+    pub b3: bool,
+    /// Accept W3C trace context headers from non-Encore callers.
+    pub w3c: bool,
+}
+
+impl From<&pb::TracePropagation> for Propagation {
+    fn from(cfg: &pb::TracePropagation) -> Self {
+        Self {
+            b3: cfg.b3,
+            w3c: cfg.w3c,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CallMeta {
     /// The trace id to use. Equal to caller_trace_id if set, and generated otherwise.
@@ -63,6 +82,9 @@ pub struct CallMeta {
     /// The span id of the caller (None if there's no parent).
     pub parent_span_id: Option<model::SpanId>,
 
+    /// Whether parent_span_id was received from a non-Encore service.
+    pub ext_parent_span: bool,
+
     /// The span id of THIS request, if predefined by the caller (None in most cases).
     pub this_span_id: Option<model::SpanId>,
 
@@ -72,6 +94,9 @@ pub struct CallMeta {
     /// Correlation id to use.
     pub ext_correlation_id: Option<String>,
 
+    /// The time by which the caller expects the request to complete, if any.
+    pub deadline: Option<SystemTime>,
+
     /// Information about an internal call, if any.
     /// If set it can be trusted as it has been authenticated.
     pub internal: Option<InternalCallMeta>,
@@ -95,11 +120,20 @@ impl CallMeta {
         headers: &axum::http::HeaderMap,
         auth_data_schemas: &HashMap<String, Option<jsonschema::JSONSchema>>,
     ) -> APIResult<Self> {
-        Self::parse(headers, auth, true, Some(auth_data_schemas))
+        Self::parse(
+            headers,
+            auth,
+            true,
+            Some(auth_data_schemas),
+            Propagation::default(),
+        )
     }
 
-    pub fn parse_without_caller(headers: &axum::http::HeaderMap) -> APIResult<Self> {
-        Self::parse(headers, &[], false, None)
+    pub fn parse_without_caller(
+        headers: &axum::http::HeaderMap,
+        propagation: Propagation,
+    ) -> APIResult<Self> {
+        Self::parse(headers, &[], false, None, propagation)
     }
 
     fn parse(
@@ -107,6 +141,7 @@ impl CallMeta {
         auth: &[Arc<dyn svcauth::ServiceAuthMethod>],
         parse_caller: bool,
         auth_data_schemas: Option<&HashMap<String, Option<jsonschema::JSONSchema>>>,
+        propagation: Propagation,
     ) -> APIResult<Self> {
         let do_parse = move || -> anyhow::Result<CallMeta> {
             use meta::MetaKey;
@@ -121,9 +156,11 @@ impl CallMeta {
                 trace_id: model::TraceId::generate(),
                 caller_trace_id: None,
                 parent_span_id: None,
+                ext_parent_span: false,
                 this_span_id: None,
                 parent_event_id: None,
                 ext_correlation_id: None,
+                deadline: None,
                 internal: None,
             };
 
@@ -185,40 +222,49 @@ impl CallMeta {
                 };
             }
 
-            // For now we only read the traceparent for internal-to-internal calls, this is because CloudRun
-            // is adding a traceparent header to all requests, which is causing our trace system to get confused
-            // and think that the initial request is a child of another already traced request
-            //
-            // In the future we should be able to remove this check and read the traceparent header for all requests
-            // to interopt with other tracing systems.
-            if let Some(traceparent) = headers.get_meta(MetaKey::TraceParent) {
+            // Only continue the trace of non-Encore callers if enabled, as some platforms
+            // such as Cloud Run add a traceparent to every request, which would make
+            // every request look like it's part of another trace.
+            let traceparent = headers
+                .get_meta(MetaKey::TraceParent)
+                .filter(|_| meta.internal.is_some() || propagation.w3c);
+            if let Some(traceparent) = traceparent {
+                let state = parse_tracestate(headers.meta_values(MetaKey::TraceState));
+
                 // Parse the traceparent.
                 if let Ok((trace_id, parent_span_id)) = parse_traceparent(traceparent) {
                     meta.trace_id = trace_id;
                     meta.caller_trace_id = Some(trace_id);
                     meta.parent_span_id = Some(parent_span_id);
+                    meta.ext_parent_span = meta.internal.is_none() || state.ext_parent;
                 };
 
                 // If the caller is a gateway, ignore the parent span id as gateways don't currently record a span.
                 // If we include it the root request won't be tagged as such.
+                // A parent span the gateway received from a non-Encore service is kept, to join up with it.
                 if let Some(internal) = &meta.internal {
-                    if matches!(internal.caller, Caller::Gateway { .. }) {
+                    if matches!(internal.caller, Caller::Gateway { .. }) && !meta.ext_parent_span {
                         meta.parent_span_id = None;
                     }
                 }
 
                 // Parse the trace state.
-                if let (Some(event_id), parent_span) =
-                    parse_tracestate(headers.meta_values(MetaKey::TraceState))
-                {
+                if let Some(event_id) = state.event_id {
                     meta.parent_event_id = Some(event_id);
                     // If we where given a parent span ID, use that instead of the one from the traceparent header
                     // This is because GCP Cloud Run will add it's own spans in before the application code is run
                     // and thus we lose the parent span ID from the traceparent header
-                    if let Some(parent_span) = parent_span {
+                    if let Some(parent_span) = state.span_id {
                         meta.parent_span_id = Some(parent_span);
                     }
                 }
+            } else if propagation.b3 {
+                if let Some((trace_id, parent_span_id)) = parse_b3(headers) {
+                    meta.trace_id = trace_id;
+                    meta.caller_trace_id = Some(trace_id);
+                    meta.parent_span_id = Some(parent_span_id);
+                    meta.ext_parent_span = true;
+                }
             }
 
             meta.ext_correlation_id = headers.get_meta(MetaKey::XCorrelationId).map(|s| {
@@ -226,6 +272,17 @@ impl CallMeta {
                 s[..s.len().min(64)].to_string()
             });
 
+            // Only trust the deadline of authenticated internal calls, as anyone
+            // could otherwise have their queries cancelled early, or never.
+            // Ignore malformed deadlines rather than rejecting the request,
+            // as the deadline is only advisory.
+            if meta.internal.is_some() {
+                meta.deadline = headers
+                    .get_meta(MetaKey::Deadline)
+                    .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+                    .map(SystemTime::from);
+            }
+
             Ok(meta)
         };
 
@@ -276,12 +333,43 @@ fn parse_traceparent(s: &str) -> anyhow::Result<(model::TraceId, model::SpanId)>
     Ok((trace_id, span_id))
 }
 
-fn parse_tracestate<'a>(
-    vals: impl Iterator<Item = &'a str>,
-) -> (Option<model::TraceEventId>, Option<model::SpanId>) {
+/// Parses B3 headers, in either the single header or the multi header format.
+/// 64-bit trace ids are zero-padded to 128 bits.
+fn parse_b3<M: MetaMap>(headers: &M) -> Option<(model::TraceId, model::SpanId)> {
+    use meta::MetaKey;
+
+    let (trace_id, span_id) = match headers.get_meta(MetaKey::B3) {
+        Some(b3) => {
+            let mut parts = b3.split('-');
+            (parts.next()?, parts.next()?)
+        }
+        None => (
+            headers.get_meta(MetaKey::B3TraceId)?,
+            headers.get_meta(MetaKey::B3SpanId)?,
+        ),
+    };
+
+    let trace_id = match trace_id.len() {
+        16 => model::TraceId::parse_std(&format!("{:0>32}", trace_id)).ok()?,
+        _ => model::TraceId::parse_std(trace_id).ok()?,
+    };
+    let span_id = model::SpanId::parse_std(span_id).ok()?;
+    Some((trace_id, span_id))
+}
+
+#[derive(Debug, Default)]
+struct TraceState {
+    event_id: Option<model::TraceEventId>,
+    span_id: Option<model::SpanId>,
+    /// Whether the traceparent's span was received from a non-Encore service.
+    ext_parent: bool,
+}
+
+fn parse_tracestate<'a>(vals: impl Iterator<Item = &'a str>) -> TraceState {
     enum Data {
         EventId(model::TraceEventId),
         SpanId(model::SpanId),
+        ExtParent,
     }
 
     let parse_entry = |val: &str| -> Option<Data> {
@@ -290,22 +378,113 @@ fn parse_tracestate<'a>(
         match key {
             "encore/event-id" => Some(Data::EventId(val.parse().ok()?)),
             "encore/span-id" => Some(Data::SpanId(model::SpanId::parse_std(val).ok()?)),
+            "encore/ext-parent" if val == "1" => Some(Data::ExtParent),
             _ => None,
         }
     };
 
-    let mut event_id = None;
-    let mut span_id = None;
+    let mut state = TraceState::default();
 
     for val in vals {
         for field in val.split(',') {
-            match parse_entry(field) {
-                Some(Data::EventId(id)) => event_id = Some(id),
-                Some(Data::SpanId(id)) => span_id = Some(id),
+            match parse_entry(field.trim()) {
+                Some(Data::EventId(id)) => state.event_id = Some(id),
+                Some(Data::SpanId(id)) => state.span_id = Some(id),
+                Some(Data::ExtParent) => state.ext_parent = true,
                 None => (),
             }
         }
     }
 
-    (event_id, span_id)
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> axum::http::HeaderMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.parse().unwrap(), v.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn b3() {
+        let trace_id = "80f198ee56343ba864fe8b2a57d3eff7";
+        let span_id = "e457b5a2e4d86bd1";
+
+        let single = headers(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1")]);
+        let (t, s) = parse_b3(&single).unwrap();
+        assert_eq!(t.serialize_std(), trace_id);
+        assert_eq!(s.serialize_std(), span_id);
+
+        let multi = headers(&[
+            ("x-b3-traceid", "64fe8b2a57d3eff7"),
+            ("x-b3-spanid", "e457b5a2e4d86bd1"),
+            ("x-b3-sampled", "1"),
+        ]);
+        let (t, s) = parse_b3(&multi).unwrap();
+        assert_eq!(t.serialize_std(), "000000000000000064fe8b2a57d3eff7");
+        assert_eq!(s.serialize_std(), span_id);
+
+        // A sampling decision alone doesn't carry a trace context.
+        assert!(parse_b3(&headers(&[("b3", "0")])).is_none());
+    }
+
+    #[test]
+    fn external_parent() {
+        let b3 = headers(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1")]);
+        let propagation = Propagation {
+            b3: true,
+            w3c: false,
+        };
+        let meta = CallMeta::parse_without_caller(&b3, propagation).unwrap();
+        assert_eq!(
+            meta.caller_trace_id.map(|t| t.serialize_std()).as_deref(),
+            Some("80f198ee56343ba864fe8b2a57d3eff7")
+        );
+        assert!(meta.ext_parent_span);
+
+        // B3 headers are ignored unless enabled.
+        let meta = CallMeta::parse_without_caller(&b3, Propagation::default()).unwrap();
+        assert!(meta.caller_trace_id.is_none());
+
+        let state =
+            parse_tracestate(["encore/span-id=e457b5a2e4d86bd1, encore/ext-parent=1"].into_iter());
+        assert!(state.ext_parent);
+        assert!(state.event_id.is_none());
+    }
+
+    #[test]
+    fn external_traceparent() {
+        let h = headers(&[(
+            "traceparent",
+            "00-80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-01",
+        )]);
+
+        // Ignored unless enabled, as platforms may add one to every request.
+        let meta = CallMeta::parse_without_caller(&h, Propagation::default()).unwrap();
+        assert!(meta.caller_trace_id.is_none());
+        assert!(!meta.ext_parent_span);
+
+        let propagation = Propagation {
+            b3: false,
+            w3c: true,
+        };
+        let meta = CallMeta::parse_without_caller(&h, propagation).unwrap();
+        assert_eq!(
+            meta.caller_trace_id.map(|t| t.serialize_std()).as_deref(),
+            Some("80f198ee56343ba864fe8b2a57d3eff7")
+        );
+        assert!(meta.ext_parent_span);
+    }
+
+    #[test]
+    fn untrusted_deadline() {
+        let h = headers(&[("x-encore-meta-deadline", "2030-01-01T00:00:00Z")]);
+        let meta = CallMeta::parse_without_caller(&h, Propagation::default()).unwrap();
+        assert!(meta.deadline.is_none());
+    }
 }
@@ -202,12 +202,12 @@ impl EncoreAuth {
                     // Skip these headers, as they are part of the auth mechanism itself.
                 }
 
-                TraceParent | TraceState => {
+                TraceParent | TraceState | B3 | B3TraceId | B3SpanId | B3Sampled => {
                     // Skip these headers, as they are part of the tracing mechanism and could be changed
                     // by things like load balancers.
                 }
 
-                XCorrelationId | Version | UserId | UserData | Caller | Callee => {
+                XCorrelationId | Version | UserId | UserData | Caller | Callee | Deadline => {
                     // Read all values for this key, and sort them.
                     let mut values = req.meta_values(key).collect::<Vec<_>>();
                     values.sort();
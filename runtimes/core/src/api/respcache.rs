@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use base64::Engine;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::api::schema;
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::metrics::counter;
+use crate::{cache, model, EncoreName, EndpointName};
+
+const DEFAULT_KEY: &str = "{path}?{query}";
+
+/// Responses with larger bodies than this are not cached.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Response headers that are not cached, since they are either
+/// connection-specific or differ between requests.
+const SKIP_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "date",
+    "keep-alive",
+    "transfer-encoding",
+    "x-encore-trace-id",
+    "x-encore-response-schema-mismatch",
+];
+
+/// Set on responses served from the cache.
+pub const CACHE_HIT_HEADER: &str = "x-encore-cache";
+
+/// Caches successful responses to GET requests in Redis.
+pub struct ResponseCache {
+    cluster: Arc<cache::Cluster>,
+    endpoint: EndpointName,
+    ttl: Duration,
+    key: Vec<KeyPart>,
+    vary_by_headers: Vec<HeaderName>,
+    vary_by_auth: bool,
+    lookups_total: counter::Schema<u64>,
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("endpoint", &self.endpoint)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum KeyPart {
+    Literal(String),
+    Path,
+    Query,
+    Param(String),
+    Header(HeaderName),
+    UserId,
+}
+
+impl ResponseCache {
+    /// Creates the cache for the endpoint. Responses to endpoints that
+    /// require auth are always cached per user, regardless of `vary_by_auth`,
+    /// so that users are never served each other's responses.
+    pub fn new(
+        endpoint: EndpointName,
+        cfg: &pb::ResponseCache,
+        requires_auth: bool,
+        cache: &cache::Manager,
+        metrics: &Arc<crate::metrics::Registry>,
+    ) -> anyhow::Result<Self> {
+        let cluster = cache
+            .cluster(&EncoreName::from(&cfg.redis))
+            .with_context(|| format!("redis database {} not found", cfg.redis))?;
+        let ttl = cfg
+            .ttl
+            .and_then(|d| Duration::try_from(d).ok())
+            .filter(|d| !d.is_zero())
+            .context("response cache ttl must be positive")?;
+        let key = parse_key(cfg.key.as_deref().unwrap_or(DEFAULT_KEY))?;
+        let vary_by_headers = cfg
+            .vary_by_headers
+            .iter()
+            .map(|h| h.parse().with_context(|| format!("invalid header {h}")))
+            .collect::<anyhow::Result<_>>()?;
+
+        let lookups_total = metrics
+            .counter_schema::<u64>("e_response_cache_lookups_total")
+            .static_labels([
+                ("service", endpoint.service()),
+                ("endpoint", endpoint.endpoint()),
+            ])
+            .require_dynamic_key("result")
+            .build();
+
+        Ok(Self {
+            cluster,
+            endpoint,
+            ttl,
+            key,
+            vary_by_headers,
+            vary_by_auth: cfg.vary_by_auth || requires_auth,
+            lookups_total,
+        })
+    }
+
+    /// Returns the storage key for the request,
+    /// or None if its response can't be cached.
+    pub fn request_key(&self, req: &model::Request, format: schema::BodyFormat) -> Option<String> {
+        let model::RequestData::RPC(data) = &req.data else {
+            return None;
+        };
+        if data.method != schema::Method::GET {
+            return None;
+        }
+
+        let (path, query) = data
+            .path_and_query
+            .split_once('?')
+            .unwrap_or((&data.path_and_query, ""));
+        let header = |name: &HeaderName| {
+            data.req_headers
+                .get(name)
+                .map_or(&b""[..], HeaderValue::as_bytes)
+        };
+
+        let mut parts: Vec<Vec<u8>> = Vec::with_capacity(self.key.len() + 4);
+        let mut rendered = Vec::new();
+        for part in &self.key {
+            match part {
+                KeyPart::Literal(s) => rendered.extend_from_slice(s.as_bytes()),
+                KeyPart::Path => rendered.extend_from_slice(path.as_bytes()),
+                KeyPart::Query => rendered.extend_from_slice(query.as_bytes()),
+                KeyPart::Param(name) => {
+                    if let Some(value) = data.path_params.as_ref().and_then(|p| p.get(name)) {
+                        rendered.extend_from_slice(value.to_string().as_bytes());
+                    }
+                }
+                KeyPart::Header(name) => rendered.extend_from_slice(header(name)),
+                KeyPart::UserId => {
+                    let user_id = data.auth_user_id.as_deref().unwrap_or_default();
+                    rendered.extend_from_slice(user_id.as_bytes())
+                }
+            }
+        }
+        parts.push(rendered);
+        parts.push(format.content_type().as_bytes().to_vec());
+        for name in &self.vary_by_headers {
+            parts.push(header(name).to_vec());
+        }
+        if self.vary_by_auth {
+            let user_id = data.auth_user_id.as_deref().unwrap_or_default();
+            parts.push(user_id.as_bytes().to_vec());
+        }
+
+        // Length-prefix each part so different parts can't run together.
+        let mut hasher = Sha256::new();
+        for part in &parts {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        let hash = hex::encode(hasher.finalize());
+        Some(
+            self.cluster
+                .key(&format!("encore:respcache:{}:{}", self.endpoint, hash)),
+        )
+    }
+
+    /// Looks up a cached response. Lookup failures are logged
+    /// and reported as misses, so the handler is called instead.
+    pub async fn get(&self, key: &str) -> Option<axum::http::Response<axum::body::Body>> {
+        let result: anyhow::Result<Option<_>> = async {
+            let mut conn = self.cluster.conn().await?;
+            let val: Option<Vec<u8>> = conn.get(key).await?;
+            val.map(|val| -> anyhow::Result<_> {
+                let cached: CachedResponse =
+                    serde_json::from_slice(&val).context("unable to parse cached response")?;
+                cached.into_response()
+            })
+            .transpose()
+        }
+        .await;
+
+        let resp = result.unwrap_or_else(|err| {
+            log::warn!("unable to look up cached response: {:?}", err);
+            None
+        });
+        let label = if resp.is_some() { "hit" } else { "miss" };
+        self.lookups_total.with([("result", label)]).increment();
+        resp
+    }
+
+    /// Stores a response in the background, if it can be cached.
+    pub fn put(&self, key: String, parts: &axum::http::response::Parts, body: &[u8]) {
+        let Some(cached) = CachedResponse::new(parts, body) else {
+            return;
+        };
+        let cluster = self.cluster.clone();
+        let ttl = self.ttl;
+        tokio::spawn(async move {
+            let result = async {
+                let mut conn = cluster.conn().await?;
+                let val = serde_json::to_vec(&cached)?;
+                let _: () = conn.pset_ex(&key, val, ttl.as_millis() as u64).await?;
+                anyhow::Ok(())
+            }
+            .await;
+            if let Err(err) = result {
+                log::warn!("unable to store cached response: {:?}", err);
+            }
+        });
+    }
+}
+
+fn parse_key(template: &str) -> anyhow::Result<Vec<KeyPart>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(KeyPart::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .with_context(|| format!("unterminated placeholder in cache key {template:?}"))?;
+        let placeholder = &rest[start + 1..end];
+        parts.push(match placeholder.split_once(':') {
+            None if placeholder == "path" => KeyPart::Path,
+            None if placeholder == "query" => KeyPart::Query,
+            None if placeholder == "user_id" => KeyPart::UserId,
+            Some(("param", name)) => KeyPart::Param(name.to_string()),
+            Some(("header", name)) => KeyPart::Header(
+                name.parse()
+                    .with_context(|| format!("invalid header {name} in cache key"))?,
+            ),
+            _ => anyhow::bail!("unknown placeholder {{{placeholder}}} in cache key"),
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(KeyPart::Literal(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl CachedResponse {
+    fn new(parts: &axum::http::response::Parts, body: &[u8]) -> Option<Self> {
+        if parts.status != StatusCode::OK || body.len() > MAX_BODY_SIZE {
+            return None;
+        }
+        let headers = parts
+            .headers
+            .iter()
+            .filter(|(name, _)| !SKIP_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        Some(Self {
+            headers,
+            body: base64::engine::general_purpose::STANDARD.encode(body),
+        })
+    }
+
+    fn into_response(self) -> anyhow::Result<axum::http::Response<axum::body::Body>> {
+        let body = base64::engine::general_purpose::STANDARD
+            .decode(self.body)
+            .context("invalid cached response body")?;
+        let mut resp = axum::http::Response::builder();
+        for (name, value) in self.headers {
+            resp = resp.header(name, value);
+        }
+        let resp = resp
+            .header(CACHE_HIT_HEADER, "hit")
+            .body(axum::body::Body::from(body))?;
+        Ok(resp)
+    }
+}
+
+/// The response caches of all hosted endpoints.
+#[derive(Debug, Default)]
+pub struct Caches {
+    endpoints: HashMap<EndpointName, Arc<ResponseCache>>,
+}
+
+impl Caches {
+    pub fn new(
+        hosted_services: &[pb::HostedService],
+        md: &meta::Data,
+        cache: &cache::Manager,
+        metrics: &Arc<crate::metrics::Registry>,
+    ) -> anyhow::Result<Self> {
+        let mut caches = Self::default();
+        for svc in hosted_services {
+            for (endpoint, cfg) in &svc.response_caches {
+                let name = EndpointName::new(svc.name.as_str(), endpoint.as_str());
+                let requires_auth = md
+                    .svcs
+                    .iter()
+                    .filter(|s| s.name == svc.name)
+                    .flat_map(|s| &s.rpcs)
+                    .any(|rpc| rpc.name == *endpoint && !rpc.allow_unauthenticated);
+                let rc = ResponseCache::new(name.clone(), cfg, requires_auth, cache, metrics)
+                    .with_context(|| format!("unable to configure response cache for {name}"))?;
+                caches.endpoints.insert(name, Arc::new(rc));
+            }
+        }
+        Ok(caches)
+    }
+
+    pub fn for_endpoint(&self, name: &EndpointName) -> Option<Arc<ResponseCache>> {
+        self.endpoints.get(name).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(
+            parse_key("{path}?{query}").unwrap(),
+            [KeyPart::Path, KeyPart::Literal("?".into()), KeyPart::Query]
+        );
+        assert_eq!(
+            parse_key("user:{user_id}:{param:id}:{header:Accept-Language}").unwrap(),
+            [
+                KeyPart::Literal("user:".into()),
+                KeyPart::UserId,
+                KeyPart::Literal(":".into()),
+                KeyPart::Param("id".into()),
+                KeyPart::Literal(":".into()),
+                KeyPart::Header(HeaderName::from_static("accept-language")),
+            ]
+        );
+        assert!(parse_key("{body}").is_err());
+        assert!(parse_key("{path").is_err());
+    }
+
+    #[test]
+    fn test_cached_response_roundtrip() {
+        let (mut parts, _) = axum::http::Response::new(()).into_parts();
+        parts
+            .headers
+            .insert("content-type", HeaderValue::from_static("application/json"));
+        parts
+            .headers
+            .insert("x-encore-trace-id", HeaderValue::from_static("abc"));
+
+        let cached = CachedResponse::new(&parts, b"{\"id\":42}").unwrap();
+        assert_eq!(
+            cached.headers,
+            [("content-type".to_string(), "application/json".to_string())]
+        );
+        let resp = cached.into_response().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[CACHE_HIT_HEADER], "hit");
+
+        parts.status = StatusCode::NOT_FOUND;
+        assert!(CachedResponse::new(&parts, b"").is_none());
+    }
+}
@@ -2,9 +2,9 @@ use axum::http::HeaderValue;
 use bytes::Bytes;
 
 use crate::api::jsonschema::DecodeConfig;
-use crate::api::schema::{JSONPayload, ToOutgoingRequest};
+use crate::api::schema::{invalid_field, BodyFormat, BodyWriter, JSONPayload, ToOutgoingRequest};
 use crate::api::{self, PValues};
-use crate::api::{jsonschema, APIResult};
+use crate::api::{etag, jsonschema, APIResult};
 use http_body_util::BodyExt;
 use reqwest::header::CONTENT_TYPE;
 
@@ -21,6 +21,7 @@ impl Body {
     pub async fn parse_incoming_request_body(
         &self,
         body: axum::body::Body,
+        format: BodyFormat,
     ) -> APIResult<Option<PValues>> {
         // Collect the bytes of the request body.
         // Serde doesn't support async streaming reads (at least not yet).
@@ -30,32 +31,98 @@ impl Body {
             .map_err(|e| api::Error::invalid_argument("unable to read request body", e))?
             .to_bytes();
 
-        let mut jsonde = serde_json::Deserializer::from_slice(&bytes);
-        let cfg = DecodeConfig {
-            coerce_strings: false,
-            arrays_as_repeated_fields: false,
-        };
-        let value = self
-            .schema
-            .deserialize(&mut jsonde, cfg)
-            .map_err(|e| api::Error::invalid_argument("unable to decode request body", e))?;
+        let value = self.decode(&bytes, format, |e| {
+            api::Error::invalid_argument("unable to decode request body", e)
+        })?;
+        Ok(Some(value))
+    }
+
+    /// Validates an incoming request body against the schema,
+    /// reporting the offending field if it doesn't match.
+    pub fn validate_request_body(&self, bytes: &[u8], format: BodyFormat) -> APIResult<()> {
+        self.decode(bytes, format, |e| {
+            invalid_field("unable to decode request body", e)
+        })?;
+        Ok(())
+    }
+
+    /// Validates a handler's response payload against the schema,
+    /// reporting the offending field if it doesn't match.
+    pub fn validate_response_payload(&self, payload: &JSONPayload) -> APIResult<()> {
+        let bytes = self.schema.to_vec(payload).map_err(api::Error::internal)?;
+        self.decode(&bytes, BodyFormat::Json, |e| {
+            invalid_field("response does not match schema", e)
+        })?;
+        Ok(())
+    }
+
+    pub async fn parse_response_body(
+        &self,
+        body: Bytes,
+        format: BodyFormat,
+    ) -> APIResult<Option<PValues>> {
+        let value = self.decode(&body, format, |e| {
+            api::Error::invalid_argument("unable to decode response body", e)
+        })?;
         Ok(Some(value))
     }
 
-    pub async fn parse_response_body(&self, body: Bytes) -> APIResult<Option<PValues>> {
-        let mut jsonde = serde_json::Deserializer::from_slice(&body);
+    /// Decodes the body according to the schema, converting
+    /// decoding errors into API errors with `map_err`.
+    fn decode<F>(&self, bytes: &[u8], format: BodyFormat, map_err: F) -> APIResult<PValues>
+    where
+        F: FnOnce(serde_path_to_error::Error<DecodeError>) -> api::Error,
+    {
         let cfg = DecodeConfig {
             coerce_strings: false,
             arrays_as_repeated_fields: false,
         };
-        let value = self
-            .schema
-            .deserialize(&mut jsonde, cfg)
-            .map_err(|e| api::Error::invalid_argument("unable to decode response body", e))?;
-        Ok(Some(value))
+        let result = match format {
+            BodyFormat::Json => {
+                let mut de = serde_json::Deserializer::from_slice(bytes);
+                self.schema
+                    .deserialize(&mut de, cfg)
+                    .map_err(|e| map_path_error(e, DecodeError::Json))
+            }
+            BodyFormat::MsgPack => {
+                let mut de = rmp_serde::Deserializer::new(bytes);
+                self.schema
+                    .deserialize(&mut de, cfg)
+                    .map_err(|e| map_path_error(e, DecodeError::MsgPack))
+            }
+            BodyFormat::Cbor => {
+                let mut de = ciborium::de::Deserializer::from_reader(bytes);
+                self.schema
+                    .deserialize(&mut de, cfg)
+                    .map_err(|e| map_path_error(e, |e| DecodeError::Cbor(e.to_string())))
+            }
+        };
+        result.map_err(map_err)
     }
 }
 
+/// An error decoding a body in any of the supported formats.
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error(transparent)]
+    Json(serde_json::Error),
+    #[error(transparent)]
+    MsgPack(rmp_serde::decode::Error),
+    #[error("{0}")]
+    Cbor(String),
+}
+
+fn map_path_error<E, F>(
+    err: serde_path_to_error::Error<E>,
+    f: F,
+) -> serde_path_to_error::Error<DecodeError>
+where
+    F: FnOnce(E) -> DecodeError,
+{
+    let path = err.path().clone();
+    serde_path_to_error::Error::new(path, f(err.into_inner()))
+}
+
 impl ToOutgoingRequest<reqwest::Request> for Body {
     fn to_outgoing_request(
         &self,
@@ -102,17 +169,51 @@ impl Body {
     pub fn to_response(
         &self,
         payload: &JSONPayload,
-        resp: axum::http::response::Builder,
+        mut resp: axum::http::response::Builder,
+        format: BodyFormat,
+        etag: Option<etag::Kind>,
     ) -> APIResult<axum::http::Response<axum::body::Body>> {
-        let buf = self.schema.to_vec(payload).map_err(api::Error::internal)?;
-        let resp = resp
-            .header(
+        let mut w = BodyWriter::new();
+        format
+            .write(&mut w, &self.schema.serialize(payload))
+            .map_err(api::Error::internal)?;
+        if let Some(kind) = etag {
+            resp = resp.header(axum::http::header::ETAG, w.etag(kind));
+        }
+        let resp = w
+            .into_body(resp.header(
                 axum::http::header::CONTENT_TYPE,
-                HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
-            )
-            .body(axum::body::Body::from(buf))
+                HeaderValue::from_static(format.content_type()),
+            ))
             .map_err(api::Error::internal)?;
 
         Ok(resp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::PValue;
+
+    #[test]
+    fn test_decode_binary_formats() {
+        let body = Body::new(jsonschema::JSONSchema::any());
+        let value = serde_json::json!({"name": "encore", "count": 3});
+
+        for format in [BodyFormat::Json, BodyFormat::MsgPack, BodyFormat::Cbor] {
+            let bytes = format.encode(&value).unwrap();
+            let decoded = body
+                .decode(&bytes, format, |e| {
+                    api::Error::invalid_argument("unable to decode", e)
+                })
+                .unwrap();
+            assert_eq!(
+                decoded.get("name"),
+                Some(&PValue::String("encore".into())),
+                "{format:?}"
+            );
+            assert!(decoded.contains_key("count"), "{format:?}");
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::io::Write;
+
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::HeaderValue;
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::api::etag;
+
+/// The size of the chunks response bodies are serialized into.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+thread_local! {
+    /// The unused tail of the last chunk written on this thread.
+    ///
+    /// Once the response bodies sharing its allocation have been sent
+    /// and dropped, `BytesMut::reserve` reclaims the whole allocation,
+    /// so consecutive requests reuse the same buffer.
+    static SPARE: RefCell<BytesMut> = RefCell::new(BytesMut::new());
+}
+
+/// Serializes a response body directly into fixed-size chunks.
+///
+/// Unlike serializing into a `Vec<u8>`, which copies the payload every
+/// time the buffer doubles, each byte is written exactly once and the
+/// chunks are handed to hyper as separate body frames.
+pub struct BodyWriter {
+    buf: BytesMut,
+    chunks: Vec<Bytes>,
+    len: usize,
+}
+
+impl BodyWriter {
+    pub fn new() -> Self {
+        let buf = SPARE.with(|spare| std::mem::take(&mut *spare.borrow_mut()));
+        Self {
+            buf,
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Computes the ETag of the bytes written so far.
+    pub fn etag(&self, kind: etag::Kind) -> HeaderValue {
+        kind.compute(
+            self.chunks
+                .iter()
+                .map(|c| c.as_ref())
+                .chain([self.buf.as_ref()]),
+        )
+    }
+
+    /// Converts the written bytes into a response body, setting the
+    /// Content-Length header so large bodies aren't sent chunked.
+    pub fn into_body(
+        mut self,
+        bld: axum::http::response::Builder,
+    ) -> axum::http::Result<axum::http::Response<axum::body::Body>> {
+        let last = self.buf.split().freeze();
+        SPARE.with(|spare| *spare.borrow_mut() = std::mem::take(&mut self.buf));
+
+        if self.chunks.is_empty() {
+            return bld.body(axum::body::Body::from(last));
+        }
+
+        self.chunks.push(last);
+        let stream = futures::stream::iter(self.chunks.into_iter().map(Ok::<_, Infallible>));
+        bld.header(CONTENT_LENGTH, HeaderValue::from(self.len))
+            .body(axum::body::Body::from_stream(stream))
+    }
+}
+
+impl Default for BodyWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, mut data: &[u8]) -> std::io::Result<usize> {
+        let n = data.len();
+        while !data.is_empty() {
+            if self.buf.len() >= CHUNK_SIZE {
+                self.chunks.push(self.buf.split().freeze());
+            }
+            if self.buf.capacity() == self.buf.len() {
+                self.buf.reserve(CHUNK_SIZE);
+            }
+            let space = (CHUNK_SIZE - self.buf.len()).min(self.buf.capacity() - self.buf.len());
+            let (head, tail) = data.split_at(space.min(data.len()));
+            self.buf.put_slice(head);
+            data = tail;
+        }
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_chunked_body() {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+
+        let mut w = BodyWriter::new();
+        for part in data.chunks(1000) {
+            w.write_all(part).unwrap();
+        }
+        assert_eq!(w.len, data.len());
+        assert_eq!(w.chunks.len(), 2);
+        assert!(w.chunks.iter().all(|c| c.len() == CHUNK_SIZE));
+
+        let resp = w.into_body(axum::http::Response::builder()).unwrap();
+        assert_eq!(
+            resp.headers()[CONTENT_LENGTH],
+            data.len().to_string().as_str()
+        );
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, data);
+    }
+
+    #[tokio::test]
+    async fn test_small_body() {
+        let mut w = BodyWriter::new();
+        w.write_all(b"{}").unwrap();
+        let resp = w.into_body(axum::http::Response::builder()).unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "{}");
+    }
+}
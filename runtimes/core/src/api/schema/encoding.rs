@@ -326,6 +326,23 @@ impl<'a> TypeArgResolver<'a> {
                 }
             }
 
+            Typ::Tuple(tuple) => {
+                let elems = self.resolve_types(&tuple.elems)?;
+                let elems = elems
+                    .into_iter()
+                    .zip(&tuple.elems)
+                    .map(|(typ, t)| schema::Type {
+                        typ: Some(typ.into_owned()),
+                        validation: t.validation.clone(),
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(Cow::Owned(Typ::Tuple(schema::Tuple {
+                    elems,
+                    min_len: tuple.min_len,
+                })))
+            }
+
             Typ::Union(union) => {
                 let types = self.resolve_types(&union.types)?;
                 let types = types
@@ -0,0 +1,163 @@
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use serde::Serialize;
+use std::io::Write;
+
+/// The encoding of a request or response body.
+///
+/// JSON is used unless a binary format is negotiated
+/// through the Content-Type and Accept headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BodyFormat {
+    #[default]
+    Json,
+    MsgPack,
+    Cbor,
+}
+
+impl BodyFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            BodyFormat::Json => "application/json",
+            BodyFormat::MsgPack => "application/msgpack",
+            BodyFormat::Cbor => "application/cbor",
+        }
+    }
+
+    /// Returns the format of the media type, if supported.
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "application/json" => Some(BodyFormat::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(BodyFormat::MsgPack)
+            }
+            "application/cbor" => Some(BodyFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Returns the format of a request or response body given its headers,
+    /// or None if it has no supported Content-Type.
+    pub fn of_content_type(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::from_media_type)
+    }
+
+    /// Returns the format of a request body, given its headers.
+    /// Bodies without a supported Content-Type are treated as JSON.
+    pub fn from_content_type(headers: &HeaderMap) -> Self {
+        Self::of_content_type(headers).unwrap_or_default()
+    }
+
+    /// Returns the format the client prefers responses in, given the
+    /// Accept header of its request. Media types with equal quality are
+    /// preferred in the order they're listed.
+    pub fn from_accept(headers: &HeaderMap) -> Self {
+        let mut best: Option<(Self, f32)> = None;
+        for value in headers.get_all(ACCEPT) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for media_range in value.split(',') {
+                let essence = media_range.split(';').next().unwrap_or_default().trim();
+                let format = match essence {
+                    "*/*" | "application/*" => BodyFormat::Json,
+                    _ => match Self::from_media_type(essence) {
+                        Some(format) => format,
+                        None => continue,
+                    },
+                };
+                let quality = media_range
+                    .split(';')
+                    .skip(1)
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                if quality > 0.0 && best.map_or(true, |(_, q)| quality > q) {
+                    best = Some((format, quality));
+                }
+            }
+        }
+        best.map(|(format, _)| format).unwrap_or_default()
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> anyhow::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(128);
+        self.write(&mut buf, value)?;
+        Ok(buf)
+    }
+
+    /// Serializes the value in this format directly into the writer.
+    pub fn write<W: Write, T: Serialize>(self, w: &mut W, value: &T) -> anyhow::Result<()> {
+        match self {
+            BodyFormat::Json => serde_json::to_writer(w, value)?,
+            BodyFormat::MsgPack => rmp_serde::encode::write_named(w, value)?,
+            BodyFormat::Cbor => ciborium::ser::into_writer(value, w)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(name: axum::http::HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_from_content_type() {
+        let tests = [
+            ("application/json; charset=utf-8", BodyFormat::Json),
+            ("application/msgpack", BodyFormat::MsgPack),
+            ("application/x-msgpack", BodyFormat::MsgPack),
+            ("application/cbor", BodyFormat::Cbor),
+            ("text/plain", BodyFormat::Json),
+        ];
+        for (content_type, want) in tests {
+            assert_eq!(
+                BodyFormat::from_content_type(&headers(CONTENT_TYPE, content_type)),
+                want,
+                "{content_type}"
+            );
+        }
+        assert_eq!(
+            BodyFormat::from_content_type(&HeaderMap::new()),
+            BodyFormat::Json
+        );
+        assert_eq!(
+            BodyFormat::of_content_type(&headers(CONTENT_TYPE, "text/plain")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_from_accept() {
+        let tests = [
+            ("application/msgpack", BodyFormat::MsgPack),
+            ("application/cbor, application/json", BodyFormat::Cbor),
+            ("application/json, application/cbor", BodyFormat::Json),
+            (
+                "application/json;q=0.5, application/msgpack;q=0.9",
+                BodyFormat::MsgPack,
+            ),
+            ("application/msgpack;q=0, */*", BodyFormat::Json),
+            ("text/html", BodyFormat::Json),
+        ];
+        for (accept, want) in tests {
+            assert_eq!(
+                BodyFormat::from_accept(&headers(ACCEPT, accept)),
+                want,
+                "{accept}"
+            );
+        }
+        assert_eq!(BodyFormat::from_accept(&HeaderMap::new()), BodyFormat::Json);
+    }
+}
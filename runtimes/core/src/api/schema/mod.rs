@@ -1,6 +1,8 @@
 use crate::api;
 pub use body::*;
+pub use bodywriter::*;
 pub use cookie::*;
+pub use format::*;
 pub use header::*;
 pub use httpstatus::*;
 pub use method::*;
@@ -8,13 +10,15 @@ pub use path::*;
 pub use query::*;
 use std::sync::Arc;
 
-use crate::api::{endpoint, APIResult, PValues, RequestPayload};
+use crate::api::{endpoint, etag, APIResult, PValue, PValues, RequestPayload};
 
 use super::ResponsePayload;
 
 mod body;
+mod bodywriter;
 mod cookie;
 pub mod encoding;
+mod format;
 mod header;
 mod httpstatus;
 mod method;
@@ -95,7 +99,8 @@ impl Request {
             RequestBody::Raw => endpoint::Body::Raw(Arc::new(std::sync::Mutex::new(Some(body)))),
             RequestBody::Typed(None) => endpoint::Body::Typed(None),
             RequestBody::Typed(Some(b)) => {
-                endpoint::Body::Typed(b.parse_incoming_request_body(body).await?)
+                let format = BodyFormat::from_content_type(&parts.headers);
+                endpoint::Body::Typed(b.parse_incoming_request_body(body, format).await?)
             }
         };
 
@@ -109,6 +114,34 @@ impl Request {
     }
 }
 
+/// Converts an error decoding a request into an InvalidArgument error,
+/// reporting the field that failed to decode in the error details.
+fn invalid_field<E: std::fmt::Display>(
+    public_msg: &str,
+    err: serde_path_to_error::Error<E>,
+) -> api::Error {
+    let field = err.path().to_string();
+    let reason = err.inner().to_string();
+
+    let mut details = PValues::new();
+    if field != "." {
+        details.insert("field".into(), PValue::String(field.clone()));
+    }
+    details.insert("reason".into(), PValue::String(reason.clone()));
+
+    api::Error {
+        code: api::ErrCode::InvalidArgument,
+        message: if field == "." {
+            format!("{public_msg}: {reason}")
+        } else {
+            format!("{public_msg}: {field}: {reason}")
+        },
+        internal_message: None,
+        stack: None,
+        details: Some(Box::new(details)),
+    }
+}
+
 /// The response schema for an endpoint.
 #[derive(Debug)]
 pub struct Response {
@@ -129,10 +162,14 @@ pub struct Response {
 }
 
 impl Response {
+    /// Encodes the response, with the body in the given format.
+    /// Successful responses carry an ETag of the given kind, if any.
     pub fn encode(
         &self,
         payload: &JSONPayload,
         status: u16,
+        format: BodyFormat,
+        etag: Option<etag::Kind>,
     ) -> APIResult<axum::http::Response<axum::body::Body>> {
         let mut bld = axum::http::Response::builder().status(status);
 
@@ -146,13 +183,25 @@ impl Response {
             bld = hs.to_response(payload, bld)?;
         }
         match &self.body {
-            Some(body) => body.to_response(payload, bld),
+            Some(body) => {
+                let etag = etag.filter(|_| status == 200);
+                body.to_response(payload, bld, format, etag)
+            }
             None => bld
                 .body(axum::body::Body::empty())
                 .map_err(api::Error::internal),
         }
     }
 
+    /// Checks that a handler's response payload conforms to the schema,
+    /// reporting the offending field if it doesn't.
+    pub fn check_conformance(&self, payload: &JSONPayload) -> APIResult<()> {
+        match &self.body {
+            Some(body) if payload.is_some() => body.validate_response_payload(payload),
+            _ => Ok(()),
+        }
+    }
+
     pub async fn extract(&self, resp: reqwest::Response) -> APIResult<ResponsePayload> {
         let header = match &self.header {
             None => None,
@@ -168,22 +217,19 @@ impl Response {
         let body = endpoint::Body::Typed(match &self.body {
             None => None,
             Some(body_schema) => {
-                // If so we expect a JSON body.
-                match resp.headers().get(axum::http::header::CONTENT_TYPE) {
-                    Some(content_type) if content_type == "application/json" => {
-                        // Collect the bytes of the request body.
-                        // Serde doesn't support async streaming reads (at least not yet).
-                        let bytes = resp.bytes().await.map_err(|e| {
-                            api::Error::invalid_argument("unable to read response body", e)
-                        })?;
-
-                        body_schema.parse_response_body(bytes).await?
-                    }
-                    _ => {
-                        // We didn't get a JSON body, so return an error.
-                        return Err(api::Error::internal(anyhow::anyhow!("expected json body")));
-                    }
-                }
+                // If so we expect a JSON or binary encoded body.
+                let Some(format) = BodyFormat::of_content_type(resp.headers()) else {
+                    // We didn't get a supported body, so return an error.
+                    return Err(api::Error::internal(anyhow::anyhow!("expected json body")));
+                };
+
+                // Collect the bytes of the response body.
+                let bytes = resp
+                    .bytes()
+                    .await
+                    .map_err(|e| api::Error::invalid_argument("unable to read response body", e))?;
+
+                body_schema.parse_response_body(bytes, format).await?
             }
         });
 
@@ -232,7 +278,7 @@ impl Stream {
         };
 
         let value = body
-            .parse_incoming_request_body(bytes.to_vec().into())
+            .parse_incoming_request_body(bytes.to_vec().into(), BodyFormat::Json)
             .await?
             .ok_or_else(|| super::Error {
                 code: super::ErrCode::InvalidArgument,
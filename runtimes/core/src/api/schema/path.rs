@@ -237,92 +237,7 @@ impl Path {
         })?;
 
         match result {
-            Ok(axum::extract::Path(params)) => {
-                let mut map = IndexMap::with_capacity(params.len());
-
-                // For each param, find the corresponding segment and deserialize it.
-                for (idx, (name, val)) in params.into_iter().enumerate() {
-                    if let Some((typ, validation)) = self.dynamic_segments.get(idx) {
-                        // Decode it into the correct type based on the type.
-                        let val = match &typ {
-                            // For strings and any, use the value directly.
-                            Basic::String | Basic::Any => PValue::String(val),
-
-                            // For numbers and booleans, use the JSON parser.
-                            Basic::Number => {
-                                let val = serde_json::from_str::<serde_json::Number>(&val)
-                                    .map_err(|err| api::Error {
-                                        code: api::ErrCode::InvalidArgument,
-                                        message: "path parameter is not a valid number".into(),
-                                        internal_message: Some(err.to_string()),
-                                        stack: None,
-                                        details: None,
-                                    })?;
-                                PValue::Number(val)
-                            }
-                            Basic::Bool => {
-                                let val = serde_json::from_str::<bool>(&val).map_err(|err| {
-                                    api::Error {
-                                        code: api::ErrCode::InvalidArgument,
-                                        message: "path parameter is not a valid boolean".into(),
-                                        internal_message: Some(err.to_string()),
-                                        stack: None,
-                                        details: None,
-                                    }
-                                })?;
-                                PValue::Bool(val)
-                            }
-
-                            Basic::DateTime => {
-                                let val =
-                                    api::DateTime::parse_from_rfc3339(&val).map_err(|err| {
-                                        api::Error {
-                                            code: api::ErrCode::InvalidArgument,
-                                            message: "path parameter is not a valid datetime"
-                                                .into(),
-                                            internal_message: Some(err.to_string()),
-                                            stack: None,
-                                            details: None,
-                                        }
-                                    })?;
-                                PValue::DateTime(val)
-                            }
-
-                            Basic::Decimal => {
-                                let val =
-                                    api::Decimal::from_str(&val).map_err(|err| api::Error {
-                                        code: api::ErrCode::InvalidArgument,
-                                        message: "path parameter is not a valid decimal".into(),
-                                        internal_message: Some(err.to_string()),
-                                        stack: None,
-                                        details: None,
-                                    })?;
-                                PValue::Decimal(val)
-                            }
-
-                            // We shouldn't have null here, but handle it just in case.
-                            Basic::Null => PValue::Null,
-                        };
-
-                        // Validate the value, if we have a validation expression.
-                        if let Some(validation) = validation.as_ref() {
-                            if let Err(err) = validation.validate_pval(&val) {
-                                return Err(api::Error {
-                                    code: api::ErrCode::InvalidArgument,
-                                    message: format!("invalid path parameter {name}: {err}"),
-                                    internal_message: None,
-                                    stack: None,
-                                    details: None,
-                                });
-                            }
-                        }
-
-                        map.insert(name, val);
-                    }
-                }
-
-                Ok(Some(map))
-            }
+            Ok(axum::extract::Path(params)) => self.parse_params(params),
             Err(err) => Err(match err {
                 PathRejection::FailedToDeserializePathParams(err) => api::Error {
                     code: api::ErrCode::InvalidArgument,
@@ -348,6 +263,98 @@ impl Path {
             }),
         }
     }
+
+    /// Parses the path parameters of a matched route, given as (name, value) pairs
+    /// in the order they appear in the path.
+    pub fn parse_params(
+        &self,
+        params: Vec<(String, String)>,
+    ) -> APIResult<Option<IndexMap<String, PValue>>> {
+        if self.dynamic_segments.is_empty() {
+            return Ok(None);
+        }
+
+        let mut map = IndexMap::with_capacity(params.len());
+
+        // For each param, find the corresponding segment and deserialize it.
+        for (idx, (name, val)) in params.into_iter().enumerate() {
+            if let Some((typ, validation)) = self.dynamic_segments.get(idx) {
+                // Decode it into the correct type based on the type.
+                let val = match &typ {
+                    // For strings and any, use the value directly.
+                    Basic::String | Basic::Any => PValue::String(val),
+
+                    // For numbers and booleans, use the JSON parser.
+                    Basic::Number => {
+                        let val =
+                            serde_json::from_str::<serde_json::Number>(&val).map_err(|err| {
+                                api::Error {
+                                    code: api::ErrCode::InvalidArgument,
+                                    message: "path parameter is not a valid number".into(),
+                                    internal_message: Some(err.to_string()),
+                                    stack: None,
+                                    details: None,
+                                }
+                            })?;
+                        PValue::Number(val)
+                    }
+                    Basic::Bool => {
+                        let val = serde_json::from_str::<bool>(&val).map_err(|err| api::Error {
+                            code: api::ErrCode::InvalidArgument,
+                            message: "path parameter is not a valid boolean".into(),
+                            internal_message: Some(err.to_string()),
+                            stack: None,
+                            details: None,
+                        })?;
+                        PValue::Bool(val)
+                    }
+
+                    Basic::DateTime => {
+                        let val =
+                            api::DateTime::parse_from_rfc3339(&val).map_err(|err| api::Error {
+                                code: api::ErrCode::InvalidArgument,
+                                message: "path parameter is not a valid datetime".into(),
+                                internal_message: Some(err.to_string()),
+                                stack: None,
+                                details: None,
+                            })?;
+                        PValue::DateTime(val)
+                    }
+
+                    Basic::Decimal => {
+                        let val = api::Decimal::from_str(&val).map_err(|err| api::Error {
+                            code: api::ErrCode::InvalidArgument,
+                            message: "path parameter is not a valid decimal".into(),
+                            internal_message: Some(err.to_string()),
+                            stack: None,
+                            details: None,
+                        })?;
+                        PValue::Decimal(val)
+                    }
+
+                    // We shouldn't have null here, but handle it just in case.
+                    Basic::Null => PValue::Null,
+                };
+
+                // Validate the value, if we have a validation expression.
+                if let Some(validation) = validation.as_ref() {
+                    if let Err(err) = validation.validate_pval(&val) {
+                        return Err(api::Error {
+                            code: api::ErrCode::InvalidArgument,
+                            message: format!("invalid path parameter {name}: {err}"),
+                            internal_message: None,
+                            stack: None,
+                            details: None,
+                        });
+                    }
+                }
+
+                map.insert(name, val);
+            }
+        }
+
+        Ok(Some(map))
+    }
 }
 
 struct FuturePendingError;
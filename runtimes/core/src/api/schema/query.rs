@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use crate::api::jsonschema::DecodeConfig;
-use crate::api::schema::{JSONPayload, ToOutgoingRequest};
+use crate::api::schema::{invalid_field, JSONPayload, ToOutgoingRequest};
 use crate::api::{self, PValues};
 use crate::api::{jsonschema, APIResult};
 use serde::Serialize;
@@ -40,6 +40,21 @@ impl Query {
         Ok(Some(decoded))
     }
 
+    /// Validates a query string against the schema,
+    /// reporting the offending field if it doesn't match.
+    pub fn validate(&self, query: Option<&str>) -> APIResult<()> {
+        let parsed = form_urlencoded::parse(query.unwrap_or_default().as_bytes());
+        let de = serde_urlencoded::Deserializer::new(parsed);
+        let cfg = DecodeConfig {
+            coerce_strings: true,
+            arrays_as_repeated_fields: true,
+        };
+        self.schema
+            .deserialize(de, cfg)
+            .map_err(|e| invalid_field("unable to decode query string", e))?;
+        Ok(())
+    }
+
     pub fn contains_name(&self, name: &str) -> bool {
         self.schema.root().contains_name(name)
     }
@@ -10,8 +10,10 @@ use crate::api::paths::Pather;
 use crate::api::reqauth::svcauth;
 use crate::api::static_assets::StaticAssetsHandler;
 use crate::api::{self, ToResponse};
+use crate::api::{concurrency, respcache};
 use crate::api::{paths, reqauth, schema, BoxedHandler, EndpointMap};
 use crate::encore::parser::meta::v1 as meta;
+use crate::metrics::slo;
 use crate::names::EndpointName;
 use crate::trace;
 
@@ -40,9 +42,19 @@ pub struct Server {
 
     /// Metrics registry for creating metrics
     metrics_registry: Arc<crate::metrics::Registry>,
+
+    /// Concurrency limits for the hosted endpoints.
+    concurrency: concurrency::Limits,
+
+    /// Response caches for the hosted endpoints.
+    response_caches: respcache::Caches,
+
+    /// SLO trackers for the hosted endpoints.
+    slos: HashMap<EndpointName, Arc<slo::Tracker>>,
 }
 
 impl Server {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         endpoints: Arc<EndpointMap>,
         hosted_endpoints: Vec<EndpointName>,
@@ -51,6 +63,10 @@ impl Server {
         tracer: trace::Tracer,
         auth_data_schemas: HashMap<String, Option<JSONSchema>>,
         metrics_registry: Arc<crate::metrics::Registry>,
+        concurrency: concurrency::Limits,
+        response_caches: respcache::Caches,
+        slos: HashMap<EndpointName, Arc<slo::Tracker>>,
+        check_response_schema: bool,
     ) -> anyhow::Result<Self> {
         // Register the routes, and track the handlers in a map so we can easily
         // set the request handler when registered.
@@ -82,6 +98,7 @@ impl Server {
             platform_auth,
             inbound_svc_auth,
             auth_data_schemas,
+            check_response_schema,
         });
 
         let mut register = |paths: &[(Arc<api::Endpoint>, Vec<String>)],
@@ -101,12 +118,21 @@ impl Server {
                                 ep.name.service(),
                                 ep.name.endpoint(),
                             );
+                            let request_duration = crate::metrics::request_duration_histogram(
+                                &metrics_registry,
+                                ep.name.service(),
+                                ep.name.endpoint(),
+                            );
 
                             let handler = EndpointHandler {
                                 endpoint: ep.clone(),
                                 handler: Arc::new(static_handler),
                                 shared: shared.clone(),
                                 requests_total,
+                                request_duration,
+                                concurrency: concurrency.for_endpoint(&ep.name),
+                                response_cache: None,
+                                slo: slos.get(&ep.name).cloned(),
                             };
                             server_handler.set(handler);
                         }
@@ -141,6 +167,9 @@ impl Server {
             router: Mutex::new(Some(router)),
             shared,
             metrics_registry,
+            concurrency,
+            response_caches,
+            slos,
         })
     }
 
@@ -169,12 +198,21 @@ impl Server {
                     endpoint.name.service(),
                     endpoint.name.endpoint(),
                 );
+                let request_duration = crate::metrics::request_duration_histogram(
+                    &self.metrics_registry,
+                    endpoint.name.service(),
+                    endpoint.name.endpoint(),
+                );
 
                 let handler = EndpointHandler {
                     endpoint,
                     handler,
                     shared: self.shared.clone(),
+                    concurrency: self.concurrency.for_endpoint(&endpoint.name),
+                    response_cache: self.response_caches.for_endpoint(&endpoint.name),
+                    slo: self.slos.get(&endpoint.name).cloned(),
                     requests_total,
+                    request_duration,
                 };
 
                 h.add(handler);
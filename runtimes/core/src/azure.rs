@@ -0,0 +1,154 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// Tokens are refreshed when they expire within this margin.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Authenticates as an Azure managed identity,
+/// caching the access token for the given resource.
+///
+/// Workload identity (as used on AKS), App Service and Container Apps
+/// identities and the Instance Metadata Service are supported.
+pub(crate) struct ManagedIdentity {
+    client_id: Option<String>,
+
+    /// The OAuth resource to request tokens for, e.g. "https://storage.azure.com/".
+    resource: &'static str,
+    token: tokio::sync::Mutex<Option<(String, SystemTime)>>,
+}
+
+impl ManagedIdentity {
+    pub fn new(client_id: Option<String>, resource: &'static str) -> Self {
+        Self {
+            client_id,
+            resource,
+            token: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Returns an access token for the resource.
+    pub async fn token(&self, http: &reqwest::Client) -> anyhow::Result<String> {
+        let mut guard = self.token.lock().await;
+        if let Some((token, expires_at)) = guard.as_ref() {
+            if *expires_at > SystemTime::now() + TOKEN_REFRESH_MARGIN {
+                return Ok(token.clone());
+            }
+        }
+
+        let (token, expires_at) = self
+            .fetch_token(http)
+            .await
+            .context("unable to get managed identity token")?;
+        *guard = Some((token.clone(), expires_at));
+        Ok(token)
+    }
+
+    async fn fetch_token(&self, http: &reqwest::Client) -> anyhow::Result<(String, SystemTime)> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: String,
+            #[serde(default)]
+            expires_on: Option<serde_json::Value>,
+            #[serde(default)]
+            expires_in: Option<serde_json::Value>,
+        }
+
+        let client_id = self
+            .client_id
+            .clone()
+            .or_else(|| std::env::var("AZURE_CLIENT_ID").ok());
+
+        let req = if let Ok(token_file) = std::env::var("AZURE_FEDERATED_TOKEN_FILE") {
+            // Workload identity, as used on AKS.
+            let assertion = std::fs::read_to_string(&token_file)
+                .with_context(|| format!("unable to read federated token {token_file}"))?;
+            let tenant_id =
+                std::env::var("AZURE_TENANT_ID").context("AZURE_TENANT_ID is not set")?;
+            let client_id = client_id.context("AZURE_CLIENT_ID is not set")?;
+            let authority = std::env::var("AZURE_AUTHORITY_HOST")
+                .unwrap_or_else(|_| "https://login.microsoftonline.com/".to_string());
+            let scope = format!("{}/.default", self.resource.trim_end_matches('/'));
+            http.post(format!(
+                "{}/{tenant_id}/oauth2/v2.0/token",
+                authority.trim_end_matches('/')
+            ))
+            .form(&[
+                ("client_id", client_id.as_str()),
+                ("scope", scope.as_str()),
+                (
+                    "client_assertion_type",
+                    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+                ),
+                ("client_assertion", assertion.trim()),
+                ("grant_type", "client_credentials"),
+            ])
+        } else if let (Ok(endpoint), Ok(header)) = (
+            std::env::var("IDENTITY_ENDPOINT"),
+            std::env::var("IDENTITY_HEADER"),
+        ) {
+            // App Service and Container Apps.
+            let mut query = vec![("api-version", "2019-08-01"), ("resource", self.resource)];
+            if let Some(client_id) = &client_id {
+                query.push(("client_id", client_id.as_str()));
+            }
+            http.get(endpoint)
+                .query(&query)
+                .header("X-IDENTITY-HEADER", header)
+        } else {
+            // The Azure Instance Metadata Service, on VMs and AKS nodes.
+            let mut query = vec![("api-version", "2018-02-01"), ("resource", self.resource)];
+            if let Some(client_id) = &client_id {
+                query.push(("client_id", client_id.as_str()));
+            }
+            http.get("http://169.254.169.254/metadata/identity/oauth2/token")
+                .query(&query)
+                .header("Metadata", "true")
+        };
+
+        let resp: TokenResponse = req
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())?
+            .json()
+            .await
+            .context("invalid token response")?;
+
+        let now = SystemTime::now();
+        let expires_at = resp
+            .expires_on
+            .as_ref()
+            .and_then(json_u64)
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .or_else(|| {
+                resp.expires_in
+                    .as_ref()
+                    .and_then(json_u64)
+                    .map(|secs| now + Duration::from_secs(secs))
+            })
+            .unwrap_or(now + TOKEN_REFRESH_MARGIN);
+        Ok((resp.access_token, expires_at))
+    }
+}
+
+/// Parses a number that may be encoded as a JSON string.
+fn json_u64(v: &serde_json::Value) -> Option<u64> {
+    match v {
+        serde_json::Value::Number(n) => n.as_u64(),
+        serde_json::Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_u64() {
+        assert_eq!(json_u64(&serde_json::json!(1700000000)), Some(1700000000));
+        assert_eq!(json_u64(&serde_json::json!("1700000000")), Some(1700000000));
+        assert_eq!(json_u64(&serde_json::json!("soon")), None);
+    }
+}
@@ -0,0 +1,22 @@
+//! Runs an Encore app's gateways on their own, without any services.
+//!
+//! Requests are routed, authenticated and rate limited as usual, and proxied
+//! to the services listed in the service discovery configuration. The runtime
+//! configuration is read from `ENCORE_INFRA_CONFIG_PATH` or `ENCORE_RUNTIME_CONFIG`,
+//! and the app metadata from `ENCORE_APP_META`, `ENCORE_APP_META_PATH` or `/encore/meta`.
+
+use anyhow::Context;
+use encore_runtime_core::Runtime;
+
+fn main() -> anyhow::Result<()> {
+    encore_runtime_core::log::init();
+
+    let runtime = Runtime::builder()
+        .with_meta_autodetect()
+        .with_runtime_config_from_env()
+        .with_gateway_mode(true)
+        .build()
+        .context("failed to initialize runtime")?;
+
+    runtime.run_blocking()
+}
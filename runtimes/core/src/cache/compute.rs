@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+use rand::Rng;
+use redis::AsyncCommands;
+
+use super::Cluster;
+
+/// How often to check for the value while another process computes it.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Releases the lock only if it's still held by the given holder,
+/// so an expired lock that was acquired by another process isn't released.
+const RELEASE_LOCK_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Options for [`Cluster::get_or_compute`].
+#[derive(Debug, Clone)]
+pub struct ComputeOptions {
+    /// How long a computed value is fresh.
+    pub ttl: Duration,
+    /// The fraction of the TTL by which it's randomly shortened or lengthened,
+    /// so values computed at the same time don't all expire at the same time.
+    /// Between 0 and 1.
+    pub ttl_jitter: f64,
+    /// How long a value is still returned after it's no longer fresh,
+    /// while it's recomputed in the background. Stale values aren't returned if unset.
+    pub stale_while_revalidate: Option<Duration>,
+    /// If set, a lock is held in Redis while computing a value, for at most this long,
+    /// so only one process computes it at a time. Otherwise only callers within
+    /// the same process wait for each other's computation.
+    pub lock_timeout: Option<Duration>,
+}
+
+impl ComputeOptions {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            ttl_jitter: 0.0,
+            stale_while_revalidate: None,
+            lock_timeout: None,
+        }
+    }
+
+    /// Returns the TTL to store a newly computed value with, including jitter.
+    fn jittered_ttl(&self) -> Duration {
+        let jitter = self.ttl_jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return self.ttl;
+        }
+        self.ttl
+            .mul_f64(1.0 + rand::thread_rng().gen_range(-jitter..=jitter))
+    }
+}
+
+type Computation = Shared<BoxFuture<'static, Result<Arc<[u8]>, Arc<anyhow::Error>>>>;
+
+/// Deduplicates concurrent computations of the same key within the process.
+#[derive(Default)]
+pub(super) struct SingleFlight {
+    inflight: Arc<Mutex<HashMap<String, Computation>>>,
+}
+
+impl SingleFlight {
+    /// Returns the computation in progress for the key,
+    /// or starts a new one if there is none.
+    fn join<F, Fut>(&self, key: &str, start: F) -> Computation
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static,
+    {
+        let mut inflight = self.inflight.lock().unwrap();
+        if let Some(computation) = inflight.get(key) {
+            return computation.clone();
+        }
+
+        let fut = start();
+        let done = self.inflight.clone();
+        let owned_key = key.to_string();
+        let computation = async move {
+            let result = fut.await.map(Arc::from).map_err(Arc::new);
+            done.lock().unwrap().remove(&owned_key);
+            result
+        }
+        .boxed()
+        .shared();
+        inflight.insert(key.to_string(), computation.clone());
+        computation
+    }
+}
+
+/// A value stored by `get_or_compute`, along with when it stops being fresh.
+struct Entry<'a> {
+    /// Milliseconds since the Unix epoch.
+    fresh_until: u64,
+    value: &'a [u8],
+}
+
+impl<'a> Entry<'a> {
+    fn encode(fresh_until: u64, value: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(8 + value.len());
+        buf.extend_from_slice(&fresh_until.to_be_bytes());
+        buf.extend_from_slice(value);
+        buf
+    }
+
+    fn decode(buf: &'a [u8]) -> Option<Self> {
+        let fresh_until = buf.get(..8)?.try_into().ok()?;
+        Some(Self {
+            fresh_until: u64::from_be_bytes(fresh_until),
+            value: &buf[8..],
+        })
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl Cluster {
+    /// Returns the value of the key, computing and storing it if it's missing or has expired.
+    ///
+    /// Concurrent callers within the process share a single computation of the key,
+    /// and with a `lock_timeout` so do callers in other processes. If the value
+    /// is stale but within the `stale_while_revalidate` window, the stale value
+    /// is returned and the value is recomputed in the background.
+    ///
+    /// Values are stored along with when they stop being fresh, so keys used
+    /// with `get_or_compute` shouldn't be read or written by other means.
+    pub async fn get_or_compute<F, Fut>(
+        self: &Arc<Self>,
+        key: &str,
+        opts: &ComputeOptions,
+        compute: F,
+    ) -> anyhow::Result<Vec<u8>>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static,
+    {
+        let key = self.key(key);
+        let stored = match self.lookup(&key).await {
+            Ok(stored) => stored,
+            Err(err) => {
+                // Compute the value instead, so the cache being unavailable
+                // doesn't fail the caller.
+                log::warn!("unable to look up cached value: {:?}", err);
+                None
+            }
+        };
+
+        let mut stale = None;
+        if let Some(entry) = stored.as_deref().and_then(Entry::decode) {
+            if unix_millis() < entry.fresh_until {
+                return Ok(entry.value.to_vec());
+            }
+            if opts.stale_while_revalidate.is_some() {
+                stale = Some(entry.value.to_vec());
+            }
+        }
+
+        let cluster = self.clone();
+        let computation = self.computing.join(&key, {
+            let key = key.clone();
+            let opts = opts.clone();
+            let stale = stale.clone();
+            move || cluster.compute_and_store(key, opts, stale, compute)
+        });
+
+        match stale {
+            Some(stale) => {
+                tokio::spawn(async move {
+                    if let Err(err) = computation.await {
+                        log::warn!("unable to recompute cached value: {:?}", err);
+                    }
+                });
+                Ok(stale)
+            }
+            None => match computation.await {
+                Ok(value) => Ok(value.to_vec()),
+                Err(err) => Err(anyhow::anyhow!("{:#}", err)),
+            },
+        }
+    }
+
+    async fn lookup(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut conn = self.conn().await?;
+        Ok(conn.get(key).await?)
+    }
+
+    /// Computes the value and stores it, holding the lock if configured.
+    /// If another process holds the lock, it waits for that process to store
+    /// the value instead, or returns the stale value if there is one.
+    async fn compute_and_store<F, Fut>(
+        self: Arc<Self>,
+        key: String,
+        opts: ComputeOptions,
+        stale: Option<Vec<u8>>,
+        compute: F,
+    ) -> anyhow::Result<Vec<u8>>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static,
+    {
+        let mut lock = None;
+        if let Some(timeout) = opts.lock_timeout {
+            match self.acquire_lock(&key, timeout, stale.is_none()).await {
+                Ok(Acquired::Lock(l)) => lock = Some(l),
+                Ok(Acquired::Value(value)) => return Ok(value),
+                Ok(Acquired::TimedOut) => {}
+                Ok(Acquired::Held) => {
+                    if let Some(stale) = stale {
+                        return Ok(stale);
+                    }
+                }
+                Err(err) => log::warn!("unable to lock cached value: {:?}", err),
+            }
+        }
+
+        let result = compute().await;
+        if let Ok(value) = &result {
+            if let Err(err) = self.store(&key, &opts, value).await {
+                log::warn!("unable to store cached value: {:?}", err);
+            }
+        }
+        if let Some(lock) = lock {
+            if let Err(err) = self.release_lock(&lock).await {
+                log::warn!("unable to unlock cached value: {:?}", err);
+            }
+        }
+        result
+    }
+
+    async fn store(&self, key: &str, opts: &ComputeOptions, value: &[u8]) -> anyhow::Result<()> {
+        let ttl = opts.jittered_ttl();
+        let fresh_until = unix_millis() + ttl.as_millis() as u64;
+        let expiry = ttl + opts.stale_while_revalidate.unwrap_or_default();
+        let mut conn = self.conn().await?;
+        let _: () = conn
+            .pset_ex(
+                key,
+                Entry::encode(fresh_until, value),
+                expiry.as_millis().max(1) as u64,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Acquires the lock for computing the key's value. If another process holds it
+    /// and `wait` is set, waits for that process to store the value or release the lock.
+    async fn acquire_lock(
+        &self,
+        key: &str,
+        timeout: Duration,
+        wait: bool,
+    ) -> anyhow::Result<Acquired> {
+        let lock = Lock {
+            key: format!("{key}:lock"),
+            holder: xid::new().to_string(),
+        };
+        let deadline = Instant::now() + timeout;
+        let mut conn = self.conn().await?;
+        loop {
+            let opts = redis::SetOptions::default()
+                .conditional_set(redis::ExistenceCheck::NX)
+                .with_expiration(redis::SetExpiry::PX(timeout.as_millis().max(1) as u64));
+            let acquired: Option<String> = conn.set_options(&lock.key, &lock.holder, opts).await?;
+            if acquired.is_some() {
+                return Ok(Acquired::Lock(lock));
+            }
+            if !wait {
+                return Ok(Acquired::Held);
+            }
+
+            // Wait for the holder to store the value, or to release the lock if it fails.
+            loop {
+                if Instant::now() >= deadline {
+                    return Ok(Acquired::TimedOut);
+                }
+                tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+
+                let stored: Option<Vec<u8>> = conn.get(key).await?;
+                if let Some(entry) = stored.as_deref().and_then(Entry::decode) {
+                    if unix_millis() < entry.fresh_until {
+                        return Ok(Acquired::Value(entry.value.to_vec()));
+                    }
+                }
+                let held: bool = conn.exists(&lock.key).await?;
+                if !held {
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn release_lock(&self, lock: &Lock) -> anyhow::Result<()> {
+        let mut conn = self.conn().await?;
+        let _: i64 = redis::Script::new(RELEASE_LOCK_SCRIPT)
+            .key(&lock.key)
+            .arg(&lock.holder)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// The lock held while computing a value.
+struct Lock {
+    key: String,
+    holder: String,
+}
+
+enum Acquired {
+    Lock(Lock),
+    /// Another process stored the value while waiting for the lock.
+    Value(Vec<u8>),
+    /// Another process holds the lock.
+    Held,
+    /// Another process held the lock for longer than the lock timeout.
+    TimedOut,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_entry() {
+        let buf = Entry::encode(1234, b"value");
+        let entry = Entry::decode(&buf).unwrap();
+        assert_eq!(entry.fresh_until, 1234);
+        assert_eq!(entry.value, b"value");
+
+        let buf = Entry::encode(1234, b"");
+        assert_eq!(Entry::decode(&buf).unwrap().value, b"");
+        assert!(Entry::decode(b"short").is_none());
+    }
+
+    #[test]
+    fn test_jittered_ttl() {
+        let mut opts = ComputeOptions::new(Duration::from_secs(100));
+        assert_eq!(opts.jittered_ttl(), Duration::from_secs(100));
+
+        opts.ttl_jitter = 0.1;
+        for _ in 0..100 {
+            let ttl = opts.jittered_ttl();
+            assert!(
+                ttl >= Duration::from_secs(90) && ttl <= Duration::from_secs(110),
+                "{ttl:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let flight = SingleFlight::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+        let start = |calls: Arc<AtomicUsize>| {
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                anyhow::Ok(b"value".to_vec())
+            }
+        };
+        let first = flight.join("key", {
+            let calls = calls.clone();
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let _ = rx.await;
+                anyhow::Ok(b"value".to_vec())
+            }
+        });
+        let second = flight.join("key", start(calls.clone()));
+        tx.send(()).unwrap();
+
+        assert_eq!(&*first.await.unwrap(), b"value");
+        assert_eq!(&*second.await.unwrap(), b"value");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Completed computations are forgotten.
+        flight.join("key", start(calls.clone())).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,266 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use futures_util::StreamExt;
+use tokio_util::sync::CancellationToken;
+
+use super::Cluster;
+
+/// How long to wait before reconnecting after the notification connection is lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The kind of keyspace notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyEventKind {
+    /// The key expired because its TTL elapsed.
+    Expired,
+    /// The key was evicted to free memory.
+    Evicted,
+}
+
+impl KeyEventKind {
+    /// The name of the event, as used in notification channel names.
+    fn event_name(self) -> &'static str {
+        match self {
+            KeyEventKind::Expired => "expired",
+            KeyEventKind::Evicted => "evicted",
+        }
+    }
+
+    /// The `notify-keyspace-events` flag enabling the event.
+    fn flag(self) -> char {
+        match self {
+            KeyEventKind::Expired => 'x',
+            KeyEventKind::Evicted => 'e',
+        }
+    }
+}
+
+/// A key that expired or was evicted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub kind: KeyEventKind,
+    /// The key, without the database's key prefix.
+    pub key: String,
+}
+
+pub trait KeyEventHandler: Debug + Send + Sync {
+    fn handle_key_event(&self, event: KeyEvent) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+}
+
+/// Delivers key events to a handler until dropped.
+pub struct KeyEventSubscription {
+    cancel: CancellationToken,
+}
+
+impl Drop for KeyEventSubscription {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+impl Cluster {
+    /// Subscribes to notifications for keys starting with the given prefix.
+    ///
+    /// The server must have keyspace notifications enabled for the given kinds
+    /// of events, using the `notify-keyspace-events` setting. Notifications are
+    /// delivered at most once: events that occur while the runtime is disconnected
+    /// from the server are lost.
+    pub async fn subscribe_key_events(
+        self: &Arc<Self>,
+        prefix: &str,
+        kinds: &[KeyEventKind],
+        handler: Arc<dyn KeyEventHandler>,
+    ) -> anyhow::Result<KeyEventSubscription> {
+        anyhow::ensure!(!kinds.is_empty(), "no key event kinds given");
+        self.check_notifications(kinds).await?;
+
+        // Connect before returning, so configuration errors are reported to the caller.
+        let channels: Vec<String> = kinds
+            .iter()
+            .map(|kind| format!("__keyevent@{}__:{}", self.db_index(), kind.event_name()))
+            .collect();
+        let pubsub = self.key_event_conn(&channels).await?;
+
+        let cancel = CancellationToken::new();
+        tokio::spawn(receive(
+            self.clone(),
+            pubsub,
+            channels,
+            self.key(prefix),
+            handler,
+            cancel.clone(),
+        ));
+        Ok(KeyEventSubscription { cancel })
+    }
+
+    /// Checks that the server sends the notifications for the given kinds of events.
+    async fn check_notifications(&self, kinds: &[KeyEventKind]) -> anyhow::Result<()> {
+        let mut conn = self.conn().await?;
+        let config = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("notify-keyspace-events")
+            .query_async::<Vec<String>>(&mut conn)
+            .await;
+        let flags = match config {
+            Ok(config) => config.into_iter().nth(1).unwrap_or_default(),
+            Err(err) => {
+                // Managed Redis services commonly disable the CONFIG command.
+                log::warn!(
+                    "redis database {}: unable to verify that keyspace notifications are enabled: {}",
+                    self.name(),
+                    err
+                );
+                return Ok(());
+            }
+        };
+
+        let missing: Vec<_> = kinds
+            .iter()
+            .filter(|kind| !notifications_enabled(&flags, **kind))
+            .collect();
+        if !missing.is_empty() {
+            let names: Vec<_> = missing.iter().map(|k| k.event_name()).collect();
+            let flags_needed: String = missing.iter().map(|k| k.flag()).collect();
+            anyhow::bail!(
+                "redis database {} doesn't send {} keyspace notifications, \
+                 enable them by adding \"E{}\" to the notify-keyspace-events setting (currently {:?})",
+                self.name(),
+                names.join(" and "),
+                flags_needed,
+                flags,
+            );
+        }
+        Ok(())
+    }
+
+    async fn key_event_conn(&self, channels: &[String]) -> anyhow::Result<redis::aio::PubSub> {
+        let mut pubsub = self.pubsub().await?;
+        for channel in channels {
+            pubsub
+                .subscribe(channel)
+                .await
+                .with_context(|| format!("unable to subscribe to {channel}"))?;
+        }
+        Ok(pubsub)
+    }
+}
+
+/// Reports whether the `notify-keyspace-events` flags enable keyevent
+/// notifications of the given kind.
+fn notifications_enabled(flags: &str, kind: KeyEventKind) -> bool {
+    // "A" is an alias for all event classes, including expired and evicted events.
+    flags.contains('E') && (flags.contains('A') || flags.contains(kind.flag()))
+}
+
+/// Parses a notification received on a keyevent channel.
+fn parse_event(channel: &str, key: String, prefix: &str) -> Option<KeyEvent> {
+    let kind = match channel.rsplit_once(':')?.1 {
+        "expired" => KeyEventKind::Expired,
+        "evicted" => KeyEventKind::Evicted,
+        _ => return None,
+    };
+    key.starts_with(prefix).then_some(KeyEvent { kind, key })
+}
+
+/// Receives notifications and passes them to the handler,
+/// reconnecting if the connection is lost.
+async fn receive(
+    cluster: Arc<Cluster>,
+    mut pubsub: redis::aio::PubSub,
+    channels: Vec<String>,
+    prefix: String,
+    handler: Arc<dyn KeyEventHandler>,
+    cancel: CancellationToken,
+) {
+    // The database's key prefix is stripped from the keys passed to the handler.
+    let db_prefix_len = cluster.key("").len();
+    loop {
+        {
+            let mut messages = pubsub.on_message();
+            loop {
+                let msg = tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    msg = messages.next() => msg,
+                };
+                let Some(msg) = msg else {
+                    break;
+                };
+                let Ok(key) = msg.get_payload::<String>() else {
+                    continue;
+                };
+                if let Some(mut event) = parse_event(msg.get_channel_name(), key, &prefix) {
+                    event.key.drain(..db_prefix_len);
+                    handler.handle_key_event(event).await;
+                }
+            }
+        }
+
+        log::warn!(
+            "redis database {}: lost connection for keyspace notifications, reconnecting",
+            cluster.name()
+        );
+        pubsub = loop {
+            tokio::select! {
+                _ = cancel.cancelled() => return,
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+            }
+            match cluster.key_event_conn(&channels).await {
+                Ok(pubsub) => break pubsub,
+                Err(err) => log::error!(
+                    "redis database {}: unable to reconnect for keyspace notifications: {:#}",
+                    cluster.name(),
+                    err
+                ),
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifications_enabled() {
+        assert!(notifications_enabled("Ex", KeyEventKind::Expired));
+        assert!(!notifications_enabled("Ex", KeyEventKind::Evicted));
+        assert!(notifications_enabled("AE", KeyEventKind::Evicted));
+        assert!(!notifications_enabled("Kx", KeyEventKind::Expired));
+        assert!(!notifications_enabled("", KeyEventKind::Expired));
+    }
+
+    #[test]
+    fn test_parse_event() {
+        assert_eq!(
+            parse_event(
+                "__keyevent@0__:expired",
+                "app:session:1".into(),
+                "app:session:"
+            ),
+            Some(KeyEvent {
+                kind: KeyEventKind::Expired,
+                key: "app:session:1".into(),
+            })
+        );
+        assert_eq!(
+            parse_event("__keyevent@2__:evicted", "app:user:1".into(), "app:"),
+            Some(KeyEvent {
+                kind: KeyEventKind::Evicted,
+                key: "app:user:1".into(),
+            })
+        );
+        assert_eq!(
+            parse_event("__keyevent@0__:expired", "other:1".into(), "app:"),
+            None
+        );
+        assert_eq!(
+            parse_event("__keyevent@0__:del", "app:1".into(), "app:"),
+            None
+        );
+    }
+}
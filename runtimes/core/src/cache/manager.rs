@@ -0,0 +1,734 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use redis::aio::{ConnectionLike, ConnectionManager, ConnectionManagerConfig};
+use redis::cluster_async::ClusterConnection;
+use redis::sentinel::{Sentinel, SentinelNodeConnectionInfo};
+use tokio::sync::{Mutex, RwLock};
+
+use super::compute::SingleFlight;
+use crate::encore::runtime::v1 as pb;
+use crate::faults;
+use crate::iamauth::{self, IamToken};
+use crate::names::EncoreName;
+use crate::secrets;
+use crate::startup;
+
+/// Provides access to the Redis databases used by the runtime itself,
+/// keyed by the encore name of the database.
+pub struct Manager {
+    clusters: HashMap<EncoreName, Arc<Cluster>>,
+}
+
+impl Manager {
+    pub fn new(
+        secrets: &secrets::Manager,
+        clusters: Vec<pb::RedisCluster>,
+        creds: &pb::infrastructure::Credentials,
+    ) -> anyhow::Result<Self> {
+        let mut result = HashMap::new();
+        for c in clusters {
+            use pb::redis_cluster::Topology;
+            let (kind, servers): (_, Vec<_>) = match c.topology() {
+                Topology::Standalone => (
+                    "primary",
+                    c.servers
+                        .iter()
+                        .filter(|s| s.kind() == pb::ServerKind::Primary)
+                        .take(1)
+                        .collect(),
+                ),
+                Topology::Cluster => (
+                    "primary",
+                    c.servers
+                        .iter()
+                        .filter(|s| s.kind() == pb::ServerKind::Primary)
+                        .collect(),
+                ),
+                Topology::Sentinel => (
+                    "sentinel",
+                    c.servers
+                        .iter()
+                        .filter(|s| s.kind() == pb::ServerKind::Sentinel)
+                        .collect(),
+                ),
+            };
+            if servers.is_empty() {
+                log::warn!(
+                    "no {} server found for redis cluster {}, skipping",
+                    kind,
+                    c.rid
+                );
+                continue;
+            }
+
+            for db in &c.databases {
+                let cluster = Cluster::new(&c, &servers, db, creds, secrets)
+                    .with_context(|| format!("invalid redis database {}", db.encore_name))?;
+                result.insert(EncoreName::from(&db.encore_name), Arc::new(cluster));
+            }
+        }
+
+        Ok(Self { clusters: result })
+    }
+
+    /// Returns the Redis database with the given name, if configured.
+    pub fn cluster(&self, name: &EncoreName) -> Option<Arc<Cluster>> {
+        self.clusters.get(name).cloned()
+    }
+
+    /// Returns the Redis databases to wait for at startup.
+    pub fn startup_dependencies(&self) -> Vec<startup::Dependency> {
+        self.clusters
+            .values()
+            .map(|cluster| {
+                let cluster = cluster.clone();
+                startup::Dependency::new(format!("redis database {}", cluster.name), move || {
+                    let cluster = cluster.clone();
+                    async move {
+                        let mut conn = cluster.conn().await?;
+                        redis::cmd("PING").query_async::<()>(&mut conn).await?;
+                        Ok(())
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// A single Redis database.
+///
+/// The connection is established lazily on first use and is
+/// automatically re-established if it is lost.
+pub struct Cluster {
+    name: EncoreName,
+    client: Client,
+    db_index: i64,
+    key_prefix: Option<String>,
+    timeouts: Timeouts,
+    /// The password to authenticate new connections with, if any.
+    password: Option<Password>,
+    conn: RwLock<Option<CachedConn>>,
+    /// Values being computed by `get_or_compute`.
+    pub(super) computing: SingleFlight,
+}
+
+/// Timeouts for connecting to the database and replacing connections.
+#[derive(Debug, Clone, Copy, Default)]
+struct Timeouts {
+    connect: Option<Duration>,
+    response: Option<Duration>,
+    idle: Option<Duration>,
+    max_lifetime: Option<Duration>,
+}
+
+impl Timeouts {
+    fn from_pool(pool: &pb::RedisConnectionPool) -> Self {
+        let duration = |d: Option<prost_types::Duration>| {
+            d.and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero())
+        };
+        Self {
+            connect: duration(pool.acquire_timeout),
+            response: duration(pool.statement_timeout),
+            idle: duration(pool.idle_timeout),
+            max_lifetime: duration(pool.max_lifetime),
+        }
+    }
+
+    fn manager_config(&self) -> ConnectionManagerConfig {
+        let mut cfg = ConnectionManagerConfig::new();
+        if let Some(timeout) = self.connect {
+            cfg = cfg.set_connection_timeout(timeout);
+        }
+        if let Some(timeout) = self.response {
+            cfg = cfg.set_response_timeout(timeout);
+        }
+        cfg
+    }
+}
+
+/// The shared connection, along with when it was created and last handed out.
+struct CachedConn {
+    conn: Connection,
+    created: Instant,
+    /// Milliseconds since `created` that the connection was last handed out.
+    last_used: AtomicU64,
+}
+
+impl CachedConn {
+    fn new(conn: Connection) -> Self {
+        Self {
+            conn,
+            created: Instant::now(),
+            last_used: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the connection unless it's stale or has expired.
+    fn get(&self, timeouts: &Timeouts) -> Option<Connection> {
+        if self.conn.is_stale() {
+            return None;
+        }
+        let age = self.created.elapsed();
+        if timeouts.max_lifetime.is_some_and(|max| age >= max) {
+            return None;
+        }
+        let last_used = Duration::from_millis(self.last_used.load(Ordering::Relaxed));
+        if timeouts
+            .idle
+            .is_some_and(|idle| age.saturating_sub(last_used) >= idle)
+        {
+            return None;
+        }
+        self.last_used
+            .store(age.as_millis() as u64, Ordering::Relaxed);
+        Some(self.conn.clone())
+    }
+}
+
+/// How new connections authenticate. Passwords are resolved when connecting,
+/// so that databases that are never used don't require their secrets.
+enum Password {
+    Secret(secrets::Secret),
+    /// Generates short-lived tokens from the runtime's cloud credentials.
+    Iam(IamToken),
+}
+
+impl Password {
+    async fn get(&self) -> anyhow::Result<String> {
+        match self {
+            Password::Secret(secret) => resolve_password(secret),
+            Password::Iam(iam) => iam
+                .token()
+                .await
+                .context("unable to generate IAM authentication token"),
+        }
+    }
+}
+
+fn resolve_password(secret: &secrets::Secret) -> anyhow::Result<String> {
+    let bytes = secret.get().context("failed to resolve redis password")?;
+    String::from_utf8(bytes.to_vec()).context("redis password is not valid utf-8")
+}
+
+enum Client {
+    Standalone(redis::Client),
+    Cluster {
+        client: redis::cluster::ClusterClient,
+        /// The seed nodes, for creating clients with the resolved password.
+        nodes: Vec<redis::ConnectionInfo>,
+        /// The CA certificate to verify the nodes with, if any.
+        ca_cert: Option<Vec<u8>>,
+    },
+    Sentinel(SentinelClient),
+}
+
+/// Finds the primary through the sentinels, which are connected to
+/// on first use so that their password is only resolved then.
+struct SentinelClient {
+    sentinel: Mutex<Option<Sentinel>>,
+    nodes: Vec<redis::ConnectionInfo>,
+    password: Option<secrets::Secret>,
+    master_name: String,
+    node_info: SentinelNodeConnectionInfo,
+}
+
+impl SentinelClient {
+    /// Asks the sentinels for a client of the current primary,
+    /// authenticating with the given password if set.
+    async fn primary(&self, password: Option<String>) -> anyhow::Result<redis::Client> {
+        let mut node_info = self.node_info.clone();
+        if let (Some(password), Some(info)) = (password, node_info.redis_connection_info.as_mut()) {
+            info.password = Some(password);
+        }
+
+        let mut sentinel = self.sentinel.lock().await;
+        if sentinel.is_none() {
+            let password = self.password.as_ref().map(resolve_password).transpose()?;
+            let nodes = self
+                .nodes
+                .iter()
+                .map(|node| {
+                    let mut node = node.clone();
+                    node.redis.password = password.clone();
+                    node
+                })
+                .collect::<Vec<_>>();
+            *sentinel =
+                Some(Sentinel::build(nodes).context("unable to create redis sentinel client")?);
+        }
+        sentinel
+            .as_mut()
+            .unwrap()
+            .async_master_for(&self.master_name, Some(&node_info))
+            .await
+            .context("unable to find redis primary")
+    }
+}
+
+impl Cluster {
+    fn new(
+        cluster: &pb::RedisCluster,
+        servers: &[&pb::RedisServer],
+        db: &pb::RedisDatabase,
+        creds: &pb::infrastructure::Credentials,
+        secrets: &secrets::Manager,
+    ) -> anyhow::Result<Self> {
+        let mut addrs = servers
+            .iter()
+            .copied()
+            .map(conn_addr)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut redis_info = redis::RedisConnectionInfo {
+            db: db.database_idx as i64,
+            ..Default::default()
+        };
+
+        // Use the read-write pool's role to authenticate, if any.
+        let pool = db.conn_pools.iter().find(|p| !p.is_readonly);
+        let mut timeouts = pool.map(Timeouts::from_pool).unwrap_or_default();
+        let role = pool
+            .map(|pool| {
+                creds
+                    .redis_roles
+                    .iter()
+                    .find(|r| r.rid == pool.role_rid)
+                    .with_context(|| format!("no role found with rid {}", pool.role_rid))
+            })
+            .transpose()?;
+        let mut password = None;
+        if let Some(auth) = role.and_then(|r| r.auth.as_ref()) {
+            match auth {
+                pb::redis_role::Auth::Acl(acl) => {
+                    redis_info.username = Some(acl.username.clone());
+                    password = acl
+                        .password
+                        .as_ref()
+                        .map(|data| Password::Secret(secrets.load(data.clone())));
+                }
+                pb::redis_role::Auth::AuthString(data) => {
+                    password = Some(Password::Secret(secrets.load(data.clone())));
+                }
+                pb::redis_role::Auth::Iam(auth) => {
+                    let provider = auth
+                        .provider
+                        .as_ref()
+                        .context("missing IAM auth provider")?;
+                    anyhow::ensure!(
+                        matches!(
+                            provider.provider,
+                            Some(pb::iam_auth::Provider::AwsElasticache(_))
+                        ),
+                        "only ElastiCache IAM authentication is supported for redis"
+                    );
+                    redis_info.username = Some(auth.username.clone());
+                    password = Some(Password::Iam(IamToken::new(provider, "", &auth.username)?));
+
+                    // Connections reconnect with the token they were created with,
+                    // so replace them before it expires.
+                    timeouts.max_lifetime = Some(
+                        timeouts
+                            .max_lifetime
+                            .map_or(iamauth::MAX_CONN_LIFETIME, |max| {
+                                max.min(iamauth::MAX_CONN_LIFETIME)
+                            }),
+                    );
+                }
+            }
+        }
+
+        use pb::redis_cluster::Topology;
+        let db_index = match cluster.topology() {
+            Topology::Cluster => 0,
+            _ => redis_info.db,
+        };
+        let client = match cluster.topology() {
+            Topology::Standalone => Client::Standalone(
+                redis::Client::open(redis::ConnectionInfo {
+                    addr: addrs.remove(0),
+                    redis: redis_info,
+                })
+                .context("unable to create redis client")?,
+            ),
+
+            Topology::Cluster => {
+                if redis_info.db != 0 {
+                    log::warn!(
+                        "redis cluster only supports database 0, ignoring database index of {}",
+                        db.encore_name
+                    );
+                    redis_info.db = 0;
+                }
+                let nodes: Vec<_> = addrs
+                    .into_iter()
+                    .map(|addr| redis::ConnectionInfo {
+                        addr,
+                        redis: redis_info.clone(),
+                    })
+                    .collect();
+                let ca_cert = servers[0]
+                    .tls_config
+                    .as_ref()
+                    .and_then(|tls| tls.server_ca_cert.clone())
+                    .map(String::into_bytes);
+                Client::Cluster {
+                    client: cluster_client(nodes.clone(), &timeouts, ca_cert.as_deref())?,
+                    nodes,
+                    ca_cert,
+                }
+            }
+
+            Topology::Sentinel => {
+                let master_name = cluster
+                    .sentinel_master_name
+                    .clone()
+                    .context("sentinel master name is required")?;
+                let nodes: Vec<_> = addrs
+                    .into_iter()
+                    .map(|addr| redis::ConnectionInfo {
+                        addr,
+                        redis: Default::default(),
+                    })
+                    .collect();
+
+                // The primary uses the same TLS settings as the sentinels.
+                let tls_config = servers[0].tls_config.as_ref();
+                if tls_config.is_some_and(|tls| tls.server_ca_cert.is_some()) {
+                    log::warn!(
+                        "custom server CA certificates are only used for the sentinels of redis database {}, the primary is verified using system roots",
+                        db.encore_name
+                    );
+                }
+                let tls_mode = tls_config.map(|tls| {
+                    if tls.disable_ca_validation {
+                        redis::TlsMode::Insecure
+                    } else {
+                        redis::TlsMode::Secure
+                    }
+                });
+                Client::Sentinel(SentinelClient {
+                    sentinel: Mutex::new(None),
+                    nodes,
+                    password: cluster
+                        .sentinel_password
+                        .as_ref()
+                        .map(|data| secrets.load(data.clone())),
+                    master_name,
+                    node_info: SentinelNodeConnectionInfo {
+                        tls_mode,
+                        redis_connection_info: Some(redis_info),
+                    },
+                })
+            }
+        };
+
+        Ok(Self {
+            name: EncoreName::from(&db.encore_name),
+            client,
+            db_index,
+            key_prefix: db.key_prefix.clone(),
+            timeouts,
+            password,
+            conn: RwLock::new(None),
+            computing: SingleFlight::default(),
+        })
+    }
+
+    pub fn name(&self) -> &EncoreName {
+        &self.name
+    }
+
+    /// Returns the key to use for the given key, with the database's key prefix applied.
+    pub fn key(&self, key: &str) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{prefix}{key}"),
+            None => key.to_string(),
+        }
+    }
+
+    /// Returns a connection to the database.
+    /// The returned connection is cheap to clone.
+    pub async fn conn(&self) -> anyhow::Result<Connection> {
+        faults::inject(faults::Target::Redis).await?;
+        if let Some(conn) = self
+            .conn
+            .read()
+            .await
+            .as_ref()
+            .and_then(|c| c.get(&self.timeouts))
+        {
+            return Ok(conn);
+        }
+
+        let mut guard = self.conn.write().await;
+        if let Some(conn) = guard.as_ref().and_then(|c| c.get(&self.timeouts)) {
+            return Ok(conn);
+        }
+        let conn = self
+            .connect()
+            .await
+            .with_context(|| format!("unable to connect to redis database {}", self.name))?;
+        *guard = Some(CachedConn::new(conn.clone()));
+        Ok(conn)
+    }
+
+    /// The index of the database within the server.
+    pub(super) fn db_index(&self) -> i64 {
+        self.db_index
+    }
+
+    /// Opens a dedicated connection for receiving Pub/Sub messages.
+    /// Redis Cluster isn't supported, as messages are only sent to clients of the node
+    /// they're published on.
+    pub(super) async fn pubsub(&self) -> anyhow::Result<redis::aio::PubSub> {
+        let password = self.password().await?;
+        let client = match &self.client {
+            Client::Standalone(client) => with_password(client, password)?,
+            Client::Cluster { .. } => {
+                anyhow::bail!(
+                    "redis database {} uses Redis Cluster, which isn't supported for Pub/Sub connections",
+                    self.name
+                )
+            }
+            Client::Sentinel(sentinel) => sentinel.primary(password).await?,
+        };
+        client
+            .get_async_pubsub()
+            .await
+            .with_context(|| format!("unable to connect to redis database {}", self.name))
+    }
+
+    /// Returns the password to authenticate new connections with, if any.
+    async fn password(&self) -> anyhow::Result<Option<String>> {
+        match &self.password {
+            Some(password) => password.get().await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn connect(&self) -> anyhow::Result<Connection> {
+        let password = self.password().await?;
+        Ok(match &self.client {
+            Client::Standalone(client) => Connection::Single(
+                ConnectionManager::new_with_config(
+                    with_password(client, password)?,
+                    self.timeouts.manager_config(),
+                )
+                .await?,
+            ),
+            Client::Cluster {
+                client,
+                nodes,
+                ca_cert,
+            } => {
+                let client = match password {
+                    Some(password) => {
+                        let nodes = nodes
+                            .iter()
+                            .map(|node| {
+                                let mut node = node.clone();
+                                node.redis.password = Some(password.clone());
+                                node
+                            })
+                            .collect();
+                        cluster_client(nodes, &self.timeouts, ca_cert.as_deref())?
+                    }
+                    None => client.clone(),
+                };
+                Connection::Cluster(client.get_async_connection().await?)
+            }
+            Client::Sentinel(sentinel) => {
+                let client = sentinel.primary(password).await?;
+                Connection::Sentinel {
+                    conn: ConnectionManager::new_with_config(
+                        client,
+                        self.timeouts.manager_config(),
+                    )
+                    .await?,
+                    stale: Arc::default(),
+                }
+            }
+        })
+    }
+}
+
+fn cluster_client(
+    nodes: Vec<redis::ConnectionInfo>,
+    timeouts: &Timeouts,
+    ca_cert: Option<&[u8]>,
+) -> anyhow::Result<redis::cluster::ClusterClient> {
+    let mut builder = redis::cluster::ClusterClient::builder(nodes);
+    if let Some(ca_cert) = ca_cert {
+        builder = builder.certs(redis::TlsCertificates {
+            client_tls: None,
+            root_cert: Some(ca_cert.to_vec()),
+        });
+    }
+    if let Some(timeout) = timeouts.connect {
+        builder = builder.connection_timeout(timeout);
+    }
+    if let Some(timeout) = timeouts.response {
+        builder = builder.response_timeout(timeout);
+    }
+    builder
+        .build()
+        .context("unable to create redis cluster client")
+}
+
+/// Returns the client, authenticating with the given password instead if set.
+fn with_password(
+    client: &redis::Client,
+    password: Option<String>,
+) -> anyhow::Result<redis::Client> {
+    let Some(password) = password else {
+        return Ok(client.clone());
+    };
+    let mut info = client.get_connection_info().clone();
+    info.redis.password = Some(password);
+    redis::Client::open(info).context("unable to create redis client")
+}
+
+fn conn_addr(server: &pb::RedisServer) -> anyhow::Result<redis::ConnectionAddr> {
+    if server.host.starts_with('/') {
+        return Ok(redis::ConnectionAddr::Unix(server.host.clone().into()));
+    }
+
+    let (host, port) = match server.host.split_once(':') {
+        Some((host, port)) => (host.to_string(), port.parse().context("invalid port")?),
+        None => (server.host.clone(), 6379),
+    };
+    let Some(tls) = &server.tls_config else {
+        return Ok(redis::ConnectionAddr::Tcp(host, port));
+    };
+    anyhow::ensure!(
+        tls.disable_ca_validation || !tls.disable_tls_hostname_verification,
+        "disabling only hostname verification is not supported for redis, configure server_ca_cert instead"
+    );
+    let addr = redis::ConnectionAddr::TcpTls {
+        host,
+        port,
+        insecure: tls.disable_ca_validation,
+        tls_params: None,
+    };
+
+    // Verify the server with its CA certificate instead of the system roots.
+    match &tls.server_ca_cert {
+        Some(ca_cert) if !tls.disable_ca_validation => {
+            let certs = redis::TlsCertificates {
+                client_tls: None,
+                root_cert: Some(ca_cert.as_bytes().to_vec()),
+            };
+            let client = redis::Client::build_with_tls(
+                redis::ConnectionInfo {
+                    addr,
+                    redis: Default::default(),
+                },
+                certs,
+            )
+            .context("invalid server CA certificate")?;
+            Ok(client.get_connection_info().addr.clone())
+        }
+        _ => Ok(addr),
+    }
+}
+
+/// A connection to a Redis database.
+#[derive(Clone)]
+pub enum Connection {
+    Single(ConnectionManager),
+    Cluster(ClusterConnection),
+    /// A connection to the primary found through the sentinels.
+    /// It's marked as stale when the primary seems to have failed over,
+    /// so the next connection asks the sentinels again.
+    Sentinel {
+        conn: ConnectionManager,
+        stale: Arc<AtomicBool>,
+    },
+}
+
+impl Connection {
+    fn is_stale(&self) -> bool {
+        matches!(self, Connection::Sentinel { stale, .. } if stale.load(Ordering::Relaxed))
+    }
+}
+
+/// Marks a sentinel connection as stale if the error suggests
+/// the primary is down or has been demoted to a replica.
+fn check_failover<T>(stale: &AtomicBool, res: &redis::RedisResult<T>) {
+    if let Err(err) = res {
+        if err.is_io_error()
+            || err.is_connection_dropped()
+            || err.is_connection_refusal()
+            || err.kind() == redis::ErrorKind::ReadOnly
+        {
+            stale.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(
+        &'a mut self,
+        cmd: &'a redis::Cmd,
+    ) -> redis::RedisFuture<'a, redis::Value> {
+        match self {
+            Connection::Single(conn) => conn.req_packed_command(cmd),
+            Connection::Cluster(conn) => conn.req_packed_command(cmd),
+            Connection::Sentinel { conn, stale } => Box::pin(async move {
+                let res = conn.req_packed_command(cmd).await;
+                check_failover(stale, &res);
+                res
+            }),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a redis::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
+        match self {
+            Connection::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            Connection::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+            Connection::Sentinel { conn, stale } => Box::pin(async move {
+                let res = conn.req_packed_commands(cmd, offset, count).await;
+                check_failover(stale, &res);
+                res
+            }),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Connection::Single(conn) | Connection::Sentinel { conn, .. } => conn.get_db(),
+            Connection::Cluster(conn) => conn.get_db(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_failover() {
+        let stale = AtomicBool::new(false);
+        check_failover::<()>(
+            &stale,
+            &Err((redis::ErrorKind::ResponseError, "WRONGTYPE").into()),
+        );
+        assert!(!stale.load(Ordering::Relaxed));
+
+        check_failover::<()>(
+            &stale,
+            &Err((redis::ErrorKind::ReadOnly, "READONLY").into()),
+        );
+        assert!(stale.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,7 @@
+pub use compute::ComputeOptions;
+pub use keyevents::{KeyEvent, KeyEventHandler, KeyEventKind, KeyEventSubscription};
+pub use manager::{Cluster, Connection, Manager};
+
+mod compute;
+mod keyevents;
+mod manager;
@@ -0,0 +1,77 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use aws_sdk_kms::primitives::Blob;
+use aws_sdk_kms::types::DataKeySpec;
+use tokio::sync::OnceCell;
+
+use crate::crypto::{DataKey, KeyProvider};
+use crate::encore::runtime::v1 as pb;
+
+/// Generates and decrypts data keys using AWS KMS.
+#[derive(Debug)]
+pub struct AwsKmsKey {
+    cfg: pb::encryption_key::AwsKms,
+    client: OnceCell<aws_sdk_kms::Client>,
+}
+
+impl AwsKmsKey {
+    pub fn new(cfg: pb::encryption_key::AwsKms) -> Self {
+        Self {
+            cfg,
+            client: OnceCell::new(),
+        }
+    }
+
+    async fn client(&self) -> &aws_sdk_kms::Client {
+        self.client
+            .get_or_init(|| async {
+                let mut builder = aws_config::defaults(aws_config::BehaviorVersion::v2025_08_07());
+                if let Some(region) = &self.cfg.region {
+                    builder = builder.region(aws_config::Region::new(region.clone()));
+                }
+                aws_sdk_kms::Client::new(&builder.load().await)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl KeyProvider for AwsKmsKey {
+    async fn generate_data_key(&self) -> anyhow::Result<DataKey> {
+        let out = self
+            .client()
+            .await
+            .generate_data_key()
+            .key_id(&self.cfg.key_id)
+            .key_spec(DataKeySpec::Aes256)
+            .send()
+            .await
+            .context("unable to generate data key")?;
+        Ok(DataKey {
+            plaintext: out
+                .plaintext
+                .context("missing plaintext data key")?
+                .into_inner(),
+            encrypted: out
+                .ciphertext_blob
+                .context("missing encrypted data key")?
+                .into_inner(),
+        })
+    }
+
+    async fn decrypt_data_key(&self, encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let out = self
+            .client()
+            .await
+            .decrypt()
+            .key_id(&self.cfg.key_id)
+            .ciphertext_blob(Blob::new(encrypted))
+            .send()
+            .await
+            .context("unable to decrypt data key")?;
+        Ok(out
+            .plaintext
+            .context("missing plaintext data key")?
+            .into_inner())
+    }
+}
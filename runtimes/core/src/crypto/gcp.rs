@@ -0,0 +1,73 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use google_cloud_kms_v1::client::KeyManagementService;
+use tokio::sync::OnceCell;
+
+use crate::crypto::{random_key, DataKey, KeyProvider};
+use crate::encore::runtime::v1 as pb;
+
+/// Encrypts and decrypts data keys using Cloud KMS.
+///
+/// Cloud KMS doesn't generate data keys, so they're generated locally
+/// and encrypted with the key.
+#[derive(Debug)]
+pub struct GcpKmsKey {
+    cfg: pb::encryption_key::GcpKms,
+    client: OnceCell<KeyManagementService>,
+}
+
+impl GcpKmsKey {
+    pub fn new(cfg: pb::encryption_key::GcpKms) -> Self {
+        Self {
+            cfg,
+            client: OnceCell::new(),
+        }
+    }
+
+    async fn client(&self) -> anyhow::Result<&KeyManagementService> {
+        self.client
+            .get_or_try_init(|| async {
+                KeyManagementService::builder()
+                    .build()
+                    .await
+                    .context("create kms client")
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl KeyProvider for GcpKmsKey {
+    async fn generate_data_key(&self) -> anyhow::Result<DataKey> {
+        let plaintext = random_key()?;
+        let resp = self
+            .client()
+            .await?
+            .encrypt()
+            .set_name(&self.cfg.key_name)
+            .set_plaintext(Bytes::copy_from_slice(&plaintext))
+            .send()
+            .await
+            .map_err(anyhow::Error::new)
+            .context("unable to encrypt data key")?;
+        Ok(DataKey {
+            plaintext,
+            encrypted: resp.ciphertext.to_vec(),
+        })
+    }
+
+    async fn decrypt_data_key(&self, encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let resp = self
+            .client()
+            .await?
+            .decrypt()
+            .set_name(&self.cfg.key_name)
+            .set_ciphertext(Bytes::copy_from_slice(encrypted))
+            .send()
+            .await
+            .map_err(anyhow::Error::new)
+            .context("unable to decrypt data key")?;
+        Ok(resp.plaintext.to_vec())
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use base64::Engine;
+
+use crate::crypto::{open, random_key, seal, DataKey, KeyProvider, DATA_KEY_LEN};
+use crate::secrets;
+
+/// Encrypts data keys with a master key held by the application.
+pub struct LocalKey {
+    master_key: secrets::Secret,
+}
+
+impl LocalKey {
+    pub fn new(master_key: secrets::Secret) -> Self {
+        Self { master_key }
+    }
+
+    fn master_key(&self) -> anyhow::Result<Vec<u8>> {
+        let data = self
+            .master_key
+            .get()
+            .map_err(|e| anyhow::anyhow!("unable to resolve master key: {e}"))?;
+        let encoded = std::str::from_utf8(data).context("master key is not valid base64")?;
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .context("master key is not valid base64")?;
+        if key.len() != DATA_KEY_LEN {
+            anyhow::bail!("master key must be {DATA_KEY_LEN} bytes, got {}", key.len());
+        }
+        Ok(key)
+    }
+}
+
+impl std::fmt::Debug for LocalKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl KeyProvider for LocalKey {
+    async fn generate_data_key(&self) -> anyhow::Result<DataKey> {
+        let master_key = self.master_key()?;
+        let plaintext = random_key()?;
+        let encrypted = seal(&master_key, &plaintext, &[])?;
+        Ok(DataKey {
+            plaintext,
+            encrypted,
+        })
+    }
+
+    async fn decrypt_data_key(&self, encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let master_key = self.master_key()?;
+        open(&master_key, encrypted, &[]).context("unable to decrypt data key")
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::crypto::{aws, gcp, local, EncryptionKey, KeyProvider};
+use crate::encore::runtime::v1 as pb;
+use crate::names::EncoreName;
+use crate::secrets;
+
+pub struct Manager {
+    keys: HashMap<EncoreName, Arc<EncryptionKey>>,
+}
+
+impl Manager {
+    pub fn new(secrets: &secrets::Manager, keys: Vec<pb::EncryptionKey>) -> Self {
+        let keys = keys
+            .into_iter()
+            .filter_map(|key| {
+                let Some(provider) = key.provider else {
+                    log::error!("missing encryption key provider: {}", key.rid);
+                    return None;
+                };
+                let name = EncoreName::from(key.encore_name);
+                let provider = new_provider(secrets, provider);
+                Some((name.clone(), Arc::new(EncryptionKey::new(name, provider))))
+            })
+            .collect();
+        Self { keys }
+    }
+
+    /// Returns the encryption key with the given name, if it's configured.
+    pub fn key(&self, name: &EncoreName) -> Option<Arc<EncryptionKey>> {
+        self.keys.get(name).cloned()
+    }
+}
+
+fn new_provider(
+    secrets: &secrets::Manager,
+    provider: pb::encryption_key::Provider,
+) -> Box<dyn KeyProvider> {
+    use pb::encryption_key::Provider;
+    match provider {
+        Provider::AwsKms(cfg) => Box::new(aws::AwsKmsKey::new(cfg)),
+        Provider::GcpKms(cfg) => Box::new(gcp::GcpKmsKey::new(cfg)),
+        Provider::Local(cfg) => Box::new(local::LocalKey::new(
+            secrets.load(cfg.master_key.unwrap_or_default()),
+        )),
+    }
+}
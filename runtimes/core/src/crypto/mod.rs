@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use thiserror::Error;
+
+pub use manager::Manager;
+
+use crate::EncoreName;
+
+mod aws;
+mod gcp;
+mod local;
+mod manager;
+
+/// The version of the envelope format.
+const VERSION: u8 = 1;
+
+const DATA_KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// How long a generated data key is used to encrypt data
+/// before a new one is generated.
+const DATA_KEY_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The maximum number of decrypted data keys to keep in memory.
+const MAX_CACHED_KEYS: usize = 1000;
+
+/// Provides the key encryption key that data keys are encrypted with.
+#[async_trait]
+trait KeyProvider: Debug + Send + Sync {
+    /// Generates a new data key.
+    async fn generate_data_key(&self) -> anyhow::Result<DataKey>;
+
+    /// Decrypts a data key previously returned by `generate_data_key`.
+    async fn decrypt_data_key(&self, encrypted: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+struct DataKey {
+    plaintext: Vec<u8>,
+    encrypted: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid ciphertext")]
+    InvalidCiphertext,
+
+    #[error("key provider error: {0:#}")]
+    Provider(anyhow::Error),
+}
+
+/// An encryption key for envelope encryption of application data.
+///
+/// Data is encrypted with AES-256-GCM using a data key, which is encrypted
+/// by the key provider and stored alongside the ciphertext. Data keys are
+/// reused for a few minutes to avoid a round-trip to the provider for every
+/// encryption, and decrypted data keys are cached in memory.
+pub struct EncryptionKey {
+    name: EncoreName,
+    provider: Box<dyn KeyProvider>,
+    current: tokio::sync::Mutex<Option<(Arc<DataKey>, Instant)>>,
+    decrypted: Mutex<HashMap<Vec<u8>, Arc<Vec<u8>>>>,
+}
+
+impl EncryptionKey {
+    fn new(name: EncoreName, provider: Box<dyn KeyProvider>) -> Self {
+        Self {
+            name,
+            provider,
+            current: tokio::sync::Mutex::new(None),
+            decrypted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn name(&self) -> &EncoreName {
+        &self.name
+    }
+
+    /// Encrypts the plaintext. The additional authenticated data, if any,
+    /// must be provided again to decrypt it.
+    pub async fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        let key = self.data_key().await?;
+        let sealed = seal(&key.plaintext, plaintext, aad).map_err(Error::Provider)?;
+
+        let mut out = Vec::with_capacity(3 + key.encrypted.len() + sealed.len());
+        out.push(VERSION);
+        out.extend_from_slice(&(key.encrypted.len() as u16).to_be_bytes());
+        out.extend_from_slice(&key.encrypted);
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    /// Decrypts data encrypted by `encrypt` with the same key.
+    pub async fn decrypt(&self, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        let (encrypted_key, sealed) = parse_envelope(ciphertext)?;
+        let key = self.decrypt_data_key(encrypted_key).await?;
+        open(&key, sealed, aad).ok_or(Error::InvalidCiphertext)
+    }
+
+    async fn data_key(&self) -> Result<Arc<DataKey>, Error> {
+        let mut current = self.current.lock().await;
+        if let Some((key, created)) = current.as_ref() {
+            if created.elapsed() < DATA_KEY_TTL {
+                return Ok(key.clone());
+            }
+        }
+
+        let key = Arc::new(
+            self.provider
+                .generate_data_key()
+                .await
+                .map_err(Error::Provider)?,
+        );
+        if key.plaintext.len() != DATA_KEY_LEN || key.encrypted.len() > u16::MAX as usize {
+            return Err(Error::Provider(anyhow::anyhow!(
+                "key provider returned an invalid data key"
+            )));
+        }
+        *current = Some((key.clone(), Instant::now()));
+        Ok(key)
+    }
+
+    async fn decrypt_data_key(&self, encrypted: &[u8]) -> Result<Arc<Vec<u8>>, Error> {
+        if let Some(key) = self.decrypted.lock().unwrap().get(encrypted) {
+            return Ok(key.clone());
+        }
+
+        let key = self
+            .provider
+            .decrypt_data_key(encrypted)
+            .await
+            .map_err(Error::Provider)?;
+        if key.len() != DATA_KEY_LEN {
+            return Err(Error::InvalidCiphertext);
+        }
+
+        let key = Arc::new(key);
+        let mut decrypted = self.decrypted.lock().unwrap();
+        if decrypted.len() >= MAX_CACHED_KEYS {
+            decrypted.clear();
+        }
+        decrypted.insert(encrypted.to_vec(), key.clone());
+        Ok(key)
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("name", &self.name)
+            .field("provider", &self.provider)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Splits an envelope into the encrypted data key and the sealed data.
+fn parse_envelope(data: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let [VERSION, a, b, rest @ ..] = data else {
+        return Err(Error::InvalidCiphertext);
+    };
+    let key_len = u16::from_be_bytes([*a, *b]) as usize;
+    if rest.len() < key_len + NONCE_LEN + TAG_LEN {
+        return Err(Error::InvalidCiphertext);
+    }
+    Ok(rest.split_at(key_len))
+}
+
+/// Encrypts the plaintext with AES-256-GCM and a random nonce,
+/// returning the nonce, ciphertext and tag.
+fn seal(key: &[u8], plaintext: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let mut tag = [0u8; TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        aad,
+        plaintext,
+        &mut tag,
+    )?;
+
+    let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len() + TAG_LEN);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    out.extend_from_slice(&tag);
+    Ok(out)
+}
+
+/// Decrypts data sealed by `seal`, returning None if it's invalid.
+fn open(key: &[u8], sealed: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return None;
+    }
+    let (nonce, rest) = sealed.split_at(NONCE_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        aad,
+        ciphertext,
+        tag,
+    )
+    .ok()
+}
+
+/// Generates a random data key.
+fn random_key() -> anyhow::Result<Vec<u8>> {
+    let mut key = vec![0u8; DATA_KEY_LEN];
+    openssl::rand::rand_bytes(&mut key)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::Secret;
+
+    fn local_key() -> EncryptionKey {
+        // base64 of 32 bytes of 0x2a.
+        let master = Secret::new_for_test("KioqKioqKioqKioqKioqKioqKioqKioqKioqKioqKio=");
+        EncryptionKey::new("key".into(), Box::new(local::LocalKey::new(master)))
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let key = local_key();
+        let ciphertext = key.encrypt(b"hello", b"aad").await.unwrap();
+        assert_ne!(&ciphertext[..], b"hello");
+        assert_eq!(key.decrypt(&ciphertext, b"aad").await.unwrap(), b"hello");
+
+        // The data key is reused, but each encryption uses a new nonce.
+        let other = key.encrypt(b"hello", b"aad").await.unwrap();
+        assert_ne!(ciphertext, other);
+        let (key_a, _) = parse_envelope(&ciphertext).unwrap();
+        let (key_b, _) = parse_envelope(&other).unwrap();
+        assert_eq!(key_a, key_b);
+
+        // Another key with the same master key can decrypt it.
+        assert_eq!(local_key().decrypt(&other, b"aad").await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_ciphertext() {
+        let key = local_key();
+        let ciphertext = key.encrypt(b"hello", b"aad").await.unwrap();
+
+        let res = key.decrypt(&ciphertext, b"other").await;
+        assert!(matches!(res, Err(Error::InvalidCiphertext)));
+
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let res = key.decrypt(&tampered, b"aad").await;
+        assert!(matches!(res, Err(Error::InvalidCiphertext)));
+
+        let res = key.decrypt(&ciphertext[..10], b"aad").await;
+        assert!(matches!(res, Err(Error::InvalidCiphertext)));
+        let res = key.decrypt(b"", b"aad").await;
+        assert!(matches!(res, Err(Error::InvalidCiphertext)));
+    }
+}
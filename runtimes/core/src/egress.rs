@@ -0,0 +1,324 @@
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::Context;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+use crate::encore::runtime::v1 as pb;
+
+/// The proxy requests to disallowed IP addresses are routed to.
+/// It never resolves, so the requests fail without leaving the host.
+const BLOCKED_PROXY: &str = "http://egress-blocked.invalid";
+const BLOCKED_HOST: &str = "egress-blocked.invalid";
+
+static POLICY: OnceLock<Arc<Policy>> = OnceLock::new();
+
+/// Restricts which hosts the runtime makes outbound connections to.
+#[derive(Debug, Default)]
+pub struct Policy {
+    hosts: Vec<HostPattern>,
+    cidrs: Vec<cidr::IpCidr>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum HostPattern {
+    /// Matches the host exactly.
+    Exact(String),
+    /// Matches subdomains of a domain, written as "*.example.com".
+    /// Holds the suffix subdomains end with, ".example.com".
+    Subdomains(String),
+}
+
+impl HostPattern {
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(h) => h == host,
+            HostPattern::Subdomains(suffix) => host
+                .strip_suffix(suffix.as_str())
+                .is_some_and(|sub| !sub.is_empty()),
+        }
+    }
+}
+
+impl Policy {
+    /// Combines the egress policies of the hosted services into one that
+    /// allows what any of them allows. Returns None if no policy is configured.
+    pub fn new<'a>(
+        policies: impl IntoIterator<Item = &'a pb::EgressPolicy>,
+    ) -> anyhow::Result<Option<Self>> {
+        let mut policy: Option<Self> = None;
+        for cfg in policies {
+            let policy = policy.get_or_insert_with(Default::default);
+            for host in &cfg.hosts {
+                let host = normalize_host(host);
+                policy.hosts.push(match host.strip_prefix("*.") {
+                    Some(domain) => HostPattern::Subdomains(format!(".{domain}")),
+                    None => HostPattern::Exact(host),
+                });
+            }
+            for cidr in &cfg.cidrs {
+                policy.cidrs.push(parse_cidr(cidr)?);
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Reports whether the host is allowed by name.
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = normalize_host(host);
+        self.hosts.iter().any(|p| p.matches(&host))
+    }
+
+    /// Reports whether the address is in an allowed CIDR range.
+    pub fn allows_addr(&self, addr: IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            IpAddr::V4(_) => addr,
+        };
+        self.cidrs.iter().any(|c| c.contains(&addr))
+    }
+
+    /// Checks that connecting to the host is allowed, either by name
+    /// or because all its addresses are in allowed CIDR ranges.
+    /// Resolves the host if needed, blocking the current thread.
+    pub fn check_endpoint(&self, host: &str, port: u16) -> anyhow::Result<()> {
+        if self.allows_host(host) {
+            return Ok(());
+        }
+        let addrs: Vec<SocketAddr> = (host, port)
+            .to_socket_addrs()
+            .with_context(|| format!("unable to resolve {host}"))?
+            .collect();
+        if !addrs.is_empty() && addrs.iter().all(|a| self.allows_addr(a.ip())) {
+            return Ok(());
+        }
+        anyhow::bail!("connecting to {host} is not allowed by the egress policy")
+    }
+}
+
+/// Enables the egress policy for HTTP clients created after this call.
+pub fn init(policy: Policy) {
+    if POLICY.set(Arc::new(policy)).is_err() {
+        log::warn!("egress policy already initialized");
+    }
+}
+
+/// Returns the egress policy, if one is enabled.
+pub fn policy() -> Option<&'static Arc<Policy>> {
+    POLICY.get()
+}
+
+/// Returns a builder for HTTP clients that enforce the egress policy, if any.
+///
+/// Requests to hosts that aren't allowed by name are only made if the host
+/// resolves to addresses in allowed CIDR ranges. System proxies are not used
+/// with an egress policy, as they would resolve hosts on the client's behalf.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    let Some(policy) = policy() else {
+        return builder;
+    };
+
+    let proxy_policy = policy.clone();
+    builder
+        .no_proxy()
+        // IP addresses in URLs aren't resolved, so check them here.
+        .proxy(reqwest::Proxy::custom(move |url| {
+            let addr = match url.host()? {
+                url::Host::Ipv4(ip) => IpAddr::V4(ip),
+                url::Host::Ipv6(ip) => IpAddr::V6(ip),
+                url::Host::Domain(_) => return None,
+            };
+            (!proxy_policy.allows_addr(addr)).then_some(BLOCKED_PROXY)
+        }))
+        .dns_resolver(Arc::new(PolicyResolver {
+            policy: policy.clone(),
+        }))
+}
+
+/// Returns an HTTP client that enforces the egress policy, if any.
+pub fn client() -> reqwest::Client {
+    client_builder()
+        .build()
+        .expect("unable to build http client")
+}
+
+/// Resolves hosts, filtering out the addresses the egress policy doesn't allow.
+struct PolicyResolver {
+    policy: Arc<Policy>,
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            if host == BLOCKED_HOST {
+                return Err("connection blocked by the egress policy".into());
+            }
+
+            let addrs = tokio::net::lookup_host((host.as_str(), 0)).await?;
+            let addrs: Vec<SocketAddr> = if policy.allows_host(&host) {
+                addrs.collect()
+            } else {
+                addrs.filter(|a| policy.allows_addr(a.ip())).collect()
+            };
+            if addrs.is_empty() {
+                log::warn!("blocked connection to {host}: not allowed by the egress policy");
+                return Err(format!("connection to {host} blocked by the egress policy").into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Returns the endpoints the infrastructure clients connect to, as host and port.
+pub fn infra_endpoints(resources: &pb::infrastructure::Resources) -> Vec<(String, u16)> {
+    let mut endpoints = Vec::new();
+    let mut add = |host: &str, default_port: u16| {
+        // Unix sockets don't leave the host.
+        if host.is_empty() || host.starts_with('/') {
+            return;
+        }
+        endpoints.push(split_host_port(host, default_port));
+    };
+
+    for cluster in &resources.sql_clusters {
+        for server in &cluster.servers {
+            add(&server.host, 5432);
+        }
+    }
+    for cluster in &resources.redis_clusters {
+        for server in &cluster.servers {
+            add(&server.host, 6379);
+        }
+    }
+    for cluster in &resources.bucket_clusters {
+        use pb::bucket_cluster::Provider;
+        let endpoint = match &cluster.provider {
+            Some(Provider::S3(s3)) => s3.endpoint.as_deref(),
+            Some(Provider::Gcs(gcs)) => gcs.endpoint.as_deref(),
+            _ => None,
+        };
+        if let Some(url) = endpoint.and_then(|e| url::Url::parse(e).ok()) {
+            if let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                endpoints.push((host.to_string(), port));
+            }
+        }
+    }
+    endpoints
+}
+
+/// Splits "host:port" into its parts, handling bracketed IPv6 addresses.
+fn split_host_port(addr: &str, default_port: u16) -> (String, u16) {
+    if let Some(rest) = addr.strip_prefix('[') {
+        if let Some((host, port)) = rest.split_once(']') {
+            let port = port
+                .strip_prefix(':')
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(default_port);
+            return (host.to_string(), port);
+        }
+    }
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => {
+            (host.to_string(), port.parse().unwrap_or(default_port))
+        }
+        _ => (addr.to_string(), default_port),
+    }
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Parses a CIDR range, or a single IP address.
+fn parse_cidr(s: &str) -> anyhow::Result<cidr::IpCidr> {
+    if !s.contains('/') {
+        let addr = IpAddr::from_str(s).with_context(|| format!("invalid IP address {s:?}"))?;
+        return Ok(cidr::IpCidr::new_host(addr));
+    }
+    cidr::IpCidr::from_str(s).with_context(|| format!("invalid CIDR range {s:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(hosts: &[&str], cidrs: &[&str]) -> Policy {
+        let cfg = pb::EgressPolicy {
+            hosts: hosts.iter().map(|s| s.to_string()).collect(),
+            cidrs: cidrs.iter().map(|s| s.to_string()).collect(),
+        };
+        Policy::new([&cfg]).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_allows_host() {
+        let p = policy(&["api.stripe.com", "*.example.com"], &[]);
+        assert!(p.allows_host("api.stripe.com"));
+        assert!(p.allows_host("API.Stripe.com."));
+        assert!(!p.allows_host("stripe.com"));
+        assert!(!p.allows_host("evil-api.stripe.com"));
+        assert!(p.allows_host("a.example.com"));
+        assert!(p.allows_host("a.b.example.com"));
+        assert!(!p.allows_host("example.com"));
+        assert!(!p.allows_host("badexample.com"));
+    }
+
+    #[test]
+    fn test_allows_addr() {
+        let p = policy(&[], &["10.0.0.0/8", "192.168.1.5", "fd00::/8"]);
+        assert!(p.allows_addr("10.1.2.3".parse().unwrap()));
+        assert!(!p.allows_addr("11.0.0.1".parse().unwrap()));
+        assert!(p.allows_addr("192.168.1.5".parse().unwrap()));
+        assert!(!p.allows_addr("192.168.1.6".parse().unwrap()));
+        assert!(p.allows_addr("fd12::1".parse().unwrap()));
+        assert!(p.allows_addr("::ffff:10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(Policy::new([]).unwrap().is_none());
+
+        let a = pb::EgressPolicy {
+            hosts: vec!["a.com".into()],
+            cidrs: vec![],
+        };
+        let b = pb::EgressPolicy {
+            hosts: vec![],
+            cidrs: vec!["10.0.0.0/8".into()],
+        };
+        let p = Policy::new([&a, &b]).unwrap().unwrap();
+        assert!(p.allows_host("a.com"));
+        assert!(p.allows_addr("10.0.0.1".parse().unwrap()));
+
+        let invalid = pb::EgressPolicy {
+            hosts: vec![],
+            cidrs: vec!["10.0.0.0/33".into()],
+        };
+        assert!(Policy::new([&invalid]).is_err());
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(
+            split_host_port("db.internal:5433", 5432),
+            ("db.internal".to_string(), 5433)
+        );
+        assert_eq!(
+            split_host_port("db.internal", 5432),
+            ("db.internal".to_string(), 5432)
+        );
+        assert_eq!(
+            split_host_port("[fd00::1]:6380", 6379),
+            ("fd00::1".to_string(), 6380)
+        );
+        assert_eq!(
+            split_host_port("fd00::1", 6379),
+            ("fd00::1".to_string(), 6379)
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt::Display;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::encore::runtime::v1 as pb;
+use crate::infracfg;
+
+/// The environment variable to configure fault injection with,
+/// using the same JSON format as `fault_injection` in the infra config.
+/// It takes precedence over the runtime config.
+const ENV_VAR: &str = "ENCORE_FAULT_INJECTION";
+
+static INJECTOR: OnceLock<Injector> = OnceLock::new();
+
+/// The kinds of infrastructure operations faults can be injected into.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    Sql,
+    Redis,
+    Bucket,
+    PubSub,
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Sql => write!(f, "sql"),
+            Target::Redis => write!(f, "redis"),
+            Target::Bucket => write!(f, "bucket"),
+            Target::PubSub => write!(f, "pubsub"),
+        }
+    }
+}
+
+/// An error injected into an operation.
+#[derive(Debug, Copy, Clone)]
+pub struct Fault {
+    pub target: Target,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "injected fault: {} operation failed", self.target)
+    }
+}
+
+impl std::error::Error for Fault {}
+
+#[derive(Debug, Clone, Default)]
+struct Rule {
+    error_rate: f64,
+    latency_rate: f64,
+    latency: Duration,
+}
+
+impl Rule {
+    fn new(cfg: &pb::FaultRule) -> Option<Self> {
+        let rule = Self {
+            error_rate: cfg.error_rate.clamp(0.0, 1.0),
+            latency_rate: cfg.latency_rate.clamp(0.0, 1.0),
+            latency: cfg
+                .latency
+                .and_then(|d| Duration::try_from(d).ok())
+                .unwrap_or_default(),
+        };
+        let active = rule.error_rate > 0.0 || (rule.latency_rate > 0.0 && !rule.latency.is_zero());
+        active.then_some(rule)
+    }
+}
+
+/// Injects latency and errors into infrastructure operations,
+/// for testing how the app copes with failing dependencies.
+#[derive(Debug, Default)]
+pub struct Injector {
+    sql: Option<Rule>,
+    redis: Option<Rule>,
+    bucket: Option<Rule>,
+    pubsub: Option<Rule>,
+}
+
+impl Injector {
+    pub fn new(cfg: &pb::FaultInjection) -> Self {
+        let rule = |r: &Option<pb::FaultRule>| r.as_ref().and_then(Rule::new);
+        Self {
+            sql: rule(&cfg.sql),
+            redis: rule(&cfg.redis),
+            bucket: rule(&cfg.buckets),
+            pubsub: rule(&cfg.pubsub),
+        }
+    }
+
+    fn rule(&self, target: Target) -> Option<&Rule> {
+        match target {
+            Target::Sql => self.sql.as_ref(),
+            Target::Redis => self.redis.as_ref(),
+            Target::Bucket => self.bucket.as_ref(),
+            Target::PubSub => self.pubsub.as_ref(),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        [Target::Sql, Target::Redis, Target::Bucket, Target::PubSub]
+            .into_iter()
+            .any(|t| self.rule(t).is_some())
+    }
+
+    /// Delays and fails the operation according to the target's rule.
+    pub async fn inject(&self, target: Target) -> Result<(), Fault> {
+        let Some(rule) = self.rule(target) else {
+            return Ok(());
+        };
+        if rule.latency_rate > 0.0 && rand::random::<f64>() < rule.latency_rate {
+            tokio::time::sleep(rule.latency).await;
+        }
+        if rule.error_rate > 0.0 && rand::random::<f64>() < rule.error_rate {
+            return Err(Fault { target });
+        }
+        Ok(())
+    }
+}
+
+/// Enables fault injection if configured, by the environment or the runtime config.
+pub fn init(cfg: Option<&pb::FaultInjection>) {
+    let from_env = match std::env::var(ENV_VAR) {
+        Ok(json) if !json.is_empty() => {
+            match serde_json::from_str::<infracfg::FaultInjection>(&json) {
+                Ok(cfg) => Some(infracfg::map_fault_injection(&cfg)),
+                Err(err) => {
+                    log::error!("invalid {ENV_VAR}, ignoring: {err}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let Some(cfg) = from_env.as_ref().or(cfg) else {
+        return;
+    };
+
+    let injector = Injector::new(cfg);
+    if injector.is_active() {
+        log::warn!("fault injection is enabled: {injector:?}");
+        let _ = INJECTOR.set(injector);
+    }
+}
+
+/// Delays and fails the operation if fault injection is enabled for the target.
+pub async fn inject(target: Target) -> Result<(), Fault> {
+    match INJECTOR.get() {
+        Some(injector) => injector.inject(target).await,
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::Instant;
+
+    fn rule(error_rate: f64, latency_rate: f64, latency_ms: i32) -> Option<pb::FaultRule> {
+        Some(pb::FaultRule {
+            error_rate,
+            latency_rate,
+            latency: Some(prost_types::Duration {
+                seconds: 0,
+                nanos: latency_ms * 1_000_000,
+            }),
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn inject() {
+        let injector = Injector::new(&pb::FaultInjection {
+            sql: rule(1.0, 0.0, 0),
+            redis: rule(0.0, 1.0, 200),
+            buckets: rule(0.0, 0.0, 0),
+            ..Default::default()
+        });
+        assert!(injector.is_active());
+
+        let err = injector.inject(Target::Sql).await.unwrap_err();
+        assert_eq!(err.to_string(), "injected fault: sql operation failed");
+
+        let start = Instant::now();
+        assert!(injector.inject(Target::Redis).await.is_ok());
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+
+        // Rules that never inject anything are ignored.
+        assert!(injector.bucket.is_none());
+        assert!(injector.inject(Target::PubSub).await.is_ok());
+    }
+
+    #[test]
+    fn inactive() {
+        assert!(!Injector::new(&Default::default()).is_active());
+        assert!(!Injector::new(&pb::FaultInjection {
+            pubsub: rule(0.0, 1.0, 0),
+            ..Default::default()
+        })
+        .is_active());
+    }
+}
@@ -0,0 +1,240 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Context;
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sigv4::http_request::{
+    sign, SignableBody, SignableRequest, SignatureLocation, SigningSettings,
+};
+use aws_sigv4::sign::v4;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::encore::runtime::v1 as pb;
+
+/// How long generated AWS tokens are valid for. This is the maximum allowed.
+const AWS_TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// How long a token is used before a new one is generated,
+/// leaving a margin before it expires.
+const TOKEN_REUSE: Duration = Duration::from_secs(10 * 60);
+
+/// How long connections authenticated with a token can be used before they're replaced.
+/// Connections that reconnect by themselves do so with the token they were created
+/// with, which must still be valid: a token is reused for up to `TOKEN_REUSE`,
+/// so together this doesn't exceed `AWS_TOKEN_LIFETIME`.
+pub const MAX_CONN_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// How often tokens are refreshed in the background.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The OAuth2 scope for Cloud SQL IAM database authentication.
+const GCP_SQL_LOGIN_SCOPE: &str = "https://www.googleapis.com/auth/sqlservice.login";
+
+/// Generates short-lived authentication tokens from the runtime's cloud credentials,
+/// to use in place of a static password.
+pub struct IamToken {
+    provider: Provider,
+    aws_config: OnceCell<aws_config::SdkConfig>,
+    current: Mutex<Option<(String, Instant)>>,
+    /// The most recent token, for callers that can't wait for a new one.
+    cached: std::sync::RwLock<Option<String>>,
+}
+
+impl std::fmt::Debug for IamToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't include the tokens.
+        f.debug_struct("IamToken")
+            .field("provider", &self.provider)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+enum Provider {
+    AwsRds {
+        region: Option<String>,
+        endpoint: String,
+        username: String,
+    },
+    AwsElastiCache {
+        region: Option<String>,
+        cache_name: String,
+        username: String,
+        serverless: bool,
+    },
+    GcpCloudSql,
+}
+
+impl IamToken {
+    /// Creates a token generator for connecting to the given endpoint, a host and port,
+    /// as the given user.
+    pub fn new(cfg: &pb::IamAuth, endpoint: &str, username: &str) -> anyhow::Result<Self> {
+        use pb::iam_auth::Provider as P;
+        let provider = match cfg.provider.as_ref().context("missing IAM auth provider")? {
+            P::AwsRds(rds) => Provider::AwsRds {
+                region: rds.region.clone(),
+                endpoint: endpoint.to_string(),
+                username: username.to_string(),
+            },
+            P::AwsElasticache(ec) => Provider::AwsElastiCache {
+                region: ec.region.clone(),
+                cache_name: ec.cache_name.to_lowercase(),
+                username: username.to_string(),
+                serverless: ec.serverless,
+            },
+            P::GcpCloudSql(_) => Provider::GcpCloudSql,
+        };
+        Ok(Self {
+            provider,
+            aws_config: OnceCell::new(),
+            current: Mutex::new(None),
+            cached: std::sync::RwLock::new(None),
+        })
+    }
+
+    /// Returns a valid token, generating a new one if needed.
+    pub async fn token(&self) -> anyhow::Result<String> {
+        let mut current = self.current.lock().await;
+        if let Some((token, generated_at)) = current.as_ref() {
+            if generated_at.elapsed() < TOKEN_REUSE {
+                return Ok(token.clone());
+            }
+        }
+
+        let token = self.generate().await?;
+        *current = Some((token.clone(), Instant::now()));
+        *self.cached.write().unwrap() = Some(token.clone());
+        Ok(token)
+    }
+
+    /// Returns the most recently generated token, if any.
+    pub fn cached(&self) -> Option<String> {
+        self.cached.read().unwrap().clone()
+    }
+
+    /// Keeps the cached token fresh, for connections made without waiting for a token.
+    pub async fn refresh(self: std::sync::Arc<Self>) {
+        loop {
+            if let Err(err) = self.token().await {
+                log::error!("unable to generate IAM authentication token: {:#}", err);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    }
+
+    async fn generate(&self) -> anyhow::Result<String> {
+        match &self.provider {
+            Provider::AwsRds {
+                region,
+                endpoint,
+                username,
+            } => {
+                let url = url::Url::parse_with_params(
+                    &format!("https://{endpoint}/"),
+                    [("Action", "connect"), ("DBUser", username)],
+                )
+                .context("invalid database endpoint")?;
+                self.aws_presign(region.as_deref(), "rds-db", url).await
+            }
+            Provider::AwsElastiCache {
+                region,
+                cache_name,
+                username,
+                serverless,
+            } => {
+                let mut url = url::Url::parse_with_params(
+                    &format!("https://{cache_name}/"),
+                    [("Action", "connect"), ("User", username)],
+                )
+                .context("invalid cache name")?;
+                if *serverless {
+                    url.query_pairs_mut()
+                        .append_pair("ResourceType", "ServerlessCache");
+                }
+                self.aws_presign(region.as_deref(), "elasticache", url)
+                    .await
+            }
+            Provider::GcpCloudSql => gcp_access_token().await,
+        }
+    }
+
+    /// Presigns the URL with the runtime's AWS credentials, and returns it
+    /// without the scheme, which is the format AWS expects tokens in.
+    async fn aws_presign(
+        &self,
+        region: Option<&str>,
+        service: &str,
+        mut url: url::Url,
+    ) -> anyhow::Result<String> {
+        let sdk = self
+            .aws_config
+            .get_or_init(|| async move {
+                let mut builder = aws_config::defaults(aws_config::BehaviorVersion::v2025_08_07());
+                if let Some(region) = region {
+                    builder = builder.region(aws_config::Region::new(region.to_string()));
+                }
+                builder.load().await
+            })
+            .await;
+        let region = sdk
+            .region()
+            .context("no AWS region configured for IAM authentication")?
+            .to_string();
+        let creds = sdk
+            .credentials_provider()
+            .context("no AWS credentials configured")?
+            .provide_credentials()
+            .await
+            .context("unable to load AWS credentials")?;
+        let identity = creds.into();
+
+        let mut settings = SigningSettings::default();
+        settings.signature_location = SignatureLocation::QueryParams;
+        settings.expires_in = Some(AWS_TOKEN_LIFETIME);
+        let params = v4::SigningParams::builder()
+            .identity(&identity)
+            .region(&region)
+            .name(service)
+            .time(SystemTime::now())
+            .settings(settings)
+            .build()
+            .context("invalid signing parameters")?
+            .into();
+
+        let request = SignableRequest::new(
+            "GET",
+            url.as_str(),
+            std::iter::empty(),
+            SignableBody::Bytes(&[]),
+        )
+        .context("invalid signing request")?;
+        let (instructions, _) = sign(request, &params)
+            .context("unable to sign token")?
+            .into_parts();
+        {
+            let mut query = url.query_pairs_mut();
+            for (name, value) in instructions.params() {
+                query.append_pair(name, value);
+            }
+        }
+
+        let token = url.as_str();
+        Ok(token.strip_prefix("https://").unwrap_or(token).to_string())
+    }
+}
+
+/// Returns an OAuth2 access token for the runtime's Google credentials.
+async fn gcp_access_token() -> anyhow::Result<String> {
+    use google_cloud_token::TokenSourceProvider;
+    let scopes = [GCP_SQL_LOGIN_SCOPE];
+    let config = google_cloud_auth::project::Config::default().with_scopes(&scopes);
+    let provider = google_cloud_auth::token::DefaultTokenSourceProvider::new(config)
+        .await
+        .context("unable to load Google credentials")?;
+    let token = provider
+        .token_source()
+        .token()
+        .await
+        .map_err(|err| anyhow::anyhow!(err))
+        .context("unable to get Google access token")?;
+    Ok(token.strip_prefix("Bearer ").unwrap_or(&token).to_string())
+}
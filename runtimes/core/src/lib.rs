@@ -4,7 +4,7 @@ use std::fmt::Display;
 use std::hash::Hash;
 use std::io::Read;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -18,11 +18,19 @@ pub use names::{CloudName, EncoreName, EndpointName};
 use crate::encore::parser::meta::v1 as metapb;
 use crate::encore::runtime::v1 as runtimepb;
 
+mod admin;
 pub mod api;
+mod azure;
 mod base32;
+pub mod cache;
+pub mod crypto;
+mod egress;
 pub mod error;
+pub mod faults;
+mod iamauth;
 pub mod infracfg;
 pub mod log;
+pub mod mesh;
 pub mod meta;
 pub mod metadata;
 pub mod metrics;
@@ -31,9 +39,14 @@ mod names;
 pub mod objects;
 pub mod proccfg;
 pub mod pubsub;
+mod reload;
+pub mod replay;
 pub mod runtime_config;
 pub mod secrets;
+pub mod shutdown;
 pub mod sqldb;
+pub mod startup;
+mod telemetry;
 mod trace;
 
 pub mod encore {
@@ -65,6 +78,9 @@ pub struct RuntimeBuilder {
     err: Option<anyhow::Error>,
     test_mode: bool,
     is_worker: bool,
+    gateway_mode: bool,
+    /// The infra config to reload on changes, if watching is enabled.
+    watch_path: Option<PathBuf>,
 }
 
 impl Default for RuntimeBuilder {
@@ -82,6 +98,8 @@ impl RuntimeBuilder {
             err: None,
             test_mode: false,
             is_worker: false,
+            gateway_mode: false,
+            watch_path: None,
         }
     }
 
@@ -98,6 +116,14 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Runs only the gateways, proxying all requests to the services
+    /// found through service discovery, so gateways can be scaled
+    /// independently of the service processes.
+    pub fn with_gateway_mode(mut self, enabled: bool) -> Self {
+        self.gateway_mode = enabled;
+        self
+    }
+
     pub fn with_runtime_config(mut self, cfg: runtimepb::RuntimeConfig) -> Self {
         self.cfg = Some(cfg);
         self
@@ -112,7 +138,14 @@ impl RuntimeBuilder {
         if self.err.is_none() {
             match infra_config_from_env() {
                 Ok(opt_cfg) => match opt_cfg {
-                    Some(cfg) => self.cfg = Some(cfg),
+                    Some((path, cfg)) => {
+                        self.cfg = Some(cfg);
+                        let watch = std::env::var("ENCORE_INFRA_CONFIG_WATCH")
+                            .is_ok_and(|v| !v.is_empty() && v != "0" && v != "false");
+                        if watch {
+                            self.watch_path = Some(path);
+                        }
+                    }
                     None => match runtime_config_from_env() {
                         Ok(cfg) => self.cfg = Some(cfg),
                         Err(e) => {
@@ -195,10 +228,17 @@ impl RuntimeBuilder {
         }
         let mut cfg = self.cfg.context("runtime config not provided")?;
         let md = self.md.context("metadata not provided")?;
-        if let Some(proc_config) = self.proc_cfg {
-            proc_config.apply(&mut cfg)?;
+        prepare_config(&mut cfg, self.proc_cfg.as_ref(), self.gateway_mode)?;
+
+        let watcher = self
+            .watch_path
+            .filter(|_| !self.test_mode)
+            .map(|path| reload::Watcher::new(path, cfg.clone(), self.proc_cfg, self.gateway_mode));
+        let runtime = Runtime::new(cfg, md, self.test_mode)?;
+        if let Some(watcher) = watcher {
+            watcher.start(&runtime);
         }
-        Runtime::new(cfg, md, self.test_mode)
+        Ok(runtime)
     }
 }
 
@@ -207,13 +247,19 @@ pub struct Runtime {
     pubsub: pubsub::Manager,
     secrets: secrets::Manager,
     sqldb: sqldb::Manager,
+    cache: cache::Manager,
     objects: objects::Manager,
+    crypto: crypto::Manager,
     api: api::Manager,
     app_meta: meta::AppMeta,
     compute: ComputeConfig,
     runtime: tokio::runtime::Runtime,
     metrics: metrics::Manager,
     runtime_config: runtime_config::RuntimeConfig,
+    graceful_shutdown: Option<runtimepb::GracefulShutdown>,
+    trace_flushers: Vec<trace::Flusher>,
+    shutdown_requested: tokio_util::sync::CancellationToken,
+    testing: bool,
 }
 
 impl Runtime {
@@ -242,15 +288,95 @@ impl Runtime {
         let creds = infra.credentials.take().unwrap_or_default();
         let encore_platform = cfg.encore_platform.take().unwrap_or_default();
 
+        let graceful_shutdown = cfg.graceful_shutdown.take();
+
         let mut deployment = cfg.deployment.take().unwrap_or_default();
+
+        // Configure the log output before anything else is logged.
+        // Logs not written while handling a request use the default output,
+        // or the hosted service's output if there's only one.
+        for svc in &deployment.hosted_services {
+            let Some(log_output) = &svc.log_output else {
+                continue;
+            };
+            let result = match deployment.hosted_services.len() {
+                1 => log::configure(log_output),
+                _ => log::configure_service(&svc.name, log_output),
+            };
+            if let Err(err) = result {
+                ::log::warn!(
+                    "invalid log output configuration for service {}, using defaults: {err:#}",
+                    svc.name
+                );
+            }
+        }
+        api::configure_responses(&deployment.error_responses.take().unwrap_or_default());
+        let startup_gate = deployment.startup_gate.take();
+        let sql_test_isolation = testing
+            && deployment
+                .sql_test_isolation
+                .as_ref()
+                .is_some_and(|c| c.rollback);
+        faults::init(deployment.fault_injection.as_ref());
+        replay::init(deployment.record_replay.as_ref())
+            .context("failed to set up record/replay")?;
+        // Enable the egress policy before creating any HTTP clients.
+        let egress_policy = egress::Policy::new(
+            deployment
+                .hosted_services
+                .iter()
+                .filter_map(|svc| svc.egress_policy.as_ref()),
+        )
+        .context("invalid egress policy")?;
+        if let Some(policy) = egress_policy {
+            for (host, port) in egress::infra_endpoints(&resources) {
+                policy
+                    .check_endpoint(&host, port)
+                    .context("infrastructure endpoint not allowed")?;
+            }
+            egress::init(policy);
+        }
+
         let service_discovery = deployment.service_discovery.take().unwrap_or_default();
+        let mesh = deployment
+            .service_mesh
+            .take()
+            .map(|cfg| mesh::Manager::new(&cfg, http_client_builder, tokio_rt.handle()))
+            .transpose()
+            .context("unable to set up service mesh")?
+            .map(Arc::new);
         let observability = deployment.observability.take().unwrap_or_default();
 
-        let http_client = reqwest::Client::builder()
+        let http_client = http_client_builder()
             .build()
             .context("failed to build http client")?;
 
-        let secrets = secrets::Manager::new(resources.app_secrets);
+        let vault = resources
+            .vault
+            .map(|cfg| secrets::Vault::new(cfg, http_client.clone()))
+            .transpose()
+            .context("invalid vault configuration")?
+            .map(Arc::new);
+        let secrets = secrets::Manager::new(
+            resources.app_secrets,
+            &deployment.hosted_services,
+            vault,
+            Some(Arc::new(secrets::AwsSecrets::new(
+                tokio_rt.handle().clone(),
+            ))),
+        );
+        secrets.load_external(tokio_rt.handle());
+        for status in secrets.report() {
+            match &status.error {
+                Some(err) => log::error!(
+                    "secret {} ({}): unable to resolve: {}",
+                    status.name,
+                    status.source,
+                    err
+                ),
+                None => log::debug!("secret {} ({}): resolved", status.name, status.source),
+            }
+        }
         let platform_validator = platform::RequestValidator::new(
             &secrets,
             encore_platform.platform_signing_keys.clone(),
@@ -267,28 +393,44 @@ impl Runtime {
         );
 
         // Set up observability.
+        if let Err(err) = log::configure_sinks(
+            &observability.logs,
+            &secrets,
+            &http_client,
+            tokio_rt.handle(),
+        ) {
+            log::error!("unable to configure log sinks: {:?}", err);
+        }
+        let propagation = observability
+            .trace_propagation
+            .as_ref()
+            .map(api::reqauth::Propagation::from)
+            .unwrap_or_default();
         let disable_tracing =
             testing || std::env::var("ENCORE_NOTRACE").is_ok_and(|v| !v.is_empty());
+        let mut trace_flushers = Vec::new();
         let tracer = if !disable_tracing {
-            let trace_endpoint = observability
+            let trace_provider = observability
                 .tracing
-                .into_iter()
-                .find_map(|p| match p.provider {
-                    Some(runtimepb::tracing_provider::Provider::Encore(encore)) => {
-                        Some(encore.trace_endpoint)
-                    }
+                .iter()
+                .find_map(|p| match &p.provider {
+                    Some(runtimepb::tracing_provider::Provider::Encore(encore)) => Some(encore),
                     _ => None,
                 })
-                .and_then(|ep| match reqwest::Url::parse(&ep) {
-                    Ok(ep) => Some(ep),
+                .and_then(|p| match reqwest::Url::parse(&p.trace_endpoint) {
+                    Ok(ep) => Some((ep, trace::Sampler::new(p))),
                     Err(err) => {
-                        ::log::warn!("disabling tracing: invalid trace endpoint {}: {}", ep, err);
+                        ::log::warn!(
+                            "disabling tracing: invalid trace endpoint {}: {}",
+                            p.trace_endpoint,
+                            err
+                        );
                         None
                     }
                 });
 
-            match trace_endpoint {
-                Some(trace_endpoint) => {
+            let tracer = match trace_provider {
+                Some((trace_endpoint, sampler)) => {
                     let config = trace::ReporterConfig {
                         app_id: environment.app_id.clone(),
                         env_id: environment.env_id.clone(),
@@ -298,17 +440,96 @@ impl Runtime {
                         platform_validator: platform_validator.clone(),
                     };
 
-                    let (tracer, reporter) = trace::streaming_tracer(http_client.clone(), config);
+                    let (tracer, reporter) =
+                        trace::streaming_tracer(http_client.clone(), config, sampler);
+                    trace_flushers.push(reporter.flusher());
                     tokio_rt.spawn(reporter.start_reporting());
                     tracer
                 }
                 None => trace::Tracer::noop(),
+            };
+
+            let otlp_provider = observability
+                .tracing
+                .iter()
+                .find_map(|p| match &p.provider {
+                    Some(runtimepb::tracing_provider::Provider::Otlp(otlp)) => Some(otlp),
+                    _ => None,
+                });
+            let tracer = match otlp_provider {
+                Some(otlp) => match trace::otlp::otlp_exporter(
+                    http_client.clone(),
+                    otlp,
+                    &secrets,
+                    &environment,
+                    &md.app_revision,
+                ) {
+                    Ok((exporter, reporter)) => {
+                        trace_flushers.push(reporter.flusher());
+                        tokio_rt.spawn(reporter.start_reporting());
+                        tracer.with_otlp(exporter)
+                    }
+                    Err(err) => {
+                        ::log::warn!("disabling OTLP tracing to {}: {:#}", otlp.endpoint, err);
+                        tracer
+                    }
+                },
+                None => tracer,
+            };
+
+            let local_provider = observability
+                .tracing
+                .iter()
+                .find_map(|p| match &p.provider {
+                    Some(runtimepb::tracing_provider::Provider::Local(local)) => Some(local),
+                    _ => None,
+                });
+            match local_provider {
+                Some(local) => match trace::local_sink(local) {
+                    Ok((sink, reporter)) => {
+                        trace_flushers.push(reporter.flusher());
+                        tokio_rt.spawn(reporter.start_reporting());
+                        tracer.with_sink(sink)
+                    }
+                    Err(err) => {
+                        ::log::warn!("disabling local trace storage at {}: {:#}", local.path, err);
+                        tracer
+                    }
+                },
+                None => tracer,
             }
         } else {
             trace::Tracer::noop()
         };
 
+        let telemetry = observability.telemetry_stream.as_ref().and_then(|cfg| {
+            match telemetry::Server::new(cfg, &secrets) {
+                Ok(server) => Some(server),
+                Err(err) => {
+                    ::log::error!("disabling telemetry stream: {:#}", err);
+                    None
+                }
+            }
+        });
+        let tracer = match &telemetry {
+            Some(server) if !disable_tracing => tracer.with_stream(server.trace_stream()),
+            _ => tracer,
+        };
+
         log::set_tracer(tracer.clone());
+        if let Some(server) = telemetry {
+            server.start(tokio_rt.handle());
+        }
+
+        // Describe the deployment for the admin API before the resources are handed out.
+        let admin = deployment.admin_api.take().map(|cfg| {
+            let desc = admin::Description::new(&md, &deployment, &resources, &service_discovery);
+            (
+                cfg,
+                desc,
+                pubsub::startup_dependencies(&resources.pubsub_clusters),
+            )
+        });
 
         // Find push subscriptions which should be proxied to the subscribing service by the gateway
         let proxied_push_subs = resources
@@ -345,18 +566,43 @@ impl Runtime {
             .collect::<Result<HashMap<_, _>, anyhow::Error>>()
             .context("failed to resolve gateway push subscriptions")?;
 
-        let pubsub = pubsub::Manager::new(tracer.clone(), resources.pubsub_clusters, &md)?;
-        let objects =
-            objects::Manager::new(&secrets, tracer.clone(), resources.bucket_clusters, &md);
+        let objects = objects::Manager::new(
+            &secrets,
+            tracer.clone(),
+            resources.bucket_clusters,
+            &md,
+            deployment.region.clone(),
+        );
+        let crypto = crypto::Manager::new(&secrets, resources.encryption_keys);
         let sqldb = sqldb::ManagerConfig {
             clusters: resources.sql_clusters,
             creds: &creds,
             secrets: &secrets,
             tracer: tracer.clone(),
+            query_metrics: observability
+                .sql_metrics
+                .as_ref()
+                .map(|cfg| Arc::new(sqldb::QueryMetrics::new(metrics_manager.registry(), cfg))),
             runtime: tokio_rt.handle().clone(),
+            test_isolation: sql_test_isolation,
         }
         .build()
         .context("unable to initialize sqldb proxy")?;
+        let pubsub_deps = pubsub::startup_dependencies(&resources.pubsub_clusters);
+        let pubsub = pubsub::Manager::new(
+            tracer.clone(),
+            resources.pubsub_clusters,
+            &md,
+            &secrets,
+            pubsub::QuarantineStores {
+                objects: &objects,
+                sqldb: &sqldb,
+                crypto: &crypto,
+                metrics: metrics_manager.registry(),
+            },
+        )?;
+        let cache = cache::Manager::new(&secrets, resources.redis_clusters, &creds)
+            .context("unable to initialize redis clusters")?;
 
         // Determine the compute configuration.
         let compute = {
@@ -397,9 +643,14 @@ impl Runtime {
             secrets: &secrets,
             service_discovery,
             http_client: http_client.clone(),
+            mesh,
             tracer,
+            propagation,
             platform_validator,
             pubsub_push_registry: pubsub.push_registry(),
+            pubsub: &pubsub,
+            sqldb: &sqldb,
+            cache: &cache,
             runtime: tokio_rt.handle().clone(),
             testing,
             proxied_push_subs,
@@ -415,6 +666,28 @@ impl Runtime {
             }
         });
 
+        if let Some((cfg, desc, pubsub_deps)) = admin.filter(|_| !testing) {
+            match admin::Server::new(&cfg, desc, &secrets) {
+                Ok(server) => {
+                    let mut deps = sqldb.startup_dependencies();
+                    deps.extend(cache.startup_dependencies());
+                    deps.extend(pubsub_deps);
+                    server.with_dependencies(deps).start(tokio_rt.handle());
+                }
+                Err(err) => ::log::error!("disabling admin API: {:#}", err),
+            }
+        }
+
+        // Wait for the infrastructure to become reachable before serving requests.
+        if let Some(gate) = startup_gate.filter(|_| !testing) {
+            let mut deps = sqldb.startup_dependencies();
+            deps.extend(cache.startup_dependencies());
+            deps.extend(pubsub_deps);
+            tokio_rt
+                .block_on(startup::Gate::new(&gate).wait(deps))
+                .context("startup dependencies unavailable")?;
+        }
+
         ::log::debug!("encore runtime successfully initialized");
 
         Ok(Self {
@@ -422,13 +695,19 @@ impl Runtime {
             pubsub,
             secrets,
             sqldb,
+            cache,
             objects,
+            crypto,
             api,
             app_meta,
             compute,
             runtime: tokio_rt,
             metrics: metrics_manager,
             runtime_config,
+            graceful_shutdown,
+            trace_flushers,
+            shutdown_requested: tokio_util::sync::CancellationToken::new(),
+            testing,
         })
     }
 
@@ -447,11 +726,21 @@ impl Runtime {
         &self.sqldb
     }
 
+    #[inline]
+    pub fn cache(&self) -> &cache::Manager {
+        &self.cache
+    }
+
     #[inline]
     pub fn objects(&self) -> &objects::Manager {
         &self.objects
     }
 
+    #[inline]
+    pub fn crypto(&self) -> &crypto::Manager {
+        &self.crypto
+    }
+
     #[inline]
     pub fn metadata(&self) -> &metapb::Data {
         &self.md
@@ -477,15 +766,67 @@ impl Runtime {
         self.runtime.handle()
     }
 
-    #[inline]
-    pub fn run_blocking(&self) {
-        self.runtime.block_on(async move {
-            let api_handle = self.api().start_serving();
+    /// Serves requests until the process is asked to shut down,
+    /// and then shuts down gracefully.
+    ///
+    /// Returns an error if serving fails. In test mode this returns
+    /// as soon as serving completes.
+    pub fn run_blocking(&self) -> anyhow::Result<()> {
+        self.runtime.block_on(self.run(shutdown::signal()))
+    }
 
-            if let Err(err) = api_handle.await {
-                ::log::error!("failed to start serving: {:?}", err);
-            }
-        });
+    /// Like [`Runtime::run_blocking`], but leaves handling signals to the host
+    /// process, which shuts the runtime down with [`Runtime::request_shutdown`].
+    pub fn run_blocking_hosted(&self) -> anyhow::Result<()> {
+        self.runtime.block_on(self.run(std::future::pending()))
+    }
+
+    /// Asks a running runtime to shut down gracefully.
+    pub fn request_shutdown(&self) {
+        self.shutdown_requested.cancel();
+    }
+
+    async fn run(&self, signal: impl std::future::Future<Output = ()>) -> anyhow::Result<()> {
+        let mut api_handle = self.api().start_serving();
+
+        if self.testing {
+            return api_handle.await.context("api server panicked")?;
+        }
+
+        let served = tokio::select! {
+            served = &mut api_handle => Some(served),
+            _ = signal => None,
+            _ = self.shutdown_requested.cancelled() => None,
+        };
+        ::log::info!("shutting down");
+        self.shutdown().await;
+
+        // The server stops once traffic is drained, unless draining timed out.
+        let served = match served {
+            Some(served) => served,
+            None if api_handle.is_finished() => api_handle.await,
+            None => return Ok(()),
+        };
+        served
+            .context("api server panicked")?
+            .context("api server failed")
+    }
+
+    /// Shuts down the runtime's subsystems in dependency order,
+    /// bounding each stage by the configured graceful shutdown timeouts.
+    pub async fn shutdown(&self) {
+        let mut seq = shutdown::Sequence::new(self.graceful_shutdown.as_ref());
+        seq.run(shutdown::Stage::Traffic, self.api.shutdown()).await;
+        seq.run(shutdown::Stage::PubSub, self.pubsub.shutdown())
+            .await;
+        seq.run(shutdown::Stage::Telemetry, async {
+            let traces = futures::future::join_all(
+                self.trace_flushers.iter().map(|flusher| flusher.flush()),
+            );
+            futures::join!(traces, self.metrics.collect_and_export());
+        })
+        .await;
+        seq.run(shutdown::Stage::Pools, self.sqldb.shutdown()).await;
     }
 
     #[inline]
@@ -504,6 +845,47 @@ impl Runtime {
     }
 }
 
+/// Returns a builder for the runtime's HTTP clients. The service mesh builds
+/// its clients from it as well, so they differ only in their TLS identity.
+fn http_client_builder() -> reqwest::ClientBuilder {
+    egress::client_builder()
+}
+
+/// Applies the process config and gateway mode to the runtime config.
+fn prepare_config(
+    cfg: &mut runtimepb::RuntimeConfig,
+    proc_cfg: Option<&proccfg::ProcessConfig>,
+    gateway_mode: bool,
+) -> anyhow::Result<()> {
+    if let Some(proc_config) = proc_cfg {
+        proc_config.apply(cfg)?;
+    }
+    if gateway_mode {
+        apply_gateway_mode(cfg)?;
+    }
+    Ok(())
+}
+
+/// Configures the runtime to host only gateways. If the config doesn't
+/// say which gateways to host, all of the configured gateways are hosted.
+fn apply_gateway_mode(cfg: &mut runtimepb::RuntimeConfig) -> anyhow::Result<()> {
+    let deployment = cfg.deployment.get_or_insert_with(Default::default);
+    deployment.hosted_services.clear();
+
+    if deployment.hosted_gateways.is_empty() {
+        deployment.hosted_gateways = cfg
+            .infra
+            .as_ref()
+            .and_then(|infra| infra.resources.as_ref())
+            .map(|res| res.gateways.iter().map(|gw| gw.rid.clone()).collect())
+            .unwrap_or_default();
+    }
+    if deployment.hosted_gateways.is_empty() {
+        anyhow::bail!("gateway mode requires at least one gateway to be configured");
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ComputeConfig {
     pub log_level: Option<String>,
@@ -517,6 +899,7 @@ enum ParseError {
     Base64(base64::DecodeError),
     Proto(prost::DecodeError),
     IO(std::io::Error),
+    InfraConfig(anyhow::Error),
 }
 
 impl Display for ParseError {
@@ -527,23 +910,30 @@ impl Display for ParseError {
             ParseError::Base64(e) => write!(f, "failed to decode environment variable: {e}"),
             ParseError::Proto(e) => write!(f, "failed to parse environment variable: {e}"),
             ParseError::IO(e) => write!(f, "failed to read file: {e}"),
+            ParseError::InfraConfig(e) => write!(f, "invalid infra config: {e:#}"),
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
-fn infra_config_from_env() -> Result<Option<runtimepb::RuntimeConfig>, ParseError> {
+fn infra_config_from_env() -> Result<Option<(PathBuf, runtimepb::RuntimeConfig)>, ParseError> {
     let cfg_path = match std::env::var("ENCORE_INFRA_CONFIG_PATH") {
-        Ok(cfg) => cfg,
+        Ok(cfg) => PathBuf::from(cfg),
         Err(std::env::VarError::NotPresent) => return Ok(None),
         Err(e) => return Err(ParseError::EnvVar(e)),
     };
-    let file_content = std::fs::read_to_string(cfg_path).map_err(ParseError::IO)?;
-    let infra_config: infracfg::InfraConfig = serde_json::from_str(&file_content)
-        .map_err(|e| ParseError::IO(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
-    let runtime_config = infracfg::map_infra_to_runtime(infra_config);
-    Ok(Some(runtime_config))
+    let cfg = load_infra_config(&cfg_path)?;
+    Ok(Some((cfg_path, cfg)))
+}
+
+/// Reads the infra config at the given path and maps it to a runtime config.
+fn load_infra_config(path: &Path) -> Result<runtimepb::RuntimeConfig, ParseError> {
+    let file_content = std::fs::read_to_string(path).map_err(ParseError::IO)?;
+    let format = infracfg::Format::detect(path, &file_content);
+    let infra_config = infracfg::parse(&file_content, format, &|name| std::env::var(name).ok())
+        .map_err(ParseError::InfraConfig)?;
+    Ok(infracfg::map_infra_to_runtime(infra_config))
 }
 
 fn runtime_config_from_env() -> Result<runtimepb::RuntimeConfig, ParseError> {
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 /// Fields control the names of the fields that are used in the log output.
 #[derive(Debug)]
 pub struct FieldConfig {
@@ -74,4 +76,32 @@ impl FieldConfig {
         }
         &DEFAULT_FIELDS
     }
+
+    /// Returns a copy of the config with fields renamed, keyed by their
+    /// name in [`DEFAULT_FIELDS`]. The copy is leaked, as it's only
+    /// created once when the logger is configured.
+    pub fn renamed(
+        &'static self,
+        names: &HashMap<String, String>,
+    ) -> anyhow::Result<&'static Self> {
+        if names.is_empty() {
+            return Ok(self);
+        }
+
+        let mut cfg = FieldConfig { ..*self };
+        for (field, name) in names {
+            let name: &'static str = Box::leak(name.clone().into_boxed_str());
+            let slot = match field.as_str() {
+                "time" => &mut cfg.timestamp_field_name,
+                "level" => &mut cfg.level_field_name,
+                "message" => &mut cfg.message_field_name,
+                "error" => &mut cfg.error_field_name,
+                "caller" => &mut cfg.caller_field_name,
+                "stack" => &mut cfg.stack_trace_field_name,
+                _ => anyhow::bail!("unknown log field {field:?}"),
+            };
+            *slot = name;
+        }
+        Ok(Box::leak(Box::new(cfg)))
+    }
 }
@@ -0,0 +1,121 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::encore::runtime::v1 as pb;
+
+/// The size at which log files are rotated, if not configured.
+const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The number of rotated log files to keep, if not configured.
+const DEFAULT_MAX_BACKUPS: u32 = 5;
+
+/// A log file that is rotated when it grows too large.
+///
+/// On rotation the file is renamed to "<path>.1", shifting
+/// older files up by one, and the oldest file is deleted.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_backups: u32,
+}
+
+impl RotatingFile {
+    pub fn open(cfg: &pb::logs_provider::File) -> anyhow::Result<Self> {
+        let path = PathBuf::from(&cfg.path);
+        let (file, size) =
+            open(&path).with_context(|| format!("unable to open log file {}", cfg.path))?;
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size: cfg.max_size_bytes.unwrap_or(DEFAULT_MAX_SIZE),
+            max_backups: cfg.max_backups.unwrap_or(DEFAULT_MAX_BACKUPS),
+        })
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.max_backups == 0 {
+            self.file.set_len(0)?;
+        } else {
+            for n in (1..self.max_backups).rev() {
+                let from = self.backup_path(n);
+                if from.exists() {
+                    std::fs::rename(&from, self.backup_path(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.backup_path(1))?;
+        }
+
+        let (file, size) = open(&self.path)?;
+        self.file = file;
+        self.size = size;
+        Ok(())
+    }
+
+    fn backup_path(&self, n: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+}
+
+fn open(path: &Path) -> std::io::Result<(File, u64)> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Rotate before the write, so log lines are never split across files.
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("encore-log-{}", xid::new()));
+        let path = dir.join("app.log");
+        let mut file = RotatingFile::open(&pb::logs_provider::File {
+            path: path.to_string_lossy().into_owned(),
+            max_size_bytes: Some(10),
+            max_backups: Some(2),
+        })
+        .unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        let read = |p: &Path| std::fs::read_to_string(p).unwrap();
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(&file.backup_path(1)), "third\n");
+        assert_eq!(read(&file.backup_path(2)), "second\n");
+        assert!(!file.backup_path(3).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
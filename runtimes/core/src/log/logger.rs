@@ -7,7 +7,8 @@ use crate::trace::Tracer;
 use anyhow::Context;
 use env_logger::filter::Filter;
 use log::{Log, Metadata, Record};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
 
@@ -17,13 +18,71 @@ pub type Fields = BTreeMap<String, serde_json::Value>;
 #[derive(Debug, Clone)]
 pub struct Logger {
     filter: Arc<Filter>,
-    app_level: log::LevelFilter,
-    field_config: &'static FieldConfig,
-    writer: Arc<dyn Writer>,
+    app_level: Arc<AppLevel>,
+    output: Arc<RwLock<Output>>,
+    /// How the logs of specific services are written, keyed by service name.
+    /// Logs of other services, and logs not written during a request, use `output`.
+    service_outputs: Arc<RwLock<HashMap<String, Output>>>,
     extra_fields: Fields,
     tracer: Arc<RwLock<Tracer>>,
 }
 
+/// The app log level, which can be changed while the logger is in use.
+#[derive(Debug)]
+struct AppLevel(AtomicUsize);
+
+impl AppLevel {
+    fn new(level: log::LevelFilter) -> Self {
+        Self(AtomicUsize::new(level as usize))
+    }
+
+    fn get(&self) -> log::LevelFilter {
+        log::LevelFilter::iter()
+            .nth(self.0.load(Ordering::Relaxed))
+            .unwrap_or(log::LevelFilter::Trace)
+    }
+
+    fn set(&self, level: log::LevelFilter) {
+        self.0.store(level as usize, Ordering::Relaxed);
+    }
+}
+
+/// Output controls how logs are written.
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub field_config: &'static FieldConfig,
+    pub writer: Arc<dyn Writer>,
+    pub timestamp_format: TimestampFormat,
+}
+
+/// How timestamps are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// RFC 3339 with millisecond precision.
+    #[default]
+    Rfc3339,
+    /// RFC 3339 with nanosecond precision.
+    Rfc3339Nano,
+    /// Seconds since the Unix epoch.
+    Unix,
+    /// Milliseconds since the Unix epoch.
+    UnixMs,
+}
+
+impl TimestampFormat {
+    fn now(&self) -> serde_json::Value {
+        let now = chrono::DateTime::<chrono::Utc>::from(SystemTime::now());
+        match self {
+            Self::Rfc3339 => iso8601_now(),
+            Self::Rfc3339Nano => {
+                serde_json::Value::from(now.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true))
+            }
+            Self::Unix => serde_json::Value::from(now.timestamp_micros() as f64 / 1e6),
+            Self::UnixMs => serde_json::Value::from(now.timestamp_millis()),
+        }
+    }
+}
+
 impl Logger {
     /// New returns a new logger with the given field config.
     pub fn new(
@@ -33,9 +92,13 @@ impl Logger {
     ) -> Self {
         Self {
             filter: Arc::new(filter),
-            app_level,
-            field_config,
-            writer: default_writer(field_config),
+            app_level: Arc::new(AppLevel::new(app_level)),
+            output: Arc::new(RwLock::new(Output {
+                field_config,
+                writer: default_writer(field_config),
+                timestamp_format: TimestampFormat::default(),
+            })),
+            service_outputs: Arc::new(RwLock::new(HashMap::new())),
             extra_fields: Fields::new(),
             tracer: Arc::new(RwLock::new(Tracer::noop())),
         }
@@ -47,22 +110,78 @@ impl Logger {
         *t = tracer;
     }
 
+    /// Sets how the logger, and the loggers derived from it, write logs.
+    pub fn set_output(&self, output: Output) {
+        let mut o = self.output.write().expect("output lock poisoned");
+        *o = output;
+    }
+
+    /// Sets how the logger, and the loggers derived from it, write the logs
+    /// of the given service.
+    pub fn set_service_output(&self, service: &str, output: Output) {
+        let mut outputs = self.service_outputs.write().expect("output lock poisoned");
+        outputs.insert(service.to_string(), output);
+    }
+
+    /// Sets where the logger, and the loggers derived from it, write logs,
+    /// keeping the configured fields and timestamp formats.
+    pub fn set_writer(&self, writer: Arc<dyn Writer>) {
+        self.map_writers(|_| writer.clone());
+    }
+
+    /// Replaces the writers of the logger, and of the loggers derived from it,
+    /// with the result of calling `f` with each of them.
+    pub(super) fn map_writers(&self, f: impl Fn(Arc<dyn Writer>) -> Arc<dyn Writer>) {
+        let mut o = self.output.write().expect("output lock poisoned");
+        o.writer = f(o.writer.clone());
+        let mut outputs = self.service_outputs.write().expect("output lock poisoned");
+        for o in outputs.values_mut() {
+            o.writer = f(o.writer.clone());
+        }
+    }
+
     /// Returns a new logger with the given log level.
     pub fn with_level(&self, level: log::LevelFilter) -> Self {
         Self {
-            app_level: level,
+            app_level: Arc::new(AppLevel::new(level)),
             ..self.clone()
         }
     }
 
+    /// Sets the log level of the logger, and of the loggers derived
+    /// from it that don't have their own level.
+    pub fn set_level(&self, level: log::LevelFilter) {
+        self.app_level.set(level);
+    }
+
     /// Returns a new logger with the given writer.
     pub fn with_writer(&self, writer: Arc<dyn Writer>) -> Self {
-        Self {
+        let output = Output {
             writer,
+            ..self.output()
+        };
+        Self {
+            output: Arc::new(RwLock::new(output)),
+            service_outputs: Arc::new(RwLock::new(HashMap::new())),
             ..self.clone()
         }
     }
 
+    fn output(&self) -> Output {
+        self.output.read().expect("output lock poisoned").clone()
+    }
+
+    /// Returns how the logs of the given service are written.
+    fn output_for(&self, service: Option<&str>) -> Output {
+        if let Some(service) = service {
+            let outputs = self.service_outputs.read().expect("output lock poisoned");
+            if let Some(output) = outputs.get(service) {
+                return output.clone();
+            }
+        }
+        self.output()
+    }
+
     /// Returns a new logger with the given fields added to the context
     /// that the logger will use when emitting logs as extra fields
     pub fn with(&self, fields: Fields) -> Self {
@@ -78,13 +197,13 @@ impl Logger {
     }
 
     /// Returns the current log level as expected by the `log` crate.
-    fn level_to_value(&self, level: ::log::Level) -> serde_json::Value {
+    fn level_to_value(field_config: &FieldConfig, level: ::log::Level) -> serde_json::Value {
         serde_json::Value::from(match level {
-            ::log::Level::Trace => self.field_config.level_trace_value,
-            ::log::Level::Debug => self.field_config.level_debug_value,
-            ::log::Level::Info => self.field_config.level_info_value,
-            ::log::Level::Warn => self.field_config.level_warn_value,
-            ::log::Level::Error => self.field_config.level_error_value,
+            ::log::Level::Trace => field_config.level_trace_value,
+            ::log::Level::Debug => field_config.level_debug_value,
+            ::log::Level::Info => field_config.level_info_value,
+            ::log::Level::Warn => field_config.level_warn_value,
+            ::log::Level::Error => field_config.level_error_value,
         })
     }
 
@@ -100,6 +219,8 @@ impl Logger {
     ) -> anyhow::Result<()> {
         self.write_to_trace(request, level, &msg, &fields);
 
+        let output = self.output_for(request.map(request_service));
+        let field_config = output.field_config;
         let mut values = Fields::new();
 
         // Copy the extra fields into the values map.
@@ -115,7 +236,7 @@ impl Logger {
         // If we have a caller field, add it to the values map.
         if let Some(caller) = caller {
             values.insert(
-                self.field_config.caller_field_name.to_string(),
+                field_config.caller_field_name.to_string(),
                 serde_json::Value::from(caller),
             );
         }
@@ -123,13 +244,13 @@ impl Logger {
         // If we have an error field, then let's add it
         if let Some(error) = error {
             values.insert(
-                self.field_config.error_field_name.to_string(),
+                field_config.error_field_name.to_string(),
                 serde_json::Value::from(error.message),
             );
 
             if !error.stack.is_empty() {
                 values.insert(
-                    self.field_config.stack_trace_field_name.to_string(),
+                    field_config.stack_trace_field_name.to_string(),
                     serde_json::to_value(error.stack)?,
                 );
             }
@@ -137,15 +258,15 @@ impl Logger {
 
         // Now add the standard fields.
         values.insert(
-            self.field_config.level_field_name.to_string(),
-            self.level_to_value(level),
+            field_config.level_field_name.to_string(),
+            Self::level_to_value(field_config, level),
         );
         values.insert(
-            self.field_config.timestamp_field_name.to_string(),
-            iso8601_now(),
+            field_config.timestamp_field_name.to_string(),
+            output.timestamp_format.now(),
         );
         values.insert(
-            self.field_config.message_field_name.to_string(),
+            field_config.message_field_name.to_string(),
             serde_json::Value::from(msg),
         );
 
@@ -226,7 +347,8 @@ impl Logger {
         }
 
         // Now write the log to the configured writer.
-        self.writer
+        output
+            .writer
             .write(level, &values)
             .context("unable to write")?;
 
@@ -325,6 +447,16 @@ impl Logger {
     }
 }
 
+/// The name of the service handling the request.
+fn request_service(req: &model::Request) -> &str {
+    match &req.data {
+        model::RequestData::RPC(rpc) => rpc.endpoint.name.service(),
+        model::RequestData::Auth(auth) => auth.auth_handler.service(),
+        model::RequestData::PubSub(msg) => msg.service.as_ref(),
+        model::RequestData::Stream(data) => data.endpoint.name.service(),
+    }
+}
+
 /// This trait defines the logging functions that are available on the `Logger` type.
 ///
 /// It is used to allow Rust code to emit structured logs via our `Logger` implementation
@@ -382,7 +514,7 @@ where
         caller: Option<String>,
         fields: Option<Fields>,
     ) -> anyhow::Result<()> {
-        if level > self.app_level {
+        if level > self.app_level.get() {
             return Ok(());
         }
 
@@ -403,7 +535,7 @@ where
 {
     #[track_caller]
     fn trace(&self, req: Option<&model::Request>, msg: T, fields: Option<Fields>) {
-        if log::Level::Trace > self.app_level {
+        if log::Level::Trace > self.app_level.get() {
             return;
         }
 
@@ -413,7 +545,7 @@ where
 
     #[track_caller]
     fn debug(&self, req: Option<&model::Request>, msg: T, fields: Option<Fields>) {
-        if log::Level::Debug > self.app_level {
+        if log::Level::Debug > self.app_level.get() {
             return;
         }
 
@@ -423,7 +555,7 @@ where
 
     #[track_caller]
     fn info(&self, req: Option<&model::Request>, msg: T, fields: Option<Fields>) {
-        if log::Level::Info > self.app_level {
+        if log::Level::Info > self.app_level.get() {
             return;
         }
 
@@ -439,7 +571,7 @@ where
         error: Option<Err>,
         fields: Option<Fields>,
     ) {
-        if log::Level::Warn > self.app_level {
+        if log::Level::Warn > self.app_level.get() {
             return;
         }
 
@@ -462,7 +594,7 @@ where
         error: Option<Err>,
         fields: Option<Fields>,
     ) {
-        if log::Level::Error > self.app_level {
+        if log::Level::Error > self.app_level.get() {
             return;
         }
 
@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::encore::runtime::v1 as pb;
+use crate::log::writers::Writer;
+use crate::secrets;
+
+const PUSH_PATH: &str = "loki/api/v1/push";
+
+/// How many log lines can be buffered before new lines are dropped.
+const MAX_BUFFERED: usize = 10_000;
+
+/// The number of buffered lines that triggers a push.
+const MAX_BATCH_SIZE: usize = 1_000;
+
+/// How often buffered lines are pushed.
+const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pushes logs to Grafana Loki, with a stream per log level.
+pub struct LokiWriter {
+    tx: mpsc::Sender<Line>,
+}
+
+struct Line {
+    level: log::Level,
+    timestamp: SystemTime,
+    line: String,
+}
+
+impl LokiWriter {
+    pub fn new(
+        cfg: &pb::logs_provider::Loki,
+        secrets: &secrets::Manager,
+        http_client: reqwest::Client,
+        rt: &tokio::runtime::Handle,
+    ) -> anyhow::Result<Self> {
+        let mut url = reqwest::Url::parse(&cfg.endpoint).context("invalid Loki endpoint")?;
+        if !url.path().ends_with(PUSH_PATH) {
+            url.path_segments_mut()
+                .map_err(|_| anyhow::anyhow!("invalid Loki endpoint"))?
+                .pop_if_empty()
+                .extend(PUSH_PATH.split('/'));
+        }
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, data) in &cfg.headers {
+            let secret = secrets.load(data.clone());
+            let value = secret
+                .get()
+                .with_context(|| format!("unable to resolve header {name}"))?;
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())?,
+                reqwest::header::HeaderValue::from_bytes(value)?,
+            );
+        }
+
+        let (tx, rx) = mpsc::channel(MAX_BUFFERED);
+        let pusher = Pusher {
+            http_client,
+            url,
+            headers,
+            labels: cfg.labels.clone(),
+            failing: false,
+        };
+        rt.spawn(pusher.run(rx));
+        Ok(Self { tx })
+    }
+}
+
+impl Writer for LokiWriter {
+    fn write(&self, level: log::Level, values: &BTreeMap<String, Value>) -> anyhow::Result<()> {
+        let line = Line {
+            level,
+            timestamp: SystemTime::now(),
+            line: serde_json::to_string(values)?,
+        };
+        // Drop the line rather than blocking the caller if Loki can't keep up.
+        self.tx
+            .try_send(line)
+            .map_err(|_| anyhow::anyhow!("loki log buffer full"))
+    }
+}
+
+struct Pusher {
+    http_client: reqwest::Client,
+    url: reqwest::Url,
+    headers: reqwest::header::HeaderMap,
+    labels: HashMap<String, String>,
+    /// Whether the last push failed, so failures are logged once
+    /// rather than for every batch while Loki is unavailable.
+    failing: bool,
+}
+
+#[derive(Serialize)]
+struct PushRequest<'a> {
+    streams: Vec<Stream<'a>>,
+}
+
+#[derive(Serialize)]
+struct Stream<'a> {
+    stream: HashMap<&'a str, &'a str>,
+    /// Pairs of the timestamp in nanoseconds, as a string, and the log line.
+    values: Vec<(String, String)>,
+}
+
+impl Pusher {
+    async fn run(mut self, mut rx: mpsc::Receiver<Line>) {
+        let mut batch = Vec::new();
+        let mut interval = tokio::time::interval(PUSH_INTERVAL);
+        loop {
+            tokio::select! {
+                line = rx.recv() => match line {
+                    Some(line) => {
+                        batch.push(line);
+                        if batch.len() >= MAX_BATCH_SIZE {
+                            self.push(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                    None => {
+                        self.push(batch).await;
+                        return;
+                    }
+                },
+                _ = interval.tick() => {
+                    self.push(std::mem::take(&mut batch)).await;
+                }
+            }
+        }
+    }
+
+    async fn push(&mut self, batch: Vec<Line>) {
+        if batch.is_empty() {
+            return;
+        }
+        let req = self.build_request(batch);
+        let result = self
+            .http_client
+            .post(self.url.clone())
+            .headers(self.headers.clone())
+            .json(&req)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status());
+
+        match result {
+            Err(err) if !self.failing => {
+                log::error!("unable to push logs to Loki, dropping logs until it recovers: {err}");
+                self.failing = true;
+            }
+            Ok(_) if self.failing => {
+                log::info!("pushing logs to Loki again");
+                self.failing = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn build_request(&self, batch: Vec<Line>) -> PushRequest<'_> {
+        let mut by_level: BTreeMap<log::Level, Vec<(String, String)>> = BTreeMap::new();
+        for line in batch {
+            let ts = line
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            by_level
+                .entry(line.level)
+                .or_default()
+                .push((ts.to_string(), line.line));
+        }
+
+        PushRequest {
+            streams: by_level
+                .into_iter()
+                .map(|(level, values)| {
+                    let mut stream: HashMap<&str, &str> = self
+                        .labels
+                        .iter()
+                        .map(|(k, v)| (k.as_str(), v.as_str()))
+                        .collect();
+                    stream.insert("level", level_label(level));
+                    Stream { stream, values }
+                })
+                .collect(),
+        }
+    }
+}
+
+fn level_label(level: log::Level) -> &'static str {
+    match level {
+        log::Level::Error => "error",
+        log::Level::Warn => "warn",
+        log::Level::Info => "info",
+        log::Level::Debug => "debug",
+        log::Level::Trace => "trace",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_request() {
+        let pusher = Pusher {
+            http_client: reqwest::Client::new(),
+            url: reqwest::Url::parse("http://loki:3100/loki/api/v1/push").unwrap(),
+            headers: reqwest::header::HeaderMap::new(),
+            labels: HashMap::from([("app".to_string(), "shop".to_string())]),
+            failing: false,
+        };
+        let ts = UNIX_EPOCH + Duration::from_secs(1);
+        let line = |level, line: &str| Line {
+            level,
+            timestamp: ts,
+            line: line.to_string(),
+        };
+
+        let req = pusher.build_request(vec![
+            line(log::Level::Info, "a"),
+            line(log::Level::Error, "b"),
+            line(log::Level::Info, "c"),
+        ]);
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            serde_json::json!({
+                "streams": [
+                    {
+                        "stream": {"app": "shop", "level": "error"},
+                        "values": [["1000000000", "b"]]
+                    },
+                    {
+                        "stream": {"app": "shop", "level": "info"},
+                        "values": [["1000000000", "a"], ["1000000000", "c"]]
+                    }
+                ]
+            })
+        );
+    }
+}
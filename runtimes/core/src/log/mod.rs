@@ -2,12 +2,22 @@ use once_cell::sync::OnceCell;
 
 mod consolewriter;
 mod fields;
+mod file;
 mod logger;
+mod loki;
+mod stream;
 mod writers;
 
+use std::sync::Arc;
+
+use crate::encore::runtime::v1 as pb;
 use crate::log::fields::FieldConfig;
-pub use logger::{Fields, LogFromExternalRuntime, LogFromRust, Logger};
+use crate::log::writers::{ActorWriter, MultiWriter, Writer};
+pub use logger::{Fields, LogFromExternalRuntime, LogFromRust, Logger, Output, TimestampFormat};
+pub use stream::LogLine;
+pub use writers::Format;
 
+use crate::secrets;
 use crate::trace::Tracer;
 
 /// The global root logger instance that is used by both the `log` crate
@@ -50,6 +60,94 @@ pub fn set_tracer(tracer: Tracer) {
     root().set_tracer(tracer);
 }
 
+/// Configure how the global logger writes logs that aren't
+/// written while handling a request of a configured service.
+pub fn configure(cfg: &pb::LogOutput) -> anyhow::Result<()> {
+    root().set_output(output(cfg)?);
+    Ok(())
+}
+
+/// Configure how the global logger writes the logs of the given service.
+pub fn configure_service(service: &str, cfg: &pb::LogOutput) -> anyhow::Result<()> {
+    root().set_service_output(service, output(cfg)?);
+    Ok(())
+}
+
+fn output(cfg: &pb::LogOutput) -> anyhow::Result<Output> {
+    use pb::log_output::{Format as PbFormat, TimestampFormat as PbTimestampFormat};
+
+    let field_config = FieldConfig::default().renamed(&cfg.field_names)?;
+    let format = match cfg.format() {
+        PbFormat::Unspecified => Format::from_env(),
+        PbFormat::Json => Format::Json,
+        PbFormat::Console => Format::Console,
+    };
+    let timestamp_format = match (format, cfg.timestamp_format()) {
+        // The console writer formats timestamps itself.
+        (Format::Console, _) | (_, PbTimestampFormat::Unspecified) => TimestampFormat::Rfc3339,
+        (_, PbTimestampFormat::Rfc3339Nano) => TimestampFormat::Rfc3339Nano,
+        (_, PbTimestampFormat::Unix) => TimestampFormat::Unix,
+        (_, PbTimestampFormat::UnixMs) => TimestampFormat::UnixMs,
+    };
+
+    Ok(Output {
+        field_config,
+        writer: writers::writer_for(format, field_config),
+        timestamp_format,
+    })
+}
+
+/// Configure where the global logger ships logs.
+/// If no sinks are configured, logs are written to stderr.
+pub fn configure_sinks(
+    providers: &[pb::LogsProvider],
+    secrets: &secrets::Manager,
+    http_client: &reqwest::Client,
+    rt: &tokio::runtime::Handle,
+) -> anyhow::Result<()> {
+    use pb::logs_provider::Provider;
+
+    let mut writers: Vec<Arc<dyn Writer>> = Vec::with_capacity(providers.len());
+    for provider in providers {
+        match &provider.provider {
+            Some(Provider::Stdout(_)) => {
+                writers.push(Arc::new(ActorWriter::new(std::io::stdout())))
+            }
+            Some(Provider::File(cfg)) => {
+                writers.push(Arc::new(ActorWriter::new(file::RotatingFile::open(cfg)?)))
+            }
+            Some(Provider::Loki(cfg)) => writers.push(Arc::new(loki::LokiWriter::new(
+                cfg,
+                secrets,
+                http_client.clone(),
+                rt,
+            )?)),
+            None => {}
+        }
+    }
+
+    match writers.len() {
+        0 => {}
+        1 => root().set_writer(writers.remove(0)),
+        _ => root().set_writer(Arc::new(MultiWriter::new(writers))),
+    }
+    Ok(())
+}
+
+/// Broadcasts the logs written by the global logger, in addition to
+/// writing them where they are configured to go, and returns the sender
+/// live subscribers can subscribe to.
+pub fn stream(capacity: usize) -> tokio::sync::broadcast::Sender<LogLine> {
+    let (tx, _) = tokio::sync::broadcast::channel(capacity);
+    root().map_writers(|current| {
+        Arc::new(MultiWriter::new(vec![
+            current,
+            Arc::new(stream::StreamWriter::new(tx.clone())),
+        ]))
+    });
+    tx
+}
+
 /// Returns a reference to the global root logger instance.
 pub fn root() -> &'static Logger {
     ROOT.get_or_init(|| {
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::log::writers::Writer;
+
+/// A log line broadcast to live subscribers.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: log::Level,
+    /// The log line encoded as a JSON object.
+    pub json: Bytes,
+}
+
+/// Broadcasts logs to live subscribers, such as a local dashboard.
+pub struct StreamWriter {
+    tx: broadcast::Sender<LogLine>,
+}
+
+impl StreamWriter {
+    pub fn new(tx: broadcast::Sender<LogLine>) -> Self {
+        Self { tx }
+    }
+}
+
+impl Writer for StreamWriter {
+    fn write(&self, level: log::Level, values: &BTreeMap<String, Value>) -> anyhow::Result<()> {
+        // Only encode the log line if someone is listening.
+        if self.tx.receiver_count() == 0 {
+            return Ok(());
+        }
+        let json = serde_json::to_vec(values)?;
+        _ = self.tx.send(LogLine {
+            level,
+            json: json.into(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_writer() {
+        let (tx, _) = broadcast::channel(4);
+        let writer = StreamWriter::new(tx.clone());
+        let values = BTreeMap::from([("message".to_string(), Value::from("hello"))]);
+
+        // Without subscribers the log line is dropped.
+        writer.write(log::Level::Info, &values).unwrap();
+
+        let mut rx = tx.subscribe();
+        writer.write(log::Level::Warn, &values).unwrap();
+        let line = rx.try_recv().unwrap();
+        assert_eq!(line.level, log::Level::Warn);
+        assert_eq!(&line.json[..], br#"{"message":"hello"}"#);
+        assert!(rx.try_recv().is_err());
+    }
+}
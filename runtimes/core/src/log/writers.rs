@@ -32,17 +32,63 @@ impl Debug for dyn Writer {
 /// will be used, otherwise a blocking writer will be used, resulting
 /// in blocking writes to stderr.
 pub fn default_writer(fields: &'static FieldConfig) -> Arc<dyn Writer> {
-    // Check if the user has set the `ENCORE_LOG_FORMAT` environment variable to `console`.
-    // if so we'll use the pretty console writer.
-    for var in &["ENCORE_LOG_FORMAT"] {
-        if let Ok(format) = env::var(var) {
-            if format == "console" {
-                return Arc::new(ConsoleWriter::new(fields, std::io::stderr()));
+    writer_for(Format::from_env(), fields)
+}
+
+/// The format logs are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Console,
+}
+
+impl Format {
+    /// Returns the format set by the `ENCORE_LOG_FORMAT` environment variable,
+    /// defaulting to JSON.
+    pub fn from_env() -> Self {
+        // Check if the user has set the `ENCORE_LOG_FORMAT` environment variable to `console`.
+        // if so we'll use the pretty console writer.
+        for var in &["ENCORE_LOG_FORMAT"] {
+            if let Ok(format) = env::var(var) {
+                if format == "console" {
+                    return Format::Console;
+                }
             }
         }
+        Format::Json
     }
+}
+
+/// writer_for returns a writer for the given format.
+pub fn writer_for(format: Format, fields: &'static FieldConfig) -> Arc<dyn Writer> {
+    match format {
+        Format::Console => Arc::new(ConsoleWriter::new(fields, std::io::stderr())),
+        Format::Json => Arc::new(ActorWriter::default()),
+    }
+}
 
-    Arc::new(ActorWriter::default())
+/// Writes logs to several writers.
+pub struct MultiWriter {
+    writers: Vec<Arc<dyn Writer>>,
+}
+
+impl MultiWriter {
+    pub fn new(writers: Vec<Arc<dyn Writer>>) -> Self {
+        Self { writers }
+    }
+}
+
+impl Writer for MultiWriter {
+    fn write(&self, level: log::Level, values: &BTreeMap<String, Value>) -> anyhow::Result<()> {
+        // Write to every writer, even if one of them fails.
+        let mut result = Ok(());
+        for writer in &self.writers {
+            if let Err(err) = writer.write(level, values) {
+                result = Err(err);
+            }
+        }
+        result
+    }
 }
 
 // ActorWriter creates a bounded channel that sends log data to a separate thread that handles the writing.
@@ -0,0 +1,173 @@
+//! Service-to-service TLS identities from a service mesh.
+//!
+//! When configured, the runtime fetches its X.509 identity from the mesh
+//! and presents it on service-to-service calls, replacing it whenever
+//! the mesh rotates it. Called services must present an identity from
+//! the same trust domain.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio::sync::watch;
+
+use crate::encore::runtime::v1 as pb;
+
+mod spiffe;
+mod verify;
+
+/// How long calls wait for the first identity to be fetched.
+const IDENTITY_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Manager {
+    client: watch::Receiver<Option<reqwest::Client>>,
+}
+
+impl Manager {
+    /// Starts fetching identities from the mesh in the background.
+    /// The clients presenting them are built from `builder`, so they
+    /// share the settings of the runtime's other HTTP clients.
+    pub fn new(
+        cfg: &pb::ServiceMesh,
+        builder: fn() -> reqwest::ClientBuilder,
+        runtime: &tokio::runtime::Handle,
+    ) -> anyhow::Result<Self> {
+        let (tx, client) = watch::channel(None);
+        match &cfg.provider {
+            Some(pb::service_mesh::Provider::Spiffe(spiffe)) => {
+                let socket = match spiffe.endpoint_socket.as_str() {
+                    "" => std::env::var("SPIFFE_ENDPOINT_SOCKET")
+                        .context("no SPIFFE Workload API endpoint configured")?,
+                    socket => socket.to_string(),
+                };
+                let path = socket
+                    .strip_prefix("unix://")
+                    .context("the SPIFFE Workload API endpoint must be a unix socket")?
+                    .to_string();
+                #[cfg(unix)]
+                runtime.spawn(watch_identities(path, builder, tx));
+                #[cfg(not(unix))]
+                {
+                    drop((tx, builder, runtime));
+                    anyhow::bail!(
+                        "the SPIFFE Workload API is not supported on this platform: {path}"
+                    );
+                }
+            }
+            None => anyhow::bail!("no service mesh provider configured"),
+        }
+        Ok(Self { client })
+    }
+
+    /// The HTTP client presenting the current identity.
+    ///
+    /// Waits for the first identity to be fetched, so calls are never made
+    /// without one, failing if it isn't fetched within [`IDENTITY_TIMEOUT`].
+    pub async fn http_client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut client = self.client.clone();
+        match tokio::time::timeout(IDENTITY_TIMEOUT, client.wait_for(Option::is_some)).await {
+            Ok(Ok(client)) => client.clone().context("no workload identity"),
+            Ok(Err(_)) => anyhow::bail!("workload identities are no longer being fetched"),
+            Err(_) => anyhow::bail!("timed out waiting for a workload identity"),
+        }
+    }
+}
+
+/// Keeps the client up to date with the identities from the Workload API,
+/// reconnecting with backoff if the connection fails.
+#[cfg(unix)]
+async fn watch_identities(
+    socket_path: String,
+    builder: fn() -> reqwest::ClientBuilder,
+    client: watch::Sender<Option<reqwest::Client>>,
+) {
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        let result =
+            spiffe::fetch_x509_svids(&socket_path, |svid| match client_for(&svid, builder()) {
+                Ok(c) => {
+                    ::log::info!("using workload identity {}", svid.spiffe_id);
+                    client.send_replace(Some(c));
+                    backoff = Duration::from_secs(1);
+                }
+                Err(err) => {
+                    ::log::error!("invalid workload identity {}: {err:#}", svid.spiffe_id)
+                }
+            })
+            .await;
+
+        if let Err(err) = result {
+            ::log::error!("unable to fetch workload identity: {err:#}");
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Builds an HTTP client presenting the given identity, which only trusts
+/// services presenting an identity from the same trust domain.
+fn client_for(
+    svid: &spiffe::X509Svid,
+    builder: reqwest::ClientBuilder,
+) -> anyhow::Result<reqwest::Client> {
+    let trust_domain = verify::trust_domain(&svid.spiffe_id).context("invalid SPIFFE ID")?;
+    let chain = split_der(&svid.x509_svid)
+        .context("invalid certificate chain")?
+        .into_iter()
+        .map(|cert| CertificateDer::from(cert.to_vec()))
+        .collect();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(svid.x509_svid_key.clone()));
+
+    let mut roots = rustls::RootCertStore::empty();
+    for ca in split_der(&svid.bundle).context("invalid trust bundle")? {
+        roots
+            .add(CertificateDer::from(ca.to_vec()))
+            .context("invalid CA certificate")?;
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = verify::SpiffeVerifier::new(roots, trust_domain.to_string(), &provider);
+    let tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_client_auth_cert(chain, key)
+        .context("invalid identity")?;
+
+    builder
+        .use_preconfigured_tls(tls)
+        .build()
+        .context("unable to build http client")
+}
+
+/// Splits concatenated DER-encoded certificates.
+fn split_der(mut data: &[u8]) -> anyhow::Result<Vec<&[u8]>> {
+    let mut certs = Vec::new();
+    while !data.is_empty() {
+        let (_, _, rest) = verify::der_next(data).context("truncated DER element")?;
+        certs.push(&data[..data.len() - rest.len()]);
+        data = rest;
+    }
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_der() {
+        let short = [0x30, 0x02, 0x01, 0x02];
+        let mut long = vec![0x30, 0x81, 0x80];
+        long.extend([0xab; 0x80]);
+
+        let data = [&short[..], &long[..]].concat();
+        let certs = split_der(&data).unwrap();
+        assert_eq!(certs, vec![&short[..], &long[..]]);
+
+        assert!(split_der(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! A minimal client for the SPIFFE Workload API.
+//! See https://github.com/spiffe/spiffe/blob/main/standards/SPIFFE_Workload_API.md.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::codegen::http::Uri;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Endpoint;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct X509SvidRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct X509SvidResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub svids: Vec<X509Svid>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct X509Svid {
+    /// The SPIFFE ID of the identity.
+    #[prost(string, tag = "1")]
+    pub spiffe_id: String,
+    /// The ASN.1 DER encoded certificate chain, leaf first.
+    #[prost(bytes = "vec", tag = "2")]
+    pub x509_svid: Vec<u8>,
+    /// The ASN.1 DER encoded PKCS#8 private key.
+    #[prost(bytes = "vec", tag = "3")]
+    pub x509_svid_key: Vec<u8>,
+    /// The ASN.1 DER encoded CA certificates of the trust domain.
+    #[prost(bytes = "vec", tag = "4")]
+    pub bundle: Vec<u8>,
+}
+
+/// Streams the workload's X.509 identities from the Workload API at the
+/// given unix socket, calling `on_svid` with the default identity each time
+/// it's issued or rotated. Returns when the stream ends.
+#[cfg(unix)]
+pub async fn fetch_x509_svids<F>(socket_path: &str, mut on_svid: F) -> anyhow::Result<()>
+where
+    F: FnMut(X509Svid),
+{
+    // The URI is required but unused, as the connector dials the socket.
+    let channel = Endpoint::from_static("http://localhost")
+        .connect_with_connector(UnixConnector(socket_path.to_string()))
+        .await?;
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready().await?;
+
+    let mut req = tonic::Request::new(X509SvidRequest {});
+    req.metadata_mut()
+        .insert("workload.spiffe.io", MetadataValue::from_static("true"));
+    let path = PathAndQuery::from_static("/SpiffeWorkloadAPI/FetchX509SVID");
+    let codec = tonic::codec::ProstCodec::<X509SvidRequest, X509SvidResponse>::default();
+    let mut stream = grpc.server_streaming(req, path, codec).await?.into_inner();
+
+    while let Some(resp) = stream.message().await? {
+        // The first identity is the default one.
+        if let Some(svid) = resp.svids.into_iter().next() {
+            on_svid(svid);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[derive(Clone)]
+struct UnixConnector(String);
+
+#[cfg(unix)]
+impl tower_service::Service<Uri> for UnixConnector {
+    type Response = tokio::net::UnixStream;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.0.clone();
+        Box::pin(async move { tokio::net::UnixStream::connect(path).await })
+    }
+}
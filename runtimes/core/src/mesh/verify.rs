@@ -0,0 +1,230 @@
+//! Verifies that called services present an identity from the caller's trust domain.
+//! See https://github.com/spiffe/spiffe/blob/main/standards/X509-SVID.md.
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::server::ParsedCertificate;
+use rustls::{CertificateError, DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+const SEQUENCE: u8 = 0x30;
+const BOOLEAN: u8 = 0x01;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+/// The explicitly tagged extensions of a TBSCertificate.
+const EXTENSIONS: u8 = 0xa3;
+/// The implicitly tagged uniformResourceIdentifier of a GeneralName.
+const URI: u8 = 0x86;
+/// The DER-encoded OID of the subject alternative name extension, 2.5.29.17.
+const SAN_OID: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Verifies the server's certificate chain against the trust bundle, and
+/// that its SPIFFE ID is in the expected trust domain. Hostnames aren't
+/// checked, as services are identified by their SPIFFE ID instead.
+#[derive(Debug)]
+pub(super) struct SpiffeVerifier {
+    roots: RootCertStore,
+    trust_domain: String,
+    algs: WebPkiSupportedAlgorithms,
+}
+
+impl SpiffeVerifier {
+    pub fn new(roots: RootCertStore, trust_domain: String, provider: &CryptoProvider) -> Self {
+        Self {
+            roots,
+            trust_domain,
+            algs: provider.signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for SpiffeVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let cert = ParsedCertificate::try_from(end_entity)?;
+        rustls::client::verify_server_cert_signed_by_trust_anchor(
+            &cert,
+            &self.roots,
+            intermediates,
+            now,
+            self.algs.all,
+        )?;
+
+        let id = spiffe_id(end_entity.as_ref())
+            .ok_or(CertificateError::ApplicationVerificationFailure)?;
+        if trust_domain(&id) != Some(self.trust_domain.as_str()) {
+            ::log::warn!(
+                "rejecting service identity {id} outside of trust domain {}",
+                self.trust_domain
+            );
+            return Err(CertificateError::ApplicationVerificationFailure.into());
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algs)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algs)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algs.supported_schemes()
+    }
+}
+
+/// The trust domain of a SPIFFE ID, like "example.org" for
+/// "spiffe://example.org/ns/default/sa/api".
+pub(super) fn trust_domain(id: &str) -> Option<&str> {
+    let domain = id.strip_prefix("spiffe://")?.split('/').next()?;
+    (!domain.is_empty()).then_some(domain)
+}
+
+/// The SPIFFE ID in the URI subject alternative name of a DER-encoded certificate.
+fn spiffe_id(cert: &[u8]) -> Option<String> {
+    let (cert, _) = der_expect(cert, SEQUENCE)?;
+    let (mut tbs, _) = der_expect(cert, SEQUENCE)?;
+
+    // The extensions are the last field of the TBSCertificate.
+    let exts = loop {
+        let (tag, contents, rest) = der_next(tbs)?;
+        if tag == EXTENSIONS {
+            break contents;
+        }
+        tbs = rest;
+    };
+    let (mut exts, _) = der_expect(exts, SEQUENCE)?;
+
+    let mut names = loop {
+        let (ext, rest) = der_expect(exts, SEQUENCE)?;
+        exts = rest;
+        let (oid, mut ext) = der_expect(ext, OID)?;
+        if oid != SAN_OID {
+            continue;
+        }
+        if let Some((BOOLEAN, _, rest)) = der_next(ext) {
+            ext = rest;
+        }
+        let (value, _) = der_expect(ext, OCTET_STRING)?;
+        break der_expect(value, SEQUENCE)?.0;
+    };
+
+    while !names.is_empty() {
+        let (tag, name, rest) = der_next(names)?;
+        names = rest;
+        if tag != URI {
+            continue;
+        }
+        let uri = std::str::from_utf8(name).ok()?;
+        if uri.starts_with("spiffe://") {
+            return Some(uri.to_string());
+        }
+    }
+    None
+}
+
+/// Splits the DER element at the start of `data` into its tag,
+/// its contents, and the data following it.
+pub(super) fn der_next(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)?;
+    let (header, len) = if first & 0x80 == 0 {
+        (2, first as usize)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let bytes = data.get(2..2 + n)?;
+        (2 + n, bytes.iter().fold(0, |acc, b| acc << 8 | *b as usize))
+    };
+    let total = header.checked_add(len)?;
+    let contents = data.get(header..total)?;
+    Some((tag, contents, &data[total..]))
+}
+
+/// Like [`der_next`], but requires the element to have the given tag.
+fn der_expect(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match der_next(data)? {
+        (t, contents, rest) if t == tag => Some((contents, rest)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag, contents.len() as u8];
+        out.extend_from_slice(contents);
+        out
+    }
+
+    /// A certificate with only the fields needed to find the SPIFFE ID.
+    fn cert_with_sans(sans: &[(u8, &str)]) -> Vec<u8> {
+        let names: Vec<u8> = sans
+            .iter()
+            .flat_map(|(tag, name)| tlv(*tag, name.as_bytes()))
+            .collect();
+        let ext = [
+            tlv(OID, SAN_OID),
+            tlv(BOOLEAN, &[0xff]),
+            tlv(OCTET_STRING, &tlv(SEQUENCE, &names)),
+        ]
+        .concat();
+        let other_ext = [tlv(OID, &[0x55, 0x1d, 0x0f]), tlv(OCTET_STRING, &[])].concat();
+        let exts = tlv(
+            SEQUENCE,
+            &[tlv(SEQUENCE, &other_ext), tlv(SEQUENCE, &ext)].concat(),
+        );
+        let tbs = [tlv(0x02, &[1]), tlv(SEQUENCE, &[]), tlv(EXTENSIONS, &exts)].concat();
+        tlv(SEQUENCE, &tlv(SEQUENCE, &tbs))
+    }
+
+    #[test]
+    fn test_spiffe_id() {
+        let cert = cert_with_sans(&[
+            (0x82, "api.internal"),
+            (URI, "https://example.org"),
+            (URI, "spiffe://example.org/ns/default/sa/api"),
+        ]);
+        assert_eq!(
+            spiffe_id(&cert).as_deref(),
+            Some("spiffe://example.org/ns/default/sa/api")
+        );
+
+        let cert = cert_with_sans(&[(0x82, "api.internal")]);
+        assert_eq!(spiffe_id(&cert), None);
+        assert_eq!(spiffe_id(&cert[..cert.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_trust_domain() {
+        assert_eq!(
+            trust_domain("spiffe://example.org/ns/default/sa/api"),
+            Some("example.org")
+        );
+        assert_eq!(trust_domain("spiffe://example.org"), Some("example.org"));
+        assert_eq!(trust_domain("spiffe:///api"), None);
+        assert_eq!(trust_domain("https://example.org/api"), None);
+    }
+}
@@ -144,6 +144,8 @@ impl Aws {
                 MetricValue::GaugeF64(val) => val,
                 MetricValue::GaugeU64(val) => val as f64,
                 MetricValue::GaugeI64(val) => val as f64,
+                // Histograms aren't supported, which is logged when the exporter is created.
+                MetricValue::Histogram(_) => continue,
             };
 
             let mut datum_builder = MetricDatum::builder()
@@ -217,6 +217,8 @@ impl Datadog {
                 MetricValue::GaugeF64(val) => (MetricIntakeType::GAUGE, val),
                 MetricValue::GaugeU64(val) => (MetricIntakeType::GAUGE, val as f64),
                 MetricValue::GaugeI64(val) => (MetricIntakeType::GAUGE, val as f64),
+                // Histograms aren't supported, which is logged when the exporter is created.
+                MetricValue::Histogram(_) => continue,
             };
 
             let point = MetricPoint::new().timestamp(now).value(value);
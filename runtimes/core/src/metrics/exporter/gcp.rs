@@ -196,6 +196,8 @@ impl Gcp {
                     TypedValue::new().set_int64_value(val),
                     TimeInterval::new().set_end_time(ts_end_time),
                 ),
+                // Histograms aren't supported, which is logged when the exporter is created.
+                MetricValue::Histogram(_) => continue,
             };
 
             // Add container instance ID to node_id if present
@@ -1,10 +1,12 @@
 mod aws;
 mod datadog;
 mod gcp;
+mod otlp;
 mod prometheus;
 pub use aws::Aws;
 pub use datadog::Datadog;
 pub use gcp::Gcp;
+pub use otlp::Otlp;
 pub use prometheus::Prometheus;
 
 #[async_trait::async_trait]
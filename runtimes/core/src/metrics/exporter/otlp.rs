@@ -0,0 +1,378 @@
+//! Exports metrics using the OpenTelemetry Protocol (OTLP).
+//! See https://opentelemetry.io/docs/specs/otlp/.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::OnceCell;
+
+use crate::encore::runtime::v1 as pb;
+use crate::metadata::ContainerMetaClient;
+use crate::metrics::exporter::Exporter;
+use crate::metrics::histogram::HistogramValue;
+use crate::metrics::{CollectedMetric, MetricValue};
+use crate::secrets;
+use crate::trace::otlp::{InstrumentationScope, KeyValue, Resource, Transport};
+
+const GRPC_EXPORT_PATH: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+const HTTP_EXPORT_PATH: &str = "v1/metrics";
+
+/// Counters and histograms are exported as totals since the metric was registered.
+const AGGREGATION_TEMPORALITY_CUMULATIVE: i32 = 2;
+
+pub struct Otlp {
+    transport: Transport,
+    container_meta_client: ContainerMetaClient,
+    /// Attributes of the environment, shared by all metrics.
+    environment: Vec<KeyValue>,
+    resource: OnceCell<Arc<Resource>>,
+}
+
+impl Otlp {
+    pub fn new(
+        provider_cfg: &pb::metrics_provider::Otlp,
+        secrets: &secrets::Manager,
+        environment: &pb::Environment,
+        http_client: reqwest::Client,
+        container_meta_client: ContainerMetaClient,
+    ) -> anyhow::Result<Self> {
+        use pb::metrics_provider::otlp::Protocol;
+
+        let transport = Transport::new(
+            http_client,
+            &provider_cfg.endpoint,
+            provider_cfg.protocol() == Protocol::Grpc,
+            HTTP_EXPORT_PATH,
+            &provider_cfg.headers,
+            secrets,
+        )?;
+
+        Ok(Self {
+            transport,
+            container_meta_client,
+            environment: vec![
+                KeyValue::string("deployment.environment.name", &environment.env_name),
+                KeyValue::string("service.namespace", &environment.app_slug),
+            ],
+            resource: OnceCell::new(),
+        })
+    }
+
+    /// Returns the resource the metrics are reported for,
+    /// describing the environment and container.
+    async fn resource(&self) -> Arc<Resource> {
+        self.resource
+            .get_or_init(|| async {
+                let labels = match self.container_meta_client.collect().await {
+                    Ok(meta) => meta.labels(),
+                    Err(e) => {
+                        log::warn!("failed fetching container metadata: {e}, using fallback");
+                        self.container_meta_client.fallback().labels()
+                    }
+                };
+                let mut attributes = self.environment.clone();
+                attributes.extend(
+                    labels
+                        .iter()
+                        .map(|(name, value)| KeyValue::string(name, value)),
+                );
+                Arc::new(Resource { attributes })
+            })
+            .await
+            .clone()
+    }
+
+    async fn export_metrics(&self, metrics: Vec<CollectedMetric>) -> anyhow::Result<()> {
+        if metrics.is_empty() {
+            return Ok(());
+        }
+
+        log::trace!("Exporting {} metrics over OTLP", metrics.len());
+
+        let req = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(self.resource().await.as_ref().clone()),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: "encore".to_string(),
+                        version: String::new(),
+                    }),
+                    metrics: to_metrics(metrics, SystemTime::now()),
+                }],
+            }],
+        };
+        self.transport
+            .send::<_, ExportMetricsServiceResponse>(req, GRPC_EXPORT_PATH)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl Exporter for Otlp {
+    async fn export(&self, metrics: Vec<CollectedMetric>) {
+        if let Err(err) = self.export_metrics(metrics).await {
+            log::error!("Failed to export metrics over OTLP: {:#}", err);
+        }
+    }
+}
+
+fn to_metrics(collected: Vec<CollectedMetric>, now: SystemTime) -> Vec<Metric> {
+    let time_unix_nano = unix_nanos(now);
+    collected
+        .into_iter()
+        .map(|metric| {
+            let attributes: Vec<KeyValue> = metric
+                .key
+                .labels()
+                .map(|label| KeyValue::string(label.key(), label.value()))
+                .collect();
+            let start_time_unix_nano = unix_nanos(metric.registered_at);
+
+            let point = |value| NumberDataPoint {
+                attributes: attributes.clone(),
+                start_time_unix_nano,
+                time_unix_nano,
+                value: Some(value),
+            };
+            let sum = |value| {
+                metric::Data::Sum(Sum {
+                    data_points: vec![point(value)],
+                    aggregation_temporality: AGGREGATION_TEMPORALITY_CUMULATIVE,
+                    is_monotonic: true,
+                })
+            };
+            let gauge = |value| {
+                metric::Data::Gauge(Gauge {
+                    data_points: vec![point(value)],
+                })
+            };
+
+            let data = match metric.value {
+                MetricValue::CounterU64(val) => sum(number_data_point::Value::AsInt(val as i64)),
+                MetricValue::CounterI64(val) => sum(number_data_point::Value::AsInt(val)),
+                MetricValue::GaugeF64(val) => gauge(number_data_point::Value::AsDouble(val)),
+                MetricValue::GaugeU64(val) => gauge(number_data_point::Value::AsInt(val as i64)),
+                MetricValue::GaugeI64(val) => gauge(number_data_point::Value::AsInt(val)),
+                MetricValue::Histogram(hist) => metric::Data::Histogram(Histogram {
+                    data_points: vec![histogram_point(
+                        hist,
+                        attributes.clone(),
+                        start_time_unix_nano,
+                        time_unix_nano,
+                    )],
+                    aggregation_temporality: AGGREGATION_TEMPORALITY_CUMULATIVE,
+                }),
+            };
+
+            Metric {
+                name: metric.key.name().to_string(),
+                data: Some(data),
+            }
+        })
+        .collect()
+}
+
+/// Converts a histogram into an OTLP data point, whose bucket counts
+/// aren't cumulative and whose bounds leave out the final infinite bucket.
+fn histogram_point(
+    hist: HistogramValue,
+    attributes: Vec<KeyValue>,
+    start_time_unix_nano: u64,
+    time_unix_nano: u64,
+) -> HistogramDataPoint {
+    let mut bucket_counts = Vec::with_capacity(hist.buckets.len());
+    let mut explicit_bounds = Vec::with_capacity(hist.buckets.len());
+    let mut exemplars = Vec::new();
+    let mut prev = 0;
+    for bucket in hist.buckets {
+        bucket_counts.push(bucket.cumulative_count.saturating_sub(prev));
+        prev = bucket.cumulative_count;
+        if !bucket.upper_bound.is_infinite() {
+            explicit_bounds.push(bucket.upper_bound);
+        }
+        if let Some(exemplar) = bucket.exemplar {
+            exemplars.push(Exemplar {
+                time_unix_nano: unix_nanos(exemplar.timestamp),
+                as_double: exemplar.value,
+                trace_id: hex::decode(&exemplar.trace_id).unwrap_or_default(),
+            });
+        }
+    }
+
+    HistogramDataPoint {
+        attributes,
+        start_time_unix_nano,
+        time_unix_nano,
+        count: hist.count,
+        sum: Some(hist.sum),
+        bucket_counts,
+        explicit_bounds,
+        exemplars,
+    }
+}
+
+fn unix_nanos(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+// The subset of the OTLP metrics protos used for exporting.
+// See https://github.com/open-telemetry/opentelemetry-proto.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportMetricsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportMetricsServiceResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ResourceMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScopeMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(oneof = "metric::Data", tags = "5, 7, 9")]
+    pub data: Option<metric::Data>,
+}
+
+pub mod metric {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Data {
+        #[prost(message, tag = "5")]
+        Gauge(super::Gauge),
+        #[prost(message, tag = "7")]
+        Sum(super::Sum),
+        #[prost(message, tag = "9")]
+        Histogram(super::Histogram),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Gauge {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Sum {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+    #[prost(int32, tag = "2")]
+    pub aggregation_temporality: i32,
+    #[prost(bool, tag = "3")]
+    pub is_monotonic: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Histogram {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<HistogramDataPoint>,
+    #[prost(int32, tag = "2")]
+    pub aggregation_temporality: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct NumberDataPoint {
+    #[prost(message, repeated, tag = "7")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(fixed64, tag = "2")]
+    pub start_time_unix_nano: u64,
+    #[prost(fixed64, tag = "3")]
+    pub time_unix_nano: u64,
+    #[prost(oneof = "number_data_point::Value", tags = "4, 6")]
+    pub value: Option<number_data_point::Value>,
+}
+
+pub mod number_data_point {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(double, tag = "4")]
+        AsDouble(f64),
+        #[prost(sfixed64, tag = "6")]
+        AsInt(i64),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HistogramDataPoint {
+    #[prost(message, repeated, tag = "9")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(fixed64, tag = "2")]
+    pub start_time_unix_nano: u64,
+    #[prost(fixed64, tag = "3")]
+    pub time_unix_nano: u64,
+    #[prost(fixed64, tag = "4")]
+    pub count: u64,
+    #[prost(double, optional, tag = "5")]
+    pub sum: Option<f64>,
+    #[prost(fixed64, repeated, tag = "6")]
+    pub bucket_counts: Vec<u64>,
+    #[prost(double, repeated, tag = "7")]
+    pub explicit_bounds: Vec<f64>,
+    #[prost(message, repeated, tag = "8")]
+    pub exemplars: Vec<Exemplar>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Exemplar {
+    #[prost(fixed64, tag = "2")]
+    pub time_unix_nano: u64,
+    #[prost(double, tag = "3")]
+    pub as_double: f64,
+    #[prost(bytes = "vec", tag = "5")]
+    pub trace_id: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::histogram::Bucket;
+
+    #[test]
+    fn test_histogram_point() {
+        let hist = HistogramValue {
+            buckets: vec![
+                Bucket {
+                    upper_bound: 0.1,
+                    cumulative_count: 2,
+                    exemplar: None,
+                },
+                Bucket {
+                    upper_bound: 1.0,
+                    cumulative_count: 5,
+                    exemplar: None,
+                },
+                Bucket {
+                    upper_bound: f64::INFINITY,
+                    cumulative_count: 6,
+                    exemplar: None,
+                },
+            ],
+            sum: 4.2,
+            count: 6,
+        };
+
+        let point = histogram_point(hist, vec![], 1, 2);
+        assert_eq!(point.bucket_counts, vec![2, 3, 1]);
+        assert_eq!(point.explicit_bounds, vec![0.1, 1.0]);
+        assert_eq!(point.count, 6);
+        assert_eq!(point.sum, Some(4.2));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::encore::runtime::v1 as pb;
 use crate::metadata::ContainerMetaClient;
 use crate::metrics::exporter::Exporter;
+use crate::metrics::histogram::HistogramValue;
 use crate::metrics::{CollectedMetric, MetricValue};
 use crate::secrets;
 use anyhow::Context;
@@ -40,7 +41,7 @@ impl Prometheus {
         };
 
         Ok(Self {
-            client: reqwest::Client::new(),
+            client: crate::egress::client(),
             remote_write_url,
             container_meta_client,
             container_labels: OnceCell::new(),
@@ -160,12 +161,6 @@ impl Prometheus {
                 });
             }
 
-            // Add __name__ label for the metric name
-            labels.push(prompb::Label {
-                name: "__name__".to_string(),
-                value: metric_name,
-            });
-
             // Convert metric value to float64
             let value = match metric.value {
                 MetricValue::CounterU64(val) => val as f64,
@@ -173,8 +168,18 @@ impl Prometheus {
                 MetricValue::GaugeF64(val) => val,
                 MetricValue::GaugeU64(val) => val as f64,
                 MetricValue::GaugeI64(val) => val as f64,
+                MetricValue::Histogram(hist) => {
+                    data.extend(histogram_series(&metric_name, labels, hist, timestamp));
+                    continue;
+                }
             };
 
+            // Add __name__ label for the metric name
+            labels.push(prompb::Label {
+                name: "__name__".to_string(),
+                value: metric_name,
+            });
+
             data.push(prompb::TimeSeries {
                 labels,
                 samples: vec![prompb::Sample { value, timestamp }],
@@ -196,6 +201,62 @@ impl Exporter for Prometheus {
     }
 }
 
+/// Converts a histogram into the `_bucket`, `_sum` and `_count` series of a
+/// classic Prometheus histogram. Bucket exemplars link to the trace they were
+/// observed in, using the `trace_id` label.
+fn histogram_series(
+    name: &str,
+    labels: Vec<prompb::Label>,
+    hist: HistogramValue,
+    timestamp: i64,
+) -> Vec<prompb::TimeSeries> {
+    let series = |suffix: &str, extra: Option<prompb::Label>, value: f64| {
+        let mut labels = labels.clone();
+        labels.extend(extra);
+        labels.push(prompb::Label {
+            name: "__name__".to_string(),
+            value: format!("{name}{suffix}"),
+        });
+        prompb::TimeSeries {
+            labels,
+            samples: vec![prompb::Sample { value, timestamp }],
+            exemplars: vec![],
+            histograms: vec![],
+        }
+    };
+
+    let mut data = Vec::with_capacity(hist.buckets.len() + 2);
+    for bucket in hist.buckets {
+        let le = if bucket.upper_bound.is_infinite() {
+            "+Inf".to_string()
+        } else {
+            bucket.upper_bound.to_string()
+        };
+        let mut ts = series(
+            "_bucket",
+            Some(prompb::Label {
+                name: "le".to_string(),
+                value: le,
+            }),
+            bucket.cumulative_count as f64,
+        );
+        if let Some(exemplar) = bucket.exemplar {
+            ts.exemplars.push(prompb::Exemplar {
+                labels: vec![prompb::Label {
+                    name: "trace_id".to_string(),
+                    value: exemplar.trace_id,
+                }],
+                value: exemplar.value,
+                timestamp: from_time(exemplar.timestamp),
+            });
+        }
+        data.push(ts);
+    }
+    data.push(series("_sum", None, hist.sum));
+    data.push(series("_count", None, hist.count as f64));
+    data
+}
+
 /// Convert SystemTime to Prometheus timestamp (milliseconds since Unix epoch)
 fn from_time(t: SystemTime) -> i64 {
     match t.duration_since(SystemTime::UNIX_EPOCH) {
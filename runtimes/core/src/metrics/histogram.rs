@@ -0,0 +1,207 @@
+use crate::metrics;
+use crate::model::TraceId;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The default bucket upper bounds for latency histograms, in seconds.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A sample linking a histogram bucket to the trace it was observed in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    /// The trace id, in the W3C trace context format.
+    pub trace_id: String,
+    pub value: f64,
+    pub timestamp: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    /// The inclusive upper bound of the bucket; infinite for the last bucket.
+    pub upper_bound: f64,
+    /// The number of observations less than or equal to the upper bound.
+    pub cumulative_count: u64,
+    /// The most recent traced observation in the bucket, if any.
+    pub exemplar: Option<Exemplar>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramValue {
+    pub buckets: Vec<Bucket>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+#[derive(Debug)]
+struct HistogramData {
+    bounds: &'static [f64],
+    /// Per-bucket counts, with a final bucket for values above all bounds.
+    counts: Vec<AtomicU64>,
+    exemplars: Vec<Mutex<Option<Exemplar>>>,
+    /// The sum of all observations, as f64 bits.
+    sum: AtomicU64,
+}
+
+/// A histogram of observed values, such as request latencies.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    data: Arc<HistogramData>,
+}
+
+impl Histogram {
+    /// Create a new histogram with the given bucket upper bounds
+    /// This is typically called by Registry, not directly by users
+    pub(crate) fn new(bounds: &'static [f64]) -> Self {
+        let n = bounds.len() + 1;
+        Self {
+            data: Arc::new(HistogramData {
+                bounds,
+                counts: (0..n).map(|_| AtomicU64::new(0)).collect(),
+                exemplars: (0..n).map(|_| Mutex::new(None)).collect(),
+                sum: AtomicU64::new(0f64.to_bits()),
+            }),
+        }
+    }
+
+    /// Record an observation.
+    pub fn observe(&self, value: f64) {
+        self.record(value);
+    }
+
+    /// Record an observation made as part of a trace,
+    /// keeping it as the exemplar for its bucket.
+    pub fn observe_traced(&self, value: f64, trace_id: TraceId) {
+        let idx = self.record(value);
+        *self.data.exemplars[idx].lock().unwrap() = Some(Exemplar {
+            trace_id: trace_id.serialize_std(),
+            value,
+            timestamp: SystemTime::now(),
+        });
+    }
+
+    fn record(&self, value: f64) -> usize {
+        let data = &self.data;
+        let idx = data
+            .bounds
+            .iter()
+            .position(|b| value <= *b)
+            .unwrap_or(data.bounds.len());
+        data.counts[idx].fetch_add(1, Ordering::Release);
+
+        let _ = data
+            .sum
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+        idx
+    }
+
+    /// Get the current value of the histogram
+    pub fn get(&self) -> metrics::MetricValue {
+        let data = &self.data;
+        let mut cumulative_count = 0;
+        let buckets = data
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(idx, count)| {
+                cumulative_count += count.load(Ordering::Acquire);
+                Bucket {
+                    upper_bound: data.bounds.get(idx).copied().unwrap_or(f64::INFINITY),
+                    cumulative_count,
+                    exemplar: data.exemplars[idx].lock().unwrap().clone(),
+                }
+            })
+            .collect();
+
+        metrics::MetricValue::Histogram(HistogramValue {
+            buckets,
+            sum: f64::from_bits(data.sum.load(Ordering::Acquire)),
+            count: cumulative_count,
+        })
+    }
+}
+
+/// A histogram schema that defines static labels and bucket bounds,
+/// creating separate time series for each unique combination of
+/// static + dynamic labels
+#[derive(Clone, Debug)]
+pub struct Schema {
+    name: String,
+    static_labels: Vec<(String, String)>,
+    bounds: &'static [f64],
+    registry: Arc<metrics::Registry>,
+}
+
+impl Schema {
+    pub fn with<L, K, V>(&self, dynamic_labels: L) -> Histogram
+    where
+        L: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let mut merged_labels = self.static_labels.clone();
+        for (key, value) in dynamic_labels {
+            merged_labels.push((key.into(), value.into()));
+        }
+
+        self.registry.get_or_create_histogram(
+            &self.name,
+            merged_labels.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+            self.bounds,
+        )
+    }
+}
+
+/// Builder for creating histogram schemas with static labels and bucket bounds
+pub struct HistogramSchemaBuilder {
+    name: String,
+    static_labels: Vec<(String, String)>,
+    bounds: &'static [f64],
+    registry: Arc<metrics::Registry>,
+}
+
+impl HistogramSchemaBuilder {
+    pub(crate) fn new(name: String, registry: Arc<metrics::Registry>) -> Self {
+        Self {
+            name,
+            static_labels: Vec::new(),
+            bounds: LATENCY_BUCKETS,
+            registry,
+        }
+    }
+
+    /// Add static labels that are set once when the schema is created
+    pub fn static_labels<I, K, V>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (key, value) in labels {
+            self.static_labels
+                .push((key.as_ref().to_string(), value.as_ref().to_string()));
+        }
+        self
+    }
+
+    /// Set the bucket upper bounds, in increasing order.
+    /// Defaults to [`LATENCY_BUCKETS`].
+    pub fn buckets(mut self, bounds: &'static [f64]) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    /// Build the histogram schema
+    pub fn build(self) -> Schema {
+        Schema {
+            name: self.name,
+            static_labels: self.static_labels,
+            bounds: self.bounds,
+            registry: self.registry,
+        }
+    }
+}
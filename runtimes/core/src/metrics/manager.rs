@@ -3,6 +3,7 @@ use crate::{
     metadata::{process_env_substitution, ContainerMetaClient},
     metrics::{
         exporter::{self, Exporter},
+        naming::Naming,
         registry::Registry,
     },
     secrets,
@@ -17,6 +18,7 @@ enum ProviderType {
     Aws(pb::metrics_provider::AwsCloudWatch),
     Datadog(pb::metrics_provider::Datadog),
     Prometheus(pb::metrics_provider::PrometheusRemoteWrite),
+    Otlp(pb::metrics_provider::Otlp),
 }
 
 impl ProviderType {
@@ -33,6 +35,7 @@ impl ProviderType {
             Some(pb::metrics_provider::Provider::PromRemoteWrite(config)) => {
                 Some(Self::Prometheus(config.clone()))
             }
+            Some(pb::metrics_provider::Provider::Otlp(config)) => Some(Self::Otlp(config.clone())),
             None => {
                 log::warn!("no metrics provider configured");
                 None
@@ -40,6 +43,14 @@ impl ProviderType {
         }
     }
 
+    /// Reports whether the provider's exporter supports histograms.
+    fn supports_histograms(&self) -> bool {
+        match self {
+            Self::Prometheus(_) | Self::Otlp(_) => true,
+            Self::Gcp(_) | Self::EncoreCloud(_) | Self::Aws(_) | Self::Datadog(_) => false,
+        }
+    }
+
     fn create_exporter(
         &self,
         env: &Environment,
@@ -57,9 +68,26 @@ impl ProviderType {
             Self::Prometheus(config) => {
                 Self::create_prometheus_exporter(config, secrets, env, http_client)
             }
+            Self::Otlp(config) => Self::create_otlp_exporter(config, secrets, env, http_client),
         }
     }
 
+    fn create_otlp_exporter(
+        provider_cfg: &pb::metrics_provider::Otlp,
+        secrets: &secrets::Manager,
+        env: &Environment,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<Arc<dyn Exporter + Send + Sync>> {
+        let container_meta_client = ContainerMetaClient::new(env.clone(), http_client.clone());
+        Ok(Arc::new(exporter::Otlp::new(
+            provider_cfg,
+            secrets,
+            env,
+            http_client.clone(),
+            container_meta_client,
+        )?))
+    }
+
     fn create_prometheus_exporter(
         provider_cfg: &pb::metrics_provider::PrometheusRemoteWrite,
         secrets: &secrets::Manager,
@@ -124,6 +152,8 @@ impl ProviderType {
 pub struct Manager {
     exporter: Option<Arc<dyn Exporter>>,
     registry: Arc<Registry>,
+    /// Rewrites metric names and labels before they're exported, if configured.
+    naming: Option<Arc<Naming>>,
 }
 
 impl Manager {
@@ -133,6 +163,7 @@ impl Manager {
         Self {
             exporter: None,
             registry,
+            naming: None,
         }
     }
 
@@ -148,11 +179,20 @@ impl Manager {
         runtime_handle: tokio::runtime::Handle,
     ) -> Self {
         let mut manager = Self::new();
+        manager.naming = observability
+            .metric_naming
+            .as_ref()
+            .map(|cfg| Arc::new(Naming::new(cfg)));
 
         for metrics_provider in &observability.metrics {
             if let Some(provider_type) = ProviderType::from_config(metrics_provider) {
                 match provider_type.create_exporter(environment, secrets, http_client) {
                     Ok(exporter) => {
+                        if !provider_type.supports_histograms() {
+                            log::warn!(
+                                "the configured metrics provider does not support histograms, so histogram metrics such as e_request_duration_seconds will not be exported"
+                            );
+                        }
                         manager.exporter = Some(exporter);
                         break; // Take the first valid provider
                     }
@@ -183,9 +223,18 @@ impl Manager {
         self
     }
 
+    pub fn with_naming(mut self, naming: &pb::MetricNaming) -> Self {
+        self.naming = Some(Arc::new(Naming::new(naming)));
+        self
+    }
+
     pub async fn collect_and_export(&self) {
         let metrics = self.registry.collect();
         if let Some(ref exporter) = self.exporter {
+            let metrics = match &self.naming {
+                Some(naming) => naming.apply(metrics),
+                None => metrics,
+            };
             exporter.export(metrics).await;
         }
     }
@@ -1,11 +1,14 @@
 mod atomic;
 mod exporter;
 mod manager;
+mod naming;
 mod registry;
 mod system;
 
 pub mod counter;
 pub mod gauge;
+pub mod histogram;
+pub mod slo;
 
 #[cfg(test)]
 mod test;
@@ -14,6 +17,7 @@ use std::sync::Arc;
 
 pub use counter::{Counter, CounterOps};
 pub use gauge::{Gauge, GaugeOps};
+pub use histogram::Histogram;
 pub use manager::Manager;
 pub use registry::{CollectedMetric, MetricValue, MetricsCollector, Registry};
 pub use system::SystemMetricsCollector;
@@ -31,6 +35,18 @@ pub fn requests_total_counter(
         .build()
 }
 
+/// Create a request duration histogram schema
+pub fn request_duration_histogram(
+    registry: &Arc<Registry>,
+    service: &str,
+    endpoint: &str,
+) -> histogram::Schema {
+    registry
+        .histogram_schema("e_request_duration_seconds")
+        .static_labels([("service", service), ("endpoint", endpoint)])
+        .build()
+}
+
 /// Create a memory usage gauge schema
 pub fn memory_usage_gauge_schema(registry: &Arc<Registry>) -> gauge::Schema<u64> {
     registry
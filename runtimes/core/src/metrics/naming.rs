@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+
+use metrics::{Key, Label};
+
+use crate::encore::runtime::v1 as pb;
+use crate::metrics::{CollectedMetric, MetricValue};
+
+/// Rewrites metric names and labels before they're exported.
+#[derive(Debug)]
+pub struct Naming {
+    prefix: String,
+    metric_names: HashMap<String, String>,
+    label_names: HashMap<String, String>,
+    drop_labels: HashSet<String>,
+    replace_chars: HashMap<char, String>,
+    sanitize: bool,
+}
+
+impl Naming {
+    pub fn new(cfg: &pb::MetricNaming) -> Self {
+        let replace_chars = cfg
+            .replace_chars
+            .iter()
+            .filter_map(|(from, to)| {
+                let mut chars = from.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some((c, to.clone())),
+                    _ => {
+                        log::warn!("metric naming: ignoring replacement of {from:?}, which is not a single character");
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Self {
+            prefix: cfg.prefix.clone(),
+            metric_names: cfg.metric_names.clone(),
+            label_names: cfg.label_names.clone(),
+            drop_labels: cfg.drop_labels.iter().cloned().collect(),
+            replace_chars,
+            sanitize: cfg.sanitize,
+        }
+    }
+
+    /// Rewrites the metrics, combining series that become identical.
+    pub fn apply(&self, metrics: Vec<CollectedMetric>) -> Vec<CollectedMetric> {
+        let mut out: Vec<CollectedMetric> = Vec::with_capacity(metrics.len());
+        let mut index: HashMap<Key, usize> = HashMap::with_capacity(metrics.len());
+        for metric in metrics {
+            let key = self.map_key(&metric.key);
+            if let Some(&idx) = index.get(&key) {
+                if merge(&mut out[idx].value, &metric.value) {
+                    continue;
+                }
+            } else {
+                index.insert(key.clone(), out.len());
+            }
+            out.push(CollectedMetric { key, ..metric });
+        }
+        out
+    }
+
+    fn map_key(&self, key: &Key) -> Key {
+        let labels: Vec<Label> = key
+            .labels()
+            .filter(|label| !self.drop_labels.contains(label.key()))
+            .map(|label| Label::new(self.map_label(label.key()), label.value().to_string()))
+            .collect();
+        Key::from_parts(self.map_name(key.name()), labels)
+    }
+
+    fn map_name(&self, name: &str) -> String {
+        if let Some(mapped) = self.metric_names.get(name) {
+            return mapped.clone();
+        }
+        let name = format!("{}{}", self.prefix, self.replace(name));
+        if self.sanitize {
+            sanitize(&name, true)
+        } else {
+            name
+        }
+    }
+
+    fn map_label(&self, name: &str) -> String {
+        if let Some(mapped) = self.label_names.get(name) {
+            return mapped.clone();
+        }
+        let name = self.replace(name);
+        if self.sanitize {
+            sanitize(&name, false)
+        } else {
+            name
+        }
+    }
+
+    fn replace(&self, name: &str) -> String {
+        let mut out = String::with_capacity(name.len());
+        for c in name.chars() {
+            match self.replace_chars.get(&c) {
+                Some(to) => out.push_str(to),
+                None => out.push(c),
+            }
+        }
+        out
+    }
+}
+
+/// Replaces characters that aren't valid in Prometheus metric names
+/// (or label names, which don't allow colons) with underscores.
+fn sanitize(name: &str, allow_colon: bool) -> String {
+    let mut out = String::with_capacity(name.len() + 1);
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        out.push('_');
+    }
+    for c in name.chars() {
+        let valid = c.is_ascii_alphanumeric() || c == '_' || (allow_colon && c == ':');
+        out.push(if valid { c } else { '_' });
+    }
+    out
+}
+
+/// Adds the value to an existing value of the same kind,
+/// reporting whether they could be combined.
+fn merge(into: &mut MetricValue, from: &MetricValue) -> bool {
+    match (into, from) {
+        (MetricValue::CounterU64(a), MetricValue::CounterU64(b)) => *a += b,
+        (MetricValue::CounterI64(a), MetricValue::CounterI64(b)) => *a += b,
+        (MetricValue::GaugeU64(a), MetricValue::GaugeU64(b)) => *a += b,
+        (MetricValue::GaugeI64(a), MetricValue::GaugeI64(b)) => *a += b,
+        (MetricValue::GaugeF64(a), MetricValue::GaugeF64(b)) => *a += b,
+        (MetricValue::Histogram(a), MetricValue::Histogram(b)) => {
+            let same_bounds = a.buckets.len() == b.buckets.len()
+                && a.buckets
+                    .iter()
+                    .zip(&b.buckets)
+                    .all(|(x, y)| x.upper_bound == y.upper_bound);
+            if !same_bounds {
+                return false;
+            }
+            for (x, y) in a.buckets.iter_mut().zip(&b.buckets) {
+                x.cumulative_count += y.cumulative_count;
+                if x.exemplar.is_none() {
+                    x.exemplar.clone_from(&y.exemplar);
+                }
+            }
+            a.sum += b.sum;
+            a.count += b.count;
+        }
+        _ => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn metric(name: &str, labels: &[(&'static str, &'static str)], value: u64) -> CollectedMetric {
+        let labels: Vec<Label> = labels.iter().map(|(k, v)| Label::new(*k, *v)).collect();
+        CollectedMetric {
+            key: Key::from_parts(name.to_string(), labels),
+            value: MetricValue::CounterU64(value),
+            registered_at: SystemTime::now(),
+        }
+    }
+
+    fn names(metrics: &[CollectedMetric]) -> Vec<(String, Vec<(String, String)>, MetricValue)> {
+        metrics
+            .iter()
+            .map(|m| {
+                let labels = m
+                    .key
+                    .labels()
+                    .map(|l| (l.key().to_string(), l.value().to_string()))
+                    .collect();
+                (m.key.name().to_string(), labels, m.value.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_naming() {
+        let naming = Naming::new(&pb::MetricNaming {
+            prefix: "acme.".into(),
+            metric_names: HashMap::from([("e_requests_total".into(), "http_requests".into())]),
+            label_names: HashMap::from([("service".into(), "app".into())]),
+            drop_labels: vec!["endpoint".into()],
+            replace_chars: HashMap::from([("-".into(), "_".into())]),
+            sanitize: true,
+        });
+
+        let metrics = naming.apply(vec![
+            metric(
+                "e_requests_total",
+                &[("service", "orders"), ("endpoint", "List")],
+                1,
+            ),
+            metric(
+                "e_requests_total",
+                &[("service", "orders"), ("endpoint", "Get")],
+                2,
+            ),
+            metric("my-counter", &[("http.code", "200")], 3),
+        ]);
+        let s = |v: &str| v.to_string();
+        assert_eq!(
+            names(&metrics),
+            [
+                (
+                    s("http_requests"),
+                    vec![(s("app"), s("orders"))],
+                    MetricValue::CounterU64(3)
+                ),
+                (
+                    s("acme_my_counter"),
+                    vec![(s("http_code"), s("200"))],
+                    MetricValue::CounterU64(3)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("e_requests_total", true), "e_requests_total");
+        assert_eq!(sanitize("ns:requests.total", true), "ns:requests_total");
+        assert_eq!(sanitize("ns:label", false), "ns_label");
+        assert_eq!(sanitize("5xx", false), "_5xx");
+    }
+}
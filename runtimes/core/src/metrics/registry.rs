@@ -1,8 +1,9 @@
 use crate::metrics::counter::{CounterOps, CounterSchemaBuilder};
 use crate::metrics::gauge::{GaugeOps, GaugeSchemaBuilder};
+use crate::metrics::histogram::{HistogramSchemaBuilder, HistogramValue};
 
 use super::system::SystemMetricsCollector;
-use super::{Counter, Gauge};
+use super::{Counter, Gauge, Histogram};
 use dashmap::DashMap;
 use malachite::base::num::basic::traits::One;
 use metrics::{Key, Label};
@@ -31,6 +32,19 @@ impl std::fmt::Debug for MetricStorage {
     }
 }
 
+struct HistogramStorage {
+    histogram: Histogram,
+    registered_at: SystemTime,
+}
+
+impl std::fmt::Debug for HistogramStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HistogramStorage")
+            .field("registered_at", &self.registered_at)
+            .finish()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum MetricValue {
     // Counter variants
@@ -41,6 +55,8 @@ pub enum MetricValue {
     GaugeU64(u64),
     GaugeI64(i64),
     GaugeF64(f64),
+
+    Histogram(HistogramValue),
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +69,7 @@ pub struct CollectedMetric {
 pub struct Registry {
     counters: DashMap<Key, MetricStorage>,
     gauges: DashMap<Key, MetricStorage>,
+    histograms: DashMap<Key, HistogramStorage>,
     system_metrics: SystemMetricsCollector,
     external_collectors: RwLock<Vec<Arc<dyn MetricsCollector>>>,
 }
@@ -62,6 +79,7 @@ impl std::fmt::Debug for Registry {
         f.debug_struct("Registry")
             .field("counters", &self.counters)
             .field("gauges", &self.gauges)
+            .field("histograms", &self.histograms)
             .field("system_metrics", &self.system_metrics)
             .finish()
     }
@@ -72,6 +90,7 @@ impl Registry {
         Self {
             counters: DashMap::new(),
             gauges: DashMap::new(),
+            histograms: DashMap::new(),
             system_metrics: SystemMetricsCollector::new(),
             external_collectors: RwLock::new(Vec::new()),
         }
@@ -145,6 +164,31 @@ impl Registry {
         Gauge::new(Arc::clone(&entry.atomic))
     }
 
+    /// Create a histogram with the given name, labels and bucket upper bounds.
+    /// The bounds are only used if the histogram doesn't exist yet.
+    pub fn get_or_create_histogram<'a>(
+        &self,
+        name: &str,
+        labels: impl IntoIterator<Item = (&'a str, &'a str)>,
+        bounds: &'static [f64],
+    ) -> Histogram {
+        let labels_vec: Vec<Label> = labels
+            .into_iter()
+            .map(|(k, v)| Label::new(k.to_string(), v.to_string()))
+            .collect();
+        let key = Key::from_parts(name.to_string(), labels_vec);
+
+        let entry = self
+            .histograms
+            .entry(key)
+            .or_insert_with(|| HistogramStorage {
+                histogram: Histogram::new(bounds),
+                registered_at: SystemTime::now(),
+            });
+
+        entry.histogram.clone()
+    }
+
     /// Create a counter schema builder for defining static and dynamic labels
     pub fn counter_schema<T>(self: &Arc<Self>, name: &str) -> CounterSchemaBuilder<T>
     where
@@ -163,6 +207,11 @@ impl Registry {
         GaugeSchemaBuilder::new(name.to_string(), Arc::clone(self))
     }
 
+    /// Create a histogram schema builder for defining static labels and buckets
+    pub fn histogram_schema(self: &Arc<Self>, name: &str) -> HistogramSchemaBuilder {
+        HistogramSchemaBuilder::new(name.to_string(), Arc::clone(self))
+    }
+
     pub fn collect(self: &Arc<Self>) -> Vec<CollectedMetric> {
         let mut collected_metrics = Vec::new();
 
@@ -196,6 +245,16 @@ impl Registry {
             });
         }
 
+        // Collect histograms
+        for entry in self.histograms.iter() {
+            let store = entry.value();
+            collected_metrics.push(CollectedMetric {
+                value: store.histogram.get(),
+                key: entry.key().clone(),
+                registered_at: store.registered_at,
+            });
+        }
+
         // Collect from external collectors (e.g., JS runtime)
         let collectors = self.external_collectors.read().expect("mutex poisoned");
         for collector in collectors.iter() {
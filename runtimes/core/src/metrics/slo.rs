@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Context;
+use metrics::{Key, Label};
+
+use crate::encore::runtime::v1 as pb;
+use crate::metrics::{CollectedMetric, MetricValue, MetricsCollector, Registry};
+use crate::names::EndpointName;
+
+/// The resolution requests are counted at.
+const BUCKET_SECS: u64 = 30;
+
+/// The windows burn rates are computed over, matching the
+/// multiwindow burn-rate alerts recommended by the Google SRE workbook.
+const WINDOWS: [(&str, u64); 4] = [
+    ("5m", 5 * 60),
+    ("30m", 30 * 60),
+    ("1h", 60 * 60),
+    ("6h", 6 * 60 * 60),
+];
+
+/// The number of buckets needed to cover the longest window.
+const NUM_BUCKETS: usize = (6 * 60 * 60 / BUCKET_SECS) as usize;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    index: u64,
+    total: u64,
+    failed: u64,
+    slow: u64,
+}
+
+/// Tracks requests against a service level objective, and exports
+/// how quickly the error budget is being consumed as e_slo_burn_rate.
+///
+/// A burn rate of 1 means the error budget is consumed exactly by the end
+/// of the SLO period; a burn rate of 14.4 over an hour consumes 2% of a
+/// 30 day budget.
+pub struct Tracker {
+    labels: Vec<Label>,
+    availability_target: f64,
+
+    /// The latency threshold and target, if latency is tracked.
+    latency: Option<(Duration, f64)>,
+
+    start: Instant,
+    registered_at: SystemTime,
+    buckets: Mutex<Vec<Bucket>>,
+}
+
+impl Tracker {
+    pub fn new(cfg: &pb::Slo, labels: &[(&str, &str)]) -> anyhow::Result<Self> {
+        let availability_target = valid_target(cfg.availability_target)
+            .context("availability target must be between 0 and 1")?;
+        let latency = match cfg.latency_threshold {
+            None => None,
+            Some(threshold) => {
+                let threshold = Duration::try_from(threshold)
+                    .ok()
+                    .filter(|d| !d.is_zero())
+                    .context("latency threshold must be positive")?;
+                let target = match cfg.latency_target {
+                    Some(target) => {
+                        valid_target(target).context("latency target must be between 0 and 1")?
+                    }
+                    None => availability_target,
+                };
+                Some((threshold, target))
+            }
+        };
+
+        Ok(Self {
+            labels: labels
+                .iter()
+                .map(|(k, v)| Label::new(k.to_string(), v.to_string()))
+                .collect(),
+            availability_target,
+            latency,
+            start: Instant::now(),
+            registered_at: SystemTime::now(),
+            buckets: Mutex::new(vec![Bucket::default(); NUM_BUCKETS]),
+        })
+    }
+
+    /// Creates a tracker and registers it with the registry, so its
+    /// burn rates are exported along with the other metrics.
+    pub fn register(
+        registry: &Registry,
+        cfg: &pb::Slo,
+        labels: &[(&str, &str)],
+    ) -> anyhow::Result<Arc<Self>> {
+        let tracker = Arc::new(Self::new(cfg, labels)?);
+        registry.register_collector(tracker.clone());
+        Ok(tracker)
+    }
+
+    /// Records a completed request.
+    pub fn record(&self, success: bool, duration: Duration) {
+        self.record_at(self.start.elapsed(), success, duration)
+    }
+
+    fn record_at(&self, elapsed: Duration, success: bool, duration: Duration) {
+        let index = elapsed.as_secs() / BUCKET_SECS;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = &mut buckets[(index % NUM_BUCKETS as u64) as usize];
+        if bucket.index != index {
+            *bucket = Bucket {
+                index,
+                ..Default::default()
+            };
+        }
+
+        bucket.total += 1;
+        if !success {
+            bucket.failed += 1;
+        }
+        if matches!(self.latency, Some((threshold, _)) if duration > threshold) {
+            bucket.slow += 1;
+        }
+    }
+
+    /// Computes the burn rate of each objective and window,
+    /// as (objective, window, burn rate).
+    fn burn_rates_at(&self, elapsed: Duration) -> Vec<(&'static str, &'static str, f64)> {
+        let now = elapsed.as_secs() / BUCKET_SECS;
+        let buckets = self.buckets.lock().unwrap();
+
+        let mut rates = Vec::with_capacity(WINDOWS.len() * 2);
+        for (window, secs) in WINDOWS {
+            let num = secs / BUCKET_SECS;
+            let (mut total, mut failed, mut slow) = (0, 0, 0);
+            for bucket in buckets.iter() {
+                if bucket.total > 0 && bucket.index <= now && now - bucket.index < num {
+                    total += bucket.total;
+                    failed += bucket.failed;
+                    slow += bucket.slow;
+                }
+            }
+
+            rates.push((
+                "availability",
+                window,
+                burn_rate(failed, total, self.availability_target),
+            ));
+            if let Some((_, target)) = self.latency {
+                rates.push(("latency", window, burn_rate(slow, total, target)));
+            }
+        }
+        rates
+    }
+}
+
+impl MetricsCollector for Tracker {
+    fn collect(&self) -> Vec<CollectedMetric> {
+        self.burn_rates_at(self.start.elapsed())
+            .into_iter()
+            .map(|(objective, window, rate)| {
+                let mut labels = self.labels.clone();
+                labels.push(Label::new("objective", objective));
+                labels.push(Label::new("window", window));
+                CollectedMetric {
+                    key: Key::from_parts("e_slo_burn_rate", labels),
+                    value: MetricValue::GaugeF64(rate),
+                    registered_at: self.registered_at,
+                }
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for Tracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracker")
+            .field("labels", &self.labels)
+            .field("availability_target", &self.availability_target)
+            .field("latency", &self.latency)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Creates trackers for the endpoint SLOs of the hosted services.
+pub fn endpoint_trackers(
+    hosted_services: &[pb::HostedService],
+    registry: &Registry,
+) -> anyhow::Result<HashMap<EndpointName, Arc<Tracker>>> {
+    let mut trackers = HashMap::new();
+    for svc in hosted_services {
+        for (endpoint, cfg) in &svc.endpoint_slos {
+            let name = EndpointName::new(svc.name.as_str(), endpoint.as_str());
+            let labels = [("service", name.service()), ("endpoint", name.endpoint())];
+            let tracker = Tracker::register(registry, cfg, &labels)
+                .with_context(|| format!("invalid slo for endpoint {name}"))?;
+            trackers.insert(name, tracker);
+        }
+    }
+    Ok(trackers)
+}
+
+fn valid_target(target: f64) -> Option<f64> {
+    (target > 0.0 && target < 1.0).then_some(target)
+}
+
+/// The rate the error budget is consumed at, relative to the rate that
+/// consumes it exactly over the SLO period.
+fn burn_rate(bad: u64, total: u64, target: f64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (bad as f64 / total as f64) / (1.0 - target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(latency_ms: Option<i32>) -> Tracker {
+        Tracker::new(
+            &pb::Slo {
+                availability_target: 0.99,
+                latency_threshold: latency_ms.map(|ms| prost_types::Duration {
+                    seconds: 0,
+                    nanos: ms * 1_000_000,
+                }),
+                latency_target: Some(0.9),
+            },
+            &[("service", "svc")],
+        )
+        .unwrap()
+    }
+
+    fn rate(rates: &[(&str, &str, f64)], objective: &str, window: &str) -> f64 {
+        let (_, _, rate) = rates
+            .iter()
+            .find(|(o, w, _)| *o == objective && *w == window)
+            .unwrap();
+        (rate * 1000.0).round() / 1000.0
+    }
+
+    #[test]
+    fn test_burn_rate() {
+        let t = tracker(Some(100));
+        let ok = Duration::from_millis(10);
+        let slow = Duration::from_millis(200);
+
+        // An hour ago: 100 requests, 10 failed.
+        let earlier = Duration::from_secs(60);
+        for i in 0..100 {
+            t.record_at(earlier, i >= 10, ok);
+        }
+
+        // Now: 100 requests, 1 failed and 20 slow.
+        let now = earlier + Duration::from_secs(60 * 60);
+        for i in 0..100 {
+            t.record_at(now, i >= 1, if i < 20 { slow } else { ok });
+        }
+
+        let rates = t.burn_rates_at(now);
+        assert_eq!(rate(&rates, "availability", "5m"), 1.0);
+        assert_eq!(rate(&rates, "availability", "1h"), 1.0);
+        assert_eq!(rate(&rates, "availability", "6h"), 5.5);
+        assert_eq!(rate(&rates, "latency", "5m"), 2.0);
+        assert_eq!(rate(&rates, "latency", "6h"), 1.0);
+    }
+
+    #[test]
+    fn test_burn_rate_expires() {
+        let t = tracker(None);
+        t.record_at(Duration::ZERO, false, Duration::ZERO);
+
+        let rates = t.burn_rates_at(Duration::ZERO);
+        assert_eq!(rate(&rates, "availability", "5m"), 100.0);
+        assert!(!rates.iter().any(|(o, _, _)| *o == "latency"));
+
+        // The bucket is reused once the longest window has passed.
+        let later = Duration::from_secs(6 * 60 * 60);
+        t.record_at(later, true, Duration::ZERO);
+        let rates = t.burn_rates_at(later);
+        assert_eq!(rate(&rates, "availability", "6h"), 0.0);
+        assert_eq!(rate(&rates, "availability", "5m"), 0.0);
+    }
+
+    #[test]
+    fn test_invalid_target() {
+        let cfg = pb::Slo {
+            availability_target: 1.0,
+            latency_threshold: None,
+            latency_target: None,
+        };
+        assert!(Tracker::new(&cfg, &[]).is_err());
+    }
+}
@@ -198,6 +198,51 @@ mod gauge_tests {
     }
 }
 
+#[cfg(test)]
+mod histogram_tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_and_exemplars() {
+        let manager = Manager::new();
+        let schema = manager
+            .registry()
+            .histogram_schema("test_histogram")
+            .static_labels([("service", "test_service")])
+            .buckets(&[0.1, 1.0])
+            .build();
+
+        let trace_id = crate::model::TraceId([1; 16]);
+        let histogram = schema.with([("code", "ok")]);
+        histogram.observe(0.05);
+        histogram.observe_traced(0.5, trace_id);
+        histogram.observe(5.0);
+
+        let collected = manager.collect_metrics();
+        let m = collected
+            .iter()
+            .find(|m| m.key.name() == "test_histogram")
+            .unwrap();
+        assert_eq!(m.key.labels().len(), 2);
+
+        let MetricValue::Histogram(value) = &m.value else {
+            panic!("Expected histogram value, got {:?}", m.value);
+        };
+        assert_eq!(value.count, 3);
+        assert!((value.sum - 5.55).abs() < 1e-9);
+
+        let counts: Vec<_> = value.buckets.iter().map(|b| b.cumulative_count).collect();
+        assert_eq!(counts, vec![1, 2, 3]);
+        assert!(value.buckets[2].upper_bound.is_infinite());
+
+        // Only the traced observation has an exemplar.
+        assert!(value.buckets[0].exemplar.is_none());
+        let exemplar = value.buckets[1].exemplar.as_ref().unwrap();
+        assert_eq!(exemplar.trace_id, trace_id.serialize_std());
+        assert_eq!(exemplar.value, 0.5);
+    }
+}
+
 #[cfg(test)]
 mod manager_tests {
     use super::*;
@@ -266,6 +311,33 @@ mod manager_tests {
             .find(|m| m.key.name() == "test_export_counter");
         assert!(metric.is_some());
     }
+
+    #[tokio::test]
+    async fn test_manager_with_naming() {
+        let mock_exporter = Arc::new(MockExporter::new());
+        let manager = Manager::new()
+            .with_exporter(mock_exporter.clone())
+            .with_naming(&crate::encore::runtime::v1::MetricNaming {
+                prefix: "acme_".into(),
+                ..Default::default()
+            });
+
+        manager
+            .registry()
+            .get_or_create_counter::<u64>("test_naming_counter", [])
+            .increment();
+        manager.collect_and_export().await;
+
+        // Names are only rewritten when exported.
+        let exported = mock_exporter.get_exported_metrics();
+        assert!(exported[0]
+            .iter()
+            .any(|m| m.key.name() == "acme_test_naming_counter"));
+        assert!(manager
+            .collect_metrics()
+            .iter()
+            .any(|m| m.key.name() == "test_naming_counter"));
+    }
 }
 
 #[cfg(test)]
@@ -137,6 +137,10 @@ pub struct Request {
     /// The externally-provided correlation ID, if any.
     pub ext_correlation_id: Option<String>,
 
+    /// The time by which the caller expects the request to complete, if any.
+    /// Propagated to outgoing calls and used to bound database queries.
+    pub deadline: Option<SystemTime>,
+
     /// True if the request originated from the Encore Platform.
     pub is_platform_request: bool,
 
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::azure;
+
+/// The OAuth resource for Azure Storage.
+const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+
+/// How long user delegation keys for signing URLs are valid for.
+/// Azure allows at most seven days, less a margin for clock skew.
+const DELEGATION_KEY_VALIDITY: Duration = Duration::from_secs(7 * 24 * 60 * 60 - 10 * 60);
+
+/// How to authenticate requests to the storage account.
+pub(super) enum Credential {
+    /// Signs requests with the storage account key.
+    SharedKey { key: Vec<u8> },
+    /// Authorizes requests with a shared access signature.
+    Sas(String),
+    /// Authorizes requests with an Entra ID token for a managed identity.
+    ManagedIdentity(ManagedIdentity),
+}
+
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credential::SharedKey { .. } => f.write_str("SharedKey"),
+            Credential::Sas(_) => f.write_str("Sas"),
+            Credential::ManagedIdentity(_) => f.write_str("ManagedIdentity"),
+        }
+    }
+}
+
+/// The parsed fields of a storage account connection string.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct ConnectionString {
+    pub account_name: Option<String>,
+    pub account_key: Option<String>,
+    pub sas: Option<String>,
+    pub blob_endpoint: Option<String>,
+}
+
+impl ConnectionString {
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let fields: HashMap<&str, &str> = s
+            .split(';')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .filter_map(|part| part.split_once('='))
+            .collect();
+
+        let blob_endpoint = match fields.get("BlobEndpoint") {
+            Some(endpoint) => Some(endpoint.to_string()),
+            None => fields.get("AccountName").map(|account| {
+                let protocol = fields
+                    .get("DefaultEndpointsProtocol")
+                    .copied()
+                    .unwrap_or("https");
+                let suffix = fields
+                    .get("EndpointSuffix")
+                    .copied()
+                    .unwrap_or("core.windows.net");
+                format!("{protocol}://{account}.blob.{suffix}")
+            }),
+        };
+
+        let cs = Self {
+            account_name: fields.get("AccountName").map(|s| s.to_string()),
+            account_key: fields.get("AccountKey").map(|s| s.to_string()),
+            sas: fields
+                .get("SharedAccessSignature")
+                .map(|s| s.trim_start_matches('?').to_string()),
+            blob_endpoint,
+        };
+        if cs.account_key.is_none() && cs.sas.is_none() {
+            anyhow::bail!("connection string has neither AccountKey nor SharedAccessSignature");
+        }
+        Ok(cs)
+    }
+}
+
+/// Computes the Shared Key signature string for a request.
+/// See https://learn.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key.
+pub(super) fn shared_key_string_to_sign(req: &reqwest::Request, account_name: &str) -> String {
+    let headers = req.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    };
+
+    // An empty body is signed as an empty Content-Length.
+    let content_length = match header("content-length") {
+        "0" => "",
+        len => len,
+    };
+
+    let mut s = String::new();
+    for part in [
+        req.method().as_str(),
+        header("content-encoding"),
+        header("content-language"),
+        content_length,
+        header("content-md5"),
+        header("content-type"),
+        "", // Date; x-ms-date is used instead.
+        header("if-modified-since"),
+        header("if-match"),
+        header("if-none-match"),
+        header("if-unmodified-since"),
+        header("range"),
+    ] {
+        s.push_str(part);
+        s.push('\n');
+    }
+
+    // Canonicalized headers.
+    let mut ms_headers: Vec<(&str, String)> = headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-ms-"))
+        .map(|(name, value)| {
+            let value = value.to_str().unwrap_or_default();
+            (
+                name.as_str(),
+                value.split_whitespace().collect::<Vec<_>>().join(" "),
+            )
+        })
+        .collect();
+    ms_headers.sort();
+    for (name, value) in ms_headers {
+        s.push_str(name);
+        s.push(':');
+        s.push_str(&value);
+        s.push('\n');
+    }
+
+    // Canonicalized resource.
+    s.push('/');
+    s.push_str(account_name);
+    s.push_str(req.url().path());
+
+    let mut params: Vec<(String, Vec<String>)> = Vec::new();
+    for (name, value) in req.url().query_pairs() {
+        let name = name.to_lowercase();
+        match params.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.push(value.into_owned()),
+            None => params.push((name, vec![value.into_owned()])),
+        }
+    }
+    params.sort();
+    for (name, mut values) in params {
+        values.sort();
+        s.push('\n');
+        s.push_str(&name);
+        s.push(':');
+        s.push_str(&values.join(","));
+    }
+
+    s
+}
+
+/// Signs the string with the given key, returning the base64-encoded signature.
+pub(super) fn sign(key: &[u8], s: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(s.as_bytes());
+    STANDARD.encode(mac.finalize().into_bytes())
+}
+
+/// Formats a time as used in shared access signatures.
+pub(super) fn sas_time(t: DateTime<Utc>) -> String {
+    t.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// A key for signing user delegation shared access signatures.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct UserDelegationKey {
+    pub signed_oid: String,
+    pub signed_tid: String,
+    pub signed_start: String,
+    pub signed_expiry: String,
+    pub signed_service: String,
+    pub signed_version: String,
+    pub value: String,
+}
+
+impl UserDelegationKey {
+    pub fn expiry(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.signed_expiry)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+/// Authenticates as a managed identity, caching the access token
+/// and the user delegation key used for signing URLs.
+pub(super) struct ManagedIdentity {
+    identity: azure::ManagedIdentity,
+    delegation_key: tokio::sync::Mutex<Option<UserDelegationKey>>,
+}
+
+impl ManagedIdentity {
+    pub fn new(client_id: Option<String>) -> Self {
+        Self {
+            identity: azure::ManagedIdentity::new(client_id, STORAGE_RESOURCE),
+            delegation_key: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Returns an access token for Azure Storage.
+    pub async fn token(&self, http: &reqwest::Client) -> anyhow::Result<String> {
+        self.identity.token(http).await
+    }
+
+    /// Returns a user delegation key that is valid until at least the given time.
+    /// The key is fetched using the given function if necessary.
+    pub async fn delegation_key<F, Fut>(
+        &self,
+        valid_until: DateTime<Utc>,
+        fetch: F,
+    ) -> anyhow::Result<UserDelegationKey>
+    where
+        F: FnOnce(DateTime<Utc>, DateTime<Utc>) -> Fut,
+        Fut: std::future::Future<Output = anyhow::Result<UserDelegationKey>>,
+    {
+        let mut guard = self.delegation_key.lock().await;
+        if let Some(key) = guard.as_ref() {
+            if key.expiry().is_some_and(|exp| exp >= valid_until) {
+                return Ok(key.clone());
+            }
+        }
+
+        let start = Utc::now();
+        let expiry = start + DELEGATION_KEY_VALIDITY;
+        let key = fetch(start, expiry).await?;
+        *guard = Some(key.clone());
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_connection_string() {
+        let cs = ConnectionString::parse(
+            "DefaultEndpointsProtocol=https;AccountName=myacct;AccountKey=a2V5;EndpointSuffix=core.windows.net",
+        )
+        .unwrap();
+        assert_eq!(
+            cs,
+            ConnectionString {
+                account_name: Some("myacct".into()),
+                account_key: Some("a2V5".into()),
+                sas: None,
+                blob_endpoint: Some("https://myacct.blob.core.windows.net".into()),
+            }
+        );
+
+        let cs = ConnectionString::parse(
+            "BlobEndpoint=https://myacct.blob.core.windows.net/;SharedAccessSignature=?sv=2021-08-06&sig=abc%3D",
+        )
+        .unwrap();
+        assert_eq!(cs.sas.as_deref(), Some("sv=2021-08-06&sig=abc%3D"));
+        assert_eq!(
+            cs.blob_endpoint.as_deref(),
+            Some("https://myacct.blob.core.windows.net/")
+        );
+
+        assert!(ConnectionString::parse("AccountName=myacct").is_err());
+    }
+
+    #[test]
+    fn test_shared_key_string_to_sign() {
+        let client = reqwest::Client::new();
+        let req = client
+            .put("https://myacct.blob.core.windows.net/photos/a%20b.jpg?comp=block&blockid=MDA%3D")
+            .header("content-length", "11")
+            .header("content-type", "image/jpeg")
+            .header("x-ms-version", "2021-08-06")
+            .header("x-ms-date", "Mon, 01 Jan 2024 00:00:00 GMT")
+            .header("x-ms-blob-type", "BlockBlob")
+            .header("if-none-match", "*")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            shared_key_string_to_sign(&req, "myacct"),
+            "PUT\n\n\n11\n\nimage/jpeg\n\n\n\n*\n\n\n\
+             x-ms-blob-type:BlockBlob\n\
+             x-ms-date:Mon, 01 Jan 2024 00:00:00 GMT\n\
+             x-ms-version:2021-08-06\n\
+             /myacct/photos/a%20b.jpg\nblockid:MDA=\ncomp:block"
+        );
+    }
+}
@@ -0,0 +1,663 @@
+use async_stream::try_stream;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::encore::runtime::v1 as pb;
+use crate::objects::{
+    self, unquote_etag, AttrsOptions, DeleteOptions, DownloadOptions, DownloadUrlOptions, Error,
+    ExistsOptions, ListEntry, ListOptions, ObjectAttrs, Preconditions, PublicUrlError,
+    UploadUrlOptions,
+};
+use crate::{CloudName, EncoreName};
+
+use super::{check_status, error_code, Client};
+
+const CHUNK_SIZE: usize = 8_388_608; // 8 Mebibytes
+
+#[derive(Debug)]
+pub struct Bucket {
+    client: Arc<Client>,
+    encore_name: EncoreName,
+    cloud_name: CloudName,
+    public_base_url: Option<String>,
+    key_prefix: Option<String>,
+}
+
+impl Bucket {
+    pub(super) fn new(client: Arc<Client>, cfg: &pb::Bucket) -> Self {
+        Self {
+            client,
+            encore_name: cfg.encore_name.clone().into(),
+            cloud_name: cfg.cloud_name.clone().into(),
+            public_base_url: cfg.public_base_url.clone(),
+            key_prefix: cfg.key_prefix.clone(),
+        }
+    }
+
+    /// Computes the object name, including the key prefix if present.
+    fn obj_name<'a>(&'_ self, name: Cow<'a, str>) -> Cow<'a, str> {
+        match &self.key_prefix {
+            Some(prefix) => {
+                let mut key = prefix.to_owned();
+                key.push_str(&name);
+                Cow::Owned(key)
+            }
+            None => name,
+        }
+    }
+
+    /// Returns the name with the key prefix stripped, if present.
+    fn strip_prefix<'a>(&'_ self, name: Cow<'a, str>) -> Cow<'a, str> {
+        match &self.key_prefix {
+            Some(prefix) => name
+                .as_ref()
+                .strip_prefix(prefix)
+                .map(|s| Cow::Owned(s.to_string()))
+                .unwrap_or(name),
+            None => name,
+        }
+    }
+
+    /// Returns the URL of the blob with the given object name.
+    async fn blob_url(&self, name: &str, version: Option<&str>) -> Result<url::Url, Error> {
+        let account = self.client.account().await?;
+        let cloud_name = self.obj_name(Cow::Borrowed(name));
+        let mut url = account.url(&self.cloud_name, Some(&cloud_name));
+        if let Some(version) = version {
+            url.query_pairs_mut().append_pair("versionid", version);
+        }
+        Ok(url)
+    }
+}
+
+impl objects::BucketImpl for Bucket {
+    fn name(&self) -> &EncoreName {
+        &self.encore_name
+    }
+
+    fn object(self: Arc<Self>, name: String) -> Arc<dyn objects::ObjectImpl> {
+        Arc::new(Object {
+            bkt: self.clone(),
+            name,
+        })
+    }
+
+    fn list(
+        self: Arc<Self>,
+        options: ListOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::ListStream, objects::Error>> + Send + 'static>>
+    {
+        Box::pin(async move {
+            let account = self.client.account().await?;
+            let mut base_url = account.url(&self.cloud_name, None);
+            {
+                let mut query = base_url.query_pairs_mut();
+                query
+                    .append_pair("restype", "container")
+                    .append_pair("comp", "list");
+
+                let prefix = format!(
+                    "{}{}",
+                    self.key_prefix.as_deref().unwrap_or_default(),
+                    options.prefix.as_deref().unwrap_or_default()
+                );
+                if !prefix.is_empty() {
+                    query.append_pair("prefix", &prefix);
+                }
+                if let Some(delimiter) = &options.delimiter {
+                    query.append_pair("delimiter", delimiter);
+                }
+                let page_size = options.limit.map_or(5000, |limit| limit.clamp(1, 5000));
+                query.append_pair("maxresults", &page_size.to_string());
+            }
+
+            let mut total_seen = 0;
+            let s: objects::ListStream = Box::new(try_stream! {
+                let mut marker: Option<String> = None;
+                'PageLoop:
+                loop {
+                    let mut url = base_url.clone();
+                    if let Some(marker) = &marker {
+                        url.query_pairs_mut().append_pair("marker", marker);
+                    }
+
+                    let resp = self.client.send(Method::GET, url, HeaderMap::new(), None).await?;
+                    let body = check_status(resp).await?.text().await.map_err(|e| Error::Other(e.into()))?;
+                    let page = parse_list_page(&body)?;
+
+                    // The blob service has no equivalent of a start offset,
+                    // so entries up to and including the cursor are skipped here.
+                    for item in page.blobs.items {
+                        let entry = match item {
+                            ListItem::Blob(blob) => ListEntry {
+                                name: self.strip_prefix(Cow::Owned(blob.name)).into_owned(),
+                                size: blob.properties.content_length,
+                                etag: unquote_etag(&blob.properties.etag).to_string(),
+                                is_prefix: false,
+                            },
+                            ListItem::BlobPrefix(prefix) => ListEntry::prefix(
+                                self.strip_prefix(Cow::Owned(prefix.name)).into_owned(),
+                            ),
+                        };
+                        if !options.after_cursor(&entry.name) {
+                            continue;
+                        }
+
+                        total_seen += 1;
+                        if let Some(limit) = options.limit {
+                            if total_seen > limit {
+                                // We've reached the limit, stop the stream.
+                                break 'PageLoop;
+                            }
+                        }
+
+                        yield entry;
+                    }
+
+                    match page.next_marker {
+                        Some(next) if !next.is_empty() => marker = Some(next),
+                        _ => break,
+                    }
+                }
+            });
+
+            Ok(s)
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Object {
+    bkt: Arc<Bucket>,
+    name: String,
+}
+
+impl objects::ObjectImpl for Object {
+    fn bucket_name(&self) -> &EncoreName {
+        &self.bkt.encore_name
+    }
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn attrs(
+        self: Arc<Self>,
+        options: AttrsOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<ObjectAttrs, Error>> + Send>> {
+        Box::pin(async move {
+            let url = self
+                .bkt
+                .blob_url(&self.name, options.version.as_deref())
+                .await?;
+            let resp = self
+                .bkt
+                .client
+                .send(Method::HEAD, url, HeaderMap::new(), None)
+                .await?;
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Err(Error::NotFound);
+            }
+            let resp = check_status(resp).await?;
+
+            let headers = resp.headers();
+            Ok(ObjectAttrs {
+                name: self.name.clone(),
+                version: header(headers, "x-ms-version-id").map(str::to_string),
+                size: header(headers, "content-length")
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or_default(),
+                content_type: header(headers, "content-type").map(str::to_string),
+                etag: parse_etag(headers),
+                crc32c: None,
+            })
+        })
+    }
+
+    fn signed_upload_url(
+        self: Arc<Self>,
+        options: UploadUrlOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send>> {
+        Box::pin(async move {
+            let obj_name = self.bkt.obj_name(Cow::Borrowed(&self.name));
+            self.bkt
+                .client
+                .signed_url(&self.bkt.cloud_name, &obj_name, "cw", options.ttl)
+                .await
+        })
+    }
+
+    fn signed_download_url(
+        self: Arc<Self>,
+        options: DownloadUrlOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send>> {
+        Box::pin(async move {
+            let obj_name = self.bkt.obj_name(Cow::Borrowed(&self.name));
+            self.bkt
+                .client
+                .signed_url(&self.bkt.cloud_name, &obj_name, "r", options.ttl)
+                .await
+        })
+    }
+
+    fn exists(
+        self: Arc<Self>,
+        options: ExistsOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, Error>> + Send>> {
+        Box::pin(async move {
+            let url = self
+                .bkt
+                .blob_url(&self.name, options.version.as_deref())
+                .await?;
+            let resp = self
+                .bkt
+                .client
+                .send(Method::HEAD, url, HeaderMap::new(), None)
+                .await?;
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Ok(false);
+            }
+            check_status(resp).await?;
+            Ok(true)
+        })
+    }
+
+    fn upload(
+        self: Arc<Self>,
+        mut data: Box<dyn AsyncRead + Unpin + Send + Sync + 'static>,
+        options: objects::UploadOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<ObjectAttrs, Error>> + Send>> {
+        Box::pin(async move {
+            let url = self.bkt.blob_url(&self.name, None).await?;
+            let precond = options.preconditions.as_ref().map(Preconditions::from);
+
+            let mut headers = HeaderMap::new();
+            if let Some(content_type) = &options.content_type {
+                headers.insert(
+                    "x-ms-blob-content-type",
+                    HeaderValue::from_str(content_type).map_err(|_| Error::InvalidArgument)?,
+                );
+            }
+            if let Some(precond) = &precond {
+                precondition_headers(&mut headers, precond)?;
+            }
+
+            let (first_chunk, complete) = read_chunk(&mut data).await?;
+            let (resp, total_size) = if complete {
+                // The file is small; do a single Put Blob request.
+                let chunk = first_chunk.freeze();
+                let total_size = chunk.len() as u64;
+                headers.insert("x-ms-blob-type", HeaderValue::from_static("BlockBlob"));
+                headers.insert(
+                    "content-md5",
+                    HeaderValue::from_str(&STANDARD.encode(md5::compute(&chunk).as_ref()))
+                        .expect("base64 is a valid header value"),
+                );
+                let resp = self
+                    .bkt
+                    .client
+                    .send(Method::PUT, url, headers, Some(chunk))
+                    .await?;
+                (resp, total_size)
+            } else {
+                // Large file; upload it in blocks and commit them at the end.
+                // Uncommitted blocks are garbage collected by the service,
+                // so there's nothing to clean up if the upload fails.
+                let mut block_ids = Vec::new();
+                let mut total_size = 0;
+                let mut chunk = first_chunk;
+                while !chunk.is_empty() {
+                    let block_id = block_id(block_ids.len());
+                    total_size += chunk.len() as u64;
+                    self.put_block(&url, &block_id, chunk.freeze()).await?;
+                    block_ids.push(block_id);
+                    chunk = read_chunk(&mut data).await?.0;
+                }
+
+                let mut url = url;
+                url.query_pairs_mut().append_pair("comp", "blocklist");
+                let resp = self
+                    .bkt
+                    .client
+                    .send(Method::PUT, url, headers, Some(block_list(&block_ids)))
+                    .await?;
+                (resp, total_size)
+            };
+
+            let resp = map_upload_resp(resp, precond.as_ref()).await?;
+            Ok(ObjectAttrs {
+                name: self.name.clone(),
+                version: header(resp.headers(), "x-ms-version-id").map(str::to_string),
+                size: total_size,
+                content_type: options.content_type,
+                etag: parse_etag(resp.headers()),
+                crc32c: None,
+            })
+        })
+    }
+
+    fn download(
+        self: Arc<Self>,
+        options: DownloadOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::DownloadStream, objects::Error>> + Send>> {
+        Box::pin(async move {
+            let url = self
+                .bkt
+                .blob_url(&self.name, options.version.as_deref())
+                .await?;
+            let mut headers = HeaderMap::new();
+            if let Some(precond) = &options.preconditions {
+                precondition_headers(&mut headers, precond)?;
+            }
+            if let Some(range) = options.range {
+                headers.insert(
+                    "x-ms-range",
+                    HeaderValue::from_str(&range.header()?).expect("ranges are valid headers"),
+                );
+            }
+
+            let resp = self
+                .bkt
+                .client
+                .send(Method::GET, url, headers, None)
+                .await?;
+            match resp.status() {
+                StatusCode::NOT_FOUND => return Err(Error::NotFound),
+                // A matching If-None-Match is reported as Not Modified.
+                StatusCode::NOT_MODIFIED | StatusCode::PRECONDITION_FAILED => {
+                    return Err(Error::PreconditionFailed)
+                }
+                StatusCode::RANGE_NOT_SATISFIABLE => return Err(Error::InvalidArgument),
+                _ => {}
+            }
+            let resp = check_status(resp).await?;
+
+            let result = resp
+                .bytes_stream()
+                .map(|chunk| chunk.map_err(|e| Error::Other(e.into())));
+            let result: objects::DownloadStream = Box::pin(result);
+            Ok(result)
+        })
+    }
+
+    fn delete(
+        self: Arc<Self>,
+        options: DeleteOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Box::pin(async move {
+            let url = self
+                .bkt
+                .blob_url(&self.name, options.version.as_deref())
+                .await?;
+            let mut headers = HeaderMap::new();
+            if let Some(precond) = &options.preconditions {
+                precondition_headers(&mut headers, precond)?;
+            }
+
+            let resp = self
+                .bkt
+                .client
+                .send(Method::DELETE, url, headers, None)
+                .await?;
+            match resp.status() {
+                StatusCode::NOT_FOUND => Err(Error::NotFound),
+                StatusCode::PRECONDITION_FAILED => Err(Error::PreconditionFailed),
+                _ => check_status(resp).await.map(|_| ()),
+            }
+        })
+    }
+
+    fn public_url(&self) -> Result<String, PublicUrlError> {
+        let Some(base_url) = self.bkt.public_base_url.clone() else {
+            return Err(PublicUrlError::PrivateBucket);
+        };
+
+        let url = objects::public_url(base_url, &self.name);
+        Ok(url)
+    }
+}
+
+impl Object {
+    async fn put_block(&self, url: &url::Url, block_id: &str, chunk: Bytes) -> Result<(), Error> {
+        let mut url = url.clone();
+        url.query_pairs_mut()
+            .append_pair("comp", "block")
+            .append_pair("blockid", block_id);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-md5",
+            HeaderValue::from_str(&STANDARD.encode(md5::compute(&chunk).as_ref()))
+                .expect("base64 is a valid header value"),
+        );
+        let resp = self
+            .bkt
+            .client
+            .send(Method::PUT, url, headers, Some(chunk))
+            .await?;
+        map_upload_resp(resp, None).await.map(|_| ())
+    }
+}
+
+/// Reads up to CHUNK_SIZE bytes from the reader.
+/// It reports whether the end of the data was reached.
+async fn read_chunk<R: AsyncRead + Unpin + ?Sized>(
+    reader: &mut R,
+) -> Result<(BytesMut, bool), Error> {
+    // Use an initial capacity of 10KiB.
+    let mut buf = BytesMut::with_capacity(10 * 1024);
+    while buf.len() < CHUNK_SIZE {
+        if buf.len() == buf.capacity() {
+            buf.reserve(buf.capacity());
+        }
+
+        let n = reader
+            .read_buf(&mut buf)
+            .await
+            .map_err(|e| Error::Other(anyhow::anyhow!("unable to read from data source: {}", e)))?;
+        if n == 0 {
+            return Ok((buf, true));
+        }
+    }
+    Ok((buf, false))
+}
+
+/// Returns the block id for the block with the given index.
+/// All block ids of a blob must have the same length.
+fn block_id(idx: usize) -> String {
+    STANDARD.encode(format!("{idx:08}"))
+}
+
+/// Returns the request body for committing the given blocks.
+fn block_list(block_ids: &[String]) -> Bytes {
+    let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+    for id in block_ids {
+        body.push_str("<Latest>");
+        body.push_str(id);
+        body.push_str("</Latest>");
+    }
+    body.push_str("</BlockList>");
+    Bytes::from(body)
+}
+
+/// Adds the conditional request headers for the preconditions.
+fn precondition_headers(headers: &mut HeaderMap, precond: &Preconditions) -> Result<(), Error> {
+    for (name, etag) in [
+        ("if-match", &precond.if_match),
+        ("if-none-match", &precond.if_none_match),
+    ] {
+        if let Some(etag) = etag {
+            let value = match etag.as_str() {
+                "*" => "*".to_string(),
+                etag => format!("\"{}\"", unquote_etag(etag)),
+            };
+            headers.insert(
+                name,
+                HeaderValue::from_str(&value).map_err(|_| Error::InvalidArgument)?,
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn map_upload_resp(
+    resp: reqwest::Response,
+    precond: Option<&Preconditions>,
+) -> Result<reqwest::Response, Error> {
+    match resp.status() {
+        StatusCode::PRECONDITION_FAILED => Err(Error::PreconditionFailed),
+        // Writing to an existing blob with If-None-Match: * is reported as a conflict.
+        StatusCode::CONFLICT
+            if precond.is_some_and(|p| p.if_none_match.as_deref() == Some("*"))
+                && error_code(&resp) == "BlobAlreadyExists" =>
+        {
+            Err(Error::PreconditionFailed)
+        }
+        // The uploaded data doesn't match the checksum sent along with it.
+        StatusCode::BAD_REQUEST if error_code(&resp) == "Md5Mismatch" => {
+            Err(Error::ChecksumMismatch)
+        }
+        _ => check_status(resp).await,
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn parse_etag(headers: &HeaderMap) -> String {
+    header(headers, "etag")
+        .map(|etag| unquote_etag(etag).to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+struct ListPage {
+    #[serde(rename = "Blobs", default)]
+    blobs: ListItems,
+    #[serde(rename = "NextMarker", default)]
+    next_marker: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ListItems {
+    #[serde(rename = "$value", default)]
+    items: Vec<ListItem>,
+}
+
+#[derive(Debug, Deserialize)]
+enum ListItem {
+    Blob(ListBlob),
+    BlobPrefix(ListBlobPrefix),
+}
+
+#[derive(Debug, Deserialize)]
+struct ListBlob {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Properties")]
+    properties: ListBlobProperties,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListBlobProperties {
+    #[serde(rename = "Content-Length", default)]
+    content_length: u64,
+    #[serde(rename = "Etag", default)]
+    etag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListBlobPrefix {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+fn parse_list_page(body: &str) -> Result<ListPage, Error> {
+    quick_xml::de::from_str(body)
+        .map_err(|e| Error::Other(anyhow::anyhow!("invalid list blobs response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list_page() {
+        let page = parse_list_page(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<EnumerationResults ServiceEndpoint="https://myacct.blob.core.windows.net/" ContainerName="photos">
+  <Prefix>2024/</Prefix>
+  <MaxResults>2</MaxResults>
+  <Delimiter>/</Delimiter>
+  <Blobs>
+    <Blob>
+      <Name>2024/a.jpg</Name>
+      <Properties>
+        <Last-Modified>Mon, 01 Jan 2024 00:00:00 GMT</Last-Modified>
+        <Etag>0x8DC0000000000A</Etag>
+        <Content-Length>1024</Content-Length>
+        <Content-Type>image/jpeg</Content-Type>
+      </Properties>
+    </Blob>
+    <BlobPrefix>
+      <Name>2024/b/</Name>
+    </BlobPrefix>
+  </Blobs>
+  <NextMarker>2!12!abc</NextMarker>
+</EnumerationResults>"#,
+        )
+        .unwrap();
+
+        assert_eq!(page.blobs.items.len(), 2);
+        match &page.blobs.items[0] {
+            ListItem::Blob(blob) => {
+                assert_eq!(blob.name, "2024/a.jpg");
+                assert_eq!(blob.properties.content_length, 1024);
+                assert_eq!(blob.properties.etag, "0x8DC0000000000A");
+            }
+            other => panic!("expected blob, got {other:?}"),
+        }
+        assert!(matches!(&page.blobs.items[1], ListItem::BlobPrefix(p) if p.name == "2024/b/"));
+        assert_eq!(page.next_marker.as_deref(), Some("2!12!abc"));
+
+        let page = parse_list_page(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<EnumerationResults ContainerName="photos"><Blobs /><NextMarker /></EnumerationResults>"#,
+        )
+        .unwrap();
+        assert!(page.blobs.items.is_empty());
+        assert!(page.next_marker.unwrap_or_default().is_empty());
+    }
+
+    #[test]
+    fn test_block_id() {
+        // Block ids must all have the same length.
+        assert_eq!(block_id(0).len(), block_id(49_999).len());
+        assert_eq!(block_id(1), "MDAwMDAwMDE=");
+    }
+
+    #[test]
+    fn test_precondition_headers() {
+        let mut headers = HeaderMap::new();
+        precondition_headers(
+            &mut headers,
+            &Preconditions {
+                if_match: Some("0x8DC".into()),
+                if_none_match: Some("*".into()),
+            },
+        )
+        .unwrap();
+        assert_eq!(headers.get("if-match").unwrap(), "\"0x8DC\"");
+        assert_eq!(headers.get("if-none-match").unwrap(), "*");
+    }
+}
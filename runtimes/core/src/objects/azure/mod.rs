@@ -0,0 +1,469 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http::header::{AUTHORIZATION, CONTENT_LENGTH};
+use http::{HeaderMap, HeaderValue, Method};
+
+use crate::encore::runtime::v1 as pb;
+use crate::objects;
+use crate::objects::azure::auth::{ConnectionString, Credential, ManagedIdentity};
+use crate::objects::azure::bucket::Bucket;
+use crate::objects::Error;
+use crate::secrets::Secret;
+
+mod auth;
+mod bucket;
+
+/// The Blob Storage REST API version to use.
+const API_VERSION: &str = "2021-08-06";
+
+#[derive(Debug)]
+pub struct Cluster {
+    client: Arc<Client>,
+}
+
+impl Cluster {
+    pub fn new(cfg: pb::bucket_cluster::AzureBlob, connection_string: Option<Secret>) -> Self {
+        let client = Arc::new(Client::new(cfg, connection_string));
+        Self { client }
+    }
+}
+
+impl objects::ClusterImpl for Cluster {
+    fn bucket(self: Arc<Self>, cfg: &pb::Bucket) -> Arc<dyn objects::BucketImpl + 'static> {
+        Arc::new(Bucket::new(self.client.clone(), cfg))
+    }
+}
+
+/// A client for the Azure Blob Storage REST API.
+/// The account credentials are resolved on first use.
+struct Client {
+    cfg: pb::bucket_cluster::AzureBlob,
+    connection_string: Option<Secret>,
+    http: reqwest::Client,
+    account: tokio::sync::OnceCell<Account>,
+}
+
+impl Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AzureBlobClient")
+            .field("account_name", &self.cfg.account_name)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct Account {
+    name: String,
+    endpoint: url::Url,
+    credential: Credential,
+}
+
+impl Account {
+    /// Returns the URL of a container, or a blob within it.
+    fn url(&self, container: &str, blob: Option<&str>) -> url::Url {
+        let mut url = self.endpoint.clone();
+        {
+            let mut segments = url
+                .path_segments_mut()
+                .expect("endpoint is a valid base url");
+            segments.pop_if_empty().push(container);
+            if let Some(blob) = blob {
+                segments.extend(blob.split('/'));
+            }
+        }
+        url
+    }
+}
+
+impl Client {
+    fn new(cfg: pb::bucket_cluster::AzureBlob, connection_string: Option<Secret>) -> Self {
+        Self {
+            cfg,
+            connection_string,
+            http: crate::egress::client(),
+            account: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    async fn account(&self) -> Result<&Account, Error> {
+        self.account
+            .get_or_try_init(|| async { self.resolve_account() })
+            .await
+            .map_err(Error::Internal)
+    }
+
+    fn resolve_account(&self) -> anyhow::Result<Account> {
+        use pb::bucket_cluster::azure_blob::Credentials;
+
+        let mut name = self.cfg.account_name.clone();
+        let mut endpoint = self.cfg.endpoint.clone();
+        let credential = match (&self.cfg.credentials, &self.connection_string) {
+            (Some(Credentials::ConnectionString(_)), Some(secret)) => {
+                let cs = secret
+                    .get()
+                    .context("unable to resolve azure connection string")?;
+                let cs = std::str::from_utf8(cs).context("azure connection string is not utf-8")?;
+                let cs = ConnectionString::parse(cs).context("invalid azure connection string")?;
+
+                if let Some(account_name) = cs.account_name {
+                    name = account_name;
+                }
+                if endpoint.is_none() {
+                    endpoint = cs.blob_endpoint;
+                }
+                match (cs.account_key, cs.sas) {
+                    (Some(key), _) => Credential::SharedKey {
+                        key: STANDARD
+                            .decode(key)
+                            .context("invalid account key in azure connection string")?,
+                    },
+                    (None, Some(sas)) => Credential::Sas(sas),
+                    (None, None) => unreachable!("validated when parsing"),
+                }
+            }
+            (Some(Credentials::ConnectionString(_)), None) => {
+                anyhow::bail!("missing azure connection string")
+            }
+            (Some(Credentials::ManagedIdentity(mi)), _) => {
+                Credential::ManagedIdentity(ManagedIdentity::new(mi.client_id.clone()))
+            }
+            (None, _) => Credential::ManagedIdentity(ManagedIdentity::new(None)),
+        };
+
+        if name.is_empty() {
+            anyhow::bail!("missing azure storage account name");
+        }
+        let endpoint = endpoint.unwrap_or_else(|| format!("https://{name}.blob.core.windows.net"));
+        let endpoint = url::Url::parse(&endpoint)
+            .with_context(|| format!("invalid azure blob endpoint {endpoint:?}"))?;
+        if endpoint.cannot_be_a_base() {
+            anyhow::bail!("invalid azure blob endpoint {endpoint:?}");
+        }
+
+        Ok(Account {
+            name,
+            endpoint,
+            credential,
+        })
+    }
+
+    /// Sends an authorized request to the storage account.
+    async fn send(
+        &self,
+        method: Method,
+        url: url::Url,
+        mut headers: HeaderMap,
+        body: Option<Bytes>,
+    ) -> Result<reqwest::Response, Error> {
+        let account = self.account().await?;
+
+        headers.insert("x-ms-version", HeaderValue::from_static(API_VERSION));
+        headers.insert(
+            "x-ms-date",
+            HeaderValue::from_str(&httpdate::fmt_http_date(SystemTime::now()))
+                .expect("http dates are valid header values"),
+        );
+        let mut req = self.http.request(method, url);
+        if let Some(body) = body {
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+            req = req.body(body);
+        }
+        let mut req = req
+            .headers(headers)
+            .build()
+            .map_err(|e| Error::Internal(e.into()))?;
+
+        match &account.credential {
+            Credential::SharedKey { key } => {
+                let signature =
+                    auth::sign(key, &auth::shared_key_string_to_sign(&req, &account.name));
+                let value = format!("SharedKey {}:{}", account.name, signature);
+                req.headers_mut().insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&value).map_err(|e| Error::Internal(e.into()))?,
+                );
+            }
+            Credential::Sas(sas) => {
+                let url = req.url_mut();
+                let query = match url.query() {
+                    Some(q) if !q.is_empty() => format!("{q}&{sas}"),
+                    _ => sas.clone(),
+                };
+                url.set_query(Some(&query));
+            }
+            Credential::ManagedIdentity(mi) => {
+                let token = mi.token(&self.http).await.map_err(Error::Other)?;
+                req.headers_mut().insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Bearer {token}"))
+                        .map_err(|e| Error::Internal(e.into()))?,
+                );
+            }
+        }
+
+        self.http
+            .execute(req)
+            .await
+            .map_err(|e| Error::Other(e.into()))
+    }
+
+    /// Returns a URL for the blob carrying a shared access signature
+    /// with the given permissions, valid for the given duration.
+    async fn signed_url(
+        &self,
+        container: &str,
+        blob: &str,
+        permissions: &str,
+        ttl: Duration,
+    ) -> Result<String, Error> {
+        let account = self.account().await?;
+        let now = Utc::now();
+        // Allow for clock skew between us and the client.
+        let start = now - chrono::Duration::minutes(5);
+        let mut expiry =
+            now + chrono::Duration::from_std(ttl).map_err(|_| Error::InvalidArgument)?;
+
+        let protocol = match account.endpoint.scheme() {
+            "https" => "https",
+            _ => "https,http",
+        };
+        let resource = format!("/blob/{}/{}/{}", account.name, container, blob);
+
+        let mut params: Vec<(&str, String)> = Vec::new();
+        let (key, string_to_sign) = match &account.credential {
+            Credential::SharedKey { key } => {
+                let string_to_sign = service_sas_string_to_sign(
+                    permissions,
+                    &auth::sas_time(start),
+                    &auth::sas_time(expiry),
+                    &resource,
+                    protocol,
+                );
+                (key.clone(), string_to_sign)
+            }
+            Credential::ManagedIdentity(mi) => {
+                let key = mi
+                    .delegation_key(expiry, |start, expiry| {
+                        self.fetch_delegation_key(account, start, expiry)
+                    })
+                    .await
+                    .map_err(Error::Other)?;
+                // The signature can't outlive the key.
+                if let Some(key_expiry) = key.expiry() {
+                    expiry = expiry.min(key_expiry);
+                }
+                let string_to_sign = user_delegation_sas_string_to_sign(
+                    permissions,
+                    &auth::sas_time(start),
+                    &auth::sas_time(expiry),
+                    &resource,
+                    &key,
+                    protocol,
+                );
+                params.extend([
+                    ("skoid", key.signed_oid.clone()),
+                    ("sktid", key.signed_tid.clone()),
+                    ("skt", key.signed_start.clone()),
+                    ("ske", key.signed_expiry.clone()),
+                    ("sks", key.signed_service.clone()),
+                    ("skv", key.signed_version.clone()),
+                ]);
+                let key = STANDARD
+                    .decode(&key.value)
+                    .map_err(|e| Error::Internal(e.into()))?;
+                (key, string_to_sign)
+            }
+            Credential::Sas(_) => {
+                return Err(Error::Other(anyhow::anyhow!(
+                    "signed urls require an account key or a managed identity"
+                )));
+            }
+        };
+
+        let mut url = account.url(container, Some(blob));
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("sv", API_VERSION)
+                .append_pair("sr", "b")
+                .append_pair("sp", permissions)
+                .append_pair("st", &auth::sas_time(start))
+                .append_pair("se", &auth::sas_time(expiry))
+                .append_pair("spr", protocol);
+            for (name, value) in &params {
+                query.append_pair(name, value);
+            }
+            query.append_pair("sig", &auth::sign(&key, &string_to_sign));
+        }
+        Ok(url.to_string())
+    }
+
+    async fn fetch_delegation_key(
+        &self,
+        account: &Account,
+        start: DateTime<Utc>,
+        expiry: DateTime<Utc>,
+    ) -> anyhow::Result<auth::UserDelegationKey> {
+        let mut url = account.endpoint.clone();
+        url.query_pairs_mut()
+            .append_pair("restype", "service")
+            .append_pair("comp", "userdelegationkey");
+        let body = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><KeyInfo><Start>{}</Start><Expiry>{}</Expiry></KeyInfo>"#,
+            auth::sas_time(start),
+            auth::sas_time(expiry)
+        );
+
+        let resp = self
+            .send(Method::POST, url, HeaderMap::new(), Some(Bytes::from(body)))
+            .await?;
+        let resp = check_status(resp).await?;
+        let body = resp.text().await?;
+        quick_xml::de::from_str(&body).context("invalid user delegation key response")
+    }
+}
+
+/// Computes the string to sign for a service SAS for a blob.
+/// See https://learn.microsoft.com/en-us/rest/api/storageservices/create-service-sas.
+fn service_sas_string_to_sign(
+    permissions: &str,
+    start: &str,
+    expiry: &str,
+    resource: &str,
+    protocol: &str,
+) -> String {
+    [
+        permissions,
+        start,
+        expiry,
+        resource,
+        "", // signed identifier
+        "", // signed ip
+        protocol,
+        API_VERSION,
+        "b", // signed resource
+        "",  // snapshot time
+        "",  // encryption scope
+        "",  // rscc
+        "",  // rscd
+        "",  // rsce
+        "",  // rscl
+        "",  // rsct
+    ]
+    .join("\n")
+}
+
+/// Computes the string to sign for a user delegation SAS for a blob.
+/// See https://learn.microsoft.com/en-us/rest/api/storageservices/create-user-delegation-sas.
+fn user_delegation_sas_string_to_sign(
+    permissions: &str,
+    start: &str,
+    expiry: &str,
+    resource: &str,
+    key: &auth::UserDelegationKey,
+    protocol: &str,
+) -> String {
+    [
+        permissions,
+        start,
+        expiry,
+        resource,
+        &key.signed_oid,
+        &key.signed_tid,
+        &key.signed_start,
+        &key.signed_expiry,
+        &key.signed_service,
+        &key.signed_version,
+        "", // authorized user object id
+        "", // unauthorized user object id
+        "", // correlation id
+        "", // signed ip
+        protocol,
+        API_VERSION,
+        "b", // signed resource
+        "",  // snapshot time
+        "",  // encryption scope
+        "",  // rscc
+        "",  // rscd
+        "",  // rsce
+        "",  // rscl
+        "",  // rsct
+    ]
+    .join("\n")
+}
+
+/// Returns the response if it was successful, or an error describing it.
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response, Error> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let code = error_code(&resp);
+    let body = resp.text().await.unwrap_or_default();
+    Err(Error::Other(anyhow::anyhow!(
+        "azure blob storage request failed with status {status} ({code}): {body}"
+    )))
+}
+
+/// Returns the Azure error code of a response, if any.
+fn error_code(resp: &reqwest::Response) -> String {
+    resp.headers()
+        .get("x-ms-error-code")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_url() {
+        let account = Account {
+            name: "myacct".into(),
+            endpoint: url::Url::parse("https://myacct.blob.core.windows.net").unwrap(),
+            credential: Credential::Sas(String::new()),
+        };
+        assert_eq!(
+            account.url("photos", Some("2024/a b#1.jpg")).as_str(),
+            "https://myacct.blob.core.windows.net/photos/2024/a%20b%231.jpg"
+        );
+        assert_eq!(
+            account.url("photos", None).as_str(),
+            "https://myacct.blob.core.windows.net/photos"
+        );
+
+        // Emulators such as Azurite include the account name in the path.
+        let account = Account {
+            endpoint: url::Url::parse("http://127.0.0.1:10000/devstoreaccount1/").unwrap(),
+            ..account
+        };
+        assert_eq!(
+            account.url("photos", Some("a.jpg")).as_str(),
+            "http://127.0.0.1:10000/devstoreaccount1/photos/a.jpg"
+        );
+    }
+
+    #[test]
+    fn test_service_sas_string_to_sign() {
+        let s = service_sas_string_to_sign(
+            "r",
+            "2024-01-01T00:00:00Z",
+            "2024-01-01T01:00:00Z",
+            "/blob/myacct/photos/a.jpg",
+            "https",
+        );
+        assert_eq!(
+            s,
+            "r\n2024-01-01T00:00:00Z\n2024-01-01T01:00:00Z\n/blob/myacct/photos/a.jpg\n\n\nhttps\n2021-08-06\nb\n\n\n\n\n\n\n"
+        );
+    }
+}
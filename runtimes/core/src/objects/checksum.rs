@@ -0,0 +1,122 @@
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use bytes::Bytes;
+use futures::StreamExt;
+
+use super::{DownloadStream, Error};
+
+/// A running CRC32C checksum.
+///
+/// Checksums are encoded as the base64 of the big-endian checksum,
+/// which is how both S3 and GCS report them.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct Crc32c(u32);
+
+impl Crc32c {
+    pub fn update(&mut self, data: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, data);
+    }
+
+    pub fn encode(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(self.0.to_be_bytes())
+    }
+
+    /// Computes the encoded checksum of the data.
+    pub fn of(data: &[u8]) -> String {
+        let mut crc = Self::default();
+        crc.update(data);
+        crc.encode()
+    }
+}
+
+/// A CRC32C checksum computed by several parties, such as
+/// a stream and the code consuming it.
+#[derive(Debug, Default, Clone)]
+pub(super) struct SharedCrc32c(Arc<Mutex<Crc32c>>);
+
+impl SharedCrc32c {
+    pub fn update(&self, data: &[u8]) {
+        self.0.lock().unwrap().update(data);
+    }
+
+    pub fn encode(&self) -> String {
+        self.0.lock().unwrap().encode()
+    }
+}
+
+/// Returns the checksum reported by the provider, if it's a checksum
+/// of the full object contents. Objects uploaded in multiple parts report
+/// a checksum of the part checksums instead, suffixed with the part count.
+pub(super) fn full_object_checksum(reported: Option<String>) -> Option<String> {
+    reported.filter(|c| !c.is_empty() && !c.contains('-'))
+}
+
+/// Wraps a download stream to verify the contents match the expected checksum.
+/// If they don't, the stream ends with [`Error::ChecksumMismatch`].
+pub(super) fn verify_download(stream: DownloadStream, expected: String) -> DownloadStream {
+    let mut crc = Crc32c::default();
+    let mut failed = false;
+    let mut stream = stream;
+    Box::pin(async_stream::stream! {
+        while let Some(chunk) = stream.next().await {
+            match &chunk {
+                Ok(data) => crc.update(data),
+                Err(_) => failed = true,
+            }
+            yield chunk;
+            if failed {
+                return;
+            }
+        }
+
+        if crc.encode() != expected {
+            yield Err::<Bytes, _>(Error::ChecksumMismatch);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32c_encoding() {
+        // The checksum of "hello world" as reported by gsutil.
+        assert_eq!(Crc32c::of(b"hello world"), "yZRlqg==");
+
+        let mut crc = Crc32c::default();
+        crc.update(b"hello ");
+        crc.update(b"world");
+        assert_eq!(crc.encode(), "yZRlqg==");
+    }
+
+    #[test]
+    fn multipart_checksums() {
+        assert_eq!(
+            full_object_checksum(Some("yZRlqg==".into())),
+            Some("yZRlqg==".into())
+        );
+        assert_eq!(full_object_checksum(Some("yZRlqg==-3".into())), None);
+        assert_eq!(full_object_checksum(None), None);
+    }
+
+    #[tokio::test]
+    async fn verify() {
+        let chunks = |data: &'static [&'static [u8]]| -> DownloadStream {
+            Box::pin(futures::stream::iter(
+                data.iter().map(|d| Ok(Bytes::from_static(d))),
+            ))
+        };
+
+        let ok: Vec<_> = verify_download(chunks(&[b"hello ", b"world"]), "yZRlqg==".into())
+            .collect()
+            .await;
+        assert!(ok.iter().all(|c| c.is_ok()));
+
+        let bad: Vec<_> = verify_download(chunks(&[b"hello ", b"there"]), "yZRlqg==".into())
+            .collect()
+            .await;
+        assert!(matches!(bad.last(), Some(Err(Error::ChecksumMismatch))));
+    }
+}
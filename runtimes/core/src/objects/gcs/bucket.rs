@@ -13,10 +13,11 @@ use std::time::SystemTime;
 use tokio::io::AsyncRead;
 
 use crate::encore::runtime::v1 as pb;
+use crate::objects::checksum::{self, SharedCrc32c};
 use crate::objects::{
     AttrsOptions, DeleteOptions, DownloadOptions, DownloadStream, DownloadUrlOptions, Error,
-    ExistsOptions, ListEntry, ListOptions, ObjectAttrs, PublicUrlError, UploadOptions,
-    UploadUrlOptions,
+    ExistsOptions, ListEntry, ListOptions, ObjectAttrs, Preconditions, PublicUrlError,
+    UploadOptions, UploadUrlOptions,
 };
 use crate::{objects, CloudName, EncoreName};
 use google_cloud_storage as gcs;
@@ -133,25 +134,46 @@ impl objects::BucketImpl for Bucket {
                             req.prefix = Some(req.prefix.unwrap_or_default().clone() + key_prefix);
                         }
 
+                        if let Some(delimiter) = &options.delimiter {
+                            req.delimiter = Some(delimiter.clone());
+                        }
+
+                        // The start offset is inclusive; the entry
+                        // at the cursor itself is skipped below.
+                        if let Some(cursor) = &options.cursor {
+                            req.start_offset = Some(self.obj_name(Cow::Borrowed(cursor)).into_owned());
+                        }
+
                         'PageLoop:
                         loop {
                             let resp = client.list_objects(&req).await.map_err(|e| Error::Other(e.into()))?;
-                            if let Some(items) = resp.items {
-                                for obj in items {
-                                    total_seen += 1;
-                                    if let Some(limit) = options.limit {
-                                        if total_seen > limit {
-                                            break 'PageLoop;
-                                        }
-                                    }
 
-                                    let entry = ListEntry {
-                                        name: self.strip_prefix(Cow::Owned(obj.name)).into_owned(),
-                                        size: obj.size as u64,
-                                        etag: obj.etag,
-                                    };
-                                    yield entry;
+                            // Objects and common prefixes are returned separately,
+                            // so merge them to yield the entries in order.
+                            let mut entries: Vec<ListEntry> = resp.items.unwrap_or_default().into_iter().map(|obj| ListEntry {
+                                name: self.strip_prefix(Cow::Owned(obj.name)).into_owned(),
+                                size: obj.size as u64,
+                                etag: obj.etag,
+                                is_prefix: false,
+                            }).collect();
+                            entries.extend(resp.prefixes.unwrap_or_default().into_iter().map(|p| {
+                                ListEntry::prefix(self.strip_prefix(Cow::Owned(p)).into_owned())
+                            }));
+                            entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+                            for entry in entries {
+                                if !options.after_cursor(&entry.name) {
+                                    continue;
+                                }
+
+                                total_seen += 1;
+                                if let Some(limit) = options.limit {
+                                    if total_seen > limit {
+                                        break 'PageLoop;
+                                    }
                                 }
+
+                                yield entry;
                             }
 
                             req.page_token = resp.next_page_token;
@@ -223,6 +245,7 @@ impl objects::ObjectImpl for Object {
                         size: obj.size as u64,
                         content_type: obj.content_type,
                         etag: obj.etag,
+                        crc32c: checksum::full_object_checksum(obj.crc32c),
                     })
                 }
                 Err(err) => Err(Error::Internal(anyhow::anyhow!(
@@ -306,24 +329,49 @@ impl objects::ObjectImpl for Object {
                     let cloud_name = self.bkt.obj_name(Cow::Borrowed(&self.key));
                     let mut media = Media::new(cloud_name.into_owned());
 
-                    apply_upload_opts(opts, &mut req, &mut media);
+                    if let Some(pre) = &opts.preconditions {
+                        req.if_generation_match = self
+                            .generation_precondition(client, &Preconditions::from(pre), None)
+                            .await?;
+                    }
+                    apply_upload_opts(opts, &mut media);
 
                     let upload_type = UploadType::Simple(media);
-                    let stream = tokio_util::io::ReaderStream::new(data);
+                    let crc = SharedCrc32c::default();
+                    let stream = tokio_util::io::ReaderStream::new(data).inspect_ok({
+                        let crc = crc.clone();
+                        move |chunk| crc.update(chunk)
+                    });
 
-                    match client
+                    let obj = client
                         .upload_streamed_object(&req, stream, &upload_type)
                         .await
-                    {
-                        Ok(obj) => Ok(ObjectAttrs {
-                            name: obj.name,
-                            version: Some(obj.generation.to_string()),
-                            size: obj.size as u64,
-                            content_type: obj.content_type,
-                            etag: obj.etag,
-                        }),
-                        Err(err) => Err(map_err(err)),
+                        .map_err(map_err)?;
+
+                    // Simple uploads can't include the checksum up front,
+                    // so verify what was stored matches what was sent.
+                    let crc32c = crc.encode();
+                    if obj.crc32c.as_deref().is_some_and(|c| c != crc32c) {
+                        let req = gcs::http::objects::delete::DeleteObjectRequest {
+                            bucket: self.bkt.cloud_name.to_string(),
+                            object: obj.name,
+                            if_generation_match: Some(obj.generation),
+                            ..Default::default()
+                        };
+                        if let Err(err) = client.delete_object(&req).await {
+                            log::error!("unable to delete corrupted object upload: {}", err);
+                        }
+                        return Err(Error::ChecksumMismatch);
                     }
+
+                    Ok(ObjectAttrs {
+                        name: obj.name,
+                        version: Some(obj.generation.to_string()),
+                        size: obj.size as u64,
+                        content_type: obj.content_type,
+                        etag: obj.etag,
+                        crc32c: Some(crc32c),
+                    })
                 }
                 Err(err) => Err(Error::Internal(anyhow::anyhow!(
                     "unable to resolve client: {}",
@@ -339,14 +387,16 @@ impl objects::ObjectImpl for Object {
     ) -> Pin<Box<dyn Future<Output = Result<DownloadStream, Error>> + Send>> {
         fn convert_err(err: gcs::http::Error) -> Error {
             use gcs::http::error::ErrorResponse;
-            match err {
-                gcs::http::Error::Response(ErrorResponse { code: 404, .. }) => Error::NotFound,
-                gcs::http::Error::HttpClient(err)
-                    if err.status().map(|s| s.as_u16()) == Some(404) =>
-                {
-                    Error::NotFound
-                }
-                err => Error::Other(err.into()),
+            let code = match &err {
+                gcs::http::Error::Response(ErrorResponse { code, .. }) => Some(*code),
+                gcs::http::Error::HttpClient(err) => err.status().map(|s| s.as_u16()),
+                _ => None,
+            };
+            match code {
+                Some(404) => Error::NotFound,
+                Some(412) => Error::PreconditionFailed,
+                Some(416) => Error::InvalidArgument,
+                _ => Error::Other(err.into()),
             }
         }
 
@@ -362,14 +412,34 @@ impl objects::ObjectImpl for Object {
                     if let Some(version) = options.version {
                         req.generation = Some(parse_version(version)?);
                     }
+                    // The download doesn't report the object's checksum, so look it up
+                    // beforehand and download that exact generation to verify it.
+                    let mut expected = None;
+                    if options.range.is_none() {
+                        let obj = client.get_object(&req).await.map_err(map_err)?;
+                        req.generation = Some(obj.generation);
+                        expected = checksum::full_object_checksum(obj.crc32c);
+                    }
 
-                    let resp = client
-                        .download_streamed_object(&req, &Range::default())
-                        .await;
+                    if let Some(pre) = &options.preconditions {
+                        req.if_generation_match = self
+                            .generation_precondition(client, pre, req.generation)
+                            .await?;
+                    }
+
+                    let range = match options.range {
+                        Some(r) => Range(Some(r.offset), r.last()?),
+                        None => Range::default(),
+                    };
+
+                    let resp = client.download_streamed_object(&req, &range).await;
 
                     let stream = resp.map_err(convert_err)?;
                     let stream: DownloadStream = Box::pin(stream.map_err(convert_err));
-                    Ok(stream)
+                    match expected {
+                        Some(expected) => Ok(checksum::verify_download(stream, expected)),
+                        None => Ok(stream),
+                    }
                 }
                 Err(err) => Err(Error::Internal(anyhow::anyhow!(
                     "unable to resolve client: {}",
@@ -395,6 +465,11 @@ impl objects::ObjectImpl for Object {
                     if let Some(version) = options.version {
                         req.generation = Some(parse_version(version)?);
                     }
+                    if let Some(pre) = &options.preconditions {
+                        req.if_generation_match = self
+                            .generation_precondition(client, pre, req.generation)
+                            .await?;
+                    }
 
                     match client.delete_object(&req).await.map_err(map_err) {
                         Ok(_) => Ok(()),
@@ -421,6 +496,43 @@ impl objects::ObjectImpl for Object {
 }
 
 impl Object {
+    /// Resolves etag preconditions into the generation the object must have
+    /// for the operation to proceed, where 0 means it must not exist.
+    ///
+    /// GCS only supports preconditions on the object generation, so the
+    /// object's current etag is checked here. Requiring the generation it was
+    /// checked against keeps the operation atomic with respect to the check.
+    async fn generation_precondition(
+        &self,
+        client: &gcs::client::Client,
+        pre: &Preconditions,
+        generation: Option<i64>,
+    ) -> Result<Option<i64>, Error> {
+        match (pre.if_match.as_deref(), pre.if_none_match.as_deref()) {
+            (None, None) => return Ok(None),
+            (None, Some("*")) => return Ok(Some(0)),
+            _ => {}
+        }
+
+        let req = GetObjectRequest {
+            bucket: self.bkt.cloud_name.to_string(),
+            object: self.bkt.obj_name(Cow::Borrowed(&self.key)).into_owned(),
+            generation,
+            ..Default::default()
+        };
+        let (etag, generation) = match client.get_object(&req).await.map_err(map_err) {
+            Ok(obj) => (Some(obj.etag), obj.generation),
+            Err(Error::NotFound) => (None, 0),
+            Err(err) => return Err(err),
+        };
+
+        if pre.holds(etag.as_deref()) {
+            Ok(Some(generation))
+        } else {
+            Err(Error::PreconditionFailed)
+        }
+    }
+
     fn signed_url(
         self: Arc<Self>,
         gcs_opts: SignedURLOptions,
@@ -491,15 +603,10 @@ fn replace_url_prefix<'a>(orig_url: &'a str, base: &str) -> Cow<'a, str> {
     }
 }
 
-fn apply_upload_opts(opts: UploadOptions, req: &mut UploadObjectRequest, media: &mut Media) {
+fn apply_upload_opts(opts: UploadOptions, media: &mut Media) {
     if let Some(content_type) = opts.content_type {
         media.content_type = Cow::Owned(content_type);
     }
-    if let Some(pre) = opts.preconditions {
-        if pre.not_exists == Some(true) {
-            req.if_generation_match = Some(0);
-        }
-    }
 }
 
 fn parse_version(version: String) -> Result<i64, Error> {
@@ -4,15 +4,21 @@ use std::sync::{Arc, RwLock};
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::names::EncoreName;
-use crate::objects::{gcs, noop, s3, BucketImpl, ClusterImpl};
-use crate::secrets;
+use crate::objects::{self, azure, gcs, noop, replicated, s3, BucketImpl, ClusterImpl};
 use crate::trace::Tracer;
+use crate::{replay, secrets};
 
 use super::Bucket;
 
+type BucketCfgMap = HashMap<EncoreName, (Arc<dyn ClusterImpl>, pb::Bucket)>;
+
 pub struct Manager {
     tracer: Tracer,
-    bucket_cfg: HashMap<EncoreName, (Arc<dyn ClusterImpl>, pb::Bucket)>,
+    bucket_cfg: BucketCfgMap,
+    /// All clusters by rid, for resolving bucket replicas.
+    clusters: HashMap<String, Arc<dyn ClusterImpl>>,
+    /// The region of the deployment, if known.
+    region: Option<String>,
 
     buckets: Arc<RwLock<HashMap<EncoreName, Arc<dyn BucketImpl>>>>,
 }
@@ -23,12 +29,15 @@ impl Manager {
         tracer: Tracer,
         clusters: Vec<pb::BucketCluster>,
         md: &meta::Data,
+        region: Option<String>,
     ) -> Self {
-        let bucket_cfg = make_cfg_maps(secrets, clusters, md);
+        let (bucket_cfg, clusters) = make_cfg_maps(secrets, clusters, md);
 
         Self {
             tracer,
             bucket_cfg,
+            clusters,
+            region,
             buckets: Arc::default(),
         }
     }
@@ -48,23 +57,64 @@ impl Manager {
 
         let bkt = {
             if let Some((cluster, bucket_cfg)) = self.bucket_cfg.get(&name) {
-                cluster.clone().bucket(bucket_cfg)
+                let bkt = cluster.clone().bucket(bucket_cfg);
+                if bucket_cfg.replicas.is_empty() {
+                    bkt
+                } else {
+                    self.with_replicas(bkt, bucket_cfg)
+                }
             } else {
                 Arc::new(noop::Bucket::new(name.clone()))
             }
         };
+        let bkt = match replay::cassette() {
+            Some(cassette) => Arc::new(objects::replay::Bucket::new(bkt, cassette)),
+            None => bkt,
+        };
 
         self.buckets.write().unwrap().insert(name, bkt.clone());
         Some(bkt)
     }
+
+    fn with_replicas(&self, primary: Arc<dyn BucketImpl>, cfg: &pb::Bucket) -> Arc<dyn BucketImpl> {
+        let replicas = cfg
+            .replicas
+            .iter()
+            .filter_map(|replica| {
+                let Some(cluster) = self.clusters.get(&replica.cluster_rid) else {
+                    log::error!(
+                        "bucket {}: replica cluster {} not found, skipping",
+                        cfg.encore_name,
+                        replica.cluster_rid
+                    );
+                    return None;
+                };
+                let bkt = cluster.clone().bucket(&pb::Bucket {
+                    encore_name: cfg.encore_name.clone(),
+                    cloud_name: replica.cloud_name.clone(),
+                    key_prefix: replica.key_prefix.clone(),
+                    region: replica.region.clone(),
+                    ..Default::default()
+                });
+                Some((bkt, replica.region.clone()))
+            })
+            .collect();
+        Arc::new(replicated::Bucket::new(
+            primary,
+            cfg.region.clone(),
+            replicas,
+            self.region.as_deref(),
+        ))
+    }
 }
 
 fn make_cfg_maps(
     secrets: &secrets::Manager,
     clusters: Vec<pb::BucketCluster>,
     _md: &meta::Data,
-) -> HashMap<EncoreName, (Arc<dyn ClusterImpl>, pb::Bucket)> {
+) -> (BucketCfgMap, HashMap<String, Arc<dyn ClusterImpl>>) {
     let mut bucket_map = HashMap::new();
+    let mut cluster_map = HashMap::new();
 
     for cluster_cfg in clusters {
         let cluster = match cluster_cfg.provider {
@@ -81,9 +131,10 @@ fn make_cfg_maps(
                 (cluster.clone(), bucket_cfg),
             );
         }
+        cluster_map.insert(cluster_cfg.rid, cluster);
     }
 
-    bucket_map
+    (bucket_map, cluster_map)
 }
 
 fn new_cluster(
@@ -99,5 +150,13 @@ fn new_cluster(
             Arc::new(s3::Cluster::new(s3cfg, secret_access_key))
         }
         pb::bucket_cluster::Provider::Gcs(gcscfg) => Arc::new(gcs::Cluster::new(gcscfg.clone())),
+        pb::bucket_cluster::Provider::AzureBlob(azcfg) => {
+            use pb::bucket_cluster::azure_blob::Credentials;
+            let connection_string = match &azcfg.credentials {
+                Some(Credentials::ConnectionString(data)) => Some(secrets.load(data.clone())),
+                _ => None,
+            };
+            Arc::new(azure::Cluster::new(azcfg, connection_string))
+        }
     }
 }
@@ -12,11 +12,15 @@ pub use manager::Manager;
 
 use crate::encore::runtime::v1 as pb;
 use crate::trace::{protocol, Tracer};
-use crate::{model, EncoreName};
+use crate::{faults, model, EncoreName};
 
+mod azure;
+mod checksum;
 mod gcs;
 mod manager;
 mod noop;
+mod replay;
+mod replicated;
 mod s3;
 
 trait ClusterImpl: Debug + Send + Sync {
@@ -99,6 +103,8 @@ impl Bucket {
         options: ListOptions,
         source: Option<Arc<model::Request>>,
     ) -> Result<ListIterator, Error> {
+        inject_fault().await?;
+        let limit = options.limit;
         let (stream, start_id) = if let Some(source) = source.as_deref() {
             let start_id =
                 self.tracer
@@ -132,8 +138,10 @@ impl Bucket {
             source,
             start_id,
             tracer: self.tracer.clone(),
+            limit,
 
             yielded_entries: 0,
+            last_name: None,
             seen_end: false,
             err: None,
         })
@@ -162,6 +170,7 @@ impl Object {
         options: ExistsOptions,
         source: Option<Arc<model::Request>>,
     ) -> Result<bool, Error> {
+        inject_fault().await?;
         if let Some(source) = source.as_deref() {
             let start_id =
                 self.tracer
@@ -201,6 +210,7 @@ impl Object {
         let imp = self.imp.clone();
 
         async move {
+            inject_fault().await?;
             if let Some(source) = source.as_deref() {
                 let start_id =
                     tracer.bucket_object_upload_start(protocol::BucketObjectUploadStart {
@@ -263,7 +273,11 @@ impl Object {
         options: DownloadOptions,
         _source: Option<Arc<model::Request>>,
     ) -> impl Future<Output = Result<DownloadStream, Error>> + Send + 'static {
-        self.imp.clone().download(options)
+        let fut = self.imp.clone().download(options);
+        async move {
+            inject_fault().await?;
+            fut.await
+        }
     }
 
     pub fn download_all(
@@ -313,6 +327,7 @@ impl Object {
     ) -> impl Future<Output = Result<Vec<u8>, Error>> + Send + 'static {
         let stream = self.imp.clone().download(options);
         async move {
+            inject_fault().await?;
             let mut bytes = Vec::new();
             let mut stream = stream.await?;
 
@@ -328,6 +343,7 @@ impl Object {
         options: AttrsOptions,
         source: Option<Arc<model::Request>>,
     ) -> Result<ObjectAttrs, Error> {
+        inject_fault().await?;
         if let Some(source) = source.as_deref() {
             let start_id =
                 self.tracer
@@ -359,6 +375,7 @@ impl Object {
         options: DeleteOptions,
         source: Option<Arc<model::Request>>,
     ) -> Result<(), Error> {
+        inject_fault().await?;
         if let Some(source) = source.as_deref() {
             let start_id =
                 self.tracer
@@ -397,6 +414,13 @@ impl Object {
     }
 }
 
+/// Fails or delays the bucket operation if fault injection is enabled.
+async fn inject_fault() -> Result<(), Error> {
+    faults::inject(faults::Target::Bucket)
+        .await
+        .map_err(|fault| Error::Other(fault.into()))
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("object not found")]
@@ -408,6 +432,9 @@ pub enum Error {
     #[error("invalid argument")]
     InvalidArgument,
 
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
+
     #[error("internal error: {0:?}")]
     Internal(anyhow::Error),
 
@@ -423,15 +450,33 @@ pub struct ObjectAttrs {
     pub size: u64,
     pub content_type: Option<String>,
     pub etag: String,
+    /// The base64-encoded CRC32C checksum of the object contents,
+    /// if known by the provider.
+    pub crc32c: Option<String>,
 }
 
 pub struct ListEntry {
     pub name: String,
     pub size: u64,
     pub etag: String,
+    /// Whether the entry is a common prefix of several objects,
+    /// as opposed to an object. Only set when listing with a delimiter.
+    pub is_prefix: bool,
 }
 
-#[derive(Debug, Default)]
+impl ListEntry {
+    /// Returns an entry for a common prefix.
+    fn prefix(name: String) -> Self {
+        Self {
+            name,
+            size: 0,
+            etag: String::new(),
+            is_prefix: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct ExistsOptions {
     pub version: Option<String>,
 }
@@ -445,14 +490,95 @@ pub struct UploadOptions {
 #[derive(Debug, Default)]
 pub struct UploadPreconditions {
     pub not_exists: Option<bool>,
+    /// Only upload if the existing object's etag matches.
+    pub if_match: Option<String>,
+    /// Only upload if the existing object's etag does not match,
+    /// or "*" to only upload if the object does not exist.
+    pub if_none_match: Option<String>,
 }
 
-#[derive(Debug, Default)]
+/// Conditions on the object's current etag for an operation to proceed.
+/// If they're not met the operation fails with [`Error::PreconditionFailed`].
+#[derive(Debug, Default, Clone)]
+pub struct Preconditions {
+    pub if_match: Option<String>,
+    pub if_none_match: Option<String>,
+}
+
+impl Preconditions {
+    /// Reports whether the conditions hold for an object with the given etag,
+    /// or None if the object does not exist.
+    fn holds(&self, etag: Option<&str>) -> bool {
+        let matches = |want: &str| match etag {
+            Some(etag) => want == "*" || unquote_etag(want) == unquote_etag(etag),
+            None => false,
+        };
+        self.if_match.as_deref().map_or(true, matches)
+            && !self.if_none_match.as_deref().is_some_and(matches)
+    }
+}
+
+impl From<&UploadPreconditions> for Preconditions {
+    fn from(value: &UploadPreconditions) -> Self {
+        Self {
+            if_match: value.if_match.clone(),
+            if_none_match: if value.not_exists == Some(true) {
+                Some("*".to_string())
+            } else {
+                value.if_none_match.clone()
+            },
+        }
+    }
+}
+
+/// Strips the quotes surrounding an etag, if any.
+fn unquote_etag(etag: &str) -> &str {
+    etag.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(etag)
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct DownloadOptions {
     pub version: Option<String>,
+    pub preconditions: Option<Preconditions>,
+    /// Only download the given range of bytes.
+    pub range: Option<ByteRange>,
 }
 
-#[derive(Debug, Default)]
+/// A range of bytes within an object.
+#[derive(Debug, Clone, Copy)]
+pub struct ByteRange {
+    pub offset: u64,
+    /// The number of bytes to read, or None to read until the end of the object.
+    pub length: Option<u64>,
+}
+
+impl ByteRange {
+    /// Returns the offset of the last byte in the range, which is inclusive
+    /// like in HTTP range requests, or None if the range is unbounded.
+    fn last(&self) -> Result<Option<u64>, Error> {
+        match self.length {
+            None => Ok(None),
+            Some(0) => Err(Error::InvalidArgument),
+            Some(len) => self
+                .offset
+                .checked_add(len - 1)
+                .map(Some)
+                .ok_or(Error::InvalidArgument),
+        }
+    }
+
+    /// Formats the range as the value of an HTTP Range header.
+    fn header(&self) -> Result<String, Error> {
+        Ok(match self.last()? {
+            Some(last) => format!("bytes={}-{}", self.offset, last),
+            None => format!("bytes={}-", self.offset),
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct AttrsOptions {
     pub version: Option<String>,
 }
@@ -462,7 +588,7 @@ pub struct UploadUrlOptions {
     pub ttl: Duration,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct DownloadUrlOptions {
     pub ttl: Duration,
 }
@@ -470,12 +596,40 @@ pub struct DownloadUrlOptions {
 #[derive(Debug, Default)]
 pub struct DeleteOptions {
     pub version: Option<String>,
+    pub preconditions: Option<Preconditions>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ListOptions {
     pub prefix: Option<String>,
+    /// Folds object names containing the delimiter after the prefix
+    /// into a single common prefix entry, like directories.
+    pub delimiter: Option<String>,
     pub limit: Option<u64>,
+    /// Resumes a previous listing after the entry with this name,
+    /// as returned by [`ListIterator::cursor`].
+    pub cursor: Option<String>,
+}
+
+impl ListOptions {
+    /// Reports whether an entry with the given name comes after the cursor.
+    ///
+    /// Objects within a common prefix that was already returned are
+    /// skipped too, as providers may return the prefix again.
+    fn after_cursor(&self, name: &str) -> bool {
+        let Some(cursor) = self.cursor.as_deref() else {
+            return true;
+        };
+        if name <= cursor {
+            return false;
+        }
+        match self.delimiter.as_deref() {
+            Some(delim) if !delim.is_empty() && cursor.ends_with(delim) => {
+                !name.starts_with(cursor)
+            }
+            _ => true,
+        }
+    }
 }
 
 pub struct ListIterator {
@@ -484,8 +638,10 @@ pub struct ListIterator {
     start_id: Option<model::TraceEventId>,
     source: Option<Arc<model::Request>>,
     err: Option<String>,
+    limit: Option<u64>,
 
     yielded_entries: u64,
+    last_name: Option<String>,
     seen_end: bool,
 }
 
@@ -497,8 +653,9 @@ impl ListIterator {
             None => {
                 self.seen_end = true;
             }
-            Some(Ok(_)) => {
+            Some(Ok(entry)) => {
                 self.yielded_entries += 1;
+                self.last_name = Some(entry.name.clone());
             }
             Some(Err(err)) => {
                 if self.err.is_none() {
@@ -509,6 +666,15 @@ impl ListIterator {
 
         res
     }
+
+    /// Returns the cursor to resume listing from, if the listing
+    /// reached the limit and there may be more entries.
+    pub fn cursor(&self) -> Option<String> {
+        match self.limit {
+            Some(limit) if self.yielded_entries >= limit => self.last_name.clone(),
+            _ => None,
+        }
+    }
 }
 
 impl Drop for ListIterator {
@@ -551,3 +717,66 @@ fn public_url(base_url: String, name: &str) -> String {
     url.push_str(&escape_path(name));
     url
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn after_cursor() {
+        let opts = ListOptions {
+            cursor: Some("b".into()),
+            ..Default::default()
+        };
+        assert!(!opts.after_cursor("a"));
+        assert!(!opts.after_cursor("b"));
+        assert!(opts.after_cursor("b/c"));
+        assert!(opts.after_cursor("c"));
+
+        // Objects within an already returned common prefix are skipped.
+        let opts = ListOptions {
+            delimiter: Some("/".into()),
+            cursor: Some("b/".into()),
+            ..Default::default()
+        };
+        assert!(!opts.after_cursor("b/"));
+        assert!(!opts.after_cursor("b/c"));
+        assert!(opts.after_cursor("c"));
+    }
+
+    #[test]
+    fn preconditions() {
+        let pre = Preconditions {
+            if_match: Some("\"abc\"".into()),
+            if_none_match: None,
+        };
+        assert!(pre.holds(Some("abc")));
+        assert!(!pre.holds(Some("def")));
+        assert!(!pre.holds(None));
+
+        let pre = Preconditions::from(&UploadPreconditions {
+            not_exists: Some(true),
+            ..Default::default()
+        });
+        assert!(pre.holds(None));
+        assert!(!pre.holds(Some("abc")));
+
+        let pre = Preconditions {
+            if_match: None,
+            if_none_match: Some("abc".into()),
+        };
+        assert!(pre.holds(None));
+        assert!(pre.holds(Some("def")));
+        assert!(!pre.holds(Some("abc")));
+    }
+
+    #[test]
+    fn byte_range_header() {
+        let range = |offset, length| ByteRange { offset, length };
+        assert_eq!(range(0, Some(10)).header().unwrap(), "bytes=0-9");
+        assert_eq!(range(5, Some(1)).header().unwrap(), "bytes=5-5");
+        assert_eq!(range(100, None).header().unwrap(), "bytes=100-");
+        assert!(range(0, Some(0)).header().is_err());
+        assert!(range(u64::MAX, Some(2)).header().is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use base64::Engine;
+use futures::{StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::AsyncRead;
+
+use crate::objects;
+use crate::replay::{Cassette, Mode};
+use crate::EncoreName;
+
+use super::{
+    AttrsOptions, DeleteOptions, DownloadOptions, DownloadUrlOptions, ExistsOptions, ListOptions,
+    PublicUrlError, UploadUrlOptions,
+};
+
+/// Records or replays the operations of a bucket.
+///
+/// In replay mode the underlying bucket is only used for its name;
+/// uploads are not read and nothing reaches the provider.
+/// Downloads and listings are buffered in full so they can be recorded.
+#[derive(Debug)]
+pub struct Bucket {
+    inner: Arc<dyn objects::BucketImpl>,
+    cassette: &'static Cassette,
+}
+
+impl Bucket {
+    pub fn new(inner: Arc<dyn objects::BucketImpl>, cassette: &'static Cassette) -> Self {
+        Self { inner, cassette }
+    }
+}
+
+#[derive(Debug)]
+pub struct Object {
+    inner: Arc<dyn objects::ObjectImpl>,
+    cassette: &'static Cassette,
+}
+
+impl Object {
+    fn cassette_key(&self) -> String {
+        format!("{}/{}", self.inner.bucket_name(), self.inner.key())
+    }
+}
+
+impl objects::BucketImpl for Bucket {
+    fn name(&self) -> &EncoreName {
+        self.inner.name()
+    }
+
+    fn object(self: Arc<Self>, name: String) -> Arc<dyn objects::ObjectImpl> {
+        Arc::new(Object {
+            inner: self.inner.clone().object(name),
+            cassette: self.cassette,
+        })
+    }
+
+    fn list(
+        self: Arc<Self>,
+        options: ListOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::ListStream, objects::Error>> + Send + 'static>>
+    {
+        let key = format!(
+            "{}/{}",
+            self.inner.name(),
+            options.prefix.as_deref().unwrap_or_default()
+        );
+        Box::pin(async move {
+            let entries: Vec<ListEntry> = run(self.cassette, "bucket.list", &key, async {
+                let stream = self.inner.clone().list(options).await?;
+                Box::into_pin(stream)
+                    .map_ok(ListEntry::from)
+                    .try_collect()
+                    .await
+            })
+            .await?;
+            let stream = futures::stream::iter(entries.into_iter().map(|e| Ok(e.into())));
+            Ok(Box::new(stream) as objects::ListStream)
+        })
+    }
+}
+
+impl objects::ObjectImpl for Object {
+    fn bucket_name(&self) -> &EncoreName {
+        self.inner.bucket_name()
+    }
+
+    fn key(&self) -> &str {
+        self.inner.key()
+    }
+
+    fn exists(
+        self: Arc<Self>,
+        options: ExistsOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, objects::Error>> + Send>> {
+        Box::pin(async move {
+            let key = self.cassette_key();
+            run(
+                self.cassette,
+                "object.exists",
+                &key,
+                self.inner.clone().exists(options),
+            )
+            .await
+        })
+    }
+
+    fn upload(
+        self: Arc<Self>,
+        data: Box<dyn AsyncRead + Unpin + Send + Sync + 'static>,
+        options: objects::UploadOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::ObjectAttrs, objects::Error>> + Send>> {
+        Box::pin(async move {
+            let key = self.cassette_key();
+            let attrs: ObjectAttrs = run(self.cassette, "object.upload", &key, async {
+                self.inner
+                    .clone()
+                    .upload(data, options)
+                    .await
+                    .map(Into::into)
+            })
+            .await?;
+            Ok(attrs.into())
+        })
+    }
+
+    fn signed_upload_url(
+        self: Arc<Self>,
+        options: UploadUrlOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<String, objects::Error>> + Send>> {
+        Box::pin(async move {
+            let key = self.cassette_key();
+            run(
+                self.cassette,
+                "object.signed_upload_url",
+                &key,
+                self.inner.clone().signed_upload_url(options),
+            )
+            .await
+        })
+    }
+
+    fn signed_download_url(
+        self: Arc<Self>,
+        options: DownloadUrlOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<String, objects::Error>> + Send>> {
+        Box::pin(async move {
+            let key = self.cassette_key();
+            run(
+                self.cassette,
+                "object.signed_download_url",
+                &key,
+                self.inner.clone().signed_download_url(options),
+            )
+            .await
+        })
+    }
+
+    fn download(
+        self: Arc<Self>,
+        options: DownloadOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::DownloadStream, objects::Error>> + Send>> {
+        Box::pin(async move {
+            let key = self.cassette_key();
+            let data: String = run(self.cassette, "object.download", &key, async {
+                let mut stream = self.inner.clone().download(options).await?;
+                let mut data = Vec::new();
+                while let Some(chunk) = stream.next().await {
+                    data.extend_from_slice(&chunk?);
+                }
+                Ok(base64::engine::general_purpose::STANDARD.encode(data))
+            })
+            .await?;
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| objects::Error::Internal(e.into()))?;
+            let stream = futures::stream::once(async move { Ok(data.into()) });
+            Ok(Box::pin(stream) as objects::DownloadStream)
+        })
+    }
+
+    fn attrs(
+        self: Arc<Self>,
+        options: AttrsOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::ObjectAttrs, objects::Error>> + Send>> {
+        Box::pin(async move {
+            let key = self.cassette_key();
+            let attrs: ObjectAttrs = run(self.cassette, "object.attrs", &key, async {
+                self.inner.clone().attrs(options).await.map(Into::into)
+            })
+            .await?;
+            Ok(attrs.into())
+        })
+    }
+
+    fn delete(
+        self: Arc<Self>,
+        options: DeleteOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<(), objects::Error>> + Send>> {
+        Box::pin(async move {
+            let key = self.cassette_key();
+            run(
+                self.cassette,
+                "object.delete",
+                &key,
+                self.inner.clone().delete(options),
+            )
+            .await
+        })
+    }
+
+    fn public_url(&self) -> Result<String, PublicUrlError> {
+        // Public URLs are computed from the bucket config
+        // without calling the provider, so there's nothing to record.
+        self.inner.public_url()
+    }
+}
+
+/// Runs the operation and records its result, or replays the recorded result.
+async fn run<T, F>(cassette: &Cassette, op: &str, key: &str, fut: F) -> Result<T, objects::Error>
+where
+    T: Serialize + DeserializeOwned,
+    F: Future<Output = Result<T, objects::Error>>,
+{
+    match cassette.mode() {
+        Mode::Replay => match cassette.replay(op, key) {
+            Ok(Ok(value)) => {
+                serde_json::from_value(value).map_err(|e| objects::Error::Internal(e.into()))
+            }
+            Ok(Err(err)) => Err(decode_error(err)),
+            Err(err) => Err(objects::Error::Internal(err)),
+        },
+        Mode::Record => {
+            let res = fut.await;
+            let recorded = match &res {
+                Ok(value) => serde_json::to_value(value).map_err(|e| e.to_string()),
+                Err(err) => Err(encode_error(err)),
+            };
+            cassette.record(op, key, recorded);
+            res
+        }
+    }
+}
+
+/// Encodes the error so its kind survives replay.
+fn encode_error(err: &objects::Error) -> String {
+    match err {
+        objects::Error::NotFound => "not_found".into(),
+        objects::Error::PreconditionFailed => "precondition_failed".into(),
+        objects::Error::InvalidArgument => "invalid_argument".into(),
+        objects::Error::ChecksumMismatch => "checksum_mismatch".into(),
+        other => other.to_string(),
+    }
+}
+
+fn decode_error(err: String) -> objects::Error {
+    match err.as_str() {
+        "not_found" => objects::Error::NotFound,
+        "precondition_failed" => objects::Error::PreconditionFailed,
+        "invalid_argument" => objects::Error::InvalidArgument,
+        "checksum_mismatch" => objects::Error::ChecksumMismatch,
+        _ => objects::Error::Other(anyhow::anyhow!(err)),
+    }
+}
+
+/// The recorded form of [`objects::ObjectAttrs`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ObjectAttrs {
+    name: String,
+    version: Option<String>,
+    size: u64,
+    content_type: Option<String>,
+    etag: String,
+    crc32c: Option<String>,
+}
+
+impl From<objects::ObjectAttrs> for ObjectAttrs {
+    fn from(a: objects::ObjectAttrs) -> Self {
+        Self {
+            name: a.name,
+            version: a.version,
+            size: a.size,
+            content_type: a.content_type,
+            etag: a.etag,
+            crc32c: a.crc32c,
+        }
+    }
+}
+
+impl From<ObjectAttrs> for objects::ObjectAttrs {
+    fn from(a: ObjectAttrs) -> Self {
+        Self {
+            name: a.name,
+            version: a.version,
+            size: a.size,
+            content_type: a.content_type,
+            etag: a.etag,
+            crc32c: a.crc32c,
+        }
+    }
+}
+
+/// The recorded form of [`objects::ListEntry`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ListEntry {
+    name: String,
+    size: u64,
+    etag: String,
+    is_prefix: bool,
+}
+
+impl From<objects::ListEntry> for ListEntry {
+    fn from(e: objects::ListEntry) -> Self {
+        Self {
+            name: e.name,
+            size: e.size,
+            etag: e.etag,
+            is_prefix: e.is_prefix,
+        }
+    }
+}
+
+impl From<ListEntry> for objects::ListEntry {
+    fn from(e: ListEntry) -> Self {
+        Self {
+            name: e.name,
+            size: e.size,
+            etag: e.etag,
+            is_prefix: e.is_prefix,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        for err in [
+            objects::Error::NotFound,
+            objects::Error::PreconditionFailed,
+            objects::Error::InvalidArgument,
+            objects::Error::ChecksumMismatch,
+        ] {
+            let decoded = decode_error(encode_error(&err));
+            assert_eq!(decoded.to_string(), err.to_string());
+        }
+        let err = decode_error(encode_error(&objects::Error::Other(anyhow::anyhow!(
+            "access denied"
+        ))));
+        assert!(matches!(err, objects::Error::Other(e) if e.to_string() == "access denied"));
+    }
+}
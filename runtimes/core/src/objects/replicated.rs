@@ -0,0 +1,363 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncRead;
+
+use crate::objects;
+use crate::EncoreName;
+
+use super::{
+    AttrsOptions, DeleteOptions, DownloadOptions, DownloadUrlOptions, ExistsOptions, ListOptions,
+    PublicUrlError, UploadUrlOptions,
+};
+
+/// How long a location is skipped after a read from it fails.
+const UNHEALTHY_FOR: Duration = Duration::from_secs(30);
+
+/// A bucket with read replicas in other locations.
+///
+/// Writes always go to the primary. Reads go to the first healthy location,
+/// preferring locations in the deployment's region and then the primary,
+/// and fail over to the next location if they fail.
+///
+/// Replication is asynchronous, so objects missing from a replica are read
+/// from the primary. Reads of specific object versions always use the primary,
+/// since versions aren't shared between locations.
+#[derive(Debug)]
+pub struct Bucket {
+    primary: Arc<dyn objects::BucketImpl>,
+    /// The locations to read from, in order of preference.
+    readers: Vec<Location>,
+}
+
+#[derive(Debug)]
+struct Location {
+    bucket: Arc<dyn objects::BucketImpl>,
+    region: Option<String>,
+    is_primary: bool,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Location {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .map_or(true, |until| now >= until)
+    }
+}
+
+impl Bucket {
+    pub fn new(
+        primary: Arc<dyn objects::BucketImpl>,
+        primary_region: Option<String>,
+        replicas: Vec<(Arc<dyn objects::BucketImpl>, Option<String>)>,
+        region: Option<&str>,
+    ) -> Self {
+        let location = |bucket, region, is_primary| Location {
+            bucket,
+            region,
+            is_primary,
+            unhealthy_until: Mutex::new(None),
+        };
+        let mut readers = vec![location(primary.clone(), primary_region, true)];
+        readers.extend(
+            replicas
+                .into_iter()
+                .map(|(bucket, region)| location(bucket, region, false)),
+        );
+        readers.sort_by_key(|loc| {
+            let nearby = region.is_some() && loc.region.as_deref() == region;
+            (!nearby, !loc.is_primary)
+        });
+        Self { primary, readers }
+    }
+
+    /// Runs a read operation against the preferred healthy location,
+    /// failing over to the next location if it fails.
+    async fn read<T, F, Fut>(&self, op: F) -> Result<T, objects::Error>
+    where
+        F: Fn(Arc<dyn objects::BucketImpl>) -> Fut,
+        Fut: Future<Output = Result<T, objects::Error>>,
+    {
+        let now = Instant::now();
+        let mut locations: Vec<&Location> = self
+            .readers
+            .iter()
+            .filter(|loc| loc.is_healthy(now))
+            .collect();
+        if locations.is_empty() {
+            // Everything is failing; try all locations rather than none.
+            locations = self.readers.iter().collect();
+        }
+
+        let mut last_err = None;
+        for loc in locations {
+            match op(loc.bucket.clone()).await {
+                Ok(val) => return Ok(val),
+
+                // The object may not have been replicated yet.
+                Err(objects::Error::NotFound) if !loc.is_primary => {
+                    last_err = Some(objects::Error::NotFound);
+                }
+
+                Err(
+                    err @ (objects::Error::NotFound
+                    | objects::Error::PreconditionFailed
+                    | objects::Error::InvalidArgument
+                    | objects::Error::ChecksumMismatch),
+                ) => return Err(err),
+
+                Err(err) => {
+                    log::warn!(
+                        "bucket {}: read from {} failed, failing over: {:?}",
+                        self.primary.name(),
+                        loc.region.as_deref().unwrap_or("replica"),
+                        err
+                    );
+                    *loc.unhealthy_until.lock().unwrap() = Some(Instant::now() + UNHEALTHY_FOR);
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or(objects::Error::NotFound))
+    }
+}
+
+#[derive(Debug)]
+pub struct Object {
+    bkt: Arc<Bucket>,
+    primary: Arc<dyn objects::ObjectImpl>,
+    name: String,
+}
+
+impl objects::BucketImpl for Bucket {
+    fn name(&self) -> &EncoreName {
+        self.primary.name()
+    }
+
+    fn object(self: Arc<Self>, name: String) -> Arc<dyn objects::ObjectImpl> {
+        Arc::new(Object {
+            primary: self.primary.clone().object(name.clone()),
+            bkt: self,
+            name,
+        })
+    }
+
+    fn list(
+        self: Arc<Self>,
+        options: ListOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::ListStream, objects::Error>> + Send + 'static>>
+    {
+        Box::pin(async move { self.read(|bkt| bkt.list(options.clone())).await })
+    }
+}
+
+impl Object {
+    fn replica_object(&self, bkt: Arc<dyn objects::BucketImpl>) -> Arc<dyn objects::ObjectImpl> {
+        bkt.object(self.name.clone())
+    }
+}
+
+impl objects::ObjectImpl for Object {
+    fn bucket_name(&self) -> &EncoreName {
+        self.primary.bucket_name()
+    }
+
+    fn key(&self) -> &str {
+        self.primary.key()
+    }
+
+    fn exists(
+        self: Arc<Self>,
+        options: ExistsOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, objects::Error>> + Send>> {
+        if options.version.is_some() {
+            return self.primary.clone().exists(options);
+        }
+        Box::pin(async move {
+            // Report missing objects as errors, so they're looked up in the primary.
+            let res = self
+                .bkt
+                .read(|bkt| {
+                    let fut = self.replica_object(bkt).exists(options.clone());
+                    async move {
+                        match fut.await {
+                            Ok(true) => Ok(()),
+                            Ok(false) => Err(objects::Error::NotFound),
+                            Err(err) => Err(err),
+                        }
+                    }
+                })
+                .await;
+            match res {
+                Ok(()) => Ok(true),
+                Err(objects::Error::NotFound) => Ok(false),
+                Err(err) => Err(err),
+            }
+        })
+    }
+
+    fn upload(
+        self: Arc<Self>,
+        data: Box<dyn AsyncRead + Unpin + Send + Sync + 'static>,
+        options: objects::UploadOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::ObjectAttrs, objects::Error>> + Send>> {
+        self.primary.clone().upload(data, options)
+    }
+
+    fn signed_upload_url(
+        self: Arc<Self>,
+        options: UploadUrlOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<String, objects::Error>> + Send>> {
+        self.primary.clone().signed_upload_url(options)
+    }
+
+    fn signed_download_url(
+        self: Arc<Self>,
+        options: DownloadUrlOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<String, objects::Error>> + Send>> {
+        Box::pin(async move {
+            self.bkt
+                .read(|bkt| {
+                    self.replica_object(bkt)
+                        .signed_download_url(options.clone())
+                })
+                .await
+        })
+    }
+
+    fn download(
+        self: Arc<Self>,
+        options: DownloadOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::DownloadStream, objects::Error>> + Send>> {
+        if options.version.is_some() {
+            return self.primary.clone().download(options);
+        }
+        // Only starting the download fails over; errors while streaming
+        // the object are returned to the caller.
+        Box::pin(async move {
+            self.bkt
+                .read(|bkt| self.replica_object(bkt).download(options.clone()))
+                .await
+        })
+    }
+
+    fn attrs(
+        self: Arc<Self>,
+        options: AttrsOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<objects::ObjectAttrs, objects::Error>> + Send>> {
+        if options.version.is_some() {
+            return self.primary.clone().attrs(options);
+        }
+        Box::pin(async move {
+            self.bkt
+                .read(|bkt| self.replica_object(bkt).attrs(options.clone()))
+                .await
+        })
+    }
+
+    fn delete(
+        self: Arc<Self>,
+        options: DeleteOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<(), objects::Error>> + Send>> {
+        self.primary.clone().delete(options)
+    }
+
+    fn public_url(&self) -> Result<String, PublicUrlError> {
+        self.primary.public_url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::objects::noop;
+
+    fn bucket() -> Arc<dyn objects::BucketImpl> {
+        Arc::new(noop::Bucket::new("bucket".into()))
+    }
+
+    #[test]
+    fn test_read_order() {
+        let bkt = Bucket::new(
+            bucket(),
+            Some("us-east-1".into()),
+            vec![
+                (bucket(), Some("us-west-2".into())),
+                (bucket(), Some("eu-west-1".into())),
+                (bucket(), None),
+            ],
+            Some("eu-west-1"),
+        );
+        let order: Vec<_> = bkt
+            .readers
+            .iter()
+            .map(|loc| (loc.region.as_deref(), loc.is_primary))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (Some("eu-west-1"), false),
+                (Some("us-east-1"), true),
+                (Some("us-west-2"), false),
+                (None, false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_failover() {
+        let bkt = Bucket::new(
+            bucket(),
+            Some("us-east-1".into()),
+            vec![(bucket(), Some("eu-west-1".into()))],
+            Some("eu-west-1"),
+        );
+
+        // The replica fails, so the read is retried against the primary.
+        let calls = AtomicUsize::new(0);
+        let res = bkt
+            .read(|_| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match call {
+                        0 => Err(objects::Error::Other(anyhow::anyhow!("unavailable"))),
+                        _ => Ok(call),
+                    }
+                }
+            })
+            .await;
+        assert_eq!(res.unwrap(), 1);
+        assert!(!bkt.readers[0].is_healthy(Instant::now()));
+
+        // The unhealthy replica is skipped until it recovers.
+        let res = bkt.read(|_| async { Ok(()) }).await;
+        assert!(res.is_ok());
+        assert!(!bkt.readers[0].is_healthy(Instant::now()));
+        assert!(bkt.readers[0].is_healthy(Instant::now() + UNHEALTHY_FOR));
+
+        // Objects missing from a replica are read from the primary,
+        // but objects missing from the primary are not found.
+        let bkt = Bucket::new(
+            bucket(),
+            None,
+            vec![(bucket(), Some("eu-west-1".into()))],
+            Some("eu-west-1"),
+        );
+        let calls = AtomicUsize::new(0);
+        let res: Result<(), _> = bkt
+            .read(|_| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Err(objects::Error::NotFound) }
+            })
+            .await;
+        assert!(matches!(res, Err(objects::Error::NotFound)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(bkt.readers[0].is_healthy(Instant::now()));
+    }
+}
@@ -1,6 +1,6 @@
 use async_stream::{stream, try_stream};
 use aws_sdk_s3 as s3;
-use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
 use aws_sdk_s3::presigning::PresigningConfig;
 use aws_smithy_types::byte_stream::ByteStream;
 use base64::Engine;
@@ -12,6 +12,7 @@ use std::sync::Arc;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
 use crate::encore::runtime::v1 as pb;
+use crate::objects::checksum::{self, Crc32c};
 use crate::objects::{
     self, AttrsOptions, DeleteOptions, DownloadOptions, DownloadUrlOptions, Error, ExistsOptions,
     ListEntry, ListOptions, ObjectAttrs, PublicUrlError, UploadUrlOptions,
@@ -101,6 +102,14 @@ impl objects::BucketImpl for Bucket {
                     req = req.prefix(new_prefix);
                 }
 
+                if let Some(delimiter) = &options.delimiter {
+                    req = req.delimiter(delimiter);
+                }
+
+                if let Some(cursor) = &options.cursor {
+                    req = req.start_after(self.obj_name(Cow::Borrowed(cursor)));
+                }
+
                 let page_size = if let Some(limit) = options.limit {
                     limit.min(1000) as i32
                 } else {
@@ -113,7 +122,24 @@ impl objects::BucketImpl for Bucket {
 
                 'PageLoop:
                 while let Some(resp) = stream.try_next().await.map_err(|e| Error::Other(e.into()))? {
-                    for obj in resp.contents.unwrap_or_default() {
+                    // Objects and common prefixes are returned separately,
+                    // so merge them to yield the entries in order.
+                    let mut entries: Vec<ListEntry> = resp.contents.unwrap_or_default().into_iter().map(|obj| ListEntry {
+                        name: self.strip_prefix(Cow::Owned(obj.key.unwrap_or_default())).into_owned(),
+                        size: obj.size.unwrap_or_default() as u64,
+                        etag: parse_etag(obj.e_tag),
+                        is_prefix: false,
+                    }).collect();
+                    entries.extend(resp.common_prefixes.unwrap_or_default().into_iter().map(|p| {
+                        ListEntry::prefix(self.strip_prefix(Cow::Owned(p.prefix.unwrap_or_default())).into_owned())
+                    }));
+                    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+                    for entry in entries {
+                        if !options.after_cursor(&entry.name) {
+                            continue;
+                        }
+
                         total_seen += 1;
                         if let Some(limit) = options.limit {
                             if total_seen > limit {
@@ -122,11 +148,6 @@ impl objects::BucketImpl for Bucket {
                             }
                         }
 
-                        let entry = ListEntry {
-                            name: self.strip_prefix(Cow::Owned(obj.key.unwrap_or_default())).into_owned(),
-                            size: obj.size.unwrap_or_default() as u64,
-                            etag: parse_etag(obj.e_tag),
-                        };
                         yield entry;
                     }
                 }
@@ -164,6 +185,7 @@ impl objects::ObjectImpl for Object {
                 .bucket(&self.bkt.cloud_name)
                 .key(cloud_name)
                 .set_version_id(options.version)
+                .checksum_mode(s3::types::ChecksumMode::Enabled)
                 .send()
                 .await;
 
@@ -174,6 +196,7 @@ impl objects::ObjectImpl for Object {
                     size: obj.content_length.unwrap_or_default() as u64,
                     content_type: obj.content_type,
                     etag: parse_etag(obj.e_tag),
+                    crc32c: checksum::full_object_checksum(obj.checksum_crc32c),
                 }),
                 Err(SdkError::ServiceError(err)) if err.err().is_not_found() => {
                     Err(Error::NotFound)
@@ -272,6 +295,7 @@ impl objects::ObjectImpl for Object {
                     let total_size = chunk.len();
                     let content_md5 = base64::engine::general_purpose::STANDARD
                         .encode(md5::compute(&chunk).as_ref());
+                    let crc32c = Crc32c::of(&chunk);
 
                     let mut req = client
                         .put_object()
@@ -279,13 +303,14 @@ impl objects::ObjectImpl for Object {
                         .key(cloud_name)
                         .content_length(total_size as i64)
                         .content_md5(content_md5)
+                        .checksum_crc32c(crc32c.clone())
                         .set_content_type(options.content_type.clone())
                         .body(ByteStream::from(chunk));
 
-                    if let Some(precond) = options.preconditions {
-                        if precond.not_exists == Some(true) {
-                            req = req.if_none_match("*");
-                        }
+                    if let Some(precond) = &options.preconditions {
+                        req = req
+                            .set_if_match(precond.if_match.clone())
+                            .set_if_none_match(upload_if_none_match(precond));
                     }
 
                     let resp = req.send().await.map_err(map_upload_err)?;
@@ -295,6 +320,7 @@ impl objects::ObjectImpl for Object {
                         size: total_size as u64,
                         content_type: options.content_type,
                         etag: resp.e_tag.unwrap_or_default(),
+                        crc32c: Some(crc32c),
                     })
                 }
 
@@ -338,6 +364,8 @@ impl objects::ObjectImpl for Object {
                             size: total_size,
                             content_type: options.content_type,
                             etag: parse_etag(output.e_tag),
+                            // Parts are checksummed individually.
+                            crc32c: None,
                         });
                     }
 
@@ -354,7 +382,7 @@ impl objects::ObjectImpl for Object {
 
                     Err(match res {
                         UploadMultipartResult::CompleteSuccess { .. } => unreachable!(),
-                        UploadMultipartResult::UploadError(err) => Error::Other(err.into()),
+                        UploadMultipartResult::UploadError(err) => map_upload_err(err),
                         UploadMultipartResult::CompleteError(err) => map_upload_err(err),
                         UploadMultipartResult::ReadContents(err) => Error::Other(anyhow::anyhow!(
                             "unable to read from data source: {}",
@@ -373,27 +401,49 @@ impl objects::ObjectImpl for Object {
         Box::pin(async move {
             let client = self.bkt.client.get().await.clone();
             let cloud_name = self.bkt.obj_name(Cow::Borrowed(&self.name));
+            let precond = options.preconditions.unwrap_or_default();
+            let range = options.range.map(|r| r.header()).transpose()?;
+            let full_object = range.is_none();
             let res = client
                 .get_object()
                 .bucket(&self.bkt.cloud_name)
                 .key(cloud_name.into_owned())
                 .set_version_id(options.version)
+                .set_if_match(precond.if_match)
+                .set_if_none_match(precond.if_none_match)
+                .set_range(range)
+                .checksum_mode(s3::types::ChecksumMode::Enabled)
                 .send()
                 .await;
 
             match res {
                 Ok(mut resp) => {
+                    let expected = checksum::full_object_checksum(resp.checksum_crc32c.take());
                     let result = stream! {
                         while let Some(chunk) = resp.body.next().await {
                             yield chunk.map_err(|e| objects::Error::Other(e.into()));
                         }
                     };
                     let result: objects::DownloadStream = Box::pin(result);
-                    Ok(result)
+                    match expected {
+                        Some(expected) if full_object => {
+                            Ok(checksum::verify_download(result, expected))
+                        }
+                        _ => Ok(result),
+                    }
                 }
                 Err(SdkError::ServiceError(err)) if err.err().is_no_such_key() => {
                     Err(objects::Error::NotFound)
                 }
+                // S3 reports a matching If-None-Match as Not Modified.
+                Err(err) if is_precondition_failed(&err) => Err(Error::PreconditionFailed),
+                Err(err)
+                    if err
+                        .raw_response()
+                        .is_some_and(|r| r.status().as_u16() == 416) =>
+                {
+                    Err(Error::InvalidArgument)
+                }
                 Err(err) => Err(objects::Error::Other(err.into())),
             }
         })
@@ -404,6 +454,12 @@ impl objects::ObjectImpl for Object {
         options: DeleteOptions,
     ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
         Box::pin(async move {
+            let precond = options.preconditions.unwrap_or_default();
+            if precond.if_none_match.is_some() {
+                // S3 only supports If-Match for deletes.
+                return Err(Error::InvalidArgument);
+            }
+
             let client = self.bkt.client.get().await.clone();
             let cloud_name = self.bkt.obj_name(Cow::Borrowed(&self.name));
             let res = client
@@ -411,6 +467,7 @@ impl objects::ObjectImpl for Object {
                 .bucket(&self.bkt.cloud_name)
                 .key(cloud_name.into_owned())
                 .set_version_id(options.version)
+                .set_if_match(precond.if_match)
                 .send()
                 .await;
             match res {
@@ -418,6 +475,7 @@ impl objects::ObjectImpl for Object {
                 Err(SdkError::ServiceError(err)) if err.raw().status().as_u16() == 404 => {
                     Err(Error::NotFound)
                 }
+                Err(err) if is_precondition_failed(&err) => Err(Error::PreconditionFailed),
                 Err(err) => Err(Error::Other(err.into())),
             }
         })
@@ -556,9 +614,9 @@ async fn upload_multipart_chunks<R: AsyncRead + Unpin + ?Sized>(
         .multipart_upload(multipart_upload);
 
     if let Some(precond) = &options.preconditions {
-        if precond.not_exists == Some(true) {
-            req = req.if_none_match("*");
-        }
+        req = req
+            .set_if_match(precond.if_match.clone())
+            .set_if_none_match(upload_if_none_match(precond));
     }
 
     let resp = req.send().await;
@@ -581,15 +639,29 @@ fn parse_etag(s: Option<String>) -> String {
     }
 }
 
+/// Returns the If-None-Match header for an upload, if any.
+fn upload_if_none_match(precond: &objects::UploadPreconditions) -> Option<String> {
+    if precond.not_exists == Some(true) {
+        Some("*".to_string())
+    } else {
+        precond.if_none_match.clone()
+    }
+}
+
+fn is_precondition_failed<E>(err: &s3::error::SdkError<E>) -> bool {
+    err.raw_response()
+        .is_some_and(|r| matches!(r.status().as_u16(), 304 | 412))
+}
+
 fn map_upload_err<E>(err: s3::error::SdkError<E>) -> objects::Error
 where
-    E: std::fmt::Debug,
+    E: std::fmt::Debug + ProvideErrorMetadata,
 {
-    if err
-        .raw_response()
-        .is_some_and(|r| r.status().as_u16() == 412)
-    {
+    if is_precondition_failed(&err) {
         Error::PreconditionFailed
+    } else if err.code() == Some("BadDigest") {
+        // The uploaded data doesn't match the checksums sent along with it.
+        Error::ChecksumMismatch
     } else {
         Error::Other(anyhow::anyhow!("failed to upload: {:?}", err))
     }
@@ -34,6 +34,13 @@ impl ProcessConfig {
                         name: s.clone(),
                         log_config: None,
                         worker_threads: None,
+                        concurrency_limit: None,
+                        endpoint_concurrency_limits: HashMap::new(),
+                        log_output: None,
+                        http_server: None,
+                        response_caches: HashMap::new(),
+                        endpoint_slos: HashMap::new(),
+                        egress_policy: None,
                     })
             })
             .collect();
@@ -66,6 +73,11 @@ impl ProcessConfig {
                 runtimepb::service_discovery::Location {
                     base_url: base_url.clone(),
                     auth_methods: deployment.auth_methods.clone(),
+                    additional_base_urls: vec![],
+                    health_check: None,
+                    dns: None,
+                    kubernetes: None,
+                    hedging: None,
                 },
             );
         }
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use http::header::AUTHORIZATION;
+use http::{HeaderMap, Method};
+use sha2::Sha256;
+
+use crate::azure::ManagedIdentity;
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub;
+use crate::pubsub::azure::sub::ServiceBusSubscription;
+use crate::pubsub::azure::topic::ServiceBusTopic;
+use crate::secrets;
+
+mod sub;
+mod topic;
+
+/// The OAuth resource for Azure Service Bus.
+const SERVICE_BUS_RESOURCE: &str = "https://servicebus.azure.net/";
+
+/// The domain of Service Bus namespaces given by name.
+const NAMESPACE_SUFFIX: &str = "servicebus.windows.net";
+
+/// The header holding the broker properties of a message, such as its id.
+const BROKER_PROPERTIES: &str = "brokerproperties";
+
+/// How long shared access signature tokens are valid for.
+const SAS_TOKEN_VALIDITY: Duration = Duration::from_secs(60 * 60);
+
+pub struct Cluster {
+    client: Arc<Client>,
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("endpoint", &self.client.endpoint.as_str())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cluster {
+    pub fn new(
+        cfg: &pb::pub_sub_cluster::AzureServiceBus,
+        secrets: &secrets::Manager,
+    ) -> anyhow::Result<Self> {
+        use pb::pub_sub_cluster::azure_service_bus::Credentials;
+
+        let mut namespace = cfg.namespace.clone();
+        let credential = match &cfg.credentials {
+            Some(Credentials::ConnectionString(data)) => {
+                let secret = secrets.load(data.clone());
+                let cs = secret
+                    .get()
+                    .context("unable to resolve Service Bus connection string")?;
+                let cs = std::str::from_utf8(cs)
+                    .context("Service Bus connection string is not utf-8")?;
+                let cs =
+                    ConnectionString::parse(cs).context("invalid Service Bus connection string")?;
+                if namespace.is_empty() {
+                    namespace = cs.namespace;
+                }
+                Credential::SharedAccessKey {
+                    name: cs.key_name,
+                    key: cs.key,
+                }
+            }
+            Some(Credentials::ManagedIdentity(mi)) => Credential::ManagedIdentity(
+                ManagedIdentity::new(mi.client_id.clone(), SERVICE_BUS_RESOURCE),
+            ),
+            None => Credential::ManagedIdentity(ManagedIdentity::new(None, SERVICE_BUS_RESOURCE)),
+        };
+
+        Ok(Self {
+            client: Arc::new(Client {
+                endpoint: namespace_endpoint(&namespace)?,
+                credential,
+                http: crate::egress::client(),
+            }),
+        })
+    }
+}
+
+impl pubsub::Cluster for Cluster {
+    fn topic(
+        &self,
+        cfg: &pb::PubSubTopic,
+        _publisher_id: xid::Id,
+    ) -> Arc<dyn pubsub::Topic + 'static> {
+        Arc::new(ServiceBusTopic::new(self.client.clone(), cfg))
+    }
+
+    fn subscription(
+        &self,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Arc<dyn pubsub::Subscription + 'static> {
+        Arc::new(ServiceBusSubscription::new(self.client.clone(), cfg, meta))
+    }
+}
+
+/// How to authenticate requests to the namespace.
+enum Credential {
+    /// Signs requests with a shared access key.
+    SharedAccessKey { name: String, key: String },
+    /// Authorizes requests with an Entra ID token for a managed identity.
+    ManagedIdentity(ManagedIdentity),
+}
+
+/// A client for the Service Bus REST API.
+struct Client {
+    endpoint: url::Url,
+    credential: Credential,
+    http: reqwest::Client,
+}
+
+impl Client {
+    /// Returns the URL of an entity within the namespace, given its path segments.
+    fn url(&self, segments: &[&str]) -> url::Url {
+        let mut url = self.endpoint.clone();
+        url.path_segments_mut()
+            .expect("endpoint is a valid base url")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: url::Url,
+        headers: HeaderMap,
+        body: Option<Vec<u8>>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let authorization = match &self.credential {
+            Credential::SharedAccessKey { name, key } => {
+                let expiry = SystemTime::now() + SAS_TOKEN_VALIDITY;
+                sas_token(self.endpoint.as_str(), name, key, expiry)
+            }
+            Credential::ManagedIdentity(mi) => {
+                format!("Bearer {}", mi.token(&self.http).await?)
+            }
+        };
+
+        let mut req = self
+            .http
+            .request(method, url)
+            .headers(headers)
+            .header(AUTHORIZATION, authorization);
+        if let Some(body) = body {
+            req = req.body(body);
+        } else {
+            req = req.header(http::header::CONTENT_LENGTH, 0);
+        }
+
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            anyhow::bail!("service bus request failed with status {status}: {body}");
+        }
+        Ok(resp)
+    }
+}
+
+/// Returns the endpoint of a namespace, given by name or fully qualified host.
+fn namespace_endpoint(namespace: &str) -> anyhow::Result<url::Url> {
+    if namespace.is_empty() {
+        anyhow::bail!("no Service Bus namespace configured");
+    }
+    let host = if namespace.contains('.') {
+        namespace.to_string()
+    } else {
+        format!("{namespace}.{NAMESPACE_SUFFIX}")
+    };
+    url::Url::parse(&format!("https://{host}/")).context("invalid Service Bus namespace")
+}
+
+/// The parsed fields of a Service Bus connection string.
+#[derive(Debug, PartialEq)]
+struct ConnectionString {
+    /// The fully qualified namespace host.
+    namespace: String,
+    key_name: String,
+    key: String,
+}
+
+impl ConnectionString {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        let fields: HashMap<&str, &str> = s
+            .split(';')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .filter_map(|part| part.split_once('='))
+            .collect();
+
+        let endpoint = fields.get("Endpoint").context("missing Endpoint")?;
+        let namespace = url::Url::parse(endpoint)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .context("invalid Endpoint")?;
+
+        Ok(Self {
+            namespace,
+            key_name: fields
+                .get("SharedAccessKeyName")
+                .context("missing SharedAccessKeyName")?
+                .to_string(),
+            key: fields
+                .get("SharedAccessKey")
+                .context("missing SharedAccessKey")?
+                .to_string(),
+        })
+    }
+}
+
+/// Computes a shared access signature token for the resource.
+/// See https://learn.microsoft.com/en-us/azure/service-bus-messaging/service-bus-sas.
+fn sas_token(resource: &str, key_name: &str, key: &str, expiry: SystemTime) -> String {
+    let expiry = expiry
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let resource: String = url::form_urlencoded::byte_serialize(resource.as_bytes()).collect();
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(format!("{resource}\n{expiry}").as_bytes());
+    let sig = STANDARD.encode(mac.finalize().into_bytes());
+    let sig: String = url::form_urlencoded::byte_serialize(sig.as_bytes()).collect();
+
+    format!("SharedAccessSignature sr={resource}&sig={sig}&se={expiry}&skn={key_name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_connection_string() {
+        let cs = ConnectionString::parse(
+            "Endpoint=sb://my-ns.servicebus.windows.net/;SharedAccessKeyName=RootManageSharedAccessKey;SharedAccessKey=a2V5PQ==",
+        )
+        .unwrap();
+        assert_eq!(
+            cs,
+            ConnectionString {
+                namespace: "my-ns.servicebus.windows.net".into(),
+                key_name: "RootManageSharedAccessKey".into(),
+                key: "a2V5PQ==".into(),
+            }
+        );
+
+        assert!(ConnectionString::parse("Endpoint=sb://my-ns.servicebus.windows.net/").is_err());
+    }
+
+    #[test]
+    fn test_namespace_endpoint() {
+        assert_eq!(
+            namespace_endpoint("my-ns").unwrap().as_str(),
+            "https://my-ns.servicebus.windows.net/"
+        );
+        assert_eq!(
+            namespace_endpoint("my-ns.servicebus.chinacloudapi.cn")
+                .unwrap()
+                .as_str(),
+            "https://my-ns.servicebus.chinacloudapi.cn/"
+        );
+        assert!(namespace_endpoint("").is_err());
+    }
+
+    #[test]
+    fn test_sas_token() {
+        let expiry = UNIX_EPOCH + Duration::from_secs(1700000000);
+        let token = sas_token(
+            "https://my-ns.servicebus.windows.net/",
+            "send",
+            "secret",
+            expiry,
+        );
+        let sig = {
+            let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+            mac.update(b"https%3A%2F%2Fmy-ns.servicebus.windows.net%2F\n1700000000");
+            let sig = STANDARD.encode(mac.finalize().into_bytes());
+            url::form_urlencoded::byte_serialize(sig.as_bytes()).collect::<String>()
+        };
+        assert_eq!(
+            token,
+            format!(
+                "SharedAccessSignature sr=https%3A%2F%2Fmy-ns.servicebus.windows.net%2F&sig={sig}&se=1700000000&skn=send"
+            )
+        );
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use http::header::LOCATION;
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use serde::Deserialize;
+
+use crate::api::APIResult;
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub;
+use crate::pubsub::azure::{Client, BROKER_PROPERTIES};
+use crate::pubsub::manager::SubHandler;
+use crate::pubsub::retry::RetryPolicy;
+use crate::pubsub::Subscription;
+
+/// The custom property holding the message attributes.
+///
+/// Custom properties are sent as HTTP headers, whose names are not case-sensitive,
+/// so the attributes are encoded in a single property to preserve their names.
+pub(super) const ATTRS_PROPERTY: &str = "encore-attributes";
+
+/// The maximum number of concurrent receive requests per subscription.
+/// Each receiver processes one message at a time.
+const MAX_RECEIVERS: usize = 32;
+
+/// How long to wait for a message before polling again.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(55);
+
+/// How long to wait before receiving again after a failed receive.
+const RECEIVE_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+/// How long before the message lock expires to renew it.
+const LOCK_RENEWAL_MARGIN: Duration = Duration::from_secs(10);
+
+pub struct ServiceBusSubscription {
+    client: Arc<Client>,
+    topic: String,
+    subscription: String,
+    receivers: usize,
+    retry: RetryPolicy,
+}
+
+impl Debug for ServiceBusSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceBusSubscription")
+            .field("topic", &self.topic)
+            .field("subscription", &self.subscription)
+            .finish()
+    }
+}
+
+impl ServiceBusSubscription {
+    pub(super) fn new(
+        client: Arc<Client>,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Self {
+        Self {
+            client,
+            topic: cfg.topic_cloud_name.clone(),
+            subscription: cfg.subscription_cloud_name.clone(),
+            receivers: meta
+                .max_concurrency
+                .map_or(MAX_RECEIVERS, |v| (v.max(1) as usize).min(MAX_RECEIVERS)),
+            retry: RetryPolicy::new(meta),
+        }
+    }
+}
+
+impl Subscription for ServiceBusSubscription {
+    fn subscribe(
+        &self,
+        handler: Arc<SubHandler>,
+    ) -> Pin<Box<dyn Future<Output = APIResult<()>> + Send + 'static>> {
+        let receiver = Arc::new(Receiver {
+            client: self.client.clone(),
+            topic: self.topic.clone(),
+            subscription: self.subscription.clone(),
+            retry: self.retry,
+        });
+        let receivers = self.receivers;
+
+        Box::pin(async move {
+            let mut set = tokio::task::JoinSet::new();
+            for _ in 0..receivers {
+                let receiver = receiver.clone();
+                let handler = handler.clone();
+                set.spawn(async move { receiver.run(&handler).await });
+            }
+            while set.join_next().await.is_some() {}
+            Ok(())
+        })
+    }
+}
+
+/// Receives messages from a subscription using peek-lock,
+/// completing them once processed.
+struct Receiver {
+    client: Arc<Client>,
+    topic: String,
+    subscription: String,
+    retry: RetryPolicy,
+}
+
+/// A message locked for processing.
+struct LockedMessage {
+    props: ReceivedProperties,
+    /// The URL of the lock, for completing, unlocking or renewing it.
+    lock_url: url::Url,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ReceivedProperties {
+    message_id: String,
+    delivery_count: u32,
+    enqueued_time_utc: Option<String>,
+    locked_until_utc: Option<String>,
+}
+
+impl Receiver {
+    async fn run(&self, handler: &SubHandler) {
+        loop {
+            let msg = match self.receive().await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::warn!(
+                        "unable to receive Service Bus message from {}/{}: {:#}",
+                        self.topic,
+                        self.subscription,
+                        err
+                    );
+                    tokio::time::sleep(RECEIVE_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+
+            if let Err(err) = self.process(handler, msg).await {
+                log::warn!("unable to settle Service Bus message: {:#}", err);
+            }
+        }
+    }
+
+    /// Receives and locks the next message, if any arrives before the timeout.
+    async fn receive(&self) -> anyhow::Result<Option<LockedMessage>> {
+        let mut url = self.client.url(&[
+            &self.topic,
+            "subscriptions",
+            &self.subscription,
+            "messages",
+            "head",
+        ]);
+        url.query_pairs_mut()
+            .append_pair("timeout", &RECEIVE_TIMEOUT.as_secs().to_string());
+
+        let resp = self
+            .client
+            .send(Method::POST, url, HeaderMap::new(), None)
+            .await?;
+        if resp.status() == StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+
+        let headers = resp.headers().clone();
+        let props: ReceivedProperties = headers
+            .get(BROKER_PROPERTIES)
+            .context("missing BrokerProperties")
+            .and_then(|v| Ok(serde_json::from_slice(v.as_bytes())?))
+            .context("invalid BrokerProperties")?;
+        let lock_url = headers
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| url::Url::parse(v).ok())
+            .context("missing message lock location")?;
+        let body = resp.bytes().await?.to_vec();
+
+        Ok(Some(LockedMessage {
+            props,
+            lock_url,
+            headers,
+            body,
+        }))
+    }
+
+    async fn process(&self, handler: &SubHandler, msg: LockedMessage) -> anyhow::Result<()> {
+        let attempt = msg.props.delivery_count.max(1);
+
+        // Messages that exhausted their retries are abandoned without being processed
+        // again, until Service Bus moves them to the subscription's dead-letter queue.
+        if attempt > 1 && !self.retry.should_retry(attempt - 1) {
+            return self.unlock(&msg.lock_url).await;
+        }
+
+        let mut locked_until = msg.props.locked_until_utc.as_deref().and_then(parse_time);
+        let result = {
+            let handle = handler.handle_message(pubsub::Message {
+                id: msg.props.message_id.clone(),
+                publish_time: msg.props.enqueued_time_utc.as_deref().and_then(parse_time),
+                attempt,
+                data: pubsub::MessageData {
+                    attrs: decode_attrs(&msg.headers),
+                    raw_body: msg.body,
+                },
+            });
+            tokio::pin!(handle);
+
+            // Keep the message locked while it's being processed.
+            loop {
+                tokio::select! {
+                    result = &mut handle => break result,
+                    _ = tokio::time::sleep(renewal_delay(locked_until)) => {
+                        match self.renew_lock(&msg.lock_url).await {
+                            Ok(until) => locked_until = until,
+                            Err(err) => {
+                                log::warn!("unable to renew Service Bus message lock: {:#}", err);
+                                locked_until = None;
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        let Err(err) = result else {
+            return self.complete(&msg.lock_url).await;
+        };
+
+        if !self.retry.should_retry(attempt) {
+            // The REST API can't dead-letter messages, so the message is abandoned
+            // and dead-lettered by Service Bus once it exceeds the subscription's
+            // maximum delivery count.
+            log::warn!(
+                "message {} failed {} times, dead-lettering it: {:?}",
+                msg.props.message_id,
+                attempt,
+                err
+            );
+            return self.unlock(&msg.lock_url).await;
+        }
+
+        // Unlocking the message makes it available again, so back off
+        // before doing so, for as long as the message remains locked.
+        let delay = self.retry.delay(attempt);
+        log::info!(
+            "message handler failed, retrying message in {:?}: {:?}",
+            delay,
+            err
+        );
+        tokio::time::sleep(delay.min(renewal_delay(locked_until))).await;
+        self.unlock(&msg.lock_url).await
+    }
+
+    async fn complete(&self, lock_url: &url::Url) -> anyhow::Result<()> {
+        self.client
+            .send(Method::DELETE, lock_url.clone(), HeaderMap::new(), None)
+            .await
+            .context("unable to complete message")?;
+        Ok(())
+    }
+
+    async fn unlock(&self, lock_url: &url::Url) -> anyhow::Result<()> {
+        self.client
+            .send(Method::PUT, lock_url.clone(), HeaderMap::new(), None)
+            .await
+            .context("unable to unlock message")?;
+        Ok(())
+    }
+
+    /// Renews the message lock, returning when it expires.
+    async fn renew_lock(&self, lock_url: &url::Url) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let resp = self
+            .client
+            .send(Method::POST, lock_url.clone(), HeaderMap::new(), None)
+            .await?;
+        Ok(resp
+            .headers()
+            .get(BROKER_PROPERTIES)
+            .and_then(|v| serde_json::from_slice::<ReceivedProperties>(v.as_bytes()).ok())
+            .and_then(|props| props.locked_until_utc)
+            .as_deref()
+            .and_then(parse_time))
+    }
+}
+
+/// Returns how long to wait before renewing a lock that expires at the given time.
+fn renewal_delay(locked_until: Option<DateTime<Utc>>) -> Duration {
+    // Service Bus locks last 30 seconds unless configured otherwise.
+    let remaining = locked_until
+        .and_then(|t| (t - Utc::now()).to_std().ok())
+        .unwrap_or(Duration::from_secs(30));
+    remaining
+        .saturating_sub(LOCK_RENEWAL_MARGIN)
+        .max(Duration::from_secs(1))
+}
+
+/// Parses a time as formatted in broker properties,
+/// e.g. "Wed, 02 Jul 2014 01:32:27 GMT".
+fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(s)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Encodes message attributes as a custom property value.
+pub(super) fn encode_attrs(attrs: &HashMap<String, String>) -> anyhow::Result<HeaderValue> {
+    let json = serde_json::to_vec(attrs)?;
+    // String property values are quoted.
+    Ok(HeaderValue::from_str(&format!(
+        "\"{}\"",
+        URL_SAFE_NO_PAD.encode(json)
+    ))?)
+}
+
+/// Decodes the message attributes from the custom properties of a received message.
+///
+/// Messages published by other clients carry their attributes as individual
+/// string properties instead, which are used if the attributes property is missing.
+fn decode_attrs(headers: &HeaderMap) -> HashMap<String, String> {
+    let quoted = |v: &HeaderValue| {
+        v.to_str()
+            .ok()
+            .filter(|s| s.len() >= 2 && s.starts_with('"') && s.ends_with('"'))
+            .and_then(|s| serde_json::from_str::<String>(s).ok())
+    };
+
+    if let Some(encoded) = headers.get(ATTRS_PROPERTY).and_then(quoted) {
+        if let Some(attrs) = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+        {
+            return attrs;
+        }
+    }
+
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), quoted(value)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attrs_roundtrip() {
+        let attrs = HashMap::from([
+            ("userId".to_string(), "123".to_string()),
+            ("kind".to_string(), "signup \"beta\"".to_string()),
+        ]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ATTRS_PROPERTY, encode_attrs(&attrs).unwrap());
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        assert_eq!(decode_attrs(&headers), attrs);
+    }
+
+    #[test]
+    fn test_decode_attrs_from_properties() {
+        let mut headers = HeaderMap::new();
+        headers.insert("priority", HeaderValue::from_static("\"high\""));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        assert_eq!(
+            decode_attrs(&headers),
+            HashMap::from([("priority".to_string(), "high".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(
+            parse_time("Wed, 02 Jul 2014 01:32:27 GMT").map(|t| t.timestamp()),
+            Some(1404264747)
+        );
+        assert_eq!(parse_time("yesterday"), None);
+    }
+
+    #[test]
+    fn test_renewal_delay() {
+        let until = Utc::now() + chrono::Duration::seconds(60);
+        let delay = renewal_delay(Some(until));
+        assert!(delay > Duration::from_secs(45) && delay <= Duration::from_secs(50));
+
+        // Expired locks are renewed right away.
+        let until = Utc::now() - chrono::Duration::seconds(5);
+        assert_eq!(renewal_delay(Some(until)), Duration::from_secs(1));
+    }
+}
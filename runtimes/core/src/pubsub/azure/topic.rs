@@ -0,0 +1,78 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, HeaderValue, Method};
+use serde::Serialize;
+
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub::azure::{sub, Client, BROKER_PROPERTIES};
+use crate::pubsub::{MessageData, MessageId, Topic};
+
+pub struct ServiceBusTopic {
+    client: Arc<Client>,
+    cloud_name: String,
+}
+
+impl std::fmt::Debug for ServiceBusTopic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceBusTopic")
+            .field("cloud_name", &self.cloud_name)
+            .finish()
+    }
+}
+
+impl ServiceBusTopic {
+    pub(super) fn new(client: Arc<Client>, cfg: &pb::PubSubTopic) -> Self {
+        Self {
+            client,
+            cloud_name: cfg.cloud_name.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct BrokerProperties<'a> {
+    message_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partition_key: Option<&'a str>,
+}
+
+impl Topic for ServiceBusTopic {
+    fn publish(
+        &self,
+        msg: MessageData,
+        ordering_key: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
+        Box::pin(async move {
+            let id = xid::new().to_string();
+
+            // Messages with the same partition key are stored in the same
+            // partition of partitioned topics.
+            let props = BrokerProperties {
+                message_id: &id,
+                partition_key: ordering_key.as_deref(),
+            };
+
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            headers.insert(
+                BROKER_PROPERTIES,
+                HeaderValue::from_str(&serde_json::to_string(&props)?)?,
+            );
+            if !msg.attrs.is_empty() {
+                headers.insert(sub::ATTRS_PROPERTY, sub::encode_attrs(&msg.attrs)?);
+            }
+
+            let url = self.client.url(&[&self.cloud_name, "messages"]);
+            self.client
+                .send(Method::POST, url, headers, Some(msg.raw_body))
+                .await
+                .context("unable to publish message")?;
+            Ok(id)
+        })
+    }
+}
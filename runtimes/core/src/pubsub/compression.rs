@@ -0,0 +1,118 @@
+use std::io::{Read, Write};
+
+use anyhow::Context;
+
+use crate::encore::runtime::v1::pub_sub_topic::Compression;
+use crate::pubsub::MessageData;
+
+/// The message attribute that records how the message body is compressed.
+pub(super) const ATTR_CONTENT_ENCODING: &str = "encore_content_encoding";
+
+/// The maximum size of a decompressed message body,
+/// to guard against messages that decompress to huge sizes.
+const MAX_DECOMPRESSED_SIZE: u64 = 256 * 1024 * 1024;
+
+const GZIP: &str = "gzip";
+const ZSTD: &str = "zstd";
+
+/// Compresses the message body, returning None if the topic doesn't use compression.
+pub(super) fn compress(compression: Compression, body: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let compressed = match compression {
+        Compression::Unspecified => return Ok(None),
+        Compression::Gzip => {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            enc.write_all(body)
+                .and_then(|_| enc.finish())
+                .context("unable to gzip message payload")?
+        }
+        Compression::Zstd => zstd::encode_all(body, zstd::DEFAULT_COMPRESSION_LEVEL)
+            .context("unable to zstd compress message payload")?,
+    };
+    Ok(Some(compressed))
+}
+
+/// The value of the content encoding attribute for the given compression.
+pub(super) fn content_encoding(compression: Compression) -> Option<&'static str> {
+    match compression {
+        Compression::Unspecified => None,
+        Compression::Gzip => Some(GZIP),
+        Compression::Zstd => Some(ZSTD),
+    }
+}
+
+/// Decompresses the message body in place if it was compressed when published,
+/// regardless of how the topic is configured now.
+pub(super) fn decompress(msg: &mut MessageData) -> anyhow::Result<()> {
+    let Some(encoding) = msg.attrs.get(ATTR_CONTENT_ENCODING) else {
+        return Ok(());
+    };
+
+    let body = msg.raw_body.as_slice();
+    let mut decompressed = Vec::new();
+    match encoding.as_str() {
+        GZIP => flate2::read::GzDecoder::new(body)
+            .take(MAX_DECOMPRESSED_SIZE + 1)
+            .read_to_end(&mut decompressed)
+            .context("invalid gzip message payload")?,
+        ZSTD => zstd::Decoder::new(body)
+            .context("invalid zstd message payload")?
+            .take(MAX_DECOMPRESSED_SIZE + 1)
+            .read_to_end(&mut decompressed)
+            .context("invalid zstd message payload")?,
+        other => anyhow::bail!("unsupported message content encoding {other:?}"),
+    };
+    if decompressed.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        anyhow::bail!("decompressed message payload exceeds {MAX_DECOMPRESSED_SIZE} bytes");
+    }
+
+    msg.raw_body = decompressed;
+    msg.attrs.remove(ATTR_CONTENT_ENCODING);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let body = br#"{"message": "hello hello hello hello"}"#;
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = compress(compression, body).unwrap().unwrap();
+            assert_ne!(compressed, body);
+
+            let mut msg = MessageData {
+                attrs: HashMap::from([(
+                    ATTR_CONTENT_ENCODING.to_string(),
+                    content_encoding(compression).unwrap().to_string(),
+                )]),
+                raw_body: compressed,
+            };
+            decompress(&mut msg).unwrap();
+            assert_eq!(msg.raw_body, body);
+            assert!(msg.attrs.is_empty());
+        }
+
+        assert_eq!(compress(Compression::Unspecified, body).unwrap(), None);
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let mut msg = MessageData {
+            attrs: HashMap::new(),
+            raw_body: b"{}".to_vec(),
+        };
+        decompress(&mut msg).unwrap();
+        assert_eq!(msg.raw_body, b"{}");
+
+        msg.attrs
+            .insert(ATTR_CONTENT_ENCODING.to_string(), GZIP.to_string());
+        assert!(decompress(&mut msg).is_err());
+
+        msg.attrs
+            .insert(ATTR_CONTENT_ENCODING.to_string(), "br".to_string());
+        assert!(decompress(&mut msg).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -8,20 +9,36 @@ use crate::encore::runtime::v1 as pb;
 use crate::pubsub;
 use crate::pubsub::gcp::sub::Subscription;
 use crate::pubsub::gcp::topic::Topic;
+use crate::secrets::{self, Secret};
 
 mod jwk;
 mod push_sub;
 mod sub;
 mod topic;
-#[derive(Debug)]
 pub struct Cluster {
     client: Arc<LazyGCPClient>,
+
+    /// The header secrets of push subscriptions, keyed by subscription rid.
+    push_secrets: HashMap<String, Arc<Secret>>,
 }
 
 impl Cluster {
-    pub fn new() -> Self {
+    pub fn new(subs: &[pb::PubSubSubscription], secrets: &secrets::Manager) -> Self {
         let client = Arc::new(LazyGCPClient::new());
-        Self { client }
+        let push_secrets = subs
+            .iter()
+            .filter_map(|sub| match &sub.provider_config {
+                Some(pb::pub_sub_subscription::ProviderConfig::GcpConfig(cfg)) => {
+                    let value = cfg.push_header_secret.as_ref()?.value.clone()?;
+                    Some((sub.rid.clone(), Arc::new(secrets.load(value))))
+                }
+                _ => None,
+            })
+            .collect();
+        Self {
+            client,
+            push_secrets,
+        }
     }
 }
 
@@ -43,8 +60,19 @@ impl pubsub::Cluster for Cluster {
         if let Some(pb::pub_sub_subscription::ProviderConfig::GcpConfig(gcp_cfg)) =
             cfg.provider_config.as_ref()
         {
-            if gcp_cfg.push_service_account.is_some() {
-                return Arc::new(push_sub::PushSubscription::new(cfg));
+            if gcp_cfg.push_service_account.is_some() || gcp_cfg.push_header_secret.is_some() {
+                let header_secret = self.push_secrets.get(&cfg.rid).cloned();
+                return match push_sub::PushSubscription::new(cfg, header_secret) {
+                    Ok(sub) => Arc::new(sub),
+                    Err(err) => {
+                        log::error!(
+                            "invalid push config for subscription {}: {:#}",
+                            cfg.rid,
+                            err
+                        );
+                        Arc::new(push_sub::MisconfiguredSubscription::new(err))
+                    }
+                };
             }
         }
 
@@ -52,6 +80,14 @@ impl pubsub::Cluster for Cluster {
     }
 }
 
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 struct LazyGCPClient {
     cell: tokio::sync::OnceCell<anyhow::Result<gcp::client::Client>>,
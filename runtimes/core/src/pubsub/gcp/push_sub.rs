@@ -9,11 +9,13 @@ use axum::RequestExt;
 use chrono::{DateTime, Utc};
 use http_body_util::BodyExt;
 use serde::Deserialize;
+use subtle::ConstantTimeEq;
 
 use crate::api::{self, APIResult, ToResponse};
 use crate::encore::runtime::v1 as pb;
 use crate::pubsub::manager::SubHandler;
 use crate::pubsub::{self, MessageId};
+use crate::secrets::Secret;
 
 use super::jwk::{self, CachingClient};
 
@@ -26,37 +28,88 @@ pub struct PushSubscription {
 struct Inner {
     subscription_id: String,
     handler: RwLock<Option<Arc<SubHandler>>>,
-    validator: GoogleJWTValidator,
+    validator: Option<GoogleJWTValidator>,
+    header_secret: Option<HeaderSecretValidator>,
 }
 
 impl PushSubscription {
-    pub(super) fn new(cfg: &pb::PubSubSubscription) -> Self {
+    pub(super) fn new(
+        cfg: &pb::PubSubSubscription,
+        header_secret: Option<Arc<Secret>>,
+    ) -> anyhow::Result<Self> {
         let Some(pb::pub_sub_subscription::ProviderConfig::GcpConfig(gcp_cfg)) =
             cfg.provider_config.as_ref()
         else {
-            panic!("missing gcp config for subscription")
+            anyhow::bail!("missing gcp config for subscription")
         };
 
-        let Some(service_account) = &gcp_cfg.push_service_account else {
-            panic!("missing push_service_account for subscription")
+        let google_validator = gcp_cfg
+            .push_service_account
+            .as_ref()
+            .map(|service_account| GoogleJWTValidator {
+                client: CachingClient::new(),
+                push_service_account: service_account.clone(),
+                audience: gcp_cfg.push_jwt_audience.clone(),
+                issuers: if gcp_cfg.push_jwt_issuers.is_empty() {
+                    GOOGLE_ISSUERS.iter().map(|s| s.to_string()).collect()
+                } else {
+                    gcp_cfg.push_jwt_issuers.clone()
+                },
+                header: gcp_cfg
+                    .push_jwt_header
+                    .clone()
+                    .unwrap_or_else(|| "Authorization".to_string()),
+            });
+
+        let header_secret = match (&gcp_cfg.push_header_secret, header_secret) {
+            (Some(cfg), Some(secret)) => Some(HeaderSecretValidator {
+                header: cfg.header.clone(),
+                secret,
+            }),
+            (Some(_), None) => anyhow::bail!("missing value for push_header_secret"),
+            (None, _) => None,
         };
 
-        let google_validator = GoogleJWTValidator {
-            client: CachingClient::new(),
-            push_service_account: service_account.clone(),
-            audience: gcp_cfg.push_jwt_audience.clone(),
-        };
+        if google_validator.is_none() && header_secret.is_none() {
+            anyhow::bail!("missing push_service_account or push_header_secret for subscription")
+        }
 
-        Self {
+        Ok(Self {
             inner: Arc::new(Inner {
                 subscription_id: cfg.rid.clone(),
                 handler: RwLock::new(None),
                 validator: google_validator,
+                header_secret,
             }),
+        })
+    }
+}
+
+/// A push subscription with an invalid config, which fails to subscribe
+/// rather than taking down the runtime.
+#[derive(Debug)]
+pub struct MisconfiguredSubscription {
+    err: String,
+}
+
+impl MisconfiguredSubscription {
+    pub(super) fn new(err: anyhow::Error) -> Self {
+        Self {
+            err: format!("{err:#}"),
         }
     }
 }
 
+impl pubsub::Subscription for MisconfiguredSubscription {
+    fn subscribe(
+        &self,
+        _handler: Arc<SubHandler>,
+    ) -> Pin<Box<dyn Future<Output = APIResult<()>> + Send + 'static>> {
+        let err = api::Error::internal(anyhow::anyhow!("invalid push subscription: {}", self.err));
+        Box::pin(async move { Err(err) })
+    }
+}
+
 impl pubsub::Subscription for PushSubscription {
     fn subscribe(
         &self,
@@ -142,11 +195,18 @@ impl Inner {
             handler
         };
 
-        // Validate the JWT token.
-        self.validator
-            .validate_google_jwt(req.headers())
-            .await
-            .map_err(api::Error::internal)?;
+        // Validate the header secret and the JWT token, if configured.
+        if let Some(header_secret) = &self.header_secret {
+            header_secret
+                .validate(req.headers())
+                .map_err(api::Error::internal)?;
+        }
+        if let Some(validator) = &self.validator {
+            validator
+                .validate_google_jwt(req.headers())
+                .await
+                .map_err(api::Error::internal)?;
+        }
 
         // Parse the request payload.
         let bytes = req
@@ -177,13 +237,48 @@ impl Inner {
     }
 }
 
+/// Validates that pushed messages carry a shared secret in a header.
+struct HeaderSecretValidator {
+    header: String,
+    secret: Arc<Secret>,
+}
+
+impl std::fmt::Debug for HeaderSecretValidator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeaderSecretValidator")
+            .field("header", &self.header)
+            .finish()
+    }
+}
+
+impl HeaderSecretValidator {
+    fn validate(&self, headers: &axum::http::HeaderMap) -> anyhow::Result<()> {
+        let value = headers
+            .get(self.header.as_str())
+            .ok_or_else(|| anyhow::anyhow!("missing {} header", self.header))?;
+        let secret = self
+            .secret
+            .get()
+            .map_err(|e| anyhow::anyhow!("unable to resolve push header secret: {e}"))?;
+        if !bool::from(value.as_bytes().ct_eq(secret)) {
+            return Err(anyhow::anyhow!("invalid {} header", self.header));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 struct GoogleJWTValidator {
     client: jwk::CachingClient,
     audience: Option<String>,
     push_service_account: String,
+    issuers: Vec<String>,
+    header: String,
 }
 
+/// The issuers of Google-signed JWTs.
+const GOOGLE_ISSUERS: [&str; 2] = ["accounts.google.com", "https://accounts.google.com"];
+
 /// The certs URL for RSA keys.
 const GOOGLE_SA_CERTS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
 
@@ -194,7 +289,7 @@ impl GoogleJWTValidator {
     pub async fn validate_google_jwt(&self, req: &axum::http::HeaderMap) -> anyhow::Result<()> {
         // Extract the JWT from the header
         let auth_header = req
-            .get("Authorization")
+            .get(self.header.as_str())
             .ok_or_else(|| anyhow::anyhow!("missing auth header"))?;
         let token = auth_header
             .to_str()
@@ -253,7 +348,7 @@ impl GoogleJWTValidator {
         if let Some(aud) = &self.audience {
             validation.set_audience(&[aud]);
         }
-        validation.set_issuer(&["accounts.google.com", "https://accounts.google.com"]);
+        validation.set_issuer(&self.issuers);
         validation.set_required_spec_claims(&["exp", "iss", "aud"]);
 
         let jwt = jsonwebtoken::decode::<Claims>(token, &decoding_key, &validation)
@@ -287,3 +382,26 @@ mod base64 {
             .map_err(serde::de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_header_secret_value() {
+        use pb::pub_sub_subscription::{GcpConfig, ProviderConfig, PushHeaderSecret};
+        let cfg = pb::PubSubSubscription {
+            rid: "sub".into(),
+            provider_config: Some(ProviderConfig::GcpConfig(GcpConfig {
+                push_header_secret: Some(PushHeaderSecret {
+                    header: "X-Push-Secret".into(),
+                    value: None,
+                }),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let err = PushSubscription::new(&cfg, None).unwrap_err();
+        assert_eq!(err.to_string(), "missing value for push_header_secret");
+    }
+}
@@ -1,8 +1,11 @@
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
+use google_cloud_gax::grpc::Code;
+use google_cloud_googleapis::pubsub::v1::DeadLetterPolicy;
 use google_cloud_pubsub as gcp;
 use google_cloud_pubsub::apiv1::default_retry_setting;
 use tokio_util::sync::CancellationToken;
@@ -40,11 +43,13 @@ impl pubsub::Subscription for Subscription {
         let inner = self.inner.clone();
         Box::pin(async move {
             let sub = inner.get_sub().await.map_err(api::Error::internal)?;
+            inner.apply_dead_letter(sub).await;
             let cancel = CancellationToken::new();
+            let lease = inner.lease;
             sub.receive(
                 move |message, cancel| {
                     let handler = handler.clone();
-                    handle_message(handler, message, cancel)
+                    handle_message(handler, message, lease, cancel)
                 },
                 cancel,
                 Some(inner.receive_cfg.clone()),
@@ -62,6 +67,9 @@ struct InnerSubscription {
     project_id: String,
     sub_name: String,
     receive_cfg: gcp::subscription::ReceiveConfig,
+    /// Set for exactly-once subscriptions, whose leases are extended while processing.
+    lease: Option<Lease>,
+    dead_letter: Option<DeadLetterPolicy>,
     cell: tokio::sync::OnceCell<Result<gcp::subscription::Subscription>>,
 }
 
@@ -77,30 +85,50 @@ impl InnerSubscription {
             panic!("missing gcp config for subscription")
         };
 
-        let receive_cfg = gcp::subscription::ReceiveConfig {
-            subscriber_config: gcp::subscriber::SubscriberConfig {
-                max_outstanding_messages: meta.max_concurrency.map_or(100, |v| v as i64),
-                retry_setting: Some(google_cloud_gax::retry::RetrySetting {
-                    from_millis: meta.retry_policy.as_ref().map_or(10, |retry| {
-                        let min_backoff = retry.min_backoff.max(0) as u64;
-                        min_backoff / 1_000_000 // nanos to millis
-                    }),
-                    max_delay: meta.retry_policy.as_ref().map(|retry| {
-                        let max_backoff = retry.max_backoff.max(0) as u64;
-                        std::time::Duration::from_nanos(max_backoff)
-                    }),
-                    ..default_retry_setting()
+        let mut subscriber_config = gcp::subscriber::SubscriberConfig {
+            max_outstanding_messages: meta.max_concurrency.map_or(100, |v| v as i64),
+            retry_setting: Some(google_cloud_gax::retry::RetrySetting {
+                from_millis: meta.retry_policy.as_ref().map_or(10, |retry| {
+                    let min_backoff = retry.min_backoff.max(0) as u64;
+                    min_backoff / 1_000_000 // nanos to millis
+                }),
+                max_delay: meta.retry_policy.as_ref().map(|retry| {
+                    let max_backoff = retry.max_backoff.max(0) as u64;
+                    std::time::Duration::from_nanos(max_backoff)
                 }),
-                ..Default::default()
-            },
+                ..default_retry_setting()
+            }),
+            ..Default::default()
+        };
+
+        // Lease received messages for the ack deadline from the infra config, if set.
+        if let Some(deadline) = cfg.ack_deadline.and_then(|d| Duration::try_from(d).ok()) {
+            subscriber_config.stream_ack_deadline_seconds =
+                deadline.clamp(MIN_ACK_DEADLINE, MAX_ACK_DEADLINE).as_secs() as i32;
+        }
+
+        let receive_cfg = gcp::subscription::ReceiveConfig {
+            subscriber_config,
             ..Default::default()
         };
 
+        let lease = gcp_cfg.exactly_once_delivery.then(|| Lease {
+            deadline: Duration::from_nanos(meta.ack_deadline.max(0) as u64)
+                .clamp(MIN_ACK_DEADLINE, MAX_ACK_DEADLINE),
+        });
+
+        let dead_letter = cfg.dead_letter.as_ref().map(|dl| DeadLetterPolicy {
+            dead_letter_topic: dead_letter_topic(&gcp_cfg.project_id, &dl.target),
+            max_delivery_attempts: clamp_delivery_attempts(dl.max_delivery_attempts),
+        });
+
         Self {
             client,
             project_id: gcp_cfg.project_id.clone(),
             sub_name: cfg.subscription_cloud_name.clone(),
             receive_cfg,
+            lease,
+            dead_letter,
             cell: tokio::sync::OnceCell::new(),
         }
     }
@@ -126,11 +154,164 @@ impl InnerSubscription {
             Err(e) => anyhow::bail!("failed to get topic: {}", e),
         }
     }
+
+    /// Sets the subscription's dead-letter policy, if one is configured.
+    /// GCP forwards messages to the dead-letter topic itself, and only reports
+    /// delivery attempts for subscriptions that have a dead-letter policy.
+    async fn apply_dead_letter(&self, sub: &gcp::subscription::Subscription) {
+        let Some(policy) = &self.dead_letter else {
+            return;
+        };
+        let update = gcp::subscription::SubscriptionConfigToUpdate {
+            dead_letter_policy: Some(policy.clone()),
+            ..Default::default()
+        };
+        if let Err(err) = sub.update(update, None).await {
+            log::error!(
+                "unable to set dead-letter policy on subscription {}: {}",
+                self.sub_name,
+                err
+            );
+        }
+    }
+}
+
+/// The range of delivery attempts GCP Pub/Sub accepts for dead-letter policies.
+const MIN_DELIVERY_ATTEMPTS: u32 = 5;
+const MAX_DELIVERY_ATTEMPTS: u32 = 100;
+
+/// Returns the fully qualified name of the dead-letter topic,
+/// which defaults to the subscription's project.
+fn dead_letter_topic(project_id: &str, target: &str) -> String {
+    if target.starts_with("projects/") {
+        target.to_string()
+    } else {
+        format!("projects/{}/topics/{}", project_id, target)
+    }
+}
+
+fn clamp_delivery_attempts(attempts: u32) -> i32 {
+    let clamped = attempts.clamp(MIN_DELIVERY_ATTEMPTS, MAX_DELIVERY_ATTEMPTS);
+    if clamped != attempts {
+        log::warn!(
+            "gcp pubsub only supports between {} and {} delivery attempts before dead-lettering, using {}",
+            MIN_DELIVERY_ATTEMPTS,
+            MAX_DELIVERY_ATTEMPTS,
+            clamped
+        );
+    }
+    clamped as i32
+}
+
+/// The range of ack deadlines GCP Pub/Sub accepts.
+const MIN_ACK_DEADLINE: Duration = Duration::from_secs(10);
+const MAX_ACK_DEADLINE: Duration = Duration::from_secs(600);
+
+/// How many times to try acknowledging a message on an exactly-once subscription
+/// before giving up on transient failures.
+const MAX_ACK_ATTEMPTS: u32 = 5;
+
+/// Lease management for messages on exactly-once subscriptions.
+///
+/// With exactly-once delivery a message can only be acknowledged while its
+/// lease is held, so the lease is extended for as long as the message is being processed.
+#[derive(Debug, Clone, Copy)]
+struct Lease {
+    /// The ack deadline requested on each extension.
+    deadline: Duration,
+}
+
+impl Lease {
+    /// How often the lease is extended, leaving plenty of time before it expires.
+    fn interval(&self) -> Duration {
+        self.deadline / 2
+    }
+
+    /// Runs the future, extending the message's lease until it completes.
+    async fn hold<F: Future>(
+        &self,
+        message: &gcp::subscriber::ReceivedMessage,
+        fut: F,
+    ) -> F::Output {
+        tokio::pin!(fut);
+        let start = tokio::time::Instant::now() + self.interval();
+        let mut ticker = tokio::time::interval_at(start, self.interval());
+        loop {
+            tokio::select! {
+                out = &mut fut => return out,
+                _ = ticker.tick() => {
+                    let secs = self.deadline.as_secs() as i32;
+                    if let Err(err) = message.modify_ack_deadline(secs).await {
+                        log::warn!(
+                            "failed to extend message lease, it may be redelivered: {}",
+                            err
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Why acknowledging a message on an exactly-once subscription failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AckFailure {
+    /// The ack was rejected, typically because the lease expired.
+    /// The message will be redelivered.
+    Rejected,
+    /// The ack may succeed if retried.
+    Transient,
+}
+
+impl AckFailure {
+    fn from_code(code: Code) -> Self {
+        match code {
+            Code::InvalidArgument
+            | Code::FailedPrecondition
+            | Code::NotFound
+            | Code::PermissionDenied => AckFailure::Rejected,
+            _ => AckFailure::Transient,
+        }
+    }
+}
+
+/// Acknowledges a message on an exactly-once subscription.
+/// Unlike with at-least-once delivery, a successful ack is guaranteed to
+/// prevent redelivery, so transient failures are retried.
+async fn ack_exactly_once(message: &gcp::subscriber::ReceivedMessage) {
+    let mut delay = Duration::from_millis(100);
+    for attempt in 1..=MAX_ACK_ATTEMPTS {
+        let Err(err) = message.ack().await else {
+            return;
+        };
+        match AckFailure::from_code(err.code()) {
+            AckFailure::Rejected => {
+                log::error!(
+                    "message was processed but its ack was rejected, it will be redelivered: {}",
+                    err
+                );
+                return;
+            }
+            AckFailure::Transient if attempt < MAX_ACK_ATTEMPTS => {
+                log::warn!("failed to ack message, retrying: {}", err);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            AckFailure::Transient => {
+                log::error!(
+                    "message was processed but acking it failed after {} attempts, it will be redelivered: {}",
+                    MAX_ACK_ATTEMPTS,
+                    err
+                );
+            }
+        }
+    }
 }
 
 async fn handle_message(
     handler: Arc<SubHandler>,
     mut message: gcp::subscriber::ReceivedMessage,
+    lease: Option<Lease>,
     _cancel: CancellationToken,
 ) {
     let attempt = message.delivery_attempt().unwrap_or(1) as u32;
@@ -153,7 +334,13 @@ async fn handle_message(
     };
 
     // Process the message asynchronously.
-    match handler.handle_message(msg).await {
+    let result = match lease {
+        Some(lease) => lease.hold(&message, handler.handle_message(msg)).await,
+        None => handler.handle_message(msg).await,
+    };
+
+    match result {
+        Ok(()) if lease.is_some() => ack_exactly_once(&message).await,
         Ok(()) => {
             // Acknowledge the message.
             if let Err(err) = message.ack().await {
@@ -168,3 +355,43 @@ async fn handle_message(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ack_failure() {
+        assert_eq!(
+            AckFailure::from_code(Code::InvalidArgument),
+            AckFailure::Rejected
+        );
+        assert_eq!(
+            AckFailure::from_code(Code::FailedPrecondition),
+            AckFailure::Rejected
+        );
+        assert_eq!(
+            AckFailure::from_code(Code::Unavailable),
+            AckFailure::Transient
+        );
+        assert_eq!(
+            AckFailure::from_code(Code::DeadlineExceeded),
+            AckFailure::Transient
+        );
+    }
+
+    #[test]
+    fn test_dead_letter_topic() {
+        assert_eq!(
+            dead_letter_topic("my-project", "dlq"),
+            "projects/my-project/topics/dlq"
+        );
+        assert_eq!(
+            dead_letter_topic("my-project", "projects/other/topics/dlq"),
+            "projects/other/topics/dlq"
+        );
+        assert_eq!(clamp_delivery_attempts(1), 5);
+        assert_eq!(clamp_delivery_attempts(10), 10);
+        assert_eq!(clamp_delivery_attempts(500), 100);
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Context;
+
+use crate::api;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub::manager::SubscribeFut;
+
+/// A dedicated tokio runtime a subscription processes its messages on,
+/// so that CPU-heavy subscribers don't starve request handling.
+pub(super) struct IsolatedRuntime {
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl IsolatedRuntime {
+    pub fn new(name: &str, cfg: &pb::pub_sub_subscription::Isolation) -> anyhow::Result<Self> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder
+            .enable_all()
+            .worker_threads(cfg.worker_threads.max(1) as usize)
+            .thread_name(format!("pubsub-{name}"));
+        if let Some(n) = cfg.max_blocking_threads {
+            builder.max_blocking_threads(n.max(1) as usize);
+        }
+        let runtime = builder
+            .build()
+            .with_context(|| format!("failed to build runtime for subscription {name}"))?;
+        Ok(Self {
+            runtime: Some(runtime),
+        })
+    }
+
+    /// Runs the subscription future on the runtime.
+    /// The runtime is kept alive until the returned future completes.
+    pub fn run(self, fut: SubscribeFut) -> SubscribeFut {
+        let handle = self.runtime.as_ref().expect("runtime is set").spawn(fut);
+        Box::pin(async move {
+            let _rt = self;
+            handle
+                .await
+                .context("subscription task failed")
+                .map_err(api::Error::internal)?
+        })
+    }
+}
+
+impl Drop for IsolatedRuntime {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which isn't allowed from within an async context.
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_on_isolated_runtime() {
+        let cfg = pb::pub_sub_subscription::Isolation {
+            worker_threads: 1,
+            max_blocking_threads: Some(1),
+        };
+        let rt = IsolatedRuntime::new("orders", &cfg).unwrap();
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let fut: SubscribeFut = Box::pin(async move {
+            let name = std::thread::current().name().map(String::from);
+            tx.send(name).unwrap();
+            Ok(())
+        });
+        rt.run(fut).await.unwrap();
+        assert_eq!(rx.await.unwrap().as_deref(), Some("pubsub-orders"));
+    }
+}
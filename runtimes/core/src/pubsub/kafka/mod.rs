@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::Context;
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::producer::FutureProducer;
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::ClientConfig;
+
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub;
+use crate::pubsub::kafka::sub::KafkaSubscription;
+use crate::pubsub::kafka::topic::KafkaTopic;
+use crate::secrets;
+
+mod sub;
+mod topic;
+
+pub struct Cluster {
+    brokers: String,
+    config: ClientConfig,
+    producer: Arc<LazyProducer>,
+    admin: Arc<TopicCreator>,
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The client config contains credentials, so don't include it.
+        f.debug_struct("Cluster")
+            .field("brokers", &self.brokers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cluster {
+    pub fn new(
+        cfg: &pb::pub_sub_cluster::Kafka,
+        topics: &[pb::PubSubTopic],
+        secrets: &secrets::Manager,
+    ) -> anyhow::Result<Self> {
+        let password = cfg
+            .sasl
+            .as_ref()
+            .and_then(|sasl| sasl.password.clone())
+            .map(|data| resolve_secret(secrets, data))
+            .transpose()
+            .context("unable to resolve Kafka SASL password")?;
+        let client_key = cfg
+            .client_cert
+            .as_ref()
+            .and_then(|cert| cert.key.clone())
+            .map(|data| resolve_secret(secrets, data))
+            .transpose()
+            .context("unable to resolve Kafka client certificate key")?;
+
+        let config = client_config(cfg, password.as_deref(), client_key.as_deref())?;
+
+        let specs = topics
+            .iter()
+            .filter_map(|topic| match &topic.provider_config {
+                Some(pb::pub_sub_topic::ProviderConfig::KafkaConfig(kafka)) => {
+                    Some((topic.cloud_name.clone(), kafka.clone()))
+                }
+                _ => None,
+            })
+            .filter(|(_, kafka)| kafka.partitions.is_some() || kafka.replication_factor.is_some())
+            .collect();
+
+        Ok(Self {
+            brokers: cfg.brokers.join(","),
+            producer: Arc::new(LazyProducer {
+                config: config.clone(),
+                cell: once_cell::sync::OnceCell::new(),
+            }),
+            admin: Arc::new(TopicCreator {
+                config: config.clone(),
+                specs,
+                created: tokio::sync::Mutex::new(HashSet::new()),
+            }),
+            config,
+        })
+    }
+}
+
+impl pubsub::Cluster for Cluster {
+    fn topic(
+        &self,
+        cfg: &pb::PubSubTopic,
+        _publisher_id: xid::Id,
+    ) -> Arc<dyn pubsub::Topic + 'static> {
+        Arc::new(KafkaTopic::new(
+            self.producer.clone(),
+            self.admin.clone(),
+            cfg,
+        ))
+    }
+
+    fn subscription(
+        &self,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Arc<dyn pubsub::Subscription + 'static> {
+        Arc::new(KafkaSubscription::new(
+            self.config.clone(),
+            self.producer.clone(),
+            self.admin.clone(),
+            cfg,
+            meta,
+        ))
+    }
+}
+
+fn resolve_secret(secrets: &secrets::Manager, data: pb::SecretData) -> anyhow::Result<String> {
+    let secret = secrets.load(data);
+    let value = secret.get()?;
+    Ok(std::str::from_utf8(value)?.to_string())
+}
+
+/// Computes the librdkafka client configuration shared by
+/// the producer, consumers and admin client of a cluster.
+fn client_config(
+    cfg: &pb::pub_sub_cluster::Kafka,
+    password: Option<&str>,
+    client_key: Option<&str>,
+) -> anyhow::Result<ClientConfig> {
+    use pb::pub_sub_cluster::kafka::sasl::Mechanism;
+
+    if cfg.brokers.is_empty() {
+        anyhow::bail!("no Kafka brokers configured");
+    }
+
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", cfg.brokers.join(","));
+
+    let protocol = match (&cfg.tls_config, &cfg.sasl) {
+        (None, None) => "plaintext",
+        (Some(_), None) => "ssl",
+        (None, Some(_)) => "sasl_plaintext",
+        (Some(_), Some(_)) => "sasl_ssl",
+    };
+    config.set("security.protocol", protocol);
+
+    if let Some(tls) = &cfg.tls_config {
+        if let Some(ca) = &tls.server_ca_cert {
+            config.set("ssl.ca.pem", ca);
+        }
+        if tls.disable_ca_validation {
+            config.set("enable.ssl.certificate.verification", "false");
+        }
+        if tls.disable_tls_hostname_verification {
+            config.set("ssl.endpoint.identification.algorithm", "none");
+        }
+        if let Some(cert) = &cfg.client_cert {
+            let key = client_key.context("missing Kafka client certificate key")?;
+            config.set("ssl.certificate.pem", &cert.cert);
+            config.set("ssl.key.pem", key);
+        }
+    } else if cfg.client_cert.is_some() {
+        anyhow::bail!("Kafka client certificates require TLS to be enabled");
+    }
+
+    if let Some(sasl) = &cfg.sasl {
+        let mechanism = match sasl.mechanism() {
+            Mechanism::Plain => "PLAIN",
+            Mechanism::ScramSha256 => "SCRAM-SHA-256",
+            Mechanism::ScramSha512 => "SCRAM-SHA-512",
+        };
+        config.set("sasl.mechanism", mechanism);
+        config.set("sasl.username", &sasl.username);
+        config.set(
+            "sasl.password",
+            password.context("missing Kafka SASL password")?,
+        );
+    }
+
+    Ok(config)
+}
+
+/// A producer that is created the first time a message is published,
+/// so that services that never publish don't connect to the brokers.
+struct LazyProducer {
+    config: ClientConfig,
+    cell: once_cell::sync::OnceCell<FutureProducer>,
+}
+
+impl LazyProducer {
+    fn get(&self) -> anyhow::Result<&FutureProducer> {
+        self.cell.get_or_try_init(|| {
+            self.config
+                .create()
+                .context("unable to create Kafka producer")
+        })
+    }
+}
+
+/// Creates the topics that have partitioning configured
+/// if they don't already exist.
+struct TopicCreator {
+    config: ClientConfig,
+    specs: HashMap<String, pb::pub_sub_topic::KafkaConfig>,
+    created: tokio::sync::Mutex<HashSet<String>>,
+}
+
+impl TopicCreator {
+    async fn ensure(&self, topic: &str) -> anyhow::Result<()> {
+        let Some(spec) = self.specs.get(topic) else {
+            return Ok(());
+        };
+
+        let mut created = self.created.lock().await;
+        if created.contains(topic) {
+            return Ok(());
+        }
+
+        let admin: AdminClient<DefaultClientContext> = self
+            .config
+            .create()
+            .context("unable to create Kafka admin client")?;
+        let new_topic = NewTopic::new(
+            topic,
+            spec.partitions.unwrap_or(-1),
+            TopicReplication::Fixed(spec.replication_factor.unwrap_or(-1)),
+        );
+        let results = admin
+            .create_topics(&[new_topic], &AdminOptions::new())
+            .await
+            .with_context(|| format!("unable to create Kafka topic {}", topic))?;
+        for result in results {
+            match result {
+                Ok(_) | Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
+                Err((name, code)) => {
+                    anyhow::bail!("unable to create Kafka topic {}: {}", name, code)
+                }
+            }
+        }
+
+        created.insert(topic.to_string());
+        Ok(())
+    }
+}
+
+/// Returns the id of the message at the given partition and offset.
+fn message_id(partition: i32, offset: i64) -> pubsub::MessageId {
+    format!("{partition}-{offset}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pb::pub_sub_cluster::kafka::{sasl::Mechanism, Sasl};
+
+    fn kafka(tls: Option<pb::TlsConfig>, sasl: Option<Sasl>) -> pb::pub_sub_cluster::Kafka {
+        pb::pub_sub_cluster::Kafka {
+            brokers: vec!["kafka-1:9092".into(), "kafka-2:9092".into()],
+            tls_config: tls,
+            client_cert: None,
+            sasl,
+        }
+    }
+
+    #[test]
+    fn test_client_config_plaintext() {
+        let config = client_config(&kafka(None, None), None, None).unwrap();
+        assert_eq!(
+            config.get("bootstrap.servers"),
+            Some("kafka-1:9092,kafka-2:9092")
+        );
+        assert_eq!(config.get("security.protocol"), Some("plaintext"));
+        assert_eq!(config.get("sasl.mechanism"), None);
+    }
+
+    #[test]
+    fn test_client_config_sasl_ssl() {
+        let cfg = kafka(
+            Some(pb::TlsConfig {
+                server_ca_cert: Some("CA".into()),
+                disable_tls_hostname_verification: true,
+                disable_ca_validation: false,
+            }),
+            Some(Sasl {
+                mechanism: Mechanism::ScramSha512 as i32,
+                username: "app".into(),
+                password: None,
+            }),
+        );
+        let config = client_config(&cfg, Some("secret"), None).unwrap();
+        assert_eq!(config.get("security.protocol"), Some("sasl_ssl"));
+        assert_eq!(config.get("ssl.ca.pem"), Some("CA"));
+        assert_eq!(
+            config.get("ssl.endpoint.identification.algorithm"),
+            Some("none")
+        );
+        assert_eq!(config.get("enable.ssl.certificate.verification"), None);
+        assert_eq!(config.get("sasl.mechanism"), Some("SCRAM-SHA-512"));
+        assert_eq!(config.get("sasl.username"), Some("app"));
+        assert_eq!(config.get("sasl.password"), Some("secret"));
+
+        // The password is required when using SASL.
+        assert!(client_config(&cfg, None, None).is_err());
+    }
+
+    #[test]
+    fn test_client_config_no_brokers() {
+        let mut cfg = kafka(None, None);
+        cfg.brokers.clear();
+        assert!(client_config(&cfg, None, None).is_err());
+    }
+}
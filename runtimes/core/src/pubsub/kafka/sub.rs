@@ -0,0 +1,365 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{Headers, Message, OwnedMessage};
+use rdkafka::producer::FutureRecord;
+use rdkafka::util::Timeout;
+use rdkafka::ClientConfig;
+
+use crate::api::{self, APIResult};
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub;
+use crate::pubsub::kafka::topic::PUBLISH_TIMEOUT;
+use crate::pubsub::kafka::{message_id, LazyProducer, TopicCreator};
+use crate::pubsub::manager::SubHandler;
+use crate::pubsub::retry::RetryPolicy;
+use crate::pubsub::Subscription;
+
+/// How long to wait before receiving again after a consumer error.
+const RECEIVE_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+pub struct KafkaSubscription {
+    topic: String,
+    group_id: String,
+    config: ClientConfig,
+    admin: Arc<TopicCreator>,
+    max_concurrency: usize,
+    retry: RetryPolicy,
+    dead_letter: Option<Arc<DeadLetter>>,
+}
+
+/// Where messages are published once they've failed too many times.
+struct DeadLetter {
+    topic: String,
+    max_attempts: u32,
+    producer: Arc<LazyProducer>,
+}
+
+impl DeadLetter {
+    /// Publishes the message to the dead-letter topic, keeping its key and headers.
+    async fn publish(&self, msg: &OwnedMessage) -> anyhow::Result<()> {
+        let producer = self.producer.get()?;
+        let mut record =
+            FutureRecord::<[u8], [u8]>::to(&self.topic).payload(msg.payload().unwrap_or_default());
+        if let Some(key) = msg.key() {
+            record = record.key(key);
+        }
+        if let Some(headers) = msg.headers() {
+            record = record.headers(headers.clone());
+        }
+        producer
+            .send(record, Timeout::After(PUBLISH_TIMEOUT))
+            .await
+            .map_err(|(err, _)| err)
+            .with_context(|| format!("unable to publish message to {}", self.topic))?;
+        Ok(())
+    }
+}
+
+impl Debug for KafkaSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaSubscription")
+            .field("topic", &self.topic)
+            .field("group_id", &self.group_id)
+            .finish()
+    }
+}
+
+impl KafkaSubscription {
+    pub(super) fn new(
+        mut config: ClientConfig,
+        producer: Arc<LazyProducer>,
+        admin: Arc<TopicCreator>,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Self {
+        use pb::pub_sub_subscription::kafka_config::InitialOffset;
+
+        let initial_offset = match &cfg.provider_config {
+            Some(pb::pub_sub_subscription::ProviderConfig::KafkaConfig(kafka)) => {
+                kafka.initial_offset()
+            }
+            _ => InitialOffset::Earliest,
+        };
+
+        // Each subscription is a consumer group, so every subscription
+        // receives each message while the instances of a service share them.
+        // Offsets are stored once messages are processed and committed
+        // periodically, so that messages are redelivered after a crash.
+        config
+            .set("group.id", &cfg.subscription_cloud_name)
+            .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false")
+            .set(
+                "auto.offset.reset",
+                match initial_offset {
+                    InitialOffset::Earliest => "earliest",
+                    InitialOffset::Latest => "latest",
+                },
+            );
+
+        Self {
+            topic: cfg.topic_cloud_name.clone(),
+            group_id: cfg.subscription_cloud_name.clone(),
+            config,
+            admin,
+            max_concurrency: meta.max_concurrency.map_or(100, |v| v.max(1) as usize),
+            retry: RetryPolicy::new(meta),
+            dead_letter: cfg.dead_letter.as_ref().map(|dl| {
+                Arc::new(DeadLetter {
+                    topic: dl.target.clone(),
+                    max_attempts: dl.max_delivery_attempts,
+                    producer,
+                })
+            }),
+        }
+    }
+}
+
+impl Subscription for KafkaSubscription {
+    fn subscribe(
+        &self,
+        handler: Arc<SubHandler>,
+    ) -> Pin<Box<dyn Future<Output = APIResult<()>> + Send + 'static>> {
+        let topic = self.topic.clone();
+        let config = self.config.clone();
+        let admin = self.admin.clone();
+        let max_concurrency = self.max_concurrency;
+        let retry = self.retry;
+        let dead_letter = self.dead_letter.clone();
+
+        Box::pin(async move {
+            if let Err(err) = admin.ensure(&topic).await {
+                log::error!("unable to create Kafka topic {}: {:#}", topic, err);
+            }
+
+            let consumer: StreamConsumer = config.create().map_err(api::Error::internal)?;
+            consumer
+                .subscribe(&[&topic])
+                .map_err(api::Error::internal)?;
+            let consumer = Arc::new(consumer);
+
+            let offsets = Arc::new(Mutex::new(OffsetTracker::default()));
+            let sem = Arc::new(tokio::sync::Semaphore::new(max_concurrency));
+            loop {
+                let permit = sem
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed");
+
+                let msg = match consumer.recv().await {
+                    Ok(msg) => msg.detach(),
+                    Err(err) => {
+                        log::warn!("unable to receive Kafka message from {}: {}", topic, err);
+                        tokio::time::sleep(RECEIVE_ERROR_DELAY).await;
+                        continue;
+                    }
+                };
+
+                let (partition, offset) = (msg.partition(), msg.offset());
+                offsets.lock().unwrap().start(partition, offset);
+
+                let handler = handler.clone();
+                let consumer = consumer.clone();
+                let offsets = offsets.clone();
+                let dead_letter = dead_letter.clone();
+                tokio::spawn(async move {
+                    if !process_message(&msg, &handler, retry, dead_letter.as_deref()).await {
+                        // Leave the message uncommitted, so that it's delivered again
+                        // once the partition is reassigned or the consumer restarts.
+                        drop(permit);
+                        return;
+                    }
+
+                    let next = offsets.lock().unwrap().finish(partition, offset);
+                    if let Err(err) = consumer.store_offset(msg.topic(), partition, next) {
+                        // This happens when the partition has been reassigned,
+                        // in which case the message is delivered again elsewhere.
+                        log::debug!(
+                            "unable to store Kafka offset {} for partition {}: {}",
+                            next,
+                            partition,
+                            err
+                        );
+                    }
+                    drop(permit);
+                });
+            }
+        })
+    }
+}
+
+/// Processes a message, retrying it in place until it succeeds or the retry
+/// policy is exhausted, in which case it's moved to the dead-letter topic.
+///
+/// Reports whether the message is done with and its offset can be committed.
+async fn process_message(
+    msg: &OwnedMessage,
+    handler: &SubHandler,
+    retry: RetryPolicy,
+    dead_letter: Option<&DeadLetter>,
+) -> bool {
+    let mut attempt: u32 = 1;
+    loop {
+        let Err(err) = handler.handle_message(to_message(msg, attempt)).await else {
+            return true;
+        };
+
+        let exhausted = !retry.should_retry(attempt)
+            || dead_letter.is_some_and(|dl| attempt >= dl.max_attempts);
+        if exhausted {
+            let id = message_id(msg.partition(), msg.offset());
+            let Some(dead_letter) = dead_letter else {
+                log::error!(
+                    "message {} failed {} times and no dead-letter topic is configured, leaving it uncommitted: {:?}",
+                    id,
+                    attempt,
+                    err
+                );
+                return false;
+            };
+            return match dead_letter.publish(msg).await {
+                Ok(()) => {
+                    log::warn!(
+                        "message {} failed {} times, moved it to the dead-letter topic {}: {:?}",
+                        id,
+                        attempt,
+                        dead_letter.topic,
+                        err
+                    );
+                    true
+                }
+                Err(dl_err) => {
+                    log::error!(
+                        "message {} failed {} times and couldn't be dead-lettered, leaving it uncommitted: {:#}",
+                        id,
+                        attempt,
+                        dl_err
+                    );
+                    false
+                }
+            };
+        }
+
+        let delay = retry.delay(attempt);
+        log::info!(
+            "message handler failed, retrying message in {:?}: {:?}",
+            delay,
+            err
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn to_message(msg: &OwnedMessage, attempt: u32) -> pubsub::Message {
+    let attrs = msg
+        .headers()
+        .map(|headers| {
+            headers
+                .iter()
+                .filter_map(|h| {
+                    let value = std::str::from_utf8(h.value?).ok()?;
+                    Some((h.key.to_string(), value.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    pubsub::Message {
+        id: message_id(msg.partition(), msg.offset()),
+        publish_time: msg
+            .timestamp()
+            .to_millis()
+            .and_then(chrono::DateTime::from_timestamp_millis),
+        attempt,
+        data: pubsub::MessageData {
+            attrs,
+            raw_body: msg.payload().unwrap_or_default().to_vec(),
+        },
+    }
+}
+
+/// Tracks the messages being processed for each partition,
+/// to compute the offset up to which all messages have been processed.
+///
+/// Messages are processed concurrently and may finish out of order,
+/// so committing the offset of the last finished message could
+/// skip messages that are still being processed.
+#[derive(Debug, Default)]
+struct OffsetTracker {
+    partitions: HashMap<i32, PartitionOffsets>,
+}
+
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    /// The highest offset that has finished processing, if any.
+    finished: Option<i64>,
+}
+
+impl OffsetTracker {
+    fn start(&mut self, partition: i32, offset: i64) {
+        let p = self.partitions.entry(partition).or_default();
+        if p.finished.is_some_and(|finished| offset <= finished) {
+            // The partition was reassigned and is being consumed again
+            // from an earlier offset.
+            p.finished = None;
+        }
+        p.in_flight.insert(offset);
+    }
+
+    /// Marks the message as processed and returns the offset to store,
+    /// which is the offset of the next message to consume.
+    fn finish(&mut self, partition: i32, offset: i64) -> i64 {
+        let p = self.partitions.entry(partition).or_default();
+        p.in_flight.remove(&offset);
+        p.finished = Some(p.finished.map_or(offset, |f| f.max(offset)));
+
+        match p.in_flight.first() {
+            Some(&earliest) => earliest,
+            None => p.finished.unwrap_or(offset) + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_tracker_out_of_order() {
+        let mut t = OffsetTracker::default();
+        t.start(0, 10);
+        t.start(0, 11);
+        t.start(0, 12);
+        t.start(1, 5);
+
+        // Offset 10 is still in flight, so nothing past it can be committed.
+        assert_eq!(t.finish(0, 12), 10);
+        assert_eq!(t.finish(0, 11), 10);
+        assert_eq!(t.finish(0, 10), 13);
+
+        // Partitions are tracked independently.
+        assert_eq!(t.finish(1, 5), 6);
+    }
+
+    #[test]
+    fn offset_tracker_rewind() {
+        let mut t = OffsetTracker::default();
+        t.start(0, 10);
+        assert_eq!(t.finish(0, 10), 11);
+
+        // The partition is consumed again from an earlier offset.
+        t.start(0, 8);
+        assert_eq!(t.finish(0, 8), 9);
+    }
+}
@@ -0,0 +1,83 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::FutureRecord;
+use rdkafka::util::Timeout;
+
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub::kafka::{message_id, LazyProducer, TopicCreator};
+use crate::pubsub::{MessageData, MessageId, Topic};
+
+/// How long to wait for a message to be acknowledged by the brokers,
+/// including time spent queued while the brokers are unavailable.
+pub(super) const PUBLISH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct KafkaTopic {
+    producer: Arc<LazyProducer>,
+    admin: Arc<TopicCreator>,
+    cloud_name: String,
+}
+
+impl std::fmt::Debug for KafkaTopic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaTopic")
+            .field("cloud_name", &self.cloud_name)
+            .finish()
+    }
+}
+
+impl KafkaTopic {
+    pub(super) fn new(
+        producer: Arc<LazyProducer>,
+        admin: Arc<TopicCreator>,
+        cfg: &pb::PubSubTopic,
+    ) -> Self {
+        Self {
+            producer,
+            admin,
+            cloud_name: cfg.cloud_name.clone(),
+        }
+    }
+}
+
+impl Topic for KafkaTopic {
+    fn publish(
+        &self,
+        msg: MessageData,
+        ordering_key: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
+        Box::pin(async move {
+            self.admin.ensure(&self.cloud_name).await?;
+            let producer = self.producer.get()?;
+
+            // Message attributes are sent as record headers.
+            let mut headers = OwnedHeaders::new_with_capacity(msg.attrs.len());
+            for (key, value) in &msg.attrs {
+                headers = headers.insert(Header {
+                    key,
+                    value: Some(value.as_bytes()),
+                });
+            }
+
+            // Records with the same key are written to the same partition,
+            // which preserves their order.
+            let mut record = FutureRecord::<str, [u8]>::to(&self.cloud_name)
+                .payload(msg.raw_body.as_slice())
+                .headers(headers);
+            if let Some(key) = &ordering_key {
+                record = record.key(key.as_str());
+            }
+
+            let (partition, offset) = producer
+                .send(record, Timeout::After(PUBLISH_TIMEOUT))
+                .await
+                .map_err(|(err, _)| err)
+                .context("unable to publish message")?;
+            Ok(message_id(partition, offset))
+        })
+    }
+}
@@ -1,31 +1,36 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, OnceLock, RwLock};
 
 use anyhow::Context;
 use chrono::Utc;
 use futures::future::Shared;
 use futures::FutureExt;
+use tokio::sync::watch;
 
 use crate::api::jsonschema::{self, JSONSchema};
 use crate::api::{APIResult, PValues};
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::parser::schema::v1 as schema;
 use crate::encore::runtime::v1 as pb;
+use crate::encore::runtime::v1::pub_sub_topic::{Compression, SchemaValidation};
 use crate::log::LogFromRust;
 use crate::model::{PubSubRequestData, RequestData, ResponseData, SpanId, SpanKey, TraceId};
 use crate::names::EncoreName;
 use crate::pubsub::noop::NoopCluster;
 use crate::pubsub::{
-    gcp, noop, nsq, sqs_sns, Cluster, Message, MessageData, MessageId, SubName, Subscription,
-    SubscriptionHandler, Topic,
+    azure, gcp, kafka, noop, nsq, rabbitmq, replay, sqs_sns, Cluster, Message, MessageData,
+    MessageId, SubName, Subscription, SubscriptionHandler, Topic,
 };
 use crate::trace::{protocol, Tracer};
-use crate::{api, model};
+use crate::{api, crypto, faults, metrics, model, objects, secrets, sqldb};
 
+use super::compression::{self, ATTR_CONTENT_ENCODING};
+use super::isolation::IsolatedRuntime;
 use super::push_registry::PushHandlerRegistry;
+use super::quarantine::Quarantine;
 
 pub struct Manager {
     tracer: Tracer,
@@ -36,6 +41,7 @@ pub struct Manager {
     topics: Arc<RwLock<HashMap<EncoreName, Arc<TopicInner>>>>,
     subs: Arc<RwLock<HashMap<SubName, Arc<SubscriptionObj>>>>,
     push_registry: PushHandlerRegistry,
+    drain: Arc<Drain>,
 }
 
 #[derive(Debug)]
@@ -50,6 +56,10 @@ struct TopicInner {
     imp: Arc<dyn Topic>,
     attr_fields: Arc<Vec<String>>,
     ordering_attr: Option<String>,
+    schema: JSONSchema,
+    schema_validation: SchemaValidation,
+    max_message_bytes: Option<usize>,
+    compression: Compression,
 }
 
 impl TopicObj {
@@ -73,9 +83,40 @@ impl TopicInner {
         let name = self.name.clone();
         let attr_fields = self.attr_fields.clone();
         let ordering_attr = self.ordering_attr.clone();
+        let schema = self.schema.clone();
+        let schema_validation = self.schema_validation;
+        let max_message_bytes = self.max_message_bytes;
+        let compression = self.compression;
         async move {
+            faults::inject(faults::Target::PubSub).await?;
+
             let raw_body = serde_json::to_vec_pretty(&payload)
                 .context("unable to serialize message payload")?;
+
+            if schema_validation != SchemaValidation::Unspecified {
+                if let Err(err) = parse_payload(&schema, &raw_body) {
+                    if schema_validation == SchemaValidation::Reject {
+                        anyhow::bail!("message payload does not match the topic's schema: {err}");
+                    }
+                    log::warn!(
+                        "message published to topic {} does not match the topic's schema: {}",
+                        name,
+                        err
+                    );
+                }
+            }
+
+            // The compressed body is published, while traces record the original payload.
+            let compressed_body = compression::compress(compression, &raw_body)?;
+            if let Some(max) = max_message_bytes {
+                let size = compressed_body.as_ref().unwrap_or(&raw_body).len();
+                if size > max {
+                    anyhow::bail!(
+                        "message of {size} bytes exceeds the maximum message size of {max} bytes for topic {name}"
+                    );
+                }
+            }
+
             let mut msg = MessageData {
                 attrs: HashMap::new(),
                 raw_body,
@@ -98,6 +139,11 @@ impl TopicInner {
                 None
             };
 
+            if let Some(encoding) = compression::content_encoding(compression) {
+                msg.attrs
+                    .insert(ATTR_CONTENT_ENCODING.to_string(), encoding.to_string());
+            }
+
             if let Some(source) = source.as_deref() {
                 msg.attrs.insert(
                     ATTR_PARENT_TRACE_ID.to_string(),
@@ -115,6 +161,9 @@ impl TopicInner {
                     topic: &name,
                     payload: &msg.raw_body,
                 });
+                if let Some(body) = compressed_body {
+                    msg.raw_body = body;
+                }
                 let result = inner.publish(msg, ordering_key).await;
                 tracer.pubsub_publish_end(protocol::PublishEndData {
                     start_id,
@@ -123,6 +172,9 @@ impl TopicInner {
                 });
                 result
             } else {
+                if let Some(body) = compressed_body {
+                    msg.raw_body = body;
+                }
                 inner.publish(msg, ordering_key).await
             }
         }
@@ -137,12 +189,29 @@ pub struct SubscriptionObj {
     topic: EncoreName,
     subscription: EncoreName,
     schema: JSONSchema,
+    schema_validation: SchemaValidation,
 
     handler: OnceLock<Arc<SubHandler>>,
     subscribe_fut: OnceLock<Shared<SubscribeFut>>,
+    drain: Arc<Drain>,
+
+    /// Whether the subscription is paused, in which case no messages are processed.
+    paused: bool,
+
+    /// Whether to wait for in-flight messages to be processed on shutdown.
+    drain_on_shutdown: bool,
+
+    /// Where to quarantine messages that repeatedly fail to be processed, if anywhere.
+    quarantine: Option<Arc<Quarantine>>,
+
+    /// Tracks message processing against the subscription's SLO, if any.
+    slo: Option<Arc<metrics::slo::Tracker>>,
+
+    /// The dedicated runtime to process messages on, if any.
+    isolation: Option<pb::pub_sub_subscription::Isolation>,
 }
 
-type SubscribeFut = Pin<Box<dyn Future<Output = APIResult<()>> + Send>>;
+pub(super) type SubscribeFut = Pin<Box<dyn Future<Output = APIResult<()>> + Send>>;
 
 impl SubscriptionObj {
     pub async fn subscribe(
@@ -158,11 +227,33 @@ impl SubscriptionObj {
         });
         h.add_handler(handler);
 
+        if self.paused {
+            log::info!(
+                "subscription {} for topic {} is paused, not processing messages",
+                self.subscription,
+                self.topic
+            );
+            return std::future::pending().await;
+        }
+
         self.subscribe_fut
-            .get_or_init(|| self.inner.subscribe(h.clone()).shared())
+            .get_or_init(|| self.start(h.clone()).shared())
             .clone()
             .await
     }
+
+    fn start(&self, handler: Arc<SubHandler>) -> SubscribeFut {
+        let fut = self.inner.subscribe(handler);
+        let Some(cfg) = &self.isolation else {
+            return fut;
+        };
+
+        let name = format!("{}-{}", self.topic, self.subscription);
+        match IsolatedRuntime::new(&name, cfg) {
+            Ok(rt) => rt.run(fut),
+            Err(err) => Box::pin(async move { Err(api::Error::internal(err)) }),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -182,9 +273,15 @@ impl SubHandler {
 
     pub(super) fn handle_message(
         &self,
-        msg: Message,
+        mut msg: Message,
     ) -> Pin<Box<dyn Future<Output = Result<(), api::Error>> + Send + '_>> {
         Box::pin(async move {
+            let Some(_guard) = self.obj.drain.enter(self.obj.drain_on_shutdown) else {
+                // We're shutting down. Leave the message unacknowledged so that it's
+                // redelivered once its ack deadline expires, without counting as a failed attempt.
+                return std::future::pending().await;
+            };
+
             let span = SpanKey(TraceId::generate(), SpanId::generate());
 
             let parent_trace_id: Option<TraceId> = msg
@@ -192,19 +289,30 @@ impl SubHandler {
                 .attrs
                 .get(ATTR_PARENT_TRACE_ID)
                 .and_then(|s| TraceId::parse_encore(s).ok());
-            let ext_correlation_id = msg.data.attrs.get(ATTR_EXT_CORRELATION_ID);
-
-            let mut de = serde_json::Deserializer::from_slice(&msg.data.raw_body);
-            let parsed_payload = self.obj.schema.deserialize(
-                &mut de,
-                jsonschema::DecodeConfig {
-                    coerce_strings: false,
-                    arrays_as_repeated_fields: false,
-                },
-            );
-            let (parsed_payload, parse_error) = match parsed_payload {
-                Ok(parsed_payload) => (Some(parsed_payload), None),
-                Err(e) => (
+            let ext_correlation_id = msg.data.attrs.get(ATTR_EXT_CORRELATION_ID).cloned();
+
+            let decompress_error = compression::decompress(&mut msg.data)
+                .err()
+                .map(|e| api::Error::invalid_argument("unable to decompress message payload", e));
+
+            let mut parsed_payload = parse_payload(&self.obj.schema, &msg.data.raw_body);
+            if self.obj.schema_validation == SchemaValidation::Warn && decompress_error.is_none() {
+                if let Err(err) = &parsed_payload {
+                    log::warn!(
+                        "message {} received by subscription {} for topic {} does not match the topic's schema: {}",
+                        msg.id,
+                        self.obj.subscription,
+                        self.obj.topic,
+                        err
+                    );
+                    // Process the message anyway, as long as it's a valid JSON object.
+                    parsed_payload = parse_payload(&JSONSchema::any(), &msg.data.raw_body);
+                }
+            }
+            let (parsed_payload, parse_error) = match (parsed_payload, decompress_error) {
+                (_, Some(err)) => (None, Some(err)),
+                (Ok(parsed_payload), None) => (Some(parsed_payload), None),
+                (Err(e), None) => (
                     None,
                     Some(api::Error::invalid_argument(
                         "unable to parse message payload",
@@ -220,7 +328,8 @@ impl SubHandler {
                 parent_trace: parent_trace_id,
                 parent_span: None,
                 caller_event_id: None,
-                ext_correlation_id: ext_correlation_id.cloned(),
+                ext_correlation_id,
+                deadline: None,
                 is_platform_request: false,
                 internal_caller: None,
                 start,
@@ -254,14 +363,48 @@ impl SubHandler {
 
             logger.info(Some(&req), "request completed", None);
 
+            let duration = tokio::time::Instant::now().duration_since(start);
+            if let Some(slo) = &self.obj.slo {
+                slo.record(result.is_ok(), duration);
+            }
+
             let resp = model::Response {
                 request: req,
-                duration: tokio::time::Instant::now().duration_since(start),
+                duration,
                 data: ResponseData::PubSub(result.clone()),
             };
 
             self.obj.tracer.request_span_end(&resp, false);
-            result
+
+            match (result, &self.obj.quarantine) {
+                (Err(err), Some(quarantine)) if quarantine.applies(msg.attempt) => {
+                    match quarantine.quarantine(&msg, &err).await {
+                        Ok(()) => {
+                            log::warn!(
+                                "message {} for subscription {} on topic {} failed {} times, quarantined it: {}",
+                                msg.id,
+                                self.obj.subscription,
+                                self.obj.topic,
+                                msg.attempt,
+                                err
+                            );
+                            // Acknowledge the message so it's not delivered again.
+                            Ok(())
+                        }
+                        Err(quarantine_err) => {
+                            log::error!(
+                                "unable to quarantine message {} for subscription {} on topic {}: {:?}",
+                                msg.id,
+                                self.obj.subscription,
+                                self.obj.topic,
+                                quarantine_err
+                            );
+                            Err(err)
+                        }
+                    }
+                }
+                (result, _) => result,
+            }
         })
     }
 
@@ -286,8 +429,10 @@ impl Manager {
         tracer: Tracer,
         clusters: Vec<pb::PubSubCluster>,
         md: &meta::Data,
+        secrets: &secrets::Manager,
+        stores: QuarantineStores<'_>,
     ) -> anyhow::Result<Self> {
-        let (topic_cfg, sub_cfg) = make_cfg_maps(clusters, md)?;
+        let (topic_cfg, sub_cfg) = make_cfg_maps(clusters, md, secrets, stores)?;
 
         Ok(Self {
             publisher_id: xid::new(),
@@ -297,6 +442,7 @@ impl Manager {
             topics: Arc::default(),
             subs: Arc::default(),
             push_registry: PushHandlerRegistry::new(),
+            drain: Arc::new(Drain::new()),
         })
     }
 
@@ -312,21 +458,32 @@ impl Manager {
 
         let topic = Arc::new({
             if let Some(cfg) = self.topic_cfg.get(&name) {
-                let imp = cfg.cluster.topic(&cfg.cfg, self.publisher_id);
+                let imp = replay::wrap(&name, cfg.cluster.topic(&cfg.cfg, self.publisher_id));
                 TopicInner {
                     name: name.clone(),
                     imp,
                     tracer: self.tracer.clone(),
                     attr_fields: cfg.attr_fields.clone(),
                     ordering_attr: cfg.cfg.ordering_attr.clone(),
+                    schema: cfg.schema.clone(),
+                    schema_validation: cfg.cfg.schema_validation(),
+                    max_message_bytes: cfg
+                        .cfg
+                        .max_message_bytes
+                        .and_then(|n| usize::try_from(n).ok()),
+                    compression: cfg.cfg.compression(),
                 }
             } else {
                 TopicInner {
                     name: name.clone(),
-                    imp: Arc::new(noop::NoopTopic),
+                    imp: replay::wrap(&name, Arc::new(noop::NoopTopic)),
                     tracer: self.tracer.clone(),
                     attr_fields: Arc::new(vec![]),
                     ordering_attr: None,
+                    schema: JSONSchema::null(),
+                    schema_validation: SchemaValidation::Unspecified,
+                    max_message_bytes: None,
+                    compression: Compression::Unspecified,
                 }
             }
         });
@@ -345,8 +502,11 @@ impl Manager {
                 let inner = cfg.cluster.subscription(&cfg.cfg, &cfg.meta);
 
                 // If we have a push handler, register it.
+                // Paused subscriptions don't accept pushes, so they're redelivered later.
                 if let Some((sub_id, push_handler)) = inner.push_handler() {
-                    self.push_registry.register(sub_id, push_handler);
+                    if !cfg.cfg.paused {
+                        self.push_registry.register(sub_id, push_handler);
+                    }
                 }
 
                 Arc::new(SubscriptionObj {
@@ -356,8 +516,15 @@ impl Manager {
                     topic: name.topic.clone(),
                     subscription: name.subscription.clone(),
                     schema: cfg.schema.clone(),
+                    schema_validation: cfg.cfg.schema_validation(),
                     handler: OnceLock::new(),
                     subscribe_fut: Default::default(),
+                    drain: self.drain.clone(),
+                    paused: cfg.cfg.paused,
+                    drain_on_shutdown: cfg.cfg.drain_on_shutdown.unwrap_or(true),
+                    quarantine: cfg.quarantine.clone(),
+                    slo: cfg.slo.clone(),
+                    isolation: cfg.cfg.isolation,
                 })
             } else {
                 let inner = Arc::new(noop::NoopSubscription);
@@ -375,9 +542,16 @@ impl Manager {
                     // We don't have a schema since it's an unknown subscription.
                     // Use a null schema.
                     schema: JSONSchema::null(),
+                    schema_validation: SchemaValidation::Unspecified,
 
                     handler: OnceLock::new(),
                     subscribe_fut: Default::default(),
+                    drain: self.drain.clone(),
+                    paused: false,
+                    drain_on_shutdown: true,
+                    quarantine: None,
+                    slo: None,
+                    isolation: None,
                 })
             }
         };
@@ -390,6 +564,67 @@ impl Manager {
     pub fn push_registry(&self) -> PushHandlerRegistry {
         self.push_registry.clone()
     }
+
+    /// Stops processing new messages and waits for in-flight messages to be processed.
+    pub async fn shutdown(&self) {
+        self.drain.start().await
+    }
+}
+
+/// The resources quarantined messages can be stored in.
+pub struct QuarantineStores<'a> {
+    pub objects: &'a objects::Manager,
+    pub sqldb: &'a sqldb::Manager,
+    pub crypto: &'a crypto::Manager,
+    pub metrics: &'a Arc<metrics::Registry>,
+}
+
+/// Tracks the messages being processed, so that subscriptions can be drained on shutdown.
+#[derive(Debug)]
+struct Drain {
+    draining: AtomicBool,
+    in_flight: watch::Sender<usize>,
+}
+
+/// Marks a message as being processed. Only messages of subscriptions
+/// that are drained on shutdown are tracked.
+struct DrainGuard<'a>(Option<&'a Drain>);
+
+impl Drop for DrainGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(drain) = self.0 {
+            drain.in_flight.send_modify(|n| *n -= 1);
+        }
+    }
+}
+
+impl Drain {
+    fn new() -> Self {
+        Self {
+            draining: AtomicBool::new(false),
+            in_flight: watch::channel(0).0,
+        }
+    }
+
+    /// Registers a message as being processed, unless the subscriptions are being drained.
+    /// If `wait` is true, draining waits for the message to be processed.
+    fn enter(&self, wait: bool) -> Option<DrainGuard<'_>> {
+        let guard = if wait {
+            self.in_flight.send_modify(|n| *n += 1);
+            DrainGuard(Some(self))
+        } else {
+            DrainGuard(None)
+        };
+        if self.draining.load(Ordering::Acquire) {
+            return None;
+        }
+        Some(guard)
+    }
+
+    async fn start(&self) {
+        self.draining.store(true, Ordering::Release);
+        let _ = self.in_flight.subscribe().wait_for(|n| *n == 0).await;
+    }
 }
 
 #[derive(Debug)]
@@ -400,6 +635,9 @@ struct TopicConfig {
     /// Names of fields in the payload that should be copied into
     /// the PubSub message attributes.
     attr_fields: Arc<Vec<String>>,
+
+    /// The schema of the topic's messages.
+    schema: JSONSchema,
 }
 
 #[derive(Debug)]
@@ -408,11 +646,15 @@ struct SubConfig {
     cfg: pb::PubSubSubscription,
     meta: meta::pub_sub_topic::Subscription,
     schema: JSONSchema,
+    quarantine: Option<Arc<Quarantine>>,
+    slo: Option<Arc<metrics::slo::Tracker>>,
 }
 
 fn make_cfg_maps(
     clusters: Vec<pb::PubSubCluster>,
     md: &meta::Data,
+    secrets: &secrets::Manager,
+    stores: QuarantineStores<'_>,
 ) -> anyhow::Result<(
     HashMap<EncoreName, TopicConfig>,
     HashMap<SubName, SubConfig>,
@@ -434,7 +676,7 @@ fn make_cfg_maps(
                 .register_type(schema_type)
                 .with_context(|| format!("invalid schema for topic {}", topic.name))?;
 
-            topic_map.insert(topic.name.clone(), Arc::new(attr_fields));
+            topic_map.insert(topic.name.clone(), (Arc::new(attr_fields), schema_idx));
             for sub in &topic.subscriptions {
                 let name = SubName {
                     topic: topic.name.clone().into(),
@@ -448,23 +690,46 @@ fn make_cfg_maps(
 
     let schemas = schema_builder.build();
     for cluster_cfg in clusters {
-        let cluster = new_cluster(&cluster_cfg);
-
-        for topic_cfg in cluster_cfg.topics {
-            let Some(attr_fields) = meta_topics.get(&topic_cfg.encore_name) else {
+        let cluster = new_cluster(&cluster_cfg, secrets);
+
+        // NSQ and SNS carry message bodies as text, so they can't carry compressed bodies.
+        let text_bodies = matches!(
+            cluster_cfg.provider,
+            Some(pb::pub_sub_cluster::Provider::Nsq(_) | pb::pub_sub_cluster::Provider::Aws(_))
+        );
+        let dead_letter_supported = matches!(
+            cluster_cfg.provider,
+            Some(
+                pb::pub_sub_cluster::Provider::Gcp(_)
+                    | pb::pub_sub_cluster::Provider::Aws(_)
+                    | pb::pub_sub_cluster::Provider::Nsq(_)
+                    | pb::pub_sub_cluster::Provider::Kafka(_)
+            )
+        );
+
+        for mut topic_cfg in cluster_cfg.topics {
+            let Some((attr_fields, idx)) = meta_topics.get(&topic_cfg.encore_name) else {
                 anyhow::bail!("topic {} not found in metadata", topic_cfg.encore_name);
             };
+            if text_bodies && topic_cfg.compression() != Compression::Unspecified {
+                log::warn!(
+                    "topic {}: compression is not supported by the PubSub provider, publishing uncompressed messages",
+                    topic_cfg.encore_name
+                );
+                topic_cfg.set_compression(Compression::Unspecified);
+            }
             topic_map.insert(
                 topic_cfg.encore_name.clone().into(),
                 TopicConfig {
                     cluster: cluster.clone(),
                     cfg: topic_cfg,
                     attr_fields: attr_fields.clone(),
+                    schema: schemas.schema(*idx),
                 },
             );
         }
 
-        for sub_cfg in cluster_cfg.subscriptions {
+        for mut sub_cfg in cluster_cfg.subscriptions {
             let topic_name = sub_cfg.topic_encore_name.clone().into();
             let sub_name = sub_cfg.subscription_encore_name.clone().into();
             let name = SubName {
@@ -475,14 +740,102 @@ fn make_cfg_maps(
                 continue;
             };
 
+            if let Some(dead_letter) = &sub_cfg.dead_letter {
+                if dead_letter.max_delivery_attempts == 0 || dead_letter.target.is_empty() {
+                    anyhow::bail!(
+                        "invalid dead letter for subscription {} on topic {}: target and a positive max_delivery_attempts are required",
+                        name.subscription,
+                        name.topic
+                    );
+                }
+                if !dead_letter_supported {
+                    log::warn!(
+                        "subscription {} on topic {}: dead-lettering is not supported by the PubSub provider, ignoring it",
+                        name.subscription,
+                        name.topic
+                    );
+                    sub_cfg.dead_letter = None;
+                }
+            }
+
+            // GCP Pub/Sub only counts delivery attempts for subscriptions with a dead-letter
+            // policy. Without one every delivery is reported as the first, so messages
+            // would never reach the quarantine's attempt limit.
+            if sub_cfg.quarantine.is_some()
+                && sub_cfg.dead_letter.is_none()
+                && matches!(
+                    cluster_cfg.provider,
+                    Some(pb::pub_sub_cluster::Provider::Gcp(_))
+                )
+            {
+                anyhow::bail!(
+                    "invalid quarantine for subscription {} on topic {}: GCP Pub/Sub subscriptions must also configure dead_letter to count delivery attempts",
+                    name.subscription,
+                    name.topic
+                );
+            }
+
+            let quarantine = sub_cfg
+                .quarantine
+                .as_ref()
+                .map(|q| {
+                    Quarantine::new(
+                        name.topic.clone(),
+                        name.subscription.clone(),
+                        q,
+                        stores.objects,
+                        stores.sqldb,
+                        stores.crypto,
+                        stores.metrics,
+                    )
+                    .map(Arc::new)
+                    .with_context(|| {
+                        format!(
+                            "invalid quarantine for subscription {} on topic {}",
+                            name.subscription, name.topic
+                        )
+                    })
+                })
+                .transpose()?;
+
+            let slo = sub_cfg
+                .slo
+                .as_ref()
+                .map(|cfg| {
+                    let labels: [(&str, &str); 2] =
+                        [("topic", &name.topic), ("subscription", &name.subscription)];
+                    metrics::slo::Tracker::register(stores.metrics, cfg, &labels).with_context(
+                        || {
+                            format!(
+                                "invalid slo for subscription {} on topic {}",
+                                name.subscription, name.topic
+                            )
+                        },
+                    )
+                })
+                .transpose()?;
+
+            let mut meta = meta_sub.to_owned();
+            if let Some(retry) = &sub_cfg.retry_policy {
+                override_retry_policy(&mut meta, retry);
+            }
+            if let Some(ack_deadline) = sub_cfg
+                .ack_deadline
+                .and_then(|d| std::time::Duration::try_from(d).ok())
+            {
+                meta.ack_deadline = ack_deadline.as_nanos().min(i64::MAX as u128) as i64;
+            }
+
             let schema = schemas.schema(idx);
             sub_map.insert(
                 name,
                 SubConfig {
                     cluster: cluster.clone(),
                     cfg: sub_cfg,
-                    meta: meta_sub.to_owned(),
+                    meta,
                     schema,
+                    quarantine,
+                    slo,
                 },
             );
         }
@@ -491,24 +844,106 @@ fn make_cfg_maps(
     Ok((topic_map, sub_map))
 }
 
-fn new_cluster(cluster: &pb::PubSubCluster) -> Arc<dyn Cluster> {
+/// Applies the retry policy configured for the subscription
+/// on top of the one defined by the application.
+fn override_retry_policy(
+    meta: &mut meta::pub_sub_topic::Subscription,
+    retry: &pb::pub_sub_subscription::RetryPolicy,
+) {
+    let nanos = |d: Option<prost_types::Duration>| {
+        d.and_then(|d| std::time::Duration::try_from(d).ok())
+            .map(|d| d.as_nanos().min(i64::MAX as u128) as i64)
+    };
+
+    let policy = meta.retry_policy.get_or_insert_with(Default::default);
+    if let Some(min_backoff) = nanos(retry.min_backoff) {
+        policy.min_backoff = min_backoff;
+    }
+    if let Some(max_backoff) = nanos(retry.max_backoff) {
+        policy.max_backoff = max_backoff;
+    }
+    if let Some(max_retries) = retry.max_retries {
+        policy.max_retries = max_retries;
+    }
+}
+
+/// Parses a message payload according to the topic's message schema.
+fn parse_payload(
+    schema: &JSONSchema,
+    raw_body: &[u8],
+) -> Result<PValues, serde_path_to_error::Error<serde_json::Error>> {
+    let mut de = serde_json::Deserializer::from_slice(raw_body);
+    schema.deserialize(
+        &mut de,
+        jsonschema::DecodeConfig {
+            coerce_strings: false,
+            arrays_as_repeated_fields: false,
+        },
+    )
+}
+
+fn new_cluster(cluster: &pb::PubSubCluster, secrets: &secrets::Manager) -> Arc<dyn Cluster> {
     let Some(provider) = &cluster.provider else {
         log::error!("missing PubSub cluster provider: {}", cluster.rid);
         return Arc::new(NoopCluster);
     };
 
     match provider {
-        pb::pub_sub_cluster::Provider::Gcp(_) => return Arc::new(gcp::Cluster::new()),
+        pb::pub_sub_cluster::Provider::Gcp(_) => {
+            return Arc::new(gcp::Cluster::new(&cluster.subscriptions, secrets));
+        }
         pb::pub_sub_cluster::Provider::Nsq(cfg) => {
             return Arc::new(nsq::Cluster::new(cfg.hosts[0].clone()));
         }
-        pb::pub_sub_cluster::Provider::Aws(_) => return Arc::new(sqs_sns::Cluster::new()),
+        pb::pub_sub_cluster::Provider::Aws(_) => {
+            match sqs_sns::Cluster::new(&cluster.topics, &cluster.subscriptions) {
+                Ok(aws) => return Arc::new(aws),
+                Err(err) => {
+                    log::error!(
+                        "invalid AWS SNS/SQS PubSub cluster {}: {:#}",
+                        cluster.rid,
+                        err
+                    );
+                }
+            }
+        }
+        #[cfg(feature = "kafka")]
+        pb::pub_sub_cluster::Provider::Kafka(cfg) => {
+            match kafka::Cluster::new(cfg, &cluster.topics, secrets) {
+                Ok(kafka) => return Arc::new(kafka),
+                Err(err) => {
+                    log::error!("invalid Kafka PubSub cluster {}: {:#}", cluster.rid, err);
+                }
+            }
+        }
+        #[cfg(not(feature = "kafka"))]
+        pb::pub_sub_cluster::Provider::Kafka(_) => {
+            log::error!(
+                "Kafka PubSub cluster {} requires a runtime built with the kafka feature",
+                cluster.rid
+            );
+        }
+        pb::pub_sub_cluster::Provider::Rabbitmq(cfg) => {
+            match rabbitmq::Cluster::new(cfg, &cluster.topics, secrets) {
+                Ok(rabbitmq) => return Arc::new(rabbitmq),
+                Err(err) => {
+                    log::error!("invalid RabbitMQ PubSub cluster {}: {:#}", cluster.rid, err);
+                }
+            }
+        }
         pb::pub_sub_cluster::Provider::Encore(_) => {
             log::error!("Encore Cloud Pub/Sub not yet supported: {}", cluster.rid);
         }
-        pb::pub_sub_cluster::Provider::Azure(_) => {
-            log::error!("Azure Pub/Sub not yet supported: {}", cluster.rid);
-        }
+        pb::pub_sub_cluster::Provider::Azure(cfg) => match azure::Cluster::new(cfg, secrets) {
+            Ok(azure) => return Arc::new(azure),
+            Err(err) => {
+                log::error!(
+                    "invalid Azure Service Bus PubSub cluster {}: {:#}",
+                    cluster.rid,
+                    err
+                );
+            }
+        },
     }
 
     Arc::new(NoopCluster)
@@ -556,6 +991,7 @@ fn message_attr_fields(
 
         Typ::Map(_)
         | Typ::List(_)
+        | Typ::Tuple(_)
         | Typ::Builtin(_)
         | Typ::Pointer(_)
         | Typ::Option(_)
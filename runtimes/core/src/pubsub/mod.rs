@@ -4,7 +4,7 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 
-pub use manager::{Manager, SubscriptionObj, TopicObj};
+pub use manager::{Manager, QuarantineStores, SubscriptionObj, TopicObj};
 pub use push_registry::PushHandlerRegistry;
 
 use crate::api::APIResult;
@@ -12,13 +12,22 @@ use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::names::EncoreName;
 use crate::pubsub::manager::SubHandler;
-use crate::{api, model};
+use crate::{api, model, startup};
 
+mod azure;
+mod compression;
 mod gcp;
+mod isolation;
+#[cfg(feature = "kafka")]
+mod kafka;
 mod manager;
 mod noop;
 mod nsq;
 mod push_registry;
+mod quarantine;
+mod rabbitmq;
+mod replay;
+mod retry;
 mod sqs_sns;
 
 pub type MessageId = String;
@@ -36,6 +45,34 @@ pub struct Message {
     pub data: MessageData,
 }
 
+/// Returns the message brokers to wait for at startup.
+///
+/// Only self-hosted brokers are included, as managed services like
+/// GCP Pub/Sub and SQS are expected to be available.
+pub fn startup_dependencies(clusters: &[pb::PubSubCluster]) -> Vec<startup::Dependency> {
+    clusters
+        .iter()
+        .filter_map(|cluster| match &cluster.provider {
+            Some(pb::pub_sub_cluster::Provider::Nsq(cfg)) => {
+                cfg.hosts.first().map(|addr| ("nsq", addr.clone()))
+            }
+            Some(pb::pub_sub_cluster::Provider::Kafka(cfg)) => {
+                cfg.brokers.first().map(|addr| ("kafka", addr.clone()))
+            }
+            _ => None,
+        })
+        .map(|(kind, addr)| {
+            startup::Dependency::new(format!("{kind} {addr}"), move || {
+                let addr = addr.clone();
+                async move {
+                    tokio::net::TcpStream::connect(&addr).await?;
+                    Ok(())
+                }
+            })
+        })
+        .collect()
+}
+
 trait Cluster: Debug + Send + Sync {
     fn topic(&self, cfg: &pb::PubSubTopic, publisher_id: xid::Id) -> Arc<dyn Topic + 'static>;
     fn subscription(
@@ -14,13 +14,32 @@ use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::pubsub;
 use crate::pubsub::manager::SubHandler;
-use crate::pubsub::nsq::topic::EncodedMessage;
+use crate::pubsub::nsq::topic::{EncodedMessage, NsqTopic};
 use crate::pubsub::Subscription;
 
+/// The delay before retrying a message, if the retry policy doesn't specify one.
+const DEFAULT_MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between retries, if the retry policy doesn't specify one.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The longest delay NSQ accepts when requeueing a message, by default.
+const MAX_REQUEUE_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How long nsqd waits for a message to be finished before redelivering it, by default.
+const DEFAULT_MSG_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The shortest interval between touching in-flight messages.
+const MIN_TOUCH_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct NsqSubscription {
     addr: String,
     config: NSQConsumerConfig,
     max_retries: i64,
+    backoff: Backoff,
+    /// How often in-flight messages are touched, to keep nsqd from redelivering them.
+    touch_interval: Duration,
+    dead_letter: Option<pb::pub_sub_subscription::DeadLetter>,
 }
 
 impl Debug for NsqSubscription {
@@ -43,7 +62,7 @@ impl NsqSubscription {
         let channel = NSQChannel::new(&cfg.subscription_cloud_name)
             .expect("subscription_cloud_name should be valid NSQ channel name");
 
-        let mut config = NSQConsumerConfig::new(topic, channel)
+        let config = NSQConsumerConfig::new(topic, channel)
             .set_sources(NSQConsumerConfigSources::Daemons(vec![addr.clone()]))
             .set_max_in_flight(meta.max_concurrency.map_or(100, |v| v as u32));
 
@@ -51,28 +70,45 @@ impl NsqSubscription {
         // We don't want to retry forever but zero retries might cause surprises when suddenly
         // things start retrying in other environments.
         let mut max_retries = 2;
+        let mut backoff = Backoff {
+            min: DEFAULT_MIN_BACKOFF,
+            max: DEFAULT_MAX_BACKOFF,
+        };
 
         if let Some(retry) = &meta.retry_policy {
-            let min_backoff = Duration::from_nanos(retry.min_backoff.max(0) as u64);
-            config = config.set_base_requeue_interval(clamp(
-                min_backoff,
-                Duration::from_secs(0),
-                Duration::from_secs(60 * 60),
-            ));
-
-            let max_backoff = Duration::from_nanos(retry.max_backoff.max(0) as u64);
-            config = config.set_max_requeue_interval(clamp(
-                max_backoff,
-                Duration::from_secs(0),
-                Duration::from_secs(60 * 60),
-            ));
+            // Zero backoffs would make failing messages retry in a hot loop,
+            // so use the defaults instead.
+            if retry.min_backoff > 0 {
+                backoff.min = clamp(
+                    Duration::from_nanos(retry.min_backoff as u64),
+                    Duration::from_secs(0),
+                    MAX_REQUEUE_DELAY,
+                );
+            }
+            if retry.max_backoff > 0 {
+                backoff.max = clamp(
+                    Duration::from_nanos(retry.max_backoff as u64),
+                    backoff.min,
+                    MAX_REQUEUE_DELAY,
+                );
+            }
             max_retries = retry.max_retries;
         }
 
+        // Touch messages halfway through nsqd's message timeout.
+        let msg_timeout = cfg
+            .ack_deadline
+            .and_then(|d| Duration::try_from(d).ok())
+            .unwrap_or(DEFAULT_MSG_TIMEOUT);
+        let touch_interval = (msg_timeout / 2).max(MIN_TOUCH_INTERVAL);
+
         NsqSubscription {
             addr,
             config,
             max_retries,
+            backoff,
+            touch_interval,
+            dead_letter: cfg.dead_letter.clone(),
         }
     }
 }
@@ -84,31 +120,93 @@ impl Subscription for NsqSubscription {
     ) -> Pin<Box<dyn Future<Output = APIResult<()>> + Send + 'static>> {
         let mut consumer = self.config.clone().build();
         let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let touch_interval = self.touch_interval;
+        let addr = self.addr.clone();
+        let dead_letter = self.dead_letter.clone();
 
         Box::pin(async move {
+            // Created here rather than up front, as publishing requires a runtime.
+            let dead_letter = dead_letter.map(|cfg| {
+                Arc::new(DeadLetter {
+                    topic: NsqTopic::with_cloud_name(addr, cfg.target),
+                    max_attempts: cfg.max_delivery_attempts,
+                })
+            });
+
             loop {
-                let Some(msg) = consumer.consume_filtered().await else {
+                let Some(mut msg) = consumer.consume_filtered().await else {
                     continue;
                 };
 
-                // If the attempt exceeds the max retries, drop it.
-                // Attempt starts at 1 for the first delivery, which means
-                // the retry count is (attempt-1).
-                let retry = msg.attempt as i64 - 1;
-                if retry > max_retries {
-                    msg.finish().await;
-                    continue;
+                if let Some(dead_letter) = &dead_letter {
+                    // The message may have failed as many times as allowed
+                    // without being dead-lettered, if the runtime stopped in between.
+                    if u32::from(msg.attempt) > dead_letter.max_attempts {
+                        let body = msg.body.drain(..).collect();
+                        dead_letter.forward(msg, body, backoff).await;
+                        continue;
+                    }
+                } else {
+                    // If the attempt exceeds the max retries, drop it.
+                    // Attempt starts at 1 for the first delivery, which means
+                    // the retry count is (attempt-1).
+                    let retry = msg.attempt as i64 - 1;
+                    if retry > max_retries {
+                        msg.finish().await;
+                        continue;
+                    }
                 }
 
                 // Process the message asynchronously.
                 let h = handler.clone();
-                tokio::spawn(async move { process_message(msg, h).await });
+                let dead_letter = dead_letter.clone();
+                tokio::spawn(async move {
+                    process_message(msg, h, backoff, touch_interval, dead_letter).await
+                });
             }
         })
     }
 }
 
-async fn process_message(mut msg: NSQMessage, handler: Arc<SubHandler>) {
+/// Forwards messages that failed too many times to the dead-letter topic.
+struct DeadLetter {
+    topic: NsqTopic,
+    max_attempts: u32,
+}
+
+impl DeadLetter {
+    /// Publishes the message to the dead-letter topic and finishes it,
+    /// or requeues it if publishing fails.
+    async fn forward(&self, msg: NSQMessage, body: Vec<u8>, backoff: Backoff) {
+        match self.topic.publish_raw(body).await {
+            Ok(()) => {
+                log::warn!(
+                    "message failed {} times, moved it to the dead-letter topic",
+                    msg.attempt
+                );
+                msg.finish().await;
+            }
+            Err(err) => {
+                let delay = backoff.delay(msg.attempt);
+                log::error!(
+                    "unable to move message to the dead-letter topic, requeueing message in {:?}: {:?}",
+                    delay,
+                    err
+                );
+                msg.requeue(NSQRequeueDelay::CustomDelay(delay)).await;
+            }
+        }
+    }
+}
+
+async fn process_message(
+    mut msg: NSQMessage,
+    handler: Arc<SubHandler>,
+    backoff: Backoff,
+    touch_interval: Duration,
+    dead_letter: Option<Arc<DeadLetter>>,
+) {
     let body: Vec<u8> = msg.body.drain(..).collect();
     let timestamp = msg.timestamp;
     let attempt = msg.attempt;
@@ -116,9 +214,9 @@ async fn process_message(mut msg: NSQMessage, handler: Arc<SubHandler>) {
     // Create a channel to signal when to stop sending touch messages
     let (stop_tx, mut stop_rx) = tokio::sync::oneshot::channel::<()>();
 
-    // Spawn a background task to send touch messages every 30 seconds
+    // Spawn a background task to send touch messages until the message is processed
     let touch_handle = tokio::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(30));
+        let mut interval = tokio::time::interval(touch_interval);
         // Skip the first tick (immediate)
         interval.tick().await;
 
@@ -138,7 +236,8 @@ async fn process_message(mut msg: NSQMessage, handler: Arc<SubHandler>) {
         msg
     });
 
-    let result = handle_message(body, timestamp, attempt, handler).await;
+    // Keep the encoded message in case it needs to be dead-lettered.
+    let result = handle_message(body.clone(), timestamp, attempt, handler).await;
 
     // Signal the touch task to stop and return the message
     let _ = stop_tx.send(());
@@ -158,10 +257,21 @@ async fn process_message(mut msg: NSQMessage, handler: Arc<SubHandler>) {
 
     match result {
         Ok(()) => msg.finish().await,
-        Err(err) => {
-            log::info!("message handler failed, requeueing message: {:?}", err);
-            msg.requeue(NSQRequeueDelay::DefaultDelay).await;
-        }
+        Err(err) => match dead_letter {
+            Some(dead_letter) if u32::from(attempt) >= dead_letter.max_attempts => {
+                log::info!("message handler failed: {:?}", err);
+                dead_letter.forward(msg, body, backoff).await;
+            }
+            _ => {
+                let delay = backoff.delay(attempt);
+                log::info!(
+                    "message handler failed, requeueing message in {:?}: {:?}",
+                    delay,
+                    err
+                );
+                msg.requeue(NSQRequeueDelay::CustomDelay(delay)).await;
+            }
+        },
     }
 }
 
@@ -192,6 +302,23 @@ async fn handle_message(
         .context("message handler failed")
 }
 
+/// Computes how long to wait before retrying a message,
+/// backing off exponentially with each attempt.
+#[derive(Debug, Copy, Clone)]
+struct Backoff {
+    min: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    /// Returns the delay before retrying a message that failed on the given attempt.
+    fn delay(&self, attempt: u16) -> Duration {
+        let exp = u32::from(attempt.max(1) - 1);
+        let factor = 1u32.checked_shl(exp).unwrap_or(u32::MAX);
+        self.min.saturating_mul(factor).min(self.max)
+    }
+}
+
 fn nano_timestamp(mut nsec: u64) -> Option<chrono::DateTime<chrono::Utc>> {
     // From Go's time.Unix.
     let mut sec: i64 = 0;
@@ -212,3 +339,21 @@ fn clamp<T: PartialOrd>(val: T, min: T, max: T) -> T {
         val
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_delay() {
+        let backoff = Backoff {
+            min: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+        };
+        assert_eq!(backoff.delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.delay(2), Duration::from_secs(2));
+        assert_eq!(backoff.delay(4), Duration::from_secs(8));
+        assert_eq!(backoff.delay(5), Duration::from_secs(10));
+        assert_eq!(backoff.delay(u16::MAX), Duration::from_secs(10));
+    }
+}
@@ -11,8 +11,9 @@ use crate::encore::runtime::v1 as pb;
 use crate::pubsub::{MessageData, MessageId, Topic};
 
 struct PublishRequest {
-    msg: MessageData,
-    resp: oneshot::Sender<Result<MessageId>>,
+    /// The encoded message.
+    bytes: Vec<u8>,
+    resp: oneshot::Sender<Result<()>>,
 }
 
 #[derive(Debug)]
@@ -22,7 +23,11 @@ pub struct NsqTopic {
 
 impl NsqTopic {
     pub(super) fn new(addr: String, cfg: &pb::PubSubTopic) -> Self {
-        let cloud_name = cfg.cloud_name.clone();
+        Self::with_cloud_name(addr, cfg.cloud_name.clone())
+    }
+
+    /// Creates a publisher for the NSQ topic with the given name.
+    pub(super) fn with_cloud_name(addr: String, cloud_name: String) -> Self {
         let (tx, mut rx) = mpsc::channel::<PublishRequest>(32);
         tokio::spawn(async move {
             let topic =
@@ -44,18 +49,13 @@ impl NsqTopic {
                             break;
                         };
 
-                        // Serialize the message body.
-                        let encoded = EncodedMessage::new_for_data(req.msg);
-                        let bytes = serde_json::to_vec(&encoded).expect("unable to serialize request");
-
-                        // TODO handle error
-                        producer
-                            .publish(&topic, bytes)
+                        let result = producer
+                            .publish(&topic, req.bytes)
                             .await
-                            .expect("failed to publish message");
+                            .context("failed to publish message");
 
                         // Ignore error.
-                        _ = req.resp.send(Ok(encoded.id));
+                        _ = req.resp.send(result);
                     }
                     _ = producer.consume() => {}
                 }
@@ -64,6 +64,18 @@ impl NsqTopic {
 
         NsqTopic { tx }
     }
+
+    /// Publishes an already encoded message as is.
+    pub(super) async fn publish_raw(&self, bytes: Vec<u8>) -> Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel::<Result<()>>();
+        let req = PublishRequest {
+            bytes,
+            resp: resp_tx,
+        };
+        self.tx.send(req).await.context("failed to send message")?;
+
+        resp_rx.await.context("failed to receive response")?
+    }
 }
 
 impl Topic for NsqTopic {
@@ -72,13 +84,12 @@ impl Topic for NsqTopic {
         msg: MessageData,
         _ordering_key: Option<String>,
     ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
-        let tx = self.tx.clone();
         Box::pin(async move {
-            let (resp_tx, resp_rx) = oneshot::channel::<Result<MessageId>>();
-            let req = PublishRequest { msg, resp: resp_tx };
-            tx.send(req).await.context("failed to send message")?;
-
-            resp_rx.await.context("failed to receive response")?
+            // Serialize the message body.
+            let encoded = EncodedMessage::new_for_data(msg);
+            let bytes = serde_json::to_vec(&encoded).context("unable to serialize message")?;
+            self.publish_raw(bytes).await?;
+            Ok(encoded.id)
         })
     }
 }
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::encore::runtime::v1 as pb;
+use crate::names::EncoreName;
+use crate::pubsub::Message;
+use crate::sqldb::is_valid_table_name;
+use crate::{api, crypto, metrics, objects, sqldb};
+
+const DEFAULT_TABLE: &str = "encore_pubsub_quarantine";
+
+/// The field holding the base64-encoded ciphertext of encrypted payloads.
+const ENCRYPTED_FIELD: &str = "$encrypted";
+
+/// The additional authenticated data that encrypted payloads are bound to.
+fn payload_aad(topic: &str, subscription: &str, message_id: &str) -> String {
+    format!("{topic}/{subscription}/{message_id}")
+}
+
+/// Quarantines messages that repeatedly fail to be processed,
+/// so that a single bad message can't block a subscription forever.
+///
+/// Quarantined messages are written to the configured store, after which
+/// they're acknowledged so they're not delivered again.
+pub struct Quarantine {
+    topic: EncoreName,
+    subscription: EncoreName,
+    max_attempts: u32,
+    store: Store,
+    /// The key to encrypt payloads with before storing them, if any.
+    encryption_key: Option<Arc<crypto::EncryptionKey>>,
+    quarantined: metrics::counter::Schema<u64>,
+}
+
+/// A message that was quarantined, as written to the store.
+#[derive(Debug, Serialize)]
+struct QuarantinedMessage<'a> {
+    topic: &'a str,
+    subscription: &'a str,
+    message_id: &'a str,
+    attempt: u32,
+    published_at: Option<DateTime<Utc>>,
+    quarantined_at: DateTime<Utc>,
+    attributes: &'a HashMap<String, String>,
+    payload: serde_json::Value,
+    error: String,
+}
+
+impl Quarantine {
+    pub fn new(
+        topic: EncoreName,
+        subscription: EncoreName,
+        cfg: &pb::pub_sub_subscription::Quarantine,
+        objects: &objects::Manager,
+        sqldb: &sqldb::Manager,
+        crypto: &crypto::Manager,
+        metrics: &Arc<metrics::Registry>,
+    ) -> anyhow::Result<Self> {
+        use pb::pub_sub_subscription::quarantine::Store as PbStore;
+        if cfg.max_attempts == 0 {
+            anyhow::bail!("quarantine max_attempts must be positive");
+        }
+
+        let store = match cfg.store.as_ref().context("missing quarantine store")? {
+            PbStore::Bucket(b) => Store::Bucket {
+                bucket: objects
+                    .bucket(EncoreName::from(&b.bucket))
+                    .with_context(|| format!("quarantine bucket {} not found", b.bucket))?,
+                key_prefix: b.key_prefix.clone().unwrap_or_default(),
+            },
+            PbStore::Sql(s) => {
+                let table = s.table.as_deref().unwrap_or(DEFAULT_TABLE);
+                if !is_valid_table_name(table) {
+                    anyhow::bail!("invalid quarantine table name {table:?}");
+                }
+                let db = sqldb.database(&EncoreName::from(&s.database));
+                let pool = db.new_pool().with_context(|| {
+                    format!("unable to connect to quarantine database {}", s.database)
+                })?;
+                Store::Sql {
+                    pool,
+                    table: table.to_string(),
+                }
+            }
+        };
+
+        let encryption_key = cfg
+            .encryption_key
+            .as_ref()
+            .map(|name| {
+                crypto
+                    .key(&EncoreName::from(name))
+                    .with_context(|| format!("quarantine encryption key {name} not found"))
+            })
+            .transpose()?;
+
+        let quarantined = metrics
+            .counter_schema::<u64>("e_pubsub_messages_quarantined_total")
+            .static_labels([
+                ("topic", topic.as_ref()),
+                ("subscription", subscription.as_ref()),
+            ])
+            .require_dynamic_key("result")
+            .build();
+
+        Ok(Self {
+            topic,
+            subscription,
+            max_attempts: cfg.max_attempts,
+            store,
+            encryption_key,
+            quarantined,
+        })
+    }
+
+    /// Reports whether a message that failed on the given attempt should be quarantined.
+    pub fn applies(&self, attempt: u32) -> bool {
+        attempt >= self.max_attempts
+    }
+
+    /// Writes the message to the quarantine store.
+    /// If it succeeds the message can safely be acknowledged.
+    pub async fn quarantine(&self, msg: &Message, err: &api::Error) -> anyhow::Result<()> {
+        let result = self.write(msg, err).await;
+        self.quarantined
+            .with([("result", if result.is_ok() { "stored" } else { "failed" })])
+            .increment();
+        result
+    }
+
+    async fn write(&self, msg: &Message, err: &api::Error) -> anyhow::Result<()> {
+        let payload = match &self.encryption_key {
+            Some(key) => self.encrypt_payload(key, msg).await?,
+            // Keep the payload as-is if it's not valid JSON, since
+            // that may well be the reason it failed to be processed.
+            None => serde_json::from_slice(&msg.data.raw_body).unwrap_or_else(|_| {
+                serde_json::Value::String(String::from_utf8_lossy(&msg.data.raw_body).into())
+            }),
+        };
+        let record = QuarantinedMessage {
+            topic: self.topic.as_ref(),
+            subscription: self.subscription.as_ref(),
+            message_id: &msg.id,
+            attempt: msg.attempt,
+            published_at: msg.publish_time,
+            quarantined_at: Utc::now(),
+            attributes: &msg.data.attrs,
+            payload,
+            error: err.to_string(),
+        };
+        self.store.write(&record).await
+    }
+
+    /// Encrypts the raw message payload, binding it to the message
+    /// so it can't be swapped with the payload of another message.
+    async fn encrypt_payload(
+        &self,
+        key: &crypto::EncryptionKey,
+        msg: &Message,
+    ) -> anyhow::Result<serde_json::Value> {
+        let ciphertext = key
+            .encrypt(
+                &msg.data.raw_body,
+                payload_aad(&self.topic, &self.subscription, &msg.id).as_bytes(),
+            )
+            .await
+            .with_context(|| format!("unable to encrypt payload with key {}", key.name()))?;
+        Ok(serde_json::json!({
+            ENCRYPTED_FIELD: base64::engine::general_purpose::STANDARD.encode(ciphertext),
+        }))
+    }
+}
+
+impl std::fmt::Debug for Quarantine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Quarantine")
+            .field("topic", &self.topic)
+            .field("subscription", &self.subscription)
+            .field("max_attempts", &self.max_attempts)
+            .finish_non_exhaustive()
+    }
+}
+
+enum Store {
+    Bucket {
+        bucket: objects::Bucket,
+        key_prefix: String,
+    },
+    /// Inserts into a table the app creates in a migration.
+    Sql { pool: sqldb::Pool, table: String },
+}
+
+impl Store {
+    async fn write(&self, msg: &QuarantinedMessage<'_>) -> anyhow::Result<()> {
+        match self {
+            Store::Bucket { bucket, key_prefix } => {
+                let key = format!(
+                    "{}{}/{}/{}.json",
+                    key_prefix, msg.topic, msg.subscription, msg.message_id
+                );
+                let data = serde_json::to_vec(msg)?;
+                bucket
+                    .object(key)
+                    .upload(
+                        Box::new(std::io::Cursor::new(data)),
+                        objects::UploadOptions {
+                            content_type: Some("application/json".to_string()),
+                            preconditions: None,
+                        },
+                        None,
+                    )
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+                Ok(())
+            }
+
+            Store::Sql { pool, table } => {
+                let query = format!(
+                    "INSERT INTO {table} (quarantined_at, topic, subscription, message_id, attempt, published_at, attributes, payload, error)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+                );
+                let attempt = msg.attempt as i32;
+                let attributes = serde_json::to_value(msg.attributes)?;
+                exec(
+                    pool,
+                    &query,
+                    &[
+                        &msg.quarantined_at,
+                        &msg.topic,
+                        &msg.subscription,
+                        &msg.message_id,
+                        &attempt,
+                        &msg.published_at,
+                        &attributes,
+                        &msg.payload,
+                        &msg.error,
+                    ],
+                )
+                .await
+            }
+        }
+    }
+}
+
+async fn exec(
+    pool: &sqldb::Pool,
+    query: &str,
+    params: &[&(dyn tokio_postgres::types::ToSql + Sync)],
+) -> anyhow::Result<()> {
+    let mut cursor = pool
+        .query_raw(query, params.iter().copied(), None)
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    while let Some(row) = cursor.next().await {
+        row?;
+    }
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use lapin::options::ExchangeDeclareOptions;
+use lapin::tcp::OwnedTLSConfig;
+use lapin::types::FieldTable;
+use lapin::uri::AMQPUri;
+use lapin::{Channel, Connection, ConnectionProperties, ExchangeKind};
+
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub;
+use crate::pubsub::rabbitmq::sub::RabbitMQSubscription;
+use crate::pubsub::rabbitmq::topic::RabbitMQTopic;
+use crate::secrets;
+
+use pb::pub_sub_topic::rabbit_mq_config::ExchangeType;
+
+mod sub;
+mod topic;
+
+pub struct Cluster {
+    host: String,
+    conn: Arc<LazyConnection>,
+    prefetch: Option<u32>,
+    exchange_types: HashMap<String, ExchangeType>,
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The URL contains credentials, so only include the host.
+        f.debug_struct("Cluster")
+            .field("host", &self.host)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cluster {
+    pub fn new(
+        cfg: &pb::pub_sub_cluster::RabbitMq,
+        topics: &[pb::PubSubTopic],
+        secrets: &secrets::Manager,
+    ) -> anyhow::Result<Self> {
+        let data = cfg.url.clone().context("no RabbitMQ URL configured")?;
+        let secret = secrets.load(data);
+        let url = secret.get().context("unable to resolve RabbitMQ URL")?;
+        let url = std::str::from_utf8(url)
+            .context("RabbitMQ URL is not utf-8")?
+            .to_string();
+        let uri: AMQPUri = url
+            .parse()
+            .map_err(|err: String| anyhow::anyhow!(err))
+            .context("invalid RabbitMQ URL")?;
+
+        let exchange_types = topics
+            .iter()
+            .map(|topic| (topic.cloud_name.clone(), topic_config(topic).0))
+            .collect();
+
+        Ok(Self {
+            host: uri.authority.host,
+            conn: Arc::new(LazyConnection {
+                url,
+                ca_cert: ca_cert(cfg.tls_config.as_ref())?,
+                conn: tokio::sync::Mutex::new(None),
+            }),
+            prefetch: cfg.prefetch,
+            exchange_types,
+        })
+    }
+}
+
+impl pubsub::Cluster for Cluster {
+    fn topic(
+        &self,
+        cfg: &pb::PubSubTopic,
+        _publisher_id: xid::Id,
+    ) -> Arc<dyn pubsub::Topic + 'static> {
+        Arc::new(RabbitMQTopic::new(self.conn.clone(), cfg))
+    }
+
+    fn subscription(
+        &self,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Arc<dyn pubsub::Subscription + 'static> {
+        let exchange_type = self
+            .exchange_types
+            .get(&cfg.topic_cloud_name)
+            .copied()
+            .unwrap_or(ExchangeType::Fanout);
+        Arc::new(RabbitMQSubscription::new(
+            self.conn.clone(),
+            exchange_type,
+            self.prefetch,
+            cfg,
+            meta,
+        ))
+    }
+}
+
+/// Returns the exchange type and routing key of a topic.
+fn topic_config(cfg: &pb::PubSubTopic) -> (ExchangeType, String) {
+    match &cfg.provider_config {
+        Some(pb::pub_sub_topic::ProviderConfig::RabbitmqConfig(rabbitmq)) => {
+            (rabbitmq.exchange_type(), rabbitmq.routing_key.clone())
+        }
+        _ => (ExchangeType::Fanout, String::new()),
+    }
+}
+
+/// Returns the CA certificate to verify the server with, if any.
+fn ca_cert(tls: Option<&pb::TlsConfig>) -> anyhow::Result<Option<String>> {
+    let Some(tls) = tls else {
+        return Ok(None);
+    };
+    if tls.disable_ca_validation || tls.disable_tls_hostname_verification {
+        anyhow::bail!("disabling TLS verification is not supported for RabbitMQ");
+    }
+    Ok(tls.server_ca_cert.clone())
+}
+
+/// A connection that is established the first time it's used,
+/// and re-established if it's lost.
+struct LazyConnection {
+    url: String,
+    ca_cert: Option<String>,
+    conn: tokio::sync::Mutex<Option<Arc<Connection>>>,
+}
+
+impl LazyConnection {
+    async fn get(&self) -> anyhow::Result<Arc<Connection>> {
+        let mut conn = self.conn.lock().await;
+        if let Some(existing) = conn.as_ref().filter(|c| c.status().connected()) {
+            return Ok(existing.clone());
+        }
+
+        let props = ConnectionProperties::default()
+            .with_executor(tokio_executor_trait::Tokio::current())
+            .with_reactor(tokio_reactor_trait::Tokio);
+        let tls = OwnedTLSConfig {
+            identity: None,
+            cert_chain: self.ca_cert.clone(),
+        };
+        let new_conn = Connection::connect_with_config(&self.url, props, tls)
+            .await
+            .context("unable to connect to RabbitMQ")?;
+        let new_conn = Arc::new(new_conn);
+        *conn = Some(new_conn.clone());
+        Ok(new_conn)
+    }
+
+    async fn channel(&self) -> anyhow::Result<Channel> {
+        self.get()
+            .await?
+            .create_channel()
+            .await
+            .context("unable to open RabbitMQ channel")
+    }
+}
+
+/// Declares a durable exchange, unless it already exists.
+async fn declare_exchange(
+    channel: &Channel,
+    name: &str,
+    exchange_type: ExchangeType,
+) -> anyhow::Result<()> {
+    let kind = match exchange_type {
+        ExchangeType::Fanout => ExchangeKind::Fanout,
+        ExchangeType::Direct => ExchangeKind::Direct,
+        ExchangeType::Topic => ExchangeKind::Topic,
+    };
+    let opts = ExchangeDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+    channel
+        .exchange_declare(name, kind, opts, FieldTable::default())
+        .await
+        .with_context(|| format!("unable to declare RabbitMQ exchange {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ca_cert() {
+        assert_eq!(ca_cert(None).unwrap(), None);
+
+        let tls = pb::TlsConfig {
+            server_ca_cert: Some("CA".into()),
+            disable_tls_hostname_verification: false,
+            disable_ca_validation: false,
+        };
+        assert_eq!(ca_cert(Some(&tls)).unwrap().as_deref(), Some("CA"));
+
+        let insecure = pb::TlsConfig {
+            disable_ca_validation: true,
+            ..tls
+        };
+        assert!(ca_cert(Some(&insecure)).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use futures::StreamExt;
+use lapin::message::Delivery;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions, QueueBindOptions,
+    QueueDeclareOptions,
+};
+use lapin::types::{AMQPValue, FieldTable};
+
+use crate::api::APIResult;
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub;
+use crate::pubsub::manager::SubHandler;
+use crate::pubsub::rabbitmq::{declare_exchange, LazyConnection};
+use crate::pubsub::retry::RetryPolicy;
+use crate::pubsub::Subscription;
+
+use pb::pub_sub_topic::rabbit_mq_config::ExchangeType;
+
+/// How long to wait before reconnecting after the consumer fails.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+pub struct RabbitMQSubscription {
+    conn: Arc<LazyConnection>,
+    binding: Arc<Binding>,
+}
+
+/// Where a subscription consumes messages from.
+#[derive(Debug)]
+struct Binding {
+    exchange: String,
+    exchange_type: ExchangeType,
+    queue: String,
+    binding_key: String,
+    prefetch: u16,
+    retry: RetryPolicy,
+}
+
+impl Debug for RabbitMQSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RabbitMQSubscription")
+            .field("exchange", &self.binding.exchange)
+            .field("queue", &self.binding.queue)
+            .finish()
+    }
+}
+
+impl RabbitMQSubscription {
+    pub(super) fn new(
+        conn: Arc<LazyConnection>,
+        exchange_type: ExchangeType,
+        cluster_prefetch: Option<u32>,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Self {
+        let (binding_key, sub_prefetch) = match &cfg.provider_config {
+            Some(pb::pub_sub_subscription::ProviderConfig::RabbitmqConfig(rabbitmq)) => {
+                (rabbitmq.binding_key.clone(), rabbitmq.prefetch)
+            }
+            _ => (String::new(), None),
+        };
+
+        Self {
+            conn,
+            binding: Arc::new(Binding {
+                exchange: cfg.topic_cloud_name.clone(),
+                exchange_type,
+                queue: cfg.subscription_cloud_name.clone(),
+                binding_key,
+                prefetch: prefetch(sub_prefetch.or(cluster_prefetch), meta.max_concurrency),
+                retry: RetryPolicy::new(meta),
+            }),
+        }
+    }
+}
+
+/// Returns the number of unacknowledged messages to hold at once, which
+/// also bounds how many messages are processed concurrently.
+/// Defaults to the subscription's max concurrency.
+fn prefetch(configured: Option<u32>, max_concurrency: Option<i32>) -> u16 {
+    let prefetch = configured.unwrap_or_else(|| max_concurrency.map_or(100, |v| v.max(1) as u32));
+    prefetch.clamp(1, u16::MAX as u32) as u16
+}
+
+impl Subscription for RabbitMQSubscription {
+    fn subscribe(
+        &self,
+        handler: Arc<SubHandler>,
+    ) -> Pin<Box<dyn Future<Output = APIResult<()>> + Send + 'static>> {
+        let conn = self.conn.clone();
+        let binding = self.binding.clone();
+
+        Box::pin(async move {
+            loop {
+                if let Err(err) = consume(&conn, &binding, &handler).await {
+                    log::warn!(
+                        "RabbitMQ subscription on queue {} failed, reconnecting: {:#}",
+                        binding.queue,
+                        err
+                    );
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        })
+    }
+}
+
+/// Declares the subscription's queue and consumes messages from it
+/// until the channel is closed.
+async fn consume(
+    conn: &LazyConnection,
+    binding: &Binding,
+    handler: &Arc<SubHandler>,
+) -> anyhow::Result<()> {
+    let channel = conn.channel().await?;
+
+    // The broker stops delivering messages once this many are unacknowledged,
+    // which limits how many are processed concurrently.
+    channel
+        .basic_qos(binding.prefetch, BasicQosOptions::default())
+        .await
+        .context("unable to set prefetch")?;
+
+    declare_exchange(&channel, &binding.exchange, binding.exchange_type).await?;
+    let queue_opts = QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+    channel
+        .queue_declare(&binding.queue, queue_opts, FieldTable::default())
+        .await
+        .with_context(|| format!("unable to declare queue {}", binding.queue))?;
+    channel
+        .queue_bind(
+            &binding.queue,
+            &binding.exchange,
+            &binding.binding_key,
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .with_context(|| format!("unable to bind queue {}", binding.queue))?;
+
+    let mut consumer = channel
+        .basic_consume(
+            &binding.queue,
+            "",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .context("unable to start consuming")?;
+
+    while let Some(delivery) = consumer.next().await {
+        let delivery = delivery.context("unable to receive message")?;
+        let handler = handler.clone();
+        let retry = binding.retry;
+        tokio::spawn(async move {
+            let result = if process_message(&delivery, &handler, retry).await {
+                delivery.ack(BasicAckOptions::default()).await
+            } else {
+                // Rejected messages are dropped, or moved to the queue's
+                // dead-letter exchange if one is configured on the broker.
+                let opts = BasicNackOptions {
+                    requeue: false,
+                    ..Default::default()
+                };
+                delivery.nack(opts).await
+            };
+            if let Err(err) = result {
+                // This happens when the channel has been closed,
+                // in which case the message is delivered again.
+                log::debug!("unable to acknowledge RabbitMQ message: {}", err);
+            }
+        });
+    }
+
+    anyhow::bail!("consumer was cancelled")
+}
+
+/// Processes a message, retrying it in place until it succeeds or the
+/// retry policy is exhausted. Reports whether processing succeeded.
+async fn process_message(delivery: &Delivery, handler: &SubHandler, retry: RetryPolicy) -> bool {
+    let mut attempt: u32 = 1;
+    loop {
+        let Err(err) = handler.handle_message(to_message(delivery, attempt)).await else {
+            return true;
+        };
+
+        if !retry.should_retry(attempt) {
+            log::warn!(
+                "message {} failed {} times, dropping it: {:?}",
+                message_id(delivery),
+                attempt,
+                err
+            );
+            return false;
+        }
+
+        let delay = retry.delay(attempt);
+        log::info!(
+            "message handler failed, retrying message in {:?}: {:?}",
+            delay,
+            err
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn message_id(delivery: &Delivery) -> pubsub::MessageId {
+    match delivery.properties.message_id() {
+        Some(id) => id.as_str().to_string(),
+        None => delivery.delivery_tag.to_string(),
+    }
+}
+
+fn to_message(delivery: &Delivery, attempt: u32) -> pubsub::Message {
+    let attrs = delivery
+        .properties
+        .headers()
+        .as_ref()
+        .map(|headers| {
+            headers
+                .inner()
+                .iter()
+                .filter_map(|(key, value)| match value {
+                    AMQPValue::LongString(s) => {
+                        let value = std::str::from_utf8(s.as_bytes()).ok()?;
+                        Some((key.as_str().to_string(), value.to_string()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    pubsub::Message {
+        id: message_id(delivery),
+        publish_time: delivery
+            .properties
+            .timestamp()
+            .as_ref()
+            .and_then(|&secs| chrono::DateTime::from_timestamp(secs as i64, 0)),
+        attempt,
+        data: pubsub::MessageData {
+            attrs,
+            raw_body: delivery.data.clone(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefetch() {
+        assert_eq!(prefetch(None, None), 100);
+        assert_eq!(prefetch(None, Some(8)), 8);
+        assert_eq!(prefetch(Some(20), Some(8)), 20);
+        assert_eq!(prefetch(Some(0), None), 1);
+        assert_eq!(prefetch(None, Some(-1)), 1);
+        assert_eq!(prefetch(Some(1_000_000), None), u16::MAX);
+    }
+}
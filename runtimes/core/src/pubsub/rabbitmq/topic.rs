@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use lapin::options::{BasicPublishOptions, ConfirmSelectOptions};
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{BasicProperties, Channel};
+
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub::rabbitmq::{declare_exchange, topic_config, LazyConnection};
+use crate::pubsub::{MessageData, MessageId, Topic};
+
+use pb::pub_sub_topic::rabbit_mq_config::ExchangeType;
+
+/// The delivery mode of messages that survive a broker restart.
+const PERSISTENT: u8 = 2;
+
+pub struct RabbitMQTopic {
+    conn: Arc<LazyConnection>,
+    exchange: String,
+    exchange_type: ExchangeType,
+    routing_key: String,
+    channel: tokio::sync::Mutex<Option<Channel>>,
+}
+
+impl std::fmt::Debug for RabbitMQTopic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RabbitMQTopic")
+            .field("exchange", &self.exchange)
+            .field("routing_key", &self.routing_key)
+            .finish()
+    }
+}
+
+impl RabbitMQTopic {
+    pub(super) fn new(conn: Arc<LazyConnection>, cfg: &pb::PubSubTopic) -> Self {
+        let (exchange_type, routing_key) = topic_config(cfg);
+        Self {
+            conn,
+            exchange: cfg.cloud_name.clone(),
+            exchange_type,
+            routing_key,
+            channel: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Returns the channel to publish on, opening a new one
+    /// if there is none or the previous one was closed.
+    async fn channel(&self) -> Result<Channel> {
+        let mut channel = self.channel.lock().await;
+        if let Some(ch) = channel.as_ref().filter(|ch| ch.status().connected()) {
+            return Ok(ch.clone());
+        }
+
+        let ch = self.conn.channel().await?;
+        // Have the broker confirm each message, so that publishing
+        // only succeeds once the message has been routed.
+        ch.confirm_select(ConfirmSelectOptions::default())
+            .await
+            .context("unable to enable publisher confirms")?;
+        declare_exchange(&ch, &self.exchange, self.exchange_type).await?;
+
+        *channel = Some(ch.clone());
+        Ok(ch)
+    }
+}
+
+impl Topic for RabbitMQTopic {
+    fn publish(
+        &self,
+        msg: MessageData,
+        _ordering_key: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
+        Box::pin(async move {
+            let channel = self.channel().await?;
+
+            // Message attributes are sent as headers.
+            let mut headers = FieldTable::default();
+            for (key, value) in msg.attrs {
+                headers.insert(key.into(), AMQPValue::LongString(value.into()));
+            }
+
+            let id = xid::new().to_string();
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let props = BasicProperties::default()
+                .with_message_id(id.clone().into())
+                .with_delivery_mode(PERSISTENT)
+                .with_timestamp(now)
+                .with_headers(headers);
+
+            let confirm = channel
+                .basic_publish(
+                    &self.exchange,
+                    &self.routing_key,
+                    BasicPublishOptions::default(),
+                    &msg.raw_body,
+                    props,
+                )
+                .await
+                .context("unable to publish message")?
+                .await
+                .context("unable to publish message")?;
+            if confirm.is_nack() {
+                anyhow::bail!("message was rejected by RabbitMQ");
+            }
+            Ok(id)
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::names::EncoreName;
+use crate::pubsub;
+use crate::replay::{self, Cassette, Mode};
+
+/// The cassette operation for publishing.
+const OP: &str = "pubsub.publish";
+
+/// Records or replays the messages published to a topic.
+///
+/// Interactions are keyed by the topic name and hold the message id,
+/// so replayed publishes return the same ids as when recorded.
+/// Subscriptions are not covered: in replay mode nothing is delivered.
+#[derive(Debug)]
+pub struct Topic {
+    name: EncoreName,
+    inner: Arc<dyn pubsub::Topic>,
+    cassette: &'static Cassette,
+}
+
+/// Wraps the topic to record or replay publishes, if enabled.
+pub fn wrap(name: &EncoreName, inner: Arc<dyn pubsub::Topic>) -> Arc<dyn pubsub::Topic> {
+    match replay::cassette() {
+        Some(cassette) => Arc::new(Topic {
+            name: name.clone(),
+            inner,
+            cassette,
+        }),
+        None => inner,
+    }
+}
+
+impl pubsub::Topic for Topic {
+    fn publish(
+        &self,
+        msg: pubsub::MessageData,
+        ordering_key: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<pubsub::MessageId>> + Send + '_>> {
+        Box::pin(async move {
+            match self.cassette.mode() {
+                Mode::Replay => match self.cassette.replay(OP, &self.name)? {
+                    Ok(serde_json::Value::String(id)) => Ok(id),
+                    Ok(other) => anyhow::bail!("invalid recorded message id: {other}"),
+                    Err(err) => Err(anyhow::anyhow!(err)),
+                },
+                Mode::Record => {
+                    let res = self.inner.publish(msg, ordering_key).await;
+                    let recorded = match &res {
+                        Ok(id) => Ok(serde_json::Value::String(id.clone())),
+                        Err(err) => Err(format!("{err:#}")),
+                    };
+                    self.cassette.record(OP, &self.name, recorded);
+                    res
+                }
+            }
+        })
+    }
+}
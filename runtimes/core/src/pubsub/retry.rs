@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use crate::encore::parser::meta::v1 as meta;
+
+/// The delay before retrying a message, if the retry policy doesn't specify one.
+const DEFAULT_MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between retries, if the retry policy doesn't specify one.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The number of retries, if the subscription has no retry policy.
+const DEFAULT_MAX_RETRIES: i64 = 2;
+
+/// How a subscription retries messages that failed to process,
+/// for providers where retries are handled by the runtime.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct RetryPolicy {
+    pub max_retries: i64,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(meta: &meta::pub_sub_topic::Subscription) -> Self {
+        let mut policy = Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            min_backoff: DEFAULT_MIN_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        };
+        if let Some(retry) = &meta.retry_policy {
+            if retry.min_backoff > 0 {
+                policy.min_backoff = Duration::from_nanos(retry.min_backoff as u64);
+            }
+            if retry.max_backoff > 0 {
+                policy.max_backoff =
+                    Duration::from_nanos(retry.max_backoff as u64).max(policy.min_backoff);
+            }
+            policy.max_retries = retry.max_retries;
+        }
+        policy
+    }
+
+    /// Returns the delay before retrying a message that failed on the given attempt,
+    /// backing off exponentially with each attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = attempt.max(1) - 1;
+        let factor = 1u32.checked_shl(exp).unwrap_or(u32::MAX);
+        self.min_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Reports whether a message that failed on the given attempt is retried.
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        // The number of retries so far is (attempt-1).
+        attempt as i64 <= self.max_retries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(100), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new(&meta::pub_sub_topic::Subscription::default());
+        assert!(policy.should_retry(1));
+        assert!(policy.should_retry(2));
+        assert!(!policy.should_retry(3));
+    }
+}
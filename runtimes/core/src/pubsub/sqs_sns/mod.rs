@@ -1,5 +1,7 @@
 use std::sync::Arc;
 
+use anyhow::Context;
+
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::pubsub;
@@ -23,14 +25,87 @@ pub struct Cluster {
 }
 
 impl Cluster {
-    pub fn new() -> Self {
+    pub fn new(
+        topics: &[pb::PubSubTopic],
+        subscriptions: &[pb::PubSubSubscription],
+    ) -> anyhow::Result<Self> {
+        for topic in topics {
+            validate_topic(topic)
+                .with_context(|| format!("invalid topic {}", topic.encore_name))?;
+        }
+        for sub in subscriptions {
+            validate_subscription(sub).with_context(|| {
+                format!(
+                    "invalid subscription {} for topic {}",
+                    sub.subscription_encore_name, sub.topic_encore_name
+                )
+            })?;
+        }
+
         let publisher_id = xid::new();
         let client = Arc::new(LazyClient::new());
-        Self {
+        Ok(Self {
             _publisher_id: publisher_id,
             client,
+        })
+    }
+}
+
+/// The suffix AWS requires for the names of FIFO topics and queues.
+const FIFO_SUFFIX: &str = ".fifo";
+
+/// Reports whether the topic ARN or queue URL refers to a FIFO topic or queue.
+fn is_fifo(cloud_name: &str) -> bool {
+    cloud_name.ends_with(FIFO_SUFFIX)
+}
+
+fn validate_topic(topic: &pb::PubSubTopic) -> anyhow::Result<()> {
+    if is_fifo(&topic.cloud_name) {
+        return Ok(());
+    }
+
+    // Everything that relies on message groups or deduplication requires a FIFO topic.
+    if let Some(attr) = &topic.ordering_attr {
+        anyhow::bail!(
+            "ordering attribute {attr:?} requires a FIFO topic, but {} does not end in {FIFO_SUFFIX:?}",
+            topic.cloud_name
+        );
+    }
+    if topic.delivery_guarantee() == pb::pub_sub_topic::DeliveryGuarantee::ExactlyOnce {
+        anyhow::bail!(
+            "exactly-once delivery requires a FIFO topic, but {} does not end in {FIFO_SUFFIX:?}",
+            topic.cloud_name
+        );
+    }
+    if let Some(pb::pub_sub_topic::ProviderConfig::AwsConfig(aws)) = &topic.provider_config {
+        if aws.content_based_deduplication {
+            anyhow::bail!(
+                "content-based deduplication requires a FIFO topic, but {} does not end in {FIFO_SUFFIX:?}",
+                topic.cloud_name
+            );
         }
     }
+    Ok(())
+}
+
+fn validate_subscription(sub: &pb::PubSubSubscription) -> anyhow::Result<()> {
+    // SNS FIFO topics can only deliver to FIFO queues, and standard topics to standard queues.
+    match (
+        is_fifo(&sub.topic_cloud_name),
+        is_fifo(&sub.subscription_cloud_name),
+    ) {
+        (true, false) => anyhow::bail!(
+            "FIFO topic {} requires a FIFO queue, but {} does not end in {FIFO_SUFFIX:?}",
+            sub.topic_cloud_name,
+            sub.subscription_cloud_name
+        ),
+        (false, true) => anyhow::bail!(
+            "FIFO queue {} cannot subscribe to standard topic {}",
+            sub.subscription_cloud_name,
+            sub.topic_cloud_name
+        ),
+        _ => Ok(()),
+    }
 }
 
 impl pubsub::Cluster for Cluster {
@@ -91,3 +166,90 @@ impl LazyClient {
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(cloud_name: &str) -> pb::PubSubTopic {
+        pb::PubSubTopic {
+            rid: String::new(),
+            encore_name: "orders".into(),
+            cloud_name: cloud_name.into(),
+            delivery_guarantee: pb::pub_sub_topic::DeliveryGuarantee::AtLeastOnce as i32,
+            ordering_attr: None,
+            schema_validation: pb::pub_sub_topic::SchemaValidation::Unspecified as i32,
+            max_message_bytes: None,
+            compression: pb::pub_sub_topic::Compression::Unspecified as i32,
+            provider_config: None,
+        }
+    }
+
+    fn sub(topic_cloud_name: &str, queue_url: &str) -> pb::PubSubSubscription {
+        pb::PubSubSubscription {
+            topic_cloud_name: topic_cloud_name.into(),
+            subscription_cloud_name: queue_url.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_fifo() {
+        let std_arn = "arn:aws:sns:us-east-1:123:orders";
+        let fifo_arn = "arn:aws:sns:us-east-1:123:orders.fifo";
+        let std_url = "https://sqs.us-east-1.amazonaws.com/123/orders";
+        let fifo_url = "https://sqs.us-east-1.amazonaws.com/123/orders.fifo";
+
+        assert!(validate_topic(&topic(std_arn)).is_ok());
+
+        let mut ordered = topic(std_arn);
+        ordered.ordering_attr = Some("customer_id".into());
+        assert!(validate_topic(&ordered).is_err());
+        ordered.cloud_name = fifo_arn.into();
+        assert!(validate_topic(&ordered).is_ok());
+
+        let mut dedup = topic(std_arn);
+        dedup.provider_config = Some(pb::pub_sub_topic::ProviderConfig::AwsConfig(
+            pb::pub_sub_topic::AwsConfig {
+                content_based_deduplication: true,
+            },
+        ));
+        assert!(validate_topic(&dedup).is_err());
+        dedup.cloud_name = fifo_arn.into();
+        assert!(validate_topic(&dedup).is_ok());
+
+        assert!(validate_subscription(&sub(std_arn, std_url)).is_ok());
+        assert!(validate_subscription(&sub(fifo_arn, fifo_url)).is_ok());
+        assert!(validate_subscription(&sub(fifo_arn, std_url)).is_err());
+        assert!(validate_subscription(&sub(std_arn, fifo_url)).is_err());
+    }
+
+    #[test]
+    fn test_fifo_ids() {
+        let client = Arc::new(LazyClient::new());
+        let publisher_id = xid::new();
+
+        let std_topic = Topic::new(client.clone(), &topic("orders"), publisher_id);
+        assert_eq!(std_topic.fifo_ids(Some("c1".into())), (None, None));
+
+        let fifo = topic("orders.fifo");
+        let fifo_topic = Topic::new(client.clone(), &fifo, publisher_id);
+        let (group, dedup) = fifo_topic.fifo_ids(Some("c1".into()));
+        assert_eq!(group.as_deref(), Some("c1"));
+        assert!(dedup.is_some_and(|id| id.starts_with("msg_")));
+        let (group, _) = fifo_topic.fifo_ids(None);
+        assert_eq!(group, Some(format!("inst_{publisher_id}")));
+
+        let mut dedup_cfg = fifo;
+        dedup_cfg.provider_config = Some(pb::pub_sub_topic::ProviderConfig::AwsConfig(
+            pb::pub_sub_topic::AwsConfig {
+                content_based_deduplication: true,
+            },
+        ));
+        let dedup_topic = Topic::new(client, &dedup_cfg, publisher_id);
+        assert_eq!(
+            dedup_topic.fifo_ids(Some("c1".into())),
+            (Some("c1".into()), None)
+        );
+    }
+}
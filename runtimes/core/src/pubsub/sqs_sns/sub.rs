@@ -25,6 +25,7 @@ pub struct Subscription {
     ack_deadline: Duration,
     fetcher_cfg: fetcher::Config,
     requeue_policy: ExponentialBackoff,
+    dead_letter: Option<pb::pub_sub_subscription::DeadLetter>,
 }
 
 impl Subscription {
@@ -63,6 +64,7 @@ impl Subscription {
             ack_deadline,
             fetcher_cfg,
             requeue_policy,
+            dead_letter: cfg.dead_letter.clone(),
         }
     }
 }
@@ -77,6 +79,7 @@ impl pubsub::Subscription for Subscription {
         let ack_deadline = self.ack_deadline;
         let requeue_policy = self.requeue_policy.clone();
         let fetcher_cfg = self.fetcher_cfg.clone();
+        let dead_letter = self.dead_letter.clone();
 
         Box::pin(async move {
             let client = client.get_sqs().await.clone();
@@ -87,6 +90,7 @@ impl pubsub::Subscription for Subscription {
                 queue_url: cloud_name.into(),
                 ack_deadline,
                 requeue_policy,
+                dead_letter,
             });
             fetcher::process_concurrently(fetcher_cfg.clone(), sqs_fetcher).await;
 
@@ -100,6 +104,7 @@ struct SqsFetcher {
     queue_url: String,
     ack_deadline: Duration,
     requeue_policy: ExponentialBackoff,
+    dead_letter: Option<pb::pub_sub_subscription::DeadLetter>,
     handler: Arc<SubHandler>,
 }
 
@@ -139,6 +144,8 @@ impl fetcher::Fetcher for Arc<SqsFetcher> {
         Box::pin(async move {
             let receipt_handle = item.receipt_handle.clone().expect("missing receipt handle");
             let attempt = parse_attempt(&item);
+            // Keep the SNS envelope in case the message needs to be dead-lettered.
+            let body = item.body.clone().unwrap_or_default();
 
             let result = match parse_message(item, attempt) {
                 Ok(msg) => self
@@ -172,34 +179,96 @@ impl fetcher::Fetcher for Arc<SqsFetcher> {
                         );
                     }
                 }
+                Err(_) if self.should_dead_letter(attempt) => {
+                    self.move_to_dead_letter(receipt_handle, body, attempt)
+                        .await;
+                }
                 Err(_) => {
-                    // Determine the requeue delay.
-                    let requeue_delay = self
-                        .requeue_policy
-                        .clone()
-                        .nth((attempt.max(1) - 1) as usize)
-                        .unwrap_or(Duration::from_secs(1));
-
-                    let requeue_action = RequeueMessageAction {
-                        fetcher: self.clone(),
-                        receipt_handle,
-                        visibility_timeout: requeue_delay,
-                    };
-
-                    // Retry requeuing a few times.
-                    let retry = ExponentialBackoff::from_millis(100).factor(2).take(5);
-                    if let Err(err) = Retry::spawn(retry, requeue_action).await {
-                        log::error!(
-                            "encore: internal error: failed to requeue aws pub/sub message: {}",
-                            err
-                        );
-                    }
+                    self.requeue(receipt_handle, attempt).await;
                 }
             }
         })
     }
 }
 
+impl SqsFetcher {
+    fn should_dead_letter(&self, attempt: u32) -> bool {
+        self.dead_letter
+            .as_ref()
+            .is_some_and(|dl| attempt >= dl.max_delivery_attempts)
+    }
+
+    /// Sends the message to the dead-letter queue and deletes it,
+    /// or requeues it if sending fails.
+    async fn move_to_dead_letter(
+        self: &Arc<Self>,
+        receipt_handle: String,
+        body: String,
+        attempt: u32,
+    ) {
+        let Some(dead_letter) = &self.dead_letter else {
+            return;
+        };
+
+        let sent = self
+            .client
+            .send_message()
+            .queue_url(&dead_letter.target)
+            .message_body(body)
+            .send()
+            .await;
+        if let Err(err) = sent {
+            log::error!(
+                "encore: unable to send message to the dead-letter queue, requeueing it: {}",
+                aws_sdk_sqs::error::DisplayErrorContext(&err)
+            );
+            self.requeue(receipt_handle, attempt).await;
+            return;
+        }
+        log::warn!(
+            "message failed {} times, moved it to the dead-letter queue",
+            attempt
+        );
+
+        let delete_action = DeleteMessageAction {
+            fetcher: self.clone(),
+            receipt_handle,
+        };
+        // If we can't delete the message, it'll be redelivered and dead-lettered again.
+        let retry = ExponentialBackoff::from_millis(100).factor(2).take(5);
+        if let Err(err) = Retry::spawn(retry, delete_action).await {
+            log::error!(
+                "encore: internal error: failed to delete aws pub/sub message: {}",
+                err
+            );
+        }
+    }
+
+    async fn requeue(self: &Arc<Self>, receipt_handle: String, attempt: u32) {
+        // Determine the requeue delay.
+        let requeue_delay = self
+            .requeue_policy
+            .clone()
+            .nth((attempt.max(1) - 1) as usize)
+            .unwrap_or(Duration::from_secs(1));
+
+        let requeue_action = RequeueMessageAction {
+            fetcher: self.clone(),
+            receipt_handle,
+            visibility_timeout: requeue_delay,
+        };
+
+        // Retry requeuing a few times.
+        let retry = ExponentialBackoff::from_millis(100).factor(2).take(5);
+        if let Err(err) = Retry::spawn(retry, requeue_action).await {
+            log::error!(
+                "encore: internal error: failed to requeue aws pub/sub message: {}",
+                err
+            );
+        }
+    }
+}
+
 struct RequeueMessageAction {
     fetcher: Arc<SqsFetcher>,
     receipt_handle: String,
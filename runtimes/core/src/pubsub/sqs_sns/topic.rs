@@ -6,7 +6,6 @@ use std::sync::Arc;
 use anyhow::{Context, Result};
 
 use crate::encore::runtime::v1 as pb;
-use crate::encore::runtime::v1::pub_sub_topic::DeliveryGuarantee;
 use crate::names::CloudName;
 use crate::pubsub::sqs_sns::LazyClient;
 use crate::pubsub::{self, MessageData, MessageId};
@@ -15,7 +14,8 @@ use crate::pubsub::{self, MessageData, MessageId};
 pub struct Topic {
     client: Arc<LazyClient>,
     cloud_name: CloudName,
-    delivery_guarantee: DeliveryGuarantee,
+    fifo: bool,
+    content_based_deduplication: bool,
     publisher_id: xid::Id,
 }
 
@@ -25,13 +25,48 @@ impl Topic {
         cfg: &pb::PubSubTopic,
         publisher_id: xid::Id,
     ) -> Self {
+        let content_based_deduplication = match &cfg.provider_config {
+            Some(pb::pub_sub_topic::ProviderConfig::AwsConfig(aws)) => {
+                aws.content_based_deduplication
+            }
+            _ => false,
+        };
         Self {
             client,
             cloud_name: cfg.cloud_name.clone().into(),
-            delivery_guarantee: cfg.delivery_guarantee(),
+            fifo: super::is_fifo(&cfg.cloud_name),
+            content_based_deduplication,
             publisher_id,
         }
     }
+
+    /// The message group id and deduplication id to publish a message with.
+    /// Both are None for standard topics, which don't support them.
+    pub(super) fn fifo_ids(
+        &self,
+        ordering_key: Option<String>,
+    ) -> (Option<String>, Option<String>) {
+        if !self.fifo {
+            return (None, None);
+        }
+
+        // Messages are ordered by the ordering attribute if there is one.
+        // Otherwise each instance publishes to its own message group,
+        // following AWS's recommendation for producers.
+        let group_id = ordering_key
+            .filter(|key| !key.is_empty())
+            .unwrap_or_else(|| format!("inst_{}", self.publisher_id));
+
+        // With content-based deduplication SNS derives the deduplication id
+        // from the message body, and an explicit id would take precedence.
+        let dedup_id = if self.content_based_deduplication {
+            None
+        } else {
+            Some(format!("msg_{}", xid::new()))
+        };
+
+        (Some(group_id), dedup_id)
+    }
 }
 
 impl pubsub::Topic for Topic {
@@ -64,13 +99,10 @@ impl pubsub::Topic for Topic {
                 .topic_arn(self.cloud_name.to_string())
                 .message(data);
 
-            if let Some(ordering_key) = ordering_key {
-                params = params.message_group_id(ordering_key);
-                params = params.message_deduplication_id(format!("msg_{}", xid::new()));
-            } else if self.delivery_guarantee == DeliveryGuarantee::ExactlyOnce {
-                params = params.message_group_id(format!("inst_{}", self.publisher_id));
-                params = params.message_deduplication_id(format!("msg_{}", xid::new()));
-            }
+            let (group_id, dedup_id) = self.fifo_ids(ordering_key);
+            params = params
+                .set_message_group_id(group_id)
+                .set_message_deduplication_id(dedup_id);
 
             let result = params.set_message_attributes(Some(attrs)).send().await;
 
@@ -0,0 +1,396 @@
+//! Reloads the infra config while the runtime is running, applying the changes
+//! that are safe to apply without a restart: secrets, statically configured
+//! service discovery base URLs, and the app log level.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::api::call::ServiceRegistry;
+use crate::encore::runtime::v1 as pb;
+use crate::{proccfg, secrets};
+
+/// How often the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Watches the infra config file, reloading it when it changes or when the
+/// process receives SIGHUP.
+pub(crate) struct Watcher {
+    path: PathBuf,
+    current: pb::RuntimeConfig,
+    proc_cfg: Option<proccfg::ProcessConfig>,
+    gateway_mode: bool,
+}
+
+impl Watcher {
+    /// Creates a watcher for the config at the given path.
+    /// The current config is what the runtime was started with,
+    /// after the process config and gateway mode were applied.
+    pub fn new(
+        path: PathBuf,
+        current: pb::RuntimeConfig,
+        proc_cfg: Option<proccfg::ProcessConfig>,
+        gateway_mode: bool,
+    ) -> Self {
+        Self {
+            path,
+            current,
+            proc_cfg,
+            gateway_mode,
+        }
+    }
+
+    /// Starts watching for changes, applying them to the given runtime.
+    pub fn start(self, rt: &crate::Runtime) {
+        let targets = Targets {
+            secrets: rt.secrets().reloader(),
+            registry: rt.api().service_registry().clone(),
+        };
+        rt.tokio_handle().spawn(self.watch(targets));
+    }
+
+    async fn watch(mut self, targets: Targets) {
+        log::info!("watching infra config {} for changes", self.path.display());
+
+        #[cfg(unix)]
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        {
+            Ok(sig) => Some(sig),
+            Err(err) => {
+                log::warn!("unable to listen for SIGHUP, only watching for file changes: {err}");
+                None
+            }
+        };
+
+        let mut last_modified = self.modified();
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            #[cfg(unix)]
+            let hangup_recv = async {
+                match hangup.as_mut() {
+                    Some(sig) => sig.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+            #[cfg(not(unix))]
+            let hangup_recv = std::future::pending::<Option<()>>();
+
+            tokio::select! {
+                _ = hangup_recv => {
+                    log::info!("received SIGHUP, reloading infra config");
+                }
+                _ = interval.tick() => {
+                    let modified = self.modified();
+                    if modified == last_modified {
+                        continue;
+                    }
+                    last_modified = modified;
+                    log::info!("infra config changed, reloading");
+                }
+            }
+
+            self.reload(&targets).await;
+        }
+    }
+
+    /// The modification time and size of the config file, to detect changes.
+    fn modified(&self) -> Option<(SystemTime, u64)> {
+        let meta = std::fs::metadata(&self.path).ok()?;
+        Some((meta.modified().ok()?, meta.len()))
+    }
+
+    /// Reads the config and applies the changes. If the config is invalid
+    /// nothing is applied, and the runtime keeps using the current config.
+    async fn reload(&mut self, targets: &Targets) {
+        let next = match self.load() {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                log::error!(
+                    "invalid infra config, keeping the current config: {:#}",
+                    err
+                );
+                return;
+            }
+        };
+
+        let changes = Changes::compute(&self.current, &next);
+        if changes.is_empty() {
+            log::info!("infra config reloaded, nothing changed");
+            self.current = next;
+            return;
+        }
+
+        if let Some(app_secrets) = &changes.app_secrets {
+            let result = targets.secrets.reload(app_secrets).await;
+            for name in &result.updated {
+                log::info!("secret {name}: updated");
+            }
+            for (name, err) in &result.failed {
+                log::error!(
+                    "secret {name}: unable to resolve new value, keeping previous value: {err}"
+                );
+            }
+            for name in &result.restart_required {
+                log::warn!("secret {name}: added or removed, restart to apply");
+            }
+        }
+
+        for (svc, base_urls) in changes.base_urls {
+            if targets.registry.set_service_base_urls(&svc, base_urls) {
+                log::info!(service = svc.as_str(); "service discovery: updated base urls");
+            }
+        }
+
+        if let Some(log_config) = &changes.log_level {
+            match log_config.parse::<::log::LevelFilter>() {
+                Ok(level) => {
+                    crate::log::root().set_level(level);
+                    log::info!("log level set to {level}");
+                }
+                Err(_) => {
+                    log::error!("invalid log level {log_config:?}, keeping the current level")
+                }
+            }
+        }
+
+        for section in &changes.restart_required {
+            log::warn!("infra config: changes to {section} require a restart to apply");
+        }
+
+        self.current = next;
+    }
+
+    fn load(&self) -> anyhow::Result<pb::RuntimeConfig> {
+        let mut cfg = crate::load_infra_config(&self.path)?;
+        crate::prepare_config(&mut cfg, self.proc_cfg.as_ref(), self.gateway_mode)?;
+
+        // Validate the service discovery config the same way it's validated on startup.
+        let sd = cfg
+            .deployment
+            .as_ref()
+            .and_then(|d| d.service_discovery.as_ref());
+        for (svc, loc) in sd.iter().flat_map(|sd| sd.services.iter()) {
+            if loc.dns.is_some() && loc.kubernetes.is_some() {
+                anyhow::bail!("service {svc} cannot use both dns and kubernetes service discovery");
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+/// What reloaded changes are applied to.
+struct Targets {
+    secrets: secrets::Reloader,
+    registry: Arc<ServiceRegistry>,
+}
+
+/// The differences between two configs.
+#[derive(Debug, Default, PartialEq)]
+struct Changes {
+    /// The new app secrets, if they changed.
+    app_secrets: Option<Vec<pb::AppSecret>>,
+    /// The new base URLs of services with statically configured locations.
+    base_urls: Vec<(String, Vec<String>)>,
+    /// The new log level, if it changed.
+    log_level: Option<String>,
+    /// The parts of the config that changed in ways that require a restart.
+    restart_required: Vec<&'static str>,
+}
+
+impl Changes {
+    fn compute(old: &pb::RuntimeConfig, new: &pb::RuntimeConfig) -> Self {
+        let mut changes = Changes::default();
+
+        let (old_secrets, new_secrets) = (app_secrets(old), app_secrets(new));
+        if old_secrets != new_secrets {
+            changes.app_secrets = Some(new_secrets.to_vec());
+        }
+
+        let (old_sd, new_sd) = (service_discovery(old), service_discovery(new));
+        for (svc, loc) in new_sd.iter().flat_map(|sd| sd.services.iter()) {
+            let Some(prev) = old_sd.and_then(|sd| sd.services.get(svc)) else {
+                continue;
+            };
+            if is_static(loc)
+                && is_static(prev)
+                && (loc.base_url != prev.base_url
+                    || loc.additional_base_urls != prev.additional_base_urls)
+            {
+                let mut base_urls = vec![loc.base_url.clone()];
+                base_urls.extend(loc.additional_base_urls.iter().cloned());
+                changes.base_urls.push((svc.clone(), base_urls));
+            }
+        }
+        changes.base_urls.sort();
+
+        let new_log_level = log_config(new);
+        if new_log_level != log_config(old) {
+            changes.log_level = new_log_level.map(str::to_string);
+        }
+
+        // Compare the rest of the config, without the parts applied above.
+        let (old, new) = (strip_reloadable(old), strip_reloadable(new));
+        let (old_deploy, new_deploy) = (
+            old.deployment.clone().unwrap_or_default(),
+            new.deployment.clone().unwrap_or_default(),
+        );
+        let sections = [
+            ("environment", old.environment != new.environment),
+            ("infra", old.infra != new.infra),
+            (
+                "encore_platform",
+                old.encore_platform != new.encore_platform,
+            ),
+            (
+                "service discovery",
+                old_deploy.service_discovery != new_deploy.service_discovery,
+            ),
+            (
+                "hosted services",
+                old_deploy.hosted_services != new_deploy.hosted_services,
+            ),
+            (
+                "observability",
+                old_deploy.observability != new_deploy.observability,
+            ),
+        ];
+        changes.restart_required = sections
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect();
+
+        let strip_deploy = |mut d: pb::Deployment| {
+            d.service_discovery = None;
+            d.hosted_services.clear();
+            d.observability = None;
+            d
+        };
+        if strip_deploy(old_deploy) != strip_deploy(new_deploy) {
+            changes.restart_required.push("deployment");
+        }
+
+        changes
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn app_secrets(cfg: &pb::RuntimeConfig) -> &[pb::AppSecret] {
+    cfg.infra
+        .as_ref()
+        .and_then(|i| i.resources.as_ref())
+        .map(|r| r.app_secrets.as_slice())
+        .unwrap_or_default()
+}
+
+fn service_discovery(cfg: &pb::RuntimeConfig) -> Option<&pb::ServiceDiscovery> {
+    cfg.deployment
+        .as_ref()
+        .and_then(|d| d.service_discovery.as_ref())
+}
+
+/// The log level of the hosted services. As on startup, the last one wins.
+fn log_config(cfg: &pb::RuntimeConfig) -> Option<&str> {
+    cfg.deployment
+        .iter()
+        .flat_map(|d| d.hosted_services.iter())
+        .filter_map(|svc| svc.log_config.as_deref())
+        .last()
+}
+
+/// Reports whether the base URLs of a service are configured statically,
+/// rather than being resolved at runtime.
+fn is_static(loc: &pb::service_discovery::Location) -> bool {
+    loc.dns.is_none() && loc.kubernetes.is_none()
+}
+
+/// Returns a copy of the config without the parts that can be applied without a restart.
+fn strip_reloadable(cfg: &pb::RuntimeConfig) -> pb::RuntimeConfig {
+    let mut cfg = cfg.clone();
+    if let Some(res) = cfg.infra.as_mut().and_then(|i| i.resources.as_mut()) {
+        res.app_secrets.clear();
+    }
+    if let Some(deploy) = cfg.deployment.as_mut() {
+        for svc in deploy.hosted_services.iter_mut() {
+            svc.log_config = None;
+        }
+        if let Some(sd) = deploy.service_discovery.as_mut() {
+            for loc in sd.services.values_mut().filter(|loc| is_static(loc)) {
+                loc.base_url.clear();
+                loc.additional_base_urls.clear();
+            }
+        }
+    }
+    cfg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(secret: &str, base_url: &str, log_config: &str, region: &str) -> pb::RuntimeConfig {
+        use pb::secret_data::{Encoding, Source};
+        pb::RuntimeConfig {
+            infra: Some(pb::Infrastructure {
+                resources: Some(pb::infrastructure::Resources {
+                    app_secrets: vec![pb::AppSecret {
+                        encore_name: "key".to_string(),
+                        data: Some(pb::SecretData {
+                            source: Some(Source::Embedded(secret.as_bytes().to_vec())),
+                            sub_path: None,
+                            encoding: Encoding::None as i32,
+                        }),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            deployment: Some(pb::Deployment {
+                hosted_services: vec![pb::HostedService {
+                    name: "svc".to_string(),
+                    log_config: Some(log_config.to_string()),
+                    ..Default::default()
+                }],
+                service_discovery: Some(pb::ServiceDiscovery {
+                    services: [(
+                        "other".to_string(),
+                        pb::service_discovery::Location {
+                            base_url: base_url.to_string(),
+                            ..Default::default()
+                        },
+                    )]
+                    .into(),
+                }),
+                region: Some(region.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = config("a", "http://one", "info", "eu");
+        assert!(Changes::compute(&old, &old).is_empty());
+
+        let new = config("b", "http://two", "debug", "eu");
+        let changes = Changes::compute(&old, &new);
+        assert_eq!(changes.app_secrets.as_deref(), Some(app_secrets(&new)));
+        assert_eq!(
+            changes.base_urls,
+            [("other".to_string(), vec!["http://two".to_string()])]
+        );
+        assert_eq!(changes.log_level.as_deref(), Some("debug"));
+        assert!(changes.restart_required.is_empty());
+
+        let new = config("a", "http://one", "info", "us");
+        let changes = Changes::compute(&old, &new);
+        assert_eq!(changes.restart_required, ["deployment"]);
+        assert_eq!(changes.app_secrets, None);
+    }
+}
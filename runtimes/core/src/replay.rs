@@ -0,0 +1,200 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::encore::runtime::v1 as pb;
+
+static CASSETTE: OnceLock<Cassette> = OnceLock::new();
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Operations run against the real infrastructure,
+    /// and their results are recorded.
+    Record,
+    /// Operations return the recorded results,
+    /// without touching the infrastructure.
+    Replay,
+}
+
+/// A recorded operation and its result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    /// The kind of operation, like "sql.query".
+    op: String,
+    /// Identifies the operation's target, like the query or object name.
+    key: String,
+    result: Outcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Ok(serde_json::Value),
+    Err(String),
+}
+
+/// Records the results of infrastructure operations to a file,
+/// or replays them from it, so tests can run without live infrastructure.
+///
+/// The file holds one JSON interaction per line, appended as they're recorded.
+/// Interactions are matched by their operation and key. Those with the
+/// same operation and key are replayed in the order they were recorded.
+#[derive(Debug)]
+pub struct Cassette {
+    mode: Mode,
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The file interactions are appended to, in record mode.
+    out: Option<std::fs::File>,
+    /// The interactions left to replay, in replay mode.
+    pending: HashMap<(String, String), VecDeque<Outcome>>,
+}
+
+impl Cassette {
+    pub fn new(mode: Mode, path: PathBuf) -> anyhow::Result<Self> {
+        let mut state = State::default();
+        match mode {
+            Mode::Record => {
+                let out = std::fs::File::create(&path)
+                    .with_context(|| format!("unable to create cassette {}", path.display()))?;
+                state.out = Some(out);
+            }
+            Mode::Replay => {
+                let data = std::fs::read_to_string(&path)
+                    .with_context(|| format!("unable to read cassette {}", path.display()))?;
+                for (idx, line) in data.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let i: Interaction = serde_json::from_str(line).with_context(|| {
+                        format!("invalid cassette {} on line {}", path.display(), idx + 1)
+                    })?;
+                    state
+                        .pending
+                        .entry((i.op, i.key))
+                        .or_default()
+                        .push_back(i.result);
+                }
+            }
+        }
+        Ok(Self {
+            mode,
+            path,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Records the result of an operation, appending it to the cassette.
+    pub fn record(&self, op: &str, key: &str, result: Result<serde_json::Value, String>) {
+        let interaction = Interaction {
+            op: op.to_string(),
+            key: key.to_string(),
+            result: match result {
+                Ok(value) => Outcome::Ok(value),
+                Err(err) => Outcome::Err(err),
+            },
+        };
+        let mut line = match serde_json::to_vec(&interaction) {
+            Ok(line) => line,
+            Err(err) => {
+                log::error!("unable to encode {op} interaction for {key}: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        // Each interaction is written as it's recorded, as tests may not shut down
+        // gracefully. Lines are written whole so concurrent records don't interleave.
+        let mut state = self.state.lock().unwrap();
+        let Some(out) = state.out.as_mut() else {
+            return;
+        };
+        if let Err(err) = out.write_all(&line) {
+            log::error!("unable to save cassette {}: {err:#}", self.path.display());
+        }
+    }
+
+    /// Returns the next recorded result of an operation.
+    /// The outer error reports that there's no such recording.
+    pub fn replay(&self, op: &str, key: &str) -> anyhow::Result<Result<serde_json::Value, String>> {
+        let mut state = self.state.lock().unwrap();
+        let outcome = state
+            .pending
+            .get_mut(&(op.to_string(), key.to_string()))
+            .and_then(|q| q.pop_front())
+            .with_context(|| format!("no recorded {op} interaction for {key}"))?;
+        Ok(match outcome {
+            Outcome::Ok(value) => Ok(value),
+            Outcome::Err(err) => Err(err),
+        })
+    }
+}
+
+/// Enables recording or replaying infrastructure operations, if configured.
+pub fn init(cfg: Option<&pb::RecordReplay>) -> anyhow::Result<()> {
+    let Some(cfg) = cfg else {
+        return Ok(());
+    };
+    let mode = match cfg.mode() {
+        pb::record_replay::Mode::Unspecified => return Ok(()),
+        pb::record_replay::Mode::Record => Mode::Record,
+        pb::record_replay::Mode::Replay => Mode::Replay,
+    };
+    let cassette = Cassette::new(mode, PathBuf::from(&cfg.path))?;
+    log::info!(
+        "{mode:?} mode enabled for infrastructure operations, using {}",
+        cfg.path
+    );
+    let _ = CASSETTE.set(cassette);
+    Ok(())
+}
+
+/// Returns the cassette to record to or replay from, if enabled.
+pub fn cassette() -> Option<&'static Cassette> {
+    CASSETTE.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir().join(format!("cassette-{}.json", xid::new()));
+
+        let rec = Cassette::new(Mode::Record, path.clone()).unwrap();
+        rec.record("sql.query", "db: SELECT 1", Ok(serde_json::json!([1])));
+        rec.record("sql.query", "db: SELECT 1", Ok(serde_json::json!([2])));
+        rec.record("pubsub.publish", "orders", Err("unavailable".into()));
+
+        let rep = Cassette::new(Mode::Replay, path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Same-key interactions replay in order.
+        assert_eq!(
+            rep.replay("sql.query", "db: SELECT 1").unwrap(),
+            Ok(serde_json::json!([1]))
+        );
+        assert_eq!(
+            rep.replay("sql.query", "db: SELECT 1").unwrap(),
+            Ok(serde_json::json!([2]))
+        );
+        assert!(rep.replay("sql.query", "db: SELECT 1").is_err());
+        assert_eq!(
+            rep.replay("pubsub.publish", "orders").unwrap(),
+            Err("unavailable".into())
+        );
+    }
+}
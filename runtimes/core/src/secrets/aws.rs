@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use anyhow::Context;
+use aws_config::{BehaviorVersion, Region};
+use tokio::sync::OnceCell;
+
+/// A reference to a secret stored in AWS.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AwsRef {
+    /// The name or ARN of a secret in Secrets Manager.
+    SecretsManager(String),
+    /// The name or ARN of a parameter in SSM Parameter Store.
+    SsmParameter(String),
+}
+
+impl AwsRef {
+    /// The region in the ARN, if the reference is an ARN.
+    fn region(&self) -> Option<&str> {
+        let (Self::SecretsManager(id) | Self::SsmParameter(id)) = self;
+        // arn:partition:service:region:account:resource
+        id.strip_prefix("arn:")?
+            .split(':')
+            .nth(2)
+            .filter(|region| !region.is_empty())
+    }
+}
+
+/// Reads secrets from AWS Secrets Manager and SSM Parameter Store.
+///
+/// Requests are authenticated using the default credential chain, such as
+/// the ECS task role or EKS pod identity. Secret values are read once and cached.
+pub struct AwsSecrets {
+    handle: tokio::runtime::Handle,
+    config: OnceCell<aws_config::SdkConfig>,
+    data: RwLock<HashMap<AwsRef, Vec<u8>>>,
+}
+
+impl AwsSecrets {
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self {
+            handle,
+            config: OnceCell::new(),
+            data: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the secret value, reading it if it hasn't been read yet.
+    pub fn get(&self, secret: &AwsRef) -> anyhow::Result<Vec<u8>> {
+        if let Some(value) = self.data.read().unwrap().get(secret) {
+            return Ok(value.clone());
+        }
+
+        // Read it on a separate thread, as we may be called from within the runtime.
+        let value = std::thread::scope(|s| {
+            s.spawn(|| self.handle.block_on(self.read(secret)))
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("reading the secret panicked")))
+        })?;
+        self.data
+            .write()
+            .unwrap()
+            .insert(secret.clone(), value.clone());
+        Ok(value)
+    }
+
+    /// Reads the given secrets concurrently and caches them.
+    pub async fn load(&self, secrets: &[AwsRef]) -> anyhow::Result<()> {
+        let results = futures::future::join_all(secrets.iter().map(|s| self.read(s))).await;
+
+        let mut first_err = None;
+        for (secret, result) in secrets.iter().zip(results) {
+            match result {
+                Ok(value) => {
+                    self.data.write().unwrap().insert(secret.clone(), value);
+                }
+                Err(err) => {
+                    log::error!("aws: unable to read secret {secret:?}: {err:#}");
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    async fn read(&self, secret: &AwsRef) -> anyhow::Result<Vec<u8>> {
+        let config = self.config(secret.region()).await;
+        match secret {
+            AwsRef::SecretsManager(id) => {
+                let out = aws_sdk_secretsmanager::Client::new(&config)
+                    .get_secret_value()
+                    .secret_id(id)
+                    .send()
+                    .await
+                    .context("unable to get secret value")?;
+                if let Some(value) = out.secret_string {
+                    Ok(value.into_bytes())
+                } else if let Some(value) = out.secret_binary {
+                    Ok(value.into_inner())
+                } else {
+                    anyhow::bail!("secret has no value")
+                }
+            }
+            AwsRef::SsmParameter(name) => {
+                let out = aws_sdk_ssm::Client::new(&config)
+                    .get_parameter()
+                    .name(name)
+                    .with_decryption(true)
+                    .send()
+                    .await
+                    .context("unable to get parameter")?;
+                out.parameter
+                    .and_then(|p| p.value)
+                    .map(String::into_bytes)
+                    .context("parameter has no value")
+            }
+        }
+    }
+
+    async fn config(&self, region: Option<&str>) -> aws_config::SdkConfig {
+        let config = self
+            .config
+            .get_or_init(|| aws_config::load_defaults(BehaviorVersion::v2025_08_07()))
+            .await;
+        match region {
+            Some(region) => config
+                .to_builder()
+                .region(Region::new(region.to_string()))
+                .build(),
+            None => config.clone(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) fn insert(&self, secret: AwsRef, value: &[u8]) {
+        self.data.write().unwrap().insert(secret, value.to_vec());
+    }
+}
+
+impl std::fmt::Debug for AwsSecrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsSecrets").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region() {
+        let arn = "arn:aws:secretsmanager:eu-west-1:123456789012:secret:db-password-AbCdEf";
+        assert_eq!(
+            AwsRef::SecretsManager(arn.to_string()).region(),
+            Some("eu-west-1")
+        );
+        let arn = "arn:aws:ssm:us-east-2:123456789012:parameter/app/api-key";
+        assert_eq!(
+            AwsRef::SsmParameter(arn.to_string()).region(),
+            Some("us-east-2")
+        );
+        assert_eq!(
+            AwsRef::SsmParameter("/app/api-key".to_string()).region(),
+            None
+        );
+    }
+}
@@ -1,6 +1,7 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::sync::{Arc, OnceLock};
+use std::sync::{Arc, OnceLock, RwLock};
 
 use base64::{engine::general_purpose, Engine as _};
 
@@ -12,36 +13,309 @@ use crate::encore;
 use crate::encore::runtime::v1::secret_data::Encoding;
 use crate::names::EncoreName;
 
+pub use aws::AwsSecrets;
+pub use vault::Vault;
+
+mod aws;
+mod vault;
+
+use aws::AwsRef;
+
 pub struct Manager {
     app_secrets: HashMap<EncoreName, Arc<Secret>>,
+    /// App secrets that are declared but have no value configured.
+    unconfigured: Vec<EncoreName>,
+    /// App secrets that are only available to processes hosting other services.
+    denied: Vec<EncoreName>,
+    /// The Vault server secrets with a vault source are read from, if any.
+    vault: Option<Arc<Vault>>,
+    /// Reads secrets with an AWS Secrets Manager or SSM Parameter Store source.
+    aws: Option<Arc<AwsSecrets>>,
+    /// The names of the hosted services, which determine the available secrets.
+    hosted_services: Vec<String>,
+    /// The status of the app secrets, computed on first use.
+    report: OnceLock<Vec<SecretStatus>>,
+}
+
+/// The resolution status of an app secret.
+#[derive(Debug, Clone)]
+pub struct SecretStatus {
+    pub name: EncoreName,
+    /// Where the secret value comes from, like "embedded" or "env:NAME".
+    pub source: String,
+    /// Why the secret could not be resolved, if it couldn't.
+    pub error: Option<String>,
 }
 
 impl Manager {
-    pub fn new(app_secrets: Vec<pb::AppSecret>) -> Self {
-        let app_secrets = app_secrets
-            .into_iter()
-            .filter_map(|s| match s.data {
-                Some(data) => Some((s.encore_name.into(), Arc::new(Secret::new(data)))),
-                None => None,
+    /// Creates a manager for the app secrets available to this process.
+    ///
+    /// Secrets restricted to specific services are only available if at least
+    /// one of them is hosted by this process. Scoping is per process: all
+    /// services hosted by the process can read the secrets available to it.
+    pub fn new(
+        app_secrets: Vec<pb::AppSecret>,
+        hosted_services: &[pb::HostedService],
+        vault: Option<Arc<Vault>>,
+        aws: Option<Arc<AwsSecrets>>,
+    ) -> Self {
+        let mut secrets = HashMap::with_capacity(app_secrets.len());
+        let mut unconfigured = Vec::new();
+        let mut denied = Vec::new();
+        let hosted: Vec<String> = hosted_services.iter().map(|svc| svc.name.clone()).collect();
+        for s in app_secrets {
+            let allowed = is_available(&s, &hosted);
+            if allowed {
+                warn_cohosted(&s, &hosted);
+            }
+            match s.data {
+                _ if !allowed => denied.push(s.encore_name.into()),
+                Some(data) => {
+                    let secret = Secret {
+                        vault: vault.clone(),
+                        aws: aws.clone(),
+                        ..Secret::new(data)
+                    };
+                    secrets.insert(s.encore_name.into(), Arc::new(secret));
+                }
+                None => unconfigured.push(s.encore_name.into()),
+            }
+        }
+        Self {
+            app_secrets: secrets,
+            unconfigured,
+            denied,
+            vault,
+            aws,
+            hosted_services: hosted,
+            report: OnceLock::new(),
+        }
+    }
+
+    /// Returns a reloader that updates the app secrets of this manager.
+    pub fn reloader(&self) -> Reloader {
+        Reloader {
+            app_secrets: self.app_secrets.clone(),
+            vault: self.vault.clone(),
+            aws: self.aws.clone(),
+            hosted_services: self.hosted_services.clone(),
+        }
+    }
+
+    /// Reads the app secrets stored in Vault and AWS, if any, and starts
+    /// refreshing the Vault secrets if a refresh interval is configured.
+    pub fn load_external(&self, handle: &tokio::runtime::Handle) {
+        if let Some(aws) = &self.aws {
+            let mut refs: Vec<AwsRef> = self
+                .app_secrets
+                .values()
+                .filter_map(|s| aws_ref(&s.data))
+                .collect();
+            refs.sort();
+            refs.dedup();
+            if !refs.is_empty() {
+                // Errors are logged, and the affected secrets are reported as unresolved.
+                let _ = handle.block_on(aws.load(&refs));
+            }
+        }
+
+        let Some(vault) = &self.vault else {
+            return;
+        };
+        let mut paths: Vec<String> = self
+            .app_secrets
+            .values()
+            .filter_map(|s| match &s.data.source {
+                Some(Source::Vault(path)) => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+        paths.sort();
+        paths.dedup();
+
+        // Errors are logged, and the affected secrets are reported as unresolved.
+        let _ = handle.block_on(vault.load(&paths));
+        if let Some(interval) = vault.refresh_interval() {
+            vault.clone().start_refresh(paths, interval, handle);
+        }
+    }
+
+    /// Resolves all app secrets and reports their status, sorted by name.
+    ///
+    /// Secrets are otherwise resolved lazily on first use, so this
+    /// surfaces misconfigured secrets at startup. The report is computed
+    /// once, so each secret is only resolved once.
+    pub fn report(&self) -> &[SecretStatus] {
+        self.report.get_or_init(|| self.compute_report())
+    }
+
+    fn compute_report(&self) -> Vec<SecretStatus> {
+        let mut report: Vec<_> = self
+            .app_secrets
+            .iter()
+            .map(|(name, secret)| SecretStatus {
+                name: name.clone(),
+                source: source_desc(&secret.data),
+                error: secret.get().err().map(|e| e.to_string()),
             })
+            .chain(self.unconfigured.iter().map(|name| SecretStatus {
+                name: name.clone(),
+                source: "none".to_string(),
+                error: Some("no value configured".to_string()),
+            }))
             .collect();
-        Self { app_secrets }
+        report.sort_by(|a, b| a.name.as_ref().cmp(b.name.as_ref()));
+        report
     }
 
     pub fn load(&self, data: SecretData) -> Secret {
-        Secret::new(data)
+        Secret {
+            aws: self.aws.clone(),
+            ..Secret::new(data)
+        }
     }
 
     /// Retrieve the secret for the given encore name.
-    /// If the secret is not found, returns None.
+    /// If the secret is not found, or it's not available
+    /// to the services hosted by this process, returns None.
     pub fn app_secret(&self, name: EncoreName) -> Option<Arc<Secret>> {
-        self.app_secrets.get(&name).cloned()
+        let secret = self.app_secrets.get(&name).cloned();
+        if secret.is_none() && self.denied.contains(&name) {
+            log::warn!("secret {name} is not available to the services hosted by this process");
+        }
+        secret
+    }
+}
+
+/// Updates the values of app secrets when the configuration is reloaded.
+#[derive(Clone)]
+pub struct Reloader {
+    app_secrets: HashMap<EncoreName, Arc<Secret>>,
+    vault: Option<Arc<Vault>>,
+    aws: Option<Arc<AwsSecrets>>,
+    hosted_services: Vec<String>,
+}
+
+/// The outcome of reloading the app secrets.
+#[derive(Debug, Default)]
+pub struct ReloadResult {
+    /// Secrets whose value was updated.
+    pub updated: Vec<EncoreName>,
+    /// Secrets whose new value could not be resolved, and keep their previous value.
+    pub failed: Vec<(EncoreName, ResolveError)>,
+    /// Secrets that were added or removed, which only takes effect after a restart.
+    pub restart_required: Vec<EncoreName>,
+}
+
+/// Reports whether the secret is available to a process hosting the given services.
+fn is_available(secret: &pb::AppSecret, hosted_services: &[String]) -> bool {
+    secret.hosted_services.is_empty()
+        || hosted_services
+            .iter()
+            .any(|svc| secret.hosted_services.contains(svc))
+}
+
+/// Warns if a restricted secret is available to hosted services it isn't
+/// restricted to, as services hosted by the same process can't be isolated.
+fn warn_cohosted(secret: &pb::AppSecret, hosted_services: &[String]) {
+    if secret.hosted_services.is_empty() {
+        return;
+    }
+    let others: Vec<&str> = hosted_services
+        .iter()
+        .filter(|svc| !secret.hosted_services.contains(svc))
+        .map(String::as_str)
+        .collect();
+    if !others.is_empty() {
+        log::warn!(
+            "secret {} is restricted to services {:?}, but is also readable by the services {:?} hosted by the same process",
+            secret.encore_name,
+            secret.hosted_services,
+            others,
+        );
+    }
+}
+
+impl Reloader {
+    /// Updates the app secrets whose configuration changed.
+    ///
+    /// New values are resolved before they're applied, so a misconfigured
+    /// secret keeps its previous value.
+    pub async fn reload(&self, app_secrets: &[pb::AppSecret]) -> ReloadResult {
+        let mut result = ReloadResult::default();
+        let mut changed = Vec::new();
+        for s in app_secrets {
+            if !is_available(s, &self.hosted_services) {
+                continue;
+            }
+            let name = EncoreName::from(s.encore_name.clone());
+            match (self.app_secrets.get(&name), &s.data) {
+                (Some(secret), Some(data)) => {
+                    if secret.current_data() != *data {
+                        changed.push((name, secret, data));
+                    }
+                }
+                (Some(_), None) | (None, Some(_)) => result.restart_required.push(name),
+                (None, None) => {}
+            }
+        }
+        for name in self.app_secrets.keys() {
+            if !app_secrets.iter().any(|s| s.encore_name == name.as_ref()) {
+                result.restart_required.push(name.clone());
+            }
+        }
+
+        // Read the new values stored in Vault and AWS, if any.
+        // Errors are logged, and the affected secrets fail to resolve below.
+        if let Some(aws) = &self.aws {
+            let mut refs: Vec<AwsRef> = changed.iter().filter_map(|(_, _, d)| aws_ref(d)).collect();
+            refs.sort();
+            refs.dedup();
+            if !refs.is_empty() {
+                let _ = aws.load(&refs).await;
+            }
+        }
+        if let Some(vault) = &self.vault {
+            let mut paths: Vec<String> = changed
+                .iter()
+                .filter_map(|(_, _, d)| match &d.source {
+                    Some(Source::Vault(path)) => Some(path.clone()),
+                    _ => None,
+                })
+                .collect();
+            paths.sort();
+            paths.dedup();
+            if !paths.is_empty() {
+                let _ = vault.load(&paths).await;
+            }
+        }
+
+        for (name, secret, data) in changed {
+            match resolve(data, self.vault.as_deref(), self.aws.as_deref()) {
+                Ok(_) => {
+                    *secret.reloaded.write().unwrap() = Some(data.clone());
+                    result.updated.push(name);
+                }
+                Err(err) => result.failed.push((name, err)),
+            }
+        }
+
+        result.updated.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        result.failed.sort_by(|a, b| a.0.as_ref().cmp(b.0.as_ref()));
+        result
+            .restart_required
+            .sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        result
     }
 }
 
 pub struct Secret {
     data: SecretData,
     resolved: OnceLock<ResolveResult<Vec<u8>>>,
+    /// Replaces `data` for `latest` when the configuration is reloaded.
+    reloaded: RwLock<Option<SecretData>>,
+    vault: Option<Arc<Vault>>,
+    aws: Option<Arc<AwsSecrets>>,
 }
 
 impl Secret {
@@ -49,6 +323,9 @@ impl Secret {
         Self {
             data,
             resolved: OnceLock::new(),
+            reloaded: RwLock::new(None),
+            vault: None,
+            aws: None,
         }
     }
 
@@ -60,13 +337,63 @@ impl Secret {
         })
     }
 
+    /// Returns the secret value, which is resolved on first use.
     pub fn get(&self) -> Result<&[u8], ResolveError> {
-        let result = self.resolved.get_or_init(|| resolve(&self.data)).as_deref();
+        let result = self
+            .resolved
+            .get_or_init(|| resolve(&self.data, self.vault.as_deref(), self.aws.as_deref()))
+            .as_deref();
         match result {
             Ok(bytes) => Ok(bytes),
             Err(err) => Err(*err),
         }
     }
+
+    /// Returns the latest secret value. It differs from `get` for
+    /// secrets read from Vault, which may have been refreshed since,
+    /// and for secrets whose configuration has been reloaded.
+    pub fn latest(&self) -> Result<Cow<'_, [u8]>, ResolveError> {
+        if let Some(data) = &*self.reloaded.read().unwrap() {
+            return resolve(data, self.vault.as_deref(), self.aws.as_deref()).map(Cow::Owned);
+        }
+        match &self.data.source {
+            Some(Source::Vault(_)) => {
+                resolve(&self.data, self.vault.as_deref(), self.aws.as_deref()).map(Cow::Owned)
+            }
+            _ => self.get().map(Cow::Borrowed),
+        }
+    }
+}
+
+impl Secret {
+    fn current_data(&self) -> SecretData {
+        match &*self.reloaded.read().unwrap() {
+            Some(data) => data.clone(),
+            None => self.data.clone(),
+        }
+    }
+}
+
+/// Describes where the secret value comes from, without revealing it.
+fn source_desc(data: &SecretData) -> String {
+    match &data.source {
+        Some(Source::Embedded(_)) => "embedded".to_string(),
+        Some(Source::Env(name)) => format!("env:{name}"),
+        Some(Source::File(path)) => format!("file:{path}"),
+        Some(Source::Vault(path)) => format!("vault:{path}"),
+        Some(Source::AwsSecretsManager(id)) => format!("aws-secrets-manager:{id}"),
+        Some(Source::AwsSsmParameter(name)) => format!("aws-ssm:{name}"),
+        None => "none".to_string(),
+    }
+}
+
+/// Returns the reference to the secret in AWS, if it's stored there.
+fn aws_ref(data: &SecretData) -> Option<AwsRef> {
+    match &data.source {
+        Some(Source::AwsSecretsManager(id)) => Some(AwsRef::SecretsManager(id.clone())),
+        Some(Source::AwsSsmParameter(name)) => Some(AwsRef::SsmParameter(name.clone())),
+        _ => None,
+    }
 }
 
 const BASE64: general_purpose::GeneralPurpose = general_purpose::STANDARD;
@@ -74,6 +401,10 @@ const BASE64: general_purpose::GeneralPurpose = general_purpose::STANDARD;
 #[derive(Debug, Copy, Clone)]
 pub enum ResolveError {
     EnvVarNotFound,
+    FileNotReadable,
+    VaultNotConfigured,
+    VaultSecretNotFound,
+    AwsSecretNotReadable,
     JsonKeyNotFound,
     JsonValueNotString,
     InvalidBase64,
@@ -89,6 +420,10 @@ impl Display for ResolveError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ResolveError::EnvVarNotFound => write!(f, "environment variable not found"),
+            ResolveError::FileNotReadable => write!(f, "unable to read secret file"),
+            ResolveError::VaultNotConfigured => write!(f, "vault is not configured"),
+            ResolveError::VaultSecretNotFound => write!(f, "secret not found in vault"),
+            ResolveError::AwsSecretNotReadable => write!(f, "unable to read secret from AWS"),
             ResolveError::JsonKeyNotFound => write!(f, "JSON key not found"),
             ResolveError::JsonValueNotString => write!(f, "JSON value is not a string"),
             ResolveError::InvalidBase64 => write!(f, "invalid base64"),
@@ -102,13 +437,39 @@ impl Display for ResolveError {
 
 type ResolveResult<T> = Result<T, ResolveError>;
 
-fn resolve(data: &SecretData) -> ResolveResult<Vec<u8>> {
+fn resolve(
+    data: &SecretData,
+    vault: Option<&Vault>,
+    aws: Option<&AwsSecrets>,
+) -> ResolveResult<Vec<u8>> {
     let value = match &data.source {
         Some(Source::Embedded(data)) => data.clone(),
         Some(Source::Env(name)) => {
             let value = std::env::var(name).map_err(|_| ResolveError::EnvVarNotFound)?;
             value.into_bytes()
         }
+        Some(Source::File(path)) => {
+            let mut value = std::fs::read(path).map_err(|_| ResolveError::FileNotReadable)?;
+            if value.ends_with(b"\n") {
+                value.pop();
+                if value.ends_with(b"\r") {
+                    value.pop();
+                }
+            }
+            value
+        }
+        Some(Source::Vault(path)) => vault
+            .ok_or(ResolveError::VaultNotConfigured)?
+            .get(path)
+            .ok_or(ResolveError::VaultSecretNotFound)?,
+        Some(Source::AwsSecretsManager(_) | Source::AwsSsmParameter(_)) => {
+            let aws = aws.ok_or(ResolveError::AwsSecretNotReadable)?;
+            let secret = aws_ref(data).ok_or(ResolveError::InvalidSecretSource)?;
+            aws.get(&secret).map_err(|err| {
+                log::error!("unable to read secret {secret:?} from AWS: {err:#}");
+                ResolveError::AwsSecretNotReadable
+            })?
+        }
         None => Err(ResolveError::InvalidSecretSource)?,
     };
 
@@ -236,6 +597,23 @@ mod tests {
             assert_eq!(secret.get().unwrap(), b"hello");
         }
 
+        // Test file sources, like mounted Kubernetes secrets.
+        {
+            let path = std::env::temp_dir().join(format!("secret-{}", xid::new()));
+            let data = SecretData {
+                source: Some(Source::File(path.to_string_lossy().into_owned())),
+                sub_path: None,
+                encoding: Encoding::None as i32,
+            };
+            let secret = Secret::new(data.clone());
+            assert_matches!(secret.get(), Err(ResolveError::FileNotReadable));
+
+            std::fs::write(&path, "hello\n").unwrap();
+            let secret = Secret::new(data);
+            assert_eq!(secret.get().unwrap(), b"hello");
+            std::fs::remove_file(&path).unwrap();
+        }
+
         // Test json_key.
         {
             let secret = Secret::new(SecretData {
@@ -264,4 +642,226 @@ mod tests {
             assert_matches!(secret.get().unwrap(), b"hello");
         }
     }
+
+    #[test]
+    fn test_resolve_vault() {
+        use super::*;
+
+        let data = SecretData {
+            source: Some(Source::Vault("app/stripe".to_string())),
+            sub_path: Some(SubPath::JsonKey("api_key".to_string())),
+            encoding: Encoding::None as i32,
+        };
+        let secret = Secret::new(data.clone());
+        assert_matches!(secret.get(), Err(ResolveError::VaultNotConfigured));
+
+        let vault = Arc::new(
+            Vault::new(
+                pb::Vault {
+                    address: "http://localhost:8200".to_string(),
+                    auth: Some(pb::vault::Auth::Token(pb::vault::TokenAuth::default())),
+                    ..Default::default()
+                },
+                reqwest::Client::new(),
+            )
+            .unwrap(),
+        );
+        let secret = Secret {
+            vault: Some(vault.clone()),
+            ..Secret::new(data)
+        };
+        assert_matches!(secret.latest(), Err(ResolveError::VaultSecretNotFound));
+
+        // The latest value reflects refreshes, while get keeps the first value.
+        vault.insert("app/stripe", br#"{"api_key": "v1"}"#);
+        assert_eq!(secret.get().unwrap(), b"v1");
+        vault.insert("app/stripe", br#"{"api_key": "v2"}"#);
+        assert_eq!(secret.get().unwrap(), b"v1");
+        assert_eq!(&*secret.latest().unwrap(), b"v2");
+    }
+
+    #[test]
+    fn test_resolve_aws() {
+        use super::*;
+
+        let data = SecretData {
+            source: Some(Source::AwsSecretsManager("prod/db".to_string())),
+            sub_path: Some(SubPath::JsonKey("password".to_string())),
+            encoding: Encoding::None as i32,
+        };
+        let secret = Secret::new(data.clone());
+        assert_matches!(secret.get(), Err(ResolveError::AwsSecretNotReadable));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let aws = Arc::new(AwsSecrets::new(rt.handle().clone()));
+        aws.insert(
+            AwsRef::SecretsManager("prod/db".to_string()),
+            br#"{"password": "hunter2"}"#,
+        );
+        aws.insert(AwsRef::SsmParameter("/app/key".to_string()), b"abc");
+
+        let secret = Secret {
+            aws: Some(aws.clone()),
+            ..Secret::new(data)
+        };
+        assert_eq!(secret.get().unwrap(), b"hunter2");
+
+        let secret = Secret {
+            aws: Some(aws),
+            ..Secret::new(SecretData {
+                source: Some(Source::AwsSsmParameter("/app/key".to_string())),
+                sub_path: None,
+                encoding: Encoding::None as i32,
+            })
+        };
+        assert_eq!(secret.get().unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_report() {
+        use super::*;
+
+        let secret = |name: &str, data: Option<SecretData>| pb::AppSecret {
+            encore_name: name.to_string(),
+            data,
+            ..Default::default()
+        };
+        let mgr = Manager::new(
+            vec![
+                secret(
+                    "b",
+                    Some(SecretData {
+                        source: Some(Source::Env("TEST_REPORT_MISSING".to_string())),
+                        sub_path: None,
+                        encoding: Encoding::None as i32,
+                    }),
+                ),
+                secret(
+                    "a",
+                    Some(SecretData {
+                        source: Some(Source::Embedded(b"hello".to_vec())),
+                        sub_path: None,
+                        encoding: Encoding::None as i32,
+                    }),
+                ),
+                secret("c", None),
+            ],
+            &[],
+            None,
+            None,
+        );
+
+        let report = mgr.report();
+        let names: Vec<_> = report.iter().map(|s| s.name.as_ref()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+
+        assert_eq!(report[0].source, "embedded");
+        assert_eq!(report[0].error, None);
+        assert_eq!(report[1].source, "env:TEST_REPORT_MISSING");
+        assert_eq!(
+            report[1].error.as_deref(),
+            Some("environment variable not found")
+        );
+        assert_eq!(report[2].source, "none");
+        assert!(report[2].error.is_some());
+    }
+
+    #[test]
+    fn test_scoped() {
+        use super::*;
+
+        let secret = |name: &str, hosted_services: &[&str]| pb::AppSecret {
+            encore_name: name.to_string(),
+            data: Some(SecretData {
+                source: Some(Source::Embedded(b"hello".to_vec())),
+                sub_path: None,
+                encoding: Encoding::None as i32,
+            }),
+            hosted_services: hosted_services.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        let hosted = |name: &str| pb::HostedService {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        let mgr = Manager::new(
+            vec![
+                secret("shared", &[]),
+                secret("billing", &["billing"]),
+                secret("users", &["users", "admin"]),
+            ],
+            &[hosted("users")],
+            None,
+            None,
+        );
+        assert!(mgr.app_secret("shared".into()).is_some());
+        assert!(mgr.app_secret("billing".into()).is_none());
+        assert!(mgr.app_secret("users".into()).is_some());
+
+        // Denied secrets are left out of the report, as they're not misconfigured.
+        let names: Vec<_> = mgr.report().iter().map(|s| s.name.clone()).collect();
+        assert_eq!(
+            names,
+            [EncoreName::from("shared"), EncoreName::from("users")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        use super::*;
+
+        let embedded = |value: &[u8]| SecretData {
+            source: Some(Source::Embedded(value.to_vec())),
+            sub_path: None,
+            encoding: Encoding::None as i32,
+        };
+        let secret = |name: &str, data: SecretData| pb::AppSecret {
+            encore_name: name.to_string(),
+            data: Some(data),
+            ..Default::default()
+        };
+        let mgr = Manager::new(
+            vec![
+                secret("a", embedded(b"a1")),
+                secret("b", embedded(b"b1")),
+                secret("c", embedded(b"c1")),
+            ],
+            &[],
+            None,
+            None,
+        );
+        let a = mgr.app_secret("a".into()).unwrap();
+
+        let result = mgr
+            .reloader()
+            .reload(&[
+                secret("a", embedded(b"a2")),
+                secret(
+                    "b",
+                    SecretData {
+                        source: Some(Source::Env("TEST_RELOAD_MISSING".to_string())),
+                        sub_path: None,
+                        encoding: Encoding::None as i32,
+                    },
+                ),
+                secret("d", embedded(b"d1")),
+            ])
+            .await;
+        assert_eq!(result.updated, [EncoreName::from("a")]);
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].0, EncoreName::from("b"));
+        assert_eq!(
+            result.restart_required,
+            [EncoreName::from("c"), EncoreName::from("d")]
+        );
+
+        // The latest value reflects the reload, while get keeps the initial value.
+        assert_eq!(&*a.latest().unwrap(), b"a2");
+        assert_eq!(a.get().unwrap(), b"a1");
+        let b = mgr.app_secret("b".into()).unwrap();
+        assert_eq!(&*b.latest().unwrap(), b"b1");
+    }
 }
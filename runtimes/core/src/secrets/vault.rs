@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::encore::runtime::v1 as pb;
+use crate::secrets::Secret;
+
+const DEFAULT_MOUNT_PATH: &str = "secret";
+const DEFAULT_KUBERNETES_TOKEN_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// Reads secrets from a HashiCorp Vault KV version 2 secrets engine.
+///
+/// Secrets are read at startup and cached, and optionally read
+/// again periodically so that rotated secrets are picked up.
+pub struct Vault {
+    address: String,
+    namespace: Option<String>,
+    mount_path: String,
+    refresh_interval: Option<Duration>,
+    auth: Auth,
+    http_client: reqwest::Client,
+
+    /// The current client token, if logged in.
+    token: tokio::sync::Mutex<Option<String>>,
+
+    /// The data of each secret path, as a JSON object.
+    data: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+enum Auth {
+    Token(Secret),
+    Kubernetes {
+        role: String,
+        mount_path: String,
+        token_path: String,
+    },
+    AppRole {
+        role_id: String,
+        secret_id: Secret,
+        mount_path: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    auth: LoginAuth,
+}
+
+#[derive(Deserialize)]
+struct LoginAuth {
+    client_token: String,
+}
+
+#[derive(Deserialize)]
+struct ReadResponse {
+    data: ReadData,
+}
+
+#[derive(Deserialize)]
+struct ReadData {
+    data: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Vault {
+    pub fn new(cfg: pb::Vault, http_client: reqwest::Client) -> anyhow::Result<Self> {
+        use pb::vault::Auth as PbAuth;
+        let auth = match cfg.auth.context("missing vault auth method")? {
+            PbAuth::Token(t) => Auth::Token(Secret::new(t.token.unwrap_or_default())),
+            PbAuth::Kubernetes(k) => Auth::Kubernetes {
+                role: k.role,
+                mount_path: k.mount_path.unwrap_or_else(|| "kubernetes".to_string()),
+                token_path: k
+                    .token_path
+                    .unwrap_or_else(|| DEFAULT_KUBERNETES_TOKEN_PATH.to_string()),
+            },
+            PbAuth::AppRole(a) => Auth::AppRole {
+                role_id: a.role_id,
+                secret_id: Secret::new(a.secret_id.unwrap_or_default()),
+                mount_path: a.mount_path.unwrap_or_else(|| "approle".to_string()),
+            },
+        };
+
+        Ok(Self {
+            address: cfg.address.trim_end_matches('/').to_string(),
+            namespace: cfg.namespace,
+            mount_path: cfg
+                .mount_path
+                .unwrap_or_else(|| DEFAULT_MOUNT_PATH.to_string())
+                .trim_matches('/')
+                .to_string(),
+            refresh_interval: cfg
+                .refresh_interval
+                .and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero()),
+            auth,
+            http_client,
+            token: tokio::sync::Mutex::new(None),
+            data: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_interval
+    }
+
+    /// Returns the cached data of the secret at the given path, as a JSON object.
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        self.data.read().unwrap().get(path).cloned()
+    }
+
+    /// Reads the secrets at the given paths and caches them.
+    /// Paths that fail to be read keep their previously cached data.
+    pub async fn load(&self, paths: &[String]) -> anyhow::Result<()> {
+        let mut first_err = None;
+        for path in paths {
+            match self.read(path).await {
+                Ok(data) => {
+                    self.data.write().unwrap().insert(path.clone(), data);
+                }
+                Err(err) => {
+                    log::error!("vault: unable to read secret {path}: {err:#}");
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Periodically reads the secrets at the given paths again.
+    pub fn start_refresh(
+        self: Arc<Self>,
+        paths: Vec<String>,
+        interval: Duration,
+        handle: &tokio::runtime::Handle,
+    ) {
+        handle.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                // Errors are logged by load.
+                let _ = self.load(&paths).await;
+            }
+        });
+    }
+
+    async fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let url = format!(
+            "{}/v1/{}/data/{}",
+            self.address,
+            self.mount_path,
+            path.trim_matches('/')
+        );
+
+        // Log in again if the token has expired or been revoked.
+        let mut retried = false;
+        loop {
+            let token = self.token().await?;
+            let resp = self
+                .request(self.http_client.get(&url))
+                .header("X-Vault-Token", token)
+                .send()
+                .await
+                .context("request failed")?;
+            if resp.status() == reqwest::StatusCode::FORBIDDEN && !retried {
+                *self.token.lock().await = None;
+                retried = true;
+                continue;
+            }
+            let resp: ReadResponse = resp
+                .error_for_status()?
+                .json()
+                .await
+                .context("invalid response")?;
+            return Ok(serde_json::to_vec(&resp.data.data)?);
+        }
+    }
+
+    async fn token(&self) -> anyhow::Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref() {
+            return Ok(token.clone());
+        }
+        let new_token = self.login().await.context("unable to log in")?;
+        *token = Some(new_token.clone());
+        Ok(new_token)
+    }
+
+    async fn login(&self) -> anyhow::Result<String> {
+        let (mount_path, body) = match &self.auth {
+            Auth::Token(token) => {
+                let token = token
+                    .get()
+                    .map_err(|e| anyhow::anyhow!("unable to resolve token: {e}"))?;
+                return Ok(String::from_utf8(token.to_vec())?);
+            }
+            Auth::Kubernetes {
+                role,
+                mount_path,
+                token_path,
+            } => {
+                let jwt = std::fs::read_to_string(token_path).with_context(|| {
+                    format!("unable to read service account token {token_path}")
+                })?;
+                (
+                    mount_path,
+                    serde_json::json!({"role": role, "jwt": jwt.trim()}),
+                )
+            }
+            Auth::AppRole {
+                role_id,
+                secret_id,
+                mount_path,
+            } => {
+                let secret_id = secret_id
+                    .get()
+                    .map_err(|e| anyhow::anyhow!("unable to resolve secret id: {e}"))?;
+                (
+                    mount_path,
+                    serde_json::json!({
+                        "role_id": role_id,
+                        "secret_id": String::from_utf8_lossy(secret_id),
+                    }),
+                )
+            }
+        };
+
+        let url = format!(
+            "{}/v1/auth/{}/login",
+            self.address,
+            mount_path.trim_matches('/')
+        );
+        let resp: LoginResponse = self
+            .request(self.http_client.post(url))
+            .json(&body)
+            .send()
+            .await
+            .context("request failed")?
+            .error_for_status()?
+            .json()
+            .await
+            .context("invalid response")?;
+        Ok(resp.auth.client_token)
+    }
+
+    #[cfg(test)]
+    pub(super) fn insert(&self, path: &str, data: &[u8]) {
+        self.data
+            .write()
+            .unwrap()
+            .insert(path.to_string(), data.to_vec());
+    }
+
+    fn request(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.namespace {
+            Some(ns) => req.header("X-Vault-Namespace", ns),
+            None => req,
+        }
+    }
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vault")
+            .field("address", &self.address)
+            .field("namespace", &self.namespace)
+            .field("mount_path", &self.mount_path)
+            .finish_non_exhaustive()
+    }
+}
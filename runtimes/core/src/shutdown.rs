@@ -0,0 +1,161 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::encore::runtime::v1 as pb;
+
+/// How long the whole shutdown may take if not configured.
+const DEFAULT_TOTAL: Duration = Duration::from_secs(30);
+
+/// The stages of a graceful shutdown, in the order they run.
+///
+/// Each stage depends on the ones before it having completed: pubsub handlers
+/// may still be serving API calls, and both produce traces and metrics that
+/// need flushing, which in turn may use database connections.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Stop accepting new requests and drain in-flight ones.
+    Traffic,
+    /// Stop receiving new messages and drain in-flight ones.
+    PubSub,
+    /// Flush buffered traces and metrics.
+    Telemetry,
+    /// Close database connection pools.
+    Pools,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Traffic => "drain traffic",
+            Stage::PubSub => "drain pubsub",
+            Stage::Telemetry => "flush telemetry",
+            Stage::Pools => "close pools",
+        }
+    }
+}
+
+/// Runs the shutdown stages in order, bounding each stage by its own
+/// timeout and by the time remaining of the total shutdown timeout.
+pub struct Sequence {
+    deadline: Instant,
+    stages: [Option<Duration>; 4],
+    last: Option<Stage>,
+}
+
+impl Sequence {
+    pub fn new(cfg: Option<&pb::GracefulShutdown>) -> Self {
+        let duration = |d: Option<prost_types::Duration>| {
+            d.and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero())
+        };
+        let total = cfg.and_then(|c| duration(c.total)).unwrap_or(DEFAULT_TOTAL);
+        let stages = match cfg {
+            Some(c) => [
+                duration(c.drain_traffic),
+                duration(c.drain_pubsub),
+                duration(c.flush_telemetry),
+                duration(c.close_pools),
+            ],
+            None => [None; 4],
+        };
+        Self {
+            deadline: Instant::now() + total,
+            stages,
+            last: None,
+        }
+    }
+
+    /// Runs a shutdown stage to completion or until it times out.
+    /// Reports whether the stage completed.
+    pub async fn run<F>(&mut self, stage: Stage, fut: F) -> bool
+    where
+        F: Future<Output = ()>,
+    {
+        debug_assert!(
+            !matches!(self.last, Some(last) if last >= stage),
+            "shutdown stages must run in order"
+        );
+        self.last = Some(stage);
+
+        let mut deadline = self.deadline;
+        if let Some(timeout) = self.stages[stage as usize] {
+            deadline = deadline.min(Instant::now() + timeout);
+        }
+
+        log::debug!("shutdown: {}", stage.name());
+        match tokio::time::timeout_at(deadline, fut).await {
+            Ok(()) => true,
+            Err(_) => {
+                log::warn!("shutdown: {} timed out, continuing", stage.name());
+                false
+            }
+        }
+    }
+}
+
+/// Completes when the process is asked to shut down.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => {},
+                    _ = tokio::signal::ctrl_c() => {},
+                }
+            }
+            Err(err) => {
+                log::error!("unable to listen for SIGTERM: {err}");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: i64) -> Option<prost_types::Duration> {
+        Some(prost_types::Duration {
+            seconds: s,
+            nanos: 0,
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stage_timeouts() {
+        let mut seq = Sequence::new(Some(&pb::GracefulShutdown {
+            total: secs(10),
+            drain_traffic: secs(2),
+            ..Default::default()
+        }));
+
+        let start = Instant::now();
+        assert!(!seq.run(Stage::Traffic, std::future::pending()).await);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        // Stages without a timeout use the remaining time.
+        assert!(!seq.run(Stage::PubSub, std::future::pending()).await);
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+
+        // Stages are polled once even if the deadline has passed.
+        assert!(seq.run(Stage::Telemetry, async {}).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn default_total() {
+        let mut seq = Sequence::new(None);
+        let start = Instant::now();
+        assert!(!seq.run(Stage::Traffic, std::future::pending()).await);
+        assert_eq!(start.elapsed(), DEFAULT_TOTAL);
+    }
+}
@@ -1,52 +1,167 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use bb8::{ErrorSink, PooledConnection, RunError};
-use bb8_postgres::PostgresConnectionManager;
 use futures_util::StreamExt;
+use rand::Rng;
 
+use tokio_postgres::error::SqlState;
 use tokio_postgres::types::BorrowToSql;
 
+use crate::model::SpanKey;
+use crate::sqldb::manager::{Flavor, PoolTimeouts, ReadRouting, ReplicaConfig};
 use crate::sqldb::val::RowValue;
 use crate::trace::{protocol, Tracer};
-use crate::{model, sqldb};
+use crate::{faults, model, replay, sqldb};
 
-use super::transaction::Transaction;
-
-type Mgr = PostgresConnectionManager<postgres_native_tls::MakeTlsConnector>;
+use super::conn::{Client, Deadline, Mgr};
+use super::metrics::QueryMetrics;
+use super::singleton::SingletonLock;
+use super::transaction::{Transaction, TransactionOptions};
 
 pub struct Pool {
+    name: String,
     pool: bb8::Pool<Mgr>,
     tracer: QueryTracer,
+    replica: Option<Replica>,
+    flavor: Flavor,
+}
+
+/// Pools of connections to a database's read replicas.
+struct Replica {
+    pools: Vec<bb8::Pool<Mgr>>,
+    next: AtomicUsize,
+    routing: ReadRouting,
+    writes: SessionWrites,
+}
+
+impl Replica {
+    /// Returns the pool of the next replica, spreading reads evenly across them.
+    fn pick(&self) -> &bb8::Pool<Mgr> {
+        let idx = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.pools[idx % self.pools.len()]
+    }
 }
 
 impl Pool {
-    pub fn new<DB: sqldb::Database>(db: &DB, tracer: Tracer) -> anyhow::Result<Self> {
+    pub(crate) fn new<DB: sqldb::Database>(db: &DB, tracer: QueryTracer) -> anyhow::Result<Self> {
         let tls = db.tls()?.clone();
-        let mgr = Mgr::new(db.config()?.clone(), tls);
-
         let pool_cfg = db.pool_config()?;
-        let mut pool = bb8::Pool::builder()
-            .error_sink(Box::new(RustLoggerSink {
-                db_name: db.name().to_string(),
-            }))
-            .max_size(if pool_cfg.max_conns > 0 {
-                pool_cfg.max_conns
-            } else {
-                30
-            });
-
-        if pool_cfg.min_conns > 0 {
-            pool = pool.min_idle(Some(pool_cfg.min_conns));
-        }
-
-        let pool = pool.build_unchecked(mgr);
+        let mgr = Mgr::new(
+            db.config()?.clone(),
+            tls,
+            pool_cfg.stmt_cache_size,
+            pool_cfg.query_timeout,
+        )
+        .with_session_setup(pool_cfg.session_setup)
+        .with_iam(pool_cfg.iam);
+
+        let pool = pool_builder(
+            db.name().to_string(),
+            pool_cfg.min_conns,
+            pool_cfg.max_conns,
+            pool_cfg.timeouts,
+        )
+        .build_unchecked(mgr);
         Ok(Self {
+            name: db.name().to_string(),
             pool,
-            tracer: QueryTracer(tracer),
+            tracer,
+            replica: None,
+            flavor: Flavor::default(),
         })
     }
+
+    /// Returns a pool using the connection isolating tests.
+    /// Read replicas aren't used, as they can't see the test's changes.
+    pub(crate) fn isolated(db_name: &str, pool: bb8::Pool<Mgr>, tracer: QueryTracer) -> Self {
+        Self {
+            name: db_name.to_string(),
+            pool,
+            tracer,
+            replica: None,
+            flavor: Flavor::default(),
+        }
+    }
+
+    /// Adapts the pool to the database the server runs.
+    pub(crate) fn with_flavor(mut self, flavor: Flavor) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
+    /// Routes read-only queries to the given read replicas.
+    pub(crate) fn with_replica(mut self, db_name: &str, cfg: &ReplicaConfig) -> Self {
+        let pools: Vec<_> = cfg
+            .servers
+            .iter()
+            .enumerate()
+            .map(|(idx, server)| {
+                let mgr = Mgr::new(
+                    (*server.config).clone(),
+                    server.tls.clone(),
+                    cfg.stmt_cache_size,
+                    cfg.query_timeout,
+                )
+                .with_session_setup(cfg.session_setup.clone())
+                .with_iam(server.iam.clone());
+                pool_builder(
+                    format!("{db_name} (read replica {})", idx + 1),
+                    cfg.min_conns,
+                    cfg.max_conns,
+                    cfg.timeouts,
+                )
+                .build_unchecked(mgr)
+            })
+            .collect();
+        if pools.is_empty() {
+            return self;
+        }
+
+        self.replica = Some(Replica {
+            pools,
+            next: AtomicUsize::new(0),
+            routing: cfg.routing,
+            writes: SessionWrites::default(),
+        });
+        self
+    }
+
+    /// Returns the pool to execute the query with.
+    fn route(&self, query: &str, source: Option<&model::Request>) -> &bb8::Pool<Mgr> {
+        let Some(replica) = &self.replica else {
+            return &self.pool;
+        };
+
+        if !is_read_only(query) {
+            self.record_write(source);
+            return &self.pool;
+        }
+        if pins_primary(query) {
+            return &self.pool;
+        }
+
+        match (replica.routing, source) {
+            (ReadRouting::Session, Some(source)) if replica.writes.contains(&source.span) => {
+                &self.pool
+            }
+            _ => replica.pick(),
+        }
+    }
+
+    /// Records that the request has written to the database,
+    /// so that its subsequent reads are routed to the primary.
+    fn record_write(&self, source: Option<&model::Request>) {
+        if let (Some(replica), Some(source)) = (&self.replica, source) {
+            if replica.routing == ReadRouting::Session {
+                replica.writes.insert(source.span);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -68,6 +183,30 @@ impl ErrorSink<tokio_postgres::Error> for RustLoggerSink {
     }
 }
 
+fn pool_builder(
+    db_name: String,
+    min_conns: u32,
+    max_conns: u32,
+    timeouts: PoolTimeouts,
+) -> bb8::Builder<Mgr> {
+    let mut builder = bb8::Pool::builder()
+        .error_sink(Box::new(RustLoggerSink { db_name }))
+        .max_size(if max_conns > 0 { max_conns } else { 30 });
+    if min_conns > 0 {
+        builder = builder.min_idle(Some(min_conns));
+    }
+    if let Some(timeout) = timeouts.acquire {
+        builder = builder.connection_timeout(timeout);
+    }
+    if let Some(timeout) = timeouts.idle {
+        builder = builder.idle_timeout(Some(timeout));
+    }
+    if let Some(lifetime) = timeouts.max_lifetime {
+        builder = builder.max_lifetime(Some(lifetime));
+    }
+    builder
+}
+
 impl Pool {
     pub async fn query_raw<P, I>(
         &self,
@@ -80,63 +219,319 @@ impl Pool {
         I: IntoIterator<Item = P>,
         I::IntoIter: ExactSizeIterator,
     {
+        let params: Vec<P> = params.into_iter().collect();
+        let key = sqldb::replay::query_key(&self.name, query, &params);
         self.tracer
-            .trace(source, query, || async {
-                let conn = self.pool.get().await.map_err(|e| match e {
-                    RunError::User(err) => Error::DB(err),
-                    RunError::TimedOut => Error::ConnectTimeout,
-                })?;
-                conn.query_raw(query, params).await.map_err(Error::from)
+            .trace(source, query, || {
+                sqldb::replay::query(key, async {
+                    let mut attempt = 1;
+                    loop {
+                        let pool = self.route(query, source);
+                        let conn = pool.get_owned().await.map_err(|e| match e {
+                            RunError::User(err) => Error::DB(err),
+                            RunError::TimedOut => Error::ConnectTimeout,
+                        })?;
+                        let conn = Lease::new(conn);
+                        let deadline = conn.deadline(source);
+                        let params = params.iter().map(|p| p.borrow_to_sql());
+                        match conn.query_raw(query, params, deadline.as_ref()).await {
+                            Err(err) if self.should_retry(&conn, &err, attempt) => {
+                                drop(conn);
+                                tokio::time::sleep(retry_backoff(attempt)).await;
+                                attempt += 1;
+                            }
+                            // The cursor keeps the connection until it's dropped,
+                            // so it isn't handed out again while rows are streamed.
+                            result => return Ok(Cursor::new(result?, deadline).holding(conn)),
+                        }
+                    }
+                })
             })
             .await
     }
 
     pub async fn acquire(&self) -> Result<Connection, tokio_postgres::Error> {
+        // Replayed queries don't need a connection.
+        if sqldb::replay::replaying() {
+            return Ok(Connection {
+                conn: tokio::sync::RwLock::new(None),
+                tracer: self.tracer.clone(),
+            });
+        }
+
+        // Dedicated connections always use the primary.
         let conn = self.pool.get_owned().await.map_err(|e| match e {
             RunError::User(err) => err,
             RunError::TimedOut => tokio_postgres::Error::__private_api_timeout(),
         })?;
         Ok(Connection {
-            conn: tokio::sync::RwLock::new(Some(conn)),
+            conn: tokio::sync::RwLock::new(Some(Lease::new(conn))),
             tracer: self.tracer.clone(),
         })
     }
 
-    pub async fn begin(&self, source: Option<&model::Request>) -> Result<Transaction, Error> {
+    pub async fn begin(
+        &self,
+        opts: &TransactionOptions,
+        source: Option<&model::Request>,
+    ) -> Result<Transaction, Error> {
+        if sqldb::replay::replaying() {
+            return Ok(Transaction::replaying(self.tracer.clone()));
+        }
+
+        // Transactions always use the primary.
+        self.record_write(source);
         let conn = self.pool.get_owned().await.map_err(|e| match e {
             RunError::User(err) => err,
             RunError::TimedOut => tokio_postgres::Error::__private_api_timeout(),
         })?;
-        Transaction::begin(conn, self.tracer.clone(), source).await
+        Transaction::begin(conn, self.tracer.clone(), opts, source).await
+    }
+
+    /// Attempts to acquire the cluster-wide singleton lock with the given name.
+    /// Returns None if another instance holds the lock.
+    ///
+    /// The lock's lease is renewed on a dedicated connection outside of the pool,
+    /// so heartbeats aren't delayed by a busy pool.
+    pub async fn try_lock_singleton(&self, name: &str) -> Result<Option<SingletonLock>, Error> {
+        let conn = self.pool.dedicated_connection().await?;
+        let lock = SingletonLock::try_acquire(conn, name).await?;
+        Ok(lock)
+    }
+}
+
+impl Pool {
+    /// Reports whether the failed query should be retried.
+    ///
+    /// CockroachDB reports serialization failures for contended queries,
+    /// which are safe to retry outside of transactions.
+    fn should_retry(&self, conn: &Client, err: &tokio_postgres::Error, attempt: u32) -> bool {
+        self.flavor == Flavor::CockroachDb
+            && attempt < MAX_QUERY_ATTEMPTS
+            && !conn.is_test_isolated()
+            && err.code() == Some(&SqlState::T_R_SERIALIZATION_FAILURE)
+    }
+}
+
+/// The maximum number of times a query is attempted when retrying serialization failures.
+const MAX_QUERY_ATTEMPTS: u32 = 5;
+
+/// Returns how long to wait before the next attempt, with exponential backoff and jitter.
+fn retry_backoff(attempt: u32) -> Duration {
+    const BASE: Duration = Duration::from_millis(10);
+    const MAX: Duration = Duration::from_secs(1);
+    let backoff = BASE.saturating_mul(1 << attempt.min(16)).min(MAX);
+    backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+/// How long writes are remembered for the purpose of session read routing.
+/// This bounds how long a request can observe its own writes.
+const SESSION_WRITE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The number of tracked requests above which expired entries are removed.
+const SESSION_PRUNE_THRESHOLD: usize = 1024;
+
+/// Tracks which requests have written to the database.
+#[derive(Default)]
+struct SessionWrites {
+    writes: Mutex<HashMap<SpanKey, Instant>>,
+}
+
+impl SessionWrites {
+    fn insert(&self, span: SpanKey) {
+        let mut writes = self.writes.lock().unwrap();
+        if writes.len() >= SESSION_PRUNE_THRESHOLD {
+            writes.retain(|_, at| at.elapsed() < SESSION_WRITE_TTL);
+        }
+        writes.insert(span, Instant::now());
+    }
+
+    fn contains(&self, span: &SpanKey) -> bool {
+        let writes = self.writes.lock().unwrap();
+        writes
+            .get(span)
+            .is_some_and(|at| at.elapsed() < SESSION_WRITE_TTL)
+    }
+}
+
+/// Reports whether the query is known to be read-only, and can be executed by a read replica.
+///
+/// This errs on the side of caution: queries that might write, take row locks,
+/// or modify sequences are not considered read-only. Function calls are not
+/// inspected, so a `SELECT` of a function that writes must carry the primary hint.
+fn is_read_only(query: &str) -> bool {
+    let query = strip_leading_comments(query).to_ascii_uppercase();
+    let starts_with_keyword = |kw: &str| {
+        query.starts_with(kw)
+            && !query[kw.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if !starts_with_keyword("SELECT") && !starts_with_keyword("SHOW") {
+        return false;
+    }
+
+    const UNSAFE: &[&str] = &[
+        " FOR UPDATE",
+        " FOR NO KEY UPDATE",
+        " FOR SHARE",
+        " FOR KEY SHARE",
+        " INTO ",
+        "NEXTVAL",
+        "SETVAL",
+        "PG_ADVISORY",
+    ];
+    let normalized = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    !UNSAFE.iter().any(|kw| normalized.contains(kw))
+}
+
+/// The query hint that routes a read-only query to the primary,
+/// for reads that must observe the latest writes.
+const PRIMARY_HINT: &str = "encore:primary";
+
+/// Reports whether the query's leading comments contain the primary hint.
+fn pins_primary(mut query: &str) -> bool {
+    loop {
+        query = query.trim_start();
+        let (comment, rest) = if let Some(rest) = query.strip_prefix("--") {
+            rest.split_once('\n').unwrap_or((rest, ""))
+        } else if let Some(rest) = query.strip_prefix("/*") {
+            rest.split_once("*/").unwrap_or((rest, ""))
+        } else {
+            return false;
+        };
+        if comment.split_whitespace().any(|word| word == PRIMARY_HINT) {
+            return true;
+        }
+        query = rest;
+    }
+}
+
+fn strip_leading_comments(mut query: &str) -> &str {
+    loop {
+        query = query.trim_start();
+        if let Some(rest) = query.strip_prefix("--") {
+            query = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(rest) = query.strip_prefix("/*") {
+            query = rest.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            return query;
+        }
     }
 }
 
 pub struct Cursor {
-    stream: Pin<Box<tokio_postgres::RowStream>>,
+    inner: CursorInner,
+}
+
+enum CursorInner {
+    Live {
+        stream: Pin<Box<tokio_postgres::RowStream>>,
+        deadline: Option<Deadline>,
+        recording: Option<sqldb::replay::Recording>,
+        /// The connection the query runs on, if owned by the cursor.
+        _conn: Option<Lease>,
+    },
+    Replayed(std::vec::IntoIter<HashMap<String, RowValue>>),
 }
 
 impl Cursor {
+    pub(crate) fn new(stream: tokio_postgres::RowStream, deadline: Option<Deadline>) -> Self {
+        Self {
+            inner: CursorInner::Live {
+                stream: Box::pin(stream),
+                deadline,
+                recording: None,
+                _conn: None,
+            },
+        }
+    }
+
+    /// Keeps the connection checked out until the cursor is dropped.
+    fn holding(mut self, conn: Lease) -> Self {
+        if let CursorInner::Live { _conn, .. } = &mut self.inner {
+            *_conn = Some(conn);
+        }
+        self
+    }
+
+    /// Returns a cursor over previously recorded rows.
+    pub(crate) fn replayed(rows: Vec<HashMap<String, RowValue>>) -> Self {
+        Self {
+            inner: CursorInner::Replayed(rows.into_iter()),
+        }
+    }
+
+    /// Records the rows as they're read.
+    pub(super) fn record(mut self, cassette: &'static replay::Cassette, key: String) -> Self {
+        if let CursorInner::Live { recording, .. } = &mut self.inner {
+            *recording = Some(sqldb::replay::Recording::new(cassette, key));
+        }
+        self
+    }
+
     pub async fn next(&mut self) -> Option<Result<Row, tokio_postgres::Error>> {
-        match self.stream.next().await {
-            Some(Ok(row)) => Some(Ok(Row { row })),
-            Some(Err(err)) => Some(Err(err)),
+        let (stream, deadline, recording) = match &mut self.inner {
+            CursorInner::Live {
+                stream,
+                deadline,
+                recording,
+                ..
+            } => (stream, deadline, recording),
+            CursorInner::Replayed(rows) => {
+                return rows.next().map(|values| {
+                    Ok(Row {
+                        row: RowData::Replayed(values),
+                    })
+                });
+            }
+        };
+
+        // The deadline also applies while streaming rows,
+        // as the query keeps running on the server until all rows are sent.
+        let next = match deadline {
+            Some(deadline) => deadline.run(stream.next()).await,
+            None => stream.next().await,
+        };
+        match next {
+            Some(Ok(row)) => {
+                let row = Row {
+                    row: RowData::Live(row),
+                };
+                if let Some(recording) = recording {
+                    recording.row(row.values());
+                }
+                Some(Ok(row))
+            }
+            Some(Err(err)) => {
+                if let Some(recording) = recording {
+                    recording.fail(err.to_string());
+                }
+                Some(Err(err))
+            }
             None => None,
         }
     }
 }
 
 pub struct Row {
-    row: tokio_postgres::Row,
+    row: RowData,
+}
+
+enum RowData {
+    Live(tokio_postgres::Row),
+    Replayed(HashMap<String, RowValue>),
 }
 
 impl Row {
     pub fn values(&self) -> anyhow::Result<HashMap<String, RowValue>> {
-        let cols = self.row.columns();
+        let row = match &self.row {
+            RowData::Live(row) => row,
+            RowData::Replayed(values) => return Ok(values.clone()),
+        };
+        let cols = row.columns();
         let mut map = HashMap::with_capacity(cols.len());
         for (i, col) in cols.iter().enumerate() {
             let name = col.name().to_string();
-            let value: RowValue = self
-                .row
+            let value: RowValue = row
                 .try_get(i)
                 .map_err(|e| anyhow::anyhow!("unable to parse column {}: {:#?}", name, e))?;
             map.insert(name, value);
@@ -145,11 +540,41 @@ impl Row {
     }
 }
 
-pub(crate) type PooledConn =
-    PooledConnection<'static, PostgresConnectionManager<postgres_native_tls::MakeTlsConnector>>;
+pub(crate) type PooledConn = PooledConnection<'static, Mgr>;
+
+/// A pooled connection checked out by a single owner.
+///
+/// Query deadlines created through the lease stop cancelling queries once it's
+/// dropped, as the released connection may be running someone else's query.
+pub(crate) struct Lease {
+    conn: PooledConn,
+    held: Arc<()>,
+}
+
+impl Lease {
+    pub fn new(conn: PooledConn) -> Self {
+        Self {
+            conn,
+            held: Arc::new(()),
+        }
+    }
+
+    /// Returns the deadline for a query executed on behalf of the given request.
+    pub fn deadline(&self, source: Option<&model::Request>) -> Option<Deadline> {
+        self.conn.deadline(source, Arc::downgrade(&self.held))
+    }
+}
+
+impl std::ops::Deref for Lease {
+    type Target = Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conn
+    }
+}
 
 pub struct Connection {
-    conn: tokio::sync::RwLock<Option<PooledConn>>,
+    conn: tokio::sync::RwLock<Option<Lease>>,
     tracer: QueryTracer,
 }
 
@@ -172,13 +597,20 @@ impl Connection {
         I: IntoIterator<Item = P>,
         I::IntoIter: ExactSizeIterator,
     {
+        let params: Vec<P> = params.into_iter().collect();
+        let key = sqldb::replay::query_key(self.tracer.database(), query, &params);
         self.tracer
-            .trace(source, query, || async {
-                let guard = self.conn.read().await;
-                let Some(conn) = guard.as_ref() else {
-                    return Err(Error::Closed);
-                };
-                conn.query_raw(query, params).await.map_err(Error::from)
+            .trace(source, query, || {
+                sqldb::replay::query(key, async {
+                    let guard = self.conn.read().await;
+                    let Some(conn) = guard.as_ref() else {
+                        return Err(Error::Closed);
+                    };
+                    let deadline = conn.deadline(source);
+                    let params = params.iter().map(|p| p.borrow_to_sql());
+                    let stream = conn.query_raw(query, params, deadline.as_ref()).await?;
+                    Ok(Cursor::new(stream, deadline))
+                })
             })
             .await
     }
@@ -189,6 +621,10 @@ pub enum Error {
     DB(tokio_postgres::Error),
     Closed,
     ConnectTimeout,
+    SavepointReleased,
+    Injected(faults::Fault),
+    /// A replayed error, or a failure to replay.
+    Replay(String),
 }
 
 impl std::fmt::Display for Error {
@@ -197,6 +633,9 @@ impl std::fmt::Display for Error {
             Error::DB(err) => <tokio_postgres::Error as std::fmt::Display>::fmt(err, f),
             Error::Closed => f.write_str("connection_closed"),
             Error::ConnectTimeout => f.write_str("timeout establishing connection"),
+            Error::SavepointReleased => f.write_str("nested transaction already completed"),
+            Error::Injected(fault) => std::fmt::Display::fmt(fault, f),
+            Error::Replay(msg) => f.write_str(msg),
         }
     }
 }
@@ -207,10 +646,46 @@ impl From<tokio_postgres::Error> for Error {
     }
 }
 
+/// Traces queries and records their metrics.
 #[derive(Debug, Clone)]
-pub(crate) struct QueryTracer(Tracer);
+pub(crate) struct QueryTracer {
+    tracer: Tracer,
+    database: Arc<str>,
+    metrics: Option<Arc<QueryMetrics>>,
+}
 
 impl QueryTracer {
+    pub(crate) fn new(tracer: Tracer, database: &str, metrics: Option<Arc<QueryMetrics>>) -> Self {
+        Self {
+            tracer,
+            database: database.into(),
+            metrics,
+        }
+    }
+
+    /// The name of the database the queries run against.
+    pub(crate) fn database(&self) -> &str {
+        &self.database
+    }
+
+    fn record<T>(
+        &self,
+        source: Option<&model::Request>,
+        query: &str,
+        start: Instant,
+        result: &Result<T, Error>,
+    ) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record(
+                &self.database,
+                source,
+                query,
+                result.is_ok(),
+                start.elapsed(),
+            );
+        }
+    }
+
     pub(crate) async fn trace<F, Fut>(
         &self,
         source: Option<&model::Request>,
@@ -219,31 +694,33 @@ impl QueryTracer {
     ) -> Result<Cursor, Error>
     where
         F: FnOnce() -> Fut,
-        Fut: Future<Output = Result<tokio_postgres::RowStream, Error>>,
+        Fut: Future<Output = Result<Cursor, Error>>,
     {
         let start_id = if let Some(source) = source {
             let id = self
-                .0
+                .tracer
                 .db_query_start(protocol::DBQueryStartData { source, query });
             Some(id)
         } else {
             None
         };
 
-        let result = exec().await;
+        let start = Instant::now();
+        let result = match faults::inject(faults::Target::Sql).await {
+            Ok(()) => exec().await,
+            Err(fault) => Err(Error::Injected(fault)),
+        };
+        self.record(source, query, start, &result);
 
         if let Some(start_id) = start_id {
-            self.0.db_query_end(protocol::DBQueryEndData {
+            self.tracer.db_query_end(protocol::DBQueryEndData {
                 start_id,
                 source: source.unwrap(),
                 error: result.as_ref().err(),
             });
         }
 
-        let stream = result?;
-        Ok(Cursor {
-            stream: Box::pin(stream),
-        })
+        result
     }
 
     pub(crate) async fn trace_batch_execute<F, Fut>(
@@ -258,17 +735,22 @@ impl QueryTracer {
     {
         let start_id = if let Some(source) = source {
             let id = self
-                .0
+                .tracer
                 .db_query_start(protocol::DBQueryStartData { source, query });
             Some(id)
         } else {
             None
         };
 
-        let result = exec().await;
+        let start = Instant::now();
+        let result = match faults::inject(faults::Target::Sql).await {
+            Ok(()) => exec().await,
+            Err(fault) => Err(Error::Injected(fault)),
+        };
+        self.record(source, query, start, &result);
 
         if let Some(start_id) = start_id {
-            self.0.db_query_end(protocol::DBQueryEndData {
+            self.tracer.db_query_end(protocol::DBQueryEndData {
                 start_id,
                 source: source.unwrap(),
                 error: result.as_ref().err(),
@@ -278,3 +760,52 @@ impl QueryTracer {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_queries() {
+        assert!(is_read_only("SELECT * FROM users WHERE id = $1"));
+        assert!(is_read_only("  select id\nfrom users"));
+        assert!(is_read_only("-- find user\n/* by id */ SELECT 1"));
+        assert!(is_read_only("SHOW search_path"));
+
+        assert!(!is_read_only("INSERT INTO users (id) VALUES ($1)"));
+        assert!(!is_read_only(
+            "WITH d AS (DELETE FROM users RETURNING *) SELECT * FROM d"
+        ));
+        assert!(!is_read_only("SELECT * FROM users FOR\nUPDATE"));
+        assert!(!is_read_only("SELECT * INTO archive FROM users"));
+        assert!(!is_read_only("SELECT nextval('users_id_seq')"));
+        assert!(!is_read_only("SELECTED"));
+    }
+
+    #[test]
+    fn retry_backoff_bounds() {
+        for attempt in 1..MAX_QUERY_ATTEMPTS {
+            let max = Duration::from_millis(10 << attempt);
+            let backoff = retry_backoff(attempt);
+            assert!(backoff >= max / 2 && backoff <= max, "{backoff:?}");
+        }
+        assert!(retry_backoff(30) <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn primary_hint() {
+        assert!(pins_primary("/* encore:primary */ SELECT 1"));
+        assert!(pins_primary("-- by id\n-- encore:primary\nSELECT 1"));
+        assert!(!pins_primary("SELECT 1"));
+        assert!(!pins_primary("SELECT 1 /* encore:primary */"));
+        assert!(!pins_primary("/* encore:primary-ish */ SELECT 1"));
+    }
+
+    #[test]
+    fn writing_function_pinned_with_hint() {
+        // Function calls aren't inspected, so only the hint keeps this off the replicas.
+        let query = "/* encore:primary */ SELECT archive_order($1)";
+        assert!(is_read_only(query));
+        assert!(pins_primary(query));
+    }
+}
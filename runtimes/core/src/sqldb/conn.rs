@@ -0,0 +1,406 @@
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bb8_postgres::PostgresConnectionManager;
+use futures_util::future::{self, BoxFuture};
+use futures_util::FutureExt;
+use lru::LruCache;
+use postgres_native_tls::MakeTlsConnector;
+use tokio::time::Instant;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::BorrowToSql;
+use tokio_postgres::{CancelToken, RowStream, Statement};
+
+use crate::iamauth::IamToken;
+use crate::model;
+
+use super::isolation;
+
+type Inner = PostgresConnectionManager<MakeTlsConnector>;
+
+/// Manages database connections that cache their prepared statements.
+pub(crate) struct Mgr {
+    inner: Inner,
+    config: tokio_postgres::Config,
+    tls: MakeTlsConnector,
+    iam: Option<Arc<IamToken>>,
+    stmt_cache_size: Option<NonZeroUsize>,
+    query_timeout: Option<Duration>,
+    session_setup: Option<String>,
+    test_isolation: bool,
+    /// The session shared by all connections when isolating tests.
+    shared: tokio::sync::OnceCell<Arc<tokio_postgres::Client>>,
+    /// The pending savepoint release of the session's last query outside of a transaction.
+    pending_release: Arc<tokio::sync::Mutex<Option<PendingRelease>>>,
+}
+
+impl Mgr {
+    pub fn new(
+        config: tokio_postgres::Config,
+        tls: MakeTlsConnector,
+        stmt_cache_size: usize,
+        query_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            inner: Inner::new(config.clone(), tls.clone()),
+            config,
+            tls,
+            iam: None,
+            stmt_cache_size: NonZeroUsize::new(stmt_cache_size),
+            query_timeout,
+            session_setup: None,
+            test_isolation: false,
+            shared: tokio::sync::OnceCell::new(),
+            pending_release: Arc::default(),
+        }
+    }
+
+    /// Executes the given statements on each new connection.
+    pub fn with_session_setup(mut self, sql: Option<String>) -> Self {
+        self.session_setup = sql;
+        self
+    }
+
+    /// Authenticates new connections with IAM tokens instead of the configured password.
+    pub fn with_iam(mut self, iam: Option<Arc<IamToken>>) -> Self {
+        self.iam = iam;
+        self
+    }
+
+    /// Connects using a current IAM token as the password.
+    async fn connect_iam(
+        &self,
+        iam: &IamToken,
+    ) -> Result<tokio_postgres::Client, tokio_postgres::Error> {
+        let token = iam.token().await.map_err(|err| {
+            log::error!("unable to generate IAM authentication token: {:#}", err);
+            tokio_postgres::Error::__private_api_timeout()
+        })?;
+        let mut config = self.config.clone();
+        config.password(token);
+        let (client, conn) = config.connect(self.tls.clone()).await?;
+        tokio::spawn(conn);
+        Ok(client)
+    }
+
+    /// Connects to the database and sets up the session.
+    async fn connect_session(&self) -> Result<tokio_postgres::Client, tokio_postgres::Error> {
+        let client = match &self.iam {
+            Some(iam) => self.connect_iam(iam).await?,
+            None => self.inner.connect().await?,
+        };
+        if let Some(sql) = &self.session_setup {
+            client.batch_execute(sql).await?;
+        }
+        Ok(client)
+    }
+
+    /// Runs all connections on a single session inside a transaction, for isolating tests.
+    ///
+    /// As the session is shared, queries see the changes made by the test,
+    /// including those made in transactions that haven't been committed yet.
+    pub fn isolated(mut self) -> Self {
+        self.test_isolation = true;
+        self
+    }
+}
+
+#[async_trait]
+impl bb8::ManageConnection for Mgr {
+    type Connection = Client;
+    type Error = tokio_postgres::Error;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let client = if self.test_isolation {
+            let shared = self.shared.get_or_try_init(|| async {
+                let client = self.connect_session().await?;
+                client.batch_execute("BEGIN").await?;
+                Ok::<_, tokio_postgres::Error>(Arc::new(client))
+            });
+            shared.await?.clone()
+        } else {
+            Arc::new(self.connect_session().await?)
+        };
+        Ok(Client {
+            canceller: Canceller {
+                token: client.cancel_token(),
+                tls: self.tls.clone(),
+            },
+            client,
+            stmts: self
+                .stmt_cache_size
+                .map(|size| Mutex::new(LruCache::new(size))),
+            query_timeout: self.query_timeout,
+            test_isolation: self.test_isolation,
+            pending_release: self.pending_release.clone(),
+        })
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        conn.client.simple_query("").await.map(|_| ())
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.client.is_closed()
+    }
+}
+
+/// A database connection with a cache of prepared statements.
+///
+/// Statements are cached per connection, as prepared statements
+/// are only valid for the connection that prepared them.
+pub(crate) struct Client {
+    client: Arc<tokio_postgres::Client>,
+    stmts: Option<Mutex<LruCache<String, Statement>>>,
+    query_timeout: Option<Duration>,
+    canceller: Canceller,
+    /// Whether the connection runs in a transaction that isolates tests.
+    test_isolation: bool,
+    /// The pending savepoint release of the session's last query outside of a transaction,
+    /// shared by all connections to the session.
+    pending_release: Arc<tokio::sync::Mutex<Option<PendingRelease>>>,
+}
+
+/// The release of a query's savepoint, whose response arrives once the query's rows are read.
+struct PendingRelease {
+    savepoint: String,
+    response: BoxFuture<'static, Result<(), tokio_postgres::Error>>,
+}
+
+impl Deref for Client {
+    type Target = tokio_postgres::Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl Client {
+    /// Reports whether the connection runs in a transaction that isolates tests,
+    /// in which case transactions are emulated with savepoints.
+    pub fn is_test_isolated(&self) -> bool {
+        self.test_isolation
+    }
+
+    /// Returns the deadline for a query executed on behalf of the given request.
+    ///
+    /// This is the earliest of the database's default query timeout
+    /// and the request's own deadline, if any.
+    ///
+    /// The deadline only cancels queries while `held` is alive, as once the
+    /// connection is released to the pool it may be running someone else's query.
+    pub fn deadline(&self, source: Option<&model::Request>, held: Weak<()>) -> Option<Deadline> {
+        let now = Instant::now();
+        let timeout = self.query_timeout.map(|t| now + t);
+        let requested = source.and_then(|r| r.deadline).map(|d| {
+            now + d
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        });
+        let at = [timeout, requested].into_iter().flatten().min()?;
+        Some(Deadline {
+            at,
+            canceller: self.canceller.clone(),
+            held,
+        })
+    }
+
+    /// Executes the query, using a cached prepared statement if possible.
+    /// If the deadline passes before the query completes the query is cancelled.
+    ///
+    /// When isolating tests the query runs in a savepoint, so that a failing
+    /// query doesn't abort the transaction the test runs in.
+    pub async fn query_raw<P, I>(
+        &self,
+        query: &str,
+        params: I,
+        deadline: Option<&Deadline>,
+    ) -> Result<RowStream, tokio_postgres::Error>
+    where
+        P: BorrowToSql,
+        I: IntoIterator<Item = P>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        if !self.test_isolation {
+            return self.query_in_transaction(query, params, deadline).await;
+        }
+
+        // Queries run one at a time, so that their savepoints don't interleave.
+        let mut pending = self.pending_release.lock().await;
+        self.settle_release(&mut pending).await?;
+        let sp = isolation::savepoint_name(isolation::STMT_SAVEPOINT);
+        self.client
+            .batch_execute(&format!("SAVEPOINT {sp}"))
+            .await?;
+        match self.query_in_transaction(query, params, deadline).await {
+            Ok(stream) => {
+                // Statements run in order, so the savepoint is released once the query
+                // completes. Polling the release once sends it, but its response only
+                // arrives once the rows have been read, so it's awaited before the next query.
+                let client = self.client.clone();
+                let release = format!("RELEASE SAVEPOINT {sp}");
+                let mut response = async move { client.batch_execute(&release).await }.boxed();
+                if let Some(result) = (&mut response).now_or_never() {
+                    response = future::ready(result).boxed();
+                }
+                *pending = Some(PendingRelease {
+                    savepoint: sp,
+                    response,
+                });
+                Ok(stream)
+            }
+            Err(err) => {
+                let stmt = format!("ROLLBACK TO SAVEPOINT {sp}; RELEASE SAVEPOINT {sp}");
+                self.client.batch_execute(&stmt).await?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Executes the query like [`Client::query_raw`], but as part of an app transaction
+    /// whose failure aborts it, even when isolating tests.
+    pub async fn query_in_transaction<P, I>(
+        &self,
+        query: &str,
+        params: I,
+        deadline: Option<&Deadline>,
+    ) -> Result<RowStream, tokio_postgres::Error>
+    where
+        P: BorrowToSql,
+        I: IntoIterator<Item = P>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        match deadline {
+            Some(deadline) => deadline.run(self.query_cached(query, params)).await,
+            None => self.query_cached(query, params).await,
+        }
+    }
+
+    /// Creates a savepoint in the transaction the test runs in,
+    /// once the savepoint of the last query outside of a transaction is released.
+    pub async fn isolated_savepoint(&self, name: &str) -> Result<(), tokio_postgres::Error> {
+        let mut pending = self.pending_release.lock().await;
+        self.settle_release(&mut pending).await?;
+        self.client
+            .batch_execute(&format!("SAVEPOINT {name}"))
+            .await
+    }
+
+    /// Rolls back the transaction the test runs in and begins a new one.
+    pub async fn reset_isolated(&self) -> Result<(), tokio_postgres::Error> {
+        let mut pending = self.pending_release.lock().await;
+        // The pending release's savepoint is rolled back along with the transaction.
+        *pending = None;
+        self.client.batch_execute("ROLLBACK; BEGIN").await
+    }
+
+    /// Awaits the release of the last query's savepoint.
+    ///
+    /// If the query failed while streaming rows, the transaction was aborted and
+    /// the release fails. The savepoint is rolled back to instead.
+    async fn settle_release(
+        &self,
+        pending: &mut Option<PendingRelease>,
+    ) -> Result<(), tokio_postgres::Error> {
+        let Some(PendingRelease {
+            savepoint: sp,
+            response,
+        }) = pending.take()
+        else {
+            return Ok(());
+        };
+        if let Err(err) = response.await {
+            if err.code() != Some(&SqlState::IN_FAILED_SQL_TRANSACTION) {
+                return Err(err);
+            }
+            let recover = format!("ROLLBACK TO SAVEPOINT {sp}; RELEASE SAVEPOINT {sp}");
+            self.client.batch_execute(&recover).await?;
+        }
+        Ok(())
+    }
+
+    async fn query_cached<P, I>(
+        &self,
+        query: &str,
+        params: I,
+    ) -> Result<RowStream, tokio_postgres::Error>
+    where
+        P: BorrowToSql,
+        I: IntoIterator<Item = P>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let Some(stmts) = &self.stmts else {
+            return self.client.query_raw(query, params).await;
+        };
+
+        let cached = stmts.lock().unwrap().get(query).cloned();
+        let stmt = match cached {
+            Some(stmt) => stmt,
+            None => {
+                let stmt = self.client.prepare(query).await?;
+                stmts.lock().unwrap().put(query.to_string(), stmt.clone());
+                stmt
+            }
+        };
+
+        let result = self.client.query_raw(&stmt, params).await;
+        if let Err(err) = &result {
+            // If the schema changed since the statement was prepared, the statement
+            // can no longer be used. Evict it so it's prepared again next time.
+            if err.code() == Some(&SqlState::FEATURE_NOT_SUPPORTED) {
+                stmts.lock().unwrap().pop(query);
+            }
+        }
+        result
+    }
+}
+
+/// Cancels the query running on a connection.
+#[derive(Clone)]
+struct Canceller {
+    token: CancelToken,
+    tls: MakeTlsConnector,
+}
+
+impl Canceller {
+    fn cancel(&self) {
+        let token = self.token.clone();
+        let tls = self.tls.clone();
+        tokio::spawn(async move {
+            if let Err(err) = token.cancel_query(tls).await {
+                log::error!("unable to cancel query: {}", err);
+            }
+        });
+    }
+}
+
+/// The time by which a query must complete.
+#[derive(Clone)]
+pub(crate) struct Deadline {
+    at: Instant,
+    canceller: Canceller,
+    held: Weak<()>,
+}
+
+impl Deadline {
+    /// Awaits the future, cancelling the running query if the deadline passes first.
+    ///
+    /// Cancellation is requested out-of-band, after which the future completes
+    /// with the error reported by the database for the cancelled query.
+    pub async fn run<F: Future>(&self, fut: F) -> F::Output {
+        tokio::pin!(fut);
+        tokio::select! {
+            out = &mut fut => return out,
+            _ = tokio::time::sleep_until(self.at) => {}
+        }
+        if self.held.strong_count() > 0 {
+            log::debug!("query deadline exceeded, cancelling query");
+            self.canceller.cancel();
+        }
+        fut.await
+    }
+}
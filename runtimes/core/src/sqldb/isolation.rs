@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+use super::client::PooledConn;
+use super::conn::Mgr;
+use super::manager::DatabaseImpl;
+
+/// The prefix of the savepoints that emulate transactions begun while isolating tests.
+pub(super) const TX_SAVEPOINT: &str = "encore_test_tx";
+
+/// The prefix of the savepoint each query outside of a transaction runs in while
+/// isolating tests, so that a failing query doesn't abort the test's transaction.
+pub(super) const STMT_SAVEPOINT: &str = "encore_test_stmt";
+
+/// Returns a savepoint name with the given prefix that's unique within the process.
+///
+/// Savepoints of concurrent transactions share the test's session, so a fixed
+/// name would release or roll back whichever savepoint was created last.
+pub(super) fn savepoint_name(prefix: &str) -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    format!("{prefix}_{}", NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Isolates the database state of tests.
+///
+/// All queries against the database run on a single session, inside a
+/// transaction that's rolled back and begun anew when the state is reset
+/// between tests. Transactions begun by the app become savepoints, and other
+/// queries run in savepoints of their own that are rolled back if they fail.
+///
+/// Connections made through the database proxy, such as by ORMs,
+/// are not isolated.
+#[derive(Default)]
+pub(crate) struct TestIsolation {
+    pool: OnceLock<bb8::Pool<Mgr>>,
+}
+
+impl TestIsolation {
+    /// Returns the pool with the isolated connection.
+    pub fn pool(&self, db: &DatabaseImpl) -> bb8::Pool<Mgr> {
+        self.pool
+            .get_or_init(|| {
+                let mgr = db.conn_manager().isolated();
+                // The connections share a session, so every query sees the test's changes.
+                // There are several so queries can run while a transaction is open.
+                bb8::Pool::builder().build_unchecked(mgr)
+            })
+            .clone()
+    }
+
+    /// Rolls back the changes made since the last reset.
+    pub async fn reset(&self) -> Result<(), tokio_postgres::Error> {
+        let Some(pool) = self.pool.get() else {
+            // Nothing has been queried yet.
+            return Ok(());
+        };
+        let conn: PooledConn = pool.get_owned().await.map_err(|e| match e {
+            bb8::RunError::User(err) => err,
+            bb8::RunError::TimedOut => tokio_postgres::Error::__private_api_timeout(),
+        })?;
+        conn.reset_isolated().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_savepoint_names() {
+        let a = savepoint_name(TX_SAVEPOINT);
+        let b = savepoint_name(TX_SAVEPOINT);
+        assert!(a.starts_with("encore_test_tx_"), "{a}");
+        assert_ne!(a, b);
+    }
+}
@@ -2,21 +2,38 @@ use anyhow::Context;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
 use tokio_postgres::proxy;
+use tokio_util::sync::CancellationToken;
 
 use tokio_postgres::proxy::{AcceptConn, AuthMethod, ClientBouncer, RejectConn};
 
 use crate::encore::runtime::v1 as pb;
+use crate::iamauth::IamToken;
 use crate::names::EncoreName;
 use crate::secrets;
+use crate::sqldb::isolation::TestIsolation;
 use crate::sqldb::Pool;
+use crate::startup;
 use crate::trace::Tracer;
 
+use super::client::QueryTracer;
+use super::conn::Mgr;
+use super::metrics::QueryMetrics;
+use super::mysql::{self, MySqlDatabase};
+
 pub struct Manager {
     databases: Arc<HashMap<EncoreName, Arc<DatabaseImpl>>>,
+    mysql_databases: HashMap<EncoreName, Arc<MySqlDatabase>>,
     proxy_port: u16,
     listener: Mutex<Option<std::net::TcpListener>>,
     runtime: tokio::runtime::Handle,
+
+    /// Cancelled to stop the proxy and close its connections.
+    shutdown: CancellationToken,
+    /// Whether the proxy is currently running.
+    serving: watch::Sender<bool>,
 }
 
 pub struct ManagerConfig<'a> {
@@ -24,7 +41,11 @@ pub struct ManagerConfig<'a> {
     pub creds: &'a pb::infrastructure::Credentials,
     pub secrets: &'a secrets::Manager,
     pub tracer: Tracer,
+    /// Records per-query metrics, if enabled.
+    pub query_metrics: Option<Arc<QueryMetrics>>,
     pub runtime: tokio::runtime::Handle,
+    /// Whether to isolate the database state of tests.
+    pub test_isolation: bool,
 }
 
 impl ManagerConfig<'_> {
@@ -37,27 +58,58 @@ impl ManagerConfig<'_> {
             .context("unable to get local address")?
             .port();
 
+        let (mysql_clusters, clusters) = self
+            .clusters
+            .into_iter()
+            .partition::<Vec<_>, _>(|c| c.engine() == pb::sql_cluster::Engine::Mysql);
+        let mut mysql_databases = HashMap::new();
+        for c in &mysql_clusters {
+            let dbs = mysql::databases_from_cluster(c, self.creds, self.secrets)
+                .context("failed to parse MySQL clusters")?;
+            for db in dbs {
+                mysql_databases.insert(db.name().clone(), Arc::new(db));
+            }
+        }
+
         let databases = databases_from_cfg(
-            self.clusters,
+            clusters,
             self.creds,
             self.secrets,
             proxy_port,
             self.tracer,
+            self.query_metrics,
+            self.test_isolation,
         )
         .context("failed to parse SQL clusters")?;
         let databases = Arc::new(databases);
 
+        // Keep IAM tokens fresh for connections made through the proxy,
+        // which can't wait for a new token.
+        for db in databases.values() {
+            let replicas = db.replica.iter().flat_map(|r| &r.servers);
+            let tokens = db.iam.iter().chain(replicas.filter_map(|s| s.iam.as_ref()));
+            for iam in tokens {
+                self.runtime.spawn(iam.clone().refresh());
+            }
+        }
+
         Ok(Manager {
             databases,
+            mysql_databases,
             proxy_port,
             runtime: self.runtime,
             listener: Mutex::new(Some(listener)),
+            shutdown: CancellationToken::new(),
+            serving: watch::channel(false).0,
         })
     }
 }
 
 impl Manager {
     pub fn database(&self, name: &EncoreName) -> Arc<dyn Database> {
+        if let Some(db) = self.mysql_databases.get(name) {
+            return db.clone();
+        }
         match self.databases.get(name) {
             Some(db) => db.clone(),
             None => {
@@ -76,8 +128,19 @@ impl Manager {
         });
 
         let listener = self.listener.lock().unwrap().take();
-        let runtime = self.runtime.clone();
+        let shutdown = self.shutdown.clone();
+        let serving = self.serving.clone();
+        serving.send_replace(true);
         self.runtime.spawn(async move {
+            // Mark the proxy as stopped when this task completes, however it completes.
+            struct StoppedGuard(watch::Sender<bool>);
+            impl Drop for StoppedGuard {
+                fn drop(&mut self) {
+                    self.0.send_replace(false);
+                }
+            }
+            let _stopped = StoppedGuard(serving);
+
             let listener = listener.context("sqldb server already started")?;
             listener
                 .set_nonblocking(true)
@@ -92,13 +155,68 @@ impl Manager {
 
             log::debug!(addr=addr; "encore runtime database proxy listening for incoming requests");
 
+            let mut conns = tokio::task::JoinSet::new();
             loop {
-                let (stream, _) = listener.accept().await?;
-                let mgr = manager.clone();
-                runtime.spawn(mgr.handle_conn(stream));
+                tokio::select! {
+                    res = listener.accept() => {
+                        let (stream, _) = res?;
+                        conns.spawn(manager.clone().handle_conn(stream));
+                    }
+                    // Reap completed connections.
+                    Some(_) = conns.join_next(), if !conns.is_empty() => {}
+                    _ = shutdown.cancelled() => {
+                        // Close all proxied connections, and with them the connections to the databases.
+                        conns.shutdown().await;
+                        return Ok(());
+                    }
+                }
             }
         })
     }
+
+    /// Returns the databases to wait for at startup.
+    pub fn startup_dependencies(&self) -> Vec<startup::Dependency> {
+        self.databases
+            .values()
+            .map(|db| {
+                let config = db.config.clone();
+                let tls = db.tls.clone();
+                let iam = db.iam.clone();
+                startup::Dependency::new(format!("sql database {}", db.name), move || {
+                    let mut config = (*config).clone();
+                    let tls = tls.clone();
+                    let iam = iam.clone();
+                    async move {
+                        if let Some(iam) = iam {
+                            config.password(iam.token().await?);
+                        }
+                        let (_client, _conn) = config.connect(tls).await?;
+                        Ok(())
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Rolls back the changes made to the databases since the last reset,
+    /// if isolating tests. Does nothing otherwise.
+    pub async fn reset_test_state(&self) -> anyhow::Result<()> {
+        for db in self.databases.values() {
+            if let Some(isolation) = &db.test_isolation {
+                isolation
+                    .reset()
+                    .await
+                    .with_context(|| format!("unable to reset database {}", db.name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops the database proxy and closes its connections.
+    pub async fn shutdown(&self) {
+        self.shutdown.cancel();
+        let _ = self.serving.subscribe().wait_for(|serving| !*serving).await;
+    }
 }
 
 pub trait Database: Send + Sync {
@@ -110,7 +228,8 @@ pub trait Database: Send + Sync {
     fn tls(&self) -> anyhow::Result<&postgres_native_tls::MakeTlsConnector>;
     fn new_pool(&self) -> anyhow::Result<Pool>;
 
-    /// Returns the connection string for connecting to this database via the proxy.
+    /// Returns the connection string for connecting to this database.
+    /// Postgres databases are connected to via the proxy.
     fn proxy_conn_string(&self) -> &str;
 }
 
@@ -119,17 +238,112 @@ pub struct DatabaseImpl {
     name: EncoreName,
     config: Arc<tokio_postgres::Config>,
     tls: postgres_native_tls::MakeTlsConnector,
+    /// Generates the password, if authenticating with IAM tokens.
+    iam: Option<Arc<IamToken>>,
     proxy_conn_string: String,
-    tracer: Tracer,
+    tracer: QueryTracer,
+    flavor: Flavor,
 
     min_conns: u32,
     max_conns: u32,
+    stmt_cache_size: usize,
+    query_timeout: Option<Duration>,
+    timeouts: PoolTimeouts,
+    session_setup: Option<String>,
+
+    /// The read replicas to route read-only queries to, if any.
+    replica: Option<ReplicaConfig>,
+
+    /// Set when isolating the database state of tests.
+    test_isolation: Option<TestIsolation>,
+}
+
+impl DatabaseImpl {
+    pub(super) fn conn_manager(&self) -> Mgr {
+        Mgr::new(
+            (*self.config).clone(),
+            self.tls.clone(),
+            self.stmt_cache_size,
+            self.query_timeout,
+        )
+        .with_session_setup(self.session_setup.clone())
+        .with_iam(self.iam.clone())
+    }
+
+    /// Returns the configuration for connecting to the database through the proxy,
+    /// with the most recent IAM token as the password if using IAM authentication.
+    fn backend_config(&self) -> Arc<tokio_postgres::Config> {
+        match self.iam.as_ref().and_then(|iam| iam.cached()) {
+            Some(token) => {
+                let mut config = (*self.config).clone();
+                config.password(token);
+                Arc::new(config)
+            }
+            None => self.config.clone(),
+        }
+    }
+}
+
+/// Describes how to connect to a database's read replicas.
+pub struct ReplicaConfig {
+    pub servers: Vec<ReplicaServer>,
+    pub routing: ReadRouting,
+    pub min_conns: u32,
+    pub max_conns: u32,
+    pub stmt_cache_size: usize,
+    pub query_timeout: Option<Duration>,
+    pub timeouts: PoolTimeouts,
+    pub session_setup: Option<String>,
+}
+
+/// The connection settings of a single read replica.
+pub struct ReplicaServer {
+    pub config: Arc<tokio_postgres::Config>,
+    pub tls: postgres_native_tls::MakeTlsConnector,
+    pub iam: Option<Arc<IamToken>>,
+}
+
+/// The Postgres-compatible database a server runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flavor {
+    #[default]
+    Postgres,
+    CockroachDb,
+}
+
+/// Determines which read-only queries are routed to the read replica.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadRouting {
+    /// All read-only queries are routed to the replica.
+    Replica,
+    /// Read-only queries are routed to the replica, unless the same
+    /// request has already written to the database.
+    Session,
 }
 
 #[derive(Debug, Clone)]
 pub struct PoolConfig {
     pub min_conns: u32,
     pub max_conns: u32,
+    pub stmt_cache_size: usize,
+    pub query_timeout: Option<Duration>,
+    pub timeouts: PoolTimeouts,
+    /// Statements to execute on each new connection, if any.
+    pub session_setup: Option<String>,
+    /// Generates the password, if authenticating with IAM tokens.
+    pub iam: Option<Arc<IamToken>>,
+}
+
+/// Timeouts for acquiring and recycling pooled connections.
+/// Unset timeouts use the pool's defaults.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolTimeouts {
+    /// How long to wait for a connection from the pool.
+    pub acquire: Option<Duration>,
+    /// How long a connection can be idle before it's closed.
+    pub idle: Option<Duration>,
+    /// How long a connection can be used before it's closed.
+    pub max_lifetime: Option<Duration>,
 }
 
 impl Database for DatabaseImpl {
@@ -141,6 +355,11 @@ impl Database for DatabaseImpl {
         Ok(PoolConfig {
             min_conns: self.min_conns,
             max_conns: self.max_conns,
+            stmt_cache_size: self.stmt_cache_size,
+            query_timeout: self.query_timeout,
+            timeouts: self.timeouts,
+            session_setup: self.session_setup.clone(),
+            iam: self.iam.clone(),
         })
     }
 
@@ -153,7 +372,18 @@ impl Database for DatabaseImpl {
     }
 
     fn new_pool(&self) -> anyhow::Result<Pool> {
-        Pool::new(self, self.tracer.clone())
+        if let Some(isolation) = &self.test_isolation {
+            let pool = isolation.pool(self);
+            return Ok(
+                Pool::isolated(&self.name, pool, self.tracer.clone()).with_flavor(self.flavor)
+            );
+        }
+
+        let pool = Pool::new(self, self.tracer.clone())?.with_flavor(self.flavor);
+        Ok(match &self.replica {
+            Some(replica) => pool.with_replica(&self.name, replica),
+            None => pool,
+        })
     }
 
     fn proxy_conn_string(&self) -> &str {
@@ -224,7 +454,7 @@ impl ClientBouncer for Bouncer {
             Ok(AcceptConn {
                 auth_method: AuthMethod::Trust,
                 tls: db.tls.clone(),
-                backend_config: db.config.clone(),
+                backend_config: db.backend_config(),
             })
         };
         futures::future::ready(resolve())
@@ -243,22 +473,40 @@ fn databases_from_cfg(
     secrets: &secrets::Manager,
     proxy_port: u16,
     tracer: Tracer,
+    query_metrics: Option<Arc<QueryMetrics>>,
+    test_isolation: bool,
 ) -> anyhow::Result<HashMap<EncoreName, Arc<DatabaseImpl>>> {
     let mut databases = HashMap::new();
     for c in clusters {
+        let flavor = match c.flavor() {
+            pb::sql_cluster::Flavor::Postgres => Flavor::Postgres,
+            pb::sql_cluster::Flavor::Cockroachdb => Flavor::CockroachDb,
+        };
+
         // Get the primary server.
         let server = c
             .servers
-            .into_iter()
+            .iter()
             .find(|s| s.kind() == pb::ServerKind::Primary);
         let Some(server) = server else {
             log::warn!("no primary server found for cluster {}, skipping", c.rid);
             continue;
         };
+        // Hot standbys serve reads too, until they're promoted.
+        let replica_servers: Vec<_> = c
+            .servers
+            .iter()
+            .filter(|s| {
+                matches!(
+                    s.kind(),
+                    pb::ServerKind::ReadReplica | pb::ServerKind::HotStandby
+                )
+            })
+            .collect();
 
         for db in c.databases {
             // Get the read-write pool for this db.
-            let pool = db.conn_pools.into_iter().find(|p| !p.is_readonly);
+            let pool = db.conn_pools.iter().find(|p| !p.is_readonly);
             let Some(pool) = pool else {
                 log::warn!(
                     "no read-write pool found for database {}, skipping",
@@ -266,114 +514,87 @@ fn databases_from_cfg(
                 );
                 continue;
             };
-
-            // Get the role to authenticate with.
-            let role = creds
-                .sql_roles
-                .iter()
-                .find(|r| r.rid == pool.role_rid)
-                .with_context(|| {
-                    format!(
-                        "no role found with rid {} for database {}",
-                        pool.role_rid, db.encore_name
-                    )
-                })?;
-
-            let mut config = tokio_postgres::Config::new();
-
-            // Add host/port configuration
-            if server.host.starts_with('/') {
-                // Unix socket
-                config.host(&server.host);
-            } else if let Some((host, port)) = server.host.split_once(':') {
-                config.host(host);
-                config.port(port.parse::<u16>().context("invalid port")?);
-            } else {
-                config.host(&server.host);
-                config.port(5432);
-            }
-
-            config.user(&role.username);
-            if let Some(password) = &role.password {
-                let sec = secrets.load(password.clone());
-                let password = sec.get().context("failed to resolve password")?;
-                config.password(password);
-            }
-
-            config.dbname(&db.cloud_name);
-            config.application_name("encore");
-
-            let mut tls_builder = native_tls::TlsConnector::builder();
-            if let Some(tls_config) = &server.tls_config {
-                if let Some(server_ca_cert) = &tls_config.server_ca_cert {
-                    let cert = native_tls::Certificate::from_pem(server_ca_cert.as_bytes())
-                        .context("unable to parse server ca certificate")?;
-                    tls_builder.add_root_certificate(cert);
-                    config.ssl_mode(tokio_postgres::config::SslMode::Require);
-                } else {
-                    config.ssl_mode(tokio_postgres::config::SslMode::Prefer);
-                }
-
-                if tls_config.disable_tls_hostname_verification {
-                    tls_builder.danger_accept_invalid_hostnames(true);
-                }
-                if tls_config.disable_ca_validation {
-                    tls_builder.danger_accept_invalid_certs(true);
+            check_pooler_mode(&db, pool);
+            let (config, tls, iam) = conn_config(server, pool, &db, creds, secrets)?;
+            let query_timeout = duration(db.query_timeout);
+
+            let read_routing = db.read_routing();
+            let replica = match (read_routing, replica_servers.as_slice()) {
+                (pb::sql_database::ReadRouting::Primary, _) => None,
+                (_, []) => {
+                    log::warn!(
+                        "no read replica found for database {}, routing all queries to the primary",
+                        db.encore_name
+                    );
+                    None
                 }
-            } else {
-                config.ssl_mode(tokio_postgres::config::SslMode::Disable);
-            }
-
-            if let Some(client_cert_rid) = &role.client_cert_rid {
-                // Add a client certificate.
-                let client_cert = creds
-                    .client_certs
-                    .iter()
-                    .find(|c| c.rid == *client_cert_rid)
-                    .with_context(|| {
-                        format!(
-                            "no client certificate found with rid {} for database {}",
-                            client_cert_rid, db.encore_name
-                        )
-                    })?;
-
-                // Parse the client key secret.
-                let client_key = client_cert
-                    .key
-                    .as_ref()
-                    .context("client certificate has no key")?;
-                let client_key = secrets.load(client_key.clone());
-                let client_key = client_key.get().context("failed to resolve client key")?;
-
-                let client_key = convert_client_key_if_necessary(client_key)
-                    .context("failed to convert client key to PKCS#8")?;
-                let identity = native_tls::Identity::from_pkcs8(
-                    client_cert.cert.as_bytes(),
-                    client_key.as_ref(),
-                )
-                .context("failed to parse client certificate")?;
-                tls_builder.identity(identity);
-            }
-
-            let tls = tls_builder
-                .build()
-                .context("failed to build TLS connector")?;
-            let tls = postgres_native_tls::MakeTlsConnector::new(tls);
+                (_, replica_servers) => match db.conn_pools.iter().find(|p| p.is_readonly) {
+                    Some(pool) => {
+                        let servers = replica_servers
+                            .iter()
+                            .map(|server| {
+                                let (config, tls, iam) =
+                                    conn_config(server, pool, &db, creds, secrets)?;
+                                Ok(ReplicaServer {
+                                    config: Arc::new(config),
+                                    tls,
+                                    iam,
+                                })
+                            })
+                            .collect::<anyhow::Result<_>>()?;
+                        Some(ReplicaConfig {
+                            servers,
+                            routing: if read_routing == pb::sql_database::ReadRouting::Session {
+                                ReadRouting::Session
+                            } else {
+                                ReadRouting::Replica
+                            },
+                            min_conns: pool.min_connections as u32,
+                            max_conns: pool.max_connections as u32,
+                            stmt_cache_size: stmt_cache_size(&db, pool),
+                            query_timeout,
+                            timeouts: pool_timeouts(pool),
+                            session_setup: session_setup(&db, pool),
+                        })
+                    }
+                    None => {
+                        log::warn!(
+                                "no read-only pool found for database {}, routing all queries to the primary",
+                                db.encore_name
+                            );
+                        None
+                    }
+                },
+            };
 
             let proxy_conn_string = proxy_conn_string(&db.encore_name, proxy_port);
 
+            let min_conns = pool.min_connections as u32;
+            let max_conns = pool.max_connections as u32;
+            let stmt_cache_size = stmt_cache_size(&db, pool);
+            let timeouts = pool_timeouts(pool);
+            let session_setup = session_setup(&db, pool);
             let name: EncoreName = db.encore_name.into();
+            let tracer = QueryTracer::new(tracer.clone(), &name, query_metrics.clone());
             databases.insert(
                 name.clone(),
                 Arc::new(DatabaseImpl {
                     name,
                     config: Arc::new(config),
                     tls,
+                    iam,
                     proxy_conn_string,
-                    tracer: tracer.clone(),
-
-                    min_conns: pool.min_connections as u32,
-                    max_conns: pool.max_connections as u32,
+                    tracer,
+                    flavor,
+
+                    min_conns,
+                    max_conns,
+                    stmt_cache_size,
+                    query_timeout,
+                    timeouts,
+                    session_setup,
+                    replica,
+                    test_isolation: test_isolation.then(TestIsolation::default),
                 }),
             );
         }
@@ -382,6 +603,203 @@ fn databases_from_cfg(
     Ok(databases)
 }
 
+/// The number of prepared statements cached per connection, if not configured.
+const DEFAULT_STMT_CACHE_SIZE: usize = 100;
+
+fn stmt_cache_size(db: &pb::SqlDatabase, pool: &pb::SqlConnectionPool) -> usize {
+    // Poolers in transaction mode may run each query on a different server connection,
+    // where statements prepared by earlier queries don't exist.
+    if db.pooler_mode() == pb::sql_database::PoolerMode::Transaction {
+        return 0;
+    }
+    pool.statement_cache_size
+        .map_or(DEFAULT_STMT_CACHE_SIZE, |size| size as usize)
+}
+
+/// Computes the statements to run on each new connection.
+///
+/// Poolers reject most startup parameters, so behind a pooler in session mode
+/// the statement timeout is set once the connection is established instead.
+fn session_setup(db: &pb::SqlDatabase, pool: &pb::SqlConnectionPool) -> Option<String> {
+    if db.pooler_mode() != pb::sql_database::PoolerMode::Session {
+        return None;
+    }
+    let timeout = duration(pool.statement_timeout)?;
+    Some(format!("SET statement_timeout = {}", timeout.as_millis()))
+}
+
+/// Warns about settings that have no effect with the database's pooler mode.
+fn check_pooler_mode(db: &pb::SqlDatabase, pool: &pb::SqlConnectionPool) {
+    if db.pooler_mode() != pb::sql_database::PoolerMode::Transaction {
+        return;
+    }
+    if pool.statement_cache_size.is_some_and(|size| size > 0) {
+        log::warn!(
+            "database {} uses a pooler in transaction mode, ignoring statement cache size",
+            db.encore_name
+        );
+    }
+    if duration(pool.statement_timeout).is_some() {
+        log::warn!(
+            "database {} uses a pooler in transaction mode, ignoring statement timeout; \
+             configure it on the database server instead",
+            db.encore_name
+        );
+    }
+}
+
+fn pool_timeouts(pool: &pb::SqlConnectionPool) -> PoolTimeouts {
+    PoolTimeouts {
+        acquire: duration(pool.acquire_timeout),
+        idle: duration(pool.idle_timeout),
+        max_lifetime: duration(pool.max_lifetime),
+    }
+}
+
+/// Converts a configured duration, treating zero as unset.
+fn duration(d: Option<prost_types::Duration>) -> Option<Duration> {
+    d.and_then(|d| Duration::try_from(d).ok())
+        .filter(|d| !d.is_zero())
+}
+
+/// Computes the configuration for connecting to the database on the given server,
+/// and the IAM token generator to authenticate with, if any.
+fn conn_config(
+    server: &pb::SqlServer,
+    pool: &pb::SqlConnectionPool,
+    db: &pb::SqlDatabase,
+    creds: &pb::infrastructure::Credentials,
+    secrets: &secrets::Manager,
+) -> anyhow::Result<(
+    tokio_postgres::Config,
+    postgres_native_tls::MakeTlsConnector,
+    Option<Arc<IamToken>>,
+)> {
+    // Get the role to authenticate with.
+    let role = creds
+        .sql_roles
+        .iter()
+        .find(|r| r.rid == pool.role_rid)
+        .with_context(|| {
+            format!(
+                "no role found with rid {} for database {}",
+                pool.role_rid, db.encore_name
+            )
+        })?;
+
+    let mut config = tokio_postgres::Config::new();
+
+    // Add host/port configuration
+    if server.host.starts_with('/') {
+        // Unix socket
+        config.host(&server.host);
+    } else if let Some((host, port)) = server.host.split_once(':') {
+        config.host(host);
+        config.port(port.parse::<u16>().context("invalid port")?);
+    } else {
+        config.host(&server.host);
+        config.port(5432);
+    }
+
+    config.user(&role.username);
+    let iam = match &role.iam_auth {
+        Some(iam_auth) => {
+            if matches!(
+                iam_auth.provider,
+                Some(pb::iam_auth::Provider::AwsElasticache(_))
+            ) {
+                anyhow::bail!(
+                    "ElastiCache IAM authentication is not supported for database {}",
+                    db.encore_name
+                );
+            }
+            if server.tls_config.is_none() {
+                log::warn!(
+                    "database {} uses IAM authentication without TLS, which cloud providers reject",
+                    db.encore_name
+                );
+            }
+            let endpoint = match server.host.split_once(':') {
+                Some(_) => server.host.clone(),
+                None => format!("{}:5432", server.host),
+            };
+            let iam = IamToken::new(iam_auth, &endpoint, &role.username)
+                .with_context(|| format!("invalid IAM auth for database {}", db.encore_name))?;
+            Some(Arc::new(iam))
+        }
+        None => None,
+    };
+    if let Some(password) = &role.password {
+        let sec = secrets.load(password.clone());
+        let password = sec.get().context("failed to resolve password")?;
+        config.password(password);
+    }
+
+    config.dbname(&db.cloud_name);
+    config.application_name("encore");
+    if db.pooler_mode() == pb::sql_database::PoolerMode::None {
+        if let Some(timeout) = duration(pool.statement_timeout) {
+            config.options(&format!("-c statement_timeout={}", timeout.as_millis()));
+        }
+    }
+
+    let mut tls_builder = native_tls::TlsConnector::builder();
+    if let Some(tls_config) = &server.tls_config {
+        if let Some(server_ca_cert) = &tls_config.server_ca_cert {
+            let cert = native_tls::Certificate::from_pem(server_ca_cert.as_bytes())
+                .context("unable to parse server ca certificate")?;
+            tls_builder.add_root_certificate(cert);
+            config.ssl_mode(tokio_postgres::config::SslMode::Require);
+        } else {
+            config.ssl_mode(tokio_postgres::config::SslMode::Prefer);
+        }
+
+        if tls_config.disable_tls_hostname_verification {
+            tls_builder.danger_accept_invalid_hostnames(true);
+        }
+        if tls_config.disable_ca_validation {
+            tls_builder.danger_accept_invalid_certs(true);
+        }
+    } else {
+        config.ssl_mode(tokio_postgres::config::SslMode::Disable);
+    }
+
+    if let Some(client_cert_rid) = &role.client_cert_rid {
+        // Add a client certificate.
+        let client_cert = creds
+            .client_certs
+            .iter()
+            .find(|c| c.rid == *client_cert_rid)
+            .with_context(|| {
+                format!(
+                    "no client certificate found with rid {} for database {}",
+                    client_cert_rid, db.encore_name
+                )
+            })?;
+
+        // Parse the client key secret.
+        let client_key = client_cert
+            .key
+            .as_ref()
+            .context("client certificate has no key")?;
+        let client_key = secrets.load(client_key.clone());
+        let client_key = client_key.get().context("failed to resolve client key")?;
+
+        let client_key = convert_client_key_if_necessary(client_key)
+            .context("failed to convert client key to PKCS#8")?;
+        let identity =
+            native_tls::Identity::from_pkcs8(client_cert.cert.as_bytes(), client_key.as_ref())
+                .context("failed to parse client certificate")?;
+        tls_builder.identity(identity);
+    }
+
+    let tls = tls_builder
+        .build()
+        .context("failed to build TLS connector")?;
+    let tls = postgres_native_tls::MakeTlsConnector::new(tls);
+    Ok((config, tls, iam))
+}
+
 /// Converts the client key from PKCS#1 to PKCS#8 if necessary.
 fn convert_client_key_if_necessary(pem: &[u8]) -> anyhow::Result<Cow<'_, [u8]>> {
     let Ok(pem_str) = std::str::from_utf8(pem) else {
@@ -402,3 +820,37 @@ fn convert_client_key_if_necessary(pem: &[u8]) -> anyhow::Result<Cow<'_, [u8]>>
         .context("failed to convert PKCS#1 private key to PKCS#8")?;
     Ok(Cow::Owned(pkcs8.as_bytes().to_owned()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pooler_mode() {
+        use pb::sql_database::PoolerMode;
+        let db = |mode: PoolerMode| pb::SqlDatabase {
+            pooler_mode: mode as i32,
+            ..Default::default()
+        };
+        let pool = pb::SqlConnectionPool {
+            statement_cache_size: Some(10),
+            statement_timeout: Some(prost_types::Duration {
+                seconds: 5,
+                nanos: 0,
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(stmt_cache_size(&db(PoolerMode::None), &pool), 10);
+        assert_eq!(session_setup(&db(PoolerMode::None), &pool), None);
+
+        assert_eq!(stmt_cache_size(&db(PoolerMode::Session), &pool), 10);
+        assert_eq!(
+            session_setup(&db(PoolerMode::Session), &pool).as_deref(),
+            Some("SET statement_timeout = 5000")
+        );
+
+        assert_eq!(stmt_cache_size(&db(PoolerMode::Transaction), &pool), 0);
+        assert_eq!(session_setup(&db(PoolerMode::Transaction), &pool), None);
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::encore::runtime::v1 as pb;
+use crate::metrics::{self, counter, histogram};
+use crate::model;
+
+/// The number of series tracked if not configured.
+const DEFAULT_MAX_SERIES: usize = 500;
+
+/// The maximum length of the normalized query used as a label value.
+const MAX_QUERY_LABEL_LEN: usize = 100;
+
+/// The label value used for queries beyond the cardinality limit.
+const OTHER: &str = "other";
+
+/// Records query latency and errors per calling endpoint and query fingerprint,
+/// so it's possible to tell which endpoint is executing which queries.
+pub struct QueryMetrics {
+    max_series: usize,
+    series: Mutex<HashSet<SeriesKey>>,
+    queries_total: counter::Schema<u64>,
+    query_duration: histogram::Schema,
+}
+
+impl std::fmt::Debug for QueryMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryMetrics")
+            .field("max_series", &self.max_series)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    database: String,
+    service: String,
+    endpoint: String,
+    fingerprint: String,
+}
+
+impl QueryMetrics {
+    pub fn new(registry: &Arc<metrics::Registry>, cfg: &pb::SqlMetrics) -> Self {
+        let max_series = match cfg.max_series {
+            0 => DEFAULT_MAX_SERIES,
+            n => n as usize,
+        };
+        Self {
+            max_series,
+            series: Mutex::new(HashSet::new()),
+            queries_total: registry
+                .counter_schema::<u64>("e_sql_queries_total")
+                .require_dynamic_keys([
+                    "database",
+                    "service",
+                    "endpoint",
+                    "fingerprint",
+                    "query",
+                    "status",
+                ])
+                .build(),
+            query_duration: registry
+                .histogram_schema("e_sql_query_duration_seconds")
+                .build(),
+        }
+    }
+
+    /// Records a query executed against the database on behalf of the source request.
+    pub(super) fn record(
+        &self,
+        database: &str,
+        source: Option<&model::Request>,
+        query: &str,
+        ok: bool,
+        duration: Duration,
+    ) {
+        let (service, endpoint) = caller(source);
+        let normalized = normalize(query);
+        let mut key = SeriesKey {
+            database: database.to_string(),
+            service,
+            endpoint,
+            fingerprint: fingerprint(&normalized),
+        };
+        let mut query_label = truncate(&normalized, MAX_QUERY_LABEL_LEN);
+
+        if !self.track(&key) {
+            key.fingerprint = OTHER.to_string();
+            query_label = OTHER.to_string();
+        }
+
+        let labels = [
+            ("database", key.database),
+            ("service", key.service),
+            ("endpoint", key.endpoint),
+            ("fingerprint", key.fingerprint),
+            ("query", query_label),
+        ];
+
+        let status = if ok { "ok" } else { "error" };
+        self.queries_total
+            .with(
+                labels
+                    .clone()
+                    .into_iter()
+                    .chain([("status", status.to_string())]),
+            )
+            .increment();
+        self.query_duration
+            .with(labels)
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Reports whether the series should be recorded with its own labels,
+    /// tracking it if there's room.
+    fn track(&self, key: &SeriesKey) -> bool {
+        let mut series = self.series.lock().unwrap();
+        if series.contains(key) {
+            return true;
+        }
+        if series.len() >= self.max_series {
+            return false;
+        }
+        series.insert(key.clone());
+        true
+    }
+}
+
+/// The service and endpoint executing the query, if known.
+fn caller(source: Option<&model::Request>) -> (String, String) {
+    let Some(source) = source else {
+        return (String::new(), String::new());
+    };
+    match &source.data {
+        model::RequestData::RPC(rpc) => (
+            rpc.endpoint.name.service().to_string(),
+            rpc.endpoint.name.endpoint().to_string(),
+        ),
+        model::RequestData::Stream(stream) => (
+            stream.endpoint.name.service().to_string(),
+            stream.endpoint.name.endpoint().to_string(),
+        ),
+        model::RequestData::Auth(auth) => (
+            auth.auth_handler.service().to_string(),
+            auth.auth_handler.endpoint().to_string(),
+        ),
+        model::RequestData::PubSub(msg) => (
+            msg.service.to_string(),
+            format!("{}.{}", msg.topic, msg.subscription),
+        ),
+    }
+}
+
+/// Normalizes the query so that executions that differ only in literal values,
+/// comments or whitespace are grouped together.
+fn normalize(query: &str) -> String {
+    let mut out = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut pending_space = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                pending_space = true;
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                pending_space = true;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                pending_space = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        if pending_space && !out.is_empty() {
+            out.push(' ');
+        }
+        pending_space = false;
+
+        match c {
+            '\'' => {
+                // String literal, with '' as an escaped quote.
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                out.push('?');
+            }
+            c if c.is_ascii_digit() && !continues_word(&out) => {
+                while chars
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.')
+                {
+                    chars.next();
+                }
+                out.push('?');
+            }
+            c => out.push(c),
+        }
+    }
+
+    // Collapse lists of literals, like IN (?, ?, ?), so they don't
+    // produce a distinct fingerprint for every list length.
+    while out.contains("?, ?") || out.contains("?,?") {
+        out = out.replace("?, ?", "?").replace("?,?", "?");
+    }
+    out
+}
+
+/// Reports whether a digit following the text is part of a word,
+/// like an identifier or a numbered parameter, rather than a number literal.
+fn continues_word(out: &str) -> bool {
+    out.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$' || c == '"')
+}
+
+/// A short, stable identifier for the normalized query.
+fn fingerprint(normalized: &str) -> String {
+    let digest = Sha256::digest(normalized.as_bytes());
+    hex::encode(&digest[..8])
+}
+
+fn truncate(s: &str, max_len: usize) -> String {
+    match s.char_indices().nth(max_len) {
+        Some((idx, _)) => format!("{}...", &s[..idx]),
+        None => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("SELECT *\n  FROM users -- by id\n WHERE id = $1 AND name = 'o''brien'"),
+            "SELECT * FROM users WHERE id = $1 AND name = ?"
+        );
+        assert_eq!(
+            normalize("/* list */ select id from t2 where id in (1, 2, 3) limit 10"),
+            "select id from t2 where id in (?) limit ?"
+        );
+        assert_eq!(
+            fingerprint(&normalize("SELECT 1")),
+            fingerprint(&normalize("SELECT  2"))
+        );
+        assert_ne!(
+            fingerprint(&normalize("SELECT 1")),
+            fingerprint(&normalize("SELECT $1"))
+        );
+    }
+
+    #[test]
+    fn test_cardinality_limit() {
+        let registry = Arc::new(metrics::Registry::new());
+        let metrics = QueryMetrics::new(&registry, &pb::SqlMetrics { max_series: 1 });
+        let ms = Duration::from_millis(1);
+        metrics.record("orders", None, "SELECT 1", true, ms);
+        metrics.record("orders", None, "SELECT 2", false, ms);
+        metrics.record("orders", None, "DELETE FROM orders", false, ms);
+
+        let queries: Vec<_> = registry
+            .collect()
+            .into_iter()
+            .filter(|m| m.key.name() == "e_sql_queries_total")
+            .map(|m| {
+                let label = |key: &str| {
+                    m.key
+                        .labels()
+                        .find(|l| l.key() == key)
+                        .map(|l| l.value().to_string())
+                        .unwrap()
+                };
+                (label("query"), label("status"))
+            })
+            .collect();
+        assert_eq!(queries.len(), 3);
+        assert!(queries.contains(&("SELECT ?".to_string(), "ok".to_string())));
+        assert!(queries.contains(&("SELECT ?".to_string(), "error".to_string())));
+        assert!(queries.contains(&(OTHER.to_string(), "error".to_string())));
+    }
+}
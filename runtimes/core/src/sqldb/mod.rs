@@ -1,10 +1,31 @@
 mod client;
+mod conn;
+mod isolation;
 mod manager;
+mod metrics;
+mod mysql;
 pub mod numeric;
+mod replay;
+mod singleton;
 mod transaction;
 mod val;
 
-pub use client::{Connection, Cursor, Pool, Row};
+pub use client::{Connection, Cursor, Error, Pool, Row};
 pub use manager::{Database, DatabaseImpl, Manager, ManagerConfig};
-pub use transaction::Transaction;
+pub use metrics::QueryMetrics;
+pub use singleton::SingletonLock;
+pub use transaction::{IsolationLevel, Savepoint, Transaction, TransactionOptions};
 pub use val::RowValue;
+
+/// Reports whether the table name is safe to interpolate into a query,
+/// optionally qualified with a schema name.
+pub fn is_valid_table_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').count() <= 2
+        && name.split('.').all(|part| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use anyhow::Context;
+use base64::Engine;
+use serde_json::{json, Value};
+use tokio_postgres::types::BorrowToSql;
+
+use crate::api::PValue;
+use crate::replay::{self, Cassette};
+use crate::sqldb::RowValue;
+
+use super::client::{Cursor, Error};
+
+/// The cassette operation for queries.
+const OP: &str = "sql.query";
+
+/// Collects the rows returned by a query as they're read,
+/// recording them when the cursor is dropped.
+///
+/// Only the rows actually read are recorded, which is enough to
+/// replay the same sequence of reads.
+pub(super) struct Recording {
+    cassette: &'static Cassette,
+    key: String,
+    rows: Vec<Value>,
+    error: Option<String>,
+}
+
+impl Recording {
+    pub fn new(cassette: &'static Cassette, key: String) -> Self {
+        Self {
+            cassette,
+            key,
+            rows: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn row(&mut self, row: anyhow::Result<HashMap<String, RowValue>>) {
+        match row {
+            Ok(row) => self.rows.push(encode_row(&row)),
+            Err(err) => self.fail(err.to_string()),
+        }
+    }
+
+    pub fn fail(&mut self, err: String) {
+        self.error.get_or_insert(err);
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        // Errors while reading rows are replayed as errors executing the query,
+        // as database errors can't be reconstructed.
+        let result = match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(Value::Array(std::mem::take(&mut self.rows))),
+        };
+        self.cassette.record(OP, &self.key, result);
+    }
+}
+
+/// Identifies a query in the cassette by its database, statement and parameters,
+/// so the same statement run with different parameters is replayed separately.
+pub(super) fn query_key<P: BorrowToSql>(db: &str, query: &str, params: &[P]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|p| format!("{:?}", p.borrow_to_sql()))
+        .collect();
+    format!("{db}: {query} [{}]", params.join(", "))
+}
+
+/// Runs the query, recording its result if recording.
+/// When replaying, the recorded result is returned instead and `run` isn't awaited.
+pub(super) async fn query<F>(key: String, run: F) -> Result<Cursor, Error>
+where
+    F: Future<Output = Result<Cursor, Error>>,
+{
+    let Some(cassette) = replay::cassette() else {
+        return run.await;
+    };
+    if cassette.mode() == replay::Mode::Replay {
+        return replay_query(cassette, &key);
+    }
+    match run.await {
+        Ok(cursor) => Ok(cursor.record(cassette, key)),
+        Err(err) => {
+            cassette.record(OP, &key, Err(err.to_string()));
+            Err(err)
+        }
+    }
+}
+
+/// Reports whether queries are replayed rather than run against the database.
+pub(super) fn replaying() -> bool {
+    replay::cassette().is_some_and(|c| c.mode() == replay::Mode::Replay)
+}
+
+/// Returns a cursor over the recorded rows of the query.
+fn replay_query(cassette: &Cassette, key: &str) -> Result<Cursor, Error> {
+    let rows = match cassette.replay(OP, key) {
+        Ok(Ok(rows)) => rows,
+        Ok(Err(err)) => return Err(Error::Replay(err)),
+        Err(err) => return Err(Error::Replay(format!("{err:#}"))),
+    };
+    let rows = match rows {
+        Value::Array(rows) => rows
+            .into_iter()
+            .map(decode_row)
+            .collect::<anyhow::Result<Vec<_>>>(),
+        _ => Err(anyhow::anyhow!("expected a list of rows")),
+    }
+    .map_err(|err| Error::Replay(format!("invalid recording for {key}: {err:#}")))?;
+    Ok(Cursor::replayed(rows))
+}
+
+fn encode_row(row: &HashMap<String, RowValue>) -> Value {
+    Value::Object(
+        row.iter()
+            .map(|(col, val)| {
+                let val = match val {
+                    RowValue::PVal(val) => json!({ "pval": encode_pvalue(val) }),
+                    RowValue::Bytes(val) => {
+                        json!({ "bytes": base64::engine::general_purpose::STANDARD.encode(val) })
+                    }
+                    RowValue::Uuid(val) => json!({ "uuid": val.to_string() }),
+                    RowValue::Inet(val) => json!({ "inet": val.to_string() }),
+                    RowValue::Cidr(val) => json!({ "cidr": val.to_string() }),
+                };
+                (col.clone(), val)
+            })
+            .collect(),
+    )
+}
+
+fn decode_row(row: Value) -> anyhow::Result<HashMap<String, RowValue>> {
+    let Value::Object(row) = row else {
+        anyhow::bail!("expected a row object");
+    };
+    row.into_iter()
+        .map(|(col, val)| {
+            let Value::Object(val) = val else {
+                anyhow::bail!("invalid value for column {col}");
+            };
+            let (kind, val) = val
+                .into_iter()
+                .next()
+                .with_context(|| format!("missing value for column {col}"))?;
+            let str = || {
+                val.as_str()
+                    .context("expected a string")
+                    .map(str::to_string)
+            };
+            let val = match kind.as_str() {
+                "pval" => RowValue::PVal(decode_pvalue(val.clone())?),
+                "bytes" => {
+                    RowValue::Bytes(base64::engine::general_purpose::STANDARD.decode(str()?)?)
+                }
+                "uuid" => RowValue::Uuid(str()?.parse()?),
+                "inet" => RowValue::Inet(str()?.parse()?),
+                "cidr" => RowValue::Cidr(str()?.parse()?),
+                other => anyhow::bail!("unknown value kind {other} for column {col}"),
+            };
+            Ok((col, val))
+        })
+        .collect()
+}
+
+/// Encodes the value as JSON, tagging datetimes and decimals
+/// so they're decoded as such rather than as strings.
+fn encode_pvalue(val: &PValue) -> Value {
+    match val {
+        PValue::DateTime(dt) => json!({ "$datetime": dt.to_rfc3339() }),
+        PValue::Decimal(d) => json!({ "$decimal": d.to_string() }),
+        PValue::Array(vals) => Value::Array(vals.iter().map(encode_pvalue).collect()),
+        PValue::Object(vals) => Value::Object(
+            vals.iter()
+                .map(|(k, v)| (k.clone(), encode_pvalue(v)))
+                .collect(),
+        ),
+        other => serde_json::to_value(other).unwrap_or(Value::Null),
+    }
+}
+
+fn decode_pvalue(val: Value) -> anyhow::Result<PValue> {
+    Ok(match val {
+        Value::Array(vals) => PValue::Array(
+            vals.into_iter()
+                .map(decode_pvalue)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Object(vals) => {
+            if vals.len() == 1 {
+                if let Some(Value::String(dt)) = vals.get("$datetime") {
+                    return Ok(PValue::DateTime(chrono::DateTime::parse_from_rfc3339(dt)?));
+                }
+                if let Some(Value::String(d)) = vals.get("$decimal") {
+                    return Ok(PValue::Decimal(
+                        d.parse()
+                            .map_err(|_| anyhow::anyhow!("invalid decimal {d}"))?,
+                    ));
+                }
+            }
+            PValue::Object(
+                vals.into_iter()
+                    .map(|(k, v)| Ok((k, decode_pvalue(v)?)))
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        other => PValue::from(other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_key_includes_params() {
+        let a = query_key("db", "SELECT $1", &[1i32]);
+        let b = query_key("db", "SELECT $1", &[2i32]);
+        assert_eq!(a, "db: SELECT $1 [1]");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let dt = chrono::DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00").unwrap();
+        let price: crate::api::Decimal = "12.50".parse().unwrap();
+        let row = HashMap::from([
+            ("id".to_string(), RowValue::Uuid(uuid::Uuid::nil())),
+            ("data".to_string(), RowValue::Bytes(vec![1, 2, 3])),
+            (
+                "created".to_string(),
+                RowValue::PVal(PValue::Array(vec![PValue::DateTime(dt)])),
+            ),
+            (
+                "price".to_string(),
+                RowValue::PVal(PValue::Decimal(price.clone())),
+            ),
+        ]);
+
+        let decoded = decode_row(encode_row(&row)).unwrap();
+        assert!(matches!(decoded["id"], RowValue::Uuid(id) if id.is_nil()));
+        assert!(matches!(&decoded["data"], RowValue::Bytes(b) if b == &[1, 2, 3]));
+        assert!(matches!(
+            &decoded["created"],
+            RowValue::PVal(PValue::Array(vals)) if vals == &[PValue::DateTime(dt)]
+        ));
+        assert!(matches!(
+            &decoded["price"],
+            RowValue::PVal(PValue::Decimal(d)) if *d == price
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+use std::future::Future;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::conn::Client;
+
+/// How often the lease is renewed.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a heartbeat may take before the lock is considered lost.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a lease is valid for without being renewed.
+/// This must exceed the time between heartbeats, including their timeout.
+const LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// A cluster-wide lock ensuring a task runs on a single instance at a time.
+///
+/// The lock is a lease stored in a table, renewed by a heartbeat on a dedicated
+/// connection. If the lease can't be renewed the lock is reported as lost, and
+/// it expires after [`LEASE_TTL`]. Leases are used rather than session-level
+/// advisory locks so the lock works through connection poolers in transaction
+/// mode, such as PgBouncer, and on CockroachDB, which has no advisory locks.
+pub struct SingletonLock {
+    name: String,
+    released: CancellationToken,
+    lost: CancellationToken,
+    heartbeat: Option<JoinHandle<()>>,
+}
+
+impl SingletonLock {
+    /// Attempts to acquire the lock with the given name on the connection.
+    /// Returns None if the lock is held by someone else.
+    ///
+    /// The `encore_singleton_locks` table is created by one of the app's
+    /// migrations, so acquiring a lock doesn't require DDL privileges.
+    pub(super) async fn try_acquire(
+        conn: Client,
+        name: &str,
+    ) -> Result<Option<Self>, tokio_postgres::Error> {
+        let key = lock_key(name);
+        let holder = xid::new().to_string();
+        let rows = conn
+            .query(
+                &format!(
+                    "INSERT INTO encore_singleton_locks (name, holder, expires_at)
+                     VALUES ($1, $2, now() + {})
+                     ON CONFLICT (name) DO UPDATE
+                     SET holder = excluded.holder, expires_at = excluded.expires_at
+                     WHERE encore_singleton_locks.expires_at < now()
+                     RETURNING holder",
+                    lease_interval()
+                ),
+                &[&key, &holder],
+            )
+            .await?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self::held(name, TableLease { conn, key, holder })))
+    }
+
+    /// Returns the lock held with the given lease, keeping the lease alive
+    /// in the background until the lock is released or lost.
+    fn held(name: &str, lease: impl Lease) -> Self {
+        let released = CancellationToken::new();
+        let lost = CancellationToken::new();
+        let heartbeat = tokio::spawn(heartbeat(
+            lease,
+            name.to_string(),
+            released.clone(),
+            lost.clone(),
+        ));
+        Self {
+            name: name.to_string(),
+            released,
+            lost,
+            heartbeat: Some(heartbeat),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reports whether the lock is still held.
+    pub fn is_held(&self) -> bool {
+        !self.lost.is_cancelled()
+    }
+
+    /// Returns a future that completes when the lock is no longer held,
+    /// either because it was released or because it was lost.
+    pub fn lost(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.lost.clone().cancelled_owned()
+    }
+
+    /// Releases the lock, waiting for it to be released.
+    pub async fn release(mut self) {
+        self.released.cancel();
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let _ = heartbeat.await;
+        }
+    }
+}
+
+impl Drop for SingletonLock {
+    fn drop(&mut self) {
+        // Release the lock in the background.
+        self.released.cancel();
+    }
+}
+
+fn lock_key(name: &str) -> String {
+    format!("encore.singleton:{name}")
+}
+
+/// The SQL interval literal for the lease TTL.
+fn lease_interval() -> String {
+    format!("INTERVAL '{} seconds'", LEASE_TTL.as_secs())
+}
+
+/// A lease on a lock, kept alive by the heartbeat.
+#[async_trait]
+trait Lease: Send + 'static {
+    /// Renews the lease, reporting whether it's still held.
+    async fn renew(&mut self) -> anyhow::Result<bool>;
+
+    /// Gives up the lease.
+    async fn release(&mut self) -> anyhow::Result<()>;
+}
+
+/// A lease stored in the `encore_singleton_locks` table.
+struct TableLease {
+    conn: Client,
+    key: String,
+    holder: String,
+}
+
+#[async_trait]
+impl Lease for TableLease {
+    async fn renew(&mut self) -> anyhow::Result<bool> {
+        let updated = self
+            .conn
+            .execute(
+                &format!(
+                    "UPDATE encore_singleton_locks SET expires_at = now() + {}
+                     WHERE name = $1 AND holder = $2",
+                    lease_interval()
+                ),
+                &[&self.key, &self.holder],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    async fn release(&mut self) -> anyhow::Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM encore_singleton_locks WHERE name = $1 AND holder = $2",
+                &[&self.key, &self.holder],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Keeps the lease alive until the lock is released,
+/// reporting the lock as lost if the lease can't be renewed.
+async fn heartbeat(
+    mut lease: impl Lease,
+    name: String,
+    released: CancellationToken,
+    lost: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = released.cancelled() => {
+                // Leases expire, so failing to release it explicitly is not a problem.
+                let _ = lease.release().await;
+                break;
+            }
+            _ = interval.tick() => {
+                match tokio::time::timeout(HEARTBEAT_TIMEOUT, lease.renew()).await {
+                    Ok(Ok(true)) => {}
+                    Ok(Ok(false)) => {
+                        log::error!("singleton lock {}: lease was taken over, lock lost", name);
+                        break;
+                    }
+                    Ok(Err(err)) => {
+                        log::error!("singleton lock {}: heartbeat failed, lock lost: {:#}", name, err);
+                        break;
+                    }
+                    Err(_) => {
+                        log::error!("singleton lock {}: heartbeat timed out, lock lost", name);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // Close the connection before reporting the lock as no longer held.
+    drop(lease);
+    lost.cancel();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A lease that renews according to `renewals`, recording its calls.
+    #[derive(Clone, Default)]
+    struct FakeLease {
+        state: Arc<Mutex<FakeState>>,
+    }
+
+    #[derive(Default)]
+    struct FakeState {
+        /// The results of the next renewals. Once exhausted, renewals succeed.
+        renewals: Vec<Renewal>,
+        renewed: usize,
+        released: bool,
+    }
+
+    #[derive(Clone, Copy)]
+    enum Renewal {
+        Lost,
+        Failed,
+        Hang,
+    }
+
+    #[async_trait]
+    impl Lease for FakeLease {
+        async fn renew(&mut self) -> anyhow::Result<bool> {
+            let renewal = {
+                let mut state = self.state.lock().unwrap();
+                state.renewed += 1;
+                (!state.renewals.is_empty()).then(|| state.renewals.remove(0))
+            };
+            match renewal {
+                None => Ok(true),
+                Some(Renewal::Lost) => Ok(false),
+                Some(Renewal::Failed) => anyhow::bail!("connection closed"),
+                Some(Renewal::Hang) => std::future::pending().await,
+            }
+        }
+
+        async fn release(&mut self) -> anyhow::Result<()> {
+            self.state.lock().unwrap().released = true;
+            Ok(())
+        }
+    }
+
+    fn lease(renewals: Vec<Renewal>) -> FakeLease {
+        let lease = FakeLease::default();
+        lease.state.lock().unwrap().renewals = renewals;
+        lease
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn renews_until_released() {
+        let lease = lease(vec![]);
+        let lock = SingletonLock::held("job", lease.clone());
+
+        tokio::time::sleep(HEARTBEAT_INTERVAL * 3 + Duration::from_secs(1)).await;
+        assert!(lock.is_held());
+        assert_eq!(lease.state.lock().unwrap().renewed, 3);
+
+        let lost = lock.lost();
+        lock.release().await;
+        lost.await;
+        assert!(lease.state.lock().unwrap().released);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_lost_leases() {
+        for renewal in [Renewal::Lost, Renewal::Failed, Renewal::Hang] {
+            let lease = lease(vec![renewal]);
+            let lock = SingletonLock::held("job", lease.clone());
+
+            lock.lost().await;
+            assert!(!lock.is_held());
+            assert!(!lease.state.lock().unwrap().released);
+        }
+    }
+}
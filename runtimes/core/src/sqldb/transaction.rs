@@ -3,8 +3,8 @@ use tokio_postgres::types::BorrowToSql;
 use crate::model;
 
 use super::{
-    client::{Error, PooledConn, QueryTracer},
-    Cursor,
+    client::{Error, Lease, PooledConn, QueryTracer},
+    isolation, replay, Cursor,
 };
 
 // Heavily inspired by rust-postgres, but where the transaction doesnt have a lifetime, so it can
@@ -13,19 +13,95 @@ use super::{
 // https://github.com/sfackler/rust-postgres/blob/720ffe83216714bf9716a03122c547a2e8e9bfd9/tokio-postgres/src/transaction.rs
 
 pub struct Transaction {
-    conn: PooledConn,
+    /// The connection running the transaction,
+    /// or None if its queries are replayed from a cassette.
+    conn: Option<Lease>,
     tracer: QueryTracer,
     done: bool,
+
+    /// The savepoint that emulates the transaction when isolating tests.
+    isolated_savepoint: Option<String>,
+
+    /// The savepoints that are currently active, innermost last.
+    savepoints: Vec<u32>,
+    next_savepoint: u32,
+}
+
+/// The transaction isolation level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn as_sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
 }
 
+impl std::str::FromStr for IsolationLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read uncommitted" => Ok(IsolationLevel::ReadUncommitted),
+            "read committed" => Ok(IsolationLevel::ReadCommitted),
+            "repeatable read" => Ok(IsolationLevel::RepeatableRead),
+            "serializable" => Ok(IsolationLevel::Serializable),
+            _ => anyhow::bail!("unknown isolation level {s:?}"),
+        }
+    }
+}
+
+/// Options for beginning a transaction.
+/// The defaults use the database's default isolation level and access mode.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionOptions {
+    pub isolation_level: Option<IsolationLevel>,
+    pub read_only: bool,
+    pub deferrable: bool,
+}
+
+impl TransactionOptions {
+    fn begin_statement(&self) -> String {
+        let mut stmt = String::from("BEGIN");
+        if let Some(level) = self.isolation_level {
+            stmt.push_str(" ISOLATION LEVEL ");
+            stmt.push_str(level.as_sql());
+        }
+        if self.read_only {
+            stmt.push_str(" READ ONLY");
+        }
+        if self.deferrable {
+            stmt.push_str(" DEFERRABLE");
+        }
+        stmt
+    }
+}
+
+/// A savepoint within a transaction, which can be released or rolled back to
+/// independently of the transaction. Used to implement nested transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Savepoint(u32);
+
 impl Transaction {
     pub(crate) async fn begin(
         conn: PooledConn,
         tracer: QueryTracer,
+        opts: &TransactionOptions,
         source: Option<&model::Request>,
     ) -> Result<Self, Error> {
         struct RollbackIfNotDone<'me> {
             client: &'me tokio_postgres::Client,
+            savepoint: Option<&'me str>,
             done: bool,
         }
 
@@ -35,10 +111,20 @@ impl Transaction {
                     return;
                 }
 
-                self.client.__private_api_rollback(None);
+                self.client.__private_api_rollback(self.savepoint);
             }
         }
 
+        // When isolating tests the connection is already in a transaction,
+        // so a savepoint is used instead. The options can't be applied to it.
+        let isolated_savepoint = conn
+            .is_test_isolated()
+            .then(|| isolation::savepoint_name(isolation::TX_SAVEPOINT));
+        let stmt = match &isolated_savepoint {
+            Some(sp) => format!("SAVEPOINT {sp}"),
+            None => opts.begin_statement(),
+        };
+
         // This is done, as `Future` created by this method can be dropped after
         // `RequestMessages` is synchronously send to the `Connection` by
         // `batch_execute()`, but before `Responses` is asynchronously polled to
@@ -47,12 +133,17 @@ impl Transaction {
         {
             let mut cleaner = RollbackIfNotDone {
                 client: &conn,
+                savepoint: isolated_savepoint.as_deref(),
                 done: false,
             };
 
             tracer
-                .trace_batch_execute(source, "BEGIN", || async {
-                    conn.batch_execute("BEGIN").await.map_err(Error::from)
+                .trace_batch_execute(source, &stmt, || async {
+                    let result = match &isolated_savepoint {
+                        Some(sp) => conn.isolated_savepoint(sp).await,
+                        None => conn.batch_execute(&stmt).await,
+                    };
+                    result.map_err(Error::from)
                 })
                 .await?;
 
@@ -60,19 +151,104 @@ impl Transaction {
         }
 
         Ok(Transaction {
-            conn,
+            conn: Some(Lease::new(conn)),
             tracer,
             done: false,
+            isolated_savepoint,
+            savepoints: Vec::new(),
+            next_savepoint: 0,
         })
     }
 
+    /// Returns a transaction whose queries are replayed from the cassette.
+    pub(crate) fn replaying(tracer: QueryTracer) -> Self {
+        Transaction {
+            conn: None,
+            tracer,
+            done: false,
+            isolated_savepoint: None,
+            savepoints: Vec::new(),
+            next_savepoint: 0,
+        }
+    }
+
+    /// Returns the name of the nested transaction's savepoint. When isolating tests
+    /// it's scoped to the transaction, as concurrent transactions share a session.
+    fn savepoint_name(&self, sp: Savepoint) -> String {
+        match &self.isolated_savepoint {
+            Some(tx) => format!("{tx}_sp_{}", sp.0),
+            None => format!("encore_sp_{}", sp.0),
+        }
+    }
+
+    /// Creates a savepoint, beginning a nested transaction.
+    pub async fn savepoint(&mut self, source: Option<&model::Request>) -> Result<Savepoint, Error> {
+        let sp = Savepoint(self.next_savepoint);
+        self.next_savepoint += 1;
+        self.batch_execute(&format!("SAVEPOINT {}", self.savepoint_name(sp)), source)
+            .await?;
+        self.savepoints.push(sp.0);
+        Ok(sp)
+    }
+
+    /// Releases the savepoint, committing the nested transaction
+    /// into the enclosing one. Nested savepoints are released as well.
+    pub async fn release_savepoint(
+        &mut self,
+        sp: Savepoint,
+        source: Option<&model::Request>,
+    ) -> Result<(), Error> {
+        let idx = self.savepoint_index(sp)?;
+        let name = self.savepoint_name(sp);
+        self.batch_execute(&format!("RELEASE SAVEPOINT {name}"), source)
+            .await?;
+        self.savepoints.truncate(idx);
+        Ok(())
+    }
+
+    /// Rolls back to the savepoint and releases it, discarding the changes made
+    /// by the nested transaction. Nested savepoints are discarded as well.
+    pub async fn rollback_to_savepoint(
+        &mut self,
+        sp: Savepoint,
+        source: Option<&model::Request>,
+    ) -> Result<(), Error> {
+        let idx = self.savepoint_index(sp)?;
+        let name = self.savepoint_name(sp);
+        self.batch_execute(
+            &format!("ROLLBACK TO SAVEPOINT {name}; RELEASE SAVEPOINT {name}"),
+            source,
+        )
+        .await?;
+        self.savepoints.truncate(idx);
+        Ok(())
+    }
+
+    fn savepoint_index(&self, sp: Savepoint) -> Result<usize, Error> {
+        if self.done {
+            return Err(Error::Closed);
+        }
+        self.savepoints
+            .iter()
+            .position(|&id| id == sp.0)
+            .ok_or(Error::SavepointReleased)
+    }
+
     pub async fn commit(mut self, source: Option<&model::Request>) -> Result<(), Error> {
         self.done = true;
+        if let Some(sp) = &self.isolated_savepoint {
+            let stmt = format!("RELEASE SAVEPOINT {sp}");
+            return self.batch_execute(&stmt, source).await;
+        }
         self.batch_execute("COMMIT", source).await
     }
 
     pub async fn rollback(mut self, source: Option<&model::Request>) -> Result<(), Error> {
         self.done = true;
+        if let Some(sp) = &self.isolated_savepoint {
+            let stmt = format!("ROLLBACK TO SAVEPOINT {sp}; RELEASE SAVEPOINT {sp}");
+            return self.batch_execute(&stmt, source).await;
+        }
         self.batch_execute("ROLLBACK", source).await
     }
 
@@ -81,9 +257,13 @@ impl Transaction {
         query: &str,
         source: Option<&model::Request>,
     ) -> Result<(), Error> {
+        // Transaction control statements return no rows, so there's nothing to replay.
+        let Some(conn) = &self.conn else {
+            return Ok(());
+        };
         self.tracer
             .trace_batch_execute(source, query, || async {
-                self.conn.batch_execute(query).await.map_err(Error::from)
+                conn.batch_execute(query).await.map_err(Error::from)
             })
             .await
     }
@@ -99,12 +279,21 @@ impl Transaction {
         I: IntoIterator<Item = P>,
         I::IntoIter: ExactSizeIterator,
     {
+        let params: Vec<P> = params.into_iter().collect();
+        let key = replay::query_key(self.tracer.database(), query, &params);
         self.tracer
-            .trace(source, query, || async {
-                self.conn
-                    .query_raw(query, params)
-                    .await
-                    .map_err(Error::from)
+            .trace(source, query, || {
+                replay::query(key, async {
+                    let Some(conn) = &self.conn else {
+                        return Err(Error::Closed);
+                    };
+                    let deadline = conn.deadline(source);
+                    let params = params.iter().map(|p| p.borrow_to_sql());
+                    let stream = conn
+                        .query_in_transaction(query, params, deadline.as_ref())
+                        .await?;
+                    Ok(Cursor::new(stream, deadline))
+                })
             })
             .await
     }
@@ -112,11 +301,34 @@ impl Transaction {
 
 impl Drop for Transaction {
     fn drop(&mut self) {
+        let Some(conn) = &self.conn else {
+            return;
+        };
         if self.done {
             return;
         }
 
         log::warn!("transaction not completed, forcing rollback");
-        self.conn.__private_api_rollback(None);
+        conn.__private_api_rollback(self.isolated_savepoint.as_deref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn begin_statement() {
+        assert_eq!(TransactionOptions::default().begin_statement(), "BEGIN");
+        assert_eq!(
+            TransactionOptions {
+                isolation_level: Some("serializable".parse().unwrap()),
+                read_only: true,
+                deferrable: true,
+            }
+            .begin_statement(),
+            "BEGIN ISOLATION LEVEL SERIALIZABLE READ ONLY DEFERRABLE"
+        );
+        assert!("snapshot".parse::<IsolationLevel>().is_err());
     }
 }
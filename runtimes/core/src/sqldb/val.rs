@@ -7,7 +7,7 @@ use uuid::Uuid;
 
 use crate::api::{DateTime, Decimal, PValue};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RowValue {
     PVal(PValue),
     Bytes(Vec<u8>),
@@ -16,8 +16,126 @@ pub enum RowValue {
     Cidr(cidr::IpCidr),
 }
 
-fn is_pgvector(ty: &Type) -> bool {
-    ty.name() == "vector"
+type BoxError = Box<dyn Error + Sync + Send>;
+
+/// Maps a Postgres type without a fixed OID, such as user-defined enums
+/// and types defined by extensions, to and from its JS representation.
+///
+/// To support a new type, add a mapping for it to [CUSTOM_TYPES].
+struct CustomType {
+    /// Reports whether the mapping applies to the given type.
+    accepts: fn(&Type) -> bool,
+    to_sql: fn(&PValue, &Type, &mut BytesMut) -> Result<IsNull, BoxError>,
+    from_sql: fn(&Type, &[u8]) -> Result<PValue, BoxError>,
+}
+
+const CUSTOM_TYPES: &[CustomType] = &[
+    // User-defined enums, represented as strings.
+    CustomType {
+        accepts: |ty| matches!(ty.kind(), Kind::Enum(_)),
+        to_sql: |val, ty, out| match val {
+            PValue::String(str) => str.to_sql(ty, out),
+            _ => Err(format!(
+                "{} not supported for enum column of type {ty}",
+                val.type_name()
+            )
+            .into()),
+        },
+        from_sql: |_, raw| Ok(PValue::String(std::str::from_utf8(raw)?.to_string())),
+    },
+    // Domains, represented the same way as their underlying type.
+    CustomType {
+        accepts: |ty| matches!(ty.kind(), Kind::Domain(inner) if <PValue as ToSql>::accepts(inner)),
+        to_sql: |val, ty, out| match ty.kind() {
+            Kind::Domain(inner) => val.to_sql(inner, out),
+            _ => unreachable!(),
+        },
+        from_sql: |ty, raw| match ty.kind() {
+            Kind::Domain(inner) => PValue::from_sql(inner, raw),
+            _ => unreachable!(),
+        },
+    },
+    // pgvector's vector type, represented as an array of numbers.
+    CustomType {
+        accepts: |ty| ty.name() == "vector",
+        to_sql: vector_to_sql,
+        from_sql: |ty, raw| {
+            let val: pgvector::Vector = FromSql::from_sql(ty, raw)?;
+            let arr = val
+                .as_slice()
+                .iter()
+                .map(|n| match serde_json::Number::from_f64(*n as f64) {
+                    Some(num) => PValue::Number(num),
+                    None => PValue::Null,
+                })
+                .collect();
+            Ok(PValue::Array(arr))
+        },
+    },
+    // The ltree extension's types, represented as strings.
+    // Their binary format is a version number followed by the text representation.
+    CustomType {
+        accepts: |ty| matches!(ty.name(), "ltree" | "lquery" | "ltxtquery"),
+        to_sql: |val, ty, out| match val {
+            PValue::String(str) => {
+                out.put_u8(LTREE_VERSION);
+                out.put_slice(str.as_bytes());
+                Ok(IsNull::No)
+            }
+            _ => Err(format!("{} not supported for column of type {ty}", val.type_name()).into()),
+        },
+        from_sql: |ty, raw| match raw.split_first() {
+            Some((&LTREE_VERSION, text)) => {
+                Ok(PValue::String(std::str::from_utf8(text)?.to_string()))
+            }
+            _ => Err(format!("unsupported binary format for type {ty}").into()),
+        },
+    },
+    // The citext extension's case-insensitive text type.
+    CustomType {
+        accepts: |ty| ty.name() == "citext",
+        to_sql: |val, ty, out| match val {
+            PValue::String(str) => {
+                out.put_slice(str.as_bytes());
+                Ok(IsNull::No)
+            }
+            _ => Err(format!("{} not supported for column of type {ty}", val.type_name()).into()),
+        },
+        from_sql: |_, raw| Ok(PValue::String(std::str::from_utf8(raw)?.to_string())),
+    },
+];
+
+const LTREE_VERSION: u8 = 1;
+
+fn custom_type(ty: &Type) -> Option<&'static CustomType> {
+    CUSTOM_TYPES.iter().find(|c| (c.accepts)(ty))
+}
+
+fn vector_to_sql(val: &PValue, ty: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+    let val = match val {
+        PValue::String(str) => {
+            serde_json::from_str::<pgvector::Vector>(str).context("unable to parse vector")?
+        }
+        PValue::Array(arr) => {
+            let floats = arr
+                .iter()
+                .map(|v| match v {
+                    PValue::Number(n) => n
+                        .as_f64()
+                        .or_else(|| n.as_i64().map(|i| i as f64))
+                        .or_else(|| n.as_u64().map(|u| u as f64))
+                        .map(|f| f as f32)
+                        .ok_or_else(|| "vector element must be a number".into()),
+                    _ => Err("vector element must be a number".into()),
+                })
+                .collect::<Result<Vec<f32>, BoxError>>()?;
+            pgvector::Vector::from(floats)
+        }
+        _ => {
+            return Err(format!("{} not supported for column of type {ty}", val.type_name()).into())
+        }
+    };
+    val.to_sql(ty, out)
 }
 
 impl ToSql for RowValue {
@@ -64,6 +182,13 @@ impl ToSql for PValue {
         ty: &Type,
         out: &mut BytesMut,
     ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        if let Some(custom) = custom_type(ty) {
+            return match self {
+                PValue::Null => Ok(IsNull::Yes),
+                _ => (custom.to_sql)(self, ty, out),
+            };
+        }
+
         match *ty {
             Type::JSON | Type::JSONB => {
                 if *ty == Type::JSONB {
@@ -123,17 +248,7 @@ impl ToSql for PValue {
                         let d = Decimal::from_str(str)?;
                         d.to_sql(ty, out)
                     }
-                    _ => {
-                        if let Kind::Enum(_) = ty.kind() {
-                            str.to_sql(ty, out)
-                        } else if is_pgvector(ty) {
-                            let val: pgvector::Vector =
-                                serde_json::from_str(str).context("unable to parse vector")?;
-                            val.to_sql(ty, out)
-                        } else {
-                            Err(format!("string not supported for column of type {ty}").into())
-                        }
-                    }
+                    _ => Err(format!("string not supported for column of type {ty}").into()),
                 },
 
                 PValue::Number(num) => match *ty {
@@ -289,25 +404,7 @@ impl ToSql for PValue {
                     Type::TEXT | Type::VARCHAR => dt.to_rfc3339().to_sql(ty, out),
                     _ => Err(format!("unsupported type for DateTime: {ty}").into()),
                 },
-                PValue::Array(arr) => {
-                    if is_pgvector(ty) {
-                        let floats = arr
-                            .iter()
-                            .map(|v| match v {
-                                PValue::Number(n) => n
-                                    .as_f64()
-                                    .or_else(|| n.as_i64().map(|i| i as f64))
-                                    .or_else(|| n.as_u64().map(|u| u as f64))
-                                    .map(|f| f as f32)
-                                    .ok_or_else(|| "vector element must be a number".into()),
-                                _ => Err("vector element must be a number".into()),
-                            })
-                            .collect::<Result<Vec<f32>, Box<dyn Error + Sync + Send>>>()?;
-                        pgvector::Vector::from(floats).to_sql(ty, out)
-                    } else {
-                        arr.to_sql(ty, out)
-                    }
-                }
+                PValue::Array(arr) => arr.to_sql(ty, out),
                 PValue::Object(_) => {
                     Err(format!("object not supported for column of type {ty}").into())
                 }
@@ -342,9 +439,8 @@ impl ToSql for PValue {
                 | Type::CIDR
                 | Type::NAME
                 | Type::NUMERIC
-        ) || matches!(ty.kind(), Kind::Enum(_))
-            || matches!(ty.kind(), Kind::Array(ty) if <PValue as ToSql>::accepts(ty))
-            || is_pgvector(ty)
+        ) || matches!(ty.kind(), Kind::Array(ty) if <PValue as ToSql>::accepts(ty))
+            || custom_type(ty).is_some()
     }
     to_sql_checked!();
 }
@@ -467,20 +563,8 @@ impl<'a> FromSql<'a> for PValue {
                 if let Kind::Array(_) = ty.kind() {
                     let val: Vec<_> = FromSql::from_sql(ty, raw)?;
                     PValue::Array(val)
-                } else if let Kind::Enum(_) = ty.kind() {
-                    let val = std::str::from_utf8(raw)?;
-                    PValue::String(val.to_string())
-                } else if is_pgvector(ty) {
-                    let val: pgvector::Vector = FromSql::from_sql(ty, raw)?;
-                    let arr = val
-                        .as_slice()
-                        .iter()
-                        .map(|n| match serde_json::Number::from_f64(*n as f64) {
-                            Some(num) => PValue::Number(num),
-                            None => PValue::Null,
-                        })
-                        .collect();
-                    PValue::Array(arr)
+                } else if let Some(custom) = custom_type(ty) {
+                    (custom.from_sql)(ty, raw)?
                 } else {
                     return Err(format!("unsupported type: {ty:?}").into());
                 }
@@ -514,9 +598,8 @@ impl<'a> FromSql<'a> for PValue {
                 | Type::INET
                 | Type::NAME
                 | Type::NUMERIC
-        ) || matches!(ty.kind(), Kind::Enum(_))
-            || matches!(ty.kind(), Kind::Array(ty) if <PValue as FromSql>::accepts(ty))
-            || is_pgvector(ty)
+        ) || matches!(ty.kind(), Kind::Array(ty) if <PValue as FromSql>::accepts(ty))
+            || custom_type(ty).is_some()
     }
 }
 
@@ -985,4 +1068,71 @@ mod tests {
             panic!("Expected PValue::Array");
         }
     }
+
+    #[test]
+    fn test_ltree_roundtrip() {
+        let ltree_type = Type::new("ltree".to_string(), 0, Kind::Simple, "".to_string());
+
+        let value = PValue::String("Top.Science.Astronomy".to_string());
+        let mut buf = BytesMut::new();
+        value.to_sql(&ltree_type, &mut buf).unwrap();
+        assert_eq!(&buf[..], b"\x01Top.Science.Astronomy");
+
+        let result = PValue::from_sql(&ltree_type, &buf).unwrap();
+        assert_eq!(result, value);
+
+        // Unknown binary format versions are rejected.
+        assert!(PValue::from_sql(&ltree_type, b"\x02Top").is_err());
+    }
+
+    #[test]
+    fn test_enum_and_domain_types() {
+        let enum_type = Type::new(
+            "mood".to_string(),
+            0,
+            Kind::Enum(vec!["happy".to_string(), "sad".to_string()]),
+            "public".to_string(),
+        );
+        let value = PValue::String("happy".to_string());
+        let mut buf = BytesMut::new();
+        value.to_sql(&enum_type, &mut buf).unwrap();
+        assert_eq!(PValue::from_sql(&enum_type, &buf).unwrap(), value);
+
+        let mut buf = BytesMut::new();
+        let result = PValue::Bool(true).to_sql(&enum_type, &mut buf);
+        assert!(result.is_err());
+
+        // Domains use the representation of their underlying type.
+        let domain_type = Type::new(
+            "positive_int".to_string(),
+            0,
+            Kind::Domain(Type::INT4),
+            "public".to_string(),
+        );
+        let value = PValue::Number(serde_json::Number::from(42));
+        let mut buf = BytesMut::new();
+        value.to_sql(&domain_type, &mut buf).unwrap();
+        assert_eq!(PValue::from_sql(&domain_type, &buf).unwrap(), value);
+    }
+
+    #[test]
+    fn test_custom_type_arrays() {
+        let citext_type = Type::new("citext".to_string(), 0, Kind::Simple, "".to_string());
+        let array_type = Type::new(
+            "_citext".to_string(),
+            0,
+            Kind::Array(citext_type),
+            "".to_string(),
+        );
+        assert!(<PValue as ToSql>::accepts(&array_type));
+        assert!(<PValue as FromSql>::accepts(&array_type));
+
+        let value = PValue::Array(vec![
+            PValue::String("Hello".to_string()),
+            PValue::String("World".to_string()),
+        ]);
+        let mut buf = BytesMut::new();
+        value.to_sql(&array_type, &mut buf).unwrap();
+        assert_eq!(PValue::from_sql(&array_type, &buf).unwrap(), value);
+    }
 }
@@ -0,0 +1,184 @@
+use std::future::Future;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::time::Instant;
+
+use crate::encore::runtime::v1 as pb;
+
+/// How long to wait for dependencies if not configured.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The longest to wait between attempts if not configured.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How long to wait after the first failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+type Probe = Box<dyn Fn() -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
+
+/// Infrastructure the app needs to be reachable before it can serve requests,
+/// such as a database.
+pub struct Dependency {
+    name: String,
+    probe: Probe,
+}
+
+impl Dependency {
+    /// Creates a dependency that is reachable once the probe succeeds.
+    pub fn new<F, Fut>(name: impl Into<String>, probe: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            probe: Box::new(move || probe().boxed()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Checks once whether the dependency is reachable.
+    pub async fn check(&self, timeout: Duration) -> anyhow::Result<()> {
+        match tokio::time::timeout(timeout, (self.probe)()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out")),
+        }
+    }
+}
+
+/// Holds back startup until the dependencies are reachable,
+/// retrying with backoff until a timeout.
+///
+/// This tolerates dependencies that start at the same time as the app,
+/// like a database container, instead of failing on the first attempt.
+#[derive(Debug, Clone)]
+pub struct Gate {
+    timeout: Duration,
+    max_backoff: Duration,
+}
+
+impl Gate {
+    pub fn new(cfg: &pb::StartupGate) -> Self {
+        let duration = |d: Option<prost_types::Duration>| {
+            d.and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero())
+        };
+        Self {
+            timeout: duration(cfg.timeout).unwrap_or(DEFAULT_TIMEOUT),
+            max_backoff: duration(cfg.max_backoff).unwrap_or(DEFAULT_MAX_BACKOFF),
+        }
+    }
+
+    /// Waits until all dependencies are reachable.
+    /// Returns an error describing the unreachable ones if the timeout passes first.
+    pub async fn wait(&self, deps: Vec<Dependency>) -> anyhow::Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        let results =
+            futures::future::join_all(deps.iter().map(|dep| self.wait_for(dep, deadline))).await;
+
+        let failed: Vec<_> = deps
+            .iter()
+            .zip(results)
+            .filter_map(|(dep, res)| res.err().map(|err| format!("{}: {err:#}", dep.name)))
+            .collect();
+        if !failed.is_empty() {
+            anyhow::bail!(
+                "dependencies not reachable after {:?}: {}",
+                self.timeout,
+                failed.join("; ")
+            );
+        }
+        Ok(())
+    }
+
+    async fn wait_for(&self, dep: &Dependency, deadline: Instant) -> anyhow::Result<()> {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let err = match tokio::time::timeout_at(deadline, (dep.probe)()).await {
+                Ok(Ok(())) => {
+                    log::debug!("startup: {} is reachable", dep.name);
+                    return Ok(());
+                }
+                Ok(Err(err)) => err,
+                Err(_) => anyhow::anyhow!("timed out"),
+            };
+
+            if Instant::now() + backoff >= deadline {
+                return Err(err);
+            }
+            log::info!(
+                "startup: waiting for {} ({err:#}), retrying in {backoff:?}",
+                dep.name
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn secs(s: i64) -> Option<prost_types::Duration> {
+        Some(prost_types::Duration {
+            seconds: s,
+            nanos: 0,
+        })
+    }
+
+    /// A dependency that becomes reachable after the given number of attempts.
+    fn flaky(failures: u32) -> (Dependency, Arc<AtomicU32>) {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let dep = Dependency::new("db", move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if n < failures {
+                    anyhow::bail!("connection refused");
+                }
+                Ok(())
+            }
+        });
+        (dep, attempts)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_until_reachable() {
+        let gate = Gate::new(&pb::StartupGate {
+            timeout: secs(10),
+            max_backoff: secs(1),
+        });
+        let (dep, attempts) = flaky(5);
+
+        let start = Instant::now();
+        gate.wait(vec![dep]).await.unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 6);
+
+        // 100ms, 200ms, 400ms, 800ms, then capped at 1s.
+        assert_eq!(start.elapsed(), Duration::from_millis(2500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out() {
+        let gate = Gate::new(&pb::StartupGate {
+            timeout: secs(3),
+            ..Default::default()
+        });
+        let (dep, _) = flaky(u32::MAX);
+
+        // Gives up once the next attempt would be past the timeout.
+        let start = Instant::now();
+        let err = gate.wait(vec![dep]).await.unwrap_err();
+        assert!(err.to_string().contains("db: connection refused"));
+        assert_eq!(start.elapsed(), Duration::from_millis(1500));
+    }
+}
@@ -0,0 +1,352 @@
+//! Serves live logs and trace events over gRPC, so a local dashboard
+//! or a collector can subscribe to them in self-hosted setups.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::Context;
+use futures::Stream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Service, StdError};
+use tonic::metadata::MetadataValue;
+use tonic::Status;
+
+use crate::encore::runtime::v1 as pb;
+use crate::log::LogLine;
+use crate::secrets;
+use crate::trace::protocol::TRACE_VERSION;
+use crate::trace::EventStream;
+
+/// How many log lines and trace events are buffered for subscribers
+/// that fall behind, before they skip ahead.
+const LOG_CAPACITY: usize = 4096;
+const TRACE_CAPACITY: usize = 16384;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// The telemetry stream server.
+pub struct Server {
+    addr: SocketAddr,
+    svc: TelemetryService,
+}
+
+impl Server {
+    /// Creates a new server, and starts broadcasting the logs of the global logger.
+    /// Trace events are broadcast by tracers using [Server::trace_stream].
+    pub fn new(cfg: &pb::TelemetryStream, secrets: &secrets::Manager) -> anyhow::Result<Self> {
+        let addr = cfg
+            .listen_addr
+            .parse()
+            .with_context(|| format!("invalid listen address {:?}", cfg.listen_addr))?;
+        let token = match &cfg.auth_token {
+            Some(data) => Some(
+                secrets
+                    .load(data.clone())
+                    .get()
+                    .context("unable to resolve auth token")?
+                    .to_vec(),
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            addr,
+            svc: TelemetryService {
+                inner: Arc::new(Inner {
+                    token,
+                    logs: crate::log::stream(LOG_CAPACITY),
+                    traces: EventStream::new(TRACE_CAPACITY),
+                }),
+            },
+        })
+    }
+
+    /// The stream tracers broadcast trace events to.
+    pub fn trace_stream(&self) -> EventStream {
+        self.svc.inner.traces.clone()
+    }
+
+    /// Starts serving subscribers on the given runtime.
+    pub fn start(self, rt: &tokio::runtime::Handle) {
+        let Self { addr, svc } = self;
+        rt.spawn(async move {
+            log::info!("telemetry: serving live logs and traces on {addr}");
+            let result = tonic::transport::Server::builder()
+                .add_service(svc)
+                .serve(addr)
+                .await;
+            if let Err(err) = result {
+                log::error!("telemetry: server failed: {err:#}");
+            }
+        });
+    }
+}
+
+struct Inner {
+    token: Option<Vec<u8>>,
+    logs: broadcast::Sender<LogLine>,
+    traces: EventStream,
+}
+
+impl Inner {
+    fn authorize<T>(&self, req: &tonic::Request<T>) -> Result<(), Status> {
+        let Some(token) = &self.token else {
+            return Ok(());
+        };
+        let provided = req
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::as_bytes);
+        match provided {
+            Some(provided)
+                if provided.len() == token.len() && openssl::memcmp::eq(provided, token) =>
+            {
+                Ok(())
+            }
+            _ => Err(Status::unauthenticated("invalid auth token")),
+        }
+    }
+
+    fn stream_logs(
+        &self,
+        req: tonic::Request<pb::StreamLogsRequest>,
+    ) -> Result<tonic::Response<ResponseStream<pb::LogEntry>>, Status> {
+        self.authorize(&req)?;
+        let min_level = match req.get_ref().min_level() {
+            pb::log_entry::Level::Unspecified => None,
+            level => Some(to_log_level(level)),
+        };
+
+        let mut rx = self.logs.subscribe();
+        let stream = async_stream::stream! {
+            let mut skipped = 0;
+            loop {
+                match rx.recv().await {
+                    Ok(line) => {
+                        // More severe levels compare as smaller.
+                        if min_level.is_some_and(|min| line.level > min) {
+                            continue;
+                        }
+                        yield Ok(pb::LogEntry {
+                            level: from_log_level(line.level) as i32,
+                            json: line.json.to_vec(),
+                            skipped,
+                        });
+                        skipped = 0;
+                    }
+                    Err(RecvError::Lagged(n)) => skipped += n,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+        Ok(tonic::Response::new(Box::pin(stream)))
+    }
+
+    fn stream_traces(
+        &self,
+        req: tonic::Request<pb::StreamTracesRequest>,
+    ) -> Result<tonic::Response<ResponseStream<pb::TraceEvent>>, Status> {
+        self.authorize(&req)?;
+
+        let mut rx = self.traces.subscribe();
+        let stream = async_stream::stream! {
+            let mut skipped = 0;
+            loop {
+                match rx.recv().await {
+                    Ok(data) => {
+                        yield Ok(pb::TraceEvent {
+                            data: data.to_vec(),
+                            skipped,
+                        });
+                        skipped = 0;
+                    }
+                    Err(RecvError::Lagged(n)) => skipped += n,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+
+        let mut resp = tonic::Response::new(Box::pin(stream) as ResponseStream<_>);
+        let md = resp.metadata_mut();
+        md.insert("x-encore-trace-version", MetadataValue::from(TRACE_VERSION));
+        md.insert(
+            "x-encore-trace-timeanchor",
+            self.traces
+                .trace_header()
+                .parse()
+                .map_err(|_| Status::internal("invalid time anchor"))?,
+        );
+        Ok(resp)
+    }
+}
+
+fn to_log_level(level: pb::log_entry::Level) -> log::Level {
+    use pb::log_entry::Level;
+    match level {
+        Level::Trace | Level::Unspecified => log::Level::Trace,
+        Level::Debug => log::Level::Debug,
+        Level::Info => log::Level::Info,
+        Level::Warn => log::Level::Warn,
+        Level::Error => log::Level::Error,
+    }
+}
+
+fn from_log_level(level: log::Level) -> pb::log_entry::Level {
+    use pb::log_entry::Level;
+    match level {
+        log::Level::Trace => Level::Trace,
+        log::Level::Debug => Level::Debug,
+        log::Level::Info => Level::Info,
+        log::Level::Warn => Level::Warn,
+        log::Level::Error => Level::Error,
+    }
+}
+
+/// Implements the encore.runtime.v1.Telemetry gRPC service.
+#[derive(Clone)]
+struct TelemetryService {
+    inner: Arc<Inner>,
+}
+
+impl tonic::server::NamedService for TelemetryService {
+    const NAME: &'static str = "encore.runtime.v1.Telemetry";
+}
+
+impl<B> Service<http::Request<B>> for TelemetryService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let inner = self.inner.clone();
+        match req.uri().path() {
+            "/encore.runtime.v1.Telemetry/StreamLogs" => Box::pin(async move {
+                let codec = tonic::codec::ProstCodec::default();
+                let mut grpc = tonic::server::Grpc::new(codec);
+                Ok(grpc.server_streaming(StreamLogs(inner), req).await)
+            }),
+            "/encore.runtime.v1.Telemetry/StreamTraces" => Box::pin(async move {
+                let codec = tonic::codec::ProstCodec::default();
+                let mut grpc = tonic::server::Grpc::new(codec);
+                Ok(grpc.server_streaming(StreamTraces(inner), req).await)
+            }),
+            _ => Box::pin(async move {
+                // Respond with the UNIMPLEMENTED status code.
+                Ok(http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", "12")
+                    .header("content-type", "application/grpc")
+                    .body(empty_body())
+                    .unwrap())
+            }),
+        }
+    }
+}
+
+struct StreamLogs(Arc<Inner>);
+
+impl tonic::server::ServerStreamingService<pb::StreamLogsRequest> for StreamLogs {
+    type Response = pb::LogEntry;
+    type ResponseStream = ResponseStream<pb::LogEntry>;
+    type Future = BoxFuture<tonic::Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, req: tonic::Request<pb::StreamLogsRequest>) -> Self::Future {
+        let result = self.0.stream_logs(req);
+        Box::pin(async move { result })
+    }
+}
+
+struct StreamTraces(Arc<Inner>);
+
+impl tonic::server::ServerStreamingService<pb::StreamTracesRequest> for StreamTraces {
+    type Response = pb::TraceEvent;
+    type ResponseStream = ResponseStream<pb::TraceEvent>;
+    type Future = BoxFuture<tonic::Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, req: tonic::Request<pb::StreamTracesRequest>) -> Self::Future {
+        let result = self.0.stream_traces(req);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(token: Option<&str>) -> Inner {
+        Inner {
+            token: token.map(|t| t.as_bytes().to_vec()),
+            logs: broadcast::channel(4).0,
+            traces: EventStream::new(4),
+        }
+    }
+
+    fn request(auth: Option<&str>) -> tonic::Request<()> {
+        let mut req = tonic::Request::new(());
+        if let Some(auth) = auth {
+            req.metadata_mut()
+                .insert("authorization", auth.parse().unwrap());
+        }
+        req
+    }
+
+    #[test]
+    fn test_authorize() {
+        assert!(inner(None).authorize(&request(None)).is_ok());
+
+        let inner = inner(Some("secret"));
+        assert!(inner.authorize(&request(Some("Bearer secret"))).is_ok());
+        assert!(inner.authorize(&request(Some("Bearer secreT"))).is_err());
+        assert!(inner.authorize(&request(Some("Bearer sec"))).is_err());
+        assert!(inner.authorize(&request(Some("secret"))).is_err());
+        assert!(inner.authorize(&request(None)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_logs_skipped() {
+        use futures::StreamExt;
+
+        let inner = inner(None);
+        let req = tonic::Request::new(pb::StreamLogsRequest {
+            min_level: pb::log_entry::Level::Warn as i32,
+        });
+        let mut stream = inner.stream_logs(req).unwrap().into_inner();
+
+        let line = |level| LogLine {
+            level,
+            json: bytes::Bytes::from_static(b"{}"),
+        };
+        // Overflow the buffer of 4 so the first two lines are skipped.
+        for level in [
+            log::Level::Error,
+            log::Level::Error,
+            log::Level::Info,
+            log::Level::Warn,
+            log::Level::Error,
+            log::Level::Debug,
+        ] {
+            inner.logs.send(line(level)).unwrap();
+        }
+
+        let entry = stream.next().await.unwrap().unwrap();
+        assert_eq!(entry.level(), pb::log_entry::Level::Warn);
+        assert_eq!(entry.skipped, 2);
+        let entry = stream.next().await.unwrap().unwrap();
+        assert_eq!(entry.level(), pb::log_entry::Level::Error);
+        assert_eq!(entry.skipped, 0);
+    }
+}
@@ -3,12 +3,14 @@ use std::sync::Arc;
 use crate::api::reqauth::platform;
 use bytes::Bytes;
 use futures::StreamExt;
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use crate::model;
 use crate::trace::eventbuf::signed_to_unsigned_i64;
 use crate::trace::protocol::{EventType, TRACE_VERSION};
+use crate::trace::sampling::Sampler;
 use crate::trace::time_anchor::TimeAnchor;
 use crate::trace::Tracer;
 
@@ -25,21 +27,54 @@ pub struct ReporterConfig {
 #[must_use]
 pub struct Reporter {
     rx: tokio::sync::mpsc::UnboundedReceiver<TraceEvent>,
+    flush_tx: tokio::sync::mpsc::UnboundedSender<oneshot::Sender<()>>,
+    flush_rx: tokio::sync::mpsc::UnboundedReceiver<oneshot::Sender<()>>,
     anchor: TimeAnchor,
     http_client: reqwest::Client,
     config: ReporterConfig,
 }
 
+/// Flushes the trace events buffered by a [Reporter].
+#[derive(Debug, Clone)]
+pub struct Flusher {
+    tx: tokio::sync::mpsc::UnboundedSender<oneshot::Sender<()>>,
+}
+
+impl Flusher {
+    pub(super) fn new(tx: tokio::sync::mpsc::UnboundedSender<oneshot::Sender<()>>) -> Self {
+        Self { tx }
+    }
+
+    /// Sends all trace events recorded so far to the trace server,
+    /// and waits for the trace server to receive them.
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(done_tx).is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+/// An in-progress streaming request to the trace server.
+struct TraceRequest {
+    sender: UnboundedSender<TraceEvent>,
+    response: tokio::task::JoinHandle<()>,
+}
+
 pub fn streaming_tracer(
     http_client: reqwest::Client,
     config: ReporterConfig,
+    sampler: Option<Sampler>,
 ) -> (Tracer, Reporter) {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-    let tracer = Tracer::new(tx);
+    let tracer = Tracer::new(tx, sampler);
+    let (flush_tx, flush_rx) = tokio::sync::mpsc::unbounded_channel();
 
     let anchor = TimeAnchor::new();
     let reporter = Reporter {
         rx,
+        flush_tx,
+        flush_rx,
         anchor,
         http_client,
         config,
@@ -47,7 +82,7 @@ pub fn streaming_tracer(
     (tracer, reporter)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(super) struct TraceEvent {
     pub typ: EventType,
     pub id: model::TraceEventId,
@@ -57,6 +92,13 @@ pub(super) struct TraceEvent {
 }
 
 impl Reporter {
+    /// Returns a handle for flushing the buffered trace events.
+    pub fn flusher(&self) -> Flusher {
+        Flusher {
+            tx: self.flush_tx.clone(),
+        }
+    }
+
     /// Starts reporting trace events to the trace server.
     ///
     /// This method runs in an infinite loop until all senders are dropped,
@@ -71,7 +113,7 @@ impl Reporter {
         };
 
         loop {
-            let mut body_sender = None;
+            let mut request = None;
 
             let timeout_duration = std::time::Duration::from_millis(1000);
             let mut no_data_timeout = Box::pin(tokio::time::sleep(timeout_duration));
@@ -81,23 +123,8 @@ impl Reporter {
                     event = self.rx.recv() => {
                         match event {
                             Some(event) => {
-                                // Wait for at least one entry on rx before we open a HTTP request.
-                                if body_sender.is_none() {
-                                    match self.setup_trace_request(&trace_headers) {
-                                        Ok(sender) => body_sender = Some(sender),
-                                        Err(err) => {
-                                            log::error!("failed to create request: {err}");
-                                            break;
-                                        }
-                                    }
-                                }
-
-                                // Add the event to the stream
-                                if let Some(sender) = &body_sender {
-                                    if let Err(err) = sender.send(event) {
-                                        log::error!("failed to stream event: {err}");
-                                        break;
-                                    }
+                                if !self.send_event(&mut request, &trace_headers, event) {
+                                    break;
                                 }
 
                                 // Reset the timeout
@@ -110,11 +137,26 @@ impl Reporter {
                             }
                         }
                     }
+                    Some(done) = self.flush_rx.recv() => {
+                        // Send the events buffered so far, and wait for the
+                        // trace server to receive them.
+                        while let Ok(event) = self.rx.try_recv() {
+                            if !self.send_event(&mut request, &trace_headers, event) {
+                                break;
+                            }
+                        }
+                        if let Some(TraceRequest { sender, response }) = request {
+                            drop(sender);
+                            let _ = response.await;
+                        }
+                        let _ = done.send(());
+                        break;
+                    }
                     _ = &mut no_data_timeout => {
                         // Timeout reached with no new events
-                        if let Some(sender) = body_sender {
+                        if let Some(request) = request {
                             // Close the stream and wait for a new event
-                            drop(sender);
+                            drop(request.sender);
                         }
                         break;
                     }
@@ -123,6 +165,35 @@ impl Reporter {
         }
     }
 
+    /// Adds the event to the current trace request, starting a new request if necessary.
+    /// Reports whether the event was sent.
+    fn send_event(
+        &self,
+        request: &mut Option<TraceRequest>,
+        trace_headers: &reqwest::header::HeaderMap,
+        event: TraceEvent,
+    ) -> bool {
+        // Wait for at least one entry on rx before we open a HTTP request.
+        if request.is_none() {
+            match self.setup_trace_request(trace_headers) {
+                Ok(r) => *request = Some(r),
+                Err(err) => {
+                    log::error!("failed to create request: {err}");
+                    return false;
+                }
+            }
+        }
+
+        // Add the event to the stream
+        if let Some(request) = request {
+            if let Err(err) = request.sender.send(event) {
+                log::error!("failed to stream event: {err}");
+                return false;
+            }
+        }
+        true
+    }
+
     fn create_body_stream(
         &self,
         rx: UnboundedReceiver<TraceEvent>,
@@ -140,7 +211,7 @@ impl Reporter {
     fn setup_trace_request(
         &self,
         trace_headers: &reqwest::header::HeaderMap,
-    ) -> Result<tokio::sync::mpsc::UnboundedSender<TraceEvent>, String> {
+    ) -> Result<TraceRequest, String> {
         {
             let http_client: &reqwest::Client = &self.http_client;
             let endpoint: &reqwest::Url = &self.config.trace_endpoint;
@@ -166,7 +237,7 @@ impl Reporter {
 
             // Start the request
             let request = http_client.execute(req);
-            tokio::spawn(async move {
+            let response = tokio::spawn(async move {
                 match request.await {
                     Ok(resp) if !resp.status().is_success() => {
                         let status = resp.status();
@@ -180,7 +251,10 @@ impl Reporter {
                 }
             });
 
-            Ok(tx)
+            Ok(TraceRequest {
+                sender: tx,
+                response,
+            })
         }
     }
 
@@ -219,13 +293,13 @@ impl Reporter {
 
 /// Represents a trace event that is being streamed.
 #[derive(Debug)]
-struct StreamingTraceEvent {
+pub(super) struct StreamingTraceEvent {
     /// The event itself.
-    event: TraceEvent,
+    pub event: TraceEvent,
 }
 
 impl StreamingTraceEvent {
-    fn header(&self, anchor: &TimeAnchor) -> Bytes {
+    pub fn header(&self, anchor: &TimeAnchor) -> Bytes {
         let event_type = self.event.typ;
         let event_id = self.event.id.0;
         let trace_id = &self.event.span.0 .0;
@@ -306,9 +380,12 @@ mod tests {
 
     fn setup_reporter() -> Reporter {
         let (_tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let (flush_tx, flush_rx) = tokio::sync::mpsc::unbounded_channel();
 
         Reporter {
             rx,
+            flush_tx,
+            flush_rx,
             anchor: TimeAnchor::new(),
             http_client: reqwest::Client::new(),
             config: ReporterConfig {
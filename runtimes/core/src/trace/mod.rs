@@ -1,7 +1,25 @@
 mod eventbuf;
 mod log;
+pub mod otlp;
 pub mod protocol;
+mod sampling;
+mod sink;
+mod sqlite;
+mod stream;
 mod time_anchor;
 
-pub use log::{streaming_tracer, ReporterConfig};
+pub use log::{streaming_tracer, Flusher, ReporterConfig};
 pub use protocol::Tracer;
+pub use sampling::Sampler;
+pub use sink::{SinkReporter, SinkSender};
+pub use stream::EventStream;
+
+use crate::encore::runtime::v1 as pb;
+
+/// Creates a sink storing trace events in a local SQLite database.
+pub fn local_sink(
+    cfg: &pb::tracing_provider::LocalTracingProvider,
+) -> anyhow::Result<(SinkSender, SinkReporter)> {
+    let sink = sqlite::SqliteSink::open(cfg)?;
+    Ok(sink::sink_reporter(Box::new(sink), cfg.sampling_rate))
+}
@@ -0,0 +1,545 @@
+//! Exports request spans using the OpenTelemetry Protocol (OTLP).
+//! See https://opentelemetry.io/docs/specs/otlp/.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::Context;
+use prost::Message;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue, MetadataMap};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+
+use crate::encore::runtime::v1 as pb;
+use crate::model;
+use crate::secrets;
+use crate::trace::sampling::is_sampled;
+use crate::trace::Flusher;
+
+/// How often buffered spans are exported.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The number of buffered spans that triggers an export.
+const MAX_BATCH_SIZE: usize = 512;
+
+const GRPC_EXPORT_PATH: &str = "/opentelemetry.proto.collector.trace.v1.TraceService/Export";
+const HTTP_EXPORT_PATH: &str = "v1/traces";
+
+// OTLP span kinds.
+const SPAN_KIND_SERVER: i32 = 2;
+const SPAN_KIND_CONSUMER: i32 = 5;
+
+// OTLP status codes.
+const STATUS_CODE_ERROR: i32 = 2;
+
+/// Hands ended request spans to a [Reporter] for export.
+#[derive(Debug, Clone)]
+pub struct SpanExporter {
+    tx: UnboundedSender<(String, Span)>,
+    rate: f64,
+}
+
+impl SpanExporter {
+    /// Exports the span of the response's request, if its trace is sampled.
+    pub(super) fn export(&self, resp: &model::Response) {
+        if !is_sampled(resp.request.span.0, self.rate) {
+            return;
+        }
+        _ = self.tx.send(span_from_response(resp));
+    }
+}
+
+/// Sends spans to an OTLP endpoint in batches.
+#[must_use]
+pub struct Reporter {
+    rx: UnboundedReceiver<(String, Span)>,
+    flush_tx: UnboundedSender<oneshot::Sender<()>>,
+    flush_rx: UnboundedReceiver<oneshot::Sender<()>>,
+    transport: Transport,
+
+    /// Resource attributes shared by all services.
+    resource: Vec<KeyValue>,
+}
+
+/// Sends export requests to an OTLP endpoint over gRPC or HTTP.
+pub(crate) enum Transport {
+    Grpc {
+        endpoint: Endpoint,
+        /// Connected on first use, as connecting requires a runtime.
+        channel: OnceLock<Channel>,
+        headers: MetadataMap,
+    },
+    Http {
+        client: reqwest::Client,
+        url: reqwest::Url,
+        headers: reqwest::header::HeaderMap,
+    },
+}
+
+impl Transport {
+    /// Creates a transport to the endpoint. With HTTP, requests are sent
+    /// to `http_path` of the endpoint, unless it already ends with it.
+    pub(crate) fn new(
+        http_client: reqwest::Client,
+        endpoint: &str,
+        grpc: bool,
+        http_path: &str,
+        headers: &HashMap<String, pb::SecretData>,
+        secrets: &secrets::Manager,
+    ) -> anyhow::Result<Self> {
+        let mut resolved = Vec::with_capacity(headers.len());
+        for (name, data) in headers {
+            let secret = secrets.load(data.clone());
+            let value = secret
+                .get()
+                .with_context(|| format!("unable to resolve header {name}"))?;
+            resolved.push((name.to_ascii_lowercase(), value.to_vec()));
+        }
+
+        if grpc {
+            let mut endpoint =
+                Endpoint::from_shared(endpoint.to_string()).context("invalid endpoint")?;
+            if endpoint.uri().scheme_str() == Some("https") {
+                endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+            }
+            let mut metadata = MetadataMap::new();
+            for (name, value) in resolved {
+                metadata.insert(
+                    AsciiMetadataKey::from_bytes(name.as_bytes())?,
+                    AsciiMetadataValue::try_from(value.as_slice())?,
+                );
+            }
+            Ok(Transport::Grpc {
+                endpoint,
+                channel: OnceLock::new(),
+                headers: metadata,
+            })
+        } else {
+            let mut url = reqwest::Url::parse(endpoint).context("invalid endpoint")?;
+            if !url.path().ends_with(http_path) {
+                url.path_segments_mut()
+                    .map_err(|_| anyhow::anyhow!("invalid endpoint"))?
+                    .pop_if_empty()
+                    .extend(http_path.split('/'));
+            }
+            let mut header_map = reqwest::header::HeaderMap::new();
+            for (name, value) in resolved {
+                header_map.insert(
+                    reqwest::header::HeaderName::from_bytes(name.as_bytes())?,
+                    reqwest::header::HeaderValue::from_bytes(&value)?,
+                );
+            }
+            Ok(Transport::Http {
+                client: http_client,
+                url,
+                headers: header_map,
+            })
+        }
+    }
+
+    /// Sends the request, using the gRPC method at `grpc_path` with gRPC.
+    pub(crate) async fn send<Req, Resp>(
+        &self,
+        req: Req,
+        grpc_path: &'static str,
+    ) -> anyhow::Result<()>
+    where
+        Req: Message + Send + Sync + 'static,
+        Resp: Message + Default + Send + Sync + 'static,
+    {
+        match self {
+            Transport::Grpc {
+                endpoint,
+                channel,
+                headers,
+            } => {
+                let channel = channel.get_or_init(|| endpoint.connect_lazy()).clone();
+                let mut grpc = tonic::client::Grpc::new(channel);
+                grpc.ready().await?;
+
+                let mut req = tonic::Request::new(req);
+                *req.metadata_mut() = headers.clone();
+                let path = PathAndQuery::from_static(grpc_path);
+                let codec = tonic::codec::ProstCodec::<Req, Resp>::default();
+                grpc.unary(req, path, codec).await?;
+                Ok(())
+            }
+            Transport::Http {
+                client,
+                url,
+                headers,
+            } => {
+                let resp = client
+                    .post(url.clone())
+                    .headers(headers.clone())
+                    .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+                    .body(req.encode_to_vec())
+                    .send()
+                    .await?;
+                if !resp.status().is_success() {
+                    anyhow::bail!("export failed with status {}", resp.status());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn otlp_exporter(
+    http_client: reqwest::Client,
+    cfg: &pb::tracing_provider::OtlpTracingProvider,
+    secrets: &secrets::Manager,
+    environment: &pb::Environment,
+    app_commit: &str,
+) -> anyhow::Result<(SpanExporter, Reporter)> {
+    use pb::tracing_provider::otlp_tracing_provider::Protocol;
+
+    let transport = Transport::new(
+        http_client,
+        &cfg.endpoint,
+        cfg.protocol() == Protocol::Grpc,
+        HTTP_EXPORT_PATH,
+        &cfg.headers,
+        secrets,
+    )?;
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let (flush_tx, flush_rx) = tokio::sync::mpsc::unbounded_channel();
+    let exporter = SpanExporter {
+        tx,
+        rate: cfg.sampling_rate.unwrap_or(1.0).clamp(0.0, 1.0),
+    };
+    let reporter = Reporter {
+        rx,
+        flush_tx,
+        flush_rx,
+        transport,
+        resource: vec![
+            KeyValue::string("deployment.environment.name", &environment.env_name),
+            KeyValue::string("service.namespace", &environment.app_slug),
+            KeyValue::string("service.version", app_commit),
+        ],
+    };
+    Ok((exporter, reporter))
+}
+
+impl Reporter {
+    /// Returns a handle for flushing the buffered spans.
+    pub fn flusher(&self) -> Flusher {
+        Flusher::new(self.flush_tx.clone())
+    }
+
+    /// Starts exporting spans to the endpoint.
+    ///
+    /// This method runs in an infinite loop until all senders are dropped,
+    /// exporting the buffered spans periodically or when the batch is full.
+    pub async fn start_reporting(mut self) {
+        let mut batch = Vec::new();
+        let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+        loop {
+            tokio::select! {
+                span = self.rx.recv() => match span {
+                    Some(span) => {
+                        batch.push(span);
+                        if batch.len() >= MAX_BATCH_SIZE {
+                            self.export(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                    None => {
+                        self.export(batch).await;
+                        return;
+                    }
+                },
+                Some(done) = self.flush_rx.recv() => {
+                    while let Ok(span) = self.rx.try_recv() {
+                        batch.push(span);
+                    }
+                    self.export(std::mem::take(&mut batch)).await;
+                    let _ = done.send(());
+                }
+                _ = interval.tick() => {
+                    self.export(std::mem::take(&mut batch)).await;
+                }
+            }
+        }
+    }
+
+    async fn export(&self, batch: Vec<(String, Span)>) {
+        if batch.is_empty() {
+            return;
+        }
+        let count = batch.len();
+        let req = self.build_request(batch);
+        let result = self
+            .transport
+            .send::<_, ExportTraceServiceResponse>(req, GRPC_EXPORT_PATH)
+            .await;
+        if let Err(err) = result {
+            log::warn!("unable to export {count} spans: {err:#}");
+        }
+    }
+
+    /// Groups the spans into a resource per service.
+    fn build_request(&self, batch: Vec<(String, Span)>) -> ExportTraceServiceRequest {
+        let mut by_service: HashMap<String, Vec<Span>> = HashMap::new();
+        for (service, span) in batch {
+            by_service.entry(service).or_default().push(span);
+        }
+
+        ExportTraceServiceRequest {
+            resource_spans: by_service
+                .into_iter()
+                .map(|(service, spans)| {
+                    let mut attributes = self.resource.clone();
+                    attributes.push(KeyValue::string("service.name", &service));
+                    ResourceSpans {
+                        resource: Some(Resource { attributes }),
+                        scope_spans: vec![ScopeSpans {
+                            scope: Some(InstrumentationScope {
+                                name: "encore".to_string(),
+                                version: String::new(),
+                            }),
+                            spans,
+                        }],
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Converts the response's request into a span,
+/// returning it together with the name of the service it belongs to.
+fn span_from_response(resp: &model::Response) -> (String, Span) {
+    let req = resp.request.as_ref();
+
+    let (service, name, kind, mut attributes) = match &req.data {
+        model::RequestData::RPC(rpc) => {
+            let (service, endpoint) = (rpc.endpoint.name.service(), rpc.endpoint.name.endpoint());
+            (
+                service,
+                format!("{service}.{endpoint}"),
+                SPAN_KIND_SERVER,
+                vec![
+                    KeyValue::string("encore.endpoint", endpoint),
+                    KeyValue::string("http.request.method", rpc.method.as_str()),
+                    KeyValue::string("url.path", &rpc.path),
+                ],
+            )
+        }
+        model::RequestData::Stream(data) => {
+            let (service, endpoint) = (data.endpoint.name.service(), data.endpoint.name.endpoint());
+            (
+                service,
+                format!("{service}.{endpoint}"),
+                SPAN_KIND_SERVER,
+                vec![
+                    KeyValue::string("encore.endpoint", endpoint),
+                    KeyValue::string("url.path", &data.path),
+                ],
+            )
+        }
+        model::RequestData::Auth(auth) => {
+            let (service, handler) = (auth.auth_handler.service(), auth.auth_handler.endpoint());
+            (
+                service,
+                format!("{service}.{handler}"),
+                SPAN_KIND_SERVER,
+                vec![KeyValue::string("encore.auth_handler", handler)],
+            )
+        }
+        model::RequestData::PubSub(msg) => (
+            msg.service.as_str(),
+            format!("{}.{}", msg.service, msg.subscription),
+            SPAN_KIND_CONSUMER,
+            vec![
+                KeyValue::string("messaging.system", "encore"),
+                KeyValue::string("messaging.destination.name", &msg.topic),
+                KeyValue::string("messaging.consumer.group.name", &msg.subscription),
+                KeyValue::string("messaging.message.id", &msg.message_id),
+                KeyValue::int("encore.pubsub.attempt", msg.attempt as i64),
+            ],
+        ),
+    };
+
+    let err = match &resp.data {
+        model::ResponseData::RPC(rpc) => {
+            attributes.push(KeyValue::int(
+                "http.response.status_code",
+                rpc.status_code as i64,
+            ));
+            rpc.error.as_ref()
+        }
+        model::ResponseData::Auth(res) => res.as_ref().err(),
+        model::ResponseData::PubSub(res) => res.as_ref().err(),
+    };
+    let status = err.map(|err| {
+        attributes.push(KeyValue::string("encore.error.code", &err.code.to_string()));
+        Status {
+            message: err.message.clone(),
+            code: STATUS_CODE_ERROR,
+        }
+    });
+
+    let start = req
+        .start_time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let end = start + resp.duration;
+
+    let span = Span {
+        trace_id: req.span.0 .0.to_vec(),
+        span_id: req.span.1 .0.to_vec(),
+        parent_span_id: req
+            .parent_span
+            .map(|parent| parent.1 .0.to_vec())
+            .unwrap_or_default(),
+        name,
+        kind,
+        start_time_unix_nano: start.as_nanos() as u64,
+        end_time_unix_nano: end.as_nanos() as u64,
+        attributes,
+        status,
+    };
+    (service.to_string(), span)
+}
+
+// The subset of the OTLP trace protos used for exporting.
+// See https://github.com/open-telemetry/opentelemetry-proto.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportTraceServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportTraceServiceResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ResourceSpans {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScopeSpans {
+    #[prost(message, optional, tag = "1")]
+    pub scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    pub spans: Vec<Span>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InstrumentationScope {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Span {
+    #[prost(bytes = "vec", tag = "1")]
+    pub trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub span_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub parent_span_id: Vec<u8>,
+    #[prost(string, tag = "5")]
+    pub name: String,
+    #[prost(int32, tag = "6")]
+    pub kind: i32,
+    #[prost(fixed64, tag = "7")]
+    pub start_time_unix_nano: u64,
+    #[prost(fixed64, tag = "8")]
+    pub end_time_unix_nano: u64,
+    #[prost(message, repeated, tag = "9")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(message, optional, tag = "15")]
+    pub status: Option<Status>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Status {
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(int32, tag = "3")]
+    pub code: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+impl KeyValue {
+    pub(crate) fn string(key: &str, value: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(value.to_string())),
+            }),
+        }
+    }
+
+    pub(crate) fn int(key: &str, value: i64) -> Self {
+        Self {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::IntValue(value)),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AnyValue {
+    #[prost(oneof = "any_value::Value", tags = "1, 3")]
+    pub value: Option<any_value::Value>,
+}
+
+pub mod any_value {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(string, tag = "1")]
+        StringValue(String),
+        #[prost(int64, tag = "3")]
+        IntValue(i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_value_encoding() {
+        // Encoded as in the OTLP protos: {key: "k", value: {string_value: "v"}}.
+        let kv = KeyValue::string("k", "v");
+        assert_eq!(
+            kv.encode_to_vec(),
+            vec![0x0a, 0x01, b'k', 0x12, 0x03, 0x0a, 0x01, b'v']
+        );
+
+        let kv = KeyValue::int("n", 5);
+        assert_eq!(
+            kv.encode_to_vec(),
+            vec![0x0a, 0x01, b'n', 0x12, 0x02, 0x18, 0x05]
+        );
+    }
+}
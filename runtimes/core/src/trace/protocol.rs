@@ -2,12 +2,17 @@
 //! Implements the trace protocol.
 
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use crate::api::reqauth::meta::HeaderValueExt;
 use crate::api::{self, PValue};
 use crate::model::{LogField, LogFieldValue, Request, TraceEventId};
 use crate::trace::eventbuf::EventBuffer;
 use crate::trace::log::TraceEvent;
+use crate::trace::otlp::SpanExporter;
+use crate::trace::sampling::Sampler;
+use crate::trace::sink::SinkSender;
+use crate::trace::stream::EventStream;
 use crate::{model, objects, EncoreName};
 
 /// Represents a type of trace event.
@@ -56,17 +61,71 @@ static EVENT_ID: AtomicU64 = AtomicU64::new(1);
 #[derive(Debug, Clone)]
 pub struct Tracer {
     tx: Option<tokio::sync::mpsc::UnboundedSender<TraceEvent>>,
+    sampler: Option<Arc<Sampler>>,
+    otlp: Option<SpanExporter>,
+    stream: Option<EventStream>,
+    sinks: Vec<SinkSender>,
 }
 
 pub static TRACE_VERSION: u16 = 14;
 
 impl Tracer {
-    pub(super) fn new(tx: tokio::sync::mpsc::UnboundedSender<TraceEvent>) -> Self {
-        Self { tx: Some(tx) }
+    pub(super) fn new(
+        tx: tokio::sync::mpsc::UnboundedSender<TraceEvent>,
+        sampler: Option<Sampler>,
+    ) -> Self {
+        Self {
+            tx: Some(tx),
+            sampler: sampler.map(Arc::new),
+            otlp: None,
+            stream: None,
+            sinks: Vec::new(),
+        }
     }
 
     pub fn noop() -> Self {
-        Self { tx: None }
+        Self {
+            tx: None,
+            sampler: None,
+            otlp: None,
+            stream: None,
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Returns a tracer that also exports request spans over OTLP.
+    pub fn with_otlp(self, exporter: SpanExporter) -> Self {
+        Self {
+            otlp: Some(exporter),
+            ..self
+        }
+    }
+
+    /// Returns a tracer that also broadcasts all trace events,
+    /// regardless of sampling, to live subscribers of the stream.
+    pub fn with_stream(self, stream: EventStream) -> Self {
+        Self {
+            stream: Some(stream),
+            ..self
+        }
+    }
+
+    /// Returns a tracer that also writes trace events to the sink.
+    pub fn with_sink(mut self, sink: SinkSender) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Reports whether the trace is reported, and can be linked to
+    /// from other telemetry such as metric exemplars.
+    pub fn is_reported(&self, trace_id: model::TraceId) -> bool {
+        if self.tx.is_none() {
+            return false;
+        }
+        match &self.sampler {
+            Some(sampler) => sampler.is_reported(trace_id),
+            None => true,
+        }
     }
 }
 
@@ -145,6 +204,10 @@ impl Tracer {
 impl Tracer {
     #[inline]
     pub fn request_span_start(&self, req: &model::Request, redact_details: bool) {
+        if let Some(sampler) = &self.sampler {
+            sampler.start(req);
+        }
+
         let mut eb = SpanStartEventData {
             parent: Parent::from(req),
             caller_event_id: req.caller_event_id,
@@ -277,17 +340,31 @@ impl Tracer {
 
     #[inline]
     pub fn request_span_end(&self, resp: &model::Response, redact_details: bool) {
+        if let Some(otlp) = &self.otlp {
+            otlp.export(resp);
+        }
+
         // If the request has no span, we don't need to do anything.
         let req = resp.request.as_ref();
 
+        let err = match &resp.data {
+            model::ResponseData::RPC(rpc) => rpc.error.as_ref(),
+            model::ResponseData::Auth(res) => res.as_ref().err(),
+            model::ResponseData::PubSub(res) => res.as_ref().err(),
+        };
+
+        // If the trace wasn't sampled but is reported on failure,
+        // report the events buffered so far before the span ends.
+        if let (Some(sampler), Some(tx)) = (&self.sampler, &self.tx) {
+            for event in sampler.end(req.span, err.is_some()) {
+                _ = tx.send(event);
+            }
+        }
+
         let mut eb = SpanEndEventData {
             parent: Parent::from(req),
             duration: resp.duration,
-            err: match &resp.data {
-                model::ResponseData::RPC(rpc) => rpc.error.as_ref(),
-                model::ResponseData::Auth(res) => res.as_ref().err(),
-                model::ResponseData::PubSub(res) => res.as_ref().err(),
-            },
+            err,
             extra_space: 100,
         }
         .into_eb();
@@ -845,15 +922,34 @@ impl Tracer {
         }
         let id = model::TraceEventId(id);
 
-        // If we have a sender, send the event. Otherwise this is a no-op tracer.
+        // If we have no sender, stream or sinks this is a no-op tracer.
+        if self.tx.is_none() && self.stream.is_none() && self.sinks.is_empty() {
+            return id;
+        }
+
+        let event = TraceEvent {
+            typ,
+            span,
+            id,
+            data: eb.freeze(),
+            ts: tokio::time::Instant::now(),
+        };
+        if let Some(stream) = &self.stream {
+            stream.send(&event);
+        }
+        for sink in &self.sinks {
+            if sink.is_sampled(span.0) {
+                sink.send(event.clone());
+            }
+        }
         if let Some(tx) = &self.tx {
-            _ = tx.send(TraceEvent {
-                typ,
-                span,
-                id,
-                data: eb.freeze(),
-                ts: tokio::time::Instant::now(),
-            });
+            let event = match &self.sampler {
+                Some(sampler) => sampler.admit(event),
+                None => Some(event),
+            };
+            if let Some(event) = event {
+                _ = tx.send(event);
+            }
         }
 
         id
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use lru::LruCache;
+
+use crate::encore::runtime::v1 as pb;
+use crate::model::{self, TraceId};
+use crate::trace::log::TraceEvent;
+
+/// The number of traces to remember sampling decisions for.
+const MAX_TRACES: usize = 10_000;
+
+/// The number of events to buffer for a trace that wasn't sampled,
+/// in case it fails. Traces with more events are dropped.
+const MAX_BUFFERED_EVENTS: usize = 1_000;
+
+/// Decides which traces are reported.
+///
+/// The decision is made when the first span of a trace starts in this
+/// process, based on the rate configured for its endpoint. Since it's derived
+/// from the trace id, processes using the same rate make the same decision
+/// for a trace spanning several of them.
+pub struct Sampler {
+    rate: f64,
+    endpoint_rates: HashMap<String, f64>,
+    sample_errors: bool,
+    traces: Mutex<LruCache<TraceId, Decision>>,
+}
+
+enum Decision {
+    Sampled,
+    /// Not sampled, but the events are buffered in case a span fails.
+    /// The buffer is discarded when the span that started the trace ends.
+    Buffered {
+        root: model::SpanId,
+        events: Vec<TraceEvent>,
+    },
+    Dropped,
+}
+
+impl Sampler {
+    /// Returns a sampler for the config, or None if all traces are sampled.
+    pub fn new(cfg: &pb::tracing_provider::EncoreTracingProvider) -> Option<Self> {
+        let rate = cfg.sampling_rate.unwrap_or(1.0).clamp(0.0, 1.0);
+        if rate >= 1.0 && cfg.endpoint_sampling_rates.values().all(|r| *r >= 1.0) {
+            return None;
+        }
+        Some(Self {
+            rate,
+            endpoint_rates: cfg
+                .endpoint_sampling_rates
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clamp(0.0, 1.0)))
+                .collect(),
+            sample_errors: cfg.sample_errors,
+            traces: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_TRACES).unwrap())),
+        })
+    }
+
+    /// Decides whether to sample the trace the request belongs to,
+    /// unless it has been decided already.
+    pub(super) fn start(&self, req: &model::Request) {
+        let (service, endpoint) = match &req.data {
+            model::RequestData::RPC(rpc) => {
+                (rpc.endpoint.name.service(), rpc.endpoint.name.endpoint())
+            }
+            model::RequestData::Stream(data) => {
+                (data.endpoint.name.service(), data.endpoint.name.endpoint())
+            }
+            model::RequestData::Auth(auth) => {
+                (auth.auth_handler.service(), auth.auth_handler.endpoint())
+            }
+            model::RequestData::PubSub(msg) => (msg.service.as_str(), ""),
+        };
+        let rate = self
+            .endpoint_rates
+            .get(&format!("{service}.{endpoint}"))
+            .or_else(|| self.endpoint_rates.get(service))
+            .copied()
+            .unwrap_or(self.rate);
+
+        let mut traces = self.traces.lock().unwrap();
+        if !traces.contains(&req.span.0) {
+            traces.put(req.span.0, self.decide(req.span.0, req.span.1, rate));
+        }
+    }
+
+    /// Records that a span ended, returning the buffered events to report
+    /// if the span failed and failing traces are always sampled.
+    pub(super) fn end(&self, span: model::SpanKey, failed: bool) -> Vec<TraceEvent> {
+        let mut traces = self.traces.lock().unwrap();
+        let Some(decision) = traces.get_mut(&span.0) else {
+            return Vec::new();
+        };
+        match decision {
+            Decision::Buffered { events, .. } if failed && self.sample_errors => {
+                let events = std::mem::take(events);
+                *decision = Decision::Sampled;
+                events
+            }
+            Decision::Buffered { root, .. } if *root == span.1 => {
+                *decision = Decision::Dropped;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the event if it should be reported now.
+    /// Otherwise it's buffered or dropped.
+    pub(super) fn admit(&self, event: TraceEvent) -> Option<TraceEvent> {
+        let mut traces = self.traces.lock().unwrap();
+        let decision = traces.get_or_insert_mut(event.span.0, || {
+            self.decide(event.span.0, event.span.1, self.rate)
+        });
+        match decision {
+            Decision::Sampled => Some(event),
+            Decision::Buffered { events, .. } => {
+                if events.len() < MAX_BUFFERED_EVENTS {
+                    events.push(event);
+                } else {
+                    *decision = Decision::Dropped;
+                }
+                None
+            }
+            Decision::Dropped => None,
+        }
+    }
+
+    /// Reports whether the trace's events are reported.
+    pub(super) fn is_reported(&self, trace: TraceId) -> bool {
+        matches!(
+            self.traces.lock().unwrap().peek(&trace),
+            Some(Decision::Sampled)
+        )
+    }
+
+    fn decide(&self, trace: TraceId, root: model::SpanId, rate: f64) -> Decision {
+        if is_sampled(trace, rate) {
+            Decision::Sampled
+        } else if self.sample_errors {
+            Decision::Buffered {
+                root,
+                events: Vec::new(),
+            }
+        } else {
+            Decision::Dropped
+        }
+    }
+}
+
+impl std::fmt::Debug for Sampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sampler")
+            .field("rate", &self.rate)
+            .field("endpoint_rates", &self.endpoint_rates)
+            .field("sample_errors", &self.sample_errors)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reports whether the trace is sampled at the given rate.
+pub(super) fn is_sampled(trace: TraceId, rate: f64) -> bool {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&trace.0[..8]);
+    let n = u64::from_be_bytes(bytes);
+    (n as f64) < rate * (u64::MAX as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(first: u8) -> TraceId {
+        let mut id = [0u8; 16];
+        id[0] = first;
+        TraceId(id)
+    }
+
+    fn event(trace: TraceId) -> TraceEvent {
+        TraceEvent {
+            typ: crate::trace::protocol::EventType::LogMessage,
+            id: model::TraceEventId(1),
+            data: bytes::Bytes::new(),
+            span: model::SpanKey(trace, model::SpanId([1; 8])),
+            ts: tokio::time::Instant::now(),
+        }
+    }
+
+    fn sampler(rate: f64, sample_errors: bool) -> Sampler {
+        Sampler::new(&pb::tracing_provider::EncoreTracingProvider {
+            sampling_rate: Some(rate),
+            sample_errors,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn rate() {
+        assert!(is_sampled(trace(0x00), 0.5));
+        assert!(!is_sampled(trace(0xff), 0.5));
+        assert!(!is_sampled(trace(0x00), 0.0));
+        assert!(Sampler::new(&Default::default()).is_none());
+    }
+
+    #[test]
+    fn drops_unsampled() {
+        let s = sampler(0.5, false);
+        assert!(s.admit(event(trace(0x00))).is_some());
+        assert!(s.admit(event(trace(0xff))).is_none());
+    }
+
+    #[test]
+    fn samples_errors() {
+        let s = sampler(0.5, true);
+        let t = trace(0xff);
+        let span = model::SpanKey(t, model::SpanId([1; 8]));
+        assert!(s.admit(event(t)).is_none());
+        assert!(s.admit(event(t)).is_none());
+
+        // The buffered events are reported once a span fails.
+        assert_eq!(s.end(span, true).len(), 2);
+        assert!(s.admit(event(t)).is_some());
+
+        // Without failures, the buffer is dropped when the root span ends.
+        let t = trace(0xfe);
+        let span = model::SpanKey(t, model::SpanId([1; 8]));
+        assert!(s.admit(event(t)).is_none());
+        assert!(s.end(span, false).is_empty());
+        assert!(s.end(span, true).is_empty());
+        assert!(!s.is_reported(t));
+        assert!(s.is_reported(trace(0xff)));
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::model;
+use crate::trace::log::TraceEvent;
+use crate::trace::sampling::is_sampled;
+use crate::trace::time_anchor::TimeAnchor;
+use crate::trace::Flusher;
+
+/// The most events written to a sink at once.
+const MAX_BATCH_SIZE: usize = 1024;
+
+/// A destination trace events are stored in, such as a local database.
+pub(super) trait Sink: Send + 'static {
+    /// Writes a batch of trace events, whose timestamps are relative to the anchor.
+    /// It's called from a blocking thread, so it can perform blocking I/O.
+    fn write(&mut self, anchor: &TimeAnchor, events: &[TraceEvent]) -> anyhow::Result<()>;
+}
+
+/// Sends trace events to a [SinkReporter].
+#[derive(Debug, Clone)]
+pub struct SinkSender {
+    tx: mpsc::UnboundedSender<TraceEvent>,
+    sampling_rate: Option<f64>,
+}
+
+impl SinkSender {
+    /// Reports whether events of the trace are sent to the sink.
+    pub(super) fn is_sampled(&self, trace_id: model::TraceId) -> bool {
+        match self.sampling_rate {
+            Some(rate) => is_sampled(trace_id, rate),
+            None => true,
+        }
+    }
+
+    pub(super) fn send(&self, event: TraceEvent) {
+        _ = self.tx.send(event);
+    }
+}
+
+/// Writes trace events to a sink.
+#[must_use]
+pub struct SinkReporter {
+    rx: mpsc::UnboundedReceiver<TraceEvent>,
+    flush_tx: mpsc::UnboundedSender<oneshot::Sender<()>>,
+    flush_rx: mpsc::UnboundedReceiver<oneshot::Sender<()>>,
+    anchor: TimeAnchor,
+    sink: Arc<Mutex<Box<dyn Sink>>>,
+}
+
+pub(super) fn sink_reporter(
+    sink: Box<dyn Sink>,
+    sampling_rate: Option<f64>,
+) -> (SinkSender, SinkReporter) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (flush_tx, flush_rx) = mpsc::unbounded_channel();
+    let sender = SinkSender { tx, sampling_rate };
+    let reporter = SinkReporter {
+        rx,
+        flush_tx,
+        flush_rx,
+        anchor: TimeAnchor::new(),
+        sink: Arc::new(Mutex::new(sink)),
+    };
+    (sender, reporter)
+}
+
+impl SinkReporter {
+    /// Returns a handle for flushing the buffered trace events.
+    pub fn flusher(&self) -> Flusher {
+        Flusher::new(self.flush_tx.clone())
+    }
+
+    /// Starts writing trace events to the sink, in batches of the events
+    /// received since the last write.
+    ///
+    /// This method runs until all senders are dropped.
+    pub async fn start_reporting(mut self) {
+        loop {
+            tokio::select! {
+                event = self.rx.recv() => {
+                    let Some(event) = event else {
+                        return;
+                    };
+                    let mut batch = vec![event];
+                    while batch.len() < MAX_BATCH_SIZE {
+                        match self.rx.try_recv() {
+                            Ok(event) => batch.push(event),
+                            Err(_) => break,
+                        }
+                    }
+                    self.write(batch).await;
+                }
+                Some(done) = self.flush_rx.recv() => {
+                    let mut batch = Vec::new();
+                    while let Ok(event) = self.rx.try_recv() {
+                        batch.push(event);
+                    }
+                    if !batch.is_empty() {
+                        self.write(batch).await;
+                    }
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    async fn write(&self, events: Vec<TraceEvent>) {
+        let sink = self.sink.clone();
+        let anchor = self.anchor.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            sink.lock()
+                .expect("sink lock poisoned")
+                .write(&anchor, &events)
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => log::error!("failed to store trace events: {err:#}"),
+            Err(err) => log::error!("failed to store trace events: {err}"),
+        }
+    }
+}
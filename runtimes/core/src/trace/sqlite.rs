@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use rusqlite::{params, Connection};
+
+use crate::encore::runtime::v1 as pb;
+use crate::trace::log::TraceEvent;
+use crate::trace::protocol::TRACE_VERSION;
+use crate::trace::sink::Sink;
+use crate::trace::time_anchor::TimeAnchor;
+
+/// The size at which the database is rotated, if not configured.
+const DEFAULT_MAX_SIZE: u64 = 500 * 1024 * 1024;
+
+/// The number of rotated databases to keep, if not configured.
+const DEFAULT_MAX_BACKUPS: u32 = 3;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS trace_event (
+    trace_id BLOB NOT NULL,
+    span_id BLOB NOT NULL,
+    event_id INTEGER NOT NULL,
+    event_type INTEGER NOT NULL,
+    time_unix_nano INTEGER NOT NULL,
+    data BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS trace_event_trace_id ON trace_event (trace_id);
+CREATE INDEX IF NOT EXISTS trace_event_time ON trace_event (time_unix_nano);
+";
+
+/// Stores trace events in a SQLite database that is rotated when it grows too large.
+///
+/// Each row holds one event, with its data encoded in the trace protocol
+/// of the version stored as the database's user_version.
+///
+/// On rotation the database is renamed to "<path>.1", shifting
+/// older databases up by one, and the oldest database is deleted.
+pub(super) struct SqliteSink {
+    path: PathBuf,
+    conn: Option<Connection>,
+    has_events: bool,
+    max_size: u64,
+    max_backups: u32,
+}
+
+impl SqliteSink {
+    pub fn open(cfg: &pb::tracing_provider::LocalTracingProvider) -> anyhow::Result<Self> {
+        let path = PathBuf::from(&cfg.path);
+        let (conn, has_events) =
+            open(&path).with_context(|| format!("unable to open trace database {}", cfg.path))?;
+        Ok(Self {
+            path,
+            conn: Some(conn),
+            has_events,
+            max_size: cfg.max_size_bytes.unwrap_or(DEFAULT_MAX_SIZE),
+            max_backups: cfg.max_backups.unwrap_or(DEFAULT_MAX_BACKUPS),
+        })
+    }
+
+    fn size(conn: &Connection) -> rusqlite::Result<u64> {
+        let pages: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok(pages * page_size)
+    }
+
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        if let Some(conn) = self.conn.take() {
+            conn.close().map_err(|(_, err)| err)?;
+        }
+
+        if self.max_backups == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.max_backups).rev() {
+                let from = self.backup_path(n);
+                if from.exists() {
+                    std::fs::rename(&from, self.backup_path(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.backup_path(1))?;
+        }
+
+        let (conn, has_events) = open(&self.path)?;
+        self.conn = Some(conn);
+        self.has_events = has_events;
+        Ok(())
+    }
+
+    fn backup_path(&self, n: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+}
+
+fn open(path: &Path) -> anyhow::Result<(Connection, bool)> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    conn.pragma_update(None, "user_version", TRACE_VERSION)?;
+    let has_events = conn.query_row("SELECT EXISTS (SELECT 1 FROM trace_event)", [], |row| {
+        row.get(0)
+    })?;
+    Ok((conn, has_events))
+}
+
+impl Sink for SqliteSink {
+    fn write(&mut self, anchor: &TimeAnchor, events: &[TraceEvent]) -> anyhow::Result<()> {
+        // Rotate before the write, so the events of a batch are never split across databases.
+        if let Some(conn) = &self.conn {
+            if self.has_events && Self::size(conn)? > self.max_size {
+                self.rotate().context("unable to rotate trace database")?;
+            }
+        } else {
+            // A previous rotation failed; try again.
+            self.rotate().context("unable to rotate trace database")?;
+        }
+        let conn = self.conn.as_mut().context("trace database is closed")?;
+
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO trace_event (trace_id, span_id, event_id, event_type, time_unix_nano, data)
+                VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            for event in events {
+                let time = anchor.system_time + event.ts.saturating_duration_since(anchor.instant);
+                stmt.execute(params![
+                    &event.span.0 .0[..],
+                    &event.span.1 .0[..],
+                    event.id.0 as i64,
+                    event.typ as u8,
+                    time.timestamp_nanos_opt().unwrap_or_default(),
+                    &event.data[..],
+                ])?;
+            }
+        }
+        tx.commit()?;
+        self.has_events |= !events.is_empty();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model;
+    use crate::trace::protocol::EventType;
+
+    fn event(id: u64) -> TraceEvent {
+        TraceEvent {
+            typ: EventType::LogMessage,
+            span: model::SpanKey(model::TraceId([1; 16]), model::SpanId([2; 8])),
+            id: model::TraceEventId(id),
+            data: bytes::Bytes::from_static(b"data"),
+            ts: tokio::time::Instant::now(),
+        }
+    }
+
+    fn event_ids(path: &Path) -> Vec<i64> {
+        let conn = Connection::open(path).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT event_id FROM trace_event ORDER BY event_id")
+            .unwrap();
+        let ids = stmt.query_map([], |row| row.get(0)).unwrap();
+        ids.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("encore-trace-{}", xid::new()));
+        let path = dir.join("traces.db");
+        let mut sink = SqliteSink::open(&pb::tracing_provider::LocalTracingProvider {
+            path: path.to_string_lossy().into_owned(),
+            max_size_bytes: Some(1),
+            max_backups: Some(1),
+            sampling_rate: None,
+        })
+        .unwrap();
+
+        let anchor = TimeAnchor::new();
+        sink.write(&anchor, &[event(1), event(2)]).unwrap();
+        sink.write(&anchor, &[event(3)]).unwrap();
+        sink.write(&anchor, &[event(4)]).unwrap();
+
+        assert_eq!(event_ids(&path), vec![4]);
+        assert_eq!(event_ids(&sink.backup_path(1)), vec![3]);
+        assert!(!sink.backup_path(2).exists());
+
+        let conn = Connection::open(&path).unwrap();
+        let version: u16 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, TRACE_VERSION);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use bytes::{Bytes, BytesMut};
+use tokio::sync::broadcast;
+
+use crate::trace::log::{StreamingTraceEvent, TraceEvent};
+use crate::trace::time_anchor::TimeAnchor;
+
+/// Broadcasts trace events to live subscribers, such as a local dashboard.
+///
+/// Events are encoded the same way as when they are streamed to Encore Cloud,
+/// and only when there are subscribers.
+#[derive(Debug, Clone)]
+pub struct EventStream {
+    tx: broadcast::Sender<Bytes>,
+    anchor: TimeAnchor,
+}
+
+impl EventStream {
+    /// Creates a new stream, buffering up to `capacity` events
+    /// for subscribers that fall behind.
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self {
+            tx,
+            anchor: TimeAnchor::new(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Bytes> {
+        self.tx.subscribe()
+    }
+
+    /// The header describing the time anchor event timestamps are relative to.
+    pub fn trace_header(&self) -> String {
+        self.anchor.trace_header()
+    }
+
+    pub(super) fn send(&self, event: &TraceEvent) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+
+        let event = StreamingTraceEvent {
+            event: event.clone(),
+        };
+        let header = event.header(&self.anchor);
+        let mut buf = BytesMut::with_capacity(header.len() + event.event.data.len());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(&event.event.data);
+        _ = self.tx.send(buf.freeze());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model;
+    use crate::trace::protocol::EventType;
+
+    fn event() -> TraceEvent {
+        TraceEvent {
+            typ: EventType::LogMessage,
+            span: model::SpanKey(model::TraceId([1; 16]), model::SpanId([2; 8])),
+            id: model::TraceEventId(3),
+            data: Bytes::from_static(b"data"),
+            ts: tokio::time::Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_send() {
+        let stream = EventStream::new(1);
+
+        // Without subscribers the event is dropped.
+        stream.send(&event());
+
+        let mut rx = stream.subscribe();
+        stream.send(&event());
+        let msg = rx.try_recv().unwrap();
+        assert!(msg.ends_with(b"data"));
+        assert_eq!(msg.len(), 45 + 4);
+        assert!(rx.try_recv().is_err());
+    }
+}
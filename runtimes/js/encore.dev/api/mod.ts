@@ -108,6 +108,18 @@ export interface APIOptions {
    * When set to true, request information such as payloads and headers will be excluded from traces.
    */
   sensitive?: boolean;
+
+  /**
+   * Whether to compute an ETag for successful GET and HEAD responses.
+   * The API gateway then answers requests with a matching `If-None-Match`
+   * header with `304 Not Modified`, without sending the response body.
+   *
+   * Set to true (or "strong") for strong ETags, or "weak" for weak ETags
+   * if a proxy in front of the gateway may re-encode responses.
+   *
+   * Defaults to false if not specified.
+   */
+  etag?: boolean | "strong" | "weak";
 }
 
 export interface StreamOptions {
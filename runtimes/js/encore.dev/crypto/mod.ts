@@ -0,0 +1,44 @@
+import * as runtime from "../internal/runtime/mod";
+
+/**
+ * EncryptionKey encrypts application data using envelope encryption.
+ *
+ * Data is encrypted with a data key, which is itself encrypted by a key
+ * encryption key managed by AWS KMS, Google Cloud KMS, or a local master key,
+ * as configured in the infrastructure config.
+ *
+ * @example
+ *  import { EncryptionKey } from "encore.dev/crypto";
+ *  const key = EncryptionKey.named("app-data");
+ *  const ciphertext = await key.encrypt(Buffer.from("secret"));
+ */
+export class EncryptionKey {
+  private impl: runtime.EncryptionKey;
+
+  private constructor(name: string) {
+    this.impl = runtime.RT.encryptionKey(name);
+  }
+
+  /**
+   * Reference an encryption key by name.
+   */
+  static named(name: string): EncryptionKey {
+    return new EncryptionKey(name);
+  }
+
+  /**
+   * Encrypts the data. If additional authenticated data is given,
+   * the same data must be provided to decrypt it.
+   */
+  async encrypt(data: Buffer, aad?: Buffer): Promise<Buffer> {
+    return this.impl.encrypt(data, aad);
+  }
+
+  /**
+   * Decrypts data encrypted with this key.
+   * Throws an error if the data has been tampered with.
+   */
+  async decrypt(data: Buffer, aad?: Buffer): Promise<Buffer> {
+    return this.impl.decrypt(data, aad);
+  }
+}
@@ -43,7 +43,19 @@ export async function run(entrypoint: string) {
       }
     }
 
-    return runtime.RT.runForever();
+    // Node owns the process, so it handles the signals and exits
+    // once the runtime has shut down gracefully.
+    const shutdown = () => runtime.RT.shutdown();
+    process.once("SIGTERM", shutdown);
+    process.once("SIGINT", shutdown);
+
+    try {
+      await runtime.RT.runForever();
+    } catch (err) {
+      console.error("encore: runtime failed:", err);
+      process.exit(1);
+    }
+    process.exit(0);
   }
 
   // Worker thread: set metrics buffer from workerData
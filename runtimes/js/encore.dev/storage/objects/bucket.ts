@@ -54,6 +54,27 @@ export class Bucket extends BucketPerms implements Uploader, SignedUploader, Dow
     }
   }
 
+  /**
+   * Lists a single page of up to `options.limit` entries.
+   * The returned cursor, if set, can be passed as `options.cursor`
+   * to list the next page.
+   */
+  async listPage(options: ListOptions): Promise<ListPage> {
+    const source = getCurrentRequest();
+    const iter = unwrapErr(await this.impl.list(options, source));
+    const entries: ListEntry[] = [];
+    while (true) {
+      const entry = await iter.next();
+      if (entry === null) {
+        break;
+      }
+      entries.push(entry);
+    }
+    const cursor = (await iter.cursor()) ?? undefined;
+    iter.markDone();
+    return { entries, cursor };
+  }
+
   /**
    * Returns whether the object exists in the bucket.
    * Throws an error on network failure.
@@ -122,6 +143,23 @@ export class Bucket extends BucketPerms implements Uploader, SignedUploader, Dow
     return unwrapErr(res);
   }
 
+  /**
+   * Downloads an object from the bucket, yielding its contents in chunks
+   * as they are received rather than buffering the whole object.
+   */
+  async *downloadStream(name: string, options?: DownloadOptions): AsyncGenerator<Buffer> {
+    const source = getCurrentRequest();
+    const impl = this.impl.object(name);
+    const stream = unwrapErr(await impl.downloadStream(options, source));
+    while (true) {
+      const chunk = unwrapErr(await stream.next());
+      if (chunk === null) {
+        break;
+      }
+      yield chunk;
+    }
+  }
+
   /**
    * Removes an object from the bucket.
    * Throws an error on network failure.
@@ -156,8 +194,20 @@ export interface ListOptions {
   */
   prefix?: string;
 
+  /**
+   * Groups objects whose names contain the delimiter after the prefix
+   * into a single entry with `isPrefix` set, like directories.
+   * Typically "/". If unset, all matching objects are listed.
+   */
+  delimiter?: string;
+
   /** Maximum number of objects to return. Defaults to no limit. */
   limit?: number;
+
+  /**
+   * Resumes a previous listing, using the cursor from `listPage`.
+   */
+  cursor?: string;
 }
 
 export interface AttrsOptions {
@@ -188,6 +238,14 @@ export interface DeleteOptions {
    * If bucket versioning is not enabled, this option is ignored.
    */
   version?: string;
+
+  /**
+   * Only delete the object if the preconditions hold.
+   * Otherwise the delete fails with `PreconditionFailed`.
+   *
+   * Note that S3 only supports `ifMatch` for deletes.
+   */
+  preconditions?: Preconditions;
 }
 
 export interface DownloadOptions {
@@ -198,6 +256,34 @@ export interface DownloadOptions {
    * If bucket versioning is not enabled, this option is ignored.
    */
   version?: string;
+
+  /**
+   * Only download the object if the preconditions hold.
+   * Otherwise the download fails with `PreconditionFailed`.
+   */
+  preconditions?: Preconditions;
+
+  /**
+   * The byte offset to start downloading from. Defaults to 0.
+   * The download fails with `InvalidArgument` if it is negative.
+   */
+  offset?: number;
+
+  /**
+   * The number of bytes to download, starting at `offset`.
+   * Defaults to the rest of the object.
+   * The download fails with `InvalidArgument` unless it is positive.
+   */
+  length?: number;
+}
+
+/** Conditions on the object's current etag for an operation to proceed. */
+export interface Preconditions {
+  /** Only proceed if the object's etag matches, or it exists if "*". */
+  ifMatch?: string;
+
+  /** Only proceed if the object's etag does not match, or it does not exist if "*". */
+  ifNoneMatch?: string;
 }
 
 export interface ObjectAttrs {
@@ -207,19 +293,40 @@ export interface ObjectAttrs {
   version?: string;
   etag: string;
   contentType?: string;
+  /**
+   * The base64-encoded CRC32C checksum of the object contents, if known.
+   * It's unset for objects uploaded to S3 in multiple parts.
+   */
+  crc32c?: string;
 }
 
 export interface ListEntry {
   name: string;
   size: number;
   etag: string;
+  /**
+   * Whether the entry is a common prefix of several objects rather than
+   * an object, when listing with a delimiter. Prefixes have no size or etag.
+   */
+  isPrefix: boolean;
+}
+
+export interface ListPage {
+  entries: ListEntry[];
+  /** The cursor for the next page, if there may be more entries. */
+  cursor?: string;
 }
 
 export interface UploadOptions {
   contentType?: string;
+  /**
+   * Only upload the object if the preconditions hold.
+   * Otherwise the upload fails with `PreconditionFailed`.
+   */
   preconditions?: {
+    /** Only upload if the object does not already exist. */
     notExists?: boolean;
-  }
+  } & Preconditions;
 }
 
 export interface UploadUrlOptions {
@@ -76,6 +76,25 @@ export class InvalidArgument extends ObjectsError {
   }
 }
 
+export class ChecksumMismatch extends ObjectsError {
+  constructor(msg: string) {
+    // extending errors causes issues after you construct them, unless you apply the following fixes
+    super(msg);
+
+    // set error name as constructor name, make it not enumerable to keep native Error behavior
+    // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Operators/new.target#new.target_in_constructors
+    Object.defineProperty(this, "name", {
+      value: "ChecksumMismatch",
+      enumerable: false,
+      configurable: true
+    });
+
+    // Fix the prototype chain, capture stack trace.
+    Object.setPrototypeOf(this, ChecksumMismatch.prototype);
+    Error.captureStackTrace(this, this.constructor);
+  }
+}
+
 export function unwrapErr<T>(val: T | runtime.TypedObjectError): T {
   if (val instanceof runtime.TypedObjectError) {
     switch (val.kind) {
@@ -85,6 +104,8 @@ export function unwrapErr<T>(val: T | runtime.TypedObjectError): T {
         throw new PreconditionFailed(val.message);
       case runtime.ObjectErrorKind.InvalidArgument:
         throw new InvalidArgument(val.message);
+      case runtime.ObjectErrorKind.ChecksumMismatch:
+        throw new ChecksumMismatch(val.message);
       default:
         throw new ObjectsError(val.message);
     }
@@ -1,6 +1,6 @@
 export { Bucket } from "./bucket";
-export type { BucketConfig, ObjectAttrs, UploadOptions } from "./bucket";
-export { ObjectsError, ObjectNotFound, PreconditionFailed } from "./error";
+export type { BucketConfig, ObjectAttrs, UploadOptions, ListOptions, ListEntry, ListPage, Preconditions } from "./bucket";
+export { ObjectsError, ObjectNotFound, PreconditionFailed, ChecksumMismatch } from "./error";
 export type {
   BucketPerms,
   Uploader,
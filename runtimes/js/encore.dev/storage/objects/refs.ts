@@ -1,5 +1,5 @@
 import type { AttrsOptions, DeleteOptions, DownloadOptions, DownloadUrlOptions, ExistsOptions, ListEntry,
-  ListOptions, ObjectAttrs, SignedDownloadUrl, SignedUploadUrl, UploadOptions, UploadUrlOptions} from "./bucket";
+  ListOptions, ListPage, ObjectAttrs, SignedDownloadUrl, SignedUploadUrl, UploadOptions, UploadUrlOptions} from "./bucket";
 
 export abstract class BucketPerms {
   private bucketPerms(): void { };
@@ -15,6 +15,7 @@ export abstract class SignedUploader extends BucketPerms {
 
 export abstract class Downloader extends BucketPerms {
   abstract download(name: string, options?: DownloadOptions): Promise<Buffer>;
+  abstract downloadStream(name: string, options?: DownloadOptions): AsyncGenerator<Buffer>;
 }
 
 export abstract class SignedDownloader extends BucketPerms {
@@ -28,6 +29,7 @@ export abstract class Attrser extends BucketPerms {
 
 export abstract class Lister extends BucketPerms {
   abstract list(options: ListOptions): AsyncGenerator<ListEntry>;
+  abstract listPage(options: ListOptions): Promise<ListPage>;
 }
 
 export abstract class Remover extends BucketPerms {
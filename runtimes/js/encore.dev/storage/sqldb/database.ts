@@ -36,6 +36,43 @@ export type Primitive =
   | null
   | undefined;
 
+/** The isolation level of a transaction. */
+export type IsolationLevel =
+  | "read uncommitted"
+  | "read committed"
+  | "repeatable read"
+  | "serializable";
+
+/** Options for beginning a transaction. */
+export interface TransactionOptions {
+  /**
+   * The isolation level of the transaction.
+   * Defaults to the database's default isolation level.
+   */
+  isolationLevel?: IsolationLevel;
+
+  /** Whether the transaction is read-only. */
+  readOnly?: boolean;
+
+  /**
+   * Whether the transaction is deferrable. Only has an effect for
+   * serializable, read-only transactions.
+   */
+  deferrable?: boolean;
+}
+
+/** The result of running a singleton task. */
+export type SingletonResult<T> =
+  | {
+      /** The lock was acquired and the task ran to completion. */
+      acquired: true;
+      result: T;
+    }
+  | {
+      /** Another instance holds the lock, so the task did not run. */
+      acquired: false;
+    };
+
 type SQLQueryExecutor =
   | runtime.SQLConn
   | runtime.SQLDatabase
@@ -301,13 +338,76 @@ export class SQLDatabase extends BaseQueryExecutor {
    * Begins a database transaction.
    *
    * Make sure to always call `rollback` or `commit` to prevent hanging transactions.
+   * @param opts options for the transaction, such as its isolation level
    * @returns a transaction object that implements AsycDisposable
    */
-  async begin(): Promise<Transaction> {
+  async begin(opts?: TransactionOptions): Promise<Transaction> {
     const source = getCurrentRequest();
-    const impl = await this.impl.begin(source);
+    const impl = await this.impl.begin(opts, source);
     return new Transaction(impl);
   }
+
+  /**
+   * Runs a task on at most one instance of the application at a time,
+   * using a lock with the given name stored in the database.
+   *
+   * If another instance is already running a task with the same name,
+   * the task is not run. The lock is a lease that is renewed periodically;
+   * if it can't be renewed, the signal passed to the task is aborted,
+   * and the task should stop.
+   *
+   * @example
+   * const res = await db.singleton("reindex", async (signal) => {
+   *   // ...
+   * });
+   * if (!res.acquired) {
+   *   log.info("reindexing already in progress");
+   * }
+   *
+   * @param name the name of the lock, unique across the database
+   * @param fn the task to run while holding the lock
+   */
+  async singleton<T>(
+    name: string,
+    fn: (signal: AbortSignal) => Promise<T>
+  ): Promise<SingletonResult<T>> {
+    const lock = await this.impl.tryLockSingleton(name);
+    if (!lock) {
+      return { acquired: false };
+    }
+
+    const controller = new AbortController();
+    let done = false;
+    lock
+      .lost()
+      .then(() => {
+        if (!done) {
+          controller.abort(new Error(`singleton lock "${name}" lost`));
+        }
+      })
+      .catch(() => {});
+
+    try {
+      const result = await fn(controller.signal);
+      return { acquired: true, result };
+    } finally {
+      done = true;
+      await lock.release();
+    }
+  }
+}
+
+/**
+ * Rolls back the changes made to all SQL databases since the last reset,
+ * when running tests with SQL test isolation enabled. Does nothing otherwise.
+ *
+ * @example
+ * beforeEach(async () => {
+ *   await resetTestDatabases();
+ * });
+ */
+export async function resetTestDatabases(): Promise<void> {
+  await runtime.RT.resetTestDatabases();
 }
 
 export class Transaction extends BaseQueryExecutor implements AsyncDisposable {
@@ -318,6 +418,20 @@ export class Transaction extends BaseQueryExecutor implements AsyncDisposable {
     super(impl);
   }
 
+  /**
+   * Begins a nested transaction, using a savepoint.
+   *
+   * Committing the nested transaction makes its changes part of this transaction,
+   * while rolling it back discards them without affecting the rest of this transaction.
+   * Make sure to always call `rollback` or `commit` on the nested transaction.
+   * @returns a transaction object that implements AsycDisposable
+   */
+  async begin(): Promise<Transaction> {
+    const source = getCurrentRequest();
+    const impl = await this.impl.begin(source);
+    return new Transaction(impl);
+  }
+
   /**
    * Commit the transaction.
    */
@@ -336,9 +450,15 @@ export class Transaction extends BaseQueryExecutor implements AsyncDisposable {
     await this.impl.rollback(source);
   }
 
+  /**
+   * Rolls back the transaction unless it was completed. Disposing a nested
+   * transaction does nothing if its enclosing transaction was already completed.
+   */
   async [Symbol.asyncDispose]() {
     if (!this.done) {
-      await this.rollback();
+      this.done = true;
+      const source = getCurrentRequest();
+      await this.impl.dispose(source);
     }
   }
 }
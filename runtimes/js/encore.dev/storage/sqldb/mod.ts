@@ -1,2 +1,8 @@
-export { SQLDatabase } from "./database";
-export type { SQLDatabaseConfig, Row as ResultRow } from "./database";
+export { SQLDatabase, resetTestDatabases } from "./database";
+export type {
+  SQLDatabaseConfig,
+  Row as ResultRow,
+  IsolationLevel,
+  TransactionOptions,
+  SingletonResult
+} from "./database";
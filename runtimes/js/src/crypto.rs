@@ -0,0 +1,44 @@
+use napi::bindgen_prelude::Buffer;
+use napi::{Error, Status};
+use napi_derive::napi;
+use std::sync::Arc;
+
+#[napi]
+pub struct EncryptionKey {
+    key: Arc<encore_runtime_core::crypto::EncryptionKey>,
+}
+
+impl EncryptionKey {
+    pub fn new(key: Arc<encore_runtime_core::crypto::EncryptionKey>) -> Self {
+        Self { key }
+    }
+}
+
+#[napi]
+impl EncryptionKey {
+    /// Encrypts the data, binding it to the additional authenticated data if given.
+    #[napi]
+    pub async fn encrypt(&self, data: Buffer, aad: Option<Buffer>) -> napi::Result<Buffer> {
+        let aad = aad.as_deref().unwrap_or_default();
+        self.key
+            .encrypt(&data, aad)
+            .await
+            .map(Buffer::from)
+            .map_err(map_err)
+    }
+
+    /// Decrypts data encrypted with this key and the same additional authenticated data.
+    #[napi]
+    pub async fn decrypt(&self, data: Buffer, aad: Option<Buffer>) -> napi::Result<Buffer> {
+        let aad = aad.as_deref().unwrap_or_default();
+        self.key
+            .decrypt(&data, aad)
+            .await
+            .map(Buffer::from)
+            .map_err(map_err)
+    }
+}
+
+fn map_err(err: encore_runtime_core::crypto::Error) -> Error {
+    Error::new(Status::GenericFailure, err.to_string())
+}
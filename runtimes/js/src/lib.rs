@@ -2,6 +2,7 @@
 
 pub mod api;
 mod cookies;
+mod crypto;
 mod error;
 mod gateway;
 mod headers;
@@ -1,4 +1,5 @@
 use encore_runtime_core::objects as core;
+use futures::StreamExt;
 use napi::bindgen_prelude::Buffer;
 use napi::{Env, JsBuffer, JsObject};
 use napi_derive::napi;
@@ -141,7 +142,10 @@ impl BucketObject {
         options: Option<DownloadOptions>,
         source: Option<&Request>,
     ) -> napi::Either<Buffer, TypedObjectError> {
-        let options = options.unwrap_or_default().into();
+        let options = match options.unwrap_or_default().try_into() {
+            Ok(options) => options,
+            Err(err) => return napi::Either::B(TypedObjectError::from(err)),
+        };
         let source = source.map(|s| s.inner.clone());
         match self.obj.download_all(options, source).await {
             Ok(buf) => napi::Either::A(buf.into()),
@@ -149,6 +153,23 @@ impl BucketObject {
         }
     }
 
+    #[napi]
+    pub async fn download_stream(
+        &self,
+        options: Option<DownloadOptions>,
+        source: Option<&Request>,
+    ) -> napi::Either<DownloadStream, TypedObjectError> {
+        let options = match options.unwrap_or_default().try_into() {
+            Ok(options) => options,
+            Err(err) => return napi::Either::B(TypedObjectError::from(err)),
+        };
+        let source = source.map(|s| s.inner.clone());
+        match self.obj.download_stream(options, source).await {
+            Ok(stream) => napi::Either::A(DownloadStream::new(stream)),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
     #[napi]
     pub async fn delete(
         &self,
@@ -182,6 +203,7 @@ pub struct ObjectAttrs {
     pub size: i64,
     pub content_type: Option<String>,
     pub etag: String,
+    pub crc32c: Option<String>,
 }
 
 impl From<core::ObjectAttrs> for ObjectAttrs {
@@ -192,6 +214,7 @@ impl From<core::ObjectAttrs> for ObjectAttrs {
             size: value.size as i64,
             content_type: value.content_type,
             etag: value.etag,
+            crc32c: value.crc32c,
         }
     }
 }
@@ -201,6 +224,7 @@ pub struct ListEntry {
     pub name: String,
     pub size: i64,
     pub etag: String,
+    pub is_prefix: bool,
 }
 
 impl From<core::ListEntry> for ListEntry {
@@ -209,6 +233,7 @@ impl From<core::ListEntry> for ListEntry {
             name: value.name,
             size: value.size as i64,
             etag: value.etag,
+            is_prefix: value.is_prefix,
         }
     }
 }
@@ -224,6 +249,8 @@ pub struct UploadOptions {
 #[derive(Debug, Default)]
 pub struct UploadPreconditions {
     pub not_exists: Option<bool>,
+    pub if_match: Option<String>,
+    pub if_none_match: Option<String>,
 }
 
 impl From<UploadOptions> for core::UploadOptions {
@@ -239,6 +266,8 @@ impl From<UploadPreconditions> for core::UploadPreconditions {
     fn from(value: UploadPreconditions) -> Self {
         Self {
             not_exists: value.not_exists,
+            if_match: value.if_match,
+            if_none_match: value.if_none_match,
         }
     }
 }
@@ -248,6 +277,7 @@ pub enum ObjectErrorKind {
     NotFound,
     PreconditionFailed,
     InvalidArgument,
+    ChecksumMismatch,
     Other,
     Internal,
 }
@@ -264,6 +294,7 @@ impl From<core::Error> for TypedObjectError {
             core::Error::NotFound => ObjectErrorKind::NotFound,
             core::Error::PreconditionFailed => ObjectErrorKind::PreconditionFailed,
             core::Error::InvalidArgument => ObjectErrorKind::InvalidArgument,
+            core::Error::ChecksumMismatch => ObjectErrorKind::ChecksumMismatch,
             core::Error::Internal(_) => ObjectErrorKind::Internal,
             core::Error::Other(_) => ObjectErrorKind::Other,
         };
@@ -304,6 +335,12 @@ impl ListIterator {
         }
     }
 
+    /// Returns the cursor to resume listing from, if the limit was reached.
+    #[napi]
+    pub async fn cursor(&self) -> Option<String> {
+        self.stream.lock().await.as_ref().and_then(|s| s.cursor())
+    }
+
     #[napi]
     pub fn mark_done(&mut self) {
         if let Some(stream) = self.stream.get_mut().take() {
@@ -312,6 +349,31 @@ impl ListIterator {
     }
 }
 
+#[napi]
+pub struct DownloadStream {
+    stream: tokio::sync::Mutex<core::DownloadStream>,
+}
+
+#[napi]
+impl DownloadStream {
+    fn new(stream: core::DownloadStream) -> Self {
+        Self {
+            stream: tokio::sync::Mutex::new(stream),
+        }
+    }
+
+    /// Returns the next chunk of the object, or None when done.
+    #[napi]
+    pub async fn next(&self) -> napi::Either<Option<Buffer>, TypedObjectError> {
+        let mut stream = self.stream.lock().await;
+        match stream.next().await {
+            Some(Ok(chunk)) => napi::Either::A(Some(chunk.to_vec().into())),
+            Some(Err(err)) => napi::Either::B(err.into()),
+            None => napi::Either::A(None),
+        }
+    }
+}
+
 #[napi(object)]
 #[derive(Debug, Default)]
 pub struct ExistsOptions {
@@ -352,26 +414,60 @@ pub struct SignedDownloadUrl {
 #[derive(Debug, Default)]
 pub struct DeleteOptions {
     pub version: Option<String>,
+    pub preconditions: Option<Preconditions>,
 }
 
 #[napi(object)]
 #[derive(Debug, Default)]
 pub struct DownloadOptions {
     pub version: Option<String>,
+    pub preconditions: Option<Preconditions>,
+    pub offset: Option<i64>,
+    pub length: Option<i64>,
+}
+
+#[napi(object)]
+#[derive(Debug, Default)]
+pub struct Preconditions {
+    pub if_match: Option<String>,
+    pub if_none_match: Option<String>,
+}
+
+impl From<Preconditions> for core::Preconditions {
+    fn from(value: Preconditions) -> Self {
+        Self {
+            if_match: value.if_match,
+            if_none_match: value.if_none_match,
+        }
+    }
 }
 
 #[napi(object)]
 #[derive(Debug, Default)]
 pub struct ListOptions {
     pub prefix: Option<String>,
+    pub delimiter: Option<String>,
     pub limit: Option<i64>,
+    pub cursor: Option<String>,
 }
 
-impl From<DownloadOptions> for core::DownloadOptions {
-    fn from(value: DownloadOptions) -> Self {
-        Self {
+impl TryFrom<DownloadOptions> for core::DownloadOptions {
+    type Error = core::Error;
+
+    /// Fails with InvalidArgument if the offset or length is negative.
+    fn try_from(value: DownloadOptions) -> Result<Self, Self::Error> {
+        let non_negative = |n: i64| u64::try_from(n).map_err(|_| core::Error::InvalidArgument);
+        Ok(Self {
             version: value.version,
-        }
+            preconditions: value.preconditions.map(|p| p.into()),
+            range: match (value.offset, value.length) {
+                (None, None) => None,
+                (offset, length) => Some(core::ByteRange {
+                    offset: non_negative(offset.unwrap_or(0))?,
+                    length: length.map(non_negative).transpose()?,
+                }),
+            },
+        })
     }
 }
 
@@ -379,6 +475,7 @@ impl From<DeleteOptions> for core::DeleteOptions {
     fn from(value: DeleteOptions) -> Self {
         Self {
             version: value.version,
+            preconditions: value.preconditions.map(|p| p.into()),
         }
     }
 }
@@ -419,7 +516,9 @@ impl From<ListOptions> for core::ListOptions {
     fn from(value: ListOptions) -> Self {
         Self {
             prefix: value.prefix,
+            delimiter: value.delimiter,
             limit: value.limit.map(|v| v as u64),
+            cursor: value.cursor,
         }
     }
 }
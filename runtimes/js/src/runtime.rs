@@ -6,7 +6,7 @@ use crate::pubsub::{PubSubSubscription, PubSubSubscriptionConfig, PubSubTopic};
 use crate::pvalue::{parse_pvalues, transform_pvalues_request, PVals};
 use crate::secret::Secret;
 use crate::sqldb::SQLDatabase;
-use crate::{meta, objects, runtime_config, websocket_api};
+use crate::{crypto, meta, objects, runtime_config, websocket_api};
 use encore_runtime_core::api::{AuthOpts, PValues};
 use encore_runtime_core::pubsub::SubName;
 use encore_runtime_core::{api, EncoreName, EndpointName};
@@ -93,7 +93,9 @@ impl Runtime {
             {
                 let rt = runtime.clone();
                 thread::spawn(move || {
-                    rt.run_blocking();
+                    if let Err(err) = rt.run_blocking_hosted() {
+                        log::error!("failed to serve: {err:#}");
+                    }
                 });
             }
 
@@ -115,15 +117,30 @@ impl Runtime {
         Ok(Self { runtime })
     }
 
+    /// Serves requests until [`Runtime::shutdown`] is called, resolving once
+    /// the runtime has shut down gracefully and rejecting if serving fails.
+    ///
+    /// Signals are left to Node, which is expected to call `shutdown`
+    /// when asked to stop, and to exit once this resolves.
     #[napi]
-    pub async fn run_forever(&self) {
+    pub async fn run_forever(&self) -> napi::Result<()> {
         let runtime = self.runtime.clone();
+        let (tx, rx) = futures::channel::oneshot::channel();
         thread::spawn(move || {
-            runtime.run_blocking();
+            let _ = tx.send(runtime.run_blocking_hosted());
         });
 
-        // Block the async function forever.
-        futures::future::pending::<()>().await;
+        let result = rx
+            .await
+            .map_err(|_| Error::new(Status::GenericFailure, "runtime thread panicked"))?;
+        result.map_err(|e| Error::new(Status::GenericFailure, format!("{e:#}")))
+    }
+
+    /// Asks the runtime to shut down gracefully, after which
+    /// the promise returned by `runForever` resolves.
+    #[napi]
+    pub fn shutdown(&self) {
+        self.runtime.request_shutdown();
     }
 
     #[napi]
@@ -133,6 +150,17 @@ impl Runtime {
         SQLDatabase::new(db)
     }
 
+    /// Rolls back the changes made to SQL databases since the last reset,
+    /// if the database state of tests is isolated.
+    #[napi]
+    pub async fn reset_test_databases(&self) -> napi::Result<()> {
+        self.runtime
+            .sqldb()
+            .reset_test_state()
+            .await
+            .map_err(|e| napi::Error::new(napi::Status::GenericFailure, format!("{e:#}")))
+    }
+
     #[napi]
     pub fn pubsub_topic(&self, encore_name: String) -> napi::Result<PubSubTopic> {
         let topic = self
@@ -153,6 +181,16 @@ impl Runtime {
         Ok(objects::Bucket::new(bkt))
     }
 
+    #[napi]
+    pub fn encryption_key(&self, encore_name: String) -> napi::Result<crypto::EncryptionKey> {
+        let key = self
+            .runtime
+            .crypto()
+            .key(&encore_name.into())
+            .ok_or_else(|| Error::new(Status::GenericFailure, "encryption key not found"))?;
+        Ok(crypto::EncryptionKey::new(key))
+    }
+
     #[napi]
     pub fn gateway(
         &self,
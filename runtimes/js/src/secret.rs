@@ -18,7 +18,7 @@ impl Secret {
     /// Returns the cached value of the secret.
     #[napi]
     pub fn cached(&self) -> napi::Result<String> {
-        let val = self.secret.get().map_err(|e| {
+        let val = self.secret.latest().map_err(|e| {
             Error::new(
                 Status::GenericFailure,
                 format!("failed to resolve secret: {e}"),
@@ -63,16 +63,22 @@ impl SQLDatabase {
 
     /// Begins a transaction
     #[napi]
-    pub async fn begin(&self, source: Option<&Request>) -> napi::Result<Transaction> {
+    pub async fn begin(
+        &self,
+        opts: Option<TransactionOptions>,
+        source: Option<&Request>,
+    ) -> napi::Result<Transaction> {
+        let opts = opts.map(TryInto::try_into).transpose()?.unwrap_or_default();
         let source = source.map(|s| s.inner.as_ref());
         let tx = self
             .pool()?
-            .begin(source)
+            .begin(&opts, source)
             .await
             .map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))?;
 
         Ok(Transaction {
-            tx: tokio::sync::Mutex::new(Some(tx)),
+            tx: Arc::new(tokio::sync::Mutex::new(Some(tx))),
+            savepoint: None,
         })
     }
 
@@ -117,6 +123,20 @@ impl SQLDatabase {
         Ok(row.map(|row| Row { row }))
     }
 
+    /// Attempts to acquire the cluster-wide singleton lock with the given name.
+    /// Resolves to null if another instance holds the lock.
+    #[napi]
+    pub async fn try_lock_singleton(&self, name: String) -> napi::Result<Option<SingletonLock>> {
+        let lock = self
+            .pool()?
+            .try_lock_singleton(&name)
+            .await
+            .map_err(to_napi_err)?;
+        Ok(lock.map(|lock| SingletonLock {
+            lock: std::sync::Mutex::new(Some(lock)),
+        }))
+    }
+
     fn pool(&self) -> napi::Result<&sqldb::Pool> {
         match self.pool_marc().as_ref() {
             Ok(pool) => Ok(pool),
@@ -135,35 +155,164 @@ impl SQLDatabase {
     }
 }
 
+/// A held singleton lock.
+#[napi]
+pub struct SingletonLock {
+    lock: std::sync::Mutex<Option<sqldb::SingletonLock>>,
+}
+
+#[napi]
+impl SingletonLock {
+    /// Reports whether the lock is still held.
+    #[napi]
+    pub fn is_held(&self) -> bool {
+        self.lock
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|l| l.is_held())
+    }
+
+    /// Resolves when the lock is no longer held.
+    #[napi]
+    pub async fn lost(&self) -> napi::Result<()> {
+        let lost = self.lock.lock().unwrap().as_ref().map(|l| l.lost());
+        if let Some(lost) = lost {
+            lost.await;
+        }
+        Ok(())
+    }
+
+    #[napi]
+    pub async fn release(&self) -> napi::Result<()> {
+        let lock = self.lock.lock().unwrap().take();
+        if let Some(lock) = lock {
+            lock.release().await;
+        }
+        Ok(())
+    }
+}
+
+#[napi(object)]
+pub struct TransactionOptions {
+    /// One of "read uncommitted", "read committed", "repeatable read" or "serializable".
+    pub isolation_level: Option<String>,
+    pub read_only: Option<bool>,
+    pub deferrable: Option<bool>,
+}
+
+impl TryFrom<TransactionOptions> for sqldb::TransactionOptions {
+    type Error = napi::Error;
+
+    fn try_from(opts: TransactionOptions) -> napi::Result<Self> {
+        Ok(Self {
+            isolation_level: opts
+                .isolation_level
+                .map(|l| l.parse())
+                .transpose()
+                .map_err(to_napi_err)?,
+            read_only: opts.read_only.unwrap_or(false),
+            deferrable: opts.deferrable.unwrap_or(false),
+        })
+    }
+}
+
+/// A transaction, or a transaction nested within another one using a savepoint.
 #[napi]
 pub struct Transaction {
-    tx: tokio::sync::Mutex<Option<sqldb::Transaction>>,
+    tx: Arc<tokio::sync::Mutex<Option<sqldb::Transaction>>>,
+    savepoint: Option<sqldb::Savepoint>,
 }
 
 #[napi]
 impl Transaction {
+    /// Begins a nested transaction.
     #[napi]
-    pub async fn commit(&self, source: Option<&Request>) -> napi::Result<()> {
+    pub async fn begin(&self, source: Option<&Request>) -> napi::Result<Transaction> {
         let source = source.map(|s| s.inner.as_ref());
-        let tx = self.tx.lock().await.take().ok_or(napi::Error::new(
-            napi::Status::GenericFailure,
-            "transaction closed",
-        ))?;
-        tx.commit(source)
+        let mut tx = self.tx.lock().await;
+        let savepoint = tx
+            .as_mut()
+            .ok_or(napi::Error::new(
+                napi::Status::GenericFailure,
+                "transaction closed",
+            ))?
+            .savepoint(source)
             .await
-            .map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))
+            .map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))?;
+        Ok(Transaction {
+            tx: self.tx.clone(),
+            savepoint: Some(savepoint),
+        })
+    }
+
+    #[napi]
+    pub async fn commit(&self, source: Option<&Request>) -> napi::Result<()> {
+        let source = source.map(|s| s.inner.as_ref());
+        let mut guard = self.tx.lock().await;
+        let result = match self.savepoint {
+            Some(sp) => {
+                let tx = guard.as_mut().ok_or(napi::Error::new(
+                    napi::Status::GenericFailure,
+                    "transaction closed",
+                ))?;
+                tx.release_savepoint(sp, source).await
+            }
+            None => {
+                let tx = guard.take().ok_or(napi::Error::new(
+                    napi::Status::GenericFailure,
+                    "transaction closed",
+                ))?;
+                tx.commit(source).await
+            }
+        };
+        result.map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))
     }
 
     #[napi]
     pub async fn rollback(&self, source: Option<&Request>) -> napi::Result<()> {
         let source = source.map(|s| s.inner.as_ref());
-        let tx = self.tx.lock().await.take().ok_or(napi::Error::new(
-            napi::Status::GenericFailure,
-            "transaction closed",
-        ))?;
-        tx.rollback(source)
-            .await
-            .map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))
+        let mut guard = self.tx.lock().await;
+        let result = match self.savepoint {
+            Some(sp) => {
+                let tx = guard.as_mut().ok_or(napi::Error::new(
+                    napi::Status::GenericFailure,
+                    "transaction closed",
+                ))?;
+                tx.rollback_to_savepoint(sp, source).await
+            }
+            None => {
+                let tx = guard.take().ok_or(napi::Error::new(
+                    napi::Status::GenericFailure,
+                    "transaction closed",
+                ))?;
+                tx.rollback(source).await
+            }
+        };
+        result.map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))
+    }
+
+    /// Rolls back the transaction if it's still open. Unlike rollback,
+    /// it does nothing if the transaction was already completed, including
+    /// when a nested transaction's enclosing transaction was completed first.
+    #[napi]
+    pub async fn dispose(&self, source: Option<&Request>) -> napi::Result<()> {
+        let source = source.map(|s| s.inner.as_ref());
+        let mut guard = self.tx.lock().await;
+        let Some(tx) = guard.as_mut() else {
+            return Ok(());
+        };
+        let result = match self.savepoint {
+            Some(sp) => match tx.rollback_to_savepoint(sp, source).await {
+                Err(sqldb::Error::SavepointReleased | sqldb::Error::Closed) => Ok(()),
+                result => result,
+            },
+            None => match guard.take() {
+                Some(tx) => tx.rollback(source).await,
+                None => Ok(()),
+            },
+        };
+        result.map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))
     }
 
     #[napi]
@@ -52,6 +52,8 @@ fn main() {
                 pc: &pc,
                 working_dir: &app_root,
                 parse_tests: false,
+                low_memory: false,
+                cancel: Default::default(),
             };
 
             match builder.parse(&pp) {
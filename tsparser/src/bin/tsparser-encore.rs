@@ -3,7 +3,7 @@ use std::fmt::{self, Display};
 use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 
 use anyhow::Result;
 use prost::Message;
@@ -11,8 +11,10 @@ use serde::Deserialize;
 use swc_common::errors::{Emitter, EmitterWriter, Handler, HANDLER};
 use swc_common::{Globals, SourceMap, SourceMapper, GLOBALS};
 
-use encore_tsparser::builder::{Builder, DebugMode, NodeJSRuntime, PlainError};
+use encore_tsparser::builder::{Builder, DebugMode, GraphFormat, NodeJSRuntime, PlainError};
+use encore_tsparser::encore::parser::meta::v1;
 use encore_tsparser::parser::parser::ParseContext;
+use encore_tsparser::parser::CancellationToken;
 use encore_tsparser::{app, builder};
 
 fn main() -> Result<()> {
@@ -34,12 +36,14 @@ fn main() -> Result<()> {
 
     let errs = Rc::new(Handler::with_emitter(true, false, Box::new(emitter)));
 
+    let commands = read_commands();
+
     GLOBALS.set(&globals, || -> Result<()> {
         HANDLER.set(&errs, || -> Result<()> {
             let builder = Builder::new()?;
             let mut parse: Option<(builder::App, app::AppDesc)> = None;
 
-            let prepare = match parse_cmd()? {
+            let prepare = match next_cmd(&commands)? {
                 Some(Command::Prepare(prepare)) => prepare,
                 Some(_) => anyhow::bail!("expected prepare command"),
                 None => return Ok(()),
@@ -80,12 +84,15 @@ fn main() -> Result<()> {
             };
 
             loop {
-                let cmd = match parse_cmd()? {
+                let cmd = match next_cmd(&commands)? {
                     Some(cmd) => cmd,
                     None => return Ok(()),
                 };
 
                 match cmd {
+                    // Handled by the command reader.
+                    Command::Cancel => {}
+
                     Command::Prepare(input) => {
                         log::debug!("got prepare input {:?}", input);
                     }
@@ -106,6 +113,8 @@ fn main() -> Result<()> {
                             pc: &pc,
                             working_dir: &cwd,
                             parse_tests: input.parse_tests,
+                            low_memory: input.low_memory,
+                            cancel: input.cancel.clone(),
                         };
 
                         match builder.parse(&pp) {
@@ -116,6 +125,15 @@ fn main() -> Result<()> {
                                 ))?;
                                 parse = Some((app, result));
                             }
+                            None if input.cancel.is_cancelled() => {
+                                // Report the cancellation alone, rather than the errors
+                                // from the work that was cut short, and exit since the
+                                // process only parses once.
+                                write_result(Err(anyhow::anyhow!(PlainError(
+                                    "parse cancelled".to_string()
+                                ))))?;
+                                return Ok(());
+                            }
                             None => {
                                 // Get errors from the emitter.
                                 let errs = errors.lock().unwrap();
@@ -179,6 +197,41 @@ fn main() -> Result<()> {
                         }
                     },
 
+                    Command::Graph(input) => match &parse {
+                        None => anyhow::bail!("no parse!"),
+                        Some((_, parse)) => {
+                            let p = builder::GraphParams {
+                                desc: parse,
+                                format: input.format,
+                            };
+                            let graph = builder.graph(&p);
+                            write_result(<Result<_, Infallible>>::Ok(graph.as_bytes()))?;
+                        }
+                    },
+
+                    Command::Diff(input) => match &parse {
+                        None => anyhow::bail!("no parse!"),
+                        Some((_, parse)) => {
+                            let base = std::fs::read(&input.base_meta)
+                                .map_err(anyhow::Error::from)
+                                .and_then(|data| Ok(v1::Data::decode(data.as_slice())?));
+                            match base {
+                                Ok(base) => {
+                                    let p = builder::DiffParams {
+                                        base: &base,
+                                        desc: parse,
+                                    };
+                                    let diff = builder.diff(&p);
+                                    let json = serde_json::to_string(&diff)?;
+                                    write_result(<Result<_, Infallible>>::Ok(json.as_bytes()))?;
+                                }
+                                Err(err) => {
+                                    write_result(Err(err.context("failed to read base metadata")))?
+                                }
+                            };
+                        }
+                    },
+
                     Command::GenUserFacing(_input) => match &parse {
                         None => anyhow::bail!("no parse!"),
                         Some((app, parse)) => {
@@ -228,11 +281,46 @@ where
     }
 }
 
+/// Reads commands from stdin on a separate thread, so that a parse can be
+/// cancelled while it's in progress. A "cancel" line cancels the current
+/// parse, if any, and is otherwise ignored.
+fn read_commands() -> mpsc::Receiver<Result<Option<Command>>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut current = CancellationToken::new();
+        loop {
+            let cmd = parse_cmd();
+            match cmd {
+                Ok(Some(Command::Cancel)) => {
+                    current.cancel();
+                    continue;
+                }
+                Ok(Some(Command::Parse(ref input))) => current = input.cancel.clone(),
+                _ => {}
+            }
+
+            let done = !matches!(cmd, Ok(Some(_)));
+            if tx.send(cmd).is_err() || done {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+fn next_cmd(commands: &mpsc::Receiver<Result<Option<Command>>>) -> Result<Option<Command>> {
+    // The reader only stops after sending the end of input or an error.
+    commands.recv().unwrap_or(Ok(None))
+}
+
 enum Command {
+    Cancel,
     Prepare(PrepareInput),
     Parse(ParseInput),
     Compile(CompileInput),
     Test(TestInput),
+    Graph(GraphInput),
+    Diff(DiffInput),
     GenUserFacing(GenUserFacingInput),
 }
 
@@ -249,6 +337,7 @@ fn parse_cmd() -> Result<Option<Command>> {
 
     match line.trim() {
         "" => Ok(None),
+        "cancel" => Ok(Some(Command::Cancel)),
         "prepare" => {
             let mut de = serde_json::Deserializer::from_reader(stdin);
             let input = PrepareInput::deserialize(&mut de)?;
@@ -274,6 +363,16 @@ fn parse_cmd() -> Result<Option<Command>> {
             let input = TestInput::deserialize(&mut de)?;
             Ok(Some(Command::Test(input)))
         }
+        "graph" => {
+            let mut de = serde_json::Deserializer::from_reader(stdin);
+            let input = GraphInput::deserialize(&mut de)?;
+            Ok(Some(Command::Graph(input)))
+        }
+        "diff" => {
+            let mut de = serde_json::Deserializer::from_reader(stdin);
+            let input = DiffInput::deserialize(&mut de)?;
+            Ok(Some(Command::Diff(input)))
+        }
         _ => anyhow::bail!("unknown command {:#?}", line),
     }
 }
@@ -284,6 +383,11 @@ struct ParseInput {
     platform_id: Option<String>,
     local_id: String,
     parse_tests: bool,
+    #[serde(default)]
+    low_memory: bool,
+    /// Cancelled by a "cancel" command while the parse is in progress.
+    #[serde(skip)]
+    cancel: CancellationToken,
 }
 
 #[derive(Deserialize, Debug)]
@@ -304,6 +408,17 @@ struct CompileInput {
 #[derive(Deserialize, Debug)]
 struct TestInput {}
 
+#[derive(Deserialize, Debug)]
+struct GraphInput {
+    format: GraphFormat,
+}
+
+#[derive(Deserialize, Debug)]
+struct DiffInput {
+    /// The path to the protobuf-encoded metadata to compare against.
+    base_meta: PathBuf,
+}
+
 #[derive(Deserialize, Debug)]
 struct GenUserFacingInput {}
 
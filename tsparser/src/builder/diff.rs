@@ -0,0 +1,775 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::app::AppDesc;
+use crate::encore::parser::meta::v1;
+use crate::encore::parser::schema::v1 as schema;
+use crate::encore::parser::schema::v1::r#type as styp;
+
+use super::Builder;
+
+#[derive(Debug)]
+pub struct DiffParams<'a> {
+    /// The metadata of the app before the changes.
+    pub base: &'a v1::Data,
+    pub desc: &'a AppDesc,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffResult {
+    pub breaking_changes: Vec<BreakingChange>,
+}
+
+/// A change that can break existing callers of an endpoint.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BreakingChange {
+    pub kind: ChangeKind,
+    /// The endpoint, as "service.endpoint".
+    pub endpoint: String,
+    /// The affected field, like "request.user.email", if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    EndpointRemoved,
+    /// The endpoint's path changed, or it no longer accepts a method.
+    PathChanged,
+    /// The endpoint requires more privileges to call.
+    AccessNarrowed,
+    RequiredFieldAdded,
+    FieldMadeRequired,
+    FieldRemoved,
+    FieldMadeOptional,
+    /// A request field accepts fewer values.
+    TypeNarrowed,
+    /// A response field may contain values it didn't before.
+    TypeWidened,
+}
+
+impl Builder<'_> {
+    /// Reports the changes to the app's endpoints since the base metadata
+    /// that can break existing callers.
+    pub fn diff(&self, params: &DiffParams) -> DiffResult {
+        DiffResult {
+            breaking_changes: diff(params.base, &params.desc.meta),
+        }
+    }
+}
+
+/// The direction data flows in, which decides what's a breaking change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Dir {
+    /// Sent by callers: new versions must accept everything old ones did.
+    Request,
+    /// Received by callers: new versions must not return anything old ones didn't.
+    Response,
+}
+
+fn diff(old: &v1::Data, new: &v1::Data) -> Vec<BreakingChange> {
+    let new_rpcs: HashMap<(&str, &str), &v1::Rpc> = new
+        .svcs
+        .iter()
+        .flat_map(|svc| {
+            svc.rpcs
+                .iter()
+                .map(|rpc| ((svc.name.as_str(), rpc.name.as_str()), rpc))
+        })
+        .collect();
+
+    let mut cmp = Comparer {
+        old_decls: old.decls.iter().map(|d| (d.id, d)).collect(),
+        new_decls: new.decls.iter().map(|d| (d.id, d)).collect(),
+        seen: HashSet::new(),
+        changes: Vec::new(),
+        endpoint: String::new(),
+    };
+
+    for svc in &old.svcs {
+        for old_rpc in &svc.rpcs {
+            cmp.endpoint = format!("{}.{}", svc.name, old_rpc.name);
+            let Some(new_rpc) = new_rpcs.get(&(svc.name.as_str(), old_rpc.name.as_str())) else {
+                cmp.report(
+                    ChangeKind::EndpointRemoved,
+                    None,
+                    "endpoint was removed".into(),
+                );
+                continue;
+            };
+            cmp.rpc(old_rpc, new_rpc);
+        }
+    }
+    cmp.changes
+}
+
+struct Comparer<'a> {
+    old_decls: HashMap<u32, &'a schema::Decl>,
+    new_decls: HashMap<u32, &'a schema::Decl>,
+    /// The declarations being compared on the current path, to stop at recursive types.
+    /// Declarations are removed once compared, so shared types are compared wherever they're used.
+    seen: HashSet<(u32, u32, Dir)>,
+    changes: Vec<BreakingChange>,
+    /// The endpoint being compared.
+    endpoint: String,
+}
+
+impl<'a> Comparer<'a> {
+    fn report(&mut self, kind: ChangeKind, field: Option<&str>, message: String) {
+        self.changes.push(BreakingChange {
+            kind,
+            endpoint: self.endpoint.clone(),
+            field: field.map(str::to_string),
+            message,
+        });
+    }
+
+    fn rpc(&mut self, old: &'a v1::Rpc, new: &'a v1::Rpc) {
+        let (old_path, new_path) = (
+            render_path(old.path.as_ref()),
+            render_path(new.path.as_ref()),
+        );
+        if old_path != new_path {
+            self.report(
+                ChangeKind::PathChanged,
+                None,
+                format!("path changed from {old_path} to {new_path}"),
+            );
+        }
+        let accepts_all = new.http_methods.iter().any(|m| m == "*");
+        for method in &old.http_methods {
+            if !accepts_all && !new.http_methods.contains(method) {
+                self.report(
+                    ChangeKind::PathChanged,
+                    None,
+                    format!("method {method} is no longer accepted"),
+                );
+            }
+        }
+
+        let rank = |access: v1::rpc::AccessType| match access {
+            v1::rpc::AccessType::Public => 0,
+            v1::rpc::AccessType::Auth => 1,
+            v1::rpc::AccessType::Private => 2,
+        };
+        if rank(new.access_type()) > rank(old.access_type()) {
+            self.report(
+                ChangeKind::AccessNarrowed,
+                None,
+                format!(
+                    "access changed from {} to {}",
+                    old.access_type().as_str_name().to_lowercase(),
+                    new.access_type().as_str_name().to_lowercase()
+                ),
+            );
+        }
+
+        // An endpoint without a request or response is treated as having an empty one.
+        let empty = schema::Type {
+            typ: Some(styp::Typ::Struct(schema::Struct { fields: vec![] })),
+            validation: None,
+        };
+        self.check(
+            old.request_schema.as_ref().unwrap_or(&empty),
+            new.request_schema.as_ref().unwrap_or(&empty),
+            Dir::Request,
+            "request",
+        );
+        self.check(
+            old.response_schema.as_ref().unwrap_or(&empty),
+            new.response_schema.as_ref().unwrap_or(&empty),
+            Dir::Response,
+            "response",
+        );
+    }
+
+    /// Compares the types, reporting the breaking changes in the given direction.
+    fn check(&mut self, old: &schema::Type, new: &schema::Type, dir: Dir, path: &str) {
+        let (old, old_id) = resolve(&self.old_decls, old);
+        let (new, new_id) = resolve(&self.new_decls, new);
+        let key = old_id.zip(new_id).map(|(o, n)| (o, n, dir));
+        if let Some(key) = key {
+            if !self.seen.insert(key) {
+                return;
+            }
+        }
+        self.check_resolved(old, new, dir, path);
+        if let Some(key) = key {
+            self.seen.remove(&key);
+        }
+    }
+
+    fn check_resolved(&mut self, old: &schema::Type, new: &schema::Type, dir: Dir, path: &str) {
+        match (&old.typ, &new.typ) {
+            (Some(styp::Typ::Struct(o)), Some(styp::Typ::Struct(n))) => {
+                self.check_struct(o, n, dir, path)
+            }
+            (Some(styp::Typ::List(o)), Some(styp::Typ::List(n))) => {
+                if let (Some(o), Some(n)) = (o.elem.as_deref(), n.elem.as_deref()) {
+                    self.check(o, n, dir, &format!("{path}[]"));
+                }
+            }
+            (Some(styp::Typ::Map(o)), Some(styp::Typ::Map(n))) => {
+                if let (Some(o), Some(n)) = (o.value.as_deref(), n.value.as_deref()) {
+                    self.check(o, n, dir, &format!("{path}{{}}"));
+                }
+            }
+            _ => {
+                let (sub, sup) = match dir {
+                    Dir::Request => (old, new),
+                    Dir::Response => (new, old),
+                };
+                let compatible = Subtyping {
+                    sub_decls: self.decls(dir, true),
+                    sup_decls: self.decls(dir, false),
+                    seen: HashSet::new(),
+                }
+                .is_subtype(sub, sup);
+                if !compatible {
+                    let (kind, verb) = match dir {
+                        Dir::Request => (ChangeKind::TypeNarrowed, "narrowed"),
+                        Dir::Response => (ChangeKind::TypeWidened, "widened"),
+                    };
+                    let message = format!(
+                        "type {verb} from {} to {}",
+                        describe(&self.old_decls, old),
+                        describe(&self.new_decls, new)
+                    );
+                    self.report(kind, Some(path), message);
+                }
+            }
+        }
+    }
+
+    fn check_struct(&mut self, old: &schema::Struct, new: &schema::Struct, dir: Dir, path: &str) {
+        let old_fields: HashMap<&str, &schema::Field> =
+            old.fields.iter().map(|f| (field_name(f), f)).collect();
+        let new_fields: HashMap<&str, &schema::Field> =
+            new.fields.iter().map(|f| (field_name(f), f)).collect();
+
+        // Iterate in declaration order for deterministic output.
+        for f in &new.fields {
+            let name = field_name(f);
+            let field_path = format!("{path}.{name}");
+            match (old_fields.get(name), dir) {
+                (None, Dir::Request) if !f.optional => self.report(
+                    ChangeKind::RequiredFieldAdded,
+                    Some(&field_path),
+                    "required field was added".into(),
+                ),
+                (Some(o), Dir::Request) if o.optional && !f.optional => self.report(
+                    ChangeKind::FieldMadeRequired,
+                    Some(&field_path),
+                    "field was made required".into(),
+                ),
+                (Some(o), Dir::Response) if !o.optional && f.optional => self.report(
+                    ChangeKind::FieldMadeOptional,
+                    Some(&field_path),
+                    "field was made optional".into(),
+                ),
+                _ => {}
+            }
+            if let Some(o) = old_fields.get(name) {
+                if let (Some(ot), Some(nt)) = (o.typ.as_ref(), f.typ.as_ref()) {
+                    self.check(ot, nt, dir, &field_path);
+                }
+            }
+        }
+
+        if dir == Dir::Response {
+            for f in &old.fields {
+                let name = field_name(f);
+                // Callers can't rely on optional fields being present anyway.
+                if !f.optional && !new_fields.contains_key(name) {
+                    self.report(
+                        ChangeKind::FieldRemoved,
+                        Some(&format!("{path}.{name}")),
+                        "field was removed".into(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns the declarations of the subtype (or supertype) compared in the direction.
+    fn decls(&self, dir: Dir, sub: bool) -> &HashMap<u32, &'a schema::Decl> {
+        match (dir, sub) {
+            (Dir::Request, true) | (Dir::Response, false) => &self.old_decls,
+            _ => &self.new_decls,
+        }
+    }
+}
+
+/// Decides whether all values of one type are values of another.
+struct Subtyping<'a, 'b> {
+    sub_decls: &'b HashMap<u32, &'a schema::Decl>,
+    sup_decls: &'b HashMap<u32, &'a schema::Decl>,
+    /// The declarations being compared on the current path, to stop at recursive types.
+    seen: HashSet<(u32, u32)>,
+}
+
+impl Subtyping<'_, '_> {
+    fn is_subtype(&mut self, sub: &schema::Type, sup: &schema::Type) -> bool {
+        let (sub, sub_id) = resolve(self.sub_decls, sub);
+        let (sup, sup_id) = resolve(self.sup_decls, sup);
+        let key = sub_id.zip(sup_id);
+        if let Some(key) = key {
+            // Assume recursive types are compatible, and check the rest.
+            if !self.seen.insert(key) {
+                return true;
+            }
+        }
+        let result = self.is_subtype_resolved(sub, sup);
+        if let Some(key) = key {
+            self.seen.remove(&key);
+        }
+        result
+    }
+
+    fn is_subtype_resolved(&mut self, sub: &schema::Type, sup: &schema::Type) -> bool {
+        let (Some(sub_typ), Some(sup_typ)) = (&sub.typ, &sup.typ) else {
+            return false;
+        };
+        use styp::Typ;
+        match (sub_typ, sup_typ) {
+            (_, Typ::Builtin(b))
+                if matches!(
+                    schema::Builtin::try_from(*b),
+                    Ok(schema::Builtin::Any | schema::Builtin::Json)
+                ) =>
+            {
+                true
+            }
+            (Typ::Union(u), _) => u.types.iter().all(|t| self.is_subtype(t, sup)),
+            (Typ::Option(o), _) => {
+                self.is_subtype(&null(), sup)
+                    && o.value.as_deref().is_some_and(|v| self.is_subtype(v, sup))
+            }
+            (Typ::Pointer(p), _) => {
+                self.is_subtype(&null(), sup)
+                    && p.base.as_deref().is_some_and(|v| self.is_subtype(v, sup))
+            }
+            (_, Typ::Union(u)) => u.types.iter().any(|t| self.is_subtype(sub, t)),
+            (_, Typ::Option(o)) => {
+                is_null(sub) || o.value.as_deref().is_some_and(|v| self.is_subtype(sub, v))
+            }
+            (_, Typ::Pointer(p)) => {
+                is_null(sub) || p.base.as_deref().is_some_and(|v| self.is_subtype(sub, v))
+            }
+            (Typ::Builtin(a), Typ::Builtin(b)) => builtin_subtype(*a, *b),
+            (Typ::Literal(a), Typ::Literal(b)) => a == b,
+            (Typ::Literal(lit), Typ::Builtin(b)) => {
+                use schema::literal::Value;
+                let Ok(b) = schema::Builtin::try_from(*b) else {
+                    return false;
+                };
+                match &lit.value {
+                    Some(Value::Str(_)) => b == schema::Builtin::String,
+                    Some(Value::Boolean(_)) => b == schema::Builtin::Bool,
+                    Some(Value::Int(_)) => is_numeric(b),
+                    Some(Value::Float(_)) => is_float(b),
+                    Some(Value::Null(_)) | None => false,
+                }
+            }
+            (Typ::Struct(a), Typ::Struct(b)) => {
+                let fields: HashMap<&str, &schema::Field> =
+                    a.fields.iter().map(|f| (field_name(f), f)).collect();
+                b.fields.iter().all(|f| match fields.get(field_name(f)) {
+                    None => f.optional,
+                    Some(sub_f) => {
+                        (f.optional || !sub_f.optional)
+                            && match (sub_f.typ.as_ref(), f.typ.as_ref()) {
+                                (Some(a), Some(b)) => self.is_subtype(a, b),
+                                _ => false,
+                            }
+                    }
+                })
+            }
+            (Typ::List(a), Typ::List(b)) => match (a.elem.as_deref(), b.elem.as_deref()) {
+                (Some(a), Some(b)) => self.is_subtype(a, b),
+                _ => false,
+            },
+            // Every value of the subtype must have a length the supertype accepts,
+            // and elements the supertype accepts at their position.
+            (Typ::Tuple(a), Typ::Tuple(b)) => {
+                a.min_len >= b.min_len
+                    && a.elems.len() <= b.elems.len()
+                    && a.elems
+                        .iter()
+                        .zip(&b.elems)
+                        .all(|(a, b)| self.is_subtype(a, b))
+            }
+            (Typ::Tuple(a), Typ::List(b)) => b
+                .elem
+                .as_deref()
+                .is_some_and(|b| a.elems.iter().all(|a| self.is_subtype(a, b))),
+            (Typ::Map(a), Typ::Map(b)) => {
+                match (
+                    a.key.as_deref(),
+                    b.key.as_deref(),
+                    a.value.as_deref(),
+                    b.value.as_deref(),
+                ) {
+                    (Some(ak), Some(bk), Some(av), Some(bv)) => {
+                        self.is_subtype(ak, bk) && self.is_subtype(av, bv)
+                    }
+                    _ => false,
+                }
+            }
+            (Typ::Config(a), Typ::Config(b)) => match (a.elem.as_deref(), b.elem.as_deref()) {
+                (Some(a), Some(b)) => self.is_subtype(a, b),
+                _ => false,
+            },
+            (Typ::TypeParameter(a), Typ::TypeParameter(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Resolves named types to their declared type,
+/// returning the id of the declaration if it was named.
+fn resolve<'t>(
+    decls: &HashMap<u32, &'t schema::Decl>,
+    mut typ: &'t schema::Type,
+) -> (&'t schema::Type, Option<u32>) {
+    let mut id = None;
+    // Bounded, in case of aliases referring to each other.
+    for _ in 0..32 {
+        let Some(styp::Typ::Named(named)) = &typ.typ else {
+            break;
+        };
+        match decls.get(&named.id).and_then(|d| d.r#type.as_ref()) {
+            Some(decl_typ) => {
+                id = Some(named.id);
+                typ = decl_typ;
+            }
+            None => break,
+        }
+    }
+    (typ, id)
+}
+
+fn null() -> schema::Type {
+    schema::Type {
+        typ: Some(styp::Typ::Literal(schema::Literal {
+            value: Some(schema::literal::Value::Null(true)),
+        })),
+        validation: None,
+    }
+}
+
+fn is_null(typ: &schema::Type) -> bool {
+    matches!(
+        &typ.typ,
+        Some(styp::Typ::Literal(schema::Literal {
+            value: Some(schema::literal::Value::Null(_))
+        }))
+    )
+}
+
+fn builtin_subtype(sub: i32, sup: i32) -> bool {
+    let (Ok(sub), Ok(sup)) = (
+        schema::Builtin::try_from(sub),
+        schema::Builtin::try_from(sup),
+    ) else {
+        return false;
+    };
+    // Integers widen to floats, as JSON doesn't tell them apart.
+    sub == sup || (is_numeric(sub) && !is_float(sub) && is_float(sup))
+}
+
+fn is_float(b: schema::Builtin) -> bool {
+    matches!(b, schema::Builtin::Float32 | schema::Builtin::Float64)
+}
+
+fn is_numeric(b: schema::Builtin) -> bool {
+    use schema::Builtin::*;
+    matches!(
+        b,
+        Int8 | Int16
+            | Int32
+            | Int64
+            | Uint8
+            | Uint16
+            | Uint32
+            | Uint64
+            | Int
+            | Uint
+            | Float32
+            | Float64
+    )
+}
+
+/// Describes the type for messages.
+fn describe(decls: &HashMap<u32, &schema::Decl>, typ: &schema::Type) -> String {
+    use styp::Typ;
+    match &typ.typ {
+        Some(Typ::Named(named)) => decls
+            .get(&named.id)
+            .map_or_else(|| "named type".into(), |d| d.name.clone()),
+        Some(Typ::Builtin(b)) => schema::Builtin::try_from(*b)
+            .map_or("unknown".into(), |b| b.as_str_name().to_lowercase()),
+        Some(Typ::Literal(lit)) => match &lit.value {
+            Some(schema::literal::Value::Str(s)) => format!("{s:?}"),
+            Some(schema::literal::Value::Boolean(b)) => b.to_string(),
+            Some(schema::literal::Value::Int(i)) => i.to_string(),
+            Some(schema::literal::Value::Float(f)) => f.to_string(),
+            Some(schema::literal::Value::Null(_)) | None => "null".into(),
+        },
+        Some(Typ::Union(u)) => u
+            .types
+            .iter()
+            .map(|t| describe(decls, t))
+            .collect::<Vec<_>>()
+            .join(" | "),
+        Some(Typ::Struct(_)) => "object".into(),
+        Some(Typ::List(_)) => "list".into(),
+        Some(Typ::Tuple(t)) => format!(
+            "[{}]",
+            t.elems
+                .iter()
+                .map(|t| describe(decls, t))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Some(Typ::Map(_)) => "map".into(),
+        Some(Typ::Option(_)) | Some(Typ::Pointer(_)) => "optional value".into(),
+        Some(Typ::TypeParameter(_)) => "type parameter".into(),
+        Some(Typ::Config(_)) => "config value".into(),
+        None => "unknown".into(),
+    }
+}
+
+/// Returns the name the field is sent as.
+fn field_name(f: &schema::Field) -> &str {
+    if f.json_name.is_empty() || f.json_name == "-" {
+        &f.name
+    } else {
+        &f.json_name
+    }
+}
+
+fn render_path(path: Option<&v1::Path>) -> String {
+    let Some(path) = path else {
+        return String::new();
+    };
+    let mut out = String::new();
+    for seg in &path.segments {
+        out.push('/');
+        // Parameter names don't matter to callers.
+        match seg.r#type() {
+            v1::path_segment::SegmentType::Literal => out.push_str(&seg.value),
+            v1::path_segment::SegmentType::Param => out.push(':'),
+            v1::path_segment::SegmentType::Wildcard => out.push('*'),
+            v1::path_segment::SegmentType::Fallback => out.push('!'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin(b: schema::Builtin) -> schema::Type {
+        schema::Type {
+            typ: Some(styp::Typ::Builtin(b as i32)),
+            validation: None,
+        }
+    }
+
+    fn union(types: Vec<schema::Type>) -> schema::Type {
+        schema::Type {
+            typ: Some(styp::Typ::Union(schema::Union { types })),
+            validation: None,
+        }
+    }
+
+    fn str_lit(s: &str) -> schema::Type {
+        schema::Type {
+            typ: Some(styp::Typ::Literal(schema::Literal {
+                value: Some(schema::literal::Value::Str(s.into())),
+            })),
+            validation: None,
+        }
+    }
+
+    fn field(name: &str, typ: schema::Type, optional: bool) -> schema::Field {
+        schema::Field {
+            typ: Some(typ),
+            name: name.into(),
+            json_name: name.into(),
+            optional,
+            ..Default::default()
+        }
+    }
+
+    fn object(fields: Vec<schema::Field>) -> schema::Type {
+        schema::Type {
+            typ: Some(styp::Typ::Struct(schema::Struct { fields })),
+            validation: None,
+        }
+    }
+
+    fn app(rpcs: Vec<v1::Rpc>) -> v1::Data {
+        v1::Data {
+            svcs: vec![v1::Service {
+                name: "users".into(),
+                rpcs,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn rpc(name: &str, req: schema::Type, resp: schema::Type) -> v1::Rpc {
+        v1::Rpc {
+            name: name.into(),
+            access_type: v1::rpc::AccessType::Public as i32,
+            http_methods: vec!["POST".into()],
+            request_schema: Some(req),
+            response_schema: Some(resp),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(changes: &[BreakingChange]) -> Vec<(ChangeKind, Option<&str>)> {
+        changes
+            .iter()
+            .map(|c| (c.kind, c.field.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn compatible_changes() {
+        use schema::Builtin::*;
+        let old = app(vec![rpc(
+            "create",
+            object(vec![field("name", builtin(String), false)]),
+            object(vec![field("id", builtin(Int), false)]),
+        )]);
+        let new = app(vec![
+            rpc(
+                "create",
+                object(vec![
+                    field("name", union(vec![builtin(String), builtin(Int)]), false),
+                    field("email", builtin(String), true),
+                ]),
+                object(vec![
+                    field("id", builtin(Int), false),
+                    field("created", builtin(Time), false),
+                ]),
+            ),
+            rpc("get", object(vec![]), object(vec![])),
+        ]);
+        assert_eq!(diff(&old, &new), vec![]);
+    }
+
+    #[test]
+    fn breaking_changes() {
+        use schema::Builtin::*;
+        let old = app(vec![
+            rpc(
+                "create",
+                object(vec![
+                    field(
+                        "role",
+                        union(vec![str_lit("admin"), str_lit("user")]),
+                        false,
+                    ),
+                    field("nickname", builtin(String), true),
+                ]),
+                object(vec![
+                    field("id", builtin(Int), false),
+                    field("name", builtin(String), false),
+                ]),
+            ),
+            rpc("delete", object(vec![]), object(vec![])),
+        ]);
+        let mut create = rpc(
+            "create",
+            object(vec![
+                field("role", str_lit("user"), false),
+                field("nickname", builtin(String), false),
+                field("email", builtin(String), false),
+            ]),
+            object(vec![field("id", builtin(Float64), true)]),
+        );
+        create.access_type = v1::rpc::AccessType::Auth as i32;
+        let new = app(vec![create]);
+
+        let changes = diff(&old, &new);
+        assert_eq!(
+            kinds(&changes),
+            vec![
+                (ChangeKind::AccessNarrowed, None),
+                (ChangeKind::TypeNarrowed, Some("request.role")),
+                (ChangeKind::FieldMadeRequired, Some("request.nickname")),
+                (ChangeKind::RequiredFieldAdded, Some("request.email")),
+                (ChangeKind::FieldMadeOptional, Some("response.id")),
+                (ChangeKind::TypeWidened, Some("response.id")),
+                (ChangeKind::FieldRemoved, Some("response.name")),
+                (ChangeKind::EndpointRemoved, None),
+            ]
+        );
+        assert_eq!(
+            changes[1].message,
+            "type narrowed from \"admin\" | \"user\" to \"user\""
+        );
+        assert_eq!(changes[7].endpoint, "users.delete");
+    }
+
+    #[test]
+    fn shared_types() {
+        use schema::Builtin::*;
+        let named = schema::Type {
+            typ: Some(styp::Typ::Named(schema::Named {
+                id: 1,
+                type_arguments: vec![],
+            })),
+            validation: None,
+        };
+        let with_user = |rpcs: Vec<v1::Rpc>, user: schema::Type| v1::Data {
+            decls: vec![schema::Decl {
+                id: 1,
+                name: "User".into(),
+                r#type: Some(user),
+                ..Default::default()
+            }],
+            ..app(rpcs)
+        };
+        let rpcs = || {
+            vec![
+                rpc("get", object(vec![]), named.clone()),
+                rpc(
+                    "list",
+                    object(vec![]),
+                    object(vec![
+                        field("first", named.clone(), false),
+                        field("last", named.clone(), false),
+                    ]),
+                ),
+            ]
+        };
+        let old = with_user(rpcs(), object(vec![field("name", builtin(String), false)]));
+        let new = with_user(rpcs(), object(vec![]));
+
+        // The change is reported wherever the shared type is used.
+        let changes = diff(&old, &new);
+        let fields: Vec<_> = changes
+            .iter()
+            .map(|c| (c.endpoint.as_str(), c.field.as_deref()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("users.get", Some("response.name")),
+                ("users.list", Some("response.first.name")),
+                ("users.list", Some("response.last.name")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+use crate::app::AppDesc;
+use crate::encore::parser::meta::v1;
+
+use super::Builder;
+
+/// The text format to render the usage graph in.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+    /// A Mermaid flowchart.
+    Mermaid,
+}
+
+#[derive(Debug)]
+pub struct GraphParams<'a> {
+    pub desc: &'a AppDesc,
+    pub format: GraphFormat,
+}
+
+impl Builder<'_> {
+    /// Renders the app's services, endpoints and infrastructure resources,
+    /// and how they use each other, for architecture documentation.
+    pub fn graph(&self, params: &GraphParams) -> String {
+        let graph = Graph::new(&params.desc.meta);
+        match params.format {
+            GraphFormat::Dot => graph.to_dot(),
+            GraphFormat::Mermaid => graph.to_mermaid(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NodeKind {
+    Database,
+    Bucket,
+    Topic,
+}
+
+/// The usage graph, with services containing their endpoints.
+#[derive(Debug, Default)]
+struct Graph {
+    /// Endpoint names by service name.
+    services: BTreeMap<String, Vec<String>>,
+    resources: BTreeSet<(NodeKind, String)>,
+    /// Edges between node ids, with their labels.
+    edges: BTreeSet<(String, String, String)>,
+}
+
+impl Graph {
+    fn new(md: &v1::Data) -> Self {
+        let mut g = Graph::default();
+        for svc in &md.svcs {
+            let eps = svc.rpcs.iter().map(|rpc| rpc.name.clone()).collect();
+            g.services.insert(svc.name.clone(), eps);
+        }
+        for db in &md.sql_databases {
+            g.resources.insert((NodeKind::Database, db.name.clone()));
+        }
+        for bkt in &md.buckets {
+            g.resources.insert((NodeKind::Bucket, bkt.name.clone()));
+        }
+
+        for svc in &md.svcs {
+            let from = svc_id(&svc.name);
+            for db in &svc.databases {
+                g.resources.insert((NodeKind::Database, db.clone()));
+                g.edge(&from, &node_id(NodeKind::Database, db), "uses");
+            }
+            for usage in &svc.buckets {
+                g.resources.insert((NodeKind::Bucket, usage.bucket.clone()));
+                g.edge(
+                    &from,
+                    &node_id(NodeKind::Bucket, &usage.bucket),
+                    bucket_access(usage),
+                );
+            }
+        }
+
+        for topic in &md.pubsub_topics {
+            g.resources.insert((NodeKind::Topic, topic.name.clone()));
+            let id = node_id(NodeKind::Topic, &topic.name);
+            for publisher in &topic.publishers {
+                g.edge(&svc_id(&publisher.service_name), &id, "publishes");
+            }
+            for sub in &topic.subscriptions {
+                g.edge(&id, &svc_id(&sub.service_name), &sub.name);
+            }
+        }
+
+        // Calls are recorded per package, and identify the endpoint by its package.
+        let pkg_svc: BTreeMap<&str, &str> = md
+            .pkgs
+            .iter()
+            .filter(|pkg| !pkg.service_name.is_empty())
+            .map(|pkg| (pkg.rel_path.as_str(), pkg.service_name.as_str()))
+            .collect();
+        for pkg in &md.pkgs {
+            if pkg.service_name.is_empty() {
+                continue;
+            }
+            for call in &pkg.rpc_calls {
+                if let Some(dst) = pkg_svc.get(call.pkg.as_str()) {
+                    g.edge(
+                        &svc_id(&pkg.service_name),
+                        &endpoint_id(dst, &call.name),
+                        "calls",
+                    );
+                }
+            }
+        }
+
+        g
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: &str) {
+        self.edges
+            .insert((from.to_string(), to.to_string(), label.to_string()));
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph app {\n  rankdir=LR;\n");
+        for (svc, eps) in &self.services {
+            let id = svc_id(svc);
+            let _ = writeln!(out, "  subgraph cluster_{id} {{");
+            let _ = writeln!(out, "    label={};", dot_quote(svc));
+            let _ = writeln!(out, "    {id} [label={}, shape=box];", dot_quote(svc));
+            for ep in eps {
+                let _ = writeln!(
+                    out,
+                    "    {} [label={}, shape=ellipse];",
+                    endpoint_id(svc, ep),
+                    dot_quote(ep)
+                );
+            }
+            out.push_str("  }\n");
+        }
+        for (kind, name) in &self.resources {
+            let shape = match kind {
+                NodeKind::Database => "cylinder",
+                NodeKind::Bucket => "folder",
+                NodeKind::Topic => "cds",
+            };
+            let _ = writeln!(
+                out,
+                "  {} [label={}, shape={shape}];",
+                node_id(*kind, name),
+                dot_quote(name)
+            );
+        }
+        for (from, to, label) in &self.edges {
+            let _ = writeln!(out, "  {from} -> {to} [label={}];", dot_quote(label));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for (svc, eps) in &self.services {
+            let _ = writeln!(out, "  subgraph {}[{}]", svc_id(svc), mermaid_quote(svc));
+            for ep in eps {
+                let _ = writeln!(out, "    {}([{}])", endpoint_id(svc, ep), mermaid_quote(ep));
+            }
+            out.push_str("  end\n");
+        }
+        for (kind, name) in &self.resources {
+            let id = node_id(*kind, name);
+            let label = mermaid_quote(name);
+            let _ = match kind {
+                NodeKind::Database => writeln!(out, "  {id}[({label})]"),
+                NodeKind::Bucket => writeln!(out, "  {id}[/{label}/]"),
+                NodeKind::Topic => writeln!(out, "  {id}>{label}]"),
+            };
+        }
+        for (from, to, label) in &self.edges {
+            let _ = writeln!(out, "  {from} -->|{}| {to}", mermaid_quote(label));
+        }
+        out
+    }
+}
+
+/// Summarizes how a service accesses a bucket.
+fn bucket_access(usage: &v1::BucketUsage) -> &'static str {
+    use v1::bucket_usage::Operation;
+    let (mut reads, mut writes) = (false, false);
+    for op in usage.operations() {
+        match op {
+            Operation::ListObjects
+            | Operation::ReadObjectContents
+            | Operation::GetObjectMetadata
+            | Operation::GetPublicUrl
+            | Operation::SignedDownloadUrl => reads = true,
+            Operation::WriteObject
+            | Operation::UpdateObjectMetadata
+            | Operation::DeleteObject
+            | Operation::SignedUploadUrl => writes = true,
+            Operation::Unknown => {}
+        }
+    }
+    match (reads, writes) {
+        (true, true) => "reads, writes",
+        (false, true) => "writes",
+        (true, false) => "reads",
+        (false, false) => "uses",
+    }
+}
+
+fn svc_id(name: &str) -> String {
+    format!("svc_{}", sanitize(name))
+}
+
+fn endpoint_id(svc: &str, name: &str) -> String {
+    format!("ep_{}__{}", sanitize(svc), sanitize(name))
+}
+
+fn node_id(kind: NodeKind, name: &str) -> String {
+    let prefix = match kind {
+        NodeKind::Database => "db",
+        NodeKind::Bucket => "bucket",
+        NodeKind::Topic => "topic",
+    };
+    format!("{prefix}_{}", sanitize(name))
+}
+
+/// Makes the name usable in a node id, which only allows word characters.
+/// Other characters are escaped by their code point, as in `_2d_` for '-',
+/// so that distinct names such as "a-b" and "a_b" get distinct ids.
+fn sanitize(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c);
+        } else {
+            let _ = write!(out, "_{:x}_", c as u32);
+        }
+    }
+    out
+}
+
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn mermaid_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "#quot;"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> v1::Data {
+        v1::Data {
+            pkgs: vec![
+                v1::Package {
+                    rel_path: "orders".into(),
+                    service_name: "orders".into(),
+                    rpc_calls: vec![v1::QualifiedName {
+                        pkg: "users".into(),
+                        name: "get".into(),
+                    }],
+                    ..Default::default()
+                },
+                v1::Package {
+                    rel_path: "users".into(),
+                    service_name: "users".into(),
+                    ..Default::default()
+                },
+            ],
+            svcs: vec![
+                v1::Service {
+                    name: "orders".into(),
+                    rpcs: vec![v1::Rpc {
+                        name: "create".into(),
+                        ..Default::default()
+                    }],
+                    databases: vec!["orders".into()],
+                    buckets: vec![v1::BucketUsage {
+                        bucket: "invoices".into(),
+                        operations: vec![v1::bucket_usage::Operation::WriteObject as i32],
+                    }],
+                    ..Default::default()
+                },
+                v1::Service {
+                    name: "users".into(),
+                    rpcs: vec![v1::Rpc {
+                        name: "get".into(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+            ],
+            pubsub_topics: vec![v1::PubSubTopic {
+                name: "order-created".into(),
+                publishers: vec![v1::pub_sub_topic::Publisher {
+                    service_name: "orders".into(),
+                }],
+                subscriptions: vec![v1::pub_sub_topic::Subscription {
+                    name: "send-welcome".into(),
+                    service_name: "users".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dot() {
+        let dot = Graph::new(&meta()).to_dot();
+        assert!(dot.starts_with("digraph app {\n"));
+        assert!(dot.contains("  subgraph cluster_svc_orders {\n"));
+        assert!(dot.contains("    ep_orders__create [label=\"create\", shape=ellipse];\n"));
+        assert!(dot.contains("  db_orders [label=\"orders\", shape=cylinder];\n"));
+        assert!(dot.contains("  svc_orders -> bucket_invoices [label=\"writes\"];\n"));
+        assert!(dot.contains("  svc_orders -> topic_order_2d_created [label=\"publishes\"];\n"));
+        assert!(dot.contains("  topic_order_2d_created -> svc_users [label=\"send-welcome\"];\n"));
+        assert!(dot.contains("  svc_orders -> ep_users__get [label=\"calls\"];\n"));
+    }
+
+    #[test]
+    fn mermaid() {
+        let mermaid = Graph::new(&meta()).to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid
+            .contains("  subgraph svc_users[\"users\"]\n    ep_users__get([\"get\"])\n  end\n"));
+        assert!(mermaid.contains("  db_orders[(\"orders\")]\n"));
+        assert!(mermaid.contains("  topic_order_2d_created>\"order-created\"]\n"));
+        assert!(mermaid.contains("  svc_orders -->|\"uses\"| db_orders\n"));
+    }
+
+    #[test]
+    fn distinct_ids() {
+        assert_eq!(sanitize("order-created"), "order_2d_created");
+        assert_ne!(sanitize("a-b"), sanitize("a_b"));
+        assert_ne!(endpoint_id("a", "_b"), endpoint_id("a_", "b"));
+    }
+}
@@ -7,12 +7,16 @@ use serde::{Deserialize, Serialize};
 
 pub use codegen::{CodegenParams, CodegenResult};
 pub use compile::CompileParams;
+pub use diff::{BreakingChange, ChangeKind, DiffParams, DiffResult};
+pub use graph::{GraphFormat, GraphParams};
 pub use parse::{ParseError, ParseParams};
 pub use prepare::{PackageVersion, PrepareParams};
 pub use test::TestParams;
 
 mod codegen;
 mod compile;
+mod diff;
+mod graph;
 mod package_mgmt;
 mod parse;
 mod prepare;
@@ -4,6 +4,7 @@ use std::path::Path;
 use crate::app::{validate_and_describe, AppDesc};
 use crate::parser::parser::{ParseContext, Parser};
 use crate::parser::resourceparser::PassOneParser;
+use crate::parser::CancellationToken;
 
 use super::{App, Builder};
 
@@ -13,6 +14,11 @@ pub struct ParseParams<'a> {
     pub pc: &'a ParseContext,
     pub working_dir: &'a Path,
     pub parse_tests: bool,
+    /// Drop function bodies that aren't needed after resource extraction,
+    /// to reduce memory usage for large apps.
+    pub low_memory: bool,
+    /// Aborts the parse when cancelled, in which case no result is returned.
+    pub cancel: CancellationToken,
 }
 
 #[derive(Debug)]
@@ -27,6 +33,8 @@ impl Display for ParseError {
 impl Builder<'_> {
     pub fn parse(&self, params: &ParseParams) -> Option<AppDesc> {
         let pc = params.pc;
+        pc.loader.set_strip_bodies(params.low_memory);
+        pc.loader.set_cancellation_token(params.cancel.clone());
         let pass1 = PassOneParser::new(
             pc.file_set.clone(),
             pc.type_checker.clone(),
@@ -35,6 +43,9 @@ impl Builder<'_> {
         let parser = Parser::new(pc, pass1);
 
         let result = parser.parse();
+        if params.cancel.is_cancelled() {
+            return None;
+        }
         let desc = validate_and_describe(pc, result)?;
 
         if pc.errs.has_errors() {
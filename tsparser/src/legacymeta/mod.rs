@@ -9,7 +9,9 @@ use crate::encore::parser::meta::v1::{self, selector, Selector};
 use crate::encore::parser::schema::v1::Builtin;
 use crate::legacymeta::schema::{loc_from_range, SchemaBuilder};
 use crate::parser::parser::{ParseContext, ParseResult, Service};
+use crate::parser::query::Query;
 use crate::parser::resourceparser::bind::{Bind, BindKind};
+use crate::parser::resources::apis::api::ETag;
 use crate::parser::resources::apis::{authhandler, gateway};
 use crate::parser::resources::infra::cron::CronJobSchedule;
 use crate::parser::resources::infra::metrics::MetricType;
@@ -19,7 +21,7 @@ use crate::parser::resources::Resource;
 use crate::parser::types::{validation, Basic, FieldName, Literal, Type};
 use crate::parser::types::{Object, ObjectId};
 use crate::parser::usageparser::Usage;
-use crate::parser::{respath, FilePath, Range};
+use crate::parser::{respath, Range};
 use litparser::{ParseResult as PResult, ToParseErr};
 
 mod api_schema;
@@ -232,6 +234,11 @@ impl MetaBuilder<'_> {
                         streaming_request: ep.streaming_request,
                         streaming_response: ep.streaming_response,
                         static_assets,
+                        etag: match ep.etag {
+                            ETag::Disabled => v1::rpc::Etag::Disabled,
+                            ETag::Strong => v1::rpc::Etag::Strong,
+                            ETag::Weak => v1::rpc::Etag::Weak,
+                        } as i32,
                     };
 
                     let Some(service_idx) =
@@ -813,14 +820,7 @@ impl MetaBuilder<'_> {
     }
 
     fn service_for_range(&self, range: &Range) -> Option<&Service> {
-        let path = match range.file(&self.pc.file_set) {
-            FilePath::Real(path) => path,
-            FilePath::Custom(_) => return None,
-        };
-        self.parse
-            .services
-            .iter()
-            .find(|svc| path.starts_with(svc.root.as_path()))
+        Query::new(&self.pc.file_set, self.parse).service_for_range(range)
     }
 }
 
@@ -964,6 +964,7 @@ mod tests {
     use swc_common::{Globals, SourceMap, GLOBALS};
     use tempdir::TempDir;
 
+    use crate::encore::parser::schema::v1::r#type::Typ;
     use crate::parser::parser::Parser;
     use crate::parser::resourceparser::PassOneParser;
     use crate::testutil::testresolve::TestResolver;
@@ -1023,4 +1024,120 @@ export const Bar = 5;
         assert_eq!(meta.svcs.len(), 0);
         Ok(())
     }
+
+    #[test]
+    fn test_field_optionality() -> anyhow::Result<()> {
+        let src = r#"
+-- svc/encore.service.ts --
+import { Service } from "encore.dev/service";
+export default new Service("svc");
+-- svc/api.ts --
+import { api } from "encore.dev/api";
+
+interface Params {
+    a?: string;
+    b: string | null;
+    c: string | undefined;
+    d?: string | null;
+}
+
+export const ping = api<Params, void>({}, async () => {});
+        "#;
+        let tmp_dir = TempDir::new("tsparser-test")?;
+        let meta = parse(tmp_dir.path(), src)?;
+
+        let rpc = &meta.svcs[0].rpcs[0];
+        let Some(Typ::Named(named)) = rpc.request_schema.as_ref().and_then(|t| t.typ.as_ref())
+        else {
+            panic!("expected named request type");
+        };
+        let Some(Typ::Struct(st)) = meta.decls[named.id as usize]
+            .r#type
+            .as_ref()
+            .and_then(|t| t.typ.as_ref())
+        else {
+            panic!("expected struct request type");
+        };
+
+        let flags: Vec<_> = st
+            .fields
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_str(),
+                    f.optional,
+                    f.nullable,
+                    f.explicit_undefined,
+                )
+            })
+            .collect();
+        assert_eq!(
+            flags,
+            vec![
+                ("a", true, false, false),
+                ("b", false, true, false),
+                ("c", true, false, true),
+                ("d", true, true, false),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_defaults() -> anyhow::Result<()> {
+        let src = r#"
+-- svc/encore.service.ts --
+import { Service } from "encore.dev/service";
+export default new Service("svc");
+-- svc/api.ts --
+import { api } from "encore.dev/api";
+
+const maxLimit = () => 100;
+
+interface Params {
+    limit?: number;
+    order?: "asc" | "desc";
+    tags?: string[];
+    max?: number;
+    query: string;
+}
+
+export const list = api<Params, void>(
+    {},
+    async ({ limit = 20, order: dir = "desc", tags = ["a"], max = maxLimit() }) => {},
+);
+        "#;
+        let tmp_dir = TempDir::new("tsparser-test")?;
+        let meta = parse(tmp_dir.path(), src)?;
+
+        let rpc = &meta.svcs[0].rpcs[0];
+        let Some(Typ::Named(named)) = rpc.request_schema.as_ref().and_then(|t| t.typ.as_ref())
+        else {
+            panic!("expected named request type");
+        };
+        let Some(Typ::Struct(st)) = meta.decls[named.id as usize]
+            .r#type
+            .as_ref()
+            .and_then(|t| t.typ.as_ref())
+        else {
+            panic!("expected struct request type");
+        };
+
+        let defaults: Vec<_> = st
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.default_value.as_deref()))
+            .collect();
+        assert_eq!(
+            defaults,
+            vec![
+                ("limit", Some("20")),
+                ("order", Some("\"desc\"")),
+                ("tags", Some("[\"a\"]")),
+                ("max", None),
+                ("query", None),
+            ]
+        );
+        Ok(())
+    }
 }
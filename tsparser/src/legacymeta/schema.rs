@@ -16,7 +16,7 @@ use crate::parser::resources::apis::api::Endpoint;
 use crate::parser::resources::apis::encoding::resolve_wire_spec;
 use crate::parser::types::{
     drop_empty_or_void, unwrap_validated, Basic, Custom, EnumValue, FieldName, Generic, Interface,
-    Literal, Named, ObjectId, Type, Union, WireLocation,
+    Literal, Named, ObjectId, Tuple, Type, Union, WireLocation,
 };
 use crate::parser::{FilePath, FileSet, Range};
 
@@ -124,7 +124,7 @@ impl BuilderCtx<'_, '_> {
                 })),
                 validation: None,
             },
-            Type::Tuple(_) => anyhow::bail!("tuple types are not yet supported in schemas"),
+            Type::Tuple(tt) => self.tuple(tt)?,
             Type::Literal(tt) => schema::Type {
                 typ: Some(styp::Typ::Literal(self.literal(tt))),
                 validation: None,
@@ -272,6 +272,7 @@ impl BuilderCtx<'_, '_> {
             };
             let (tt, had_undefined) = drop_undefined_union(&f.typ);
             let optional = f.optional || had_undefined;
+            let nullable = self.is_nullable(&tt);
 
             let mut tags = vec![];
 
@@ -297,7 +298,7 @@ impl BuilderCtx<'_, '_> {
                             tags.push(schema::Tag {
                                 key: "header".into(),
                                 name,
-                                options: if f.optional {
+                                options: if optional {
                                     vec!["optional".into()]
                                 } else {
                                     vec![]
@@ -318,7 +319,7 @@ impl BuilderCtx<'_, '_> {
                             tags.push(schema::Tag {
                                 key: "query".into(),
                                 name: query_string_name.clone(),
-                                options: if f.optional {
+                                options: if optional {
                                     vec!["optional".into()]
                                 } else {
                                     vec![]
@@ -350,7 +351,7 @@ impl BuilderCtx<'_, '_> {
                             tags.push(schema::Tag {
                                 key: "cookie".into(),
                                 name: name.clone(),
-                                options: if f.optional {
+                                options: if optional {
                                     vec!["optional".into()]
                                 } else {
                                     vec![]
@@ -370,7 +371,7 @@ impl BuilderCtx<'_, '_> {
                             tags.push(schema::Tag {
                                 key: "encore".into(),
                                 name: "httpstatus".into(),
-                                options: if f.optional {
+                                options: if optional {
                                     vec!["optional".into()]
                                 } else {
                                     vec![]
@@ -421,6 +422,9 @@ impl BuilderCtx<'_, '_> {
                 name: field_name.clone(),
                 json_name: field_name.clone(),
                 optional,
+                nullable,
+                explicit_undefined: had_undefined,
+                default_value: None,
                 wire,
                 tags,
                 raw_tag,
@@ -435,6 +439,21 @@ impl BuilderCtx<'_, '_> {
         })
     }
 
+    /// Reports whether the type accepts null, as in `T | null`.
+    fn is_nullable(&self, typ: &Type) -> bool {
+        match typ {
+            Type::Basic(Basic::Null) => true,
+            Type::Union(union) => union.types.iter().any(|t| self.is_nullable(t)),
+            Type::Validated(v) => self.is_nullable(&v.typ),
+            Type::Custom(Custom::WireSpec(spec)) => self.is_nullable(&spec.underlying),
+            Type::Named(named) => {
+                let state = self.builder.pc.type_checker.state();
+                self.is_nullable(&named.underlying(state))
+            }
+            _ => false,
+        }
+    }
+
     fn named(&mut self, typ: &Named) -> Result<schema::Named> {
         let type_arguments = self.types(&typ.type_arguments)?;
         let obj = &typ.obj;
@@ -511,6 +530,30 @@ impl BuilderCtx<'_, '_> {
         Ok(schema::Named { id, type_arguments })
     }
 
+    fn tuple(&mut self, tuple: &Tuple) -> Result<schema::Type> {
+        let mut elems = Vec::with_capacity(tuple.types.len());
+        let mut min_len = 0;
+        for (i, typ) in tuple.types.iter().enumerate() {
+            let elem = match typ {
+                // Optional elements can be omitted from the end of the tuple.
+                Type::Optional(opt) => self.typ(&opt.0)?,
+                typ => {
+                    min_len = i + 1;
+                    self.typ(typ)?
+                }
+            };
+            elems.push(elem);
+        }
+
+        Ok(schema::Type {
+            typ: Some(styp::Typ::Tuple(schema::Tuple {
+                elems,
+                min_len: min_len as u32,
+            })),
+            validation: None,
+        })
+    }
+
     fn types(&mut self, types: &[Type]) -> Result<Vec<schema::Type>> {
         let mut result = Vec::with_capacity(types.len());
         for t in types {
@@ -532,14 +575,48 @@ impl BuilderCtx<'_, '_> {
     }
     fn transform_request(&mut self, ep: &Endpoint) -> ParseResult<Option<schema::Type>> {
         let schema = ep.encoding.raw_req_schema.as_ref().map(|s| s.get());
-        self.transform_request_type(ep, schema).map_err(|err| {
-            let sp = ep
-                .encoding
-                .raw_req_schema
-                .as_ref()
-                .map_or(ep.range.to_span(), |s| s.span());
-            sp.parse_err(err.to_string())
-        })
+        self.transform_request_type(ep, schema)
+            .map(|typ| {
+                let mut typ = typ?;
+                self.apply_request_defaults(ep, &mut typ);
+                Some(typ)
+            })
+            .map_err(|err| {
+                let sp = ep
+                    .encoding
+                    .raw_req_schema
+                    .as_ref()
+                    .map_or(ep.range.to_span(), |s| s.span());
+                sp.parse_err(err.to_string())
+            })
+    }
+
+    /// Records the handler's defaults on the request fields.
+    /// The request schema is specific to the endpoint, so they don't leak to other endpoints.
+    fn apply_request_defaults(&mut self, ep: &Endpoint, typ: &mut schema::Type) {
+        if ep.request_defaults.is_empty() {
+            return;
+        }
+        let typ = match &mut typ.typ {
+            Some(styp::Typ::Named(named)) => self
+                .builder
+                .decls
+                .get_mut(named.id as usize)
+                .and_then(|decl| decl.r#type.as_mut()),
+            _ => Some(typ),
+        };
+        let Some(schema::Type {
+            typ: Some(styp::Typ::Struct(st)),
+            ..
+        }) = typ
+        else {
+            return;
+        };
+        for field in &mut st.fields {
+            if let Some(value) = ep.request_defaults.get(&field.name) {
+                field.default_value = Some(value.to_string());
+            }
+        }
     }
 
     fn transform_request_type(
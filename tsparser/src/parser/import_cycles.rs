@@ -0,0 +1,289 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use swc_common::errors::HANDLER;
+use swc_common::sync::Lrc;
+use swc_common::Span;
+
+use crate::parser::module_loader::{Module, ModuleId, ModuleLoader};
+use crate::parser::resourceparser::bind::{Bind, BindKind};
+use crate::parser::service_discovery::DiscoveredService;
+use crate::parser::FilePath;
+
+/// Reports import cycles between modules defining resources in different services.
+///
+/// Such cycles mean a service's resources can be used before the module defining
+/// them has been evaluated, which otherwise surfaces as confusing resolution errors.
+pub fn check_import_cycles(
+    loader: &ModuleLoader,
+    app_root: &Path,
+    services: &[DiscoveredService],
+    binds: &[Lrc<Bind>],
+) {
+    let resource_mods: HashSet<ModuleId> = binds
+        .iter()
+        .filter(|b| matches!(b.kind, BindKind::Create))
+        .map(|b| b.module_id)
+        .collect();
+
+    let mut modules: Vec<Lrc<Module>> = loader
+        .modules()
+        .into_iter()
+        .filter(|m| is_app_module(app_root, m))
+        .collect();
+    modules.sort_by_key(|m| m.id.0);
+    let by_id: HashMap<ModuleId, &Lrc<Module>> = modules.iter().map(|m| (m.id, m)).collect();
+
+    // Build the import graph, keeping the span of the first import of each edge.
+    let mut graph: HashMap<ModuleId, Vec<ModuleId>> = HashMap::new();
+    let mut import_spans: HashMap<(ModuleId, ModuleId), Span> = HashMap::new();
+    for module in &modules {
+        let edges = graph.entry(module.id).or_default();
+        for import in module.imports() {
+            // Type-only imports are erased, so they can't cause cycles at runtime.
+            if import.type_only {
+                continue;
+            }
+            // Unresolvable imports are reported elsewhere.
+            let Ok(Some(target)) = loader.resolve_import_from_module(module, &import.src.value)
+            else {
+                continue;
+            };
+            if by_id.contains_key(&target.id)
+                && import_spans
+                    .insert((module.id, target.id), import.span)
+                    .is_none()
+            {
+                edges.push(target.id);
+            }
+        }
+    }
+
+    let nodes: Vec<ModuleId> = modules.iter().map(|m| m.id).collect();
+    let service_of = |id: ModuleId| {
+        if !resource_mods.contains(&id) {
+            return None;
+        }
+        let FilePath::Real(path) = &by_id[&id].file_path else {
+            return None;
+        };
+        services
+            .iter()
+            .filter(|svc| path.starts_with(&svc.root))
+            .max_by_key(|svc| svc.root.components().count())
+            .map(|svc| svc.name.as_str())
+    };
+
+    for cycle in find_cycles(&nodes, &graph, service_of) {
+        let display = |id: &ModuleId| display_path(app_root, by_id[id]);
+        let path = cycle.iter().map(display).collect::<Vec<_>>().join(" -> ");
+
+        HANDLER.with(|h| {
+            let first = import_spans[&(cycle[0], cycle[1])];
+            let mut diag = h.struct_span_err(
+                first,
+                &format!("import cycle between resources of different services: {path}"),
+            );
+            for pair in cycle.windows(2).skip(1) {
+                let (from, to) = (pair[0], pair[1]);
+                diag.span_note(
+                    import_spans[&(from, to)],
+                    &format!("{} imports {}", display(&from), display(&to)),
+                );
+            }
+            diag.help("move the shared resources into a module that doesn't import the others");
+            diag.emit();
+        });
+    }
+}
+
+/// Finds a cycle through each strongly connected component of the graph that
+/// contains nodes with different owners. Each cycle starts and ends with the
+/// first owned node in the component, in the order of `nodes`.
+fn find_cycles<O: PartialEq>(
+    nodes: &[ModuleId],
+    graph: &HashMap<ModuleId, Vec<ModuleId>>,
+    owner: impl Fn(ModuleId) -> Option<O>,
+) -> Vec<Vec<ModuleId>> {
+    let mut cycles = Vec::new();
+    for component in strongly_connected(nodes, graph) {
+        let mut owned = nodes
+            .iter()
+            .filter(|n| component.contains(*n))
+            .filter_map(|&n| owner(n).map(|o| (n, o)));
+        let Some((start, start_owner)) = owned.next() else {
+            continue;
+        };
+        let Some((other, _)) = owned.find(|(_, o)| *o != start_owner) else {
+            continue;
+        };
+
+        let there = shortest_path(start, other, &component, graph);
+        let back = shortest_path(other, start, &component, graph);
+        if let (Some(mut cycle), Some(back)) = (there, back) {
+            cycle.extend(&back[1..]);
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+/// Returns the strongly connected components of the graph, using Tarjan's algorithm.
+fn strongly_connected(
+    nodes: &[ModuleId],
+    graph: &HashMap<ModuleId, Vec<ModuleId>>,
+) -> Vec<HashSet<ModuleId>> {
+    struct Tarjan<'a> {
+        graph: &'a HashMap<ModuleId, Vec<ModuleId>>,
+        next_index: usize,
+        index: HashMap<ModuleId, usize>,
+        low_link: HashMap<ModuleId, usize>,
+        stack: Vec<ModuleId>,
+        on_stack: HashSet<ModuleId>,
+        components: Vec<HashSet<ModuleId>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, node: ModuleId) {
+            self.index.insert(node, self.next_index);
+            self.low_link.insert(node, self.next_index);
+            self.next_index += 1;
+            self.stack.push(node);
+            self.on_stack.insert(node);
+
+            let graph = self.graph;
+            for &next in graph.get(&node).into_iter().flatten() {
+                if !self.index.contains_key(&next) {
+                    self.visit(next);
+                    let low = self.low_link[&node].min(self.low_link[&next]);
+                    self.low_link.insert(node, low);
+                } else if self.on_stack.contains(&next) {
+                    let low = self.low_link[&node].min(self.index[&next]);
+                    self.low_link.insert(node, low);
+                }
+            }
+
+            if self.low_link[&node] == self.index[&node] {
+                let mut component = HashSet::new();
+                while let Some(n) = self.stack.pop() {
+                    self.on_stack.remove(&n);
+                    component.insert(n);
+                    if n == node {
+                        break;
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut t = Tarjan {
+        graph,
+        next_index: 0,
+        index: HashMap::new(),
+        low_link: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        components: Vec::new(),
+    };
+    for &node in nodes {
+        if !t.index.contains_key(&node) {
+            t.visit(node);
+        }
+    }
+    t.components
+}
+
+/// Returns the shortest path between two nodes within the component.
+fn shortest_path(
+    from: ModuleId,
+    to: ModuleId,
+    component: &HashSet<ModuleId>,
+    graph: &HashMap<ModuleId, Vec<ModuleId>>,
+) -> Option<Vec<ModuleId>> {
+    let mut prev: HashMap<ModuleId, ModuleId> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            let mut path = vec![to];
+            let mut curr = to;
+            while let Some(&p) = prev.get(&curr) {
+                path.push(p);
+                curr = p;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for &next in graph.get(&node).into_iter().flatten() {
+            if next != from && component.contains(&next) && !prev.contains_key(&next) {
+                prev.insert(next, node);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+fn is_app_module(app_root: &Path, module: &Module) -> bool {
+    match &module.file_path {
+        FilePath::Real(path) => {
+            path.starts_with(app_root)
+                && !path.components().any(|c| c.as_os_str() == "node_modules")
+        }
+        FilePath::Custom(_) => false,
+    }
+}
+
+fn display_path(app_root: &Path, module: &Module) -> String {
+    match &module.file_path {
+        FilePath::Real(path) => path
+            .strip_prefix(app_root)
+            .unwrap_or(path)
+            .display()
+            .to_string(),
+        FilePath::Custom(name) => name.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(usize, usize)]) -> HashMap<ModuleId, Vec<ModuleId>> {
+        let mut graph: HashMap<ModuleId, Vec<ModuleId>> = HashMap::new();
+        for &(from, to) in edges {
+            graph.entry(ModuleId(from)).or_default().push(ModuleId(to));
+        }
+        graph
+    }
+
+    fn ids(ids: &[usize]) -> Vec<ModuleId> {
+        ids.iter().map(|&id| ModuleId(id)).collect()
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        let nodes = ids(&[1, 2, 3, 4, 5, 6]);
+        // 1 -> 2 -> 3 -> 1 is a cycle, with a shortcut 1 -> 3.
+        // 4 <-> 5 is another cycle, and 6 isn't part of one.
+        let g = graph(&[(1, 2), (2, 3), (3, 1), (1, 3), (4, 5), (5, 4), (3, 6)]);
+        let owners = |owners: &'static [(usize, &'static str)]| {
+            move |id: ModuleId| owners.iter().find(|(n, _)| *n == id.0).map(|(_, o)| *o)
+        };
+
+        let cycles = find_cycles(&nodes, &g, owners(&[(2, "a"), (1, "b"), (6, "c")]));
+        assert_eq!(cycles, vec![ids(&[1, 2, 3, 1])]);
+
+        let cycles = find_cycles(
+            &nodes,
+            &g,
+            owners(&[(3, "a"), (1, "b"), (4, "a"), (5, "b")]),
+        );
+        assert_eq!(cycles.len(), 2);
+        assert!(cycles.contains(&ids(&[1, 3, 1])));
+        assert!(cycles.contains(&ids(&[4, 5, 4])));
+
+        // Cycles within a single owner aren't reported.
+        assert!(find_cycles(&nodes, &g, owners(&[(1, "a"), (2, "a"), (6, "b")])).is_empty());
+    }
+}
@@ -1,8 +1,11 @@
+mod cancel;
 mod doc_comments;
 mod fileset;
+mod import_cycles;
 pub mod module_loader;
 #[allow(clippy::module_inception)]
 pub mod parser;
+pub mod query;
 pub mod resourceparser;
 pub mod resources;
 pub mod respath;
@@ -10,4 +13,5 @@ mod service_discovery;
 pub mod types;
 pub mod usageparser;
 
+pub use cancel::CancellationToken;
 pub use fileset::{FilePath, FileSet, Pos, Range, ZERO_RANGE};
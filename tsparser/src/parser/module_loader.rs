@@ -1,4 +1,4 @@
-use std::cell::{OnceCell, RefCell};
+use std::cell::{Cell, OnceCell, RefCell};
 use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::io;
@@ -14,11 +14,11 @@ use swc_ecma_ast::EsVersion;
 use swc_ecma_loader::resolve::Resolve;
 use swc_ecma_parser::lexer::Lexer;
 use swc_ecma_parser::{Parser, Syntax};
-use swc_ecma_visit::FoldWith;
+use swc_ecma_visit::{FoldWith, VisitMut, VisitMutWith};
 use thiserror::Error;
 
 use crate::parser::fileset::SourceFile;
-use crate::parser::{FilePath, FileSet, Pos};
+use crate::parser::{CancellationToken, FilePath, FileSet, Pos};
 
 // File extensions that should be parsed as modules
 const MODULE_EXTENSIONS: &[&str] = &["js", "ts", "mjs", "mts", "cjs", "cts", "jsx", "tsx"];
@@ -27,6 +27,87 @@ const MODULE_EXTENSIONS: &[&str] = &["js", "ts", "mjs", "mts", "cjs", "cts", "js
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ModuleId(pub usize);
 
+/// A module generated by the parser rather than loaded from a file.
+///
+/// Each synthetic module has a stable identity: it's stored under the same
+/// file path and keeps its [ModuleId] when it's regenerated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntheticModule {
+    /// Declarations of the TypeScript built-ins.
+    Universe,
+    /// The generated encore.gen/clients module.
+    AppClients,
+    /// The generated encore.gen/auth module.
+    Auth,
+}
+
+impl SyntheticModule {
+    pub const ALL: [SyntheticModule; 3] = [
+        SyntheticModule::Universe,
+        SyntheticModule::AppClients,
+        SyntheticModule::Auth,
+    ];
+
+    /// The path the module is stored under.
+    pub fn file_path(self) -> FilePath {
+        FilePath::Real(self.name().into())
+    }
+
+    /// Returns the synthetic module stored under the given path, if any.
+    pub fn from_file_path(path: &FilePath) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| &m.file_path() == path)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SyntheticModule::Universe => "universe.ts",
+            SyntheticModule::AppClients => "encore.gen/clients",
+            SyntheticModule::Auth => "encore.gen/auth",
+        }
+    }
+
+    fn module_path(self) -> &'static str {
+        match self {
+            SyntheticModule::Universe => "__universe__",
+            SyntheticModule::AppClients => "encore.gen/clients",
+            SyntheticModule::Auth => "encore.gen/auth",
+        }
+    }
+
+    /// The source the module starts out with.
+    fn initial_source(self) -> &'static str {
+        match self {
+            SyntheticModule::Universe => UNIVERSE_TS,
+            SyntheticModule::AppClients | SyntheticModule::Auth => "",
+        }
+    }
+
+    /// Returns the generated module imported with the given path alias.
+    fn from_import_path(import_path: &str) -> Option<Self> {
+        match import_path {
+            "~encore/clients" => Some(SyntheticModule::AppClients),
+            "~encore/auth" => Some(SyntheticModule::Auth),
+            _ => None,
+        }
+    }
+
+    /// Returns the generated module a path within encore.gen resolves to.
+    fn from_encore_gen_path(suffix: &Path) -> Option<Self> {
+        // Check for the directory, since the resolved path
+        // will be something like "clients/index.js".
+        if suffix.starts_with("clients/") {
+            Some(SyntheticModule::AppClients)
+        } else if suffix.starts_with("auth/") {
+            Some(SyntheticModule::Auth)
+        } else {
+            None
+        }
+    }
+}
+
+/// Called with the new module when a synthetic module is regenerated.
+pub type RegenerateHook = Box<dyn Fn(SyntheticModule, &Lrc<Module>)>;
+
 pub struct ModuleLoader {
     errs: Lrc<Handler>,
     file_set: Lrc<FileSet>,
@@ -34,13 +115,20 @@ pub struct ModuleLoader {
     encore_gen_root: PathBuf,
     by_path: RefCell<HashMap<FilePath, Lrc<Module>>>,
 
-    // The universe module, if it's been loaded.
-    universe: OnceCell<Lrc<Module>>,
+    /// The id to give the next module that's loaded.
+    next_id: Cell<usize>,
 
-    /// The generated encore.gen/clients module.
-    encore_app_clients: OnceCell<Lrc<Module>>,
-    /// The generated encore.gen/auth module.
-    encore_auth: OnceCell<Lrc<Module>>,
+    /// Whether to drop function bodies where they aren't needed.
+    strip_bodies: Cell<bool>,
+
+    /// Cancels loading further modules when triggered.
+    cancel: RefCell<CancellationToken>,
+
+    /// The synthetic modules that have been loaded.
+    synthetic: RefCell<HashMap<SyntheticModule, Lrc<Module>>>,
+
+    /// Called when a synthetic module is regenerated.
+    regenerate_hooks: RefCell<Vec<RegenerateHook>>,
 }
 
 impl std::fmt::Debug for ModuleLoader {
@@ -62,12 +150,17 @@ pub enum Error {
     LoadFile(#[source] io::Error),
     #[error("error when parsing module")]
     ParseError(swc_ecma_parser::error::Error),
+    #[error("parse cancelled")]
+    Cancelled,
 }
 
 impl Error {
     pub fn span(&self) -> Option<Span> {
         match self {
-            Error::UnableToResolve(..) | Error::InvalidFilename(_) | Error::LoadFile(_) => None,
+            Error::UnableToResolve(..)
+            | Error::InvalidFilename(_)
+            | Error::LoadFile(_)
+            | Error::Cancelled => None,
             Error::ParseError(e) => Some(e.span()),
         }
     }
@@ -77,7 +170,7 @@ impl Error {
             Error::UnableToResolve(s, source) => {
                 format!("unable to resolve module {s}: {source:?}")
             }
-            Error::InvalidFilename(_) | Error::LoadFile(_) => self.to_string(),
+            Error::InvalidFilename(_) | Error::LoadFile(_) | Error::Cancelled => self.to_string(),
             Error::ParseError(e) => e.clone().into_kind().msg().to_string(),
         }
     }
@@ -97,12 +190,36 @@ impl ModuleLoader {
             resolver,
             encore_gen_root,
             by_path: RefCell::new(HashMap::new()),
-            universe: OnceCell::new(),
-            encore_app_clients: OnceCell::new(),
-            encore_auth: OnceCell::new(),
+            next_id: Cell::new(1),
+            strip_bodies: Cell::new(false),
+            cancel: RefCell::default(),
+            synthetic: RefCell::new(HashMap::new()),
+            regenerate_hooks: RefCell::new(Vec::new()),
         }
     }
 
+    /// Configures whether function bodies are dropped where they aren't needed,
+    /// to reduce memory usage for large apps. This applies to modules loaded
+    /// from `node_modules`, since resources and their usage are never parsed
+    /// from dependencies, and to the declarations kept by the type checker.
+    pub fn set_strip_bodies(&self, strip: bool) {
+        self.strip_bodies.set(strip);
+    }
+
+    pub fn strip_bodies(&self) -> bool {
+        self.strip_bodies.get()
+    }
+
+    /// Sets the token for cancelling the parse. Once cancelled,
+    /// loading modules fails with [Error::Cancelled].
+    pub fn set_cancellation_token(&self, token: CancellationToken) {
+        *self.cancel.borrow_mut() = token;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.borrow().is_cancelled()
+    }
+
     pub fn modules(&self) -> Vec<Lrc<Module>> {
         self.by_path.borrow().values().cloned().collect::<Vec<_>>()
     }
@@ -129,10 +246,8 @@ impl ModuleLoader {
         // Special case for the generated clients.
         // TODO: Fix this to do actual import path resolution.
         // It's a bit tricky because we can't use the resolver since the files may not exist.
-        if import_path == "~encore/clients" {
-            return Ok(Some(self.encore_app_clients()));
-        } else if import_path == "~encore/auth" {
-            return Ok(Some(self.encore_auth()));
+        if let Some(synthetic) = SyntheticModule::from_import_path(import_path) {
+            return Ok(Some(self.synthetic(synthetic)));
         }
 
         let target_file_path = {
@@ -151,14 +266,12 @@ impl ModuleLoader {
 
                     // Check for the generated clients again, using the resolved path,
                     // in case the "~encore/*" alias is not set up.
-                    if let Ok(suffix) = buf.strip_prefix(&self.encore_gen_root) {
-                        // Need to check for trailing slash since the resolved path
-                        // will be something like "clients/index.js".
-                        if suffix.starts_with("clients/") {
-                            return Ok(Some(self.encore_app_clients()));
-                        } else if suffix.starts_with("auth/") {
-                            return Ok(Some(self.encore_auth()));
-                        }
+                    if let Some(synthetic) = buf
+                        .strip_prefix(&self.encore_gen_root)
+                        .ok()
+                        .and_then(SyntheticModule::from_encore_gen_path)
+                    {
+                        return Ok(Some(self.synthetic(synthetic)));
                     }
 
                     FilePath::Real(buf.clone())
@@ -168,6 +281,9 @@ impl ModuleLoader {
             }
         };
 
+        if let Some(synthetic) = SyntheticModule::from_file_path(&target_file_path) {
+            return Ok(Some(self.synthetic(synthetic)));
+        }
         if let Some(module) = self.by_path.borrow().get(&target_file_path) {
             return Ok(Some(module.clone()));
         }
@@ -199,12 +315,19 @@ impl ModuleLoader {
     ) -> Result<Lrc<Module>, Error> {
         // Is it already stored?
         let file_name = FilePath::from(path.to_owned());
+        if let Some(synthetic) = SyntheticModule::from_file_path(&file_name) {
+            return Ok(self.synthetic(synthetic));
+        }
         if let Some(module) = self.by_path.borrow().get(&file_name) {
             return Ok(module.clone());
         }
 
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
         let file = self.file_set.load_file(path).map_err(Error::LoadFile)?;
-        let module = self.parse_and_store(file, module_path)?;
+        let module = self.parse_and_store(file, module_path, None)?;
         Ok(module)
     }
 
@@ -216,63 +339,98 @@ impl ModuleLoader {
         module_path: Option<String>,
     ) -> Result<Lrc<Module>, Error> {
         // Is it already stored?
+        if let Some(synthetic) = SyntheticModule::from_file_path(&file_name) {
+            return Ok(self.synthetic(synthetic));
+        }
         if let Some(module) = self.by_path.borrow().get(&file_name) {
             return Ok(module.clone());
         }
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
         let file = self
             .file_set
             .new_source_file(file_name.to_owned(), src.into());
-        let module = self.parse_and_store(file, module_path)?;
+        let module = self.parse_and_store(file, module_path, None)?;
         Ok(module)
     }
 
     pub fn universe(&self) -> Lrc<Module> {
-        self.universe
-            .get_or_init(|| {
-                let file = self
-                    .file_set
-                    .new_source_file(FilePath::Real("universe.ts".into()), UNIVERSE_TS.into());
-                self.parse_and_store(file, Some("__universe__".into()))
-                    .unwrap()
-            })
-            .to_owned()
+        self.synthetic(SyntheticModule::Universe)
     }
 
     pub fn encore_app_clients(&self) -> Lrc<Module> {
-        self.encore_app_clients
-            .get_or_init(|| {
-                let file = self
-                    .file_set
-                    .new_source_file(FilePath::Real("encore.gen/clients".into()), "".into());
-                self.parse_and_store(file, Some("encore.gen/clients".into()))
-                    .unwrap()
-            })
-            .to_owned()
+        self.synthetic(SyntheticModule::AppClients)
     }
 
     pub fn encore_auth(&self) -> Lrc<Module> {
-        self.encore_auth
-            .get_or_init(|| {
-                let file = self
-                    .file_set
-                    .new_source_file(FilePath::Real("encore.gen/auth".into()), "".into());
-                self.parse_and_store(file, Some("encore.gen/auth".into()))
-                    .unwrap()
-            })
-            .to_owned()
-    }
-
-    /// Parse and store a file.
+        self.synthetic(SyntheticModule::Auth)
+    }
+
+    /// Returns the synthetic module, loading it with its initial source if needed.
+    pub fn synthetic(&self, synthetic: SyntheticModule) -> Lrc<Module> {
+        if let Some(module) = self.synthetic.borrow().get(&synthetic) {
+            return module.clone();
+        }
+
+        let file = self
+            .file_set
+            .new_source_file(synthetic.file_path(), synthetic.initial_source().into());
+        let module = self
+            .parse_and_store(file, Some(synthetic.module_path().into()), None)
+            .expect("synthetic modules should parse");
+        self.synthetic
+            .borrow_mut()
+            .insert(synthetic, module.clone());
+        module
+    }
+
+    /// Replaces the source of a synthetic module, for when the code it's
+    /// generated from changes. The new module keeps the module's id, so
+    /// references to it stay valid, and the regeneration hooks are called with it.
+    pub fn regenerate(
+        &self,
+        synthetic: SyntheticModule,
+        src: String,
+    ) -> Result<Lrc<Module>, Error> {
+        let id = self.synthetic(synthetic).id;
+        let file = self.file_set.new_source_file(synthetic.file_path(), src);
+        let module = self.parse_and_store(file, Some(synthetic.module_path().into()), Some(id))?;
+        self.synthetic
+            .borrow_mut()
+            .insert(synthetic, module.clone());
+
+        for hook in self.regenerate_hooks.borrow().iter() {
+            hook(synthetic, &module);
+        }
+        Ok(module)
+    }
+
+    /// Registers a hook to call when a synthetic module is regenerated.
+    pub fn on_regenerate(&self, hook: impl Fn(SyntheticModule, &Lrc<Module>) + 'static) {
+        self.regenerate_hooks.borrow_mut().push(Box::new(hook));
+    }
+
+    /// Parse and store a file, with the given id or a new one.
     fn parse_and_store(
         &self,
         file: Lrc<SourceFile>,
         module_path: Option<String>,
+        id: Option<ModuleId>,
     ) -> Result<Lrc<Module>, Error> {
-        let (ast, comments) = self.parse_file(file.clone())?;
+        let (mut ast, comments) = self.parse_file(file.clone())?;
+        if self.strip_bodies.get() && is_dependency(&file.name()) {
+            strip_function_bodies(&mut ast);
+        }
 
-        let mut mods = self.by_path.borrow_mut();
-        let id = ModuleId(mods.len() + 1);
+        let id = id.unwrap_or_else(|| {
+            let id = self.next_id.get();
+            self.next_id.set(id + 1);
+            ModuleId(id)
+        });
 
+        let mut mods = self.by_path.borrow_mut();
         let module = Module::new(
             self.file_set.clone(),
             id,
@@ -321,6 +479,62 @@ impl ModuleLoader {
     }
 }
 
+/// Reports whether the file is part of an installed package.
+fn is_dependency(path: &FilePath) -> bool {
+    match path {
+        FilePath::Real(buf) => buf.components().any(|c| c.as_os_str() == "node_modules"),
+        FilePath::Custom(_) => false,
+    }
+}
+
+/// Replaces the bodies of all functions, methods and accessors with empty blocks,
+/// keeping their signatures. Arrow functions with expression bodies are kept.
+pub fn strip_function_bodies<N: VisitMutWith<StripBodies>>(node: &mut N) {
+    node.visit_mut_with(&mut StripBodies);
+}
+
+pub struct StripBodies;
+
+impl VisitMut for StripBodies {
+    fn visit_mut_block_stmt_or_expr(&mut self, body: &mut ast::BlockStmtOrExpr) {
+        match body {
+            ast::BlockStmtOrExpr::BlockStmt(block) => block.stmts.clear(),
+            ast::BlockStmtOrExpr::Expr(expr) => expr.as_mut().visit_mut_with(self),
+        }
+    }
+
+    fn visit_mut_function(&mut self, func: &mut ast::Function) {
+        for param in &mut func.params {
+            param.visit_mut_with(self);
+        }
+        if let Some(body) = &mut func.body {
+            body.stmts.clear();
+        }
+    }
+
+    fn visit_mut_constructor(&mut self, cons: &mut ast::Constructor) {
+        if let Some(body) = &mut cons.body {
+            body.stmts.clear();
+        }
+    }
+
+    fn visit_mut_static_block(&mut self, block: &mut ast::StaticBlock) {
+        block.body.stmts.clear();
+    }
+
+    fn visit_mut_getter_prop(&mut self, prop: &mut ast::GetterProp) {
+        if let Some(body) = &mut prop.body {
+            body.stmts.clear();
+        }
+    }
+
+    fn visit_mut_setter_prop(&mut self, prop: &mut ast::SetterProp) {
+        if let Some(body) = &mut prop.body {
+            body.stmts.clear();
+        }
+    }
+}
+
 pub struct Module {
     file_set: Lrc<FileSet>,
     pub id: ModuleId,
@@ -415,7 +629,7 @@ impl ModuleLoader {
         let globals = Globals::new();
         GLOBALS.set(&globals, || {
             let file = self.file_set.new_source_file(path, src.into());
-            let module = self.parse_and_store(file, None)?;
+            let module = self.parse_and_store(file, None, None)?;
             Ok(module)
         })
     }
@@ -433,7 +647,7 @@ impl ModuleLoader {
 
             let file_name = FilePath::Real(base.join(&file.name));
             let file = self.file_set.new_source_file(file_name, file.data.clone());
-            let module = self.parse_and_store(file, None)?;
+            let module = self.parse_and_store(file, None, None)?;
             result.insert(module.file_path.clone(), module);
         }
 
@@ -446,3 +660,110 @@ impl ModuleLoader {
 }
 
 const UNIVERSE_TS: &str = include_str!("./universe.ts");
+
+#[cfg(test)]
+mod tests {
+    use swc_common::{FileName, SourceMap};
+    use swc_ecma_parser::{parse_file_as_module, TsConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_strip_function_bodies() {
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(
+            FileName::Anon,
+            "
+function f(a: string): number { return 1; }
+class C { m(): void { x(); } }
+const g = () => { y(); };
+const h = () => z;
+"
+            .into(),
+        );
+        let mut module = parse_file_as_module(
+            &fm,
+            Syntax::Typescript(TsConfig::default()),
+            EsVersion::Es2022,
+            None,
+            &mut vec![],
+        )
+        .unwrap();
+
+        strip_function_bodies(&mut module);
+
+        let stmt = |idx: usize| module.body[idx].as_stmt().unwrap().as_decl().unwrap();
+        let var_init = |idx: usize| {
+            let var = stmt(idx).as_var().unwrap();
+            var.decls[0]
+                .init
+                .as_ref()
+                .unwrap()
+                .as_arrow()
+                .unwrap()
+                .clone()
+        };
+
+        let func = stmt(0).as_fn_decl().unwrap();
+        assert!(func.function.body.as_ref().unwrap().stmts.is_empty());
+        assert_eq!(func.function.params.len(), 1);
+        assert!(func.function.return_type.is_some());
+
+        let class = stmt(1).as_class().unwrap();
+        let method = class.class.body[0].as_method().unwrap();
+        assert!(method.function.body.as_ref().unwrap().stmts.is_empty());
+
+        assert!(var_init(2).body.as_block_stmt().unwrap().stmts.is_empty());
+        assert!(var_init(3).body.is_expr());
+    }
+
+    #[test]
+    fn test_regenerate_synthetic() {
+        use std::rc::Rc;
+
+        use assert_fs::TempDir;
+        use swc_common::{Globals, SourceMap, GLOBALS};
+
+        use crate::parser::parser::ParseContext;
+
+        let root = TempDir::new().unwrap();
+        let cm: Rc<SourceMap> = Default::default();
+        let errs = Rc::new(Handler::with_tty_emitter(
+            swc_common::errors::ColorConfig::Auto,
+            true,
+            false,
+            Some(cm.clone()),
+        ));
+        let pc = ParseContext::new(root.to_path_buf(), None, cm, errs).unwrap();
+        let loader = &pc.loader;
+
+        GLOBALS.set(&Globals::new(), || {
+            let clients = loader.encore_app_clients();
+            assert!(Rc::ptr_eq(&clients, &loader.encore_app_clients()));
+
+            // Loading the synthetic path returns the registered module.
+            let path = SyntheticModule::AppClients.file_path();
+            let resolved = loader.load_custom_file(path, "", None).unwrap();
+            assert!(Rc::ptr_eq(&clients, &resolved));
+
+            let regenerated = Rc::new(Cell::new(None));
+            let hook_regenerated = regenerated.clone();
+            loader.on_regenerate(move |m, module| hook_regenerated.set(Some((m, module.id))));
+
+            let module = loader
+                .regenerate(SyntheticModule::AppClients, "export const x = 1;".into())
+                .unwrap();
+            assert_eq!(module.id, clients.id);
+            assert_eq!(module.ast.body.len(), 1);
+            assert!(Rc::ptr_eq(&module, &loader.encore_app_clients()));
+            assert_eq!(
+                regenerated.get(),
+                Some((SyntheticModule::AppClients, clients.id))
+            );
+
+            // New modules don't reuse the regenerated module's id.
+            let other = loader.inject_file("other.ts".into(), "").unwrap();
+            assert_ne!(other.id, module.id);
+        });
+    }
+}
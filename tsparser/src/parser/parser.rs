@@ -12,7 +12,8 @@ use swc_ecma_loader::resolvers::node::NodeModulesResolver;
 use swc_ecma_loader::TargetEnv;
 use walkdir::WalkDir;
 
-use crate::parser::module_loader::ModuleLoader;
+use crate::parser::import_cycles::check_import_cycles;
+use crate::parser::module_loader::{self, ModuleLoader};
 use crate::parser::resourceparser::bind::{Bind, BindKind};
 use crate::parser::resourceparser::PassOneParser;
 use crate::parser::resources::apis::service_client::ServiceClient;
@@ -171,6 +172,10 @@ impl<'a> Parser<'a> {
             let mut curr_service: Option<(PathBuf, String)> = None;
 
             for entry in walker {
+                if loader.is_cancelled() {
+                    break;
+                }
+
                 let entry = match entry {
                     Ok(e) => e,
                     Err(err) => {
@@ -206,6 +211,7 @@ impl<'a> Parser<'a> {
                 // Parse the module.
                 let module = match loader.load_fs_file(entry.path(), None) {
                     Ok(module) => module,
+                    Err(module_loader::Error::Cancelled) => break,
                     Err(err) => {
                         HANDLER.with(|handler| {
                             if let Some(span) = err.span() {
@@ -266,11 +272,18 @@ impl<'a> Parser<'a> {
         resources.extend(additional_resources);
         binds.extend(additional_binds);
 
+        if !self.pc.loader.is_cancelled() {
+            check_import_cycles(&self.pc.loader, &self.pc.app_root, &services, &binds);
+        }
+
         let resolver =
             UsageResolver::new(&self.pc.loader, &self.pc.type_checker, &resources, &binds);
         let mut usages = Vec::new();
 
         for module in self.pc.loader.modules() {
+            if self.pc.loader.is_cancelled() {
+                break;
+            }
             let exprs = resolver.scan_usage_exprs(&module);
             let u = resolver.resolve_usage(&module, &exprs);
             usages.extend(u);
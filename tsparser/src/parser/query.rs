@@ -0,0 +1,203 @@
+use swc_common::sync::Lrc;
+
+use crate::parser::parser::{ParseResult, Service};
+use crate::parser::resourceparser::bind::{Bind, BindKind};
+use crate::parser::resources::Resource;
+use crate::parser::usageparser::{Usage, UsageOperation};
+use crate::parser::{FilePath, FileSet, Range};
+
+/// Answers questions about the binds and usages of a parsed app,
+/// for tools that need them without walking the parse result themselves.
+pub struct Query<'a> {
+    file_set: &'a FileSet,
+    parse: &'a ParseResult,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(file_set: &'a FileSet, parse: &'a ParseResult) -> Self {
+        Self { file_set, parse }
+    }
+
+    /// Returns the binds of the resource, both where it's created and referenced.
+    pub fn binds_for(&self, resource: &Resource) -> Vec<&'a Lrc<Bind>> {
+        self.parse
+            .binds
+            .iter()
+            .filter(|b| b.resource.is_same(resource))
+            .collect()
+    }
+
+    /// Returns the bind that creates the resource, if any.
+    pub fn definition(&self, resource: &Resource) -> Option<&'a Lrc<Bind>> {
+        self.parse
+            .binds
+            .iter()
+            .find(|b| matches!(b.kind, BindKind::Create) && b.resource.is_same(resource))
+    }
+
+    /// Returns the service with the given name.
+    pub fn service(&self, name: &str) -> Option<&'a Service> {
+        self.parse.services.iter().find(|svc| svc.name == name)
+    }
+
+    /// Returns the service the source range is within, if any.
+    pub fn service_for_range(&self, range: &Range) -> Option<&'a Service> {
+        let path = match range.file(self.file_set) {
+            FilePath::Real(path) => path,
+            FilePath::Custom(_) => return None,
+        };
+        self.parse
+            .services
+            .iter()
+            .find(|svc| path.starts_with(svc.root.as_path()))
+    }
+
+    /// Returns the usages of the resource.
+    pub fn usages_of(&self, resource: &Resource) -> Vec<&'a Usage> {
+        self.parse
+            .usages
+            .iter()
+            .filter(|u| u.uses(resource))
+            .collect()
+    }
+
+    /// Returns the usages within the service with the given name.
+    pub fn usages_in_service(&self, name: &str) -> Vec<&'a Usage> {
+        self.parse
+            .usages
+            .iter()
+            .filter(|u| {
+                self.service_for_range(&u.range())
+                    .is_some_and(|svc| svc.name == name)
+            })
+            .collect()
+    }
+
+    /// Returns the usages that perform the operation.
+    pub fn usages_with_operation(&self, op: UsageOperation) -> Vec<&'a Usage> {
+        self.parse
+            .usages
+            .iter()
+            .filter(|u| u.operations().contains(&op))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use swc_common::errors::{Handler, HANDLER};
+    use swc_common::{Globals, SourceMap, GLOBALS};
+    use tempdir::TempDir;
+
+    use crate::parser::parser::{ParseContext, Parser};
+    use crate::parser::resourceparser::PassOneParser;
+    use crate::parser::resources::infra::pubsub_topic::TopicOperation;
+    use crate::testutil::testresolve::TestResolver;
+    use crate::testutil::JS_RUNTIME_PATH;
+
+    use super::*;
+
+    fn parse(tmp_dir: &Path, src: &str, f: impl FnOnce(Query)) {
+        let globals = Globals::new();
+        let cm: Rc<SourceMap> = Default::default();
+        let errs = Rc::new(Handler::with_tty_emitter(
+            swc_common::errors::ColorConfig::Auto,
+            true,
+            false,
+            Some(cm.clone()),
+        ));
+
+        GLOBALS.set(&globals, || {
+            HANDLER.set(&errs, || {
+                let ar = txtar::from_str(src);
+                ar.materialize(tmp_dir).unwrap();
+
+                let resolver = Box::new(TestResolver::new(tmp_dir.to_path_buf(), ar.clone()));
+                let pc = ParseContext::with_resolver(
+                    tmp_dir.to_path_buf(),
+                    Some(JS_RUNTIME_PATH.clone()),
+                    resolver,
+                    cm,
+                    errs.clone(),
+                )
+                .unwrap();
+                pc.loader.load_archive(tmp_dir, &ar).unwrap();
+
+                let pass1 = PassOneParser::new(
+                    pc.file_set.clone(),
+                    pc.type_checker.clone(),
+                    Default::default(),
+                );
+                let result = Parser::new(&pc, pass1).parse();
+                assert!(!errs.has_errors());
+                f(Query::new(&pc.file_set, &result));
+            })
+        })
+    }
+
+    #[test]
+    fn test_query() {
+        let tmp_dir = TempDir::new("tsparser-test").unwrap();
+        parse(
+            tmp_dir.path(),
+            r#"
+-- orders/encore.service.ts --
+import { Service } from "encore.dev/service";
+export default new Service("orders");
+
+-- orders/topic.ts --
+import { Topic } from "encore.dev/pubsub";
+export const created = new Topic<{ id: string }>("created", {
+  deliveryGuarantee: "at-least-once",
+});
+
+-- orders/api.ts --
+import { api } from "encore.dev/api";
+import { created } from "./topic";
+
+export const create = api({}, async (): Promise<void> => {
+  await created.publish({ id: "1" });
+});
+
+-- email/encore.service.ts --
+import { Service } from "encore.dev/service";
+export default new Service("email");
+"#,
+            |q| {
+                let topic = q
+                    .parse
+                    .resources
+                    .iter()
+                    .find(|r| matches!(r, Resource::PubSubTopic(_)))
+                    .unwrap();
+
+                let def = q.definition(topic).unwrap();
+                assert_eq!(def.name.as_deref(), Some("created"));
+                assert!(!q.binds_for(topic).is_empty());
+
+                let usages = q.usages_of(topic);
+                assert_eq!(usages.len(), 1);
+                assert_eq!(
+                    usages[0].operations(),
+                    vec![UsageOperation::Topic(TopicOperation::Publish)]
+                );
+                let svc = q.service_for_range(&usages[0].range()).unwrap();
+                assert_eq!(svc.name, "orders");
+
+                assert_eq!(q.usages_in_service("orders").len(), 1);
+                assert!(q.usages_in_service("email").is_empty());
+                assert_eq!(
+                    q.usages_with_operation(UsageOperation::Topic(TopicOperation::Publish))
+                        .len(),
+                    1
+                );
+                assert!(q
+                    .usages_with_operation(UsageOperation::AccessDatabase)
+                    .is_empty());
+            },
+        );
+    }
+}
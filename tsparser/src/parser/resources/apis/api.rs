@@ -50,6 +50,21 @@ pub struct Endpoint {
     pub static_assets: Option<StaticAssets>,
 
     pub encoding: EndpointEncoding,
+
+    /// Whether responses carry an ETag.
+    pub etag: ETag,
+
+    /// Default values of request fields, from destructuring
+    /// the request in the handler's parameters.
+    pub request_defaults: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ETag {
+    #[default]
+    Disabled,
+    Strong,
+    Weak,
 }
 
 #[derive(Debug, Clone)]
@@ -248,9 +263,15 @@ pub const ENDPOINT_PARSER: ResourceParser = ResourceParser {
             let mut streaming_request = false;
             let mut streaming_response = false;
             let mut static_assets = None;
+            let mut request_defaults = HashMap::new();
 
             let encoding = match r.kind {
-                EndpointKind::Typed { request, response } => {
+                EndpointKind::Typed {
+                    request,
+                    response,
+                    defaults,
+                } => {
+                    request_defaults = defaults;
                     let request = match request {
                         None => None,
                         Some(t) => Some(pass.type_checker.resolve_type(module.clone(), &t)),
@@ -393,6 +414,8 @@ pub const ENDPOINT_PARSER: ResourceParser = ResourceParser {
                 encoding,
                 tags: cfg.tags.unwrap_or_default(),
                 sensitive: cfg.sensitive.unwrap_or(false),
+                etag: cfg.etag.unwrap_or_default(),
+                request_defaults,
             }));
 
             pass.add_resource(resource.clone());
@@ -466,6 +489,7 @@ enum EndpointKind {
     Typed {
         request: Option<ast::TsType>,
         response: Option<ast::TsType>,
+        defaults: HashMap<String, serde_json::Value>,
     },
     TypedStream {
         handshake: Option<ast::TsType>,
@@ -547,6 +571,7 @@ struct EndpointConfig {
     bodyLimit: Option<Nullable<u64>>,
     tags: Option<Vec<String>>,
     sensitive: Option<bool>,
+    etag: Option<ETag>,
 
     // For static assets.
     dir: Option<Sp<LocalRelPath>>,
@@ -818,7 +843,8 @@ impl ReferenceParser for APIEndpointLiteral {
                                 .span_hi()
                                 .parse_err("API endpoint must have a handler function"));
                         };
-                        let (mut req, mut resp) = parse_endpoint_signature(&handler.expr)?;
+                        let (mut req, mut resp, defaults) =
+                            parse_endpoint_signature(&handler.expr)?;
 
                         if req.is_none() {
                             req = extract_type_param(expr.type_args.as_deref(), 0);
@@ -836,6 +862,7 @@ impl ReferenceParser for APIEndpointLiteral {
                             kind: EndpointKind::Typed {
                                 request: req.cloned(),
                                 response: resp.cloned(),
+                                defaults,
                             },
                         }
                     }
@@ -871,9 +898,13 @@ fn parse_stream_endpoint_signature(expr: &ast::Expr) -> ParseResult<(bool, Optio
     Ok((has_handshake_param, return_type))
 }
 
-fn parse_endpoint_signature(
-    expr: &ast::Expr,
-) -> ParseResult<(Option<&ast::TsType>, Option<&ast::TsType>)> {
+type EndpointSignature<'a> = (
+    Option<&'a ast::TsType>,
+    Option<&'a ast::TsType>,
+    HashMap<String, serde_json::Value>,
+);
+
+fn parse_endpoint_signature(expr: &ast::Expr) -> ParseResult<EndpointSignature<'_>> {
     let (req_param, type_params, return_type) = match expr {
         ast::Expr::Fn(func) => (
             func.function.params.first().map(|p| &p.pat),
@@ -885,7 +916,7 @@ fn parse_endpoint_signature(
             arrow.type_params.as_deref(),
             arrow.return_type.as_deref(),
         ),
-        _ => return Ok((None, None)),
+        _ => return Ok((None, None, HashMap::new())),
     };
 
     if let Some(type_params) = type_params {
@@ -906,8 +937,129 @@ fn parse_endpoint_signature(
 
     let req = req_type.map(|t| t.type_ann.as_ref());
     let resp = return_type.map(|t| t.type_ann.as_ref());
+    let defaults = req_param.map(destructured_defaults).unwrap_or_default();
 
-    Ok((req, resp))
+    Ok((req, resp, defaults))
+}
+
+/// Extracts the defaults of fields destructured from the request,
+/// like `limit` in `({ limit = 20 }: Params) => ...`.
+/// Defaults that aren't constant, like `limit = maxLimit()`, are skipped.
+fn destructured_defaults(param: &ast::Pat) -> HashMap<String, serde_json::Value> {
+    let mut defaults = HashMap::new();
+    let ast::Pat::Object(obj) = param else {
+        return defaults;
+    };
+
+    for prop in &obj.props {
+        let (key, value) = match prop {
+            // { limit = 20 }
+            ast::ObjectPatProp::Assign(prop) => match &prop.value {
+                Some(value) => (prop.key.sym.to_string(), value.as_ref()),
+                None => continue,
+            },
+            // { limit: max = 20 }
+            ast::ObjectPatProp::KeyValue(prop) => {
+                let ast::Pat::Assign(assign) = prop.value.as_ref() else {
+                    continue;
+                };
+                let key = match &prop.key {
+                    ast::PropName::Ident(id) => id.sym.to_string(),
+                    ast::PropName::Str(str) => str.value.to_string(),
+                    _ => continue,
+                };
+                (key, assign.right.as_ref())
+            }
+            ast::ObjectPatProp::Rest(_) => continue,
+        };
+
+        if let Some(value) = const_json(value) {
+            defaults.insert(key, value);
+        }
+    }
+    defaults
+}
+
+/// Evaluates a constant expression, like a literal, to its JSON value.
+fn const_json(expr: &ast::Expr) -> Option<serde_json::Value> {
+    use serde_json::Value;
+
+    fn number(n: f64) -> Option<Value> {
+        // Keep integers integral, so 20 isn't encoded as 20.0.
+        if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+            Some(Value::from(n as i64))
+        } else {
+            serde_json::Number::from_f64(n).map(Value::Number)
+        }
+    }
+
+    Some(match expr {
+        ast::Expr::Lit(ast::Lit::Str(str)) => Value::String(str.value.to_string()),
+        ast::Expr::Lit(ast::Lit::Num(num)) => number(num.value)?,
+        ast::Expr::Lit(ast::Lit::Bool(b)) => Value::Bool(b.value),
+        ast::Expr::Lit(ast::Lit::Null(_)) => Value::Null,
+        ast::Expr::Unary(ast::UnaryExpr {
+            op: ast::UnaryOp::Minus,
+            arg,
+            ..
+        }) => match arg.as_ref() {
+            ast::Expr::Lit(ast::Lit::Num(num)) => number(-num.value)?,
+            _ => return None,
+        },
+        ast::Expr::Tpl(tpl) if tpl.exprs.is_empty() => {
+            Value::String(tpl.quasis.first()?.cooked.as_ref()?.to_string())
+        }
+        ast::Expr::Array(arr) => Value::Array(
+            arr.elems
+                .iter()
+                .map(|elem| match elem {
+                    Some(elem) if elem.spread.is_none() => const_json(&elem.expr),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        ),
+        ast::Expr::Object(obj) => Value::Object(
+            obj.props
+                .iter()
+                .map(|prop| {
+                    let ast::PropOrSpread::Prop(prop) = prop else {
+                        return None;
+                    };
+                    let ast::Prop::KeyValue(kv) = prop.as_ref() else {
+                        return None;
+                    };
+                    let key = match &kv.key {
+                        ast::PropName::Ident(id) => id.sym.to_string(),
+                        ast::PropName::Str(str) => str.value.to_string(),
+                        _ => return None,
+                    };
+                    Some((key, const_json(&kv.value)?))
+                })
+                .collect::<Option<_>>()?,
+        ),
+        ast::Expr::Paren(expr) => const_json(&expr.expr)?,
+        ast::Expr::TsAs(expr) => const_json(&expr.expr)?,
+        ast::Expr::TsConstAssertion(expr) => const_json(&expr.expr)?,
+        ast::Expr::TsSatisfies(expr) => const_json(&expr.expr)?,
+        _ => return None,
+    })
+}
+
+impl LitParser for ETag {
+    fn parse_lit(expr: &ast::Expr) -> ParseResult<Self> {
+        Ok(match expr {
+            ast::Expr::Lit(ast::Lit::Bool(b)) if b.value => Self::Strong,
+            ast::Expr::Lit(ast::Lit::Bool(_)) => Self::Disabled,
+            ast::Expr::Lit(ast::Lit::Str(s)) => match s.value.as_ref() {
+                "strong" => Self::Strong,
+                "weak" => Self::Weak,
+                _ => return Err(s.parse_err("invalid etag: must be \"strong\" or \"weak\"")),
+            },
+            _ => {
+                return Err(expr.parse_err("invalid etag: must be a boolean or string"));
+            }
+        })
+    }
 }
 
 impl LitParser for Methods {
@@ -66,6 +66,28 @@ impl Display for Resource {
     }
 }
 
+impl Resource {
+    /// Reports whether both refer to the same resource.
+    pub fn is_same(&self, other: &Resource) -> bool {
+        use Resource::*;
+        match (self, other) {
+            (ServiceClient(a), ServiceClient(b)) => Lrc::ptr_eq(a, b),
+            (APIEndpoint(a), APIEndpoint(b)) => Lrc::ptr_eq(a, b),
+            (AuthHandler(a), AuthHandler(b)) => Lrc::ptr_eq(a, b),
+            (Gateway(a), Gateway(b)) => Lrc::ptr_eq(a, b),
+            (Service(a), Service(b)) => Lrc::ptr_eq(a, b),
+            (SQLDatabase(a), SQLDatabase(b)) => Lrc::ptr_eq(a, b),
+            (Bucket(a), Bucket(b)) => Lrc::ptr_eq(a, b),
+            (PubSubTopic(a), PubSubTopic(b)) => Lrc::ptr_eq(a, b),
+            (PubSubSubscription(a), PubSubSubscription(b)) => Lrc::ptr_eq(a, b),
+            (CronJob(a), CronJob(b)) => Lrc::ptr_eq(a, b),
+            (Secret(a), Secret(b)) => Lrc::ptr_eq(a, b),
+            (Metric(a), Metric(b)) => Lrc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
 pub static DEFAULT_RESOURCE_PARSERS: &[&ResourceParser] = &[
     // The service parser must come first, as other resources may depend on
     // knowing which service they belong to.
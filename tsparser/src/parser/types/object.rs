@@ -75,6 +75,25 @@ pub enum ObjectKind {
 }
 
 impl ObjectKind {
+    /// Drops the function bodies within the object's declaration.
+    fn strip_bodies(&mut self) {
+        match self {
+            ObjectKind::Var(Var { expr, .. }) | ObjectKind::Using(Using { expr, .. }) => {
+                if let Some(expr) = expr {
+                    module_loader::strip_function_bodies(expr.as_mut());
+                }
+            }
+            ObjectKind::Func(Func { spec }) => module_loader::strip_function_bodies(spec.as_mut()),
+            ObjectKind::Class(Class { spec }) => {
+                module_loader::strip_function_bodies(spec.as_mut())
+            }
+            ObjectKind::TypeName(_)
+            | ObjectKind::Enum(_)
+            | ObjectKind::Module(_)
+            | ObjectKind::Namespace(_) => {}
+        }
+    }
+
     pub fn type_params<'a>(&'a self) -> Box<dyn Iterator<Item = &'a ast::TsTypeParam> + 'a> {
         match self {
             ObjectKind::TypeName(TypeName { decl }) => match decl {
@@ -142,13 +161,13 @@ pub struct Using {
     pub expr: Option<Box<ast::Expr>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NamedReexport {
     pub orig_name: String,
     pub renamed: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Reexport {
     List {
         items: Vec<NamedReexport>,
@@ -159,7 +178,7 @@ pub enum Reexport {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NSData {
     /// The objects imported by the module.
     pub imports: HashMap<AstId, ImportedName>,
@@ -261,13 +280,77 @@ impl NSData {
     }
 
     fn add_top_level(&mut self, id: AstId, obj: Rc<Object>) -> Rc<Object> {
-        if let Some(other) = self.top_level.get(&id) {
+        let Some(other) = self.top_level.get(&id).cloned() else {
+            self.top_level.insert(id, obj.clone());
+            return obj;
+        };
+
+        let Some(merged) = merge_decls(&other, &obj) else {
             // Unhandled overload most likely, return the existing object for now.
-            return other.clone();
+            return other;
+        };
+
+        // Replace the existing object wherever it's referenced.
+        for export in self.named_exports.values_mut() {
+            if Rc::ptr_eq(export, &other) {
+                *export = merged.clone();
+            }
+        }
+        if let Some(default) = &mut self.default_export {
+            if Rc::ptr_eq(default, &other) {
+                *default = merged.clone();
+            }
         }
+        self.top_level.insert(id, merged.clone());
+        merged
+    }
+
+    /// Adds the objects of another declaration of the same namespace.
+    fn merge(&mut self, other: &NSData) {
+        for (id, obj) in &other.top_level {
+            self.add_top_level(id.clone(), obj.clone());
+        }
+        for (name, export) in &other.named_exports {
+            // Declarations in separate blocks have distinct identifiers,
+            // so exported declarations are merged by name.
+            let merged = self
+                .named_exports
+                .get(name)
+                .and_then(|existing| Some((existing.clone(), merge_decls(existing, export)?)));
+            let Some((existing, merged)) = merged else {
+                self.named_exports.insert(name.clone(), export.clone());
+                continue;
+            };
 
-        self.top_level.insert(id, obj.clone());
-        obj
+            for obj in self.top_level.values_mut() {
+                if Rc::ptr_eq(obj, &existing) || Rc::ptr_eq(obj, export) {
+                    *obj = merged.clone();
+                }
+            }
+            self.named_exports.insert(name.clone(), merged);
+        }
+        for (id, import) in &other.imports {
+            self.imports
+                .entry(id.clone())
+                .or_insert_with(|| import.clone());
+        }
+        if self.default_export.is_none() {
+            self.default_export = other.default_export.clone();
+        }
+        self.reexports.extend(other.reexports.iter().cloned());
+    }
+
+    /// Finds the object for the identifier within nested namespaces.
+    fn namespace_ident(&self, ast_id: &AstId) -> Option<Rc<Object>> {
+        self.top_level.values().find_map(|obj| match &obj.kind {
+            ObjectKind::Namespace(ns) => ns
+                .data
+                .top_level
+                .get(ast_id)
+                .cloned()
+                .or_else(|| ns.data.namespace_ident(ast_id)),
+            _ => None,
+        })
     }
 
     fn add_import(&mut self, id: AstId, import: ImportedName) {
@@ -282,6 +365,43 @@ impl NSData {
     }
 }
 
+/// Merges two declarations with the same name, like TypeScript does for
+/// interfaces and namespaces. Returns None if they can't be merged.
+fn merge_decls(a: &Object, b: &Object) -> Option<Rc<Object>> {
+    let kind = match (&a.kind, &b.kind) {
+        (
+            ObjectKind::TypeName(TypeName {
+                decl: TypeNameDecl::Interface(x),
+            }),
+            ObjectKind::TypeName(TypeName {
+                decl: TypeNameDecl::Interface(y),
+            }),
+        ) => {
+            let mut merged = x.clone();
+            merged.extends.extend(y.extends.iter().cloned());
+            merged.body.body.extend(y.body.body.iter().cloned());
+            ObjectKind::TypeName(TypeName {
+                decl: TypeNameDecl::Interface(merged),
+            })
+        }
+        (ObjectKind::Namespace(x), ObjectKind::Namespace(y)) => {
+            let mut data = x.data.clone();
+            data.merge(&y.data);
+            ObjectKind::Namespace(Namespace { data })
+        }
+        _ => return None,
+    };
+
+    Some(Rc::new(Object {
+        id: a.id,
+        range: a.range,
+        name: a.name.clone(),
+        kind,
+        module_id: a.module_id,
+        state: RefCell::new(CheckState::NotStarted),
+    }))
+}
+
 fn process_module_items(ctx: &ResolveState, ns: &mut NSData, items: &[ast::ModuleItem]) {
     for it in items {
         match it {
@@ -394,24 +514,48 @@ fn process_local_exports(ns: &mut NSData) {
                             .map(module_export_name_to_string)
                             .unwrap_or_else(|| orig_name.clone());
 
-                        // Look up the object in top_level by name.
-                        if let Some(obj) = ns.top_level.values().find(|obj| {
-                            obj.name.as_ref().is_some_and(|n| n == &orig_name)
-                        }) {
-                            ns.named_exports.insert(export_name, obj.clone());
-                        } else if let Some(import) = ns.imports.values().find(|imp| {
-                            matches!(&imp.kind, ImportKind::Named(name) if name == &orig_name)
-                        }) {
-                            // The export refers to an import - we need to add it as a reexport.
+                        // Look up the object by its identifier, falling back to its name.
+                        let ast_id = match &named.orig {
+                            ast::ModuleExportName::Ident(id) => Some(AstId::from(id)),
+                            ast::ModuleExportName::Str(_) => None,
+                        };
+                        let local = ast_id
+                            .as_ref()
+                            .and_then(|id| ns.top_level.get(id))
+                            .or_else(|| {
+                                ns.top_level
+                                    .values()
+                                    .find(|obj| obj.name.as_ref().is_some_and(|n| n == &orig_name))
+                            })
+                            .cloned();
+                        let import = ast_id.as_ref().and_then(|id| ns.imports.get(id)).cloned();
+
+                        if let Some(obj) = local {
+                            if export_name == "default" {
+                                ns.default_export = Some(obj.clone());
+                            }
+                            ns.named_exports.insert(export_name, obj);
+                        } else if let Some(import) = import {
+                            // The export refers to an import, possibly under an alias
+                            // (`import { a as b }; export { b as c }`).
+                            // Re-export it under the name it was imported as.
+                            let imported_name = match &import.kind {
+                                ImportKind::Named(name) => name.clone(),
+                                ImportKind::Default => "default".to_string(),
+                                ImportKind::Namespace => {
+                                    log::debug!("TODO: local export of namespace import");
+                                    continue;
+                                }
+                            };
                             ns.reexports.push(Reexport::List {
                                 import_path: import.import_path.clone(),
                                 items: vec![NamedReexport {
-                                    orig_name: orig_name.clone(),
-                                    renamed: if export_name != orig_name {
+                                    renamed: if export_name != imported_name {
                                         Some(export_name)
                                     } else {
                                         None
                                     },
+                                    orig_name: imported_name,
                                 }],
                             });
                         } else {
@@ -576,8 +720,7 @@ fn process_decl(ctx: &ResolveState, ns: &mut NSData, decl: &ast::Decl) -> Vec<Rc
                     decl: TypeNameDecl::Interface(*d.clone()),
                 }),
             );
-            ns.add_top_level(AstId::from(&d.id), obj.clone());
-            vec![obj]
+            vec![ns.add_top_level(AstId::from(&d.id), obj)]
         }
 
         ast::Decl::TsTypeAlias(d) => {
@@ -614,13 +757,12 @@ fn process_decl(ctx: &ResolveState, ns: &mut NSData, decl: &ast::Decl) -> Vec<Rc
                         data: Box::new(NSData::new()),
                     };
                     if let Some(body) = &d.body {
-                        process_namespace_body(ctx, &mut ns2.data, body);
+                        process_namespace_body(ctx, &mut ns2.data, body, d.declare);
                     }
 
                     let name = Some(id.sym.to_string());
                     let obj = ctx.new_obj(name, range, ObjectKind::Namespace(ns2));
-                    ns.add_top_level(AstId::from(id), obj.clone());
-                    vec![obj]
+                    vec![ns.add_top_level(AstId::from(id), obj)]
                 }
                 ast::TsModuleName::Str(_) => {
                     // This is not valid for namespace declarations, ignore it.
@@ -669,21 +811,41 @@ fn process_default_decl(ctx: &ResolveState, decl: &ast::DefaultDecl) -> Rc<Objec
     }
 }
 
-fn process_namespace_body(ctx: &ResolveState, ns: &mut NSData, body: &ast::TsNamespaceBody) {
+/// Processes the body of a namespace.
+/// Ambient namespaces (`declare namespace`) export all their declarations,
+/// as is common in `.d.ts` files.
+fn process_namespace_body(
+    ctx: &ResolveState,
+    ns: &mut NSData,
+    body: &ast::TsNamespaceBody,
+    ambient: bool,
+) {
     match body {
         ast::TsNamespaceBody::TsModuleBlock(block) => {
             process_module_items(ctx, ns, &block.body[..]);
+            if ambient {
+                for obj in ns.top_level.values() {
+                    if let Some(name) = &obj.name {
+                        ns.named_exports
+                            .entry(name.clone())
+                            .or_insert_with(|| obj.clone());
+                    }
+                }
+            }
         }
         ast::TsNamespaceBody::TsNamespaceDecl(decl) => {
+            // A dotted namespace, like `namespace A.B {}`.
             let name = Some(decl.id.sym.to_string());
             let mut ns2 = Namespace {
                 data: Box::new(NSData::new()),
             };
-            process_namespace_body(ctx, &mut ns2.data, &decl.body);
+            process_namespace_body(ctx, &mut ns2.data, &decl.body, ambient || decl.declare);
 
             let range = decl.span.into();
             let obj = ctx.new_obj(name, range, ObjectKind::Namespace(ns2));
-            ns.add_top_level(AstId::from(&decl.id), obj);
+            let obj = ns.add_top_level(AstId::from(&decl.id), obj);
+            // The inner namespace is implicitly exported from the outer one.
+            ns.named_exports.insert(decl.id.sym.to_string(), obj);
         }
     }
 }
@@ -728,6 +890,12 @@ impl ResolveState {
         let obj_id = self.next_id.get();
         self.next_id.set(obj_id + 1);
 
+        // Function bodies aren't used for type resolution.
+        let mut kind = kind;
+        if self.loader.strip_bodies() {
+            kind.strip_bodies();
+        }
+
         let module_id = self.module_id().expect("no current module");
         Rc::new(Object {
             id: ObjectId(obj_id),
@@ -829,6 +997,12 @@ impl ResolveState {
             return self.resolve_import(&module, imp_name);
         }
 
+        // Is it declared within a namespace, referenced from within it?
+        // Identifiers are unique after resolution, so this can't shadow anything.
+        if let Some(obj) = module.data.namespace_ident(&ast_id) {
+            return Some(obj);
+        }
+
         // Is it in universe scope?
         {
             let universe = self.universe();
@@ -866,9 +1040,12 @@ impl ResolveState {
 
         let ast_module = ast_module
             .inspect_err(|err| {
-                HANDLER.with(|handler| {
-                    handler.span_err(imp.range.to_span(), &format!("import not found: {err}"))
-                })
+                // A cancelled parse is reported once by the caller.
+                if !matches!(err, module_loader::Error::Cancelled) {
+                    HANDLER.with(|handler| {
+                        handler.span_err(imp.range.to_span(), &format!("import not found: {err}"))
+                    })
+                }
             })
             .ok()?;
 
@@ -0,0 +1,10 @@
+const ROLES = ["admin", "user"] as const;
+const LEVELS = { low: 1, high: 2 };
+const NAMES = ["alice", "bob"];
+
+export type Role = typeof ROLES[number];
+export type Second = typeof ROLES[1];
+export type Level = keyof typeof LEVELS;
+export type Common = keyof ({ a: string; b: string } | { a: number });
+export type Keys = keyof { [key: string]: number };
+export type Name = typeof NAMES[number];
@@ -0,0 +1,31 @@
+namespace Shapes {
+  type Coord = number;
+  export type Size = Coord;
+
+  export interface Point {
+    x: number;
+  }
+}
+
+namespace Shapes {
+  export interface Point {
+    y: number;
+  }
+}
+
+declare namespace Lib.Models {
+  type Id = string;
+}
+
+interface Merged {
+  a: string;
+}
+
+interface Merged {
+  b: boolean;
+}
+
+export type T1 = Shapes.Size;
+export type T2 = Shapes.Point;
+export type T3 = Lib.Models.Id;
+export type T4 = Merged;
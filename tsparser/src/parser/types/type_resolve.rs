@@ -82,6 +82,19 @@ impl TypeChecker {
         ctx.obj_type(obj)
     }
 
+    /// Resolves the object a module exports under the given name,
+    /// following re-exports and `export { a as b }` aliases.
+    pub fn resolve_export(
+        &self,
+        module: Lrc<module_loader::Module>,
+        name: &str,
+    ) -> Option<Rc<Object>> {
+        let module = self.ctx.get_or_init_module(module);
+        module
+            .data
+            .get_named_export(&self.ctx, &module.base.swc_file_path, name)
+    }
+
     pub fn resolve_default_export(&self, module: Lrc<module_loader::Module>) -> Option<Rc<Object>> {
         // Ensure the module is initialized.
         let module_id = module.id;
@@ -353,6 +366,21 @@ impl Ctx<'_> {
                 }
             },
 
+            // T[number] is the element type of arrays and tuples.
+            (Type::Array(arr), Type::Basic(Basic::Number)) => *arr.0.clone(),
+            (Type::Tuple(tuple), Type::Basic(Basic::Number)) => simplify_union(tuple.types.clone()),
+            (Type::Tuple(tuple), Type::Literal(Literal::Number(n))) => {
+                match tuple.types.get(*n as usize) {
+                    Some(typ) if n.fract() == 0.0 && *n >= 0.0 => typ.clone(),
+                    _ => {
+                        HANDLER.with(|handler| {
+                            handler.span_err(span, &format!("tuple index {n} out of bounds"))
+                        });
+                        Type::Basic(Basic::Never)
+                    }
+                }
+            }
+
             (Type::Validated(v), idx) => {
                 let typ = self.type_index(span, &v.typ, idx);
                 Type::Validated(Validated {
@@ -433,7 +461,7 @@ impl Ctx<'_> {
             Type::Array(_) | Type::Tuple(_) => Type::Union(Union { types: vec![] }),
 
             Type::Interface(interface) => {
-                let keys = interface
+                let mut keys: Vec<_> = interface
                     .fields
                     .iter()
                     .filter_map(|f| match &f.name {
@@ -443,6 +471,17 @@ impl Ctx<'_> {
                         FieldName::Symbol(_) => None,
                     })
                     .collect();
+
+                // Index signatures add their key type, and string keys
+                // also allow numbers, since obj[1] is obj["1"].
+                if let Some((key, _)) = &interface.index {
+                    match key.as_ref() {
+                        Type::Basic(Basic::String) => {
+                            keys.extend([Type::Basic(Basic::String), Type::Basic(Basic::Number)])
+                        }
+                        key => keys.push(key.clone()),
+                    }
+                }
                 Type::Union(Union { types: keys })
             }
 
@@ -457,9 +496,15 @@ impl Ctx<'_> {
             }
 
             Type::Optional(typ) => self.keyof(&typ.0),
+            // keyof (A | B) is the keys common to both, (keyof A) & (keyof B).
             Type::Union(union) => {
-                let res: Vec<_> = union.types.iter().map(|t| self.keyof(t)).collect();
-                Type::Union(Union { types: res })
+                let mut keys = union.types.iter().map(|t| self.keyof(t));
+                let Some(first) = keys.next() else {
+                    return Type::Basic(Basic::Never);
+                };
+                keys.fold(first, |acc, k| {
+                    intersect(self, Cow::Owned(acc), Cow::Owned(k)).into_owned()
+                })
             }
 
             // keyof "blah" is the same as keyof string, which should yield all properties.
@@ -1041,15 +1086,22 @@ impl Ctx<'_> {
     }
 
     fn tuple(&self, tuple: &ast::TsTupleType) -> Type {
-        let types = self.types(tuple.elem_types.iter().filter_map(|t|
-            // As far as I can tell labels don't actually impact type-checking
-            // at all, so we can ignore them.
-            // See https://www.typescriptlang.org/docs/handbook/release-notes/typescript-4-0.html.
-            if t.label.is_some() {
-                None
-            } else {
-                Some(t.ty.as_ref())
-            }));
+        // As far as I can tell labels don't actually impact type-checking
+        // at all, except for marking the element as optional, as in `[a: string, b?: string]`.
+        // See https://www.typescriptlang.org/docs/handbook/release-notes/typescript-4-0.html.
+        let types = tuple
+            .elem_types
+            .iter()
+            .map(|t| {
+                let typ = self.typ(&t.ty);
+                match &t.label {
+                    Some(ast::Pat::Ident(id)) if id.optional => {
+                        Type::Optional(Optional(Box::new(typ)))
+                    }
+                    _ => typ,
+                }
+            })
+            .collect();
 
         Type::Tuple(Tuple { types })
     }
@@ -1227,7 +1279,7 @@ impl Ctx<'_> {
     fn expr(&self, expr: &ast::Expr) -> Type {
         match expr {
             ast::Expr::This(_) => Type::This(This),
-            ast::Expr::Array(lit) => self.array_lit(lit),
+            ast::Expr::Array(lit) => self.array_lit(lit, true),
             ast::Expr::Object(lit) => self.object_lit(lit),
             ast::Expr::Fn(_) => {
                 HANDLER.with(|handler| handler.span_err(expr.span(), "fn expr not yet supported"));
@@ -1386,7 +1438,10 @@ impl Ctx<'_> {
             // foo as T
             ast::Expr::TsAs(expr) => self.typ(&expr.type_ann),
 
-            ast::Expr::TsConstAssertion(expr) => self.expr(&expr.expr),
+            ast::Expr::TsConstAssertion(expr) => match expr.expr.as_ref() {
+                ast::Expr::Array(lit) => self.const_array_lit(lit),
+                other => self.expr(other),
+            },
 
             // https://www.typescriptlang.org/docs/handbook/release-notes/typescript-4-9.html
             ast::Expr::TsSatisfies(expr) => self.expr(&expr.expr),
@@ -1439,35 +1494,51 @@ impl Ctx<'_> {
         }
     }
 
-    fn array_lit(&self, lit: &ast::ArrayLit) -> Type {
-        let elem_types = Vec::with_capacity(lit.elems.len());
-
-        // Track the current element type.
-        let mut elem_type: Option<Type> = None;
+    /// Resolves an array literal to an array of its element types.
+    /// Unless `widen` is false, as for `as const`, literal element types are
+    /// widened as TypeScript does for mutable arrays, so ["a"] is string[].
+    fn array_lit(&self, lit: &ast::ArrayLit, widen: bool) -> Type {
+        let mut elem_types = Vec::with_capacity(lit.elems.len());
 
         for elem in lit.elems.iter().flatten() {
             let mut base = self.expr(&elem.expr);
             if elem.spread.is_some() {
                 // The type of [...["a"]] is string[].
-                if let Type::Array(arr) = base {
-                    base = *arr.0;
+                match base {
+                    Type::Array(arr) => base = *arr.0,
+                    Type::Tuple(tuple) => base = simplify_union(tuple.types),
+                    _ => {}
                 }
             }
-
-            match &elem_type {
-                Some(Type::Union(_elem_types)) => {}
-                Some(typ) => {
-                    elem_type = Some(Type::Union(Union {
-                        types: vec![typ.clone(), base],
-                    }));
-                }
-                None => {
-                    elem_type = Some(base);
-                }
+            if widen {
+                base = widen_literals(base);
             }
+            elem_types.push(base);
         }
 
-        Type::Union(Union { types: elem_types })
+        Type::Array(Array(Box::new(simplify_union(elem_types))))
+    }
+
+    /// Resolves `[...] as const`, which yields a tuple of the element types.
+    fn const_array_lit(&self, lit: &ast::ArrayLit) -> Type {
+        let mut types = Vec::with_capacity(lit.elems.len());
+        for elem in &lit.elems {
+            match elem {
+                Some(elem) if elem.spread.is_none() => types.push(match elem.expr.as_ref() {
+                    // Nested array literals are constant as well.
+                    ast::Expr::Array(lit) => self.const_array_lit(lit),
+                    expr => self.expr(expr),
+                }),
+                Some(elem) => match self.expr(&elem.expr) {
+                    Type::Tuple(tuple) => types.extend(tuple.types),
+                    // Spreading an array of unknown length yields an array.
+                    _ => return self.array_lit(lit, false),
+                },
+                // Holes, as in [1, , 2].
+                None => types.push(Type::Basic(Basic::Undefined)),
+            }
+        }
+        Type::Tuple(Tuple { types })
     }
 
     fn object_lit(&self, lit: &ast::ObjectLit) -> Type {
@@ -2504,3 +2575,12 @@ impl Ctx<'_> {
         })
     }
 }
+
+/// Widens literal types to their basic type, including within unions.
+fn widen_literals(typ: Type) -> Type {
+    match typ {
+        Type::Literal(lit) => Type::Basic(lit.basic()),
+        Type::Union(union) => simplify_union(union.types.into_iter().map(widen_literals).collect()),
+        typ => typ,
+    }
+}
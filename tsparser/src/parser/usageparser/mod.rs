@@ -9,7 +9,7 @@ use swc_ecma_visit::fields::{
 };
 use swc_ecma_visit::{AstNodePath, AstParentNodeRef, VisitAstPath, VisitWithPath};
 
-use crate::parser::module_loader::{Module, ModuleId, ModuleLoader};
+use crate::parser::module_loader::{self, Module, ModuleId, ModuleLoader};
 use crate::parser::resourceparser::bind::Bind;
 use crate::parser::resources::{apis, infra, Resource};
 use crate::parser::Range;
@@ -154,6 +154,7 @@ impl<'a> UsageResolver<'a> {
             {
                 Ok(None) => continue,
                 Ok(Some(resolved_module)) => resolved_module,
+                Err(module_loader::Error::Cancelled) => continue,
                 Err(err) => {
                     HANDLER.with(|handler| {
                         handler.span_err(err.span().unwrap_or_else(|| imp.span()), &err.msg())
@@ -177,7 +178,8 @@ impl<'a> UsageResolver<'a> {
                         let found_bind = resolved_binds
                             .into_iter()
                             .flatten()
-                            .find(|b| b.name.as_ref().is_some_and(|i| i == src_name));
+                            .find(|b| b.name.as_ref().is_some_and(|i| i == src_name))
+                            .or_else(|| self.aliased_bind(&resolved_module, src_name));
 
                         if let Some(bind) = found_bind {
                             external.push(BindToScan {
@@ -214,6 +216,17 @@ impl<'a> UsageResolver<'a> {
         external
     }
 
+    /// aliased_bind finds the bind a module exports under a different name,
+    /// through `export { a as b }` or a re-export from another module.
+    fn aliased_bind(&self, module: &Lrc<Module>, name: &str) -> Option<&Lrc<Bind>> {
+        let obj = self.type_checker.resolve_export(module.clone(), name)?;
+        let obj_name = obj.name.as_ref()?;
+        self.binds_by_module
+            .get(&obj.module_id)?
+            .iter()
+            .find(|b| b.name.as_ref() == Some(obj_name))
+    }
+
     /// internal_binds_to_scan_for computes the internal binds to scan for given a module.
     fn internal_binds_to_scan_for(&self, module: &Module) -> Vec<BindToScan<'_>> {
         let mut internal = Vec::new();
@@ -243,6 +256,55 @@ pub enum Usage {
     Metric(infra::metrics::MetricUsage),
 }
 
+/// An operation a usage performs on the resource it uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageOperation {
+    CallEndpoint,
+    AccessDatabase,
+    Topic(infra::pubsub_topic::TopicOperation),
+    Bucket(infra::objects::Operation),
+    Metric(infra::metrics::MetricOperation),
+}
+
+impl Usage {
+    /// The source range of the usage.
+    pub fn range(&self) -> Range {
+        match self {
+            Usage::CallEndpoint(u) => u.range,
+            Usage::Topic(u) => u.range,
+            Usage::AccessDatabase(u) => u.range,
+            Usage::Bucket(u) => u.range,
+            Usage::Metric(u) => u.range,
+        }
+    }
+
+    /// The operations the usage performs.
+    pub fn operations(&self) -> Vec<UsageOperation> {
+        match self {
+            Usage::CallEndpoint(_) => vec![UsageOperation::CallEndpoint],
+            Usage::AccessDatabase(_) => vec![UsageOperation::AccessDatabase],
+            Usage::Topic(u) => u.ops.iter().map(|op| UsageOperation::Topic(*op)).collect(),
+            Usage::Bucket(u) => u.ops.iter().map(|op| UsageOperation::Bucket(*op)).collect(),
+            Usage::Metric(u) => u.ops.iter().map(|op| UsageOperation::Metric(*op)).collect(),
+        }
+    }
+
+    /// Reports whether the usage is of the given resource.
+    /// Endpoint calls are matched by service and endpoint name.
+    pub fn uses(&self, resource: &Resource) -> bool {
+        match (self, resource) {
+            (Usage::CallEndpoint(u), Resource::APIEndpoint(ep)) => {
+                u.endpoint.0 == ep.service_name && u.endpoint.1 == ep.name
+            }
+            (Usage::Topic(u), Resource::PubSubTopic(topic)) => Lrc::ptr_eq(&u.topic, topic),
+            (Usage::AccessDatabase(u), Resource::SQLDatabase(db)) => Lrc::ptr_eq(&u.db, db),
+            (Usage::Bucket(u), Resource::Bucket(bkt)) => Lrc::ptr_eq(&u.bucket, bkt),
+            (Usage::Metric(u), Resource::Metric(metric)) => Lrc::ptr_eq(&u.metric, metric),
+            _ => false,
+        }
+    }
+}
+
 pub struct ResolveUsageData<'a> {
     pub module: &'a Lrc<Module>,
     pub type_checker: &'a TypeChecker,
@@ -587,6 +649,101 @@ export const Bar = 5;
                 static_assets: None,
                 tags: vec![],
                 sensitive: false,
+                etag: Default::default(),
+                request_defaults: Default::default(),
+            }));
+
+            let bar_binds = vec![Lrc::new(Bind {
+                kind: BindKind::Create,
+                object: None,
+                id: 1.into(),
+                range: None,
+                name: Some("Bar".into()),
+                resource: res.clone(),
+                internal_bound_id: None,
+                module_id: bar_mod.id,
+            })];
+
+            let resources = [res];
+            let ur = UsageResolver::new(&pc.loader, &pc.type_checker, &resources, &bar_binds);
+
+            let result = ur.external_binds_to_scan_for(foo_mod);
+            assert_eq!(result.len(), 1);
+            assert_eq!(result[0].bind, bar_binds[0]);
+        });
+    }
+
+    #[test]
+    fn test_scan_aliased_binds() {
+        let globals = Globals::new();
+        GLOBALS.set(&globals, || {
+            let ar = txtar::from_str(
+                "
+-- foo.ts --
+import { Baz as Local } from './reexport.ts';
+-- reexport.ts --
+export { Bar as Baz } from './bar.ts';
+-- bar.ts --
+export const Bar = 5;
+        ",
+            );
+
+            let base = PathBuf::from("/dummy");
+            let resolver = Box::new(TestResolver::new(base.to_path_buf(), ar.clone()));
+            let tmp = TempDir::new().unwrap();
+            let app_root = tmp.child("app_root").to_path_buf();
+            let cm: Rc<SourceMap> = Default::default();
+            let errs = Rc::new(Handler::with_tty_emitter(
+                swc_common::errors::ColorConfig::Auto,
+                true,
+                false,
+                Some(cm.clone()),
+            ));
+            let pc = ParseContext::with_resolver(
+                app_root,
+                Some(JS_RUNTIME_PATH.clone()),
+                resolver,
+                cm,
+                errs,
+            )
+            .unwrap();
+            let mods = pc.loader.load_archive(&base, &ar).unwrap();
+
+            let foo_mod = mods.get(&"/dummy/foo.ts".into()).unwrap();
+            let bar_mod = mods.get(&"/dummy/bar.ts".into()).unwrap();
+
+            let res = Resource::APIEndpoint(Lrc::new(Endpoint {
+                range: Default::default(),
+                service_name: "svc".into(),
+                name: "Bar".into(),
+                name_range: Default::default(),
+                doc: None,
+                expose: true,
+                raw: false,
+                require_auth: false,
+                body_limit: None,
+                encoding: EndpointEncoding {
+                    span: DUMMY_SP,
+                    default_method: Method::Post,
+                    methods: Methods::Some(vec![Method::Post]),
+                    handshake: None,
+                    req: vec![RequestEncoding {
+                        methods: Methods::Some(vec![Method::Post]),
+                        params: vec![],
+                    }],
+                    resp: ResponseEncoding { params: vec![] },
+                    path: Path::parse(DUMMY_SP, "/svc.Bar", Default::default()).unwrap(),
+                    raw_handshake_schema: None,
+                    raw_req_schema: None,
+                    raw_resp_schema: None,
+                },
+                streaming_request: false,
+                streaming_response: false,
+                static_assets: None,
+                tags: vec![],
+                sensitive: false,
+                etag: Default::default(),
+                request_defaults: Default::default(),
             }));
 
             let bar_binds = vec![Lrc::new(Bind {
@@ -690,6 +847,8 @@ export const Bar = 5;
                 static_assets: None,
                 tags: vec![],
                 sensitive: false,
+                etag: Default::default(),
+                request_defaults: Default::default(),
             }));
             let bar_binds = vec![Lrc::new(Bind {
                 kind: BindKind::Create,
@@ -23,7 +23,7 @@ fn test_parser() {
         let ar = txtar::from_str(&input);
         let tmp_dir = TempDir::new("parse").unwrap();
         ar.materialize(&tmp_dir).unwrap();
-        match parse_txtar(tmp_dir.path()) {
+        match parse_txtar(tmp_dir.path(), false) {
             Ok(_) => {}
             Err(e) => {
                 panic!("{:#?}\n{}", e, e.backtrace());
@@ -32,7 +32,22 @@ fn test_parser() {
     });
 }
 
-fn parse_txtar(app_root: &Path) -> Result<app::AppDesc> {
+#[test]
+fn test_low_memory() {
+    glob!("testdata/*.txt", |path| {
+        let input = fs::read_to_string(path).unwrap();
+        let ar = txtar::from_str(&input);
+        let tmp_dir = TempDir::new("parse").unwrap();
+        ar.materialize(&tmp_dir).unwrap();
+
+        // Dropping function bodies must not change what's extracted.
+        let full = parse_txtar(tmp_dir.path(), false).unwrap();
+        let low = parse_txtar(tmp_dir.path(), true).unwrap();
+        assert_eq!(full.meta, low.meta);
+    });
+}
+
+fn parse_txtar(app_root: &Path, low_memory: bool) -> Result<app::AppDesc> {
     let globals = Globals::new();
     let cm: Rc<SourceMap> = Default::default();
     let errs = Rc::new(Handler::with_tty_emitter(
@@ -62,6 +77,8 @@ fn parse_txtar(app_root: &Path) -> Result<app::AppDesc> {
                 pc: &pc,
                 working_dir: app_root,
                 parse_tests: false,
+                low_memory,
+                cancel: Default::default(),
             };
 
             builder.parse(&pp).ok_or(anyhow::anyhow!("parse failed"))
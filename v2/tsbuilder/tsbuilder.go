@@ -43,6 +43,7 @@ type parseInput struct {
 	PlatformID string `json:"platform_id,omitempty"`
 	LocalID    string `json:"local_id"`
 	ParseTests bool   `json:"parse_tests"`
+	LowMemory  bool   `json:"low_memory,omitempty"`
 }
 
 type data struct {
@@ -119,6 +120,14 @@ func (i *BuilderImpl) Parse(ctx context.Context, p builder.ParseParams) (*builde
 	if err != nil {
 		return nil, fmt.Errorf("unable to get stdin: %s", err)
 	}
+
+	// When the ctx is canceled, ask the parser to stop an in-progress parse
+	// so it exits promptly. It's killed after WaitDelay if it doesn't.
+	cmd.Cancel = func() error {
+		_, err := stdin.Write([]byte("cancel\n"))
+		return err
+	}
+
 	stdout, err := cmd.StdoutPipe()
 	if err != nil {
 		return nil, fmt.Errorf("unable to get stdin: %s", err)
@@ -174,6 +183,7 @@ func (i *BuilderImpl) Parse(ctx context.Context, p builder.ParseParams) (*builde
 		PlatformID: p.App.PlatformID(),
 		LocalID:    p.App.LocalID(),
 		ParseTests: p.ParseTests,
+		LowMemory:  os.Getenv("ENCORE_TSPARSER_LOW_MEMORY") == "1",
 	})
 	_, _ = stdin.Write([]byte("parse\n"))
 	if _, err := stdin.Write(input); err != nil {
@@ -181,7 +191,10 @@ func (i *BuilderImpl) Parse(ctx context.Context, p builder.ParseParams) (*builde
 	}
 
 	isSuccess, parseResp, err := readResp(stdout)
-	if err != nil {
+	if ctx.Err() != nil {
+		// The parse was cancelled.
+		return nil, ctx.Err()
+	} else if err != nil {
 		return nil, fmt.Errorf("unable to read response: %s", err)
 	} else if !isSuccess {
 		return nil, errors.New(string(parseResp))